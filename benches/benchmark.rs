@@ -1,89 +1,117 @@
-use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion};
+use criterion::measurement::WallTime;
+use criterion::{
+    black_box, criterion_group, criterion_main, BenchmarkGroup, BenchmarkId, Criterion, Throughput,
+};
 use cutsplit::classifier::Classifier;
 use cutsplit::cutsplit::classifier::CutSplitClassifier;
 use cutsplit::hicuts::classifier::HiCutsClassifier;
 use cutsplit::hypersplit::classifier::HyperSplitClassifier;
 use cutsplit::linear::LinearClassifier;
+use cutsplit::packet::FiveTuple;
 use cutsplit::partitionsort::classifier::PartitionSortClassifier;
+use cutsplit::rule::Rule;
+use cutsplit::simulation::traffic::FlowDistribution;
 use cutsplit::simulation::Simulation;
 use cutsplit::tss::classifier::TSSClassifier;
 // cutsplit::cutsplit::classifier::CutSplitClassifier is ... lib->cutsplit->classifier->CSClassifier.
 // But lib.rs has `pub mod cutsplit`. And `cutsplit/mod.rs` has `pub mod classifier`.
 // So usage is `cutsplit::cutsplit::classifier::CutSplitClassifier`.
 
-fn benchmark_classification(c: &mut Criterion) {
-    let mut sim = Simulation::new(42); // Deterministic seed
-
-    // Benchmark steps requested by user
-    let rule_counts = vec![
-        100, 300, 500, 700, 900, 1000, 3000, 5000, 7000, 9000, 10000, 20000,
-    ];
+/// Packets replayed per iteration.
+const N_PACKETS: usize = 1000;
 
-    let mut group = c.benchmark_group("Classification");
-    // Set a lower sample size/time to accommodate many steps if needed
-    group.sample_size(50);
+/// Kind of packet trace replayed against the classifiers.
+#[derive(Clone, Copy)]
+enum Trace {
+    /// Independent random 5-tuples (no locality).
+    Random,
+    /// 100 flows, 10% of which carry 90% of the packets.
+    Flows,
+}
 
-    for &n_rules in &rule_counts {
-        let rules = sim.generate_rules(n_rules);
-        let packets = sim.generate_packets(1000); // 1000 packets for throughput test
+impl Trace {
+    fn name(self) -> &'static str {
+        match self {
+            Trace::Random => "random",
+            Trace::Flows => "flows",
+        }
+    }
 
-        // Build Classifiers
-        let linear = LinearClassifier::build(&rules);
-        let cutsplit = CutSplitClassifier::build(&rules);
-        let hicuts = HiCutsClassifier::build(&rules);
-        let hypersplit = HyperSplitClassifier::build(&rules);
-        let tss = TSSClassifier::build(&rules);
-        let ps = PartitionSortClassifier::build(&rules);
+    fn generate(self, sim: &mut Simulation, n_packets: usize) -> Vec<FiveTuple> {
+        match self {
+            Trace::Random => sim.generate_packets(n_packets),
+            Trace::Flows => {
+                let flows = sim.generate_flows(100);
+                let heavy = FlowDistribution::HeavyHitters {
+                    fraction: 0.1,
+                    share: 0.9,
+                };
+                sim.generate_flow_trace(&flows, n_packets, &heavy)
+            }
+        }
+    }
+}
 
-        group.bench_function(format!("Linear/{}", n_rules), |b| {
-            b.iter(|| {
-                for p in &packets {
-                    linear.classify(p);
-                }
-            })
-        });
+/// Build `C` over `rules` and time the classification of the whole trace.
+fn bench_classifier<C: Classifier>(
+    group: &mut BenchmarkGroup<WallTime>,
+    name: &str,
+    n_rules: usize,
+    rules: &[Rule],
+    packets: &[FiveTuple],
+) {
+    let classifier = C::build(rules);
+    group.bench_with_input(BenchmarkId::new(name, n_rules), packets, |b, packets| {
+        b.iter(|| {
+            for p in packets {
+                black_box(classifier.classify(black_box(p)));
+            }
+        })
+    });
+}
 
-        group.bench_function(format!("CutSplit/{}", n_rules), |b| {
-            b.iter(|| {
-                for p in &packets {
-                    cutsplit.classify(p);
-                }
-            })
-        });
+fn benchmark_classification(c: &mut Criterion) {
+    // Benchmark steps requested by user
+    let rule_counts = vec![
+        100, 300, 500, 700, 900, 1000, 3000, 5000, 7000, 9000, 10000, 20000,
+    ];
 
-        group.bench_function(format!("HiCuts/{}", n_rules), |b| {
-            b.iter(|| {
-                for p in &packets {
-                    hicuts.classify(p);
-                }
-            })
-        });
+    for trace in [Trace::Random, Trace::Flows] {
+        let mut sim = Simulation::new(42); // Deterministic seed
 
-        group.bench_function(format!("HyperSplit/{}", n_rules), |b| {
-            b.iter(|| {
-                for p in &packets {
-                    hypersplit.classify(p);
-                }
-            })
-        });
+        let mut group = c.benchmark_group(format!("Classification/{}", trace.name()));
+        // Set a lower sample size/time to accommodate many steps if needed
+        group.sample_size(50);
+        // Report packets/second so numbers compare directly as Mpps.
+        group.throughput(Throughput::Elements(N_PACKETS as u64));
 
-        group.bench_function(format!("TSS/{}", n_rules), |b| {
-            b.iter(|| {
-                for p in &packets {
-                    tss.classify(p);
-                }
-            })
-        });
+        for &n_rules in &rule_counts {
+            let rules = sim.generate_rules(n_rules);
+            let packets = trace.generate(&mut sim, N_PACKETS);
 
-        group.bench_function(format!("PartitionSort/{}", n_rules), |b| {
-            b.iter(|| {
-                for p in &packets {
-                    ps.classify(p);
-                }
-            })
-        });
+            bench_classifier::<LinearClassifier>(&mut group, "Linear", n_rules, &rules, &packets);
+            bench_classifier::<CutSplitClassifier>(
+                &mut group, "CutSplit", n_rules, &rules, &packets,
+            );
+            bench_classifier::<HiCutsClassifier>(&mut group, "HiCuts", n_rules, &rules, &packets);
+            bench_classifier::<HyperSplitClassifier>(
+                &mut group,
+                "HyperSplit",
+                n_rules,
+                &rules,
+                &packets,
+            );
+            bench_classifier::<TSSClassifier>(&mut group, "TSS", n_rules, &rules, &packets);
+            bench_classifier::<PartitionSortClassifier>(
+                &mut group,
+                "PartitionSort",
+                n_rules,
+                &rules,
+                &packets,
+            );
+        }
+        group.finish();
     }
-    group.finish();
 }
 
 criterion_group!(benches, benchmark_classification);
@@ -0,0 +1,97 @@
+//! Build-time scaling of every classifier, from 100 to 100k rules.
+//!
+//! Construction cost decides whether rebuild-on-update is viable, so this is
+//! measured separately from lookups. The largest sizes take minutes per
+//! algorithm; use a filter such as `cargo bench --bench build -- HyperSplit`
+//! to run a subset.
+//!
+//! `TSS hot bucket` builds rule sets whose rules all land in the same hash
+//! bucket, the case where maintaining bucket order per insert would make
+//! construction quadratic; build time should grow linearly with the rules.
+
+use criterion::measurement::WallTime;
+use criterion::{
+    black_box, criterion_group, criterion_main, BenchmarkGroup, BenchmarkId, Criterion,
+};
+use cutsplit::classifier::Classifier;
+use cutsplit::cutsplit::classifier::CutSplitClassifier;
+use cutsplit::hicuts::classifier::HiCutsClassifier;
+use cutsplit::hypersplit::classifier::HyperSplitClassifier;
+use cutsplit::linear::LinearClassifier;
+use cutsplit::partitionsort::classifier::PartitionSortClassifier;
+use cutsplit::rule::{Range, Rule};
+use cutsplit::simulation::Simulation;
+use cutsplit::tss::classifier::TSSClassifier;
+use std::time::Duration;
+
+fn bench_build<C: Classifier>(
+    group: &mut BenchmarkGroup<WallTime>,
+    name: &str,
+    n_rules: usize,
+    rules: &[Rule],
+) {
+    group.bench_with_input(BenchmarkId::new(name, n_rules), rules, |b, rules| {
+        b.iter(|| black_box(C::build(black_box(rules))))
+    });
+}
+
+fn benchmark_build(c: &mut Criterion) {
+    let rule_counts = [100, 300, 1000, 3000, 10000, 30000, 100000];
+    let mut sim = Simulation::new(42); // Same seed as the classification bench
+
+    let mut group = c.benchmark_group("Build");
+    // Builds are slow at the top end; keep the minimum number of samples.
+    group.sample_size(10);
+    group.measurement_time(Duration::from_secs(10));
+
+    for &n_rules in &rule_counts {
+        let rules = sim.generate_rules(n_rules);
+
+        bench_build::<LinearClassifier>(&mut group, "Linear", n_rules, &rules);
+        bench_build::<CutSplitClassifier>(&mut group, "CutSplit", n_rules, &rules);
+        bench_build::<HiCutsClassifier>(&mut group, "HiCuts", n_rules, &rules);
+        bench_build::<HyperSplitClassifier>(&mut group, "HyperSplit", n_rules, &rules);
+        bench_build::<TSSClassifier>(&mut group, "TSS", n_rules, &rules);
+        bench_build::<PartitionSortClassifier>(&mut group, "PartitionSort", n_rules, &rules);
+    }
+    group.finish();
+}
+
+/// `n` rules that TupleMerge puts in one TSS bucket: the first takes
+/// destination ports 1024-1039, the others one port each in that block.
+fn hot_bucket_rules(sim: &mut Simulation, n: usize) -> Vec<Rule> {
+    let base = sim.generate_rules(0).remove(0);
+    (0..n)
+        .map(|i| {
+            let mut rule = base.clone();
+            rule.id = i as u32;
+            rule.priority = i as u32;
+            rule.src_ip = Range::new(0x0a00_0000, 0x0aff_ffff);
+            rule.dst_ip = Range::new(0xc0a8_0000, 0xc0a8_ffff);
+            rule.dst_port = if i == 0 {
+                Range::new(1024, 1039)
+            } else {
+                Range::exact(1024 + (i % 16) as u16)
+            };
+            rule
+        })
+        .rev()
+        .collect()
+}
+
+fn benchmark_tss_hot_bucket(c: &mut Criterion) {
+    let rule_counts = [1000, 3000, 10000, 30000];
+    let mut sim = Simulation::new(42);
+
+    let mut group = c.benchmark_group("TSS hot bucket");
+    group.sample_size(10);
+
+    for &n_rules in &rule_counts {
+        let rules = hot_bucket_rules(&mut sim, n_rules);
+        bench_build::<TSSClassifier>(&mut group, "TSS", n_rules, &rules);
+    }
+    group.finish();
+}
+
+criterion_group!(benches, benchmark_build, benchmark_tss_hot_bucket);
+criterion_main!(benches);
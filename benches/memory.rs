@@ -0,0 +1,92 @@
+//! Memory consumption of every classifier across rule counts.
+//!
+//! A counting global allocator tracks live heap bytes; for each build we
+//! record the peak reached during construction and the bytes still held by the
+//! finished classifier. Output is CSV on stdout:
+//!
+//! ```text
+//! cargo bench --bench memory > memory.csv
+//! ```
+
+use cutsplit::classifier::Classifier;
+use cutsplit::compact::CompactTree;
+use cutsplit::cutsplit::classifier::CutSplitClassifier;
+use cutsplit::hicuts::classifier::HiCutsClassifier;
+use cutsplit::hypersplit::classifier::HyperSplitClassifier;
+use cutsplit::linear::LinearClassifier;
+use cutsplit::partitionsort::classifier::PartitionSortClassifier;
+use cutsplit::rule::Rule;
+use cutsplit::simulation::Simulation;
+use cutsplit::tss::classifier::TSSClassifier;
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+/// System allocator that tracks current and peak live bytes.
+struct CountingAlloc;
+
+static CURRENT: AtomicUsize = AtomicUsize::new(0);
+static PEAK: AtomicUsize = AtomicUsize::new(0);
+
+unsafe impl GlobalAlloc for CountingAlloc {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        let ptr = System.alloc(layout);
+        if !ptr.is_null() {
+            let now = CURRENT.fetch_add(layout.size(), Ordering::Relaxed) + layout.size();
+            PEAK.fetch_max(now, Ordering::Relaxed);
+        }
+        ptr
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout);
+        CURRENT.fetch_sub(layout.size(), Ordering::Relaxed);
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        let new = System.realloc(ptr, layout, new_size);
+        if !new.is_null() {
+            if new_size > layout.size() {
+                let grow = new_size - layout.size();
+                let now = CURRENT.fetch_add(grow, Ordering::Relaxed) + grow;
+                PEAK.fetch_max(now, Ordering::Relaxed);
+            } else {
+                CURRENT.fetch_sub(layout.size() - new_size, Ordering::Relaxed);
+            }
+        }
+        new
+    }
+}
+
+#[global_allocator]
+static GLOBAL: CountingAlloc = CountingAlloc;
+
+/// Build `C` over `rules` and print `name,rules,peak_bytes,resident_bytes`.
+fn measure<C: Classifier>(name: &str, n_rules: usize, rules: &[Rule]) {
+    let baseline = CURRENT.load(Ordering::Relaxed);
+    PEAK.store(baseline, Ordering::Relaxed);
+
+    let classifier = C::build(rules);
+    let peak = PEAK.load(Ordering::Relaxed) - baseline;
+    let resident = CURRENT.load(Ordering::Relaxed).saturating_sub(baseline);
+    drop(classifier);
+
+    println!("{},{},{},{}", name, n_rules, peak, resident);
+}
+
+fn main() {
+    let rule_counts = [100, 1000, 5000, 10000, 20000];
+    let mut sim = Simulation::new(42); // Same seed as the classification bench
+
+    println!("algorithm,rules,peak_bytes,resident_bytes");
+    for &n_rules in &rule_counts {
+        let rules = sim.generate_rules(n_rules);
+
+        measure::<LinearClassifier>("Linear", n_rules, &rules);
+        measure::<CutSplitClassifier>("CutSplit", n_rules, &rules);
+        measure::<HiCutsClassifier>("HiCuts", n_rules, &rules);
+        measure::<HyperSplitClassifier>("HyperSplit", n_rules, &rules);
+        measure::<CompactTree>("HyperSplit (compact)", n_rules, &rules);
+        measure::<TSSClassifier>("TSS", n_rules, &rules);
+        measure::<PartitionSortClassifier>("PartitionSort", n_rules, &rules);
+    }
+}
@@ -0,0 +1,165 @@
+//! Differential fuzzing of dynamic classifiers.
+//!
+//! The input is decoded into a sequence of inserts, removes, modifications and
+//! classifications applied to every dynamic classifier (TSS both updated in
+//! place and rebuilt); each verdict is compared with a naive scan of a
+//! reference rule list.
+//!
+//! ```text
+//! cargo +nightly fuzz run dynamic_updates
+//! ```
+
+#![no_main]
+
+use cutsplit::classifier::Classifier;
+use cutsplit::dimension::Dimension;
+use cutsplit::linear::LinearClassifier;
+use cutsplit::packet::FiveTuple;
+use cutsplit::partitionsort::classifier::PartitionSortClassifier;
+use cutsplit::rule::{Action, Range, Rule};
+use cutsplit::tss::classifier::TSSClassifier;
+use cutsplit::update::{DynamicClassifier, Rebuilding, RuleChange};
+use libfuzzer_sys::fuzz_target;
+
+/// Byte cursor yielding zeros once the input is exhausted.
+struct Input<'a> {
+    bytes: &'a [u8],
+}
+
+impl Input<'_> {
+    fn u8(&mut self) -> u8 {
+        match self.bytes.split_first() {
+            Some((&b, rest)) => {
+                self.bytes = rest;
+                b
+            }
+            None => 0,
+        }
+    }
+
+    fn u16(&mut self) -> u16 {
+        u16::from_le_bytes([self.u8(), self.u8()])
+    }
+
+    fn u32(&mut self) -> u32 {
+        u32::from_le_bytes([self.u8(), self.u8(), self.u8(), self.u8()])
+    }
+
+    fn range_u32(&mut self) -> Range<u32> {
+        let (a, b) = (self.u32(), self.u32());
+        Range::new(a.min(b), a.max(b))
+    }
+
+    fn range_u16(&mut self) -> Range<u16> {
+        let (a, b) = (self.u16(), self.u16());
+        Range::new(a.min(b), a.max(b))
+    }
+
+    fn range_u8(&mut self) -> Range<u8> {
+        let (a, b) = (self.u8(), self.u8());
+        Range::new(a.min(b), a.max(b))
+    }
+
+    fn rule(&mut self, id: u32, priority: u32) -> Rule {
+        let mut rule = Rule {
+            id,
+            priority,
+            src_ip: self.range_u32(),
+            dst_ip: self.range_u32(),
+            src_port: self.range_u16(),
+            dst_port: self.range_u16(),
+            proto: self.range_u8(),
+            zone: {
+                let zone = self.range_u8();
+                Range::new(zone.min as u32, zone.max as u32)
+            },
+            bidirectional: self.u8() & 1 == 1,
+            field_sets: None,
+            action: if self.u8() & 1 == 0 {
+                Action::Permit
+            } else {
+                Action::Deny
+            },
+        };
+        // Negated fields, possibly over their whole domain (no plain copy).
+        if let Some(&dim) = Dimension::ALL.get((self.u8() % 16) as usize) {
+            rule.negate(dim);
+        }
+        rule
+    }
+
+    fn packet(&mut self) -> FiveTuple {
+        FiveTuple {
+            src_ip: self.u32(),
+            dst_ip: self.u32(),
+            src_port: self.u16(),
+            dst_port: self.u16(),
+            proto: self.u8(),
+            zone: self.u8() as u32,
+        }
+    }
+}
+
+fn expected(reference: &[Rule], packet: &FiveTuple) -> Option<u32> {
+    reference
+        .iter()
+        .filter(|r| r.matches(packet))
+        .min_by_key(|r| r.priority)
+        .map(|r| r.id)
+}
+
+fn check<D: DynamicClassifier>(classifier: &D, reference: &[Rule], packet: &FiveTuple) {
+    assert_eq!(
+        classifier.classify_rule(packet).map(|r| r.id),
+        expected(reference, packet)
+    );
+}
+
+fuzz_target!(|data: &[u8]| {
+    let mut input = Input { bytes: data };
+    let mut reference: Vec<Rule> = Vec::new();
+    let mut linear = LinearClassifier::build(&reference);
+    let mut tss = TSSClassifier::build(&reference);
+    let mut rebuilt_tss = Rebuilding::<TSSClassifier>::build(&reference);
+    let mut ps = Rebuilding::<PartitionSortClassifier>::build(&reference);
+    let mut next_id = 0u32;
+
+    // Bound the work per input: every change rebuilds the tree-based classifiers.
+    for _ in 0..64 {
+        if input.bytes.is_empty() {
+            break;
+        }
+        let change = match input.u8() % 4 {
+            0 => {
+                // Priorities are the id, so they are unique.
+                let rule = input.rule(next_id, next_id);
+                next_id += 1;
+                RuleChange::Insert(rule)
+            }
+            1 if !reference.is_empty() => {
+                let idx = input.u8() as usize % reference.len();
+                RuleChange::Remove(reference[idx].clone())
+            }
+            2 if !reference.is_empty() => {
+                let idx = input.u8() as usize % reference.len();
+                let before = reference[idx].clone();
+                let after = input.rule(before.id, before.priority);
+                RuleChange::Modify { before, after }
+            }
+            _ => {
+                let packet = input.packet();
+                check(&linear, &reference, &packet);
+                check(&tss, &reference, &packet);
+                check(&rebuilt_tss, &reference, &packet);
+                check(&ps, &reference, &packet);
+                continue;
+            }
+        };
+
+        assert!(change.apply(&mut reference));
+        assert!(linear.apply(&change));
+        assert!(tss.apply(&change));
+        assert!(rebuilt_tss.apply(&change));
+        assert!(ps.apply(&change));
+    }
+});
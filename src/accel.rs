@@ -0,0 +1,426 @@
+//! Flat export of a decision tree and packet batches for accelerators.
+//!
+//! GPUs and FPGAs cannot chase boxed nodes or run the bit-vector leaf index.
+//! `DeviceImage` re-encodes a `CompactTree` as three arrays of fixed-size,
+//! index-linked `#[repr(C)]` records (nodes, leaf entries, rules) with every
+//! leaf a plain priority-ordered list, and `PacketBatch` packs packets as a
+//! structure of arrays for coalesced loads. `to_bytes` serializes both to a
+//! little-endian blob to copy to device memory.
+//!
+//! A kernel walks the nodes from index 0: an internal node sends the packet
+//! to `left` when its field value is below `value`, else to `right`; a leaf
+//! scans `left` entries from `value` and returns the first rule that
+//! matches, or `NO_MATCH`. `DeviceImage::lookup` is this algorithm on the
+//! host, and `verify` checks an accelerator's results against the CPU
+//! classifier.
+//!
+//! Images carry the fingerprint of the rules the tree was built from, so a
+//! loader can refuse an image of an outdated policy
+//! (`DeviceImage::from_bytes_checked`).
+
+use crate::classifier::Classifier;
+use crate::compact::{CompactTree, INDEXED_LEAF, LEAF};
+use crate::dimension::Dimension;
+use crate::fingerprint::{Fingerprint, LoadError};
+use crate::packet::FiveTuple;
+use crate::rule::{Action, Rule};
+use crate::update::audit::{DecodeError, Reader};
+use alloc::vec::Vec;
+
+/// Result of a lookup that matched no rule.
+pub const NO_MATCH: u32 = u32::MAX;
+
+/// `DeviceNode::kind` of a leaf; internal nodes hold a dimension index.
+pub const DEVICE_LEAF: u32 = 0xFF;
+
+const MAGIC: &[u8; 4] = b"CSDI";
+/// Current format version. Version 1 predates fingerprints.
+const VERSION: u8 = 2;
+
+/// A tree node.
+///
+/// | `kind`          | `value`               | `left`        | `right`        |
+/// |-----------------|-----------------------|---------------|----------------|
+/// | dimension index | cut value             | child `< cut` | child `>= cut` |
+/// | `DEVICE_LEAF`   | offset in `leaf_rules`| entry count   | unused         |
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DeviceNode {
+    pub kind: u32,
+    pub value: u32,
+    pub left: u32,
+    pub right: u32,
+}
+
+/// A rule as inclusive bounds per field, in `Dimension::ALL` order.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DeviceRule {
+    pub min: [u32; Dimension::COUNT],
+    pub max: [u32; Dimension::COUNT],
+    pub zone_min: u32,
+    pub zone_max: u32,
+    /// Id of the rule it was exported from.
+    pub rule_id: u32,
+    /// 0 = permit, 1 = deny.
+    pub action: u32,
+}
+
+impl DeviceRule {
+    fn new(rule: &Rule) -> Self {
+        // Trees only hold plain rules.
+        debug_assert!(rule.is_plain());
+        let ranges = Dimension::ALL.map(|dim| dim.rule_range(rule));
+        Self {
+            min: ranges.map(|r| r.min),
+            max: ranges.map(|r| r.max),
+            zone_min: rule.zone.min,
+            zone_max: rule.zone.max,
+            rule_id: rule.id,
+            action: match rule.action {
+                Action::Permit => 0,
+                Action::Deny => 1,
+            },
+        }
+    }
+
+    fn matches(&self, values: &[u32; Dimension::COUNT], zone: u32) -> bool {
+        (0..Dimension::COUNT).all(|k| self.min[k] <= values[k] && values[k] <= self.max[k])
+            && self.zone_min <= zone
+            && zone <= self.zone_max
+    }
+}
+
+/// Index-based tree layout for accelerators.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DeviceImage {
+    /// Nodes; the root is at index 0 (empty for a tree without rules).
+    pub nodes: Vec<DeviceNode>,
+    /// Concatenated leaf contents, as indices into `rules`.
+    pub leaf_rules: Vec<u32>,
+    pub rules: Vec<DeviceRule>,
+    /// Fingerprint of the rules the tree was built from.
+    pub fingerprint: Option<Fingerprint>,
+}
+
+impl DeviceImage {
+    /// Export `tree`, flattening indexed leaves into plain lists.
+    pub fn new(tree: &CompactTree) -> Self {
+        let mut image = DeviceImage {
+            nodes: Vec::with_capacity(tree.nodes.len()),
+            leaf_rules: Vec::with_capacity(tree.leaf_rules.len()),
+            rules: tree.rules.iter().map(DeviceRule::new).collect(),
+            fingerprint: tree.fingerprint(),
+        };
+        for node in &tree.nodes {
+            let exported = match node.kind {
+                LEAF => {
+                    let start = node.value as usize;
+                    let offset = image.leaf_rules.len() as u32;
+                    image
+                        .leaf_rules
+                        .extend_from_slice(&tree.leaf_rules[start..start + node.left as usize]);
+                    DeviceNode {
+                        kind: DEVICE_LEAF,
+                        value: offset,
+                        left: node.left,
+                        right: 0,
+                    }
+                }
+                INDEXED_LEAF => {
+                    // Index rules are already plain and in priority order.
+                    let rules = tree.indexes[node.value as usize].rules();
+                    let offset = image.leaf_rules.len() as u32;
+                    for rule in rules {
+                        image.leaf_rules.push(image.rules.len() as u32);
+                        image.rules.push(DeviceRule::new(rule));
+                    }
+                    DeviceNode {
+                        kind: DEVICE_LEAF,
+                        value: offset,
+                        left: image.leaf_rules.len() as u32 - offset,
+                        right: 0,
+                    }
+                }
+                dim => DeviceNode {
+                    kind: dim as u32,
+                    value: node.value,
+                    left: node.left,
+                    right: node.right,
+                },
+            };
+            image.nodes.push(exported);
+        }
+        image
+    }
+
+    /// Index in `rules` of the rule matching packet `i` of `batch`, or
+    /// `NO_MATCH`: the reference for accelerator kernels.
+    pub fn lookup(&self, batch: &PacketBatch, i: usize) -> u32 {
+        let values = batch.values(i);
+        let zone = batch.zone[i];
+        let Some(mut node) = self.nodes.first() else {
+            return NO_MATCH;
+        };
+        while node.kind != DEVICE_LEAF {
+            let child = if values[node.kind as usize] < node.value {
+                node.left
+            } else {
+                node.right
+            };
+            node = &self.nodes[child as usize];
+        }
+        let start = node.value as usize;
+        self.leaf_rules[start..start + node.left as usize]
+            .iter()
+            .copied()
+            .find(|&idx| self.rules[idx as usize].matches(&values, zone))
+            .unwrap_or(NO_MATCH)
+    }
+
+    /// `lookup` of every packet of `batch`.
+    pub fn run(&self, batch: &PacketBatch) -> Vec<u32> {
+        (0..batch.len()).map(|i| self.lookup(batch, i)).collect()
+    }
+
+    /// Id of the rule behind a lookup result (`None` for `NO_MATCH`).
+    pub fn rule_id(&self, result: u32) -> Option<u32> {
+        self.rules.get(result as usize).map(|r| r.rule_id)
+    }
+
+    /// Serialize to a versioned little-endian blob: magic, version, the
+    /// fingerprint (a presence byte, then the `u64` if present), the three
+    /// array lengths as `u32`, then the arrays, each record as its `u32`
+    /// fields in declaration order.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut out = Vec::new();
+        out.extend_from_slice(MAGIC);
+        out.push(VERSION);
+        match self.fingerprint {
+            Some(fingerprint) => {
+                out.push(1);
+                out.extend_from_slice(&fingerprint.0.to_le_bytes());
+            }
+            None => out.push(0),
+        }
+        for len in [self.nodes.len(), self.leaf_rules.len(), self.rules.len()] {
+            out.extend_from_slice(&(len as u32).to_le_bytes());
+        }
+        let mut put = |v: u32| out.extend_from_slice(&v.to_le_bytes());
+        for n in &self.nodes {
+            [n.kind, n.value, n.left, n.right]
+                .into_iter()
+                .for_each(&mut put);
+        }
+        self.leaf_rules.iter().copied().for_each(&mut put);
+        for r in &self.rules {
+            r.min.into_iter().chain(r.max).for_each(&mut put);
+            [r.zone_min, r.zone_max, r.rule_id, r.action]
+                .into_iter()
+                .for_each(&mut put);
+        }
+        out
+    }
+
+    /// Decode an image produced by [`DeviceImage::to_bytes`], rejecting as
+    /// `Corrupt` any node graph `lookup` could not walk.
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, DecodeError> {
+        let mut r = Reader { bytes, pos: 0 };
+        if r.take(4)? != MAGIC {
+            return Err(DecodeError::BadMagic);
+        }
+        let version = r.u8()?;
+        if !(1..=VERSION).contains(&version) {
+            return Err(DecodeError::UnsupportedVersion(version));
+        }
+        let fingerprint = if version >= 2 && r.u8()? != 0 {
+            Some(Fingerprint(r.u64()?))
+        } else {
+            None
+        };
+        let (nodes, leaf_rules, rules) = (r.u32()?, r.u32()?, r.u32()?);
+        // Grow as records are read so corrupt lengths cannot force a huge
+        // allocation.
+        let mut image = DeviceImage {
+            nodes: Vec::new(),
+            leaf_rules: Vec::new(),
+            rules: Vec::new(),
+            fingerprint,
+        };
+        for _ in 0..nodes {
+            image.nodes.push(DeviceNode {
+                kind: r.u32()?,
+                value: r.u32()?,
+                left: r.u32()?,
+                right: r.u32()?,
+            });
+        }
+        for _ in 0..leaf_rules {
+            image.leaf_rules.push(r.u32()?);
+        }
+        for _ in 0..rules {
+            let mut bounds = [0u32; 2 * Dimension::COUNT];
+            for b in &mut bounds {
+                *b = r.u32()?;
+            }
+            let (min, max) = bounds.split_at(Dimension::COUNT);
+            image.rules.push(DeviceRule {
+                min: min.try_into().unwrap(),
+                max: max.try_into().unwrap(),
+                zone_min: r.u32()?,
+                zone_max: r.u32()?,
+                rule_id: r.u32()?,
+                action: r.u32()?,
+            });
+        }
+        image.validate()?;
+        Ok(image)
+    }
+
+    /// Check that every index stays in bounds and children come after their
+    /// parent (so lookups terminate).
+    fn validate(&self) -> Result<(), DecodeError> {
+        let nodes = self.nodes.len();
+        for (i, node) in self.nodes.iter().enumerate() {
+            let valid = match node.kind {
+                DEVICE_LEAF => (node.value as usize)
+                    .checked_add(node.left as usize)
+                    .is_some_and(|end| end <= self.leaf_rules.len()),
+                dim if (dim as usize) < Dimension::COUNT => [node.left, node.right]
+                    .iter()
+                    .all(|&child| i < child as usize && (child as usize) < nodes),
+                _ => false,
+            };
+            if !valid {
+                return Err(DecodeError::Corrupt);
+            }
+        }
+        if self
+            .leaf_rules
+            .iter()
+            .any(|&idx| idx as usize >= self.rules.len())
+        {
+            return Err(DecodeError::Corrupt);
+        }
+        Ok(())
+    }
+
+    /// Decode an image, refusing it unless it was built from the rules with
+    /// fingerprint `expected`.
+    pub fn from_bytes_checked(bytes: &[u8], expected: Fingerprint) -> Result<Self, LoadError> {
+        let image = Self::from_bytes(bytes)?;
+        Fingerprint::check(image.fingerprint, expected)?;
+        Ok(image)
+    }
+}
+
+/// Packets as a structure of arrays, one array per field.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct PacketBatch {
+    pub src_ip: Vec<u32>,
+    pub dst_ip: Vec<u32>,
+    /// Source port in the high half, destination port in the low half.
+    pub ports: Vec<u32>,
+    pub proto: Vec<u8>,
+    pub zone: Vec<u32>,
+}
+
+impl PacketBatch {
+    /// Pack `packets`.
+    pub fn pack(packets: &[FiveTuple]) -> Self {
+        Self {
+            src_ip: packets.iter().map(|p| p.src_ip).collect(),
+            dst_ip: packets.iter().map(|p| p.dst_ip).collect(),
+            ports: packets
+                .iter()
+                .map(|p| (p.src_port as u32) << 16 | p.dst_port as u32)
+                .collect(),
+            proto: packets.iter().map(|p| p.proto).collect(),
+            zone: packets.iter().map(|p| p.zone).collect(),
+        }
+    }
+
+    /// Number of packets.
+    pub fn len(&self) -> usize {
+        self.src_ip.len()
+    }
+
+    /// Returns true if the batch holds no packet.
+    pub fn is_empty(&self) -> bool {
+        self.src_ip.is_empty()
+    }
+
+    /// Packet `i`.
+    pub fn get(&self, i: usize) -> FiveTuple {
+        FiveTuple {
+            src_ip: self.src_ip[i],
+            dst_ip: self.dst_ip[i],
+            src_port: (self.ports[i] >> 16) as u16,
+            dst_port: self.ports[i] as u16,
+            proto: self.proto[i],
+            zone: self.zone[i],
+        }
+    }
+
+    /// Field values of packet `i`, in `Dimension::ALL` order.
+    fn values(&self, i: usize) -> [u32; Dimension::COUNT] {
+        [
+            self.src_ip[i],
+            self.dst_ip[i],
+            self.ports[i] >> 16,
+            self.ports[i] & 0xFFFF,
+            self.proto[i] as u32,
+        ]
+    }
+
+    /// Serialize to a little-endian blob: the packet count as `u32`, then
+    /// the arrays in declaration order (`proto` one byte per packet).
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut out = Vec::with_capacity(4 + self.len() * 17);
+        out.extend_from_slice(&(self.len() as u32).to_le_bytes());
+        for array in [&self.src_ip, &self.dst_ip, &self.ports] {
+            array
+                .iter()
+                .for_each(|v| out.extend_from_slice(&v.to_le_bytes()));
+        }
+        out.extend_from_slice(&self.proto);
+        self.zone
+            .iter()
+            .for_each(|v| out.extend_from_slice(&v.to_le_bytes()));
+        out
+    }
+}
+
+/// A packet the accelerator classified differently from the CPU.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Mismatch {
+    /// Index of the packet in the batch.
+    pub packet: usize,
+    /// Rule id the CPU classifier matched.
+    pub expected: Option<u32>,
+    /// Rule id behind the accelerator's result.
+    pub actual: Option<u32>,
+}
+
+/// Compare accelerator `results` for `batch` (indices into `image.rules`)
+/// with `cpu`, by matched rule id. Results missing from a short slice count
+/// as mismatches.
+pub fn verify<C: Classifier>(
+    cpu: &C,
+    image: &DeviceImage,
+    batch: &PacketBatch,
+    results: &[u32],
+) -> Vec<Mismatch> {
+    (0..batch.len())
+        .filter_map(|i| {
+            let expected = cpu.classify_rule(&batch.get(i)).map(|r| r.id);
+            let actual = results.get(i).and_then(|&r| image.rule_id(r));
+            let missing = i >= results.len();
+            (missing || expected != actual).then_some(Mismatch {
+                packet: i,
+                expected,
+                actual,
+            })
+        })
+        .collect()
+}
@@ -0,0 +1,328 @@
+//! Named address sets (ipset-style) referenced by rules.
+//!
+//! An `AddressSets` registry holds sets of IPv4 prefixes under numeric ids.
+//! All sets share one longest-prefix-match trie whose entries list the sets
+//! each prefix belongs to, so a membership test is a single trie walk no
+//! matter how many sets exist. Rules point at a set through
+//! `FieldSets::src_set` / `FieldSets::dst_set` instead of carrying its
+//! prefixes, which keeps rule sets small and lets a set change without
+//! touching (or rebuilding) the rules that reference it.
+//!
+//! `SetClassifier` serves a rule set containing such references: rules
+//! without references go through any `Classifier`, while referencing rules
+//! are checked in priority order against the registry, in the same way
+//! `WildcardSplit` serves its sidecar.
+//!
+//! Sets are updated in place, prefix by prefix or with `SetDelta` batches as
+//! delivered by threat-intel feeds: only the shared trie changes, and the next
+//! lookup sees the new content without any rule or classifier being rebuilt.
+
+use crate::classifier::{Classifier, LookupStats};
+use crate::geometry::Region;
+use crate::packet::FiveTuple;
+use crate::prefix::{range_to_prefixes_u32, Prefix};
+use crate::query::{Overlapping, RegionQuery};
+use crate::rule::{Action, Range, Rule};
+use crate::trie::PrefixTrie;
+use alloc::vec::Vec;
+use hashbrown::HashMap;
+
+/// Identifier of an address set.
+pub type SetId = u32;
+
+/// A batch of changes to one address set, e.g. one feed update.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct SetDelta {
+    /// The set being changed (defined by the delta if needed).
+    pub set: SetId,
+    /// Prefixes to add.
+    pub added: Vec<Prefix<u32>>,
+    /// Prefixes to remove (applied before the additions).
+    pub removed: Vec<Prefix<u32>>,
+}
+
+/// Number of prefixes actually changed by a `SetDelta`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct DeltaStats {
+    /// Prefixes that were not in the set and now are.
+    pub added: usize,
+    /// Prefixes that were in the set and no longer are.
+    pub removed: usize,
+}
+
+/// Registry of address sets sharing one LPM trie.
+#[derive(Debug, Clone, Default)]
+pub struct AddressSets {
+    /// Prefix to the (sorted) ids of the sets containing it.
+    trie: PrefixTrie<u32, Vec<SetId>>,
+    /// Number of prefixes in each defined set.
+    sizes: HashMap<SetId, usize>,
+    /// Bumped by every change to the content of a set.
+    generation: u64,
+}
+
+impl AddressSets {
+    /// Create an empty registry.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Define set `id` as exactly `prefixes`, replacing any previous content.
+    pub fn define(&mut self, id: SetId, prefixes: impl IntoIterator<Item = Prefix<u32>>) {
+        self.clear(id);
+        self.sizes.insert(id, 0);
+        for prefix in prefixes {
+            self.insert(id, prefix);
+        }
+    }
+
+    /// Add `prefix` to set `id` (defining the set if needed).
+    ///
+    /// Returns false if the set already held this prefix.
+    pub fn insert(&mut self, id: SetId, prefix: Prefix<u32>) -> bool {
+        let added = match self.trie.get_mut(prefix) {
+            Some(ids) => match ids.binary_search(&id) {
+                Ok(_) => false,
+                Err(pos) => {
+                    ids.insert(pos, id);
+                    true
+                }
+            },
+            None => {
+                self.trie.insert(prefix, alloc::vec![id]);
+                true
+            }
+        };
+        let size = self.sizes.entry(id).or_default();
+        if added {
+            *size += 1;
+            self.generation += 1;
+        }
+        added
+    }
+
+    /// Add every address of `range` to set `id`, as the prefixes covering it.
+    pub fn insert_range(&mut self, id: SetId, range: Range<u32>) {
+        for prefix in range_to_prefixes_u32(range.min, range.max, 32) {
+            self.insert(id, prefix);
+        }
+    }
+
+    /// Remove `prefix` from set `id`. Returns false if the set did not hold it.
+    pub fn remove(&mut self, id: SetId, prefix: Prefix<u32>) -> bool {
+        let Some(ids) = self.trie.get_mut(prefix) else {
+            return false;
+        };
+        let Ok(pos) = ids.binary_search(&id) else {
+            return false;
+        };
+        ids.remove(pos);
+        if ids.is_empty() {
+            self.trie.remove(prefix);
+        }
+        if let Some(size) = self.sizes.get_mut(&id) {
+            *size -= 1;
+        }
+        self.generation += 1;
+        true
+    }
+
+    /// Apply a batch of changes: removals first, then additions.
+    pub fn apply(&mut self, delta: &SetDelta) -> DeltaStats {
+        self.sizes.entry(delta.set).or_default();
+        let removed = delta
+            .removed
+            .iter()
+            .filter(|&&p| self.remove(delta.set, p))
+            .count();
+        let added = delta
+            .added
+            .iter()
+            .filter(|&&p| self.insert(delta.set, p))
+            .count();
+        DeltaStats { added, removed }
+    }
+
+    /// Counter bumped by every change to the content of a set, so callers can
+    /// tell whether anything changed since they last looked.
+    pub fn generation(&self) -> u64 {
+        self.generation
+    }
+
+    /// Remove every prefix of set `id`, keeping it defined (and empty).
+    pub fn clear(&mut self, id: SetId) {
+        if self.sizes.get(&id).is_none_or(|&n| n == 0) {
+            return;
+        }
+        for prefix in self.prefixes(id) {
+            self.remove(id, prefix);
+        }
+    }
+
+    /// Forget set `id` entirely. Returns false if it was not defined.
+    pub fn undefine(&mut self, id: SetId) -> bool {
+        self.clear(id);
+        self.sizes.remove(&id).is_some()
+    }
+
+    /// Returns true if set `id` contains `addr`. Undefined sets are empty.
+    pub fn contains(&self, id: SetId, addr: u32) -> bool {
+        self.trie
+            .matches(addr)
+            .iter()
+            .any(|(_, ids)| ids.binary_search(&id).is_ok())
+    }
+
+    /// Ids of every set containing `addr`, sorted and without duplicates.
+    pub fn memberships(&self, addr: u32) -> Vec<SetId> {
+        let mut out: Vec<SetId> = self
+            .trie
+            .matches(addr)
+            .into_iter()
+            .flat_map(|(_, ids)| ids.iter().copied())
+            .collect();
+        out.sort_unstable();
+        out.dedup();
+        out
+    }
+
+    /// Prefixes of set `id`, in address order.
+    pub fn prefixes(&self, id: SetId) -> Vec<Prefix<u32>> {
+        self.trie
+            .iter()
+            .into_iter()
+            .filter(|(_, ids)| ids.binary_search(&id).is_ok())
+            .map(|(prefix, _)| prefix)
+            .collect()
+    }
+
+    /// Returns true if set `id` is defined.
+    pub fn is_defined(&self, id: SetId) -> bool {
+        self.sizes.contains_key(&id)
+    }
+
+    /// Number of prefixes in set `id` (0 if undefined).
+    pub fn set_len(&self, id: SetId) -> usize {
+        self.sizes.get(&id).copied().unwrap_or(0)
+    }
+
+    /// Number of defined sets.
+    pub fn len(&self) -> usize {
+        self.sizes.len()
+    }
+
+    /// Returns true if no set is defined.
+    pub fn is_empty(&self) -> bool {
+        self.sizes.is_empty()
+    }
+
+    /// Number of distinct prefixes in the shared trie.
+    pub fn prefix_count(&self) -> usize {
+        self.trie.len()
+    }
+}
+
+/// Classifier resolving address-set references against a registry.
+///
+/// Rules without references are served by `C`; referencing rules are kept
+/// whole, in priority order, and checked with `Rule::matches_with`. The higher
+/// priority match of both parts wins.
+pub struct SetClassifier<C> {
+    main: C,
+    /// Rules referencing an address set, sorted by priority.
+    referencing: Vec<Rule>,
+    sets: AddressSets,
+}
+
+impl<C: Classifier> SetClassifier<C> {
+    /// Build over `rules`, resolving their set references against `sets`.
+    pub fn new(rules: &[Rule], sets: AddressSets) -> Self {
+        let (mut referencing, plain): (Vec<Rule>, Vec<Rule>) =
+            rules.iter().cloned().partition(Rule::references_sets);
+        referencing.sort_by_key(|r| r.priority);
+        Self {
+            main: C::build(&plain),
+            referencing,
+            sets,
+        }
+    }
+
+    /// The address sets referenced by the rules.
+    pub fn sets(&self) -> &AddressSets {
+        &self.sets
+    }
+
+    /// Add `prefix` to set `id`, effective from the next lookup.
+    ///
+    /// Only the shared trie changes; no rule is touched and nothing is rebuilt.
+    pub fn insert_prefix(&mut self, id: SetId, prefix: Prefix<u32>) -> bool {
+        self.sets.insert(id, prefix)
+    }
+
+    /// Remove `prefix` from set `id`, effective from the next lookup.
+    pub fn remove_prefix(&mut self, id: SetId, prefix: Prefix<u32>) -> bool {
+        self.sets.remove(id, prefix)
+    }
+
+    /// Apply a batch of set changes, effective from the next lookup.
+    pub fn apply(&mut self, delta: &SetDelta) -> DeltaStats {
+        self.sets.apply(delta)
+    }
+
+    /// Replace the content of set `id`, effective from the next lookup.
+    pub fn define_set(&mut self, id: SetId, prefixes: impl IntoIterator<Item = Prefix<u32>>) {
+        self.sets.define(id, prefixes);
+    }
+
+    /// The classifier holding the rules without set references.
+    pub fn main(&self) -> &C {
+        &self.main
+    }
+
+    /// Number of rules referencing an address set.
+    pub fn referencing(&self) -> usize {
+        self.referencing.len()
+    }
+
+    fn lookup<'a>(&'a self, packet: &FiveTuple, main: Option<&'a Rule>) -> (Option<&'a Rule>, u32) {
+        let limit = main.map_or(u32::MAX, |r| r.priority);
+        let mut compared = 0;
+        let side = self
+            .referencing
+            .iter()
+            .take_while(|r| r.priority < limit)
+            .find(|r| {
+                compared += 1;
+                r.matches_with(packet, &self.sets)
+            });
+        (side.or(main), compared)
+    }
+}
+
+impl<C: Classifier> Classifier for SetClassifier<C> {
+    /// Build with an empty registry: every referenced set is empty.
+    fn build(rules: &[Rule]) -> Self {
+        Self::new(rules, AddressSets::new())
+    }
+
+    fn classify_rule(&self, packet: &FiveTuple) -> Option<&Rule> {
+        self.lookup(packet, self.main.classify_rule(packet)).0
+    }
+
+    fn classify_with_stats(&self, packet: &FiveTuple) -> (Option<Action>, LookupStats) {
+        // Stats-only path: picking the winner needs the main match's priority.
+        let (_, mut stats) = self.main.classify_with_stats(packet);
+        let (rule, compared) = self.lookup(packet, self.main.classify_rule(packet));
+        stats.rules_compared += compared;
+        (rule.map(|r| r.action), stats)
+    }
+}
+
+impl<C: RegionQuery> RegionQuery for SetClassifier<C> {
+    fn rules_overlapping(&self, region: &Region) -> Vec<&Rule> {
+        let mut found = Overlapping::new(region);
+        found.extend(self.main.rules_overlapping(region));
+        found.scan(&self.referencing);
+        found.finish()
+    }
+}
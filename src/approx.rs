@@ -0,0 +1,265 @@
+//! Approximate deny pre-filter.
+//!
+//! In front of a slower stage (a full firewall, IDS, ...) most packets only
+//! need to be told "certainly not denied". `ApproximateClassifier` answers
+//! that from a Bloom filter over the source and destination address blocks
+//! that deny rules cover, and runs the exact classifier only on positives:
+//!
+//! - a packet is denied only if the exact classifier denies it, so there are
+//!   no false denies;
+//! - a packet can be falsely permitted only by a deny rule left out of the
+//!   filter. Deny rules covering more than `max_keys_per_rule` address blocks
+//!   are checked by a small linear sidecar instead, up to `max_broad_rules`
+//!   of them (in priority order); the rest are omitted.
+//!
+//! Packets matching no rule are permitted. `analyze` replays traffic against
+//! the exact classifier and reports the false-permit, fallback and filter
+//! false-positive rates, to size the filter for a policy.
+
+use crate::classifier::Classifier;
+use crate::linear::LinearClassifier;
+use crate::packet::FiveTuple;
+use crate::rule::{expand_rules, Action, Rule};
+use alloc::vec;
+use alloc::vec::Vec;
+
+/// Sizing of an `ApproximateClassifier`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ApproxParams {
+    /// Leading bits of the source and destination addresses forming a filter
+    /// key (0..=32).
+    pub prefix_len: u32,
+    /// Filter bits per inserted key.
+    pub bits_per_key: u32,
+    /// Hash functions of the filter.
+    pub hashes: u32,
+    /// Keys a deny rule may insert before it is treated as broad.
+    pub max_keys_per_rule: u64,
+    /// Broad deny rules checked by the sidecar; further ones are omitted.
+    pub max_broad_rules: usize,
+}
+
+impl Default for ApproxParams {
+    /// /16 keys, 10 bits and 7 hashes per key (about 1% false positives),
+    /// 4096 keys per rule and 16 broad rules.
+    fn default() -> Self {
+        Self {
+            prefix_len: 16,
+            bits_per_key: 10,
+            hashes: 7,
+            max_keys_per_rule: 4096,
+            max_broad_rules: 16,
+        }
+    }
+}
+
+/// Bloom filter over `u64` keys.
+#[derive(Debug, Clone)]
+pub struct BloomFilter {
+    bits: Vec<u64>,
+    hashes: u32,
+}
+
+impl BloomFilter {
+    /// Filter sized for `keys` keys at `bits_per_key` bits each, with
+    /// `hashes` hash functions.
+    pub fn new(keys: usize, bits_per_key: u32, hashes: u32) -> Self {
+        let words = (keys * bits_per_key as usize).div_ceil(64).max(1);
+        Self {
+            bits: vec![0; words],
+            hashes: hashes.max(1),
+        }
+    }
+
+    /// Add `key`.
+    pub fn insert(&mut self, key: u64) {
+        for bit in self.positions(key) {
+            self.bits[bit / 64] |= 1 << (bit % 64);
+        }
+    }
+
+    /// False if `key` was never inserted; true if it was or by collision.
+    pub fn contains(&self, key: u64) -> bool {
+        self.positions(key)
+            .all(|bit| self.bits[bit / 64] & (1 << (bit % 64)) != 0)
+    }
+
+    /// Size of the bit array in bytes.
+    pub fn size_bytes(&self) -> usize {
+        self.bits.len() * 8
+    }
+
+    /// Bit positions of `key` (double hashing).
+    fn positions(&self, key: u64) -> impl Iterator<Item = usize> {
+        let len = self.bits.len() as u64 * 64;
+        let h = mix(key);
+        let (h1, h2) = (h & 0xFFFF_FFFF, (h >> 32) | 1);
+        (0..self.hashes as u64).map(move |i| (h1.wrapping_add(i.wrapping_mul(h2)) % len) as usize)
+    }
+}
+
+/// SplitMix64 finalizer.
+fn mix(mut x: u64) -> u64 {
+    x = (x ^ (x >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+    x = (x ^ (x >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+    x ^ (x >> 31)
+}
+
+/// Error rates of an `ApproximateClassifier` over a packet sample.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct ApproxReport {
+    /// Packets replayed.
+    pub packets: usize,
+    /// Packets sent to the exact classifier.
+    pub fallbacks: usize,
+    /// Packets permitted although the exact classifier denies them.
+    pub false_permits: usize,
+    /// Fallbacks the exact classifier did not deny.
+    pub false_positives: usize,
+}
+
+impl ApproxReport {
+    /// Fraction of packets falsely permitted.
+    pub fn false_permit_rate(&self) -> f64 {
+        ratio(self.false_permits, self.packets)
+    }
+
+    /// Fraction of packets that needed the exact classifier.
+    pub fn fallback_rate(&self) -> f64 {
+        ratio(self.fallbacks, self.packets)
+    }
+
+    /// Fraction of packets sent to the exact classifier for nothing.
+    pub fn false_positive_rate(&self) -> f64 {
+        ratio(self.false_positives, self.packets)
+    }
+}
+
+fn ratio(n: usize, total: usize) -> f64 {
+    if total == 0 {
+        return 0.0;
+    }
+    n as f64 / total as f64
+}
+
+/// Block of `addr` with `shift` low bits dropped (a shift by the full width,
+/// for `prefix_len` 0, leaves a single block).
+fn block_of(addr: u32, shift: u32) -> u64 {
+    addr.checked_shr(shift).unwrap_or(0) as u64
+}
+
+/// Deny pre-filter falling back to an exact classifier on positives.
+pub struct ApproximateClassifier<C> {
+    exact: C,
+    filter: BloomFilter,
+    broad: LinearClassifier,
+    params: ApproxParams,
+    omitted: usize,
+}
+
+impl<C: Classifier> ApproximateClassifier<C> {
+    /// Build over `rules` with the default sizing.
+    pub fn build(rules: &[Rule]) -> Self {
+        Self::build_with(rules, ApproxParams::default(), C::build)
+    }
+
+    /// Build over `rules` with `params`, building the exact classifier with
+    /// `build`.
+    ///
+    /// Panics if `params.prefix_len` is over 32.
+    pub fn build_with(rules: &[Rule], params: ApproxParams, build: impl Fn(&[Rule]) -> C) -> Self {
+        assert!(params.prefix_len <= 32, "prefix_len over 32 bits");
+        let shift = 32 - params.prefix_len;
+        let plain = expand_rules(rules);
+        let mut denies: Vec<&Rule> = plain.iter().filter(|r| r.action == Action::Deny).collect();
+        denies.sort_by_key(|r| r.priority);
+
+        let blocks = |min: u32, max: u32| block_of(min, shift)..=block_of(max, shift);
+        let key_count = |r: &Rule| {
+            let (src, dst) = (
+                blocks(r.src_ip.min, r.src_ip.max),
+                blocks(r.dst_ip.min, r.dst_ip.max),
+            );
+            (src.end() - src.start() + 1).saturating_mul(dst.end() - dst.start() + 1)
+        };
+        let (narrow, broad): (Vec<&Rule>, Vec<&Rule>) = denies
+            .into_iter()
+            .partition(|r| key_count(r) <= params.max_keys_per_rule);
+
+        let keys: u64 = narrow.iter().map(|r| key_count(r)).sum();
+        let mut filter = BloomFilter::new(keys as usize, params.bits_per_key, params.hashes);
+        for rule in &narrow {
+            for src in blocks(rule.src_ip.min, rule.src_ip.max) {
+                for dst in blocks(rule.dst_ip.min, rule.dst_ip.max) {
+                    filter.insert(src << 32 | dst);
+                }
+            }
+        }
+        let kept = broad.len().min(params.max_broad_rules);
+        let sidecar: Vec<Rule> = broad[..kept].iter().map(|&r| r.clone()).collect();
+        Self {
+            exact: build(rules),
+            filter,
+            broad: LinearClassifier::build(&sidecar),
+            params,
+            omitted: broad.len() - kept,
+        }
+    }
+
+    /// The exact classifier.
+    pub fn exact(&self) -> &C {
+        &self.exact
+    }
+
+    /// The sizing the classifier was built with.
+    pub fn params(&self) -> ApproxParams {
+        self.params
+    }
+
+    /// Deny rules neither in the filter nor in the sidecar: the only source
+    /// of false permits.
+    pub fn omitted_rules(&self) -> usize {
+        self.omitted
+    }
+
+    /// The Bloom filter.
+    pub fn filter(&self) -> &BloomFilter {
+        &self.filter
+    }
+
+    /// True if a deny rule may match `packet`, i.e. the exact classifier is
+    /// needed.
+    pub fn prefilter(&self, packet: &FiveTuple) -> bool {
+        let shift = 32 - self.params.prefix_len;
+        let key = block_of(packet.src_ip, shift) << 32 | block_of(packet.dst_ip, shift);
+        self.filter.contains(key) || self.broad.classify_rule(packet).is_some()
+    }
+
+    /// `Deny` if the exact classifier denies `packet` and the filter caught
+    /// it, `Permit` otherwise.
+    pub fn classify(&self, packet: &FiveTuple) -> Action {
+        if self.prefilter(packet) && self.exact.classify(packet) == Some(Action::Deny) {
+            Action::Deny
+        } else {
+            Action::Permit
+        }
+    }
+
+    /// Replay `packets` through the filter and the exact classifier.
+    pub fn analyze(&self, packets: &[FiveTuple]) -> ApproxReport {
+        let mut report = ApproxReport {
+            packets: packets.len(),
+            ..ApproxReport::default()
+        };
+        for packet in packets {
+            let denied = self.exact.classify(packet) == Some(Action::Deny);
+            if self.prefilter(packet) {
+                report.fallbacks += 1;
+                report.false_positives += usize::from(!denied);
+            } else {
+                report.false_permits += usize::from(denied);
+            }
+        }
+        report
+    }
+}
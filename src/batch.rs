@@ -0,0 +1,104 @@
+//! Batched tree walks behind `Classifier::classify_batch`.
+//!
+//! Classifying packets one at a time walks the tree from the root for each
+//! of them, paying the cache misses of every node on every path. A batch walk
+//! moves the whole batch down together instead: each node splits the indices
+//! of the packets that reached it by child, so a node is loaded once per batch
+//! rather than once per packet, and a leaf scans its rules for all the packets
+//! that reached it while the rules are in cache.
+
+use crate::compact::{BinaryNode, NodeView};
+use crate::leaf::BitVectorIndex;
+use crate::packet::FiveTuple;
+use crate::rule::{Action, Rule};
+use alloc::vec::Vec;
+
+/// Panics unless there is one output slot per packet.
+pub(crate) fn check_lengths(packets: &[FiveTuple], out: &[Option<Action>]) {
+    assert_eq!(
+        packets.len(),
+        out.len(),
+        "classify_batch needs one output slot per packet"
+    );
+}
+
+/// Indices of every packet of the batch, the starting group of a walk.
+pub(crate) fn all(packets: &[FiveTuple]) -> Vec<u32> {
+    (0..packets.len() as u32).collect()
+}
+
+/// Reorder `group` so the packets `left` accepts come first, and return how
+/// many there are.
+pub(crate) fn split(group: &mut [u32], mut left: impl FnMut(u32) -> bool) -> usize {
+    let mut mid = 0;
+    for i in 0..group.len() {
+        if left(group[i]) {
+            group.swap(mid, i);
+            mid += 1;
+        }
+    }
+    mid
+}
+
+/// Resolve the packets of `group` against a leaf's priority-ordered rules.
+pub(crate) fn scan<'a>(
+    rules: impl Iterator<Item = &'a Rule> + Clone,
+    group: &[u32],
+    packets: &[FiveTuple],
+    out: &mut [Option<Action>],
+) {
+    for &i in group {
+        let packet = &packets[i as usize];
+        out[i as usize] = rules.clone().find(|r| r.matches(packet)).map(|r| r.action);
+    }
+}
+
+/// Resolve the packets of `group` against an indexed leaf.
+pub(crate) fn scan_index(
+    index: &BitVectorIndex,
+    group: &[u32],
+    packets: &[FiveTuple],
+    out: &mut [Option<Action>],
+) {
+    let mut stats = Default::default();
+    for &i in group {
+        out[i as usize] = index
+            .lookup(&packets[i as usize], &mut stats)
+            .map(|r| r.action);
+    }
+}
+
+/// Batch walk of a binary cut tree (CutSplit, HyperSplit).
+pub(crate) fn walk_binary<N: BinaryNode>(
+    root: &N,
+    packets: &[FiveTuple],
+    out: &mut [Option<Action>],
+) {
+    check_lengths(packets, out);
+    let mut order = all(packets);
+    let mut stack = alloc::vec![(root, 0, order.len())];
+    while let Some((node, start, end)) = stack.pop() {
+        let group = &mut order[start..end];
+        match node.view() {
+            NodeView::Internal {
+                dimension,
+                cut,
+                left,
+                right,
+            } => {
+                let mid = start
+                    + split(group, |i| {
+                        dimension.packet_value(&packets[i as usize]) < cut
+                    });
+                if mid < end {
+                    stack.push((right, mid, end));
+                }
+                if start < mid {
+                    stack.push((left, start, mid));
+                }
+            }
+            NodeView::Leaf(rules) => scan(rules.iter(), group, packets, out),
+            NodeView::IndexedLeaf(index) => scan_index(index, group, packets, out),
+        }
+    }
+}
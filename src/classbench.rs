@@ -0,0 +1,199 @@
+//! ClassBench filter and trace formats.
+//!
+//! Filters are one rule per line, in priority order, with tab-separated fields:
+//!
+//! ```text
+//! @192.168.1.0/24  10.0.0.0/8  0 : 65535  80 : 80  0x06/0xFF
+//! ```
+//!
+//! (source prefix, destination prefix, source port range, destination port
+//! range, protocol/mask). Standard ClassBench files may carry a trailing
+//! flags field (`0x0000/0x0200`), which is ignored. Since ClassBench has no
+//! actions, a trailing `permit` or `deny` token is accepted; the default is
+//! `permit`. Rule ids and priorities are the 0-based line index.
+//!
+//! Traces are one packet per line as whitespace-separated decimal numbers:
+//! `src_ip dst_ip src_port dst_port proto expected`, where `expected` is the
+//! id of the matching rule or `-1` when no rule matches. Any further columns
+//! are ignored.
+//!
+//! Blank lines and lines starting with `#` are skipped in both formats.
+
+use crate::packet::FiveTuple;
+use crate::prefix::Prefix;
+use crate::rule::{Action, Range, Rule, ANY_ZONE};
+use alloc::vec::Vec;
+use core::fmt;
+
+/// Error while parsing a ClassBench file.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ParseError {
+    /// 1-based line number.
+    pub line: usize,
+    pub kind: ParseErrorKind,
+}
+
+/// What was wrong with a line.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ParseErrorKind {
+    /// Filter line does not start with `@`.
+    MissingAt,
+    /// A field is missing.
+    MissingField,
+    /// Malformed IPv4 prefix.
+    BadPrefix,
+    /// Malformed port range.
+    BadPortRange,
+    /// Malformed protocol/mask, or a mask other than 0x00 or 0xFF.
+    BadProtocol,
+    /// Unknown action token.
+    BadAction,
+    /// Malformed number in a trace line.
+    BadNumber,
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "line {}: {:?}", self.line, self.kind)
+    }
+}
+
+/// A trace entry: the packet and the id of the rule expected to match it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TraceEntry {
+    pub packet: FiveTuple,
+    pub expected: Option<u32>,
+}
+
+/// Parse a ClassBench filter file.
+pub fn parse_rules(input: &str) -> Result<Vec<Rule>, ParseError> {
+    let mut rules = Vec::new();
+    for (line_no, line) in lines(input) {
+        let err = |kind| ParseError {
+            line: line_no,
+            kind,
+        };
+        let line = line
+            .strip_prefix('@')
+            .ok_or(err(ParseErrorKind::MissingAt))?;
+        let mut fields = line.split('\t').map(str::trim).filter(|f| !f.is_empty());
+        let mut next = || fields.next().ok_or(err(ParseErrorKind::MissingField));
+
+        let src_ip = parse_prefix(next()?).ok_or(err(ParseErrorKind::BadPrefix))?;
+        let dst_ip = parse_prefix(next()?).ok_or(err(ParseErrorKind::BadPrefix))?;
+        let src_port = parse_port_range(next()?).ok_or(err(ParseErrorKind::BadPortRange))?;
+        let dst_port = parse_port_range(next()?).ok_or(err(ParseErrorKind::BadPortRange))?;
+        let proto = parse_protocol(next()?).ok_or(err(ParseErrorKind::BadProtocol))?;
+
+        let mut action = Action::Permit;
+        for extra in fields {
+            match extra {
+                "permit" => action = Action::Permit,
+                "deny" => action = Action::Deny,
+                flags if flags.starts_with("0x") => {}
+                _ => return Err(err(ParseErrorKind::BadAction)),
+            }
+        }
+
+        let id = rules.len() as u32;
+        rules.push(Rule {
+            id,
+            priority: id,
+            src_ip,
+            dst_ip,
+            src_port,
+            dst_port,
+            proto,
+            zone: ANY_ZONE,
+            bidirectional: false,
+            field_sets: None,
+            action,
+        });
+    }
+    Ok(rules)
+}
+
+/// Parse a trace file with expected matches.
+pub fn parse_trace(input: &str) -> Result<Vec<TraceEntry>, ParseError> {
+    let mut entries = Vec::new();
+    for (line_no, line) in lines(input) {
+        let err = |kind| ParseError {
+            line: line_no,
+            kind,
+        };
+        let mut cols = line.split_whitespace();
+
+        let packet = FiveTuple {
+            src_ip: number(&mut cols, line_no)?,
+            dst_ip: number(&mut cols, line_no)?,
+            src_port: number(&mut cols, line_no)?,
+            dst_port: number(&mut cols, line_no)?,
+            proto: number(&mut cols, line_no)?,
+            zone: 0,
+        };
+        let expected = match number::<i64>(&mut cols, line_no)? {
+            -1 => None,
+            id => Some(id.try_into().map_err(|_| err(ParseErrorKind::BadNumber))?),
+        };
+        entries.push(TraceEntry { packet, expected });
+    }
+    Ok(entries)
+}
+
+/// Non-empty, non-comment lines with their 1-based numbers.
+fn lines(input: &str) -> impl Iterator<Item = (usize, &str)> {
+    input
+        .lines()
+        .enumerate()
+        .map(|(i, l)| (i + 1, l.trim()))
+        .filter(|(_, l)| !l.is_empty() && !l.starts_with('#'))
+}
+
+/// Next column of a trace line as a number of type `T`.
+fn number<'a, T: core::str::FromStr>(
+    cols: &mut impl Iterator<Item = &'a str>,
+    line: usize,
+) -> Result<T, ParseError> {
+    let col = cols.next().ok_or(ParseError {
+        line,
+        kind: ParseErrorKind::MissingField,
+    })?;
+    col.parse().map_err(|_| ParseError {
+        line,
+        kind: ParseErrorKind::BadNumber,
+    })
+}
+
+/// `a.b.c.d/len` to an address range.
+fn parse_prefix(s: &str) -> Option<Range<u32>> {
+    let (addr, len) = s.split_once('/')?;
+    let len: u32 = len.parse().ok()?;
+    if len > 32 {
+        return None;
+    }
+    let mut value = 0u32;
+    let mut octets = 0;
+    for octet in addr.split('.') {
+        value = (value << 8) | octet.parse::<u8>().ok()? as u32;
+        octets += 1;
+    }
+    (octets == 4).then(|| Prefix::new(value, len).to_range())
+}
+
+/// `lo : hi` to a port range.
+fn parse_port_range(s: &str) -> Option<Range<u16>> {
+    let (lo, hi) = s.split_once(':')?;
+    let (lo, hi) = (lo.trim().parse().ok()?, hi.trim().parse().ok()?);
+    (lo <= hi).then(|| Range::new(lo, hi))
+}
+
+/// `0xPP/0xMM` to a protocol range (mask 0xFF: exact, 0x00: any).
+fn parse_protocol(s: &str) -> Option<Range<u8>> {
+    let (value, mask) = s.split_once('/')?;
+    let value = u8::from_str_radix(value.strip_prefix("0x")?, 16).ok()?;
+    match u8::from_str_radix(mask.strip_prefix("0x")?, 16).ok()? {
+        0xFF => Some(Range::exact(value)),
+        0x00 => Some(Range::any(0, 255)),
+        _ => None,
+    }
+}
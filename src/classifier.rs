@@ -1,7 +1,162 @@
-use crate::packet::FiveTuple;
+use crate::packet::{FiveTuple, PacketKey};
 
 use crate::rule::{Action, Rule};
 
+/// Work performed by a single lookup.
+///
+/// Cheap enough to collect on every packet, so adaptive systems can monitor
+/// classifier health online without enabling any global profiling.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct LookupStats {
+    /// Number of tree nodes traversed before reaching a leaf.
+    pub depth: u32,
+    /// Number of rules compared against the packet.
+    pub rules_compared: u32,
+    /// Number of hash tables probed (tuple-space based classifiers).
+    pub tables_probed: u32,
+}
+
+/// Outcome of classifying a packet.
+///
+/// Unlike `Option<Action>`, this keeps "explicitly denied by a rule" apart from
+/// "fell through the policy", which accounting and default-action handling
+/// need to tell apart.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Verdict {
+    /// A rule matched.
+    Matched {
+        /// Action of the matching rule.
+        action: Action,
+        /// Id of the matching rule.
+        rule_id: u32,
+    },
+    /// No rule matched.
+    NoMatch,
+}
+
+impl Verdict {
+    /// Action of the matching rule, if any.
+    pub fn action(&self) -> Option<Action> {
+        match *self {
+            Verdict::Matched { action, .. } => Some(action),
+            Verdict::NoMatch => None,
+        }
+    }
+
+    /// Id of the matching rule, if any.
+    pub fn rule_id(&self) -> Option<u32> {
+        match *self {
+            Verdict::Matched { rule_id, .. } => Some(rule_id),
+            Verdict::NoMatch => None,
+        }
+    }
+
+    /// Returns true if a rule matched.
+    pub fn is_match(&self) -> bool {
+        matches!(self, Verdict::Matched { .. })
+    }
+
+    /// The action to apply, using `default` when no rule matched.
+    pub fn action_or(&self, default: Action) -> Action {
+        self.action().unwrap_or(default)
+    }
+}
+
+impl From<Option<&Rule>> for Verdict {
+    fn from(rule: Option<&Rule>) -> Self {
+        match rule {
+            Some(rule) => Verdict::Matched {
+                action: rule.action,
+                rule_id: rule.id,
+            },
+            None => Verdict::NoMatch,
+        }
+    }
+}
+
+/// The rule a packet matched, for attributing hits (counters, logging) to
+/// rules.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MatchResult<'a> {
+    /// Id of the matching rule.
+    pub rule_id: u32,
+    /// Priority of the matching rule (lower wins).
+    pub priority: u32,
+    /// Action of the matching rule.
+    pub action: Action,
+    /// The matching rule itself.
+    pub rule: &'a Rule,
+}
+
+impl<'a> From<&'a Rule> for MatchResult<'a> {
+    fn from(rule: &'a Rule) -> Self {
+        MatchResult {
+            rule_id: rule.id,
+            priority: rule.priority,
+            action: rule.action,
+            rule,
+        }
+    }
+}
+
+impl From<MatchResult<'_>> for Verdict {
+    fn from(m: MatchResult<'_>) -> Self {
+        Verdict::Matched {
+            action: m.action,
+            rule_id: m.rule_id,
+        }
+    }
+}
+
+/// Tuning parameters for `Classifier::build_with_config`, so parameter
+/// sweeps need not know each algorithm's builder.
+///
+/// Fields left `None` keep the algorithm's default, and each algorithm only
+/// reads the fields it has: the decision trees read `leaf_threshold`,
+/// `max_depth` and `spfac`, TSS reads `merge_bits`.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct Config {
+    /// Max rules in a leaf (`binth` in the HiCuts paper).
+    pub leaf_threshold: Option<usize>,
+    /// Maximum depth of the tree.
+    pub max_depth: Option<usize>,
+    /// Space factor: most rule copies a cut's children may hold, as a
+    /// multiple of the node's rules. HiCuts rounds it up to a whole factor.
+    pub spfac: Option<f32>,
+    /// Most prefix bits a TSS rule may lose to be merged into an existing,
+    /// coarser table.
+    pub merge_bits: Option<u32>,
+}
+
+impl Config {
+    /// Every parameter at the algorithm's default.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_leaf_threshold(mut self, leaf_threshold: usize) -> Self {
+        self.leaf_threshold = Some(leaf_threshold);
+        self
+    }
+
+    pub fn with_max_depth(mut self, max_depth: usize) -> Self {
+        self.max_depth = Some(max_depth);
+        self
+    }
+
+    pub fn with_spfac(mut self, spfac: f32) -> Self {
+        self.spfac = Some(spfac);
+        self
+    }
+
+    pub fn with_merge_bits(mut self, merge_bits: u32) -> Self {
+        self.merge_bits = Some(merge_bits);
+        self
+    }
+}
+
 /// Trait for Packet Classification algorithms
 pub trait Classifier {
     /// Build the classifier with a set of rules
@@ -9,6 +164,82 @@ pub trait Classifier {
     where
         Self: Sized;
 
-    /// Classify a packet (5-tuple) and return the matching Action (if any)
-    fn classify(&self, packet: &FiveTuple) -> Option<Action>;
+    /// Build the classifier with the tuning parameters of `config`.
+    ///
+    /// Classifiers without tuning parameters build as `build` does; so do
+    /// wrappers, which take `|r| C::build_with_config(r, &config)` as the
+    /// build closure of their `build_with` instead.
+    fn build_with_config(rules: &[Rule], _config: &Config) -> Self
+    where
+        Self: Sized,
+    {
+        Self::build(rules)
+    }
+
+    /// Classify a packet (5-tuple) and return the highest-priority matching rule (if any)
+    ///
+    /// The best rule has the lowest `priority` value. Rules of equal priority
+    /// win in the order they were given to `build` in `LinearClassifier` and
+    /// the decision trees (first match wins); other classifiers may pick any
+    /// of them.
+    fn classify_rule(&self, packet: &FiveTuple) -> Option<&Rule>;
+
+    /// Classify a packet and return the matching Action (if any).
+    ///
+    /// Takes any `PacketKey`, e.g. a `FiveTuple` or a view over a raw
+    /// buffer. Through `dyn Classifier`, use `classify_rule`.
+    fn classify<K: PacketKey + ?Sized>(&self, packet: &K) -> Option<Action>
+    where
+        Self: Sized,
+    {
+        self.classify_rule(&packet.to_five_tuple())
+            .map(|r| r.action)
+    }
+
+    /// Classify every packet of `packets`, writing its action to the same
+    /// position of `out`.
+    ///
+    /// Gives the same results as `classify` on each packet; decision trees
+    /// override it to walk the tree once for the whole batch.
+    ///
+    /// Panics if `out` and `packets` differ in length.
+    fn classify_batch(&self, packets: &[FiveTuple], out: &mut [Option<Action>]) {
+        crate::batch::check_lengths(packets, out);
+        for (packet, slot) in packets.iter().zip(out) {
+            *slot = self.classify_rule(packet).map(|r| r.action);
+        }
+    }
+
+    /// Classify a packet and return the id, priority, action and rule of the
+    /// match (if any).
+    fn classify_full(&self, packet: &FiveTuple) -> Option<MatchResult<'_>> {
+        self.classify_rule(packet).map(MatchResult::from)
+    }
+
+    /// Classify a packet, distinguishing a matching rule from no match at all.
+    fn classify_verdict(&self, packet: &FiveTuple) -> Verdict {
+        Verdict::from(self.classify_rule(packet))
+    }
+
+    /// Classify a packet and report the work performed by the lookup.
+    ///
+    /// The default implementation reports no work; algorithms override it.
+    fn classify_with_stats(&self, packet: &FiveTuple) -> (Option<Action>, LookupStats) {
+        (
+            self.classify_rule(packet).map(|r| r.action),
+            LookupStats::default(),
+        )
+    }
+
+    /// Classify a packet and return the matching rule along with the work
+    /// performed by the lookup.
+    ///
+    /// Lets wrappers that combine several classifiers pick the winning rule
+    /// and report the work in one lookup per part. The default
+    /// implementation looks the packet up twice (once for the rule, once for
+    /// the stats); algorithms override it.
+    fn classify_rule_with_stats(&self, packet: &FiveTuple) -> (Option<&Rule>, LookupStats) {
+        let (_, stats) = self.classify_with_stats(packet);
+        (self.classify_rule(packet), stats)
+    }
 }
@@ -0,0 +1,472 @@
+//! Compact 16-byte node encoding for binary decision trees.
+//!
+//! CutSplit and HyperSplit trees are boxed enums whose leaves own cloned
+//! rules. `CompactTree` re-encodes such a tree as one flat array of 16-byte
+//! nodes (four per cache line): internal nodes hold the dimension, the cut
+//! value and the `u32` indices of both children; leaves hold an offset and
+//! length into a shared array of rule indices, so a rule duplicated across
+//! leaves is stored once. CutSplit compiles its tree into one right after
+//! the build; HyperSplit trees are encoded at freeze time through
+//! `FreezeCompact::freeze_compact`.
+
+use crate::batch;
+use crate::classifier::{Classifier, LookupStats};
+use crate::dimension::Dimension;
+use crate::fingerprint::Fingerprint;
+use crate::freeze::{Freeze, FrozenClassifier};
+use crate::geometry::Region;
+use crate::hypersplit::classifier::HyperSplitClassifier;
+use crate::leaf::BitVectorIndex;
+use crate::memory::{Footprint, MemoryStats, Tally};
+use crate::packet::FiveTuple;
+use crate::query::{Overlapping, RegionQuery};
+use crate::rule::{Action, Rule};
+use crate::serial::{self, Codec};
+use crate::update::audit::{DecodeError, Reader};
+use alloc::vec::Vec;
+use hashbrown::HashMap;
+
+/// Node kind of a leaf; internal nodes use the dimension index instead.
+pub(crate) const LEAF: u8 = 0xFE;
+/// Node kind of a leaf backed by a `BitVectorIndex`.
+pub(crate) const INDEXED_LEAF: u8 = 0xFF;
+
+/// A tree node in 16 bytes.
+///
+/// | kind            | `value`                | `left`        | `right`        |
+/// |-----------------|------------------------|---------------|----------------|
+/// | dimension index | cut value              | child `< cut` | child `>= cut` |
+/// | `LEAF`          | offset in `leaf_rules` | rule count    | unused         |
+/// | `INDEXED_LEAF`  | index in `indexes`     | unused        | unused         |
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct CompactNode {
+    pub(crate) kind: u8,
+    pub(crate) value: u32,
+    pub(crate) left: u32,
+    pub(crate) right: u32,
+}
+
+const _: () = assert!(core::mem::size_of::<CompactNode>() == 16);
+
+/// Borrowed view of one node of a binary decision tree.
+pub(crate) enum NodeView<'a, N> {
+    Internal {
+        dimension: Dimension,
+        cut: u32,
+        left: &'a N,
+        right: &'a N,
+    },
+    Leaf(&'a [Rule]),
+    IndexedLeaf(&'a BitVectorIndex),
+}
+
+/// Binary decision tree node that can be encoded as a `CompactTree`.
+/// Packets go left when their value is below the cut.
+pub(crate) trait BinaryNode: Sized {
+    fn view(&self) -> NodeView<'_, Self>;
+    /// Internal node sending values below `cut` to `left`.
+    fn internal(dimension: Dimension, cut: u32, left: Self, right: Self) -> Self;
+}
+
+/// Flat, read-only encoding of a binary decision tree.
+#[derive(Debug, Clone)]
+pub struct CompactTree {
+    /// Nodes in pre-order; the root is at index 0.
+    pub(crate) nodes: Vec<CompactNode>,
+    /// Concatenated leaf contents, as indices into `rules`.
+    pub(crate) leaf_rules: Vec<u32>,
+    /// Every distinct rule of the tree once.
+    pub(crate) rules: Vec<Rule>,
+    pub(crate) indexes: Vec<BitVectorIndex>,
+    pub(crate) fingerprint: Option<Fingerprint>,
+}
+
+impl CompactTree {
+    /// Encode the tree rooted at `root`, built from rules with the given
+    /// fingerprint.
+    pub(crate) fn from_root<N: BinaryNode>(root: &N, fingerprint: Option<Fingerprint>) -> Self {
+        let mut encoder = Encoder {
+            tree: CompactTree {
+                nodes: Vec::new(),
+                leaf_rules: Vec::new(),
+                rules: Vec::new(),
+                indexes: Vec::new(),
+                fingerprint,
+            },
+            arena: HashMap::new(),
+        };
+        encoder.encode(root);
+        let mut tree = encoder.tree;
+        tree.shrink_to_fit();
+        tree
+    }
+
+    /// Number of nodes (internal and leaves).
+    pub fn node_count(&self) -> usize {
+        self.nodes.len()
+    }
+
+    /// Fingerprint of the rules the tree was built from.
+    pub fn fingerprint(&self) -> Option<Fingerprint> {
+        self.fingerprint
+    }
+
+    /// Number of distinct rules stored.
+    pub fn rule_count(&self) -> usize {
+        self.rules.len()
+    }
+
+    fn lookup(&self, packet: &FiveTuple, stats: &mut LookupStats) -> Option<&Rule> {
+        let mut node = self.nodes.first()?;
+        loop {
+            match node.kind {
+                LEAF => {
+                    let start = node.value as usize;
+                    let leaf = &self.leaf_rules[start..start + node.left as usize];
+                    for &idx in leaf {
+                        stats.rules_compared += 1;
+                        let rule = &self.rules[idx as usize];
+                        if rule.matches(packet) {
+                            return Some(rule);
+                        }
+                    }
+                    return None;
+                }
+                INDEXED_LEAF => return self.indexes[node.value as usize].lookup(packet, stats),
+                dim => {
+                    stats.depth += 1;
+                    let val = Dimension::ALL[dim as usize].packet_value(packet);
+                    let child = if val < node.value {
+                        node.left
+                    } else {
+                        node.right
+                    };
+                    node = &self.nodes[child as usize];
+                }
+            }
+        }
+    }
+}
+
+/// Builds a `CompactTree`, deduplicating rules shared by several leaves.
+struct Encoder {
+    tree: CompactTree,
+    /// `(id, priority)` to arena indices of the rules carrying it.
+    arena: HashMap<(u32, u32), Vec<u32>>,
+}
+
+impl Encoder {
+    /// Append the subtree rooted at `node`, returning its index.
+    fn encode<N: BinaryNode>(&mut self, node: &N) -> u32 {
+        let slot = self.tree.nodes.len() as u32;
+        self.tree.nodes.push(CompactNode {
+            kind: LEAF,
+            value: 0,
+            left: 0,
+            right: 0,
+        });
+
+        let encoded = match node.view() {
+            NodeView::Internal {
+                dimension,
+                cut,
+                left,
+                right,
+            } => CompactNode {
+                kind: dimension.index() as u8,
+                value: cut,
+                left: self.encode(left),
+                right: self.encode(right),
+            },
+            NodeView::Leaf(rules) => {
+                let offset = self.tree.leaf_rules.len() as u32;
+                for rule in rules {
+                    let idx = self.intern(rule);
+                    self.tree.leaf_rules.push(idx);
+                }
+                CompactNode {
+                    kind: LEAF,
+                    value: offset,
+                    left: rules.len() as u32,
+                    right: 0,
+                }
+            }
+            NodeView::IndexedLeaf(index) => {
+                self.tree.indexes.push(index.clone());
+                CompactNode {
+                    kind: INDEXED_LEAF,
+                    value: self.tree.indexes.len() as u32 - 1,
+                    left: 0,
+                    right: 0,
+                }
+            }
+        };
+        self.tree.nodes[slot as usize] = encoded;
+        slot
+    }
+
+    /// Arena index of `rule`, adding it if it was not seen yet.
+    fn intern(&mut self, rule: &Rule) -> u32 {
+        let candidates = self.arena.entry((rule.id, rule.priority)).or_default();
+        if let Some(&idx) = candidates
+            .iter()
+            .find(|&&idx| self.tree.rules[idx as usize] == *rule)
+        {
+            return idx;
+        }
+        let idx = self.tree.rules.len() as u32;
+        self.tree.rules.push(rule.clone());
+        candidates.push(idx);
+        idx
+    }
+}
+
+impl Classifier for CompactTree {
+    /// Build a HyperSplit tree and encode it.
+    fn build(rules: &[Rule]) -> Self {
+        HyperSplitClassifier::build(rules).to_compact()
+    }
+
+    fn classify_rule(&self, packet: &FiveTuple) -> Option<&Rule> {
+        self.lookup(packet, &mut LookupStats::default())
+    }
+
+    fn classify_batch(&self, packets: &[FiveTuple], out: &mut [Option<Action>]) {
+        batch::check_lengths(packets, out);
+        if self.nodes.is_empty() {
+            out.fill(None);
+            return;
+        }
+        let mut order = batch::all(packets);
+        let mut stack = alloc::vec![(0u32, 0, order.len())];
+        while let Some((node, start, end)) = stack.pop() {
+            let node = &self.nodes[node as usize];
+            let group = &mut order[start..end];
+            match node.kind {
+                LEAF => {
+                    let first = node.value as usize;
+                    let leaf = &self.leaf_rules[first..first + node.left as usize];
+                    let rules = leaf.iter().map(|&idx| &self.rules[idx as usize]);
+                    batch::scan(rules, group, packets, out);
+                }
+                INDEXED_LEAF => {
+                    batch::scan_index(&self.indexes[node.value as usize], group, packets, out)
+                }
+                dim => {
+                    let dimension = Dimension::ALL[dim as usize];
+                    let mid = start
+                        + batch::split(group, |i| {
+                            dimension.packet_value(&packets[i as usize]) < node.value
+                        });
+                    if mid < end {
+                        stack.push((node.right, mid, end));
+                    }
+                    if start < mid {
+                        stack.push((node.left, start, mid));
+                    }
+                }
+            }
+        }
+    }
+
+    fn classify_with_stats(&self, packet: &FiveTuple) -> (Option<Action>, LookupStats) {
+        let mut stats = LookupStats::default();
+        let action = self.lookup(packet, &mut stats).map(|r| r.action);
+        (action, stats)
+    }
+
+    fn classify_rule_with_stats(&self, packet: &FiveTuple) -> (Option<&Rule>, LookupStats) {
+        let mut stats = LookupStats::default();
+        let rule = self.lookup(packet, &mut stats);
+        (rule, stats)
+    }
+}
+
+impl RegionQuery for CompactTree {
+    fn rules_overlapping(&self, region: &Region) -> Vec<&Rule> {
+        let mut found = Overlapping::new(region);
+        let mut stack: Vec<&CompactNode> = self.nodes.first().into_iter().collect();
+        while let Some(node) = stack.pop() {
+            match node.kind {
+                LEAF => {
+                    let start = node.value as usize;
+                    let leaf = &self.leaf_rules[start..start + node.left as usize];
+                    found.scan(leaf.iter().map(|&idx| &self.rules[idx as usize]));
+                }
+                INDEXED_LEAF => found.scan(self.indexes[node.value as usize].rules()),
+                dim => {
+                    let range = region.get(Dimension::ALL[dim as usize]);
+                    if range.max >= node.value {
+                        stack.push(&self.nodes[node.right as usize]);
+                    }
+                    if range.min < node.value {
+                        stack.push(&self.nodes[node.left as usize]);
+                    }
+                }
+            }
+        }
+        found.finish()
+    }
+}
+
+impl MemoryStats for CompactTree {
+    /// Rules shared by several leaves are stored once, but count as a copy
+    /// per leaf in `stored_rules`.
+    fn footprint(&self) -> Footprint {
+        let bytes = size_of::<Self>()
+            + self.nodes.capacity() * size_of::<CompactNode>()
+            + self.leaf_rules.capacity() * size_of::<u32>()
+            + self.rules.capacity() * size_of::<Rule>()
+            + self.indexes.capacity() * size_of::<BitVectorIndex>()
+            + self
+                .indexes
+                .iter()
+                .map(BitVectorIndex::heap_bytes)
+                .sum::<usize>();
+        let mut tally = Tally::new(bytes);
+        let mut stack: Vec<(&CompactNode, usize)> = self
+            .nodes
+            .first()
+            .map(|root| (root, 0))
+            .into_iter()
+            .collect();
+        while let Some((node, depth)) = stack.pop() {
+            match node.kind {
+                LEAF => {
+                    let start = node.value as usize;
+                    let leaf = &self.leaf_rules[start..start + node.left as usize];
+                    tally.leaf(depth, leaf.iter().map(|&idx| &self.rules[idx as usize]));
+                }
+                INDEXED_LEAF => tally.leaf(depth, self.indexes[node.value as usize].rules()),
+                _ => {
+                    tally.internal();
+                    stack.push((&self.nodes[node.right as usize], depth + 1));
+                    stack.push((&self.nodes[node.left as usize], depth + 1));
+                }
+            }
+        }
+        tally.finish()
+    }
+}
+
+impl Freeze for CompactTree {
+    fn shrink_to_fit(&mut self) {
+        self.nodes.shrink_to_fit();
+        self.leaf_rules.shrink_to_fit();
+        self.rules.shrink_to_fit();
+        self.indexes.shrink_to_fit();
+        for index in &mut self.indexes {
+            index.shrink_to_fit();
+        }
+    }
+}
+
+impl Codec for CompactTree {
+    fn serialize(&self) -> Vec<u8> {
+        let mut out = serial::header(serial::COMPACT, self.fingerprint);
+        self.encode_body(&mut out);
+        out
+    }
+
+    fn deserialize(bytes: &[u8]) -> Result<Self, DecodeError> {
+        let mut r = serial::reader(bytes);
+        let fingerprint = serial::open(&mut r, serial::COMPACT)?;
+        let tree = Self::decode_body(&mut r, fingerprint)?;
+        serial::finish(&r)?;
+        Ok(tree)
+    }
+}
+
+impl CompactTree {
+    /// Write the tree after a blob header.
+    pub(crate) fn encode_body(&self, out: &mut Vec<u8>) {
+        serial::put_u32(out, self.nodes.len() as u32);
+        for node in &self.nodes {
+            out.push(node.kind);
+            for v in [node.value, node.left, node.right] {
+                serial::put_u32(out, v);
+            }
+        }
+        serial::put_u32(out, self.leaf_rules.len() as u32);
+        for &idx in &self.leaf_rules {
+            serial::put_u32(out, idx);
+        }
+        serial::encode_rules(out, &self.rules);
+        serial::put_u32(out, self.indexes.len() as u32);
+        for index in &self.indexes {
+            serial::encode_rules(out, index.rules());
+        }
+    }
+
+    /// Read and validate a tree written by `encode_body`.
+    pub(crate) fn decode_body(
+        r: &mut Reader<'_>,
+        fingerprint: Option<Fingerprint>,
+    ) -> Result<Self, DecodeError> {
+        let mut tree = CompactTree {
+            nodes: Vec::new(),
+            leaf_rules: Vec::new(),
+            rules: Vec::new(),
+            indexes: Vec::new(),
+            fingerprint,
+        };
+        for _ in 0..r.u32()? {
+            tree.nodes.push(CompactNode {
+                kind: r.u8()?,
+                value: r.u32()?,
+                left: r.u32()?,
+                right: r.u32()?,
+            });
+        }
+        for _ in 0..r.u32()? {
+            tree.leaf_rules.push(r.u32()?);
+        }
+        tree.rules = serial::decode_rules(r)?;
+        for _ in 0..r.u32()? {
+            tree.indexes.push(serial::decode_index(r)?);
+        }
+        tree.validate()?;
+        Ok(tree)
+    }
+
+    /// Check that every index stays in bounds and children come after their
+    /// parent (so lookups terminate).
+    fn validate(&self) -> Result<(), DecodeError> {
+        let nodes = self.nodes.len();
+        for (i, node) in self.nodes.iter().enumerate() {
+            let valid = match node.kind {
+                LEAF => (node.value as usize)
+                    .checked_add(node.left as usize)
+                    .is_some_and(|end| end <= self.leaf_rules.len()),
+                INDEXED_LEAF => (node.value as usize) < self.indexes.len(),
+                dim if (dim as usize) < Dimension::COUNT => [node.left, node.right]
+                    .iter()
+                    .all(|&child| i < child as usize && (child as usize) < nodes),
+                tag => return Err(DecodeError::InvalidTag(tag)),
+            };
+            if !valid {
+                return Err(DecodeError::Corrupt);
+            }
+        }
+        if self
+            .leaf_rules
+            .iter()
+            .any(|&idx| idx as usize >= self.rules.len())
+        {
+            return Err(DecodeError::Corrupt);
+        }
+        Ok(())
+    }
+}
+
+/// Classifier whose tree can be frozen into the compact encoding.
+pub trait FreezeCompact: Freeze {
+    /// Encode the lookup structure as a `CompactTree`.
+    fn to_compact(&self) -> CompactTree;
+
+    /// Freeze into the compact encoding instead of the classifier's own layout.
+    fn freeze_compact(self) -> FrozenClassifier<CompactTree> {
+        self.to_compact().freeze()
+    }
+}
@@ -0,0 +1,97 @@
+//! Cut-cost models shared by the tree builders.
+//!
+//! Every builder compares candidate cuts of a node by a cost (lower is
+//! better). The cost is a `CutCost` held by the builder (`with_cut_cost`),
+//! so a tree can be optimized for memory, depth or worst-case leaf size
+//! without changing the builder, and a tuner can drive the builders through
+//! one interface. Closures `Fn(&Cut) -> f32` are cost models too.
+//!
+//! Each builder defaults to the model it has always used: `Replication` for
+//! CutSplit, `Balance` for HyperSplit and `LargestChild` for HiCuts.
+
+use crate::dimension::Dimension;
+
+/// A candidate cut of a tree node.
+#[derive(Debug, Clone, Copy)]
+pub struct Cut<'a> {
+    /// Dimension being cut.
+    pub dimension: Dimension,
+    /// Depth of the node being cut (0 = root).
+    pub depth: usize,
+    /// Rules held by the node.
+    pub rules: usize,
+    /// Rules each child would hold, in order.
+    pub children: &'a [usize],
+}
+
+impl Cut<'_> {
+    /// Rule copies held by the children together.
+    pub fn total(&self) -> usize {
+        self.children.iter().sum()
+    }
+
+    /// Rules held by the fullest child.
+    pub fn largest(&self) -> usize {
+        self.children.iter().copied().max().unwrap_or(0)
+    }
+
+    /// Rule copies per parent rule (1.0 = no duplication).
+    pub fn replication(&self) -> f32 {
+        if self.rules == 0 {
+            return 1.0;
+        }
+        self.total() as f32 / self.rules as f32
+    }
+}
+
+/// Objective the builders minimize when choosing among candidate cuts.
+pub trait CutCost: Send + Sync {
+    /// Cost of making `cut` (lower is better).
+    fn cost(&self, cut: &Cut) -> f32;
+}
+
+impl<F: Fn(&Cut) -> f32 + Send + Sync> CutCost for F {
+    fn cost(&self, cut: &Cut) -> f32 {
+        self(cut)
+    }
+}
+
+/// Memory: the fewer rule copies the children hold, the better.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Replication;
+
+impl CutCost for Replication {
+    fn cost(&self, cut: &Cut) -> f32 {
+        cut.replication()
+    }
+}
+
+/// Search depth with a duplication penalty: the fullest child's rules plus
+/// `penalty` per rule copy.
+#[derive(Debug, Clone, Copy)]
+pub struct Balance {
+    /// Weight of the total number of copies.
+    pub penalty: f32,
+}
+
+impl Default for Balance {
+    fn default() -> Self {
+        Self { penalty: 0.1 }
+    }
+}
+
+impl CutCost for Balance {
+    fn cost(&self, cut: &Cut) -> f32 {
+        cut.largest() as f32 + self.penalty * cut.total() as f32
+    }
+}
+
+/// Worst-case leaf: rules held by the fullest child.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct LargestChild;
+
+impl CutCost for LargestChild {
+    fn cost(&self, cut: &Cut) -> f32 {
+        cut.largest() as f32
+    }
+}
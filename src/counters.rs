@@ -0,0 +1,319 @@
+//! Per-rule hit counters.
+//!
+//! `Counted` wraps any classifier and counts, for every rule, the packets it
+//! matched and their bytes, the way iptables/nftables report rule counters.
+//! Counters are updated through `&self`, so a shared classifier can be
+//! queried from several cores. Packets that match no rule are counted
+//! separately.
+//!
+//! `top` and `top_since` report the most-hit rules, overall or over a period
+//! that started with a `Baseline` snapshot, to spot rules worth reordering or
+//! unexpectedly hot deny rules.
+//!
+//! Rules can also be given a sample rate: every Nth packet a rule matches is
+//! flagged for mirroring (sFlow-style), driven by that rule's packet counter
+//! so the selection is deterministic.
+//!
+//! Counters are atomic by default. With the `single-core` feature they are
+//! plain `Cell`s instead, for cores without atomic instructions
+//! (Cortex-M0/M0+); `Counted` is then not `Sync`, which is what keeps the
+//! non-atomic updates sound.
+
+use crate::classifier::{Classifier, LookupStats, Verdict};
+use crate::geometry::Region;
+use crate::packet::FiveTuple;
+use crate::query::RegionQuery;
+use crate::rule::{Action, Rule};
+use alloc::vec::Vec;
+use hashbrown::HashMap;
+
+/// Counter values at one point in time.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct CounterSnapshot {
+    /// Packets counted.
+    pub packets: u64,
+    /// Bytes counted (only packets classified with a length contribute).
+    pub bytes: u64,
+}
+
+/// Counters of every rule at the start of a period.
+#[derive(Debug, Clone)]
+pub struct Baseline {
+    /// Per-slot counters, in the order of `Counted::counters`.
+    slots: Vec<CounterSnapshot>,
+}
+
+/// A rule's activity over a period, as reported by `Counted::top_since`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct HotRule {
+    /// Id of the rule.
+    pub rule_id: u32,
+    /// Packets and bytes counted during the period.
+    pub delta: CounterSnapshot,
+    /// Counters at the end of the period.
+    pub total: CounterSnapshot,
+}
+
+/// Verdict of a sampled lookup.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Sampled {
+    /// Classification result.
+    pub verdict: Verdict,
+    /// True if the packet was selected for mirroring by its rule's sample rate.
+    pub mirror: bool,
+}
+
+/// A `u64` counter updated through `&self`.
+#[cfg(not(feature = "single-core"))]
+#[derive(Debug, Default)]
+pub(crate) struct Cell64(core::sync::atomic::AtomicU64);
+
+#[cfg(not(feature = "single-core"))]
+impl Cell64 {
+    /// Add `n`, returning the previous value.
+    pub(crate) fn add(&self, n: u64) -> u64 {
+        self.0.fetch_add(n, core::sync::atomic::Ordering::Relaxed)
+    }
+
+    pub(crate) fn get(&self) -> u64 {
+        self.0.load(core::sync::atomic::Ordering::Relaxed)
+    }
+
+    pub(crate) fn clear(&self) {
+        self.0.store(0, core::sync::atomic::Ordering::Relaxed);
+    }
+}
+
+/// A `u64` counter updated through `&self`.
+#[cfg(feature = "single-core")]
+#[derive(Debug, Default)]
+pub(crate) struct Cell64(core::cell::Cell<u64>);
+
+#[cfg(feature = "single-core")]
+impl Cell64 {
+    /// Add `n` (wrapping like the atomic version), returning the previous value.
+    pub(crate) fn add(&self, n: u64) -> u64 {
+        let old = self.0.get();
+        self.0.set(old.wrapping_add(n));
+        old
+    }
+
+    pub(crate) fn get(&self) -> u64 {
+        self.0.get()
+    }
+
+    pub(crate) fn clear(&self) {
+        self.0.set(0);
+    }
+}
+
+#[derive(Debug, Default)]
+struct Counter {
+    packets: Cell64,
+    bytes: Cell64,
+}
+
+impl Counter {
+    /// Count one packet, returning the new packet count.
+    fn hit(&self, bytes: u64) -> u64 {
+        self.bytes.add(bytes);
+        self.packets.add(1) + 1
+    }
+
+    fn snapshot(&self) -> CounterSnapshot {
+        CounterSnapshot {
+            packets: self.packets.get(),
+            bytes: self.bytes.get(),
+        }
+    }
+
+    fn reset(&self) {
+        self.packets.clear();
+        self.bytes.clear();
+    }
+}
+
+/// Classifier that counts packets and bytes per matching rule.
+pub struct Counted<C> {
+    inner: C,
+    /// Rule id to counter slot. Rules sharing an id share a counter.
+    slots: HashMap<u32, usize>,
+    counters: Vec<Counter>,
+    /// Per-slot sample rate (0: not sampled).
+    sample_rates: Vec<u32>,
+    unmatched: Counter,
+}
+
+impl<C: Classifier> Counted<C> {
+    /// Count lookups of an already built classifier over `rules`.
+    pub fn new(inner: C, rules: &[Rule]) -> Self {
+        let mut slots = HashMap::with_capacity(rules.len());
+        for rule in rules {
+            let next = slots.len();
+            slots.entry(rule.id).or_insert(next);
+        }
+        let counters = (0..slots.len()).map(|_| Counter::default()).collect();
+        let sample_rates = alloc::vec![0; slots.len()];
+        Self {
+            inner,
+            slots,
+            counters,
+            sample_rates,
+            unmatched: Counter::default(),
+        }
+    }
+
+    /// The wrapped classifier.
+    pub fn inner(&self) -> &C {
+        &self.inner
+    }
+
+    /// Classify a packet of `wire_len` bytes, adding it to the matched rule's
+    /// packet and byte counters.
+    pub fn classify_with_len(&self, packet: &FiveTuple, wire_len: u32) -> Option<Action> {
+        self.lookup(packet, wire_len).map(|r| r.action)
+    }
+
+    /// Mirror every `rate`th packet matched by the rule (0 disables sampling).
+    ///
+    /// Returns false if no rule has this id.
+    pub fn set_sample_rate(&mut self, rule_id: u32, rate: u32) -> bool {
+        match self.slots.get(&rule_id) {
+            Some(&slot) => {
+                self.sample_rates[slot] = rate;
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Classify a packet of `wire_len` bytes, counting it like
+    /// `classify_with_len` and reporting whether it is to be mirrored.
+    pub fn classify_sampled(&self, packet: &FiveTuple, wire_len: u32) -> Sampled {
+        let rule = self.inner.classify_rule(packet);
+        Sampled {
+            verdict: Verdict::from(rule),
+            mirror: self.count(rule, wire_len),
+        }
+    }
+
+    /// Counters of the rule with the given id.
+    pub fn counters(&self, rule_id: u32) -> Option<CounterSnapshot> {
+        self.slots
+            .get(&rule_id)
+            .map(|&slot| self.counters[slot].snapshot())
+    }
+
+    /// Counters of packets that matched no rule.
+    pub fn unmatched(&self) -> CounterSnapshot {
+        self.unmatched.snapshot()
+    }
+
+    /// Counters of every rule, by rule id (in no particular order).
+    pub fn iter(&self) -> impl Iterator<Item = (u32, CounterSnapshot)> + '_ {
+        self.slots
+            .iter()
+            .map(|(&id, &slot)| (id, self.counters[slot].snapshot()))
+    }
+
+    /// Snapshot every rule's counters, to start a period for `top_since`.
+    pub fn baseline(&self) -> Baseline {
+        Baseline {
+            slots: self.counters.iter().map(Counter::snapshot).collect(),
+        }
+    }
+
+    /// The `n` most-hit rules since the counters were created or reset.
+    pub fn top(&self, n: usize) -> Vec<HotRule> {
+        self.top_since(&Baseline { slots: Vec::new() }, n)
+    }
+
+    /// The `n` rules with the most packets since `baseline`, busiest first
+    /// (ties by rule id). Rules with no packet in the period are left out.
+    ///
+    /// Take a new baseline after `reset`: older ones no longer apply.
+    pub fn top_since(&self, baseline: &Baseline, n: usize) -> Vec<HotRule> {
+        let mut hot: Vec<HotRule> = self
+            .slots
+            .iter()
+            .filter_map(|(&rule_id, &slot)| {
+                let total = self.counters[slot].snapshot();
+                let start = baseline.slots.get(slot).copied().unwrap_or_default();
+                let delta = CounterSnapshot {
+                    packets: total.packets.saturating_sub(start.packets),
+                    bytes: total.bytes.saturating_sub(start.bytes),
+                };
+                (delta.packets > 0).then_some(HotRule {
+                    rule_id,
+                    delta,
+                    total,
+                })
+            })
+            .collect();
+        let by_heat = |a: &HotRule, b: &HotRule| {
+            b.delta
+                .packets
+                .cmp(&a.delta.packets)
+                .then(a.rule_id.cmp(&b.rule_id))
+        };
+        if n < hot.len() {
+            hot.select_nth_unstable_by(n, by_heat);
+            hot.truncate(n);
+        }
+        hot.sort_unstable_by(by_heat);
+        hot
+    }
+
+    /// Zero every counter.
+    pub fn reset(&self) {
+        for counter in &self.counters {
+            counter.reset();
+        }
+        self.unmatched.reset();
+    }
+
+    fn lookup(&self, packet: &FiveTuple, wire_len: u32) -> Option<&Rule> {
+        let rule = self.inner.classify_rule(packet);
+        self.count(rule, wire_len);
+        rule
+    }
+
+    /// Count a lookup result, returning true if the packet is to be mirrored.
+    fn count(&self, rule: Option<&Rule>, wire_len: u32) -> bool {
+        match rule.and_then(|r| self.slots.get(&r.id)) {
+            Some(&slot) => {
+                let count = self.counters[slot].hit(wire_len as u64);
+                let rate = self.sample_rates[slot];
+                rate != 0 && count.is_multiple_of(rate as u64)
+            }
+            None => {
+                self.unmatched.hit(wire_len as u64);
+                false
+            }
+        }
+    }
+}
+
+impl<C: Classifier> Classifier for Counted<C> {
+    fn build(rules: &[Rule]) -> Self {
+        Self::new(C::build(rules), rules)
+    }
+
+    /// Counts the packet (with no bytes) against the matching rule.
+    fn classify_rule(&self, packet: &FiveTuple) -> Option<&Rule> {
+        self.lookup(packet, 0)
+    }
+
+    fn classify_with_stats(&self, packet: &FiveTuple) -> (Option<Action>, LookupStats) {
+        let (action, stats) = self.inner.classify_with_stats(packet);
+        // `LookupStats` does not say which rule matched, so count with a second lookup.
+        self.lookup(packet, 0);
+        (action, stats)
+    }
+}
+
+impl<C: RegionQuery> RegionQuery for Counted<C> {
+    fn rules_overlapping(&self, region: &Region) -> Vec<&Rule> {
+        self.inner.rules_overlapping(region)
+    }
+}
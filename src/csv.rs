@@ -0,0 +1,368 @@
+//! Rule import from CSV exports of firewall policies (requires the `std`
+//! feature).
+//!
+//! One rule per row, in priority order; rule ids and priorities are the
+//! 0-based row index. Which column holds which field is set by a
+//! `CsvMapping`, by position or by header name, so spreadsheets exported
+//! from different tools can be read as they are:
+//!
+//! ```text
+//! name,src,dst,sport,dport,proto,action
+//! web,any,10.0.0.0/8,any,80,tcp,permit
+//! dns,192.168.1.0/24,any,1024-65535,53,udp,allow
+//! ```
+//!
+//! Fields may be quoted (`"a, b"`, with `""` for a quote). Field values:
+//!
+//! - addresses: `a.b.c.d`, `a.b.c.d/len` or `a.b.c.d-a.b.c.d`;
+//! - ports: `80`, `1024-65535` or `1024:65535`;
+//! - protocols: a number or a name from `packet::PROTOCOLS` (`tcp`, `udp`, ...);
+//! - actions: `permit`, `allow`, `accept`, `deny`, `drop` or `reject`.
+//!
+//! An empty field, `any` or `*` matches everything, as does a field without
+//! a column; rules without an action column are permits. Keywords are case
+//! insensitive. Blank lines and lines starting with `#` are skipped.
+
+use crate::packet::protocol_by_name;
+use crate::prefix::Prefix;
+use crate::rule::{Action, Range, Rule, ANY_ZONE};
+use alloc::string::String;
+use alloc::vec::Vec;
+use core::fmt;
+use core::net::Ipv4Addr;
+use std::io::BufRead;
+
+/// Where a field is read from.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Column {
+    /// 0-based column position.
+    Index(usize),
+    /// Column whose header cell is this name (case insensitive).
+    Name(String),
+}
+
+impl From<usize> for Column {
+    fn from(index: usize) -> Self {
+        Column::Index(index)
+    }
+}
+
+impl From<&str> for Column {
+    fn from(name: &str) -> Self {
+        Column::Name(name.into())
+    }
+}
+
+/// Columns of the rule fields (None = wildcard, or permit for the action).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CsvMapping {
+    /// Field separator.
+    pub delimiter: char,
+    /// The first row names the columns instead of holding a rule.
+    pub header: bool,
+    pub src_ip: Option<Column>,
+    pub dst_ip: Option<Column>,
+    pub src_port: Option<Column>,
+    pub dst_port: Option<Column>,
+    pub proto: Option<Column>,
+    pub action: Option<Column>,
+}
+
+impl Default for CsvMapping {
+    /// Comma-separated, with a header naming the columns `src`, `dst`,
+    /// `sport`, `dport`, `proto` and `action`.
+    fn default() -> Self {
+        Self {
+            delimiter: ',',
+            header: true,
+            src_ip: Some("src".into()),
+            dst_ip: Some("dst".into()),
+            src_port: Some("sport".into()),
+            dst_port: Some("dport".into()),
+            proto: Some("proto".into()),
+            action: Some("action".into()),
+        }
+    }
+}
+
+impl CsvMapping {
+    /// Headerless rows with the fields in these positions.
+    pub fn by_index(
+        src_ip: usize,
+        dst_ip: usize,
+        src_port: usize,
+        dst_port: usize,
+        proto: usize,
+        action: usize,
+    ) -> Self {
+        Self {
+            delimiter: ',',
+            header: false,
+            src_ip: Some(src_ip.into()),
+            dst_ip: Some(dst_ip.into()),
+            src_port: Some(src_port.into()),
+            dst_port: Some(dst_port.into()),
+            proto: Some(proto.into()),
+            action: Some(action.into()),
+        }
+    }
+
+    /// Use `delimiter` to separate fields.
+    pub fn with_delimiter(mut self, delimiter: char) -> Self {
+        self.delimiter = delimiter;
+        self
+    }
+}
+
+/// Error while importing a CSV file.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CsvError {
+    /// 1-based line number (0 for errors not tied to a line).
+    pub line: usize,
+    pub kind: CsvErrorKind,
+}
+
+/// What was wrong with the input.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum CsvErrorKind {
+    /// The mapping names a column the header does not have.
+    UnknownColumn(String),
+    /// The mapping names columns but the input has no header row.
+    MissingHeader,
+    /// The row has no column at this position.
+    MissingField(usize),
+    /// A quoted field is not closed.
+    UnterminatedQuote,
+    /// Malformed address, prefix or address range.
+    BadAddress,
+    /// Malformed port or port range.
+    BadPortRange,
+    /// Unknown protocol.
+    BadProtocol,
+    /// Unknown action.
+    BadAction,
+    /// Reading the input failed.
+    Io(std::io::ErrorKind),
+}
+
+impl fmt::Display for CsvError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "line {}: {:?}", self.line, self.kind)
+    }
+}
+
+impl std::error::Error for CsvError {}
+
+/// Import the rules of a CSV document.
+pub fn parse_rules(input: &str, mapping: &CsvMapping) -> Result<Vec<Rule>, CsvError> {
+    let mut importer = Importer::new(mapping);
+    for (i, line) in input.lines().enumerate() {
+        importer.line(i + 1, line)?;
+    }
+    importer.finish()
+}
+
+/// Import the rules of a CSV file, reading it line by line.
+pub fn read_rules(input: impl BufRead, mapping: &CsvMapping) -> Result<Vec<Rule>, CsvError> {
+    let mut importer = Importer::new(mapping);
+    for (i, line) in input.lines().enumerate() {
+        let line = line.map_err(|e| CsvError {
+            line: i + 1,
+            kind: CsvErrorKind::Io(e.kind()),
+        })?;
+        importer.line(i + 1, &line)?;
+    }
+    importer.finish()
+}
+
+/// Column positions of the fields, in `Dimension::ALL` order then the action.
+type Positions = [Option<usize>; 6];
+
+/// Import state: the mapping, resolved once the header is seen.
+struct Importer<'m> {
+    mapping: &'m CsvMapping,
+    positions: Option<Positions>,
+    rules: Vec<Rule>,
+}
+
+impl<'m> Importer<'m> {
+    fn new(mapping: &'m CsvMapping) -> Self {
+        Self {
+            mapping,
+            positions: None,
+            rules: Vec::new(),
+        }
+    }
+
+    /// Positions of the mapped columns, looking names up in `header`.
+    fn resolve(&self, header: Option<&[String]>) -> Result<Positions, CsvErrorKind> {
+        let m = self.mapping;
+        let columns = [
+            &m.src_ip,
+            &m.dst_ip,
+            &m.src_port,
+            &m.dst_port,
+            &m.proto,
+            &m.action,
+        ];
+        let mut positions = [None; 6];
+        for (slot, column) in positions.iter_mut().zip(columns) {
+            *slot = match column {
+                None => None,
+                Some(Column::Index(i)) => Some(*i),
+                Some(Column::Name(name)) => {
+                    let header = header.ok_or(CsvErrorKind::MissingHeader)?;
+                    let found = header
+                        .iter()
+                        .position(|f| f.trim().eq_ignore_ascii_case(name));
+                    Some(found.ok_or_else(|| CsvErrorKind::UnknownColumn(name.clone()))?)
+                }
+            };
+        }
+        Ok(positions)
+    }
+
+    /// Handle line `line_no`.
+    fn line(&mut self, line_no: usize, line: &str) -> Result<(), CsvError> {
+        let trimmed = line.trim();
+        if trimmed.is_empty() || trimmed.starts_with('#') {
+            return Ok(());
+        }
+        let err = |kind| CsvError {
+            line: line_no,
+            kind,
+        };
+        let fields =
+            split(trimmed, self.mapping.delimiter).ok_or(err(CsvErrorKind::UnterminatedQuote))?;
+
+        let positions = match self.positions {
+            Some(positions) => positions,
+            None if self.mapping.header => {
+                self.positions = Some(self.resolve(Some(&fields)).map_err(err)?);
+                return Ok(());
+            }
+            None => *self.positions.insert(self.resolve(None).map_err(err)?),
+        };
+
+        let mut values = [""; 6];
+        for (value, position) in values.iter_mut().zip(positions) {
+            if let Some(i) = position {
+                *value = fields
+                    .get(i)
+                    .ok_or(err(CsvErrorKind::MissingField(i)))?
+                    .trim();
+            }
+        }
+        let [src_ip, dst_ip, src_port, dst_port, proto, action] = values;
+
+        let id = self.rules.len() as u32;
+        self.rules.push(Rule {
+            id,
+            priority: id,
+            src_ip: parse_address(src_ip).ok_or(err(CsvErrorKind::BadAddress))?,
+            dst_ip: parse_address(dst_ip).ok_or(err(CsvErrorKind::BadAddress))?,
+            src_port: parse_ports(src_port).ok_or(err(CsvErrorKind::BadPortRange))?,
+            dst_port: parse_ports(dst_port).ok_or(err(CsvErrorKind::BadPortRange))?,
+            proto: parse_protocol(proto).ok_or(err(CsvErrorKind::BadProtocol))?,
+            zone: ANY_ZONE,
+            bidirectional: false,
+            field_sets: None,
+            action: parse_action(action).ok_or(err(CsvErrorKind::BadAction))?,
+        });
+        Ok(())
+    }
+
+    fn finish(self) -> Result<Vec<Rule>, CsvError> {
+        if self.positions.is_none() && self.mapping.header {
+            return Err(CsvError {
+                line: 0,
+                kind: CsvErrorKind::MissingHeader,
+            });
+        }
+        Ok(self.rules)
+    }
+}
+
+/// Fields of a row, with quotes removed (None if a quote is not closed).
+fn split(line: &str, delimiter: char) -> Option<Vec<String>> {
+    let mut fields = Vec::new();
+    let mut field = String::new();
+    let mut quoted = false;
+    let mut chars = line.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '"' if quoted && chars.peek() == Some(&'"') => {
+                chars.next();
+                field.push('"');
+            }
+            '"' => quoted = !quoted,
+            c if c == delimiter && !quoted => fields.push(core::mem::take(&mut field)),
+            c => field.push(c),
+        }
+    }
+    if quoted {
+        return None;
+    }
+    fields.push(field);
+    Some(fields)
+}
+
+/// True for the values matching everything.
+fn is_any(s: &str) -> bool {
+    s.is_empty() || s == "*" || s.eq_ignore_ascii_case("any")
+}
+
+/// `a.b.c.d`, `a.b.c.d/len` or `a.b.c.d-a.b.c.d` to an address range.
+fn parse_address(s: &str) -> Option<Range<u32>> {
+    if is_any(s) {
+        return Some(Range::any(0, u32::MAX));
+    }
+    let addr = |s: &str| s.trim().parse::<Ipv4Addr>().ok().map(u32::from);
+    if let Some((lo, hi)) = s.split_once('-') {
+        let (lo, hi) = (addr(lo)?, addr(hi)?);
+        return (lo <= hi).then(|| Range::new(lo, hi));
+    }
+    match s.split_once('/') {
+        Some((value, len)) => {
+            let len: u32 = len.trim().parse().ok()?;
+            let value = addr(value)?;
+            (len <= 32).then(|| Prefix::new(value, len).to_range())
+        }
+        None => addr(s).map(Range::exact),
+    }
+}
+
+/// `80`, `lo-hi` or `lo:hi` to a port range.
+fn parse_ports(s: &str) -> Option<Range<u16>> {
+    if is_any(s) {
+        return Some(Range::any(0, u16::MAX));
+    }
+    match s.split_once(['-', ':']) {
+        Some((lo, hi)) => {
+            let (lo, hi) = (lo.trim().parse().ok()?, hi.trim().parse().ok()?);
+            (lo <= hi).then(|| Range::new(lo, hi))
+        }
+        None => s.parse().ok().map(Range::exact),
+    }
+}
+
+/// Protocol number or name to a protocol range.
+fn parse_protocol(s: &str) -> Option<Range<u8>> {
+    if is_any(s) || s.eq_ignore_ascii_case("ip") {
+        return Some(Range::any(0, u8::MAX));
+    }
+    let proto = match protocol_by_name(s) {
+        Some(proto) => proto,
+        None => s.parse().ok()?,
+    };
+    Some(Range::exact(proto))
+}
+
+/// Action keyword (empty = permit).
+fn parse_action(s: &str) -> Option<Action> {
+    match s.to_ascii_lowercase().as_str() {
+        "" | "permit" | "allow" | "accept" => Some(Action::Permit),
+        "deny" | "drop" | "reject" => Some(Action::Deny),
+        _ => None,
+    }
+}
@@ -1,5 +1,14 @@
-use crate::cutsplit::tree::{Dimension, Node};
-use crate::rule::{Range, Rule};
+use crate::classifier::Config;
+use crate::cost::{Cut, CutCost, Replication};
+use crate::cutsplit::tree::Node;
+use crate::dimension::Dimension;
+use crate::dtree::{
+    self, count_split, BuildStats, CutStrategy, NodeCut, TreeParams, WorstCase, WorstCaseError,
+};
+use crate::geometry::Region;
+use crate::heuristic::DimensionHeuristic;
+use crate::leaf::LeafPolicy;
+use crate::rule::Rule;
 use alloc::boxed::Box;
 use alloc::vec::Vec;
 
@@ -12,6 +21,17 @@ pub struct Builder {
     pub leaf_threshold: usize,
     /// Maximum depth of the tree to prevent excessive size/stack usage.
     pub max_depth: usize,
+    /// Stop splitting when the children would hold more than `spfac` times
+    /// the parent's rules (None = no limit).
+    pub spfac: Option<f32>,
+    /// How `leaf_threshold` evolves with depth and duplication.
+    pub leaf_policy: LeafPolicy,
+    /// Leaves with more rules than this get a bit-vector index (None = always linear).
+    pub secondary_threshold: Option<usize>,
+    /// Picks the dimension of each cut (None = try every dimension).
+    pub dimension_heuristic: Option<Box<dyn DimensionHeuristic>>,
+    /// Cost of a candidate cut; the cheapest one is made.
+    pub cut_cost: Box<dyn CutCost>,
 }
 
 impl Builder {
@@ -20,49 +40,122 @@ impl Builder {
         Self {
             leaf_threshold,
             max_depth,
+            spfac: None,
+            leaf_policy: LeafPolicy::Fixed,
+            secondary_threshold: None,
+            dimension_heuristic: None,
+            cut_cost: Box::new(Replication),
         }
     }
 
+    /// Use the given leaf policy instead of a fixed threshold.
+    pub fn with_leaf_policy(mut self, policy: LeafPolicy) -> Self {
+        self.leaf_policy = policy;
+        self
+    }
+
+    /// Index leaves holding more than `threshold` rules with a `BitVectorIndex`.
+    pub fn with_secondary_index(mut self, threshold: usize) -> Self {
+        self.secondary_threshold = Some(threshold);
+        self
+    }
+
+    /// Let `heuristic` choose the dimension of each cut; the cut point is
+    /// then searched on that dimension only.
+    pub fn with_dimension_heuristic(
+        mut self,
+        heuristic: impl DimensionHeuristic + 'static,
+    ) -> Self {
+        self.dimension_heuristic = Some(Box::new(heuristic));
+        self
+    }
+
+    /// Choose cuts by `cost` instead of `Replication`.
+    pub fn with_cut_cost(mut self, cost: impl CutCost + 'static) -> Self {
+        self.cut_cost = Box::new(cost);
+        self
+    }
+
+    /// Stop splitting a node when its children would hold more than `spfac`
+    /// times its rules in total, bounding replication on wildcard-dense sets.
+    pub fn with_spfac(mut self, spfac: f32) -> Self {
+        self.spfac = Some(spfac);
+        self
+    }
+
+    /// Override the parameters `config` sets.
+    pub fn with_config(mut self, config: &Config) -> Self {
+        self.leaf_threshold = config.leaf_threshold.unwrap_or(self.leaf_threshold);
+        self.max_depth = config.max_depth.unwrap_or(self.max_depth);
+        self.spfac = config.spfac.or(self.spfac);
+        self
+    }
+
     /// Build a decision tree from a set of rules.
     pub fn build(&self, rules: &[Rule]) -> Node {
-        self.build_recursive(rules, 0)
+        self.build_with_stats(rules).0
     }
 
-    /// Recursively build the tree.
-    fn build_recursive(&self, rules: &[Rule], depth: usize) -> Node {
-        // Base case: Few enough rules or max depth reached
-        if rules.len() <= self.leaf_threshold || depth >= self.max_depth {
-            return Node::Leaf {
-                rules: rules.to_vec(),
-            };
-        }
+    /// Build the tree and report its shape and stop conditions.
+    pub fn build_with_stats(&self, rules: &[Rule]) -> (Node, BuildStats) {
+        dtree::build(self, rules, &Region::full())
+    }
 
-        // Try to find a good cut
-        if let Some((dim, val)) = self.find_best_cut(rules) {
-            let (left_rules, right_rules) = self.partition_rules(rules, dim, val);
+    /// Build a tree whose lookups stay within `bound`, or fail if none can
+    /// (see `dtree::build_bounded`).
+    pub fn build_bounded(
+        &self,
+        rules: &[Rule],
+        bound: WorstCase,
+    ) -> Result<(Node, BuildStats), WorstCaseError> {
+        dtree::build_bounded(self, rules, &Region::full(), bound)
+    }
 
-            // Heuristic to stop if split is ineffective (e.g., all rules go to one side)
-            // But strict duplication might cause both sides to have many rules if they all overlap.
-            // If both children satisfy base condition check? No, we recurse.
+    /// Build the tree and count the rule copies left out of nodes where a
+    /// better rule covers the whole node, so they could never win there.
+    pub fn build_counting_shadowed(&self, rules: &[Rule]) -> (Node, usize) {
+        let (root, stats) = self.build_with_stats(rules);
+        (root, stats.shadowed_rules)
+    }
+}
 
-            // If we didn't reduce the rule set size in at least one branch effectively, or if we are just duplicating everything:
-            // For now, accept the cut if it exists.
+impl CutStrategy for Builder {
+    type Node = Node;
+    const NAME: &'static str = "cutsplit";
+
+    fn params(&self) -> TreeParams {
+        TreeParams {
+            leaf_threshold: self.leaf_threshold,
+            max_depth: self.max_depth,
+            leaf_policy: self.leaf_policy,
+            secondary_threshold: self.secondary_threshold,
+            spfac: self.spfac,
+        }
+    }
 
-            Node::Internal {
-                dimension: dim,
-                cut_val: val,
-                left: Box::new(self.build_recursive(&left_rules, depth + 1)),
-                right: Box::new(self.build_recursive(&right_rules, depth + 1)),
-            }
-        } else {
-            // No good cut found
-            Node::Leaf {
-                rules: rules.to_vec(),
-            }
+    fn choose_cut(&self, rules: &[&Rule], region: &Region, depth: usize) -> Option<NodeCut> {
+        let (dim, val) = self.find_best_cut(rules, region, depth)?;
+        Some(NodeCut::binary(dim, val))
+    }
+
+    fn internal(&self, cut: &NodeCut, children: Vec<Node>) -> Node {
+        let [left, right] = <[Node; 2]>::try_from(children).expect("binary cut");
+        Node::Internal {
+            dimension: cut.dimension,
+            cut_val: cut.children[1].min,
+            left: Box::new(left),
+            right: Box::new(right),
         }
     }
+}
 
-    fn find_best_cut(&self, rules: &[Rule]) -> Option<(Dimension, u32)> {
+impl Builder {
+    fn find_best_cut(
+        &self,
+        rules: &[&Rule],
+        region: &Region,
+        depth: usize,
+    ) -> Option<(Dimension, u32)> {
         // Simple heuristic: Try to cut on IP/Port dimensions.
         // We look for a median point of start/end points of ranges in these dimensions.
 
@@ -72,14 +165,22 @@ impl Builder {
             Dimension::SrcPort,
             Dimension::DstPort,
         ];
-        let mut best_score = -1.0;
+        let selected;
+        let dimensions: &[Dimension] = match &self.dimension_heuristic {
+            Some(heuristic) => {
+                selected = [heuristic.select(rules, &dimensions, region)?];
+                &selected
+            }
+            None => &dimensions,
+        };
+        let mut best_cost = f32::MAX;
         let mut best_cut = None;
 
-        for &dim in &dimensions {
+        for &dim in dimensions {
             // Collect all endpoints
             let mut points = Vec::new();
             for rule in rules {
-                let range = self.get_range(rule, dim);
+                let range = dim.rule_range(rule);
                 points.push(range.min);
                 points.push(range.max.saturating_add(1)); // Exclusive end
             }
@@ -91,9 +192,7 @@ impl Builder {
             let mid_idx = points.len() / 2;
             if mid_idx > 0 && mid_idx < points.len() {
                 let val = points[mid_idx];
-                // Calculate score: Balance + Duplication penalty
-                // Score = 1 - abs(left_count - right_count)/total - duplication_factor
-                let (l, r) = self.count_split(rules, dim, val);
+                let (l, r) = count_split(rules, dim, val);
 
                 // Avoid useless cuts
                 if l == rules.len() && r == rules.len() {
@@ -103,13 +202,15 @@ impl Builder {
                     continue;
                 } // Pure split not useful if it doesn't separate? Wait, if l=0, all in right.
 
-                let duplication = (l + r) as f32 / rules.len() as f32;
-                // We want minimizing duplication (closer to 1.0) and creating balance.
-                // Let's use negative duplication as score component.
-                let score = 1.0 / duplication;
+                let cost = self.cut_cost.cost(&Cut {
+                    dimension: dim,
+                    depth,
+                    rules: rules.len(),
+                    children: &[l, r],
+                });
 
-                if score > best_score {
-                    best_score = score;
+                if cost < best_cost {
+                    best_cost = cost;
                     best_cut = Some((dim, val));
                 }
             }
@@ -117,51 +218,4 @@ impl Builder {
 
         best_cut
     }
-
-    fn partition_rules(&self, rules: &[Rule], dim: Dimension, val: u32) -> (Vec<Rule>, Vec<Rule>) {
-        let mut left = Vec::new();
-        let mut right = Vec::new();
-
-        for rule in rules {
-            let range = self.get_range(rule, dim);
-
-            // Left child: < val
-            // Range overlaps left if min < val
-            if range.min < val {
-                left.push(rule.clone());
-            }
-
-            // Right child: >= val
-            // Range overlaps right if max >= val
-            if range.max >= val {
-                right.push(rule.clone());
-            }
-        }
-        (left, right)
-    }
-
-    fn count_split(&self, rules: &[Rule], dim: Dimension, val: u32) -> (usize, usize) {
-        let mut l = 0;
-        let mut r = 0;
-        for rule in rules {
-            let range = self.get_range(rule, dim);
-            if range.min < val {
-                l += 1;
-            }
-            if range.max >= val {
-                r += 1;
-            }
-        }
-        (l, r)
-    }
-
-    fn get_range(&self, rule: &Rule, dim: Dimension) -> Range<u32> {
-        match dim {
-            Dimension::SrcIp => rule.src_ip,
-            Dimension::DstIp => rule.dst_ip,
-            Dimension::SrcPort => Range::new(rule.src_port.min as u32, rule.src_port.max as u32),
-            Dimension::DstPort => Range::new(rule.dst_port.min as u32, rule.dst_port.max as u32),
-            Dimension::Proto => Range::new(rule.proto.min as u32, rule.proto.max as u32),
-        }
-    }
 }
@@ -4,19 +4,66 @@
 //! "CutSplit: A Decision-Tree Combining Cutting and Splitting for Scalable Packet Classification"
 //! Wenjun Li, et al. (IEEE INFOCOM 2018)
 //! <https://ieeexplore.ieee.org/document/8464035>
+//!
+//! The builder produces a boxed `Node` tree, which is compiled into a flat
+//! `CompactTree` (16-byte nodes with index children) right after the build
+//! and dropped: lookups walk the flat array and never chase boxes.
 
-use crate::classifier::Classifier;
+use crate::classifier::{Classifier, Config, LookupStats};
+use crate::compact::{CompactTree, FreezeCompact};
 use crate::cutsplit::builder::Builder;
-use crate::cutsplit::tree::{Dimension, Node};
+use crate::dtree::{WorstCase, WorstCaseError};
+use crate::fingerprint::Fingerprint;
+use crate::freeze::Freeze;
+use crate::geometry::Region;
+use crate::memory::{Footprint, MemoryStats};
 use crate::packet::FiveTuple;
+use crate::query::RegionQuery;
 use crate::rule::{Action, Rule};
+use crate::serial::{self, Codec};
+use crate::update::audit::DecodeError;
+use alloc::vec::Vec;
 
 /// CutSplit Packet Classifier.
 ///
 /// Uses a decision tree (HyperCuts-like) to quickly classify packets.
 /// Rules are duplicated into subtrees if they overlap the cut.
+#[derive(Clone)]
 pub struct CutSplitClassifier {
-    root: Node,
+    tree: CompactTree,
+}
+
+impl CutSplitClassifier {
+    /// Build the classifier with a custom-configured `Builder`.
+    pub fn from_builder(builder: &Builder, rules: &[Rule]) -> Self {
+        let root = builder.build(rules);
+        Self {
+            tree: CompactTree::from_root(&root, Some(Fingerprint::of(rules))),
+        }
+    }
+
+    /// Build the classifier with `builder`, guaranteeing every lookup stays
+    /// within `bound`; fails if the rules cannot be classified within it.
+    pub fn from_builder_bounded(
+        builder: &Builder,
+        rules: &[Rule],
+        bound: WorstCase,
+    ) -> Result<Self, WorstCaseError> {
+        let (root, _) = builder.build_bounded(rules, bound)?;
+        Ok(Self {
+            tree: CompactTree::from_root(&root, Some(Fingerprint::of(rules))),
+        })
+    }
+
+    /// Fingerprint of the rules the tree was built from.
+    pub fn fingerprint(&self) -> Option<Fingerprint> {
+        self.tree.fingerprint()
+    }
+
+    /// The flat tree lookups walk.
+    pub fn tree(&self) -> &CompactTree {
+        &self.tree
+    }
 }
 
 impl Classifier for CutSplitClassifier {
@@ -24,50 +71,70 @@ impl Classifier for CutSplitClassifier {
     ///
     /// Constructs the decision tree using the `Builder` with default settings (threshold=10, depth=20).
     fn build(rules: &[Rule]) -> Self {
+        Self::build_with_config(rules, &Config::default())
+    }
+
+    fn build_with_config(rules: &[Rule], config: &Config) -> Self {
         // CutSplit builder params
         // Threshold: typically 8-16 rules for linear scan in leaf
         // Depth: prevent stack overflow
-        let builder = Builder::new(10, 20);
-        let root = builder.build(rules);
-        Self { root }
+        Self::from_builder(&Builder::new(10, 20).with_config(config), rules)
     }
 
     /// Classify the packet using the decision tree.
-    fn classify(&self, packet: &FiveTuple) -> Option<Action> {
-        let mut current = &self.root;
-
-        loop {
-            match current {
-                Node::Internal {
-                    dimension,
-                    cut_val,
-                    left,
-                    right,
-                } => {
-                    let val = match dimension {
-                        Dimension::SrcIp => packet.src_ip,
-                        Dimension::DstIp => packet.dst_ip,
-                        Dimension::SrcPort => packet.src_port as u32,
-                        Dimension::DstPort => packet.dst_port as u32,
-                        Dimension::Proto => packet.proto as u32,
-                    };
-
-                    if val < *cut_val {
-                        current = left;
-                    } else {
-                        current = right;
-                    }
-                }
-                Node::Leaf { rules } => {
-                    // Linear search in leaf
-                    for rule in rules {
-                        if rule.matches(packet) {
-                            return Some(rule.action);
-                        }
-                    }
-                    return None;
-                }
-            }
-        }
+    fn classify_rule(&self, packet: &FiveTuple) -> Option<&Rule> {
+        self.tree.classify_rule(packet)
+    }
+
+    fn classify_batch(&self, packets: &[FiveTuple], out: &mut [Option<Action>]) {
+        self.tree.classify_batch(packets, out)
+    }
+
+    fn classify_with_stats(&self, packet: &FiveTuple) -> (Option<Action>, LookupStats) {
+        self.tree.classify_with_stats(packet)
+    }
+
+    fn classify_rule_with_stats(&self, packet: &FiveTuple) -> (Option<&Rule>, LookupStats) {
+        self.tree.classify_rule_with_stats(packet)
+    }
+}
+
+impl RegionQuery for CutSplitClassifier {
+    fn rules_overlapping(&self, region: &Region) -> Vec<&Rule> {
+        self.tree.rules_overlapping(region)
+    }
+}
+
+impl MemoryStats for CutSplitClassifier {
+    fn footprint(&self) -> Footprint {
+        self.tree.footprint()
+    }
+}
+
+impl Freeze for CutSplitClassifier {
+    fn shrink_to_fit(&mut self) {
+        self.tree.shrink_to_fit();
+    }
+}
+
+impl FreezeCompact for CutSplitClassifier {
+    fn to_compact(&self) -> CompactTree {
+        self.tree.clone()
+    }
+}
+
+impl Codec for CutSplitClassifier {
+    fn serialize(&self) -> Vec<u8> {
+        let mut out = serial::header(serial::CUTSPLIT, self.fingerprint());
+        self.tree.encode_body(&mut out);
+        out
+    }
+
+    fn deserialize(bytes: &[u8]) -> Result<Self, DecodeError> {
+        let mut r = serial::reader(bytes);
+        let fingerprint = serial::open(&mut r, serial::CUTSPLIT)?;
+        let tree = CompactTree::decode_body(&mut r, fingerprint)?;
+        serial::finish(&r)?;
+        Ok(Self { tree })
     }
 }
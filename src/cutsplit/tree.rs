@@ -1,24 +1,17 @@
+use crate::compact::{BinaryNode, NodeView};
+pub use crate::dimension::Dimension;
+use crate::dtree::TreeNode;
+use crate::leaf::BitVectorIndex;
 use crate::rule::Rule;
 use alloc::boxed::Box;
 use alloc::vec::Vec;
 
-/// Dimensions to cut on.
-///
-/// Use to select which field of the 5-tuple to split the search space.
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
-pub enum Dimension {
-    SrcIp,
-    DstIp,
-    SrcPort,
-    DstPort,
-    Proto,
-}
-
 /// A node in the CutSplit decision tree.
 ///
 /// Can be:
 /// - `Internal`: A node that splits traffic based on a dimension and value.
 /// - `Leaf`: A node containing a list of rules to match linearly.
+/// - `IndexedLeaf`: A large leaf searched through a `BitVectorIndex`.
 #[derive(Debug, Clone)]
 pub enum Node {
     /// Internal node performing a cut.
@@ -40,11 +33,82 @@ pub enum Node {
         /// Should be checked linearly in priority order.
         rules: Vec<Rule>,
     },
+    /// Oversized leaf backed by a bit-vector index instead of a linear list.
+    IndexedLeaf {
+        /// Secondary structure holding the leaf's rules.
+        index: Box<BitVectorIndex>,
+    },
 }
 
 impl Node {
     /// Returns true if the node is a Leaf.
     pub fn is_leaf(&self) -> bool {
-        matches!(self, Node::Leaf { .. })
+        matches!(self, Node::Leaf { .. } | Node::IndexedLeaf { .. })
+    }
+
+    /// Release spare capacity in the whole subtree.
+    pub fn shrink_to_fit(&mut self) {
+        match self {
+            Node::Internal { left, right, .. } => {
+                left.shrink_to_fit();
+                right.shrink_to_fit();
+            }
+            Node::Leaf { rules } => rules.shrink_to_fit(),
+            Node::IndexedLeaf { index } => index.shrink_to_fit(),
+        }
+    }
+
+    /// Heap bytes held by the subtree.
+    pub fn heap_bytes(&self) -> usize {
+        match self {
+            Node::Internal { left, right, .. } => {
+                2 * size_of::<Node>() + left.heap_bytes() + right.heap_bytes()
+            }
+            Node::Leaf { rules } => rules.capacity() * size_of::<Rule>(),
+            Node::IndexedLeaf { index } => size_of::<BitVectorIndex>() + index.heap_bytes(),
+        }
+    }
+}
+
+impl BinaryNode for Node {
+    fn view(&self) -> NodeView<'_, Self> {
+        match self {
+            Node::Internal {
+                dimension,
+                cut_val,
+                left,
+                right,
+            } => NodeView::Internal {
+                dimension: *dimension,
+                cut: *cut_val,
+                left,
+                right,
+            },
+            Node::Leaf { rules } => NodeView::Leaf(rules),
+            Node::IndexedLeaf { index } => NodeView::IndexedLeaf(index),
+        }
+    }
+
+    fn internal(dimension: Dimension, cut: u32, left: Self, right: Self) -> Self {
+        Node::Internal {
+            dimension,
+            cut_val: cut,
+            left: Box::new(left),
+            right: Box::new(right),
+        }
+    }
+}
+
+impl TreeNode for Node {
+    fn leaf(rules: Vec<Rule>) -> Self {
+        Node::Leaf { rules }
+    }
+
+    fn indexed_leaf(index: Box<BitVectorIndex>) -> Self {
+        Node::IndexedLeaf { index }
+    }
+
+    fn heap_bytes(&self) -> usize {
+        Node::heap_bytes(self)
     }
 }
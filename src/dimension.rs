@@ -0,0 +1,160 @@
+//! Packet fields (dimensions) shared by every algorithm.
+//!
+//! Algorithms never match on the variants of `Dimension`: they iterate
+//! `Dimension::ALL`, access fields through `rule_range`, `set_rule_range`,
+//! `packet_value` and `set_packet_value` (every field widened to `u32`),
+//! bound cuts with `max_value`, and keep per-field data in
+//! `[T; Dimension::COUNT]` arrays indexed by `index`. All of these read the
+//! field's `FieldSpec` in `FIELDS`, so adding a field (VLAN, DSCP, metadata)
+//! means a variant and a `FIELDS` entry here, plus the field itself in
+//! `Rule`, `FieldSets` and `FiveTuple`; the classifiers pick it up unchanged.
+
+use crate::packet::FiveTuple;
+use crate::rule::{Range, Rule};
+
+/// Packet fields rules match on.
+///
+/// Used to select which field of the 5-tuple to split the search space.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Dimension {
+    SrcIp,
+    DstIp,
+    SrcPort,
+    DstPort,
+    Proto,
+}
+
+impl Dimension {
+    /// Number of dimensions.
+    pub const COUNT: usize = 5;
+
+    /// All dimensions, in 5-tuple order.
+    pub const ALL: [Dimension; Dimension::COUNT] = [
+        Dimension::SrcIp,
+        Dimension::DstIp,
+        Dimension::SrcPort,
+        Dimension::DstPort,
+        Dimension::Proto,
+    ];
+
+    /// Position of the dimension in 5-tuple order (index into `ALL`).
+    pub fn index(self) -> usize {
+        self as usize
+    }
+
+    /// Dimension at `index` in 5-tuple order.
+    pub fn from_index(index: usize) -> Option<Dimension> {
+        Dimension::ALL.get(index).copied()
+    }
+
+    /// How the field is stored in rules and packets.
+    pub fn spec(self) -> &'static FieldSpec {
+        &FIELDS[self.index()]
+    }
+
+    /// Short name of the field.
+    pub fn name(self) -> &'static str {
+        self.spec().name
+    }
+
+    /// The rule's range on this dimension, widened to `u32`.
+    pub fn rule_range(self, rule: &Rule) -> Range<u32> {
+        (self.spec().rule_range)(rule)
+    }
+
+    /// Set the rule's range on this dimension (narrowed to the field's type).
+    pub fn set_rule_range(self, rule: &mut Rule, range: Range<u32>) {
+        (self.spec().set_rule_range)(rule, range)
+    }
+
+    /// The packet's value on this dimension, widened to `u32`.
+    pub fn packet_value(self, packet: &FiveTuple) -> u32 {
+        (self.spec().packet_value)(packet)
+    }
+
+    /// Set the packet's value on this dimension (narrowed to the field's type).
+    pub fn set_packet_value(self, packet: &mut FiveTuple, value: u32) {
+        (self.spec().set_packet_value)(packet, value)
+    }
+
+    /// Largest value of the field.
+    pub fn max_value(self) -> u32 {
+        self.spec().max_value()
+    }
+}
+
+/// Accessors and width of one field: the single place that knows where the
+/// field lives in `Rule` and `FiveTuple`.
+#[derive(Debug, Clone, Copy)]
+pub struct FieldSpec {
+    /// The field's dimension.
+    pub dimension: Dimension,
+    /// Short name of the field.
+    pub name: &'static str,
+    /// Width of the field in bits (at most 32).
+    pub bits: u32,
+    /// The rule's range, widened to `u32`.
+    pub rule_range: fn(&Rule) -> Range<u32>,
+    /// Set the rule's range (narrowed to the field's type).
+    pub set_rule_range: fn(&mut Rule, Range<u32>),
+    /// The packet's value, widened to `u32`.
+    pub packet_value: fn(&FiveTuple) -> u32,
+    /// Set the packet's value (narrowed to the field's type).
+    pub set_packet_value: fn(&mut FiveTuple, u32),
+}
+
+impl FieldSpec {
+    /// Largest value of the field.
+    pub fn max_value(&self) -> u32 {
+        u32::MAX >> (32 - self.bits)
+    }
+}
+
+/// Every field, in `Dimension::ALL` order.
+pub static FIELDS: [FieldSpec; Dimension::COUNT] = [
+    FieldSpec {
+        dimension: Dimension::SrcIp,
+        name: "src_ip",
+        bits: 32,
+        rule_range: |r| r.src_ip,
+        set_rule_range: |r, v| r.src_ip = v,
+        packet_value: |p| p.src_ip,
+        set_packet_value: |p, v| p.src_ip = v,
+    },
+    FieldSpec {
+        dimension: Dimension::DstIp,
+        name: "dst_ip",
+        bits: 32,
+        rule_range: |r| r.dst_ip,
+        set_rule_range: |r, v| r.dst_ip = v,
+        packet_value: |p| p.dst_ip,
+        set_packet_value: |p, v| p.dst_ip = v,
+    },
+    FieldSpec {
+        dimension: Dimension::SrcPort,
+        name: "src_port",
+        bits: 16,
+        rule_range: |r| Range::new(r.src_port.min as u32, r.src_port.max as u32),
+        set_rule_range: |r, v| r.src_port = Range::new(v.min as u16, v.max as u16),
+        packet_value: |p| p.src_port as u32,
+        set_packet_value: |p, v| p.src_port = v as u16,
+    },
+    FieldSpec {
+        dimension: Dimension::DstPort,
+        name: "dst_port",
+        bits: 16,
+        rule_range: |r| Range::new(r.dst_port.min as u32, r.dst_port.max as u32),
+        set_rule_range: |r, v| r.dst_port = Range::new(v.min as u16, v.max as u16),
+        packet_value: |p| p.dst_port as u32,
+        set_packet_value: |p, v| p.dst_port = v as u16,
+    },
+    FieldSpec {
+        dimension: Dimension::Proto,
+        name: "proto",
+        bits: 8,
+        rule_range: |r| Range::new(r.proto.min as u32, r.proto.max as u32),
+        set_rule_range: |r, v| r.proto = Range::new(v.min as u8, v.max as u8),
+        packet_value: |p| p.proto as u32,
+        set_packet_value: |p, v| p.proto = v as u8,
+    },
+];
@@ -0,0 +1,399 @@
+//! Decision-tree construction shared by the cutting algorithms.
+//!
+//! CutSplit, HiCuts and HyperSplit differ only in how they cut a node; the
+//! rest of the build is common and lives here: tracking the rules reaching
+//! each node (as `RuleSet`s) and the node's region, dropping rules shadowed
+//! inside it, the leaf threshold and depth limit, partitioning the rules
+//! among the children, replication limits, leaves (plain or indexed) and
+//! build statistics. An algorithm implements `CutStrategy` (choose a cut,
+//! assemble an internal node) and `TreeNode` for its node type, and `build`
+//! does the rest.
+//!
+//! Leaves list their rules by priority (rules of equal priority in input
+//! order), so a lookup returns the first rule of its leaf that matches.
+//!
+//! `build_bounded` builds under a `WorstCase` bound instead: the tree is
+//! guaranteed to cap the work of every lookup, or is not built at all, so a
+//! classifier can be certified against a fixed cycle budget.
+
+use crate::dimension::Dimension;
+use crate::geometry::Region;
+use crate::leaf::{BitVectorIndex, LeafPolicy};
+use crate::rule::{expand_rules, Range, Rule, ANY_ZONE};
+use crate::ruleset::{RuleSet, RuleStore};
+use crate::trace::build_trace;
+use alloc::borrow::Cow;
+use alloc::boxed::Box;
+use alloc::vec::Vec;
+
+/// Shape of a built tree and why its branches stopped splitting.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct BuildStats {
+    /// Number of internal nodes.
+    pub internal_nodes: usize,
+    /// Number of leaves.
+    pub leaves: usize,
+    /// Rule copies held by the leaves.
+    pub stored_rules: usize,
+    /// Depth of the deepest leaf.
+    pub max_depth: usize,
+    /// Leaves small enough for the leaf threshold.
+    pub threshold_stops: usize,
+    /// Leaves cut off by `max_depth`.
+    pub depth_stops: usize,
+    /// Leaves no split could divide.
+    pub no_split_stops: usize,
+    /// Leaves whose best split replicated more than `spfac` allows.
+    pub replication_stops: usize,
+    /// Rule copies left out of nodes where a better rule covers the whole
+    /// node, so they could never win there.
+    pub shadowed_rules: usize,
+    /// Most rules a lookup can compare the packet against in one leaf.
+    pub max_leaf_compares: usize,
+    /// Rules held by the largest leaf.
+    pub largest_leaf: usize,
+}
+
+/// Limits common to every tree builder.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct TreeParams {
+    /// Max rules in a leaf (before `leaf_policy` adjusts it).
+    pub leaf_threshold: usize,
+    /// Maximum depth of the tree.
+    pub max_depth: usize,
+    /// How `leaf_threshold` evolves with depth and duplication.
+    pub leaf_policy: LeafPolicy,
+    /// Leaves with more rules than this get a bit-vector index (None = always linear).
+    pub secondary_threshold: Option<usize>,
+    /// Make a leaf instead of a cut whose children would hold more than
+    /// `spfac` times the node's rules (None = no limit).
+    pub spfac: Option<f32>,
+}
+
+/// Hard limits on the work of any lookup in a tree.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct WorstCase {
+    /// Most internal nodes a lookup traverses.
+    pub max_depth: usize,
+    /// Most rules a lookup compares the packet against in a leaf. Larger
+    /// leaves get a bit-vector index, which only compares rules bound to a
+    /// zone (the zone is not indexed) and the winner.
+    pub max_leaf_compares: usize,
+    /// Most rules an indexed leaf may hold: a lookup ANDs `rules / 64`
+    /// bitmap words per field there.
+    pub max_indexed_rules: usize,
+}
+
+/// Why a tree cannot be built within its `WorstCase` bound.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WorstCaseError {
+    /// A leaf at `depth` would compare a packet against `compares` rules.
+    LeafCompares { depth: usize, compares: usize },
+    /// An indexed leaf at `depth` would hold `rules` rules.
+    IndexedRules { depth: usize, rules: usize },
+}
+
+/// How a node is cut: along one dimension, each child taking a range of
+/// values. The ranges are contiguous, ascending and together span every
+/// value the node can see; they may extend beyond the node's region.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct NodeCut {
+    /// Dimension being cut.
+    pub dimension: Dimension,
+    /// Values sent to each child, in order.
+    pub children: Vec<Range<u32>>,
+}
+
+impl NodeCut {
+    /// Two children: values below `value`, and `value` and above.
+    pub fn binary(dimension: Dimension, value: u32) -> Self {
+        debug_assert!(value > 0);
+        Self {
+            dimension,
+            children: alloc::vec![
+                Range::new(0, value - 1),
+                Range::new(value, dimension.max_value()),
+            ],
+        }
+    }
+}
+
+/// Node type of a decision tree.
+pub trait TreeNode {
+    /// Leaf scanning `rules` (by priority, first match wins).
+    fn leaf(rules: Vec<Rule>) -> Self;
+    /// Leaf backed by a bit-vector index.
+    fn indexed_leaf(index: Box<BitVectorIndex>) -> Self;
+    /// Heap bytes held by the subtree, for build reports (0 if untracked).
+    fn heap_bytes(&self) -> usize {
+        0
+    }
+}
+
+/// The cutting rule of a decision-tree algorithm.
+pub trait CutStrategy {
+    /// Node type of the trees built.
+    type Node: TreeNode;
+    /// Name used in build traces.
+    const NAME: &'static str;
+
+    /// Limits of the build.
+    fn params(&self) -> TreeParams;
+
+    /// Cut for a node over `rules` covering `region` at `depth`, or None to
+    /// make it a leaf.
+    fn choose_cut(&self, rules: &[&Rule], region: &Region, depth: usize) -> Option<NodeCut>;
+
+    /// Internal node for `cut` over the built `children`.
+    fn internal(&self, cut: &NodeCut, children: Vec<Self::Node>) -> Self::Node;
+}
+
+/// Build a tree over `rules`, covering `region`.
+pub fn build<S: CutStrategy>(
+    strategy: &S,
+    rules: &[Rule],
+    region: &Region,
+) -> (S::Node, BuildStats) {
+    let rules = leaf_order(rules);
+    build_trace!("{}: building tree over {} rules", S::NAME, rules.len());
+    let mut tree = Tree {
+        strategy,
+        params: strategy.params(),
+        store: RuleStore::new(&rules),
+        stats: BuildStats::default(),
+        bound: None,
+        violation: None,
+    };
+    tree.run(region)
+}
+
+/// Build a tree over `rules`, covering `region`, whose lookups stay within
+/// `bound`: the strategy's depth limit is lowered to the bound, and leaves
+/// comparing more rules than allowed are indexed. Fails if a leaf still
+/// exceeds the bound (too many zone-bound rules, or too many rules to index).
+pub fn build_bounded<S: CutStrategy>(
+    strategy: &S,
+    rules: &[Rule],
+    region: &Region,
+    bound: WorstCase,
+) -> Result<(S::Node, BuildStats), WorstCaseError> {
+    let rules = leaf_order(rules);
+    build_trace!(
+        "{}: building bounded tree over {} rules",
+        S::NAME,
+        rules.len()
+    );
+    let mut params = strategy.params();
+    params.max_depth = params.max_depth.min(bound.max_depth);
+    params.secondary_threshold = Some(
+        params
+            .secondary_threshold
+            .map_or(bound.max_leaf_compares, |t| t.min(bound.max_leaf_compares)),
+    );
+    let mut tree = Tree {
+        strategy,
+        params,
+        store: RuleStore::new(&rules),
+        stats: BuildStats::default(),
+        bound: Some(bound),
+        violation: None,
+    };
+    let built = tree.run(region);
+    match tree.violation {
+        Some(err) => Err(err),
+        None => Ok(built),
+    }
+}
+
+/// The plain rules of `rules`, sorted by priority (stably, so rules of equal
+/// priority keep their order): the order of every leaf's rules.
+fn leaf_order(rules: &[Rule]) -> Cow<'_, [Rule]> {
+    let mut rules = expand_rules(rules);
+    if !rules.is_sorted_by_key(|r| r.priority) {
+        rules.to_mut().sort_by_key(|r| r.priority);
+    }
+    rules
+}
+
+/// Region of the child taking `values` on `dim`. A child whose values miss
+/// the region gets a sliver on the region's edge; no packet reaches it, so
+/// its exact bounds do not matter.
+pub fn child_region(region: &Region, dim: Dimension, values: Range<u32>) -> Region {
+    let bounds = region.get(dim);
+    let min = values.min.clamp(bounds.min, bounds.max);
+    let max = values.max.clamp(min, bounds.max);
+    region.with(dim, Range::new(min, max))
+}
+
+/// Returns true if `rule` matches some value of `values` on `dim`.
+pub fn rule_reaches(rule: &Rule, dim: Dimension, values: &Range<u32>) -> bool {
+    let range = dim.rule_range(rule);
+    range.min <= values.max && range.max >= values.min
+}
+
+/// Number of `rules` reaching below `value` on `dim`, and reaching `value`
+/// or above: the children sizes of a binary cut there.
+pub fn count_split(rules: &[&Rule], dim: Dimension, value: u32) -> (usize, usize) {
+    let mut left = 0;
+    let mut right = 0;
+    for rule in rules {
+        let range = dim.rule_range(rule);
+        if range.min < value {
+            left += 1;
+        }
+        if range.max >= value {
+            right += 1;
+        }
+    }
+    (left, right)
+}
+
+/// State of one build.
+struct Tree<'s, 'r, S> {
+    strategy: &'s S,
+    params: TreeParams,
+    store: RuleStore<'r>,
+    stats: BuildStats,
+    bound: Option<WorstCase>,
+    violation: Option<WorstCaseError>,
+}
+
+impl<S: CutStrategy> Tree<'_, '_, S> {
+    /// Build the tree over every rule.
+    fn run(&mut self, region: &Region) -> (S::Node, BuildStats) {
+        let set = self.store.full();
+        let root = self.node(set, region, 0, 1.0);
+        self.stats.shadowed_rules = self.store.shadowed();
+        (root, self.stats)
+    }
+
+    /// Build the node over `set` covering `region`; `pressure` is the
+    /// duplication ratio of the cut that produced it.
+    fn node(&mut self, mut set: RuleSet, region: &Region, depth: usize, pressure: f32) -> S::Node {
+        if self.violation.is_some() {
+            // The tree is thrown away; stop building it.
+            return S::Node::leaf(Vec::new());
+        }
+        self.store.drop_shadowed(&mut set, region);
+        let threshold =
+            self.params
+                .leaf_policy
+                .threshold(self.params.leaf_threshold, depth, pressure);
+        if set.len() <= threshold {
+            self.stats.threshold_stops += 1;
+            return self.leaf(&set, depth);
+        }
+        if depth >= self.params.max_depth {
+            self.stats.depth_stops += 1;
+            return self.leaf(&set, depth);
+        }
+
+        let cut = self
+            .strategy
+            .choose_cut(&self.store.select(&set), region, depth);
+        let Some(cut) = cut else {
+            self.stats.no_split_stops += 1;
+            return self.leaf(&set, depth);
+        };
+
+        let children: Vec<RuleSet> = cut
+            .children
+            .iter()
+            .map(|values| self.partition(&set, cut.dimension, values))
+            .collect();
+        if children.iter().all(|c| c.len() == set.len()) {
+            self.stats.no_split_stops += 1;
+            return self.leaf(&set, depth);
+        }
+        let copies: usize = children.iter().map(RuleSet::len).sum();
+        if self
+            .params
+            .spfac
+            .is_some_and(|spfac| copies as f32 > spfac * set.len() as f32)
+        {
+            build_trace!(
+                "{}: split of {} rules into {} copies exceeds spfac at depth {}",
+                S::NAME,
+                set.len(),
+                copies,
+                depth
+            );
+            self.stats.replication_stops += 1;
+            return self.leaf(&set, depth);
+        }
+
+        let pressure = copies as f32 / set.len() as f32;
+        drop(set);
+        self.stats.internal_nodes += 1;
+        let nodes = children
+            .into_iter()
+            .zip(&cut.children)
+            .map(|(child, &values)| {
+                let region = child_region(region, cut.dimension, values);
+                self.node(child, &region, depth + 1, pressure)
+            })
+            .collect();
+        self.strategy.internal(&cut, nodes)
+    }
+
+    /// Rules of `set` reaching some value of `values` on `dim`.
+    fn partition(&self, set: &RuleSet, dim: Dimension, values: &Range<u32>) -> RuleSet {
+        let mut child = self.store.empty();
+        for i in set.iter() {
+            if rule_reaches(self.store.get(i), dim, values) {
+                child.insert(i);
+            }
+        }
+        child
+    }
+
+    /// Leaf over `set`, indexed if it is oversized.
+    fn leaf(&mut self, set: &RuleSet, depth: usize) -> S::Node {
+        self.stats.leaves += 1;
+        self.stats.stored_rules += set.len();
+        self.stats.max_depth = self.stats.max_depth.max(depth);
+        self.stats.largest_leaf = self.stats.largest_leaf.max(set.len());
+        let indexed = self
+            .params
+            .secondary_threshold
+            .is_some_and(|limit| set.len() > limit);
+        let compares = if indexed {
+            // Only zone-bound candidates can fail the zone check.
+            let zoned = set
+                .iter()
+                .filter(|&i| self.store.get(i).zone != ANY_ZONE)
+                .count();
+            set.len().min(zoned + 1)
+        } else {
+            set.len()
+        };
+        self.stats.max_leaf_compares = self.stats.max_leaf_compares.max(compares);
+        if let Some(bound) = self.bound {
+            if compares > bound.max_leaf_compares {
+                self.violation = Some(WorstCaseError::LeafCompares { depth, compares });
+            } else if indexed && set.len() > bound.max_indexed_rules {
+                self.violation = Some(WorstCaseError::IndexedRules {
+                    depth,
+                    rules: set.len(),
+                });
+            }
+        }
+        if indexed {
+            build_trace!(
+                "{}: indexed leaf of {} rules at depth {}",
+                S::NAME,
+                set.len(),
+                depth
+            );
+            S::Node::indexed_leaf(self.store.leaf_index(set))
+        } else {
+            build_trace!(
+                "{}: leaf of {} rules at depth {}",
+                S::NAME,
+                set.len(),
+                depth
+            );
+            S::Node::leaf(self.store.leaf_rules(set))
+        }
+    }
+}
@@ -0,0 +1,222 @@
+//! EffiCuts-style rule separation before tree construction.
+//!
+//! Based on the paper:
+//! "EffiCuts: Optimizing Packet Classification for Memory and Throughput"
+//! Vamanan, et al. (SIGCOMM 2010)
+//! <https://dl.acm.org/doi/10.1145/1851182.1851208>
+//!
+//! A tree that cuts a field must copy every rule spanning the cut into both
+//! sides, and wildcard-heavy rules span almost every cut: mixed with narrow
+//! rules, they are what makes decision trees blow up on real ACLs. `EffiCuts`
+//! sorts the rules into categories by which fields are large (cover at least
+//! `largeness` of the field) and builds one tree per category, so each tree
+//! only cuts fields that are small in all its rules. Categories too small to
+//! be worth a tree of their own share one.
+//!
+//! A lookup searches the trees by the best rule each holds and stops once
+//! its match outranks every remaining tree.
+
+use crate::classifier::{Classifier, LookupStats};
+use crate::dimension::Dimension;
+use crate::freeze::Freeze;
+use crate::geometry::Region;
+use crate::packet::FiveTuple;
+use crate::query::{Overlapping, RegionQuery};
+use crate::rule::{expand_rules, Action, Rule};
+use alloc::vec::Vec;
+
+/// How rules are separated.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct EffiCutsParams {
+    /// Fraction of a field a range must cover to be large.
+    pub largeness: f64,
+    /// Categories with fewer rules are merged into one.
+    pub min_category: usize,
+}
+
+impl Default for EffiCutsParams {
+    /// Half of a field is large; categories need 16 rules.
+    fn default() -> Self {
+        Self {
+            largeness: 0.5,
+            min_category: 16,
+        }
+    }
+}
+
+/// Set of fields that are large, one bit per dimension index.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub struct Pattern(pub u8);
+
+impl Pattern {
+    /// Pattern of a plain rule.
+    pub fn of(rule: &Rule, largeness: f64) -> Self {
+        let mut bits = 0;
+        for dim in Dimension::ALL {
+            let range = dim.rule_range(rule);
+            let size = (range.max - range.min) as f64 + 1.0;
+            let field = dim.max_value() as f64 + 1.0;
+            if size >= largeness * field {
+                bits |= 1 << dim.index();
+            }
+        }
+        Self(bits)
+    }
+
+    /// Whether `dim` is large.
+    pub fn is_large(self, dim: Dimension) -> bool {
+        self.0 & (1 << dim.index()) != 0
+    }
+}
+
+/// Classifier built as one tree per wildcard pattern.
+#[derive(Clone)]
+pub struct EffiCuts<C> {
+    /// Classifiers, by best priority.
+    classifiers: Vec<C>,
+    /// Large fields of each classifier's rules (the union for merged
+    /// categories).
+    patterns: Vec<Pattern>,
+    sizes: Vec<usize>,
+    /// Best priority held by each classifier.
+    best: Vec<u32>,
+    params: EffiCutsParams,
+}
+
+impl<C: Classifier> EffiCuts<C> {
+    /// Separate `rules` with `params` and build each category with `build`,
+    /// e.g. `EffiCuts::build_with(&rules, params, |r| HiCutsClassifier::from_builder(&b, r))`.
+    pub fn build_with(
+        rules: &[Rule],
+        params: EffiCutsParams,
+        build: impl Fn(&[Rule]) -> C,
+    ) -> Self {
+        let mut parts: Vec<_> = categorize(&expand_rules(rules), params)
+            .into_iter()
+            .map(|(pattern, part)| {
+                let best = part.iter().map(|r| r.priority).min().unwrap_or(u32::MAX);
+                (best, pattern, part)
+            })
+            .collect();
+        parts.sort_by_key(|&(best, ..)| best);
+        Self {
+            classifiers: parts.iter().map(|(_, _, part)| build(part)).collect(),
+            patterns: parts.iter().map(|&(_, pattern, _)| pattern).collect(),
+            sizes: parts.iter().map(|(_, _, part)| part.len()).collect(),
+            best: parts.iter().map(|&(best, ..)| best).collect(),
+            params,
+        }
+    }
+
+    /// The parameters the classifier was built with.
+    pub fn params(&self) -> EffiCutsParams {
+        self.params
+    }
+
+    /// The per-category classifiers, by best priority.
+    pub fn classifiers(&self) -> &[C] {
+        &self.classifiers
+    }
+
+    /// Large fields of each category.
+    pub fn patterns(&self) -> &[Pattern] {
+        &self.patterns
+    }
+
+    /// Number of rules held by each category.
+    pub fn category_sizes(&self) -> &[usize] {
+        &self.sizes
+    }
+
+    /// Search the categories until no remaining one can beat the match,
+    /// calling `probe` on each one searched.
+    fn lookup(&self, packet: &FiveTuple, mut probe: impl FnMut(&C)) -> Option<&Rule> {
+        let mut best_match: Option<&Rule> = None;
+        for (classifier, &best) in self.classifiers.iter().zip(&self.best) {
+            if best_match.is_some_and(|m| m.priority <= best) {
+                break;
+            }
+            probe(classifier);
+            if let Some(rule) = classifier.classify_rule(packet) {
+                if best_match.is_none_or(|m| rule.priority < m.priority) {
+                    best_match = Some(rule);
+                }
+            }
+        }
+        best_match
+    }
+}
+
+/// Sort plain `rules` into categories by pattern, merging the small ones.
+/// Each category keeps the input order of its rules.
+pub fn categorize(rules: &[Rule], params: EffiCutsParams) -> Vec<(Pattern, Vec<Rule>)> {
+    let mut parts: Vec<(Pattern, Vec<usize>)> = Vec::new();
+    for (i, rule) in rules.iter().enumerate() {
+        let pattern = Pattern::of(rule, params.largeness);
+        match parts.iter_mut().find(|(p, _)| *p == pattern) {
+            Some((_, part)) => part.push(i),
+            None => parts.push((pattern, alloc::vec![i])),
+        }
+    }
+    let (mut kept, small): (Vec<_>, Vec<_>) = parts
+        .into_iter()
+        .partition(|(_, part)| part.len() >= params.min_category);
+    if !small.is_empty() {
+        let pattern = Pattern(small.iter().fold(0, |bits, (p, _)| bits | p.0));
+        let mut merged: Vec<usize> = small.into_iter().flat_map(|(_, part)| part).collect();
+        merged.sort_unstable();
+        kept.push((pattern, merged));
+    }
+    kept.into_iter()
+        .map(|(pattern, part)| {
+            (
+                pattern,
+                part.into_iter().map(|i| rules[i].clone()).collect(),
+            )
+        })
+        .collect()
+}
+
+impl<C: Classifier> Classifier for EffiCuts<C> {
+    fn build(rules: &[Rule]) -> Self {
+        Self::build_with(rules, EffiCutsParams::default(), C::build)
+    }
+
+    fn classify_rule(&self, packet: &FiveTuple) -> Option<&Rule> {
+        self.lookup(packet, |_| {})
+    }
+
+    fn classify_with_stats(&self, packet: &FiveTuple) -> (Option<Action>, LookupStats) {
+        // Stats-only path: picking the winner needs rule priorities, hence the
+        // extra lookup. The category dispatch counts as one level.
+        let mut stats = LookupStats {
+            depth: 1,
+            ..LookupStats::default()
+        };
+        let rule = self.lookup(packet, |c| {
+            let (_, s) = c.classify_with_stats(packet);
+            stats.depth = stats.depth.max(s.depth + 1);
+            stats.rules_compared += s.rules_compared;
+            stats.tables_probed += s.tables_probed;
+        });
+        (rule.map(|r| r.action), stats)
+    }
+}
+
+impl<C: RegionQuery> RegionQuery for EffiCuts<C> {
+    fn rules_overlapping(&self, region: &Region) -> Vec<&Rule> {
+        let mut found = Overlapping::new(region);
+        for classifier in &self.classifiers {
+            found.extend(classifier.rules_overlapping(region));
+        }
+        found.finish()
+    }
+}
+
+impl<C: Freeze> Freeze for EffiCuts<C> {
+    fn shrink_to_fit(&mut self) {
+        for classifier in &mut self.classifiers {
+            classifier.shrink_to_fit();
+        }
+    }
+}
@@ -0,0 +1,215 @@
+//! Trace-driven algorithm comparison harness (requires the `std` feature).
+//!
+//! Builds every classifier over the same rule set, replays a packet trace
+//! through each of them and reports build time, memory footprint, per-lookup
+//! latency percentiles and lookup cost (nodes, rule comparisons and table
+//! probes, from `classify_with_stats`), plus the number of verdicts that
+//! disagree with the linear reference. This is the programmatic counterpart of the Criterion bench.
+
+use crate::classifier::{Classifier, LookupStats};
+use crate::cutsplit::classifier::CutSplitClassifier;
+use crate::hicuts::classifier::HiCutsClassifier;
+use crate::hypersplit::classifier::HyperSplitClassifier;
+use crate::linear::LinearClassifier;
+use crate::memory::{Footprint, MemoryStats};
+use crate::packet::FiveTuple;
+use crate::partitionsort::classifier::PartitionSortClassifier;
+use crate::rule::{Action, Rule};
+use crate::tss::classifier::TSSClassifier;
+use alloc::string::ToString;
+use alloc::vec::Vec;
+use core::fmt;
+use std::time::{Duration, Instant};
+
+/// Measurements for a single algorithm.
+#[derive(Debug, Clone)]
+pub struct AlgorithmReport {
+    /// Algorithm name.
+    pub name: &'static str,
+    /// Wall-clock time spent in `build`.
+    pub build_time: Duration,
+    /// Mean lookup latency in nanoseconds.
+    pub mean_ns: f64,
+    /// Median lookup latency in nanoseconds.
+    pub p50_ns: u64,
+    /// 99th percentile lookup latency in nanoseconds.
+    pub p99_ns: u64,
+    /// Worst observed lookup latency in nanoseconds.
+    pub max_ns: u64,
+    /// Memory held by the built classifier.
+    pub memory: Footprint,
+    /// Tree nodes traversed per lookup.
+    pub depth: CostSummary,
+    /// Rules compared per lookup.
+    pub rules_compared: CostSummary,
+    /// Hash tables probed per lookup.
+    pub tables_probed: CostSummary,
+    /// Packets whose verdict differs from the linear reference.
+    pub mismatches: usize,
+}
+
+/// Distribution of one lookup counter over the trace.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct CostSummary {
+    /// Mean per lookup.
+    pub mean: f64,
+    /// 99th percentile.
+    pub p99: u32,
+}
+
+impl CostSummary {
+    fn of(samples: &mut [u32]) -> Self {
+        samples.sort_unstable();
+        let mean = if samples.is_empty() {
+            0.0
+        } else {
+            samples.iter().map(|&v| v as f64).sum::<f64>() / samples.len() as f64
+        };
+        let idx = ((samples.len().saturating_sub(1)) as f64 * 0.99) as usize;
+        Self {
+            mean,
+            p99: samples.get(idx).copied().unwrap_or(0),
+        }
+    }
+}
+
+impl fmt::Display for CostSummary {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:.1}/{}", self.mean, self.p99)
+    }
+}
+
+/// Comparison table across algorithms.
+#[derive(Debug, Clone)]
+pub struct Comparison {
+    /// Number of rules in the evaluated rule set.
+    pub n_rules: usize,
+    /// Number of packets in the trace.
+    pub n_packets: usize,
+    /// One row per algorithm.
+    pub rows: Vec<AlgorithmReport>,
+}
+
+/// Evaluate a single classifier type against a trace.
+///
+/// `reference` holds the expected verdict for each packet (e.g. from the linear classifier).
+pub fn evaluate<C: Classifier + MemoryStats>(
+    name: &'static str,
+    rules: &[Rule],
+    packets: &[FiveTuple],
+    reference: &[Option<Action>],
+) -> AlgorithmReport {
+    let start = Instant::now();
+    let classifier = C::build(rules);
+    let build_time = start.elapsed();
+
+    let mut samples = Vec::with_capacity(packets.len());
+    let mut mismatches = 0;
+    for (packet, expected) in packets.iter().zip(reference) {
+        let t = Instant::now();
+        let verdict = classifier.classify(packet);
+        samples.push(t.elapsed().as_nanos() as u64);
+        if verdict != *expected {
+            mismatches += 1;
+        }
+    }
+
+    // Counters come from a second, untimed pass so they do not skew latency.
+    let stats: Vec<LookupStats> = packets
+        .iter()
+        .map(|p| classifier.classify_with_stats(p).1)
+        .collect();
+    let counter = |field: fn(&LookupStats) -> u32| {
+        CostSummary::of(&mut stats.iter().map(field).collect::<Vec<_>>())
+    };
+
+    samples.sort_unstable();
+    let mean_ns = if samples.is_empty() {
+        0.0
+    } else {
+        samples.iter().sum::<u64>() as f64 / samples.len() as f64
+    };
+
+    AlgorithmReport {
+        name,
+        build_time,
+        mean_ns,
+        p50_ns: percentile(&samples, 0.50),
+        p99_ns: percentile(&samples, 0.99),
+        max_ns: samples.last().copied().unwrap_or(0),
+        memory: classifier.footprint(),
+        depth: counter(|s| s.depth),
+        rules_compared: counter(|s| s.rules_compared),
+        tables_probed: counter(|s| s.tables_probed),
+        mismatches,
+    }
+}
+
+/// Run every algorithm in the crate over `rules` and `packets`.
+pub fn compare(rules: &[Rule], packets: &[FiveTuple]) -> Comparison {
+    let linear = LinearClassifier::build(rules);
+    let reference: Vec<Option<Action>> = packets.iter().map(|p| linear.classify(p)).collect();
+
+    let rows = alloc::vec![
+        evaluate::<LinearClassifier>("Linear", rules, packets, &reference),
+        evaluate::<CutSplitClassifier>("CutSplit", rules, packets, &reference),
+        evaluate::<HiCutsClassifier>("HiCuts", rules, packets, &reference),
+        evaluate::<HyperSplitClassifier>("HyperSplit", rules, packets, &reference),
+        evaluate::<TSSClassifier>("TSS", rules, packets, &reference),
+        evaluate::<PartitionSortClassifier>("PartitionSort", rules, packets, &reference),
+    ];
+
+    Comparison {
+        n_rules: rules.len(),
+        n_packets: packets.len(),
+        rows,
+    }
+}
+
+fn percentile(sorted: &[u64], q: f64) -> u64 {
+    if sorted.is_empty() {
+        return 0;
+    }
+    let idx = ((sorted.len() - 1) as f64 * q) as usize;
+    sorted[idx]
+}
+
+impl fmt::Display for Comparison {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "{} rules, {} packets", self.n_rules, self.n_packets)?;
+        writeln!(
+            f,
+            "{:<14} {:>12} {:>12} {:>10} {:>10} {:>10} {:>10} {:>10} {:>10} {:>10} {:>10}",
+            "Algorithm",
+            "Build (ms)",
+            "Memory (KB)",
+            "Mean (ns)",
+            "p50 (ns)",
+            "p99 (ns)",
+            "Max (ns)",
+            "Depth",
+            "Compares",
+            "Probes",
+            "Mismatch"
+        )?;
+        // Cost columns are mean/p99 per lookup.
+        for row in &self.rows {
+            writeln!(
+                f,
+                "{:<14} {:>12.3} {:>12.1} {:>10.1} {:>10} {:>10} {:>10} {:>10} {:>10} {:>10} {:>10}",
+                row.name,
+                row.build_time.as_secs_f64() * 1000.0,
+                row.memory.bytes as f64 / 1024.0,
+                row.mean_ns,
+                row.p50_ns,
+                row.p99_ns,
+                row.max_ns,
+                row.depth.to_string(),
+                row.rules_compared.to_string(),
+                row.tables_probed.to_string(),
+                row.mismatches
+            )?;
+        }
+        Ok(())
+    }
+}
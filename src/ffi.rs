@@ -0,0 +1,195 @@
+//! C ABI.
+//!
+//! The opaque `CutsplitClassifier` handle wraps a `HyperSplitClassifier` built
+//! with the default parameters. Link the crate as a `staticlib` or `cdylib`,
+//! e.g. `cargo rustc --release --features ffi --crate-type staticlib`, and
+//! declare in C:
+//!
+//! ```c
+//! typedef struct CutsplitClassifier CutsplitClassifier;
+//! CutsplitClassifier *cutsplit_build(const CutsplitRule *rules, size_t len);
+//! int32_t cutsplit_classify(const CutsplitClassifier *c, const CutsplitPacket *p);
+//! int64_t cutsplit_classify_rule(const CutsplitClassifier *c, const CutsplitPacket *p);
+//! void cutsplit_free(CutsplitClassifier *c);
+//! ```
+
+use crate::classifier::Classifier;
+use crate::hypersplit::classifier::HyperSplitClassifier;
+use crate::packet::{FiveTuple, PacketKey};
+use crate::rule::{Action, Range, Rule};
+use alloc::boxed::Box;
+use alloc::vec::Vec;
+
+/// `cutsplit_classify` result: no rule matched.
+pub const CUTSPLIT_NO_MATCH: i32 = -1;
+/// `cutsplit_classify` result / rule action: permit.
+pub const CUTSPLIT_PERMIT: i32 = 0;
+/// `cutsplit_classify` result / rule action: deny.
+pub const CUTSPLIT_DENY: i32 = 1;
+/// Rule flag: also match with source and destination swapped.
+pub const CUTSPLIT_RULE_BIDIRECTIONAL: u32 = 1;
+
+/// A rule as laid out by C callers. Bounds are inclusive.
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+pub struct CutsplitRule {
+    pub id: u32,
+    pub priority: u32,
+    pub src_ip_min: u32,
+    pub src_ip_max: u32,
+    pub dst_ip_min: u32,
+    pub dst_ip_max: u32,
+    pub src_port_min: u16,
+    pub src_port_max: u16,
+    pub dst_port_min: u16,
+    pub dst_port_max: u16,
+    pub proto_min: u8,
+    pub proto_max: u8,
+    /// Ingress zone bounds (`0`..`UINT32_MAX` for any zone).
+    pub zone_min: u32,
+    pub zone_max: u32,
+    /// Bitwise OR of `CUTSPLIT_RULE_*` flags.
+    pub flags: u32,
+    /// `CUTSPLIT_PERMIT` or `CUTSPLIT_DENY` (anything else denies).
+    pub action: i32,
+}
+
+/// A packet 5-tuple and its ingress zone as laid out by C callers.
+///
+/// ICMP packets carry their type in `src_port` and code in `dst_port`, IGMP
+/// packets their type in `src_port` (see `packet::L4Key`).
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+pub struct CutsplitPacket {
+    pub src_ip: u32,
+    pub dst_ip: u32,
+    pub src_port: u16,
+    pub dst_port: u16,
+    pub proto: u8,
+    pub zone: u32,
+}
+
+/// Opaque classifier handle.
+pub struct CutsplitClassifier(HyperSplitClassifier);
+
+impl From<&CutsplitRule> for Rule {
+    fn from(r: &CutsplitRule) -> Self {
+        Rule {
+            id: r.id,
+            priority: r.priority,
+            src_ip: Range::new(r.src_ip_min, r.src_ip_max),
+            dst_ip: Range::new(r.dst_ip_min, r.dst_ip_max),
+            src_port: Range::new(r.src_port_min, r.src_port_max),
+            dst_port: Range::new(r.dst_port_min, r.dst_port_max),
+            proto: Range::new(r.proto_min, r.proto_max),
+            zone: Range::new(r.zone_min, r.zone_max),
+            bidirectional: r.flags & CUTSPLIT_RULE_BIDIRECTIONAL != 0,
+            field_sets: None,
+            action: if r.action == CUTSPLIT_PERMIT {
+                Action::Permit
+            } else {
+                Action::Deny
+            },
+        }
+    }
+}
+
+impl From<&CutsplitPacket> for FiveTuple {
+    fn from(p: &CutsplitPacket) -> Self {
+        p.to_five_tuple()
+    }
+}
+
+impl PacketKey for CutsplitPacket {
+    fn src_ip(&self) -> u32 {
+        self.src_ip
+    }
+
+    fn dst_ip(&self) -> u32 {
+        self.dst_ip
+    }
+
+    fn src_port(&self) -> u16 {
+        self.src_port
+    }
+
+    fn dst_port(&self) -> u16 {
+        self.dst_port
+    }
+
+    fn proto(&self) -> u8 {
+        self.proto
+    }
+
+    fn zone(&self) -> u32 {
+        self.zone
+    }
+}
+
+/// Build a classifier over `len` rules. Free it with `cutsplit_free`.
+///
+/// # Safety
+///
+/// `rules` must point to `len` valid rules (it may be null when `len` is 0).
+#[no_mangle]
+pub unsafe extern "C" fn cutsplit_build(
+    rules: *const CutsplitRule,
+    len: usize,
+) -> *mut CutsplitClassifier {
+    let rules: Vec<Rule> = if len == 0 {
+        Vec::new()
+    } else {
+        core::slice::from_raw_parts(rules, len)
+            .iter()
+            .map(Rule::from)
+            .collect()
+    };
+    Box::into_raw(Box::new(CutsplitClassifier(HyperSplitClassifier::build(
+        &rules,
+    ))))
+}
+
+/// Action for a packet: `CUTSPLIT_PERMIT`, `CUTSPLIT_DENY` or `CUTSPLIT_NO_MATCH`.
+///
+/// # Safety
+///
+/// `classifier` must come from `cutsplit_build` and not be freed; `packet` must be valid.
+#[no_mangle]
+pub unsafe extern "C" fn cutsplit_classify(
+    classifier: *const CutsplitClassifier,
+    packet: *const CutsplitPacket,
+) -> i32 {
+    match (*classifier).0.classify(&*packet) {
+        Some(Action::Permit) => CUTSPLIT_PERMIT,
+        Some(Action::Deny) => CUTSPLIT_DENY,
+        None => CUTSPLIT_NO_MATCH,
+    }
+}
+
+/// Id of the matching rule, or -1 when no rule matches.
+///
+/// # Safety
+///
+/// Same as `cutsplit_classify`.
+#[no_mangle]
+pub unsafe extern "C" fn cutsplit_classify_rule(
+    classifier: *const CutsplitClassifier,
+    packet: *const CutsplitPacket,
+) -> i64 {
+    (*classifier)
+        .0
+        .classify_rule(&FiveTuple::from(&*packet))
+        .map_or(-1, |r| r.id as i64)
+}
+
+/// Release a classifier. Null is ignored.
+///
+/// # Safety
+///
+/// `classifier` must come from `cutsplit_build` and must not be used afterwards.
+#[no_mangle]
+pub unsafe extern "C" fn cutsplit_free(classifier: *mut CutsplitClassifier) {
+    if !classifier.is_null() {
+        drop(Box::from_raw(classifier));
+    }
+}
@@ -0,0 +1,82 @@
+//! Rule-set fingerprints.
+//!
+//! A `Fingerprint` is a stable 64-bit hash of a rule list: every field of
+//! every rule (address-set references included, not their contents), in list
+//! order. It depends only on the rules, not on the platform, build or crate
+//! version, so a control plane and its data planes agree on it.
+//!
+//! Classifiers built from a rule list remember its fingerprint and write it
+//! into their serialized forms; loading one with an expected fingerprint
+//! (the current policy's) fails with `LoadError::Stale` instead of
+//! classifying against an outdated tree. The hash guards against mistakes,
+//! not against forged blobs.
+
+use crate::rule::Rule;
+use crate::update::audit::{encode_rule, DecodeError};
+use alloc::vec::Vec;
+use core::fmt;
+
+const FNV_OFFSET: u64 = 0xCBF2_9CE4_8422_2325;
+const FNV_PRIME: u64 = 0x0000_0100_0000_01B3;
+
+/// Content hash of a rule list.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Fingerprint(pub u64);
+
+impl Fingerprint {
+    /// Fingerprint of `rules`.
+    ///
+    /// Reordering rules, or changing any field of one, changes it.
+    pub fn of(rules: &[Rule]) -> Self {
+        let mut hash = FNV_OFFSET;
+        let mut feed = |bytes: &[u8]| {
+            for &b in bytes {
+                hash = (hash ^ b as u64).wrapping_mul(FNV_PRIME);
+            }
+        };
+        feed(&(rules.len() as u64).to_le_bytes());
+        let mut buf = Vec::new();
+        for rule in rules {
+            buf.clear();
+            encode_rule(&mut buf, rule);
+            feed(&buf);
+        }
+        Self(hash)
+    }
+
+    /// Check the fingerprint `found` in a serialized classifier against the
+    /// expected one.
+    pub fn check(found: Option<Fingerprint>, expected: Fingerprint) -> Result<(), LoadError> {
+        if found == Some(expected) {
+            Ok(())
+        } else {
+            Err(LoadError::Stale { expected, found })
+        }
+    }
+}
+
+impl fmt::Display for Fingerprint {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:016x}", self.0)
+    }
+}
+
+/// Errors returned when loading a serialized classifier against an expected
+/// fingerprint.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LoadError {
+    /// The input is not a valid serialized classifier.
+    Decode(DecodeError),
+    /// The classifier was built from other rules than expected (`found` is
+    /// `None` if it was built without a rule list to fingerprint).
+    Stale {
+        expected: Fingerprint,
+        found: Option<Fingerprint>,
+    },
+}
+
+impl From<DecodeError> for LoadError {
+    fn from(e: DecodeError) -> Self {
+        LoadError::Decode(e)
+    }
+}
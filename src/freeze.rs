@@ -0,0 +1,65 @@
+//! Read-only deployment form of a classifier.
+//!
+//! Classifiers are built (and possibly updated) in a mutable phase, then
+//! `freeze` hands back a `FrozenClassifier`: the same lookup structure with
+//! every spare allocation released and no way to change it. This keeps the
+//! build/update side and the deployed lookup side apart in the type system.
+
+use crate::classifier::{Classifier, LookupStats};
+use crate::geometry::Region;
+use crate::packet::FiveTuple;
+use crate::query::RegionQuery;
+use crate::rule::{Action, Rule};
+use alloc::vec::Vec;
+
+/// Classifier that can be compacted into a `FrozenClassifier`.
+pub trait Freeze: Classifier + Sized {
+    /// Release spare capacity held by the lookup structure.
+    fn shrink_to_fit(&mut self);
+
+    /// Compact the classifier and make it read-only.
+    fn freeze(mut self) -> FrozenClassifier<Self> {
+        self.shrink_to_fit();
+        FrozenClassifier { inner: self }
+    }
+}
+
+/// Compacted, immutable classifier produced by `Freeze::freeze`.
+///
+/// Lookups behave exactly as on the classifier it was frozen from; update
+/// APIs are not available.
+#[derive(Clone)]
+pub struct FrozenClassifier<C> {
+    inner: C,
+}
+
+impl<C> FrozenClassifier<C> {
+    /// The frozen classifier.
+    pub fn inner(&self) -> &C {
+        &self.inner
+    }
+}
+
+impl<C: Freeze> Classifier for FrozenClassifier<C> {
+    fn build(rules: &[Rule]) -> Self {
+        C::build(rules).freeze()
+    }
+
+    fn classify_rule(&self, packet: &FiveTuple) -> Option<&Rule> {
+        self.inner.classify_rule(packet)
+    }
+
+    fn classify_batch(&self, packets: &[FiveTuple], out: &mut [Option<Action>]) {
+        self.inner.classify_batch(packets, out)
+    }
+
+    fn classify_with_stats(&self, packet: &FiveTuple) -> (Option<Action>, LookupStats) {
+        self.inner.classify_with_stats(packet)
+    }
+}
+
+impl<C: Freeze + RegionQuery> RegionQuery for FrozenClassifier<C> {
+    fn rules_overlapping(&self, region: &Region) -> Vec<&Rule> {
+        self.inner.rules_overlapping(region)
+    }
+}
@@ -0,0 +1,306 @@
+//! Packet-space geometry.
+//!
+//! A rule matches an axis-aligned box in the 5-dimensional packet space.
+//! `Region` models such a box (all fields widened to `u32`, indexed in
+//! `Dimension::ALL` order) with intersection, containment, subtraction and
+//! volume, which is the shared foundation for shadowing/coverage analysis and
+//! region-aware pruning.
+//!
+//! The ingress zone is not a dimension of the space: regions built from rules
+//! ignore it and rules built from regions match every zone.
+
+use crate::dimension::Dimension;
+use crate::packet::FiveTuple;
+use crate::rule::{expand_rules, Action, Range, Rule, ANY_ZONE};
+use alloc::vec::Vec;
+
+/// Axis-aligned box of packet space. Bounds are inclusive.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Region {
+    /// Per-dimension bounds, in `Dimension::ALL` order.
+    pub bounds: [Range<u32>; Dimension::COUNT],
+}
+
+impl Region {
+    /// The whole packet space.
+    pub fn full() -> Self {
+        Self {
+            bounds: Dimension::ALL.map(|d| Range::new(0, d.max_value())),
+        }
+    }
+
+    /// The box matched by `rule`.
+    pub fn from_rule(rule: &Rule) -> Self {
+        Self {
+            bounds: Dimension::ALL.map(|d| d.rule_range(rule)),
+        }
+    }
+
+    /// The single point corresponding to `packet`.
+    pub fn from_packet(packet: &FiveTuple) -> Self {
+        Self {
+            bounds: Dimension::ALL.map(|d| {
+                let v = d.packet_value(packet);
+                Range::new(v, v)
+            }),
+        }
+    }
+
+    /// Bounds on one dimension.
+    pub fn get(&self, dim: Dimension) -> Range<u32> {
+        self.bounds[dim.index()]
+    }
+
+    /// Replace the bounds on one dimension.
+    pub fn with(mut self, dim: Dimension, range: Range<u32>) -> Self {
+        self.bounds[dim.index()] = range;
+        self
+    }
+
+    /// Returns true if `packet` lies inside the region.
+    pub fn contains_packet(&self, packet: &FiveTuple) -> bool {
+        Dimension::ALL
+            .iter()
+            .all(|&d| self.get(d).contains(d.packet_value(packet)))
+    }
+
+    /// Returns true if `other` lies entirely inside `self`.
+    pub fn contains(&self, other: &Region) -> bool {
+        self.bounds
+            .iter()
+            .zip(other.bounds.iter())
+            .all(|(a, b)| a.min <= b.min && b.max <= a.max)
+    }
+
+    /// The region with `dim` left unspecified (its whole domain), e.g. to
+    /// query a packet whose source port is unknown.
+    pub fn any(self, dim: Dimension) -> Self {
+        self.with(dim, Range::new(0, dim.max_value()))
+    }
+
+    /// The packet at the lowest corner of the region, in zone 0.
+    pub fn first_packet(&self) -> FiveTuple {
+        let mut packet = FiveTuple::default();
+        for dim in Dimension::ALL {
+            dim.set_packet_value(&mut packet, self.get(dim).min);
+        }
+        packet
+    }
+
+    /// Returns true if the regions share at least one point.
+    pub fn intersects(&self, other: &Region) -> bool {
+        self.bounds
+            .iter()
+            .zip(other.bounds.iter())
+            .all(|(a, b)| a.min <= b.max && b.min <= a.max)
+    }
+
+    /// The common part of both regions, if any.
+    pub fn intersection(&self, other: &Region) -> Option<Region> {
+        if !self.intersects(other) {
+            return None;
+        }
+        let mut out = *self;
+        for (o, b) in out.bounds.iter_mut().zip(other.bounds.iter()) {
+            o.min = o.min.max(b.min);
+            o.max = o.max.min(b.max);
+        }
+        Some(out)
+    }
+
+    /// `self` minus `other`, as at most 10 disjoint regions.
+    pub fn subtract(&self, other: &Region) -> Vec<Region> {
+        let Some(cut) = self.intersection(other) else {
+            return alloc::vec![*self];
+        };
+
+        // Peel off the slabs outside `cut`, one dimension at a time.
+        let mut out = Vec::new();
+        let mut rest = *self;
+        for (i, c) in cut.bounds.iter().enumerate() {
+            let r = rest.bounds[i];
+            if r.min < c.min {
+                let mut below = rest;
+                below.bounds[i] = Range::new(r.min, c.min - 1);
+                out.push(below);
+            }
+            if c.max < r.max {
+                let mut above = rest;
+                above.bounds[i] = Range::new(c.max + 1, r.max);
+                out.push(above);
+            }
+            rest.bounds[i] = *c;
+        }
+        out
+    }
+
+    /// Number of packets in the region.
+    pub fn volume(&self) -> u128 {
+        self.bounds
+            .iter()
+            .map(|r| (r.max - r.min) as u128 + 1)
+            .product()
+    }
+
+    /// Build a rule matching exactly this region.
+    pub fn to_rule(&self, id: u32, priority: u32, action: Action) -> Rule {
+        let mut rule = Rule {
+            id,
+            priority,
+            src_ip: Range::exact(0),
+            dst_ip: Range::exact(0),
+            src_port: Range::exact(0),
+            dst_port: Range::exact(0),
+            proto: Range::exact(0),
+            zone: ANY_ZONE,
+            bidirectional: false,
+            field_sets: None,
+            action,
+        };
+        for dim in Dimension::ALL {
+            dim.set_rule_range(&mut rule, self.get(dim));
+        }
+        rule
+    }
+}
+
+/// `base` minus the union of `cutters`, as disjoint regions.
+///
+/// The result can grow quickly with many overlapping cutters; callers that
+/// only need emptiness should stop early on their own.
+pub fn difference(base: &Region, cutters: &[Region]) -> Vec<Region> {
+    let mut remaining = alloc::vec![*base];
+    for cutter in cutters {
+        if remaining.is_empty() {
+            break;
+        }
+        let mut next = Vec::with_capacity(remaining.len());
+        for piece in &remaining {
+            next.extend(piece.subtract(cutter));
+        }
+        remaining = next;
+    }
+    remaining
+}
+
+/// Total volume of a set of disjoint regions.
+pub fn total_volume(regions: &[Region]) -> u128 {
+    regions.iter().map(Region::volume).sum()
+}
+
+/// Some part of `base` covered by none of `cutters`, or `None` if they cover it.
+///
+/// Unlike `difference`, the search is depth-first and stops at the first gap,
+/// so proving coverage only keeps one branch of pieces alive at a time.
+/// Ordering `cutters` largest first keeps the search short.
+pub fn find_uncovered(base: &Region, cutters: &[Region]) -> Option<Region> {
+    let mut stack = alloc::vec![(*base, 0usize)];
+    while let Some((piece, from)) = stack.pop() {
+        let Some(i) = cutters[from..].iter().position(|c| c.intersects(&piece)) else {
+            return Some(piece);
+        };
+        let cutter = &cutters[from + i];
+        if !cutter.contains(&piece) {
+            stack.extend(
+                piece
+                    .subtract(cutter)
+                    .into_iter()
+                    .map(|p| (p, from + i + 1)),
+            );
+        }
+    }
+    None
+}
+
+/// Prove that every packet matches some rule, as a deploy-time check that a
+/// rule set really ends in a default rule (or otherwise covers the space).
+///
+/// Returns a region no rule matches otherwise; `Region::first_packet` gives a
+/// witness. The proof is exact over the five fields. Only rules that match in
+/// every zone and reference no address set count towards coverage, so a gap
+/// may still be covered in some zones or under some set contents.
+pub fn verify_complete(rules: &[Rule]) -> Result<(), Region> {
+    let unconditional: Vec<Rule> = rules
+        .iter()
+        .filter(|r| r.zone == ANY_ZONE && !r.references_sets())
+        .cloned()
+        .collect();
+    let mut cutters: Vec<Region> = expand_rules(&unconditional)
+        .iter()
+        .map(Region::from_rule)
+        .collect();
+    cutters.sort_by_key(|r| core::cmp::Reverse(r.volume()));
+    match find_uncovered(&Region::full(), &cutters) {
+        Some(gap) => Err(gap),
+        None => Ok(()),
+    }
+}
+
+/// Rules ahead of `rules[index]` in first-match order: lower priority values,
+/// then equal priorities earlier in the slice (as a stable sort places them).
+fn ahead_of(rules: &[Rule], index: usize) -> impl Iterator<Item = &Rule> {
+    let priority = rules[index].priority;
+    rules
+        .iter()
+        .enumerate()
+        .filter(move |&(i, r)| r.priority < priority || (r.priority == priority && i < index))
+        .map(|(_, r)| r)
+}
+
+/// Regions a shadowing rule takes away from `target`: only rules matching in
+/// every zone the target does and referencing no address set shadow it.
+fn shadowing_regions(rules: &[Rule], index: usize) -> Vec<Region> {
+    let target = &rules[index];
+    let shadowing: Vec<Rule> = ahead_of(rules, index)
+        .filter(|r| {
+            r.zone.min <= target.zone.min && target.zone.max <= r.zone.max && !r.references_sets()
+        })
+        .cloned()
+        .collect();
+    let mut regions: Vec<Region> = expand_rules(&shadowing)
+        .iter()
+        .map(Region::from_rule)
+        .collect();
+    regions.sort_by_key(|r| core::cmp::Reverse(r.volume()));
+    regions
+}
+
+/// Packet space where `rules[index]` is the rule that matches: its own
+/// region(s) minus those of every rule ahead of it, as disjoint regions.
+///
+/// An empty result means the rule is dead (see `is_dead`). Rules bound to
+/// fewer zones than the target, or referencing address sets, are not
+/// subtracted since they only shadow it in some cases; the result may then
+/// overstate where the rule wins, never understate it. The target's own set
+/// references are likewise ignored. Fragmented policies can produce many
+/// pieces: use `is_dead` when only emptiness matters.
+pub fn effective_region(rules: &[Rule], index: usize) -> Vec<Region> {
+    let cutters = shadowing_regions(rules, index);
+    let own = expand_rules(core::slice::from_ref(&rules[index]));
+    let mut pieces = Vec::new();
+    for region in own.iter().map(Region::from_rule) {
+        let relevant: Vec<Region> = cutters
+            .iter()
+            .filter(|c| c.intersects(&region))
+            .copied()
+            .collect();
+        // Pieces of a later copy must not repeat those of an earlier one.
+        let rest: Vec<Region> = difference(&region, &relevant)
+            .into_iter()
+            .flat_map(|piece| difference(&piece, &pieces))
+            .collect();
+        pieces.extend(rest);
+    }
+    pieces
+}
+
+/// Returns true if rules ahead of `rules[index]` match every packet it
+/// matches, so it can never be the matching rule. Same caveats as
+/// `effective_region`, but stops as soon as one live packet is found.
+pub fn is_dead(rules: &[Rule], index: usize) -> bool {
+    let cutters = shadowing_regions(rules, index);
+    expand_rules(core::slice::from_ref(&rules[index]))
+        .iter()
+        .all(|r| find_uncovered(&Region::from_rule(r), &cutters).is_none())
+}
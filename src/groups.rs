@@ -0,0 +1,152 @@
+//! Rule groups that can be switched on and off at runtime.
+//!
+//! Maintenance windows and time-of-day policies usually toggle a whole set of
+//! rules at once. `Grouped` builds one classifier per group (plus one for the
+//! ungrouped rules) and keeps a bitmap of enabled groups that is checked on
+//! every lookup, so enabling or disabling a group is a single bit flip: no
+//! rule is touched and nothing is rebuilt. The schedule itself is up to the
+//! caller (the crate is no_std and has no clock).
+
+use crate::classifier::{Classifier, LookupStats};
+use crate::geometry::Region;
+use crate::packet::FiveTuple;
+use crate::query::{Overlapping, RegionQuery};
+use crate::rule::{Action, Rule};
+use alloc::vec::Vec;
+use hashbrown::HashMap;
+
+/// Identifier of a rule group.
+pub type GroupId = u32;
+
+/// Classifier whose rule groups can be enabled and disabled without rebuild.
+///
+/// Groups start enabled. Results are those of a single classifier built over
+/// the ungrouped rules and the rules of the enabled groups.
+pub struct Grouped<C> {
+    /// Classifier over the rules belonging to no group.
+    main: C,
+    /// One classifier per group, by slot.
+    groups: Vec<C>,
+    /// Group id to slot.
+    slots: HashMap<GroupId, usize>,
+    /// Enabled bit per slot.
+    enabled: Vec<u64>,
+}
+
+impl<C: Classifier> Grouped<C> {
+    /// Build over `rules`, assigning each rule to the group returned by
+    /// `group_of` (`None` for rules that are always active).
+    pub fn new(rules: &[Rule], group_of: impl Fn(&Rule) -> Option<GroupId>) -> Self {
+        let mut ungrouped = Vec::new();
+        let mut members: Vec<Vec<Rule>> = Vec::new();
+        let mut slots = HashMap::new();
+        for rule in rules {
+            match group_of(rule) {
+                Some(group) => {
+                    let next = slots.len();
+                    let slot = *slots.entry(group).or_insert(next);
+                    if slot == members.len() {
+                        members.push(Vec::new());
+                    }
+                    members[slot].push(rule.clone());
+                }
+                None => ungrouped.push(rule.clone()),
+            }
+        }
+        let mut enabled = alloc::vec![0u64; members.len().div_ceil(64)];
+        for slot in 0..members.len() {
+            enabled[slot / 64] |= 1 << (slot % 64);
+        }
+        Self {
+            main: C::build(&ungrouped),
+            groups: members.iter().map(|rules| C::build(rules)).collect(),
+            slots,
+            enabled,
+        }
+    }
+
+    /// Enable or disable a group. Returns false if no rule is in this group.
+    pub fn set_enabled(&mut self, group: GroupId, enabled: bool) -> bool {
+        let Some(&slot) = self.slots.get(&group) else {
+            return false;
+        };
+        if enabled {
+            self.enabled[slot / 64] |= 1 << (slot % 64);
+        } else {
+            self.enabled[slot / 64] &= !(1 << (slot % 64));
+        }
+        true
+    }
+
+    /// Enable a group. Returns false if no rule is in this group.
+    pub fn enable(&mut self, group: GroupId) -> bool {
+        self.set_enabled(group, true)
+    }
+
+    /// Disable a group. Returns false if no rule is in this group.
+    pub fn disable(&mut self, group: GroupId) -> bool {
+        self.set_enabled(group, false)
+    }
+
+    /// Whether a group is enabled (`None` if no rule is in this group).
+    pub fn is_enabled(&self, group: GroupId) -> Option<bool> {
+        self.slots.get(&group).map(|&slot| self.slot_enabled(slot))
+    }
+
+    /// Ids of every group (in no particular order).
+    pub fn groups(&self) -> impl Iterator<Item = GroupId> + '_ {
+        self.slots.keys().copied()
+    }
+
+    fn slot_enabled(&self, slot: usize) -> bool {
+        self.enabled[slot / 64] & (1 << (slot % 64)) != 0
+    }
+
+    /// Classifiers to query: the ungrouped rules, then every enabled group.
+    fn active(&self) -> impl Iterator<Item = &C> + '_ {
+        let enabled = self
+            .groups
+            .iter()
+            .enumerate()
+            .filter(|&(slot, _)| self.slot_enabled(slot))
+            .map(|(_, c)| c);
+        core::iter::once(&self.main).chain(enabled)
+    }
+}
+
+impl<C: Classifier> Classifier for Grouped<C> {
+    /// Build with every rule ungrouped.
+    fn build(rules: &[Rule]) -> Self {
+        Self::new(rules, |_| None)
+    }
+
+    fn classify_rule(&self, packet: &FiveTuple) -> Option<&Rule> {
+        // Ties go to the ungrouped rules, then to the earliest group.
+        self.active()
+            .filter_map(|c| c.classify_rule(packet))
+            .reduce(|best, r| if r.priority < best.priority { r } else { best })
+    }
+
+    fn classify_with_stats(&self, packet: &FiveTuple) -> (Option<Action>, LookupStats) {
+        // Stats-only path: picking the winner needs rule priorities, hence the extra lookup.
+        let mut stats = LookupStats::default();
+        for c in self.active() {
+            let (_, s) = c.classify_with_stats(packet);
+            stats.depth += s.depth;
+            stats.rules_compared += s.rules_compared;
+            stats.tables_probed += s.tables_probed;
+        }
+        (self.classify(packet), stats)
+    }
+}
+
+impl<C: RegionQuery> RegionQuery for Grouped<C> {
+    /// Rules of the ungrouped rules and of the enabled groups only.
+    fn rules_overlapping(&self, region: &Region) -> Vec<&Rule> {
+        let mut found = Overlapping::new(region);
+        for c in self.active() {
+            found.extend(c.rules_overlapping(region));
+        }
+        found.finish()
+    }
+}
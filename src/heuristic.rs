@@ -0,0 +1,133 @@
+//! Dimension-selection heuristics shared by the tree builders.
+//!
+//! Each builder has its own search for the next cut. A `DimensionHeuristic`
+//! set on a builder (`with_dimension_heuristic`) first picks the dimension to
+//! cut, and the builder then searches only that dimension for the cut point,
+//! so selection policies can be compared without touching the builders.
+//! Heuristics look at the rules clipped to the node's region: a rule wider
+//! than the node is a wildcard there.
+
+use crate::dimension::Dimension;
+use crate::geometry::Region;
+use crate::rule::{Range, Rule};
+use alloc::vec::Vec;
+
+/// Policy choosing the dimension a tree node is cut on.
+pub trait DimensionHeuristic: Send + Sync {
+    /// How good cutting `rules` along `dim` inside `region` looks (higher is
+    /// better). Zero or less means the dimension cannot separate the rules.
+    fn score(&self, rules: &[&Rule], dim: Dimension, region: &Region) -> f32;
+
+    /// Best-scoring dimension among `dims` (ties to the first), skipping
+    /// dimensions the region has already narrowed to a single value.
+    fn select(&self, rules: &[&Rule], dims: &[Dimension], region: &Region) -> Option<Dimension> {
+        let mut best: Option<(Dimension, f32)> = None;
+        for &dim in dims {
+            let range = region.get(dim);
+            if range.min >= range.max {
+                continue;
+            }
+            let score = self.score(rules, dim, region);
+            if score > 0.0 && best.is_none_or(|(_, s)| score > s) {
+                best = Some((dim, score));
+            }
+        }
+        best.map(|(dim, _)| dim)
+    }
+}
+
+/// Rule ranges on `dim` clipped to the region.
+fn clipped<'a>(
+    rules: &'a [&'a Rule],
+    dim: Dimension,
+    region: &Region,
+) -> impl Iterator<Item = Range<u32>> + 'a {
+    let bounds = region.get(dim);
+    rules.iter().filter_map(move |rule| {
+        let range = dim.rule_range(rule);
+        let (min, max) = (range.min.max(bounds.min), range.max.min(bounds.max));
+        (min <= max).then(|| Range::new(min, max))
+    })
+}
+
+/// Number of distinct rule endpoints strictly inside the node: each is a
+/// place where a cut separates some rules (the FiCuts/HyperCuts criterion).
+#[derive(Debug, Clone, Copy, Default)]
+pub struct DistinctEndpoints;
+
+impl DimensionHeuristic for DistinctEndpoints {
+    fn score(&self, rules: &[&Rule], dim: Dimension, region: &Region) -> f32 {
+        let bounds = region.get(dim);
+        let mut points: Vec<u32> = clipped(rules, dim, region)
+            .flat_map(|r| [Some(r.min), r.max.checked_add(1)])
+            .flatten()
+            .filter(|&p| p > bounds.min && p <= bounds.max)
+            .collect();
+        points.sort_unstable();
+        points.dedup();
+        points.len() as f32
+    }
+}
+
+/// Shannon entropy (in bits) of the rules' distinct ranges on the dimension:
+/// high when rules spread over many different ranges, zero when they all
+/// share one.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Entropy;
+
+impl DimensionHeuristic for Entropy {
+    fn score(&self, rules: &[&Rule], dim: Dimension, region: &Region) -> f32 {
+        let mut ranges: Vec<(u32, u32)> = clipped(rules, dim, region)
+            .map(|r| (r.min, r.max))
+            .collect();
+        if ranges.is_empty() {
+            return 0.0;
+        }
+        ranges.sort_unstable();
+        let total = ranges.len() as f32;
+        let mut entropy = 0.0;
+        for group in ranges.chunk_by(|a, b| a == b) {
+            let p = group.len() as f32 / total;
+            entropy -= p * log2(p);
+        }
+        entropy
+    }
+}
+
+/// HiCuts space measure of halving the node on the dimension: the fewer rule
+/// copies the two halves hold together, the better. Scores `n / (left +
+/// right)`: 1.0 without replication, 0.5 when every rule spans the cut.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SpaceMeasure;
+
+impl DimensionHeuristic for SpaceMeasure {
+    fn score(&self, rules: &[&Rule], dim: Dimension, region: &Region) -> f32 {
+        let bounds = region.get(dim);
+        let mid = bounds.min + (bounds.max - bounds.min) / 2;
+        let (mut left, mut right, mut n) = (0usize, 0usize, 0usize);
+        for range in clipped(rules, dim, region) {
+            n += 1;
+            left += (range.min <= mid) as usize;
+            right += (range.max > mid) as usize;
+        }
+        if n == 0 || (left == n && right == n) {
+            return 0.0;
+        }
+        n as f32 / (left + right) as f32
+    }
+}
+
+/// Base-2 logarithm of a positive value (no `std` float functions here).
+///
+/// Exact on the exponent; the mantissa `m` in [1, 2) goes through the series
+/// `ln m = 2 (t + t^3/3 + t^5/5 + t^7/7)` with `t = (m - 1) / (m + 1) <= 1/3`,
+/// accurate to about 1e-5.
+fn log2(x: f32) -> f32 {
+    let bits = x.to_bits();
+    let exponent = ((bits >> 23) & 0xFF) as i32 - 127;
+    let m = f32::from_bits((bits & 0x007F_FFFF) | 0x3F80_0000);
+    let t = (m - 1.0) / (m + 1.0);
+    let t2 = t * t;
+    let ln = 2.0 * t * (1.0 + t2 * (1.0 / 3.0 + t2 * (1.0 / 5.0 + t2 / 7.0)));
+    exponent as f32 + ln * core::f32::consts::LOG2_E
+}
@@ -1,14 +1,43 @@
-use crate::cutsplit::tree::Dimension;
+use crate::classifier::Config;
+use crate::cost::{Cut, CutCost, LargestChild};
+use crate::dimension::Dimension;
+use crate::dtree::{
+    self, rule_reaches, BuildStats, CutStrategy, NodeCut, TreeParams, WorstCase, WorstCaseError,
+};
+use crate::fingerprint::Fingerprint;
+use crate::geometry::Region;
+use crate::heuristic::DimensionHeuristic;
 use crate::hicuts::tree::Node;
+use crate::leaf::LeafPolicy;
 use crate::rule::{Range, Rule};
 use alloc::boxed::Box;
+#[cfg(feature = "single-core")]
+use alloc::rc::Rc as Arc;
+#[cfg(not(feature = "single-core"))]
+use alloc::sync::Arc;
 use alloc::vec::Vec;
+use hashbrown::HashMap;
 
 pub struct Builder {
     pub leaf_threshold: usize,
     pub max_depth: usize,
     pub binth: usize, // Max cuts multiplier or similar tuning param
-    pub spfac: usize, // Space factor max expansion
+    /// Space factor: a cut's children may hold at most `spfac` times the
+    /// node's rules, counting one extra per child (the paper's `spmf`).
+    pub spfac: usize,
+    /// Most cuts of a node; powers of two up to this are tried, as long as
+    /// `spfac` allows them.
+    pub max_cuts: u32,
+    /// How `leaf_threshold` evolves with depth and duplication.
+    pub leaf_policy: LeafPolicy,
+    /// Leaves with more rules than this get a bit-vector index (None = always linear).
+    pub secondary_threshold: Option<usize>,
+    /// Picks the dimension of each cut (None = try every dimension).
+    pub dimension_heuristic: Option<Box<dyn DimensionHeuristic>>,
+    /// Cost of a candidate cut; the cheapest one is made.
+    pub cut_cost: Box<dyn CutCost>,
+    /// Share identical subtrees once built (see `share_subtrees`).
+    pub share_subtrees: bool,
 }
 
 impl Builder {
@@ -18,122 +47,293 @@ impl Builder {
             max_depth,
             binth: 8,
             spfac: 4,
+            max_cuts: 64,
+            leaf_policy: LeafPolicy::Fixed,
+            secondary_threshold: None,
+            dimension_heuristic: None,
+            cut_cost: Box::new(LargestChild),
+            share_subtrees: true,
         }
     }
 
+    /// Override the parameters `config` sets; `spfac` is rounded up.
+    pub fn with_config(mut self, config: &Config) -> Self {
+        self.leaf_threshold = config.leaf_threshold.unwrap_or(self.leaf_threshold);
+        self.max_depth = config.max_depth.unwrap_or(self.max_depth);
+        if let Some(spfac) = config.spfac {
+            let whole = spfac as usize;
+            self.spfac = whole + usize::from((whole as f32) < spfac);
+        }
+        self
+    }
+
+    /// Use the given leaf policy instead of a fixed threshold.
+    pub fn with_leaf_policy(mut self, policy: LeafPolicy) -> Self {
+        self.leaf_policy = policy;
+        self
+    }
+
+    /// Index leaves holding more than `threshold` rules with a `BitVectorIndex`.
+    pub fn with_secondary_index(mut self, threshold: usize) -> Self {
+        self.secondary_threshold = Some(threshold);
+        self
+    }
+
+    /// Try at most `max_cuts` cuts per node.
+    pub fn with_max_cuts(mut self, max_cuts: u32) -> Self {
+        self.max_cuts = max_cuts;
+        self
+    }
+
+    /// Let `heuristic` choose the dimension of each cut; the number of cuts
+    /// is then searched on that dimension only.
+    pub fn with_dimension_heuristic(
+        mut self,
+        heuristic: impl DimensionHeuristic + 'static,
+    ) -> Self {
+        self.dimension_heuristic = Some(Box::new(heuristic));
+        self
+    }
+
+    /// Choose cuts by `cost` instead of `LargestChild`.
+    pub fn with_cut_cost(mut self, cost: impl CutCost + 'static) -> Self {
+        self.cut_cost = Box::new(cost);
+        self
+    }
+
+    /// Keep every subtree separate instead of sharing identical ones.
+    pub fn with_shared_subtrees(mut self, share: bool) -> Self {
+        self.share_subtrees = share;
+        self
+    }
+
     pub fn build(&self, rules: &[Rule]) -> Node {
         // Initial region: Full 5-tuple space
-        // We track the current range for each dimension to calculate cuts
-        let ranges = [
-            (Dimension::SrcIp, 0, u32::MAX),
-            (Dimension::DstIp, 0, u32::MAX),
-            (Dimension::SrcPort, 0, 65535),
-            (Dimension::DstPort, 0, 65535),
-            (Dimension::Proto, 0, 255),
-        ];
+        self.build_region(rules, &Region::full())
+    }
 
-        self.build_recursive(rules, 0, &ranges)
+    /// Build the tree and report its shape and stop conditions.
+    pub fn build_with_stats(&self, rules: &[Rule]) -> (Node, BuildStats) {
+        let (root, stats) = dtree::build(self, rules, &Region::full());
+        (self.finish(root), stats)
     }
 
-    fn build_recursive(
+    /// Build a tree whose lookups stay within `bound`, or fail if none can
+    /// (see `dtree::build_bounded`).
+    pub fn build_bounded(
         &self,
         rules: &[Rule],
-        depth: usize,
-        ranges: &[(Dimension, u32, u32)],
-    ) -> Node {
-        if rules.len() <= self.leaf_threshold || depth >= self.max_depth {
-            return Node::Leaf {
-                rules: rules.to_vec(),
-            };
+        bound: WorstCase,
+    ) -> Result<(Node, BuildStats), WorstCaseError> {
+        let (root, stats) = dtree::build_bounded(self, rules, &Region::full(), bound)?;
+        Ok((self.finish(root), stats))
+    }
+
+    /// Build a tree covering only `region` (rules are kept whole).
+    ///
+    /// Packets outside the region are handled by the classifier's `OutOfRange` mode.
+    pub fn build_region(&self, rules: &[Rule], region: &Region) -> Node {
+        self.build_region_counting_shadowed(rules, region).0
+    }
+
+    /// Build a tree covering only `region`, and count the rule copies left
+    /// out of nodes where a better rule covers the whole node.
+    pub fn build_region_counting_shadowed(&self, rules: &[Rule], region: &Region) -> (Node, usize) {
+        let (root, stats) = dtree::build(self, rules, region);
+        (self.finish(root), stats.shadowed_rules)
+    }
+
+    fn finish(&self, root: Node) -> Node {
+        if self.share_subtrees {
+            share_subtrees(root)
+        } else {
+            root
         }
+    }
+}
 
-        // Heuristic: Select dimension and number of cuts
-        let (best_dim, num_cuts) = self.select_dimension_and_cuts(rules, ranges);
+/// Share the structurally identical subtrees of `root`: equal leaves, and
+/// internal nodes making the same cut over the same children.
+///
+/// A cut falling inside the ranges of a node's rules leaves several children
+/// with the same rules, and cutting these the same way repeats whole
+/// subtrees; wildcard-heavy rule sets are mostly such copies. Lookups are
+/// unchanged, as every subtree sends each packet to the same rule.
+pub fn share_subtrees(root: Node) -> Node {
+    let mut shared = HashMap::new();
+    share_children(root, &mut shared)
+}
 
-        if num_cuts <= 1 {
-            // Cannot cut effectively
-            return Node::Leaf {
-                rules: rules.to_vec(),
-            };
+/// What makes two subtrees identical, their children being shared already.
+#[derive(PartialEq, Eq, Hash)]
+enum Shape {
+    Internal {
+        dimension: usize,
+        cuts: [u32; 4],
+        children: Vec<*const Node>,
+    },
+    /// Leaves are told apart by their rules; equal fingerprints are checked.
+    Leaf(Fingerprint),
+    IndexedLeaf(Fingerprint),
+}
+
+/// `node` with its children replaced by their shared copies.
+fn share_children(mut node: Node, shared: &mut HashMap<Shape, Vec<Arc<Node>>>) -> Node {
+    if let Node::Internal { children, .. } = &mut node {
+        *children = core::mem::take(children)
+            .into_iter()
+            .map(|child| share(Arc::unwrap_or_clone(child), shared))
+            .collect();
+    }
+    node
+}
+
+/// The shared copy of `node`.
+fn share(node: Node, shared: &mut HashMap<Shape, Vec<Arc<Node>>>) -> Arc<Node> {
+    let node = share_children(node, shared);
+    let shape = match &node {
+        Node::Internal {
+            dimension,
+            start,
+            end,
+            step,
+            num_cuts,
+            children,
+        } => Shape::Internal {
+            dimension: dimension.index(),
+            cuts: [*start, *end, *step, *num_cuts],
+            children: children.iter().map(Arc::as_ptr).collect(),
+        },
+        Node::Leaf { rules } => Shape::Leaf(Fingerprint::of(rules)),
+        Node::IndexedLeaf { index } => Shape::IndexedLeaf(Fingerprint::of(index.rules())),
+    };
+    let same = shared.entry(shape).or_default();
+    let existing = same.iter().find(|other| match (&node, &***other) {
+        (Node::Leaf { rules }, Node::Leaf { rules: others }) => rules == others,
+        (Node::IndexedLeaf { index }, Node::IndexedLeaf { index: other }) => {
+            index.rules() == other.rules()
         }
+        _ => true,
+    });
+    if let Some(existing) = existing {
+        return existing.clone();
+    }
+    let node = Arc::new(node);
+    same.push(node.clone());
+    node
+}
 
-        // Create children
-        let range_info = ranges.iter().find(|(d, _, _)| *d == best_dim).unwrap();
-        let (dim, min_val, max_val) = *range_info;
+impl CutStrategy for Builder {
+    type Node = Node;
+    const NAME: &'static str = "hicuts";
 
-        let range_size = max_val as u64 - min_val as u64 + 1;
-        let step = (range_size / num_cuts as u64) as u32; // Integer division, last bin might be larger/smaller slightly?
-                                                          // To be safe in coverage, careful with step size.
-                                                          // Simplification: Divide linearly.
-
-        let mut children = Vec::with_capacity(num_cuts as usize);
-
-        for i in 0..num_cuts {
-            let cut_min = min_val + i * step;
-            let cut_max = if i == num_cuts - 1 {
-                max_val
-            } else {
-                min_val + (i + 1) * step - 1
-            };
-
-            // Filter rules
-            let mut child_rules = Vec::new();
-            for rule in rules {
-                if self.rule_overlaps(rule, dim, cut_min, cut_max) {
-                    child_rules.push(rule.clone());
-                }
-            }
+    fn params(&self) -> TreeParams {
+        TreeParams {
+            leaf_threshold: self.leaf_threshold,
+            max_depth: self.max_depth,
+            leaf_policy: self.leaf_policy,
+            secondary_threshold: self.secondary_threshold,
+            spfac: None,
+        }
+    }
 
-            // Recurse
-            let mut new_ranges = ranges.to_vec();
-            for r in &mut new_ranges {
-                if r.0 == dim {
-                    *r = (dim, cut_min, cut_max);
-                    break;
-                }
-            }
+    fn choose_cut(&self, rules: &[&Rule], region: &Region, depth: usize) -> Option<NodeCut> {
+        // Cut decisions look at the rules clipped to this node's region: a
+        // rule wider than the node is indistinguishable from a wildcard here.
+        // The stored rules stay whole, for matching.
+        let ranges = Dimension::ALL.map(|d| (d, region.get(d).min, region.get(d).max));
+        let clipped: Vec<Option<Region>> = rules
+            .iter()
+            .map(|r| Region::from_rule(r).intersection(region))
+            .collect();
 
-            children.push(Box::new(self.build_recursive(
-                &child_rules,
-                depth + 1,
-                &new_ranges,
-            )));
+        // Heuristic: Select dimension and number of cuts
+        let (dim, num_cuts) =
+            self.select_dimension_and_cuts(rules, &clipped, &ranges, region, depth);
+        if num_cuts <= 1 {
+            // Cannot cut effectively
+            return None;
         }
 
-        Node::Internal {
+        // Equal-width bins, the last one taking the remainder.
+        let Range {
+            min: min_val,
+            max: max_val,
+        } = region.get(dim);
+        let range_size = max_val as u64 - min_val as u64 + 1;
+        let step = (range_size / num_cuts as u64) as u32;
+        let children = (0..num_cuts)
+            .map(|i| {
+                let cut_max = if i == num_cuts - 1 {
+                    max_val
+                } else {
+                    min_val + (i + 1) * step - 1
+                };
+                Range::new(min_val + i * step, cut_max)
+            })
+            .collect();
+        Some(NodeCut {
             dimension: dim,
-            start: min_val,
-            step,
-            num_cuts,
             children,
+        })
+    }
+
+    fn internal(&self, cut: &NodeCut, children: Vec<Node>) -> Node {
+        let first = cut.children[0];
+        Node::Internal {
+            dimension: cut.dimension,
+            start: first.min,
+            end: cut.children[cut.children.len() - 1].max,
+            step: first.max - first.min + 1,
+            num_cuts: children.len() as u32,
+            children: children.into_iter().map(Arc::new).collect(),
         }
     }
+}
 
+impl Builder {
     fn select_dimension_and_cuts(
         &self,
-        rules: &[Rule],
+        rules: &[&Rule],
+        clipped: &[Option<Region>],
         ranges: &[(Dimension, u32, u32)],
+        region: &Region,
+        depth: usize,
     ) -> (Dimension, u32) {
+        let selected = match &self.dimension_heuristic {
+            Some(heuristic) => match heuristic.select(rules, &Dimension::ALL, region) {
+                Some(dim) => Some(dim),
+                None => return (Dimension::SrcIp, 1),
+            },
+            None => None,
+        };
         let mut best_dim = Dimension::SrcIp;
         let mut best_cut_count = 1;
-        let mut min_max_rules = usize::MAX;
+        let mut best_cost = f32::MAX;
+        let mut best_distinct = 0;
 
         for &(dim, min_val, max_val) in ranges {
             // Can't cut if range is singular
-            if min_val >= max_val {
+            if min_val >= max_val || selected.is_some_and(|s| s != dim) {
                 continue;
             }
 
-            // Try cuts: 2, 4, 8, ... up to 16? Simplified HiCuts
-            for &cuts in &[2, 4, 8, 16] {
-                let range_len = max_val as u64 - min_val as u64 + 1;
-                if range_len < cuts as u64 {
-                    continue;
-                }
+            // Ties go to the dimension with the most distinct clipped ranges,
+            // the one along which rules differ most inside this node.
+            let mut distinct: Vec<Range<u32>> =
+                clipped.iter().flatten().map(|c| c.get(dim)).collect();
+            distinct.sort_unstable_by_key(|r| (r.min, r.max));
+            distinct.dedup();
+            let distinct = distinct.len();
 
+            // Double the number of cuts while the children fit the space
+            // budget; two cuts are always allowed.
+            let range_len = max_val as u64 - min_val as u64 + 1;
+            let space_budget = self.spfac.saturating_mul(rules.len());
+            let mut cuts = 2u32;
+            while cuts <= self.max_cuts.max(2) && range_len >= cuts as u64 {
                 let step = (range_len / cuts as u64) as u32;
-                let mut max_rules_in_bin = 0;
-                let mut _total_rules = 0;
+                let mut bins = Vec::with_capacity(cuts as usize);
 
                 for i in 0..cuts {
                     let c_min = min_val + i * step;
@@ -143,40 +343,39 @@ impl Builder {
                         min_val + (i + 1) * step - 1
                     };
 
-                    let mut bin_count = 0;
-                    for rule in rules {
-                        if self.rule_overlaps(rule, dim, c_min, c_max) {
-                            bin_count += 1;
-                        }
-                    }
-                    if bin_count > max_rules_in_bin {
-                        max_rules_in_bin = bin_count;
-                    }
+                    bins.push(
+                        rules
+                            .iter()
+                            .filter(|rule| rule_reaches(rule, dim, &Range::new(c_min, c_max)))
+                            .count(),
+                    );
+                }
+                let cut = Cut {
+                    dimension: dim,
+                    depth,
+                    rules: rules.len(),
+                    children: &bins,
+                };
+                if cuts > 2 && cut.total() + cuts as usize > space_budget {
+                    break;
                 }
 
-                // Cost function: Minimize max bucket size + penalty for duplication
-                // Simplified: Just minimize max bucket size for now, ensure "progress".
-                if max_rules_in_bin < min_max_rules && max_rules_in_bin < rules.len() {
-                    min_max_rules = max_rules_in_bin;
+                // Only cuts making progress (no bin keeps every rule) count.
+                let cost = self.cut_cost.cost(&cut);
+                let better = cost < best_cost || (cost == best_cost && distinct > best_distinct);
+                if better && cut.largest() < rules.len() {
+                    best_cost = cost;
                     best_dim = dim;
                     best_cut_count = cuts;
+                    best_distinct = distinct;
                 }
+                let Some(next) = cuts.checked_mul(2) else {
+                    break;
+                };
+                cuts = next;
             }
         }
 
         (best_dim, best_cut_count)
     }
-
-    fn rule_overlaps(&self, rule: &Rule, dim: Dimension, min_val: u32, max_val: u32) -> bool {
-        let range = match dim {
-            Dimension::SrcIp => rule.src_ip,
-            Dimension::DstIp => rule.dst_ip,
-            Dimension::SrcPort => Range::new(rule.src_port.min as u32, rule.src_port.max as u32),
-            Dimension::DstPort => Range::new(rule.dst_port.min as u32, rule.dst_port.max as u32),
-            Dimension::Proto => Range::new(rule.proto.min as u32, rule.proto.max as u32),
-        };
-
-        // Range overlap: rule.min <= region.max && rule.max >= region.min
-        range.min <= max_val && range.max >= min_val
-    }
 }
@@ -5,25 +5,118 @@
 //! Pankaj Gupta and Nick McKeown (2000)
 //! <http://yuba.stanford.edu/~nickm/papers/sigcomm2000.pdf>
 
-use crate::classifier::Classifier;
-use crate::cutsplit::tree::Dimension;
+use crate::batch;
+use crate::classifier::{Classifier, Config, LookupStats};
+use crate::dimension::Dimension;
+use crate::dtree::{WorstCase, WorstCaseError};
+use crate::fingerprint::Fingerprint;
+use crate::freeze::Freeze;
+use crate::geometry::Region;
 use crate::hicuts::builder::Builder;
 use crate::hicuts::tree::Node;
+use crate::memory::{Footprint, MemoryStats, Tally};
 use crate::packet::FiveTuple;
-use crate::rule::{Action, Rule};
+use crate::query::{Overlapping, RegionQuery};
+use crate::rule::{Action, Range, Rule};
+use crate::serial::{self, Codec};
+use crate::update::audit::{DecodeError, Reader};
+use alloc::boxed::Box;
+#[cfg(feature = "single-core")]
+use alloc::rc::Rc as Arc;
+#[cfg(not(feature = "single-core"))]
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+use hashbrown::{HashMap, HashSet};
 
+/// What a lookup does when a packet value falls outside an internal node's range.
+///
+/// Trees built by `Builder::build` cover the whole packet space, so this only
+/// matters for trees built over a narrower region (`Builder::build_region`).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum OutOfRange {
+    /// Descend into the nearest child (first or last cut). Any rule returned
+    /// still matches the packet, but better rules outside the region are missed.
+    #[default]
+    Clamp,
+    /// Fail the lookup: `classify` returns no match and `try_classify_rule`
+    /// reports the offending value.
+    Error,
+}
+
+/// Lookup failure in `OutOfRange::Error` mode.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct OutOfRangeError {
+    /// Dimension of the node that could not place the packet.
+    pub dimension: Dimension,
+    /// Packet value on that dimension.
+    pub value: u32,
+    /// Range covered by the node.
+    pub range: Range<u32>,
+}
+
+#[derive(Clone)]
 pub struct HiCutsClassifier {
     root: Node,
+    out_of_range: OutOfRange,
+    fingerprint: Option<Fingerprint>,
 }
 
-impl Classifier for HiCutsClassifier {
-    fn build(rules: &[Rule]) -> Self {
-        let builder = Builder::new(10, 20);
-        let root = builder.build(rules);
-        Self { root }
+impl HiCutsClassifier {
+    /// Build the classifier with a custom-configured `Builder`.
+    pub fn from_builder(builder: &Builder, rules: &[Rule]) -> Self {
+        Self::from_tree(builder.build(rules)).with_fingerprint(Fingerprint::of(rules))
+    }
+
+    /// Build the classifier with `builder`, guaranteeing every lookup stays
+    /// within `bound`; fails if the rules cannot be classified within it.
+    pub fn from_builder_bounded(
+        builder: &Builder,
+        rules: &[Rule],
+        bound: WorstCase,
+    ) -> Result<Self, WorstCaseError> {
+        let (root, _) = builder.build_bounded(rules, bound)?;
+        Ok(Self::from_tree(root).with_fingerprint(Fingerprint::of(rules)))
+    }
+
+    /// Use a tree built elsewhere (e.g. with `Builder::build_region`).
+    ///
+    /// The tree has no fingerprint until `with_fingerprint` sets one.
+    pub fn from_tree(root: Node) -> Self {
+        Self {
+            root,
+            out_of_range: OutOfRange::default(),
+            fingerprint: None,
+        }
+    }
+
+    /// Record the fingerprint of the rules the tree was built from.
+    pub fn with_fingerprint(mut self, fingerprint: Fingerprint) -> Self {
+        self.fingerprint = Some(fingerprint);
+        self
     }
 
-    fn classify(&self, packet: &FiveTuple) -> Option<Action> {
+    /// Fingerprint of the rules the tree was built from, if known.
+    pub fn fingerprint(&self) -> Option<Fingerprint> {
+        self.fingerprint
+    }
+
+    /// Set the out-of-range handling mode.
+    pub fn with_out_of_range(mut self, mode: OutOfRange) -> Self {
+        self.out_of_range = mode;
+        self
+    }
+
+    /// Like `classify_rule`, but reports packets the tree cannot place
+    /// when the out-of-range mode is `Error`.
+    pub fn try_classify_rule(&self, packet: &FiveTuple) -> Result<Option<&Rule>, OutOfRangeError> {
+        self.lookup(packet, &mut LookupStats::default())
+    }
+
+    fn lookup(
+        &self,
+        packet: &FiveTuple,
+        stats: &mut LookupStats,
+    ) -> Result<Option<&Rule>, OutOfRangeError> {
         let mut current = &self.root;
 
         loop {
@@ -31,27 +124,25 @@ impl Classifier for HiCutsClassifier {
                 Node::Internal {
                     dimension,
                     start,
+                    end,
                     step,
                     num_cuts,
                     children,
                 } => {
-                    let val = match dimension {
-                        Dimension::SrcIp => packet.src_ip,
-                        Dimension::DstIp => packet.dst_ip,
-                        Dimension::SrcPort => packet.src_port as u32,
-                        Dimension::DstPort => packet.dst_port as u32,
-                        Dimension::Proto => packet.proto as u32,
-                    };
+                    stats.depth += 1;
+                    let val = dimension.packet_value(packet);
 
-                    // Calculate index
-                    // idx = (val - start) / step
-                    if val < *start {
-                        // Should technically not happen if start matches root range 0, but safety check for unexpected ranges?
-                        // If outside, usually means defaults or should clamp.
-                        return None;
+                    if (val < *start || val > *end) && self.out_of_range == OutOfRange::Error {
+                        return Err(OutOfRangeError {
+                            dimension: *dimension,
+                            value: val,
+                            range: Range::new(*start, *end),
+                        });
                     }
 
-                    let offset = val - start;
+                    // Calculate index
+                    // idx = (val - start) / step, clamped to the first and last cut
+                    let offset = val.saturating_sub(*start);
                     let mut index = offset / step;
 
                     if index >= *num_cuts {
@@ -62,13 +153,315 @@ impl Classifier for HiCutsClassifier {
                 }
                 Node::Leaf { rules } => {
                     for rule in rules {
+                        stats.rules_compared += 1;
                         if rule.matches(packet) {
-                            return Some(rule.action);
+                            return Ok(Some(rule));
+                        }
+                    }
+                    return Ok(None);
+                }
+                Node::IndexedLeaf { index } => return Ok(index.lookup(packet, stats)),
+            }
+        }
+    }
+}
+
+impl Classifier for HiCutsClassifier {
+    fn build(rules: &[Rule]) -> Self {
+        Self::build_with_config(rules, &Config::default())
+    }
+
+    fn build_with_config(rules: &[Rule], config: &Config) -> Self {
+        let builder = Builder::new(10, 20).with_config(config);
+        Self::from_builder(&builder, rules)
+    }
+
+    fn classify_rule(&self, packet: &FiveTuple) -> Option<&Rule> {
+        self.try_classify_rule(packet).unwrap_or(None)
+    }
+
+    fn classify_batch(&self, packets: &[FiveTuple], out: &mut [Option<Action>]) {
+        batch::check_lengths(packets, out);
+        let mut order = batch::all(packets);
+        let mut stack = alloc::vec![(&self.root, 0, order.len())];
+        while let Some((node, mut start, end)) = stack.pop() {
+            let group = &mut order[start..end];
+            match node {
+                Node::Internal {
+                    dimension,
+                    start: low,
+                    end: high,
+                    step,
+                    num_cuts,
+                    children,
+                } => {
+                    let value = |i: u32| dimension.packet_value(&packets[i as usize]);
+                    let mut group = group;
+                    if self.out_of_range == OutOfRange::Error {
+                        // Packets the node cannot place match nothing.
+                        let outside = batch::split(group, |i| !(*low..=*high).contains(&value(i)));
+                        for &i in &group[..outside] {
+                            out[i as usize] = None;
                         }
+                        group = &mut group[outside..];
+                        start += outside;
+                    }
+                    // Same child selection as `lookup`; sorting by child
+                    // leaves one run of packets per child reached.
+                    let cut = |i: u32| (value(i).saturating_sub(*low) / step).min(num_cuts - 1);
+                    group.sort_unstable_by_key(|&i| cut(i));
+                    let mut run = 0;
+                    while run < group.len() {
+                        let child = cut(group[run]);
+                        let len = group[run..].partition_point(|&i| cut(i) == child);
+                        stack.push((&children[child as usize], start + run, start + run + len));
+                        run += len;
                     }
-                    return None;
                 }
+                Node::Leaf { rules } => batch::scan(rules.iter(), group, packets, out),
+                Node::IndexedLeaf { index } => batch::scan_index(index, group, packets, out),
             }
         }
     }
+
+    fn classify_with_stats(&self, packet: &FiveTuple) -> (Option<Action>, LookupStats) {
+        let mut stats = LookupStats::default();
+        let action = self
+            .lookup(packet, &mut stats)
+            .unwrap_or(None)
+            .map(|r| r.action);
+        (action, stats)
+    }
+
+    fn classify_rule_with_stats(&self, packet: &FiveTuple) -> (Option<&Rule>, LookupStats) {
+        let mut stats = LookupStats::default();
+        let rule = self.lookup(packet, &mut stats).unwrap_or(None);
+        (rule, stats)
+    }
+}
+
+impl RegionQuery for HiCutsClassifier {
+    fn rules_overlapping(&self, region: &Region) -> Vec<&Rule> {
+        let mut found = Overlapping::new(region);
+        let mut stack = alloc::vec![&self.root];
+        while let Some(node) = stack.pop() {
+            match node {
+                Node::Internal {
+                    dimension,
+                    start,
+                    step,
+                    num_cuts,
+                    children,
+                    ..
+                } => {
+                    // Children of the cuts the range spans, clamped like lookups.
+                    let range = region.get(*dimension);
+                    let cut = |v: u32| (v.saturating_sub(*start) / step).min(num_cuts - 1) as usize;
+                    stack.extend(
+                        children[cut(range.min)..=cut(range.max)]
+                            .iter()
+                            .map(|child| &**child),
+                    );
+                }
+                Node::Leaf { rules } => found.scan(rules),
+                Node::IndexedLeaf { index } => found.scan(index.rules()),
+            }
+        }
+        found.finish()
+    }
+}
+
+impl MemoryStats for HiCutsClassifier {
+    fn footprint(&self) -> Footprint {
+        // Shared subtrees are counted once, but still reached at every depth.
+        let mut tally = Tally::new(size_of::<Self>() + self.root.heap_bytes());
+        let mut seen = HashSet::new();
+        let mut stack = alloc::vec![(&self.root, 0)];
+        while let Some((node, depth)) = stack.pop() {
+            let first = seen.insert(node as *const Node);
+            match node {
+                Node::Internal { children, .. } => {
+                    if first {
+                        tally.internal();
+                    }
+                    stack.extend(children.iter().map(|child| (&**child, depth + 1)));
+                }
+                Node::Leaf { .. } | Node::IndexedLeaf { .. } if !first => tally.reached(depth),
+                Node::Leaf { rules } => tally.leaf(depth, rules),
+                Node::IndexedLeaf { index } => tally.leaf(depth, index.rules()),
+            }
+        }
+        tally.finish()
+    }
+}
+
+impl Freeze for HiCutsClassifier {
+    fn shrink_to_fit(&mut self) {
+        self.root.shrink_to_fit();
+    }
+}
+
+impl Codec for HiCutsClassifier {
+    fn serialize(&self) -> Vec<u8> {
+        let mut out = serial::header(serial::HICUTS, self.fingerprint);
+        out.push(match self.out_of_range {
+            OutOfRange::Clamp => 0,
+            OutOfRange::Error => 1,
+        });
+        encode_node(&mut out, &self.root, &mut HashMap::new());
+        out
+    }
+
+    fn deserialize(bytes: &[u8]) -> Result<Self, DecodeError> {
+        let mut r = serial::reader(bytes);
+        let fingerprint = serial::open(&mut r, serial::HICUTS)?;
+        let out_of_range = match r.u8()? {
+            0 => OutOfRange::Clamp,
+            1 => OutOfRange::Error,
+            tag => return Err(DecodeError::InvalidTag(tag)),
+        };
+        let (root, _, _) = decode_node(r.u8()?, &mut r, 0, &mut Vec::new())?;
+        serial::finish(&r)?;
+        Ok(Self {
+            root,
+            out_of_range,
+            fingerprint,
+        })
+    }
+}
+
+/// Tag of a child already written: a `u32` index into the subtrees written
+/// so far, in the order they were completed.
+const NODE_SHARED: u8 = 3;
+
+/// Most nodes a decoded tree may reach through its back-references, so a
+/// small blob cannot describe a tree whose traversal never ends; far above
+/// what the builder produces.
+const MAX_EXPANDED_NODES: usize = 1 << 24;
+
+/// Encode `node`, writing each shared child once and a back-reference to it
+/// wherever it appears again.
+fn encode_node(out: &mut Vec<u8>, node: &Node, written: &mut HashMap<*const Node, u32>) {
+    match node {
+        Node::Internal {
+            dimension,
+            start,
+            end,
+            step,
+            num_cuts,
+            children,
+        } => {
+            out.push(0);
+            out.push(dimension.index() as u8);
+            for v in [*start, *end, *step, *num_cuts] {
+                serial::put_u32(out, v);
+            }
+            for child in children {
+                let ptr = Arc::as_ptr(child);
+                if let Some(&index) = written.get(&ptr) {
+                    out.push(NODE_SHARED);
+                    serial::put_u32(out, index);
+                } else {
+                    encode_node(out, child, written);
+                    let index = written.len() as u32;
+                    written.insert(ptr, index);
+                }
+            }
+        }
+        Node::Leaf { rules } => {
+            out.push(1);
+            serial::encode_rules(out, rules);
+        }
+        Node::IndexedLeaf { index } => {
+            out.push(2);
+            serial::encode_rules(out, index.rules());
+        }
+    }
+}
+
+/// A decoded child, kept for back-references: the node, the number of
+/// internal levels below and including it, and its expanded node count.
+#[derive(Clone)]
+struct Subtree {
+    node: Arc<Node>,
+    height: usize,
+    size: usize,
+}
+
+/// Decode the node with `tag` at `depth`, returning it with its height and
+/// expanded size. Completed children are appended to `shared`.
+fn decode_node(
+    tag: u8,
+    r: &mut Reader<'_>,
+    depth: usize,
+    shared: &mut Vec<Subtree>,
+) -> Result<(Node, usize, usize), DecodeError> {
+    match tag {
+        0 => {
+            if depth >= serial::MAX_DEPTH {
+                return Err(DecodeError::Corrupt);
+            }
+            let dimension = serial::dimension(r.u8()?)?;
+            let (start, end, step, num_cuts) = (r.u32()?, r.u32()?, r.u32()?, r.u32()?);
+            // Lookups divide by `step` and index up to `num_cuts - 1`.
+            if step == 0 || num_cuts == 0 {
+                return Err(DecodeError::Corrupt);
+            }
+            let (mut height, mut size) = (1, 1usize);
+            let mut children = Vec::new();
+            for _ in 0..num_cuts {
+                let child = decode_child(r, depth + 1, shared)?;
+                height = height.max(child.height + 1);
+                size = size.saturating_add(child.size);
+                if size > MAX_EXPANDED_NODES {
+                    return Err(DecodeError::Corrupt);
+                }
+                children.push(child.node);
+            }
+            let node = Node::Internal {
+                dimension,
+                start,
+                end,
+                step,
+                num_cuts,
+                children,
+            };
+            Ok((node, height, size))
+        }
+        1 => {
+            let rules = serial::decode_rules(r)?;
+            Ok((Node::Leaf { rules }, 0, 1))
+        }
+        2 => {
+            let index = Box::new(serial::decode_index(r)?);
+            Ok((Node::IndexedLeaf { index }, 0, 1))
+        }
+        tag => Err(DecodeError::InvalidTag(tag)),
+    }
+}
+
+/// Decode a child at `depth`, either in full or as a back-reference to a
+/// subtree decoded earlier.
+fn decode_child(
+    r: &mut Reader<'_>,
+    depth: usize,
+    shared: &mut Vec<Subtree>,
+) -> Result<Subtree, DecodeError> {
+    let tag = r.u8()?;
+    if tag == NODE_SHARED {
+        let subtree = shared.get(r.u32()? as usize).ok_or(DecodeError::Corrupt)?;
+        // The subtree was only checked at the depth it was written at.
+        if depth + subtree.height > serial::MAX_DEPTH {
+            return Err(DecodeError::Corrupt);
+        }
+        return Ok(subtree.clone());
+    }
+    let (node, height, size) = decode_node(tag, r, depth, shared)?;
+    let subtree = Subtree {
+        node: Arc::new(node),
+        height,
+        size,
+    };
+    shared.push(subtree.clone());
+    Ok(subtree)
 }
@@ -1,7 +1,14 @@
-use crate::cutsplit::tree::Dimension;
+use crate::dimension::Dimension;
+use crate::dtree::TreeNode;
+use crate::leaf::BitVectorIndex;
 use crate::rule::Rule;
 use alloc::boxed::Box;
+#[cfg(feature = "single-core")]
+use alloc::rc::Rc as Arc;
+#[cfg(not(feature = "single-core"))]
+use alloc::sync::Arc;
 use alloc::vec::Vec; // Reuse Dimension enum
+use hashbrown::HashSet;
 
 /// A node in the HiCuts decision tree.
 #[derive(Debug, Clone)]
@@ -11,16 +18,79 @@ pub enum Node {
         dimension: Dimension,
         /// Start of the range covered by this node (for calculating offset)
         start: u32,
-        /// End of the range (exclusive or inclusive? usually implied by parent, but helpful for calculation)
-        /// Let's store the step size or shift to make classification fast.
-        /// If we divide range [min, max] into N cuts, step = (max - min) / N.
+        /// End of the range covered by this node (inclusive).
+        end: u32,
+        /// Width of each cut (the last child also takes the remainder).
+        /// If we divide range [min, max] into N cuts, step = (max - min + 1) / N.
         step: u32,
         /// Number of cuts (children len)
         num_cuts: u32,
-        /// Children nodes
-        children: Vec<Box<Node>>,
+        /// Children nodes. Identical subtrees are built once and shared
+        /// (see `builder::share_subtrees`), so several cuts, here or in
+        /// other nodes, may point to the same child.
+        children: Vec<Arc<Node>>,
     },
     Leaf {
         rules: Vec<Rule>,
     },
+    /// Oversized leaf backed by a bit-vector index instead of a linear list.
+    IndexedLeaf {
+        /// Secondary structure holding the leaf's rules.
+        index: Box<BitVectorIndex>,
+    },
+}
+
+impl Node {
+    /// Release spare capacity in the whole subtree (shared children are
+    /// left as they are).
+    pub fn shrink_to_fit(&mut self) {
+        match self {
+            Node::Internal { children, .. } => {
+                children.shrink_to_fit();
+                for child in children.iter_mut().filter_map(Arc::get_mut) {
+                    child.shrink_to_fit();
+                }
+            }
+            Node::Leaf { rules } => rules.shrink_to_fit(),
+            Node::IndexedLeaf { index } => index.shrink_to_fit(),
+        }
+    }
+
+    /// Heap bytes held by the subtree, counting shared children once.
+    pub fn heap_bytes(&self) -> usize {
+        self.unique_heap_bytes(&mut HashSet::new())
+    }
+
+    fn unique_heap_bytes(&self, seen: &mut HashSet<*const Node>) -> usize {
+        match self {
+            Node::Internal { children, .. } => {
+                let mut bytes = children.capacity() * size_of::<Arc<Node>>();
+                for child in children {
+                    if seen.insert(Arc::as_ptr(child)) {
+                        // The node itself, next to the reference counts.
+                        bytes += size_of::<Node>()
+                            + 2 * size_of::<usize>()
+                            + child.unique_heap_bytes(seen);
+                    }
+                }
+                bytes
+            }
+            Node::Leaf { rules } => rules.capacity() * size_of::<Rule>(),
+            Node::IndexedLeaf { index } => size_of::<BitVectorIndex>() + index.heap_bytes(),
+        }
+    }
+}
+
+impl TreeNode for Node {
+    fn leaf(rules: Vec<Rule>) -> Self {
+        Node::Leaf { rules }
+    }
+
+    fn indexed_leaf(index: Box<BitVectorIndex>) -> Self {
+        Node::IndexedLeaf { index }
+    }
+
+    fn heap_bytes(&self) -> usize {
+        Node::heap_bytes(self)
+    }
 }
@@ -1,12 +1,33 @@
-use crate::cutsplit::tree::Dimension;
+use crate::classifier::Config;
+use crate::cost::{Balance, Cut, CutCost};
+use crate::dimension::Dimension;
+use crate::dtree::{
+    self, count_split, CutStrategy, NodeCut, TreeParams, WorstCase, WorstCaseError,
+};
+use crate::geometry::Region;
+use crate::heuristic::DimensionHeuristic;
 use crate::hypersplit::tree::Node;
-use crate::rule::{Range, Rule};
+use crate::leaf::LeafPolicy;
+use crate::rule::Rule;
 use alloc::boxed::Box;
 use alloc::vec::Vec;
 
+pub use crate::dtree::BuildStats;
+
 pub struct Builder {
     pub leaf_threshold: usize,
     pub max_depth: usize,
+    /// Stop splitting when the children would hold more than `spfac` times
+    /// the parent's rules (None = no limit).
+    pub spfac: Option<f32>,
+    /// How `leaf_threshold` evolves with depth and duplication.
+    pub leaf_policy: LeafPolicy,
+    /// Leaves with more rules than this get a bit-vector index (None = always linear).
+    pub secondary_threshold: Option<usize>,
+    /// Picks the dimension of each cut (None = try every dimension).
+    pub dimension_heuristic: Option<Box<dyn DimensionHeuristic>>,
+    /// Cost of a candidate cut; the cheapest one is made.
+    pub cut_cost: Box<dyn CutCost>,
 }
 
 impl Builder {
@@ -14,46 +35,114 @@ impl Builder {
         Self {
             leaf_threshold,
             max_depth,
+            spfac: None,
+            leaf_policy: LeafPolicy::Fixed,
+            secondary_threshold: None,
+            dimension_heuristic: None,
+            cut_cost: Box::new(Balance::default()),
         }
     }
 
+    /// Use the given leaf policy instead of a fixed threshold.
+    pub fn with_leaf_policy(mut self, policy: LeafPolicy) -> Self {
+        self.leaf_policy = policy;
+        self
+    }
+
+    /// Index leaves holding more than `threshold` rules with a `BitVectorIndex`.
+    pub fn with_secondary_index(mut self, threshold: usize) -> Self {
+        self.secondary_threshold = Some(threshold);
+        self
+    }
+
+    /// Let `heuristic` choose the dimension of each cut; the cut point is
+    /// then searched on that dimension only.
+    pub fn with_dimension_heuristic(
+        mut self,
+        heuristic: impl DimensionHeuristic + 'static,
+    ) -> Self {
+        self.dimension_heuristic = Some(Box::new(heuristic));
+        self
+    }
+
+    /// Choose cuts by `cost` instead of `Balance::default()`.
+    pub fn with_cut_cost(mut self, cost: impl CutCost + 'static) -> Self {
+        self.cut_cost = Box::new(cost);
+        self
+    }
+
+    /// Stop splitting a node when its children would hold more than `spfac`
+    /// times its rules in total, bounding replication on wildcard-dense sets.
+    pub fn with_spfac(mut self, spfac: f32) -> Self {
+        self.spfac = Some(spfac);
+        self
+    }
+
+    /// Override the parameters `config` sets.
+    pub fn with_config(mut self, config: &Config) -> Self {
+        self.leaf_threshold = config.leaf_threshold.unwrap_or(self.leaf_threshold);
+        self.max_depth = config.max_depth.unwrap_or(self.max_depth);
+        self.spfac = config.spfac.or(self.spfac);
+        self
+    }
+
     pub fn build(&self, rules: &[Rule]) -> Node {
-        self.build_recursive(rules, 0)
+        self.build_with_stats(rules).0
     }
 
-    fn build_recursive(&self, rules: &[Rule], depth: usize) -> Node {
-        if rules.len() <= self.leaf_threshold || depth >= self.max_depth {
-            return Node::Leaf {
-                rules: rules.to_vec(),
-            };
-        }
+    /// Build the tree and report its shape and stop conditions.
+    pub fn build_with_stats(&self, rules: &[Rule]) -> (Node, BuildStats) {
+        dtree::build(self, rules, &Region::full())
+    }
 
-        // Find best split
-        if let Some((dim, pivot)) = self.find_best_split(rules) {
-            let (left_rules, right_rules) = self.split_rules(rules, dim, pivot);
+    /// Build a tree whose lookups stay within `bound`, or fail if none can
+    /// (see `dtree::build_bounded`).
+    pub fn build_bounded(
+        &self,
+        rules: &[Rule],
+        bound: WorstCase,
+    ) -> Result<(Node, BuildStats), WorstCaseError> {
+        dtree::build_bounded(self, rules, &Region::full(), bound)
+    }
+}
 
-            // Optimization: If split doesn't reduce max set size significantly, stop or change strategy.
-            // For now, simple recursion.
-            if left_rules.len() == rules.len() && right_rules.len() == rules.len() {
-                return Node::Leaf {
-                    rules: rules.to_vec(),
-                };
-            }
+impl CutStrategy for Builder {
+    type Node = Node;
+    const NAME: &'static str = "hypersplit";
+
+    fn params(&self) -> TreeParams {
+        TreeParams {
+            leaf_threshold: self.leaf_threshold,
+            max_depth: self.max_depth,
+            leaf_policy: self.leaf_policy,
+            secondary_threshold: self.secondary_threshold,
+            spfac: self.spfac,
+        }
+    }
 
-            Node::Internal {
-                dimension: dim,
-                pivot,
-                left: Box::new(self.build_recursive(&left_rules, depth + 1)),
-                right: Box::new(self.build_recursive(&right_rules, depth + 1)),
-            }
-        } else {
-            Node::Leaf {
-                rules: rules.to_vec(),
-            }
+    fn choose_cut(&self, rules: &[&Rule], region: &Region, depth: usize) -> Option<NodeCut> {
+        let (dim, pivot) = self.find_best_split(rules, region, depth)?;
+        Some(NodeCut::binary(dim, pivot))
+    }
+
+    fn internal(&self, cut: &NodeCut, children: Vec<Node>) -> Node {
+        let [left, right] = <[Node; 2]>::try_from(children).expect("binary cut");
+        Node::Internal {
+            dimension: cut.dimension,
+            pivot: cut.children[1].min,
+            left: Box::new(left),
+            right: Box::new(right),
         }
     }
+}
 
-    fn find_best_split(&self, rules: &[Rule]) -> Option<(Dimension, u32)> {
+impl Builder {
+    fn find_best_split(
+        &self,
+        rules: &[&Rule],
+        region: &Region,
+        depth: usize,
+    ) -> Option<(Dimension, u32)> {
         let dimensions = [
             Dimension::SrcIp,
             Dimension::DstIp,
@@ -61,14 +150,22 @@ impl Builder {
             Dimension::DstPort,
             Dimension::Proto,
         ];
-        let mut best_score = f32::MAX;
+        let selected;
+        let dimensions: &[Dimension] = match &self.dimension_heuristic {
+            Some(heuristic) => {
+                selected = [heuristic.select(rules, &dimensions, region)?];
+                &selected
+            }
+            None => &dimensions,
+        };
+        let mut best_cost = f32::MAX;
         let mut best_split = None;
 
-        for &dim in &dimensions {
+        for &dim in dimensions {
             // Collect candidates
             let mut points = Vec::new();
             for rule in rules {
-                let range = self.get_range(rule, dim);
+                let range = dim.rule_range(rule);
                 points.push(range.min);
                 points.push(range.max.saturating_add(1));
             }
@@ -88,7 +185,7 @@ impl Builder {
                     continue;
                 } // Avoid splitting at 0 if min is 0
 
-                let (l, r) = self.count_split(rules, dim, pivot);
+                let (l, r) = count_split(rules, dim, pivot);
 
                 // Avoid empty splits
                 if l == 0 || r == 0 {
@@ -98,55 +195,19 @@ impl Builder {
                     continue;
                 }
 
-                // Cost: Max(L, R) roughly approximates worst-case search + penalty for sum (duplication)
-                let score = (l.max(r) as f32) + 0.1 * ((l + r) as f32);
+                let cost = self.cut_cost.cost(&Cut {
+                    dimension: dim,
+                    depth,
+                    rules: rules.len(),
+                    children: &[l, r],
+                });
 
-                if score < best_score {
-                    best_score = score;
+                if cost < best_cost {
+                    best_cost = cost;
                     best_split = Some((dim, pivot));
                 }
             }
         }
         best_split
     }
-
-    fn split_rules(&self, rules: &[Rule], dim: Dimension, pivot: u32) -> (Vec<Rule>, Vec<Rule>) {
-        let mut left = Vec::new();
-        let mut right = Vec::new();
-        for rule in rules {
-            let range = self.get_range(rule, dim);
-            if range.min < pivot {
-                left.push(rule.clone());
-            }
-            if range.max >= pivot {
-                right.push(rule.clone());
-            }
-        }
-        (left, right)
-    }
-
-    fn count_split(&self, rules: &[Rule], dim: Dimension, pivot: u32) -> (usize, usize) {
-        let mut l = 0;
-        let mut r = 0;
-        for rule in rules {
-            let range = self.get_range(rule, dim);
-            if range.min < pivot {
-                l += 1;
-            }
-            if range.max >= pivot {
-                r += 1;
-            }
-        }
-        (l, r)
-    }
-
-    fn get_range(&self, rule: &Rule, dim: Dimension) -> Range<u32> {
-        match dim {
-            Dimension::SrcIp => rule.src_ip,
-            Dimension::DstIp => rule.dst_ip,
-            Dimension::SrcPort => Range::new(rule.src_port.min as u32, rule.src_port.max as u32),
-            Dimension::DstPort => Range::new(rule.dst_port.min as u32, rule.dst_port.max as u32),
-            Dimension::Proto => Range::new(rule.proto.min as u32, rule.proto.max as u32),
-        }
-    }
 }
@@ -5,26 +5,64 @@
 //! Yaxuan Qi, et al. (IEEE INFOCOM 2009)
 //! <https://ieeexplore.ieee.org/document/5061887>
 
-use crate::classifier::Classifier;
-use crate::cutsplit::tree::Dimension;
-use crate::hypersplit::builder::Builder;
+use crate::classifier::{Classifier, Config, LookupStats};
+use crate::compact::{CompactTree, FreezeCompact};
+use crate::dtree::{WorstCase, WorstCaseError};
+use crate::fingerprint::Fingerprint;
+use crate::freeze::Freeze;
+use crate::geometry::Region;
+use crate::hypersplit::builder::{BuildStats, Builder};
 use crate::hypersplit::tree::Node;
+use crate::memory::{self, Footprint, MemoryStats};
 use crate::packet::FiveTuple;
+use crate::query::{Overlapping, RegionQuery};
 use crate::rule::{Action, Rule};
+use crate::serial::{self, Codec};
+use crate::update::audit::DecodeError;
+use alloc::vec::Vec;
 
+#[derive(Clone)]
 pub struct HyperSplitClassifier {
     root: Node,
+    fingerprint: Option<Fingerprint>,
 }
 
-impl Classifier for HyperSplitClassifier {
-    fn build(rules: &[Rule]) -> Self {
-        // HyperSplit usually builds deeper trees with lower duplicate ratio
-        let builder = Builder::new(8, 32);
-        let root = builder.build(rules);
-        Self { root }
+impl HyperSplitClassifier {
+    /// Build the classifier with a custom-configured `Builder`.
+    pub fn from_builder(builder: &Builder, rules: &[Rule]) -> Self {
+        Self {
+            root: builder.build(rules),
+            fingerprint: Some(Fingerprint::of(rules)),
+        }
+    }
+
+    /// Build the classifier with `builder`, guaranteeing every lookup stays
+    /// within `bound`; fails if the rules cannot be classified within it.
+    pub fn from_builder_bounded(
+        builder: &Builder,
+        rules: &[Rule],
+        bound: WorstCase,
+    ) -> Result<Self, WorstCaseError> {
+        let (root, _) = builder.build_bounded(rules, bound)?;
+        Ok(Self {
+            root,
+            fingerprint: Some(Fingerprint::of(rules)),
+        })
     }
 
-    fn classify(&self, packet: &FiveTuple) -> Option<Action> {
+    /// Like `from_builder`, also reporting how the tree was built.
+    pub fn from_builder_with_stats(builder: &Builder, rules: &[Rule]) -> (Self, BuildStats) {
+        let (root, stats) = builder.build_with_stats(rules);
+        let fingerprint = Some(Fingerprint::of(rules));
+        (Self { root, fingerprint }, stats)
+    }
+
+    /// Fingerprint of the rules the tree was built from.
+    pub fn fingerprint(&self) -> Option<Fingerprint> {
+        self.fingerprint
+    }
+
+    fn lookup(&self, packet: &FiveTuple, stats: &mut LookupStats) -> Option<&Rule> {
         let mut current = &self.root;
 
         loop {
@@ -35,13 +73,8 @@ impl Classifier for HyperSplitClassifier {
                     left,
                     right,
                 } => {
-                    let val = match dimension {
-                        Dimension::SrcIp => packet.src_ip,
-                        Dimension::DstIp => packet.dst_ip,
-                        Dimension::SrcPort => packet.src_port as u32,
-                        Dimension::DstPort => packet.dst_port as u32,
-                        Dimension::Proto => packet.proto as u32,
-                    };
+                    stats.depth += 1;
+                    let val = dimension.packet_value(packet);
 
                     if val < *pivot {
                         current = left;
@@ -51,13 +84,88 @@ impl Classifier for HyperSplitClassifier {
                 }
                 Node::Leaf { rules } => {
                     for rule in rules {
+                        stats.rules_compared += 1;
                         if rule.matches(packet) {
-                            return Some(rule.action);
+                            return Some(rule);
                         }
                     }
                     return None;
                 }
+                Node::IndexedLeaf { index } => return index.lookup(packet, stats),
             }
         }
     }
 }
+
+impl Classifier for HyperSplitClassifier {
+    fn build(rules: &[Rule]) -> Self {
+        Self::build_with_config(rules, &Config::default())
+    }
+
+    fn build_with_config(rules: &[Rule], config: &Config) -> Self {
+        // HyperSplit usually builds deeper trees with lower duplicate ratio
+        Self::from_builder(&Builder::new(8, 32).with_config(config), rules)
+    }
+
+    fn classify_rule(&self, packet: &FiveTuple) -> Option<&Rule> {
+        self.lookup(packet, &mut LookupStats::default())
+    }
+
+    fn classify_batch(&self, packets: &[FiveTuple], out: &mut [Option<Action>]) {
+        crate::batch::walk_binary(&self.root, packets, out);
+    }
+
+    fn classify_with_stats(&self, packet: &FiveTuple) -> (Option<Action>, LookupStats) {
+        let mut stats = LookupStats::default();
+        let action = self.lookup(packet, &mut stats).map(|r| r.action);
+        (action, stats)
+    }
+
+    fn classify_rule_with_stats(&self, packet: &FiveTuple) -> (Option<&Rule>, LookupStats) {
+        let mut stats = LookupStats::default();
+        let rule = self.lookup(packet, &mut stats);
+        (rule, stats)
+    }
+}
+
+impl RegionQuery for HyperSplitClassifier {
+    fn rules_overlapping(&self, region: &Region) -> Vec<&Rule> {
+        let mut found = Overlapping::new(region);
+        found.descend(&self.root);
+        found.finish()
+    }
+}
+
+impl MemoryStats for HyperSplitClassifier {
+    fn footprint(&self) -> Footprint {
+        memory::binary(&self.root, size_of::<Self>() + self.root.heap_bytes())
+    }
+}
+
+impl Freeze for HyperSplitClassifier {
+    fn shrink_to_fit(&mut self) {
+        self.root.shrink_to_fit();
+    }
+}
+
+impl FreezeCompact for HyperSplitClassifier {
+    fn to_compact(&self) -> CompactTree {
+        CompactTree::from_root(&self.root, self.fingerprint)
+    }
+}
+
+impl Codec for HyperSplitClassifier {
+    fn serialize(&self) -> Vec<u8> {
+        let mut out = serial::header(serial::HYPERSPLIT, self.fingerprint);
+        serial::encode_binary(&mut out, &self.root);
+        out
+    }
+
+    fn deserialize(bytes: &[u8]) -> Result<Self, DecodeError> {
+        let mut r = serial::reader(bytes);
+        let fingerprint = serial::open(&mut r, serial::HYPERSPLIT)?;
+        let root = serial::decode_binary(&mut r)?;
+        serial::finish(&r)?;
+        Ok(Self { root, fingerprint })
+    }
+}
@@ -1,4 +1,7 @@
-use crate::cutsplit::tree::Dimension;
+use crate::compact::{BinaryNode, NodeView};
+use crate::dimension::Dimension;
+use crate::dtree::TreeNode;
+use crate::leaf::BitVectorIndex;
 use crate::rule::Rule;
 use alloc::boxed::Box;
 use alloc::vec::Vec;
@@ -14,4 +17,77 @@ pub enum Node {
     Leaf {
         rules: Vec<Rule>,
     },
+    /// Oversized leaf backed by a bit-vector index instead of a linear list.
+    IndexedLeaf {
+        /// Secondary structure holding the leaf's rules.
+        index: Box<BitVectorIndex>,
+    },
+}
+
+impl Node {
+    /// Release spare capacity in the whole subtree.
+    pub fn shrink_to_fit(&mut self) {
+        match self {
+            Node::Internal { left, right, .. } => {
+                left.shrink_to_fit();
+                right.shrink_to_fit();
+            }
+            Node::Leaf { rules } => rules.shrink_to_fit(),
+            Node::IndexedLeaf { index } => index.shrink_to_fit(),
+        }
+    }
+
+    /// Heap bytes held by the subtree.
+    pub fn heap_bytes(&self) -> usize {
+        match self {
+            Node::Internal { left, right, .. } => {
+                2 * size_of::<Node>() + left.heap_bytes() + right.heap_bytes()
+            }
+            Node::Leaf { rules } => rules.capacity() * size_of::<Rule>(),
+            Node::IndexedLeaf { index } => size_of::<BitVectorIndex>() + index.heap_bytes(),
+        }
+    }
+}
+
+impl BinaryNode for Node {
+    fn view(&self) -> NodeView<'_, Self> {
+        match self {
+            Node::Internal {
+                dimension,
+                pivot,
+                left,
+                right,
+            } => NodeView::Internal {
+                dimension: *dimension,
+                cut: *pivot,
+                left,
+                right,
+            },
+            Node::Leaf { rules } => NodeView::Leaf(rules),
+            Node::IndexedLeaf { index } => NodeView::IndexedLeaf(index),
+        }
+    }
+
+    fn internal(dimension: Dimension, cut: u32, left: Self, right: Self) -> Self {
+        Node::Internal {
+            dimension,
+            pivot: cut,
+            left: Box::new(left),
+            right: Box::new(right),
+        }
+    }
+}
+
+impl TreeNode for Node {
+    fn leaf(rules: Vec<Rule>) -> Self {
+        Node::Leaf { rules }
+    }
+
+    fn indexed_leaf(index: Box<BitVectorIndex>) -> Self {
+        Node::IndexedLeaf { index }
+    }
+
+    fn heap_bytes(&self) -> usize {
+        Node::heap_bytes(self)
+    }
 }
@@ -0,0 +1,230 @@
+//! IGMP messages and multicast group matching.
+//!
+//! `IgmpMessage` parses an IGMP payload into the query, report and leave
+//! messages of IGMPv1, v2 and v3 (RFC 1112, 2236 and 3376), with the group
+//! records of v3 reports.
+//!
+//! The group a message is about is usually not the IP destination: leaves go
+//! to all-routers (224.0.0.2), v3 reports to 224.0.0.22 and carry several
+//! groups. For multicast admission control, `group_keys` gives one key per
+//! group with the group address in place of the destination, so ordinary
+//! rules (see `Rule::igmp_group`) and classifiers decide which groups a host
+//! may join. The key of a v1 or v2 report is the packet's own.
+//!
+//! `snooping` learns from the same messages which zones have members of each
+//! group, to forward multicast traffic only where it is wanted.
+
+pub mod snooping;
+
+use crate::classifier::Classifier;
+use crate::packet::{
+    FiveTuple, Ipv4Bytes, PacketKey, IGMP_LEAVE_GROUP, IGMP_MEMBERSHIP_QUERY,
+    IGMP_V1_MEMBERSHIP_REPORT, IGMP_V2_MEMBERSHIP_REPORT, IGMP_V3_MEMBERSHIP_REPORT, PROTO_IGMP,
+};
+use crate::rule::Rule;
+use alloc::vec::Vec;
+
+/// Version of an IGMP query or report.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum IgmpVersion {
+    V1,
+    V2,
+    V3,
+}
+
+/// Group record types of IGMPv3 reports.
+pub const MODE_IS_INCLUDE: u8 = 1;
+pub const MODE_IS_EXCLUDE: u8 = 2;
+pub const CHANGE_TO_INCLUDE_MODE: u8 = 3;
+pub const CHANGE_TO_EXCLUDE_MODE: u8 = 4;
+pub const ALLOW_NEW_SOURCES: u8 = 5;
+pub const BLOCK_OLD_SOURCES: u8 = 6;
+
+/// Group record of an IGMPv3 report.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct GroupRecord {
+    /// Record type (`MODE_IS_INCLUDE`, ...).
+    pub record_type: u8,
+    pub group: u32,
+    pub sources: Vec<u32>,
+}
+
+impl GroupRecord {
+    /// Returns true if the host wants traffic of the group after this
+    /// record: it excludes some sources (possibly none) or includes some.
+    ///
+    /// `BLOCK_OLD_SOURCES` only narrows the sources of a membership the
+    /// record does not describe, so it counts as no change and gives false.
+    pub fn joins(&self) -> bool {
+        match self.record_type {
+            MODE_IS_EXCLUDE | CHANGE_TO_EXCLUDE_MODE => true,
+            MODE_IS_INCLUDE | CHANGE_TO_INCLUDE_MODE | ALLOW_NEW_SOURCES => {
+                !self.sources.is_empty()
+            }
+            _ => false,
+        }
+    }
+
+    /// Returns true if the host stops listening to the group: it switches
+    /// to including no source.
+    pub fn leaves(&self) -> bool {
+        matches!(self.record_type, MODE_IS_INCLUDE | CHANGE_TO_INCLUDE_MODE)
+            && self.sources.is_empty()
+    }
+}
+
+/// A parsed IGMP message.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum IgmpMessage {
+    /// Membership query; `group` is 0 for a general query. Only v3 queries
+    /// list sources.
+    Query {
+        version: IgmpVersion,
+        group: u32,
+        /// Max response time, in tenths of a second (v1 queries send 0).
+        max_resp_time: u8,
+        sources: Vec<u32>,
+    },
+    /// IGMPv1 or v2 membership report.
+    Report { version: IgmpVersion, group: u32 },
+    /// IGMPv2 leave group.
+    Leave { group: u32 },
+    /// IGMPv3 membership report.
+    ReportV3 { records: Vec<GroupRecord> },
+}
+
+impl IgmpMessage {
+    /// Parse an IGMP message (the IP payload), or `None` if it is truncated
+    /// or of an unknown type. The checksum is not verified.
+    ///
+    /// The query version follows RFC 3376: an 8-byte query is v1 when its
+    /// max response time is 0 and v2 otherwise, and a longer one is v3.
+    pub fn parse(bytes: &[u8]) -> Option<Self> {
+        let group = u32_at(bytes, 4)?;
+        let message = match bytes[0] {
+            IGMP_MEMBERSHIP_QUERY if bytes.len() >= 12 => {
+                let count = u16::from_be_bytes([bytes[10], bytes[11]]) as usize;
+                IgmpMessage::Query {
+                    version: IgmpVersion::V3,
+                    group,
+                    max_resp_time: bytes[1],
+                    sources: addresses(bytes, 12, count)?,
+                }
+            }
+            IGMP_MEMBERSHIP_QUERY => IgmpMessage::Query {
+                version: if bytes[1] == 0 {
+                    IgmpVersion::V1
+                } else {
+                    IgmpVersion::V2
+                },
+                group,
+                max_resp_time: bytes[1],
+                sources: Vec::new(),
+            },
+            IGMP_V1_MEMBERSHIP_REPORT => IgmpMessage::Report {
+                version: IgmpVersion::V1,
+                group,
+            },
+            IGMP_V2_MEMBERSHIP_REPORT => IgmpMessage::Report {
+                version: IgmpVersion::V2,
+                group,
+            },
+            IGMP_LEAVE_GROUP => IgmpMessage::Leave { group },
+            IGMP_V3_MEMBERSHIP_REPORT => {
+                let count = u16::from_be_bytes([bytes[6], bytes[7]]);
+                let mut records = Vec::with_capacity(count as usize);
+                let mut at = 8;
+                for _ in 0..count {
+                    let header = bytes.get(at..at + 8)?;
+                    let aux_len = header[1] as usize * 4;
+                    let sources = u16::from_be_bytes([header[2], header[3]]) as usize;
+                    records.push(GroupRecord {
+                        record_type: header[0],
+                        group: u32_at(bytes, at + 4)?,
+                        sources: addresses(bytes, at + 8, sources)?,
+                    });
+                    at += 8 + sources * 4 + aux_len;
+                }
+                IgmpMessage::ReportV3 { records }
+            }
+            _ => return None,
+        };
+        Some(message)
+    }
+
+    /// Parse the IGMP message of an unfragmented IPv4 packet, or `None` if
+    /// the packet is not one or its message does not parse.
+    pub fn from_ipv4(packet: &Ipv4Bytes) -> Option<Self> {
+        if packet.proto() != PROTO_IGMP || packet.is_fragment() {
+            return None;
+        }
+        Self::parse(packet.payload())
+    }
+
+    /// IGMP type of the message.
+    pub fn igmp_type(&self) -> u8 {
+        match self {
+            IgmpMessage::Query { .. } => IGMP_MEMBERSHIP_QUERY,
+            IgmpMessage::Report {
+                version: IgmpVersion::V1,
+                ..
+            } => IGMP_V1_MEMBERSHIP_REPORT,
+            IgmpMessage::Report { .. } => IGMP_V2_MEMBERSHIP_REPORT,
+            IgmpMessage::Leave { .. } => IGMP_LEAVE_GROUP,
+            IgmpMessage::ReportV3 { .. } => IGMP_V3_MEMBERSHIP_REPORT,
+        }
+    }
+
+    /// Groups the message is about, in message order (none for a general
+    /// query).
+    pub fn groups(&self) -> Vec<u32> {
+        match self {
+            IgmpMessage::Query { group: 0, .. } => Vec::new(),
+            IgmpMessage::Query { group, .. }
+            | IgmpMessage::Report { group, .. }
+            | IgmpMessage::Leave { group } => alloc::vec![*group],
+            IgmpMessage::ReportV3 { records } => records.iter().map(|r| r.group).collect(),
+        }
+    }
+
+    /// Keys to classify for each group of the message, received as `packet`:
+    /// the packet's key with the group as destination address and, for a
+    /// v3 report, the record type as destination port.
+    pub fn group_keys(&self, packet: &FiveTuple) -> Vec<(u32, FiveTuple)> {
+        let key = |group: u32, record_type: u8| FiveTuple {
+            dst_ip: group,
+            src_port: self.igmp_type() as u16,
+            dst_port: record_type as u16,
+            ..*packet
+        };
+        match self {
+            IgmpMessage::ReportV3 { records } => records
+                .iter()
+                .map(|r| (r.group, key(r.group, r.record_type)))
+                .collect(),
+            _ => self.groups().into_iter().map(|g| (g, key(g, 0))).collect(),
+        }
+    }
+
+    /// The best rule of `classifier` for each group of the message (see
+    /// `group_keys`), in message order.
+    pub fn classify_groups<'a, C: Classifier>(
+        &self,
+        classifier: &'a C,
+        packet: &FiveTuple,
+    ) -> Vec<(u32, Option<&'a Rule>)> {
+        self.group_keys(packet)
+            .into_iter()
+            .map(|(group, key)| (group, classifier.classify_rule(&key)))
+            .collect()
+    }
+}
+
+fn u32_at(bytes: &[u8], at: usize) -> Option<u32> {
+    let b = bytes.get(at..at + 4)?;
+    Some(u32::from_be_bytes([b[0], b[1], b[2], b[3]]))
+}
+
+fn addresses(bytes: &[u8], at: usize, count: usize) -> Option<Vec<u32>> {
+    (0..count).map(|i| u32_at(bytes, at + i * 4)).collect()
+}
@@ -0,0 +1,271 @@
+//! IGMP snooping: multicast group membership learned from IGMP messages.
+//!
+//! A `MembershipTable` records which zones (ingress ports, interfaces) have
+//! members of each group, from the reports and leaves seen there. Entries
+//! age out unless refreshed: hosts answer the router's periodic queries,
+//! and `expire` drops the memberships not reported within the timeout
+//! (`GROUP_MEMBERSHIP_INTERVAL` by default). Time is whatever the caller
+//! counts in (seconds, ticks, ...), as long as `timeout` and `now` agree.
+//!
+//! `MembershipFront` puts the table in front of a classifier: traffic to a
+//! group no zone has members of matches a rule of its own (typically a
+//! drop), everything else goes to the classifier. Following RFC 4541,
+//! 224.0.0.0/24 (routing protocols, IGMP itself) is never filtered.
+
+use crate::classifier::{Classifier, LookupStats};
+use crate::dimension::Dimension;
+use crate::geometry::Region;
+use crate::igmp::IgmpMessage;
+use crate::packet::{FiveTuple, Ipv4Bytes};
+use crate::query::{Overlapping, RegionQuery};
+use crate::rule::{Action, Range, Rule};
+use alloc::vec::Vec;
+use hashbrown::HashMap;
+
+/// Default membership timeout, in seconds (RFC 2236 and 3376).
+pub const GROUP_MEMBERSHIP_INTERVAL: u64 = 260;
+
+/// Multicast addresses, 224.0.0.0/4.
+pub const MULTICAST: Range<u32> = Range {
+    min: 0xe000_0000,
+    max: 0xefff_ffff,
+};
+
+/// Local network control block, 224.0.0.0/24: flooded, never snooped.
+pub const LOCAL_CONTROL: Range<u32> = Range {
+    min: 0xe000_0000,
+    max: 0xe000_00ff,
+};
+
+/// Returns true if traffic to `addr` is forwarded by group membership:
+/// multicast, outside the local control block.
+pub fn is_snooped(addr: u32) -> bool {
+    MULTICAST.contains(addr) && !LOCAL_CONTROL.contains(addr)
+}
+
+/// Group memberships per zone, with their expiry times.
+#[derive(Debug, Clone)]
+pub struct MembershipTable {
+    /// Zones with members of each group, and when each membership expires.
+    groups: HashMap<u32, HashMap<u32, u64>>,
+    timeout: u64,
+}
+
+impl MembershipTable {
+    /// Empty table whose memberships last `timeout` after being reported.
+    pub fn new(timeout: u64) -> Self {
+        Self {
+            groups: HashMap::new(),
+            timeout,
+        }
+    }
+
+    /// Record that `zone` has members of `group` as of `now`.
+    pub fn join(&mut self, group: u32, zone: u32, now: u64) {
+        let expires = now.saturating_add(self.timeout);
+        self.groups.entry(group).or_default().insert(zone, expires);
+    }
+
+    /// Record that `zone` has no members of `group` left, returning true if
+    /// it had some.
+    ///
+    /// Leaves take effect at once (fast leave), which assumes one host per
+    /// zone; with several, a querier would ask whether others remain.
+    pub fn leave(&mut self, group: u32, zone: u32) -> bool {
+        let Some(zones) = self.groups.get_mut(&group) else {
+            return false;
+        };
+        let removed = zones.remove(&zone).is_some();
+        if zones.is_empty() {
+            self.groups.remove(&group);
+        }
+        removed
+    }
+
+    /// Update the table from an IGMP message received in `zone` at `now`.
+    /// Queries change nothing.
+    pub fn learn(&mut self, message: &IgmpMessage, zone: u32, now: u64) {
+        match message {
+            IgmpMessage::Query { .. } => {}
+            IgmpMessage::Report { group, .. } => self.join(*group, zone, now),
+            IgmpMessage::Leave { group } => {
+                self.leave(*group, zone);
+            }
+            IgmpMessage::ReportV3 { records } => {
+                for record in records {
+                    if record.joins() {
+                        self.join(record.group, zone, now);
+                    } else if record.leaves() {
+                        self.leave(record.group, zone);
+                    }
+                }
+            }
+        }
+    }
+
+    /// Learn from `packet` if it carries an IGMP message (see `learn`),
+    /// returning the message.
+    pub fn observe(&mut self, packet: &Ipv4Bytes, zone: u32, now: u64) -> Option<IgmpMessage> {
+        let message = IgmpMessage::from_ipv4(packet)?;
+        self.learn(&message, zone, now);
+        Some(message)
+    }
+
+    /// Drop the memberships not refreshed since `now - timeout`, returning
+    /// how many were dropped.
+    pub fn expire(&mut self, now: u64) -> usize {
+        let mut dropped = 0;
+        self.groups.retain(|_, zones| {
+            let before = zones.len();
+            zones.retain(|_, &mut expires| expires > now);
+            dropped += before - zones.len();
+            !zones.is_empty()
+        });
+        dropped
+    }
+
+    /// Returns true if some zone has members of `group`.
+    pub fn has_members(&self, group: u32) -> bool {
+        self.groups.contains_key(&group)
+    }
+
+    /// Returns true if `zone` has members of `group`.
+    pub fn is_member(&self, group: u32, zone: u32) -> bool {
+        self.groups
+            .get(&group)
+            .is_some_and(|zones| zones.contains_key(&zone))
+    }
+
+    /// Zones with members of `group`, ascending.
+    pub fn member_zones(&self, group: u32) -> Vec<u32> {
+        let mut zones: Vec<u32> = self
+            .groups
+            .get(&group)
+            .map(|zones| zones.keys().copied().collect())
+            .unwrap_or_default();
+        zones.sort_unstable();
+        zones
+    }
+
+    /// Groups with members somewhere, ascending.
+    pub fn groups(&self) -> Vec<u32> {
+        let mut groups: Vec<u32> = self.groups.keys().copied().collect();
+        groups.sort_unstable();
+        groups
+    }
+
+    /// Returns true if `packet` goes to a snooped group some zone has
+    /// members of: the "destination group has local members" predicate.
+    /// Packets to other destinations give false.
+    pub fn has_local_members(&self, packet: &FiveTuple) -> bool {
+        is_snooped(packet.dst_ip) && self.has_members(packet.dst_ip)
+    }
+
+    /// Returns true if the table decides `packet`: it goes to a snooped
+    /// group no zone has members of.
+    pub fn filters(&self, packet: &FiveTuple) -> bool {
+        is_snooped(packet.dst_ip) && !self.has_members(packet.dst_ip)
+    }
+
+    /// How long memberships last.
+    pub fn timeout(&self) -> u64 {
+        self.timeout
+    }
+}
+
+/// Classifier checking group membership before a main classifier.
+///
+/// Packets to a snooped group without members match the front's rule, ahead
+/// of every rule of the main classifier; the others are classified by it.
+/// The table is updated through `table_mut`, typically from the IGMP
+/// packets the data path sees and a periodic `expire`.
+pub struct MembershipFront<C> {
+    table: MembershipTable,
+    main: C,
+    /// Rule standing for the table, returned for packets it filters.
+    rule: Rule,
+}
+
+impl<C: Classifier> MembershipFront<C> {
+    /// Front `main` with `table`. Packets the table filters match a rule
+    /// with `id` and `action`, covering the multicast addresses.
+    pub fn new(table: MembershipTable, main: C, id: u32, action: Action) -> Self {
+        Self {
+            table,
+            main,
+            rule: Rule {
+                id,
+                dst_ip: MULTICAST,
+                ..Rule::wildcard(action)
+            },
+        }
+    }
+
+    /// The membership table.
+    pub fn table(&self) -> &MembershipTable {
+        &self.table
+    }
+
+    /// The membership table, for learning and expiry.
+    pub fn table_mut(&mut self) -> &mut MembershipTable {
+        &mut self.table
+    }
+
+    /// The classifier consulted for packets the table does not filter.
+    pub fn main(&self) -> &C {
+        &self.main
+    }
+
+    /// Rule reported for packets the table filters.
+    pub fn rule(&self) -> &Rule {
+        &self.rule
+    }
+}
+
+impl<C: Classifier> Classifier for MembershipFront<C> {
+    /// Build the main classifier behind an empty table (timing out after
+    /// `GROUP_MEMBERSHIP_INTERVAL`) that denies traffic to snooped groups
+    /// until members join.
+    fn build(rules: &[Rule]) -> Self {
+        let table = MembershipTable::new(GROUP_MEMBERSHIP_INTERVAL);
+        Self::new(table, C::build(rules), 0, Action::Deny)
+    }
+
+    fn classify_rule(&self, packet: &FiveTuple) -> Option<&Rule> {
+        if self.table.filters(packet) {
+            Some(&self.rule)
+        } else {
+            self.main.classify_rule(packet)
+        }
+    }
+
+    fn classify_with_stats(&self, packet: &FiveTuple) -> (Option<Action>, LookupStats) {
+        if self.table.filters(packet) {
+            let stats = LookupStats {
+                tables_probed: 1,
+                ..LookupStats::default()
+            };
+            return (Some(self.rule.action), stats);
+        }
+        let (action, mut stats) = self.main.classify_with_stats(packet);
+        stats.tables_probed += 1;
+        (action, stats)
+    }
+}
+
+impl<C: RegionQuery> RegionQuery for MembershipFront<C> {
+    /// The table is reported through its rule when the region reaches a
+    /// snooped address.
+    fn rules_overlapping(&self, region: &Region) -> Vec<&Rule> {
+        let mut found = Overlapping::new(region);
+        let dst = region.get(Dimension::DstIp);
+        let snooped = dst.min <= MULTICAST.max
+            && dst.max >= MULTICAST.min
+            && !(LOCAL_CONTROL.min <= dst.min && dst.max <= LOCAL_CONTROL.max);
+        if snooped {
+            found.extend(core::iter::once(&self.rule));
+        }
+        found.extend(self.main.rules_overlapping(region));
+        found.finish()
+    }
+}
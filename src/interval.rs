@@ -0,0 +1,120 @@
+//! Elementary-interval decomposition of a field.
+//!
+//! The endpoints of all rule ranges on one field split its domain into
+//! elementary intervals: maximal ranges in which every value is covered by
+//! exactly the same set of rules. This is the building block behind
+//! HyperSplit's endpoint-based splits, bit-vector search and RFC-style
+//! cross-producting, so it is exposed once here instead of being reimplemented
+//! by each algorithm. Point queries are a binary search over the sorted
+//! intervals, equivalent to a static segment-tree stabbing query.
+
+use crate::dimension::Dimension;
+use crate::rule::{Range, Rule};
+use alloc::vec::Vec;
+
+/// A maximal range of field values covered by the same set of rules.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ElementaryInterval {
+    /// The values spanned by the interval (inclusive).
+    pub range: Range<u32>,
+    /// Indices (into the input rule slice) of the rules covering the interval, ascending.
+    pub rules: Vec<u32>,
+}
+
+/// Elementary intervals of one dimension, covering its whole domain.
+#[derive(Debug, Clone)]
+pub struct ElementaryIntervals {
+    dimension: Dimension,
+    intervals: Vec<ElementaryInterval>,
+}
+
+/// Sorted, deduplicated interval start points for `dim` (always includes 0).
+pub fn endpoints(rules: &[Rule], dim: Dimension) -> Vec<u32> {
+    let mut points = Vec::with_capacity(rules.len() * 2 + 1);
+    points.push(0);
+    for rule in rules {
+        let range = dim.rule_range(rule);
+        points.push(range.min);
+        if range.max < dim.max_value() {
+            points.push(range.max + 1);
+        }
+    }
+    points.sort_unstable();
+    points.dedup();
+    points
+}
+
+impl ElementaryIntervals {
+    /// Decompose `dim` according to `rules`.
+    pub fn build(rules: &[Rule], dim: Dimension) -> Self {
+        let starts = endpoints(rules, dim);
+        let mut intervals: Vec<ElementaryInterval> = starts
+            .iter()
+            .enumerate()
+            .map(|(i, &start)| {
+                let end = starts.get(i + 1).map_or(dim.max_value(), |&next| next - 1);
+                ElementaryInterval {
+                    range: Range::new(start, end),
+                    rules: Vec::new(),
+                }
+            })
+            .collect();
+
+        for (idx, rule) in rules.iter().enumerate() {
+            let range = dim.rule_range(rule);
+            let first = starts.partition_point(|&s| s < range.min);
+            for interval in intervals[first..].iter_mut() {
+                if interval.range.min > range.max {
+                    break;
+                }
+                interval.rules.push(idx as u32);
+            }
+        }
+
+        Self {
+            dimension: dim,
+            intervals,
+        }
+    }
+
+    /// The decomposed dimension.
+    pub fn dimension(&self) -> Dimension {
+        self.dimension
+    }
+
+    /// All intervals in ascending order.
+    pub fn intervals(&self) -> &[ElementaryInterval] {
+        &self.intervals
+    }
+
+    /// Number of intervals.
+    pub fn len(&self) -> usize {
+        self.intervals.len()
+    }
+
+    /// Returns true if there are no intervals (never the case for a built decomposition).
+    pub fn is_empty(&self) -> bool {
+        self.intervals.is_empty()
+    }
+
+    /// Index of the interval containing `value`.
+    pub fn locate_index(&self, value: u32) -> usize {
+        self.intervals
+            .partition_point(|iv| iv.range.min <= value)
+            .saturating_sub(1)
+    }
+
+    /// The interval containing `value`.
+    pub fn locate(&self, value: u32) -> &ElementaryInterval {
+        &self.intervals[self.locate_index(value)]
+    }
+
+    /// Largest number of rules covering a single interval.
+    pub fn max_overlap(&self) -> usize {
+        self.intervals
+            .iter()
+            .map(|iv| iv.rules.len())
+            .max()
+            .unwrap_or(0)
+    }
+}
@@ -0,0 +1,165 @@
+//! IPv6 classification.
+//!
+//! The classifiers cut and mask on `u32` fields. `V6Classifier` runs any of
+//! them on IPv6 rules by compressing the 128-bit addresses first: the start
+//! and end of every rule's address ranges split the address space into at
+//! most `4n + 1` classes, and each address is replaced by the index of its
+//! class. Class indices keep the address order and every rule range covers
+//! whole classes, so a rule matches an address exactly when it matches its
+//! class: the projection is exact, and CutSplit, HiCuts, HyperSplit, TSS and
+//! PartitionSort build and search over the class indices unchanged.
+//!
+//! A lookup costs two binary searches over the class starts (one per
+//! address) before the inner lookup. Both addresses share one class table so
+//! bidirectional rules, which compare a packet's source against a rule's
+//! destination, stay exact.
+
+use crate::classifier::{Classifier, LookupStats, Verdict};
+use crate::packet::{FiveTuple, SixTuple};
+use crate::rule::{Action, Range, Rule, RuleV6};
+use alloc::vec::Vec;
+
+/// Range of the addresses in `addr/len` (`len` at most 128).
+pub fn prefix_range(addr: u128, len: u32) -> Range<u128> {
+    let host = u128::MAX.checked_shr(len).unwrap_or(0);
+    Range::new(addr & !host, addr | host)
+}
+
+/// Sorted starts of the address classes (the first is 0).
+#[derive(Debug, Clone)]
+struct AddressClasses {
+    starts: Vec<u128>,
+}
+
+impl AddressClasses {
+    fn new(rules: &[RuleV6]) -> Self {
+        let mut starts = Vec::with_capacity(rules.len() * 4 + 1);
+        starts.push(0);
+        for rule in rules {
+            for range in [rule.src_ip, rule.dst_ip] {
+                starts.push(range.min);
+                starts.extend(range.max.checked_add(1));
+            }
+        }
+        starts.sort_unstable();
+        starts.dedup();
+        Self { starts }
+    }
+
+    /// Class of `addr`.
+    fn class(&self, addr: u128) -> u32 {
+        (self.starts.partition_point(|&s| s <= addr) - 1) as u32
+    }
+
+    /// Classes covered by `range`. The last class stands for the rest of the
+    /// `u32` domain, so full ranges stay wildcards after projection.
+    fn project(&self, range: Range<u128>) -> Range<u32> {
+        let max = self.class(range.max);
+        let max = if max as usize == self.starts.len() - 1 {
+            u32::MAX
+        } else {
+            max
+        };
+        Range::new(self.class(range.min), max)
+    }
+}
+
+/// IPv6 classifier running an IPv4 algorithm over compressed addresses.
+#[derive(Clone)]
+pub struct V6Classifier<C> {
+    classes: AddressClasses,
+    rules: Vec<RuleV6>,
+    inner: C,
+}
+
+impl<C: Classifier> V6Classifier<C> {
+    /// Build over `rules` with `C`'s default configuration.
+    pub fn build(rules: &[RuleV6]) -> Self {
+        Self::build_with(rules, C::build)
+    }
+
+    /// Build over `rules`, building the inner classifier over the projected
+    /// rules with `build`, e.g. to use a configured tree builder.
+    ///
+    /// Projected rules have the priority of their IPv6 rule and its index in
+    /// `rules` as id.
+    pub fn build_with(rules: &[RuleV6], build: impl Fn(&[Rule]) -> C) -> Self {
+        let classes = AddressClasses::new(rules);
+        let projected: Vec<Rule> = rules
+            .iter()
+            .enumerate()
+            .map(|(i, rule)| Rule {
+                id: i as u32,
+                priority: rule.priority,
+                src_ip: classes.project(rule.src_ip),
+                dst_ip: classes.project(rule.dst_ip),
+                src_port: rule.src_port,
+                dst_port: rule.dst_port,
+                proto: rule.proto,
+                zone: rule.zone,
+                bidirectional: rule.bidirectional,
+                field_sets: None,
+                action: rule.action,
+            })
+            .collect();
+        Self {
+            inner: build(&projected),
+            classes,
+            rules: rules.to_vec(),
+        }
+    }
+
+    /// The rules, in build order.
+    pub fn rules(&self) -> &[RuleV6] {
+        &self.rules
+    }
+
+    /// The classifier over the projected rules.
+    pub fn inner(&self) -> &C {
+        &self.inner
+    }
+
+    /// Number of address classes.
+    pub fn address_classes(&self) -> usize {
+        self.classes.starts.len()
+    }
+
+    /// The tuple the inner classifier sees for `packet`.
+    pub fn project(&self, packet: &SixTuple) -> FiveTuple {
+        FiveTuple {
+            src_ip: self.classes.class(packet.src_ip),
+            dst_ip: self.classes.class(packet.dst_ip),
+            src_port: packet.src_port,
+            dst_port: packet.dst_port,
+            proto: packet.proto,
+            zone: packet.zone,
+        }
+    }
+
+    /// Highest-priority rule matching `packet`, if any.
+    pub fn classify_rule(&self, packet: &SixTuple) -> Option<&RuleV6> {
+        let rule = self.inner.classify_rule(&self.project(packet))?;
+        Some(&self.rules[rule.id as usize])
+    }
+
+    /// Action of the matching rule, if any.
+    pub fn classify(&self, packet: &SixTuple) -> Option<Action> {
+        self.classify_rule(packet).map(|r| r.action)
+    }
+
+    /// Verdict for `packet`, with the id of the matching IPv6 rule.
+    pub fn classify_verdict(&self, packet: &SixTuple) -> Verdict {
+        match self.classify_rule(packet) {
+            Some(rule) => Verdict::Matched {
+                action: rule.action,
+                rule_id: rule.id,
+            },
+            None => Verdict::NoMatch,
+        }
+    }
+
+    /// Classify `packet` and report the work of the inner lookup.
+    pub fn classify_with_stats(&self, packet: &SixTuple) -> (Option<Action>, LookupStats) {
+        self.inner.classify_with_stats(&self.project(packet))
+    }
+}
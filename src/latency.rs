@@ -0,0 +1,251 @@
+//! Latency histogram instrumentation for classification.
+//!
+//! `Histogram` is a fixed-size, allocation-free log-linear histogram (in the
+//! spirit of HdrHistogram): values below 16 get exact buckets, and every
+//! power-of-two range above is split into 16 sub-buckets, giving ~6% relative
+//! precision over the full `u64` range. `Timed` wraps any classifier and
+//! records one sample per lookup using a user-supplied `Clock`, so p99/p999
+//! latency can be reported on targets without an OS (e.g. from a cycle counter).
+
+use crate::classifier::Classifier;
+use crate::packet::FiveTuple;
+use crate::rule::Action;
+
+const SUB_BITS: u32 = 4;
+const SUB_BUCKETS: usize = 1 << SUB_BITS;
+const BUCKETS: usize = SUB_BUCKETS + (64 - SUB_BITS as usize) * SUB_BUCKETS;
+
+/// Fixed-bucket log-linear histogram of `u64` samples.
+#[derive(Clone)]
+pub struct Histogram {
+    counts: [u64; BUCKETS],
+    total: u64,
+    sum: u128,
+    min: u64,
+    max: u64,
+}
+
+impl Default for Histogram {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl core::fmt::Debug for Histogram {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.debug_struct("Histogram")
+            .field("count", &self.total)
+            .field("min", &self.min())
+            .field("max", &self.max())
+            .field("p50", &self.value_at_quantile(0.5))
+            .field("p99", &self.value_at_quantile(0.99))
+            .finish()
+    }
+}
+
+impl Histogram {
+    /// Create an empty histogram.
+    pub const fn new() -> Self {
+        Self {
+            counts: [0; BUCKETS],
+            total: 0,
+            sum: 0,
+            min: u64::MAX,
+            max: 0,
+        }
+    }
+
+    /// Record a single sample.
+    pub fn record(&mut self, value: u64) {
+        self.counts[Self::index_of(value)] += 1;
+        self.total += 1;
+        self.sum += value as u128;
+        self.min = self.min.min(value);
+        self.max = self.max.max(value);
+    }
+
+    /// Number of recorded samples.
+    pub fn count(&self) -> u64 {
+        self.total
+    }
+
+    /// Smallest recorded sample (0 if empty).
+    pub fn min(&self) -> u64 {
+        if self.total == 0 {
+            0
+        } else {
+            self.min
+        }
+    }
+
+    /// Largest recorded sample.
+    pub fn max(&self) -> u64 {
+        self.max
+    }
+
+    /// Mean of all samples (exact, not bucketed).
+    pub fn mean(&self) -> f64 {
+        if self.total == 0 {
+            0.0
+        } else {
+            self.sum as f64 / self.total as f64
+        }
+    }
+
+    /// Value below which a fraction `q` (0.0..=1.0) of the samples fall.
+    ///
+    /// Returns the upper bound of the bucket holding the quantile, clamped to
+    /// the observed maximum, so results never under-report latency.
+    pub fn value_at_quantile(&self, q: f64) -> u64 {
+        if self.total == 0 {
+            return 0;
+        }
+        let q = q.clamp(0.0, 1.0);
+        let mut rank = (q * self.total as f64) as u64;
+        if rank == 0 {
+            rank = 1;
+        }
+
+        let mut seen = 0;
+        for (idx, &count) in self.counts.iter().enumerate() {
+            seen += count;
+            if seen >= rank {
+                return Self::upper_bound(idx).min(self.max);
+            }
+        }
+        self.max
+    }
+
+    /// Add all samples from `other` into `self`.
+    pub fn merge(&mut self, other: &Histogram) {
+        for (a, b) in self.counts.iter_mut().zip(other.counts.iter()) {
+            *a += b;
+        }
+        self.total += other.total;
+        self.sum += other.sum;
+        self.min = self.min.min(other.min);
+        self.max = self.max.max(other.max);
+    }
+
+    /// Remove all samples.
+    pub fn reset(&mut self) {
+        *self = Self::new();
+    }
+
+    /// Iterate over non-empty buckets as `(lower, upper, count)`.
+    pub fn buckets(&self) -> impl Iterator<Item = (u64, u64, u64)> + '_ {
+        self.counts
+            .iter()
+            .enumerate()
+            .filter(|(_, &c)| c > 0)
+            .map(|(idx, &c)| (Self::lower_bound(idx), Self::upper_bound(idx), c))
+    }
+
+    fn index_of(value: u64) -> usize {
+        if value < SUB_BUCKETS as u64 {
+            return value as usize;
+        }
+        let msb = 63 - value.leading_zeros();
+        let shift = msb - SUB_BITS;
+        let sub = ((value >> shift) as usize) - SUB_BUCKETS;
+        SUB_BUCKETS + shift as usize * SUB_BUCKETS + sub
+    }
+
+    fn lower_bound(idx: usize) -> u64 {
+        if idx < SUB_BUCKETS {
+            return idx as u64;
+        }
+        let shift = (idx - SUB_BUCKETS) / SUB_BUCKETS;
+        let sub = (idx - SUB_BUCKETS) % SUB_BUCKETS;
+        ((SUB_BUCKETS + sub) as u64) << shift
+    }
+
+    fn upper_bound(idx: usize) -> u64 {
+        if idx < SUB_BUCKETS {
+            return idx as u64;
+        }
+        let shift = (idx - SUB_BUCKETS) / SUB_BUCKETS;
+        Self::lower_bound(idx) + ((1u64 << shift) - 1)
+    }
+}
+
+/// A monotonic time or cycle source.
+pub trait Clock {
+    /// Current reading, in arbitrary but consistent units (ns, cycles, ticks).
+    fn now(&self) -> u64;
+}
+
+impl<F: Fn() -> u64> Clock for F {
+    fn now(&self) -> u64 {
+        self()
+    }
+}
+
+/// Nanosecond clock based on `std::time::Instant`.
+#[cfg(feature = "std")]
+pub struct StdClock {
+    origin: std::time::Instant,
+}
+
+#[cfg(feature = "std")]
+impl Default for StdClock {
+    fn default() -> Self {
+        Self {
+            origin: std::time::Instant::now(),
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl Clock for StdClock {
+    fn now(&self) -> u64 {
+        self.origin.elapsed().as_nanos() as u64
+    }
+}
+
+/// Classifier wrapper recording the latency of every lookup.
+pub struct Timed<C, K> {
+    inner: C,
+    clock: K,
+    histogram: Histogram,
+}
+
+impl<C: Classifier, K: Clock> Timed<C, K> {
+    /// Wrap `inner`, timing lookups with `clock`.
+    pub fn new(inner: C, clock: K) -> Self {
+        Self {
+            inner,
+            clock,
+            histogram: Histogram::new(),
+        }
+    }
+
+    /// Classify a packet and record how long it took.
+    pub fn classify(&mut self, packet: &FiveTuple) -> Option<Action> {
+        let start = self.clock.now();
+        let verdict = self.inner.classify(packet);
+        let end = self.clock.now();
+        self.histogram.record(end.saturating_sub(start));
+        verdict
+    }
+
+    /// Latency histogram collected so far.
+    pub fn histogram(&self) -> &Histogram {
+        &self.histogram
+    }
+
+    /// Clear collected samples.
+    pub fn reset(&mut self) {
+        self.histogram.reset();
+    }
+
+    /// The wrapped classifier.
+    pub fn inner(&self) -> &C {
+        &self.inner
+    }
+
+    /// Unwrap, returning the classifier and the collected histogram.
+    pub fn into_parts(self) -> (C, Histogram) {
+        (self.inner, self.histogram)
+    }
+}
@@ -0,0 +1,179 @@
+//! Leaf handling shared by the decision-tree builders.
+//!
+//! A `LeafPolicy` decides when a builder stops cutting and emits a leaf.
+//! With a single constant threshold, shallow nodes may stop too late (deep,
+//! exploding trees near `max_depth`) or too early (huge leaves); the adaptive
+//! policy lets the threshold grow with depth and with the rule duplication
+//! caused by the last cut.
+//!
+//! Leaves that remain oversized anyway (typically at `max_depth` in heavily
+//! wildcarded regions) can be backed by a `BitVectorIndex` instead of a plain
+//! list, bounding worst-case lookup cost.
+
+use crate::classifier::LookupStats;
+use crate::dimension::Dimension;
+use crate::packet::FiveTuple;
+use crate::rule::{expand_rules, Rule};
+use alloc::vec::Vec;
+
+/// Policy deciding the leaf threshold at a given node.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum LeafPolicy {
+    /// Stop when the node holds at most the builder's `leaf_threshold` rules.
+    #[default]
+    Fixed,
+    /// Threshold grows with depth and with duplication pressure.
+    Adaptive {
+        /// Extra rules tolerated per level of depth.
+        per_depth: usize,
+        /// Multiplier applied to the duplication ratio of the parent cut
+        /// (sum of child rule counts / parent rule count, 1.0 = no duplication).
+        /// A ratio of 2.0 with a factor of 1.0 doubles the threshold.
+        pressure_factor: f32,
+    },
+}
+
+impl LeafPolicy {
+    /// Adaptive policy with moderate defaults (+1 rule per level, linear pressure).
+    pub fn adaptive() -> Self {
+        LeafPolicy::Adaptive {
+            per_depth: 1,
+            pressure_factor: 1.0,
+        }
+    }
+
+    /// Leaf threshold for a node at `depth` whose parent cut duplicated rules by `pressure`.
+    pub fn threshold(&self, base: usize, depth: usize, pressure: f32) -> usize {
+        match *self {
+            LeafPolicy::Fixed => base,
+            LeafPolicy::Adaptive {
+                per_depth,
+                pressure_factor,
+            } => {
+                let grown = base + per_depth * depth;
+                let excess = (pressure - 1.0).max(0.0) * pressure_factor;
+                grown + (grown as f32 * excess) as usize
+            }
+        }
+    }
+}
+
+/// Bit-vector index used as a secondary structure for oversized leaves.
+///
+/// Implements the Lakshman–Stiliadis scheme: for each field, the rule
+/// endpoints split the value space into elementary intervals, and each
+/// interval stores a bitmap of the rules covering it (rules are sorted by
+/// priority, so bit `i` is the `i`-th best rule). A lookup does one binary
+/// search per field and ANDs five bitmaps; the first set bit is the winner.
+/// Worst-case cost is bounded by `5 * log2(2n)` comparisons plus `5 * n / 64`
+/// word operations, instead of `n` full rule matches.
+#[derive(Debug, Clone)]
+pub struct BitVectorIndex {
+    rules: Vec<Rule>,
+    words: usize,
+    fields: [FieldBitmaps; Dimension::COUNT],
+}
+
+/// Elementary intervals of one field with their rule bitmaps.
+#[derive(Debug, Clone)]
+struct FieldBitmaps {
+    /// Sorted start points of the elementary intervals (first is always 0).
+    starts: Vec<u32>,
+    /// `starts.len() * words` bitmap words, interval-major.
+    bits: Vec<u64>,
+}
+
+impl BitVectorIndex {
+    /// Build the index over `rules`.
+    pub fn build(rules: &[Rule]) -> Self {
+        let mut sorted = expand_rules(rules).into_owned();
+        sorted.sort_by_key(|r| r.priority);
+        let words = sorted.len().div_ceil(64).max(1);
+
+        let fields = Dimension::ALL.map(|dim| {
+            let mut starts = Vec::with_capacity(sorted.len() * 2 + 1);
+            starts.push(0);
+            for rule in &sorted {
+                let range = dim.rule_range(rule);
+                starts.push(range.min);
+                if let Some(end) = range.max.checked_add(1) {
+                    starts.push(end);
+                }
+            }
+            starts.sort_unstable();
+            starts.dedup();
+
+            let mut bits = alloc::vec![0u64; starts.len() * words];
+            for (i, rule) in sorted.iter().enumerate() {
+                let range = dim.rule_range(rule);
+                // Intervals never straddle an endpoint, so checking the start is enough.
+                let first = starts.partition_point(|&s| s < range.min);
+                for (j, &start) in starts.iter().enumerate().skip(first) {
+                    if start > range.max {
+                        break;
+                    }
+                    bits[j * words + i / 64] |= 1u64 << (i % 64);
+                }
+            }
+
+            FieldBitmaps { starts, bits }
+        });
+
+        Self {
+            rules: sorted,
+            words,
+            fields,
+        }
+    }
+
+    /// Release spare capacity.
+    pub fn shrink_to_fit(&mut self) {
+        self.rules.shrink_to_fit();
+        for field in &mut self.fields {
+            field.starts.shrink_to_fit();
+            field.bits.shrink_to_fit();
+        }
+    }
+
+    /// Heap bytes held by the index.
+    pub fn heap_bytes(&self) -> usize {
+        self.rules.capacity() * size_of::<Rule>()
+            + self
+                .fields
+                .iter()
+                .map(|f| f.starts.capacity() * 4 + f.bits.capacity() * 8)
+                .sum::<usize>()
+    }
+
+    /// Rules held by the index, in priority order.
+    pub fn rules(&self) -> &[Rule] {
+        &self.rules
+    }
+
+    /// Find the highest-priority rule matching the packet.
+    pub fn lookup(&self, packet: &FiveTuple, stats: &mut LookupStats) -> Option<&Rule> {
+        let mut offsets = [0usize; Dimension::COUNT];
+        for (k, (field, dim)) in self.fields.iter().zip(Dimension::ALL).enumerate() {
+            let val = dim.packet_value(packet);
+            let interval = field.starts.partition_point(|&s| s <= val) - 1;
+            offsets[k] = interval * self.words;
+        }
+
+        for w in 0..self.words {
+            let mut word = !0u64;
+            for (field, offset) in self.fields.iter().zip(offsets) {
+                word &= field.bits[offset + w];
+            }
+            // The zone is not indexed, so check it on each candidate.
+            while word != 0 {
+                stats.rules_compared += 1;
+                let rule = &self.rules[w * 64 + word.trailing_zeros() as usize];
+                if rule.zone.contains(packet.zone) {
+                    return Some(rule);
+                }
+                word &= word - 1;
+            }
+        }
+        None
+    }
+}
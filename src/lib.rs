@@ -3,17 +3,69 @@
 
 extern crate alloc;
 
+pub mod accel;
+pub mod addrset;
+pub mod approx;
+mod batch;
+pub mod classbench;
 pub mod classifier;
+pub mod compact;
+pub mod cost;
+pub mod counters;
+#[cfg(feature = "std")]
+pub mod csv;
 pub mod cutsplit;
+pub mod dimension;
+pub mod dtree;
+pub mod efficuts;
+#[cfg(feature = "std")]
+pub mod eval;
+#[cfg(feature = "ffi")]
+pub mod ffi;
+pub mod fingerprint;
+pub mod freeze;
+pub mod geometry;
+pub mod groups;
+pub mod heuristic;
 pub mod hicuts;
 pub mod hypersplit;
+pub mod igmp;
+pub mod interval;
+pub mod ipv6;
+pub mod latency;
+pub mod leaf;
 pub mod linear;
+pub mod memory;
+pub mod multitable;
+pub mod normalize;
+pub mod optimize;
 pub mod packet;
+#[cfg(feature = "rayon")]
+pub mod parallel;
 pub mod partitionsort;
+#[cfg(feature = "pcap")]
+pub mod pcap;
+pub mod prefix;
+pub mod prefixlist;
+pub mod preprocess;
+pub mod priority;
+pub mod query;
+pub mod replica;
+#[cfg(feature = "std")]
+pub mod report;
 pub mod rule;
+pub mod ruleset;
+pub mod serial;
+pub mod shadow;
+pub mod shard;
 pub mod simulation; // Export simulation
+pub mod suricata;
+mod trace;
+pub mod trie;
 pub mod tss;
+pub mod twostage;
+pub mod update;
 
-// Tests can use std
-#[cfg(test)]
+// Tests and the `std` feature can use std
+#[cfg(any(test, feature = "std"))]
 extern crate std;
@@ -1,12 +1,31 @@
-use crate::classifier::Classifier;
+use crate::classifier::{Classifier, LookupStats};
+use crate::freeze::Freeze;
+use crate::geometry::Region;
+use crate::memory::{Footprint, MemoryStats, Tally};
 use crate::packet::FiveTuple;
+use crate::query::{Overlapping, RegionQuery};
 use crate::rule::{Action, Rule};
+use crate::update::policy::{Health, Monitored};
+use crate::update::DynamicClassifier;
 use alloc::vec::Vec;
 
+#[derive(Clone)]
 pub struct LinearClassifier {
     rules: Vec<Rule>,
 }
 
+impl LinearClassifier {
+    fn lookup(&self, packet: &FiveTuple, stats: &mut LookupStats) -> Option<&Rule> {
+        for rule in &self.rules {
+            stats.rules_compared += 1;
+            if rule.matches(packet) {
+                return Some(rule);
+            }
+        }
+        None // Implicit default deny or no match
+    }
+}
+
 impl Classifier for LinearClassifier {
     fn build(rules: &[Rule]) -> Self {
         // Sort rules by priority (lower is higher priority)
@@ -18,12 +37,67 @@ impl Classifier for LinearClassifier {
         }
     }
 
-    fn classify(&self, packet: &FiveTuple) -> Option<Action> {
-        for rule in &self.rules {
-            if rule.matches(packet) {
-                return Some(rule.action);
-            }
+    fn classify_rule(&self, packet: &FiveTuple) -> Option<&Rule> {
+        self.lookup(packet, &mut LookupStats::default())
+    }
+
+    fn classify_with_stats(&self, packet: &FiveTuple) -> (Option<Action>, LookupStats) {
+        let mut stats = LookupStats::default();
+        let action = self.lookup(packet, &mut stats).map(|r| r.action);
+        (action, stats)
+    }
+
+    fn classify_rule_with_stats(&self, packet: &FiveTuple) -> (Option<&Rule>, LookupStats) {
+        let mut stats = LookupStats::default();
+        let rule = self.lookup(packet, &mut stats);
+        (rule, stats)
+    }
+}
+
+impl DynamicClassifier for LinearClassifier {
+    fn insert(&mut self, rule: Rule) {
+        // After rules of equal priority, as a stable sort in `build` would place it.
+        let idx = self.rules.partition_point(|r| r.priority <= rule.priority);
+        self.rules.insert(idx, rule);
+    }
+
+    fn remove(&mut self, id: u32) -> Option<Rule> {
+        let idx = self.rules.iter().position(|r| r.id == id)?;
+        Some(self.rules.remove(idx))
+    }
+}
+
+impl Monitored for LinearClassifier {
+    /// The rule list is one bucket, scanned whole by a miss.
+    fn health(&self) -> Health {
+        Health {
+            rules: self.rules.len(),
+            stored_rules: self.rules.len(),
+            largest_bucket: self.rules.len(),
+            tables: 1,
         }
-        None // Implicit default deny or no match
+    }
+}
+
+impl RegionQuery for LinearClassifier {
+    fn rules_overlapping(&self, region: &Region) -> Vec<&Rule> {
+        let mut found = Overlapping::new(region);
+        found.scan(&self.rules);
+        found.finish()
+    }
+}
+
+impl MemoryStats for LinearClassifier {
+    /// The rule list counts as a single leaf.
+    fn footprint(&self) -> Footprint {
+        let mut tally = Tally::new(size_of::<Self>() + self.rules.capacity() * size_of::<Rule>());
+        tally.leaf(0, &self.rules);
+        tally.finish()
+    }
+}
+
+impl Freeze for LinearClassifier {
+    fn shrink_to_fit(&mut self) {
+        self.rules.shrink_to_fit();
     }
 }
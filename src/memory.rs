@@ -0,0 +1,120 @@
+//! Memory footprint of built classifiers.
+//!
+//! The algorithms trade space for lookup time, and rule replication is where
+//! the space goes: a decision tree copies every rule spanning a cut into
+//! both sides. `MemoryStats` reports a classifier's `Footprint` (bytes,
+//! nodes, rule copies, depth) so builds can be compared on both axes.
+//!
+//! Byte counts cover the classifier and the heap blocks it owns, by
+//! capacity; allocator overhead is not included. For hash-based classifiers
+//! they are estimates, as the tables' control bytes are not counted.
+
+use crate::compact::{BinaryNode, NodeView};
+use crate::rule::Rule;
+use hashbrown::HashSet;
+
+/// Space used by a built classifier.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Footprint {
+    /// Bytes held, the classifier itself included.
+    pub bytes: usize,
+    /// Nodes, leaves included (tables and buckets for TSS).
+    pub nodes: usize,
+    /// Leaves (rule lists a lookup may scan).
+    pub leaves: usize,
+    /// Distinct rules held, by id.
+    pub rules: usize,
+    /// Rule copies held by the leaves.
+    pub stored_rules: usize,
+    /// Depth of the deepest leaf.
+    pub max_depth: usize,
+}
+
+impl Footprint {
+    /// Rule copies per rule (1.0 = no duplication).
+    pub fn duplication(&self) -> f64 {
+        if self.rules == 0 {
+            return 1.0;
+        }
+        self.stored_rules as f64 / self.rules as f64
+    }
+
+    /// Rule copies per leaf.
+    pub fn average_leaf_size(&self) -> f64 {
+        if self.leaves == 0 {
+            return 0.0;
+        }
+        self.stored_rules as f64 / self.leaves as f64
+    }
+}
+
+/// Classifier that can report its memory footprint.
+pub trait MemoryStats {
+    /// Current footprint, computed by walking the structure.
+    fn footprint(&self) -> Footprint;
+}
+
+/// Accumulates a `Footprint` over a walk of a structure.
+pub(crate) struct Tally {
+    footprint: Footprint,
+    ids: HashSet<u32>,
+}
+
+impl Tally {
+    /// Start a walk of a classifier holding `bytes`.
+    pub(crate) fn new(bytes: usize) -> Self {
+        Self {
+            footprint: Footprint {
+                bytes,
+                ..Footprint::default()
+            },
+            ids: HashSet::new(),
+        }
+    }
+
+    pub(crate) fn internal(&mut self) {
+        self.footprint.nodes += 1;
+    }
+
+    pub(crate) fn leaf<'a>(&mut self, depth: usize, rules: impl IntoIterator<Item = &'a Rule>) {
+        let f = &mut self.footprint;
+        f.nodes += 1;
+        f.leaves += 1;
+        f.max_depth = f.max_depth.max(depth);
+        for rule in rules {
+            f.stored_rules += 1;
+            self.ids.insert(rule.id);
+        }
+    }
+
+    /// Record a leaf counted already, reached again at `depth` through a
+    /// shared subtree.
+    pub(crate) fn reached(&mut self, depth: usize) {
+        self.footprint.max_depth = self.footprint.max_depth.max(depth);
+    }
+
+    pub(crate) fn finish(self) -> Footprint {
+        Footprint {
+            rules: self.ids.len(),
+            ..self.footprint
+        }
+    }
+}
+
+/// Footprint of a binary cut tree (CutSplit, HyperSplit) held in `bytes`.
+pub(crate) fn binary<N: BinaryNode>(root: &N, bytes: usize) -> Footprint {
+    let mut tally = Tally::new(bytes);
+    let mut stack = alloc::vec![(root, 0)];
+    while let Some((node, depth)) = stack.pop() {
+        match node.view() {
+            NodeView::Internal { left, right, .. } => {
+                tally.internal();
+                stack.push((right, depth + 1));
+                stack.push((left, depth + 1));
+            }
+            NodeView::Leaf(rules) => tally.leaf(depth, rules),
+            NodeView::IndexedLeaf(index) => tally.leaf(depth, index.rules()),
+        }
+    }
+    tally.finish()
+}
@@ -0,0 +1,120 @@
+//! Multi-tenant rule tables.
+//!
+//! A `MultiTableClassifier` holds one independent rule set per table id (VRF,
+//! tenant, ...). The table is chosen by the caller alongside the packet, so a
+//! single instance can serve a multi-VRF router. Tables installed with an
+//! identical rule set share one built classifier instead of each building
+//! their own copy.
+//!
+//! Sharing uses `Arc`, or `Rc` with the `single-core` feature for targets
+//! without atomics.
+
+use crate::classifier::{Classifier, MatchResult, Verdict};
+use crate::packet::FiveTuple;
+use crate::rule::{Action, Rule};
+#[cfg(feature = "single-core")]
+use alloc::rc::Rc as Arc;
+#[cfg(not(feature = "single-core"))]
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+use hashbrown::HashMap;
+
+/// A built rule set, possibly shared by several tables.
+struct Shared<C> {
+    rules: Vec<Rule>,
+    classifier: C,
+}
+
+/// Per-table classifiers selected by a table id.
+pub struct MultiTableClassifier<C> {
+    tables: HashMap<u32, Arc<Shared<C>>>,
+}
+
+impl<C: Classifier> MultiTableClassifier<C> {
+    /// Create a classifier with no tables.
+    pub fn new() -> Self {
+        Self {
+            tables: HashMap::new(),
+        }
+    }
+
+    /// Install (or replace) the rule set of `table`.
+    ///
+    /// If another table already holds exactly the same rules, its classifier
+    /// is reused rather than built again.
+    pub fn insert_table(&mut self, table: u32, rules: &[Rule]) {
+        let existing = self
+            .tables
+            .values()
+            .find(|shared| shared.rules == rules)
+            .cloned();
+        let shared = existing.unwrap_or_else(|| {
+            Arc::new(Shared {
+                rules: rules.to_vec(),
+                classifier: C::build(rules),
+            })
+        });
+        self.tables.insert(table, shared);
+    }
+
+    /// Remove a table, returning true if it existed.
+    pub fn remove_table(&mut self, table: u32) -> bool {
+        self.tables.remove(&table).is_some()
+    }
+
+    /// Rules installed in `table`.
+    pub fn rules(&self, table: u32) -> Option<&[Rule]> {
+        self.tables
+            .get(&table)
+            .map(|shared| shared.rules.as_slice())
+    }
+
+    /// Classifier serving `table`.
+    pub fn table(&self, table: u32) -> Option<&C> {
+        self.tables.get(&table).map(|shared| &shared.classifier)
+    }
+
+    /// Number of installed tables.
+    pub fn len(&self) -> usize {
+        self.tables.len()
+    }
+
+    /// Returns true if no table is installed.
+    pub fn is_empty(&self) -> bool {
+        self.tables.is_empty()
+    }
+
+    /// Number of distinct classifiers actually built (shared tables count once).
+    pub fn distinct_classifiers(&self) -> usize {
+        let mut seen: Vec<*const Shared<C>> = self.tables.values().map(Arc::as_ptr).collect();
+        seen.sort_unstable();
+        seen.dedup();
+        seen.len()
+    }
+
+    /// Highest-priority matching rule of `table`; an unknown table matches nothing.
+    pub fn classify_rule(&self, table: u32, packet: &FiveTuple) -> Option<&Rule> {
+        self.table(table)?.classify_rule(packet)
+    }
+
+    /// Action of the matching rule of `table`, if any.
+    pub fn classify(&self, table: u32, packet: &FiveTuple) -> Option<Action> {
+        self.classify_rule(table, packet).map(|r| r.action)
+    }
+
+    /// Id, priority, action and rule of the match in `table`, if any.
+    pub fn classify_full(&self, table: u32, packet: &FiveTuple) -> Option<MatchResult<'_>> {
+        self.classify_rule(table, packet).map(MatchResult::from)
+    }
+
+    /// Verdict for `packet` in `table`.
+    pub fn classify_verdict(&self, table: u32, packet: &FiveTuple) -> Verdict {
+        Verdict::from(self.classify_rule(table, packet))
+    }
+}
+
+impl<C: Classifier> Default for MultiTableClassifier<C> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
@@ -0,0 +1,103 @@
+//! Rule normalization to canonical prefix form.
+//!
+//! Rewrites arbitrary-range rules into an equivalent set of rules whose fields
+//! are all prefixes (value/len), which is what tries, TCAMs and TSS work on
+//! natively. Each expanded rule keeps the original id, priority and action,
+//! so first-match semantics are unchanged.
+
+use crate::rule::{expand_rules, Rule};
+use crate::tss::utils::{range_to_prefixes_u16, range_to_prefixes_u32, range_to_prefixes_u8};
+use alloc::vec::Vec;
+
+/// Statistics describing the cost of a prefix expansion.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct ExpansionStats {
+    /// Number of input rules.
+    pub input_rules: usize,
+    /// Number of prefix-only rules produced.
+    pub output_rules: usize,
+    /// Input rules that were already prefix-only.
+    pub already_prefix: usize,
+    /// Largest number of output rules produced from a single input rule.
+    pub max_expansion: usize,
+}
+
+impl ExpansionStats {
+    /// Average number of output rules per input rule.
+    pub fn ratio(&self) -> f32 {
+        if self.input_rules == 0 {
+            return 1.0;
+        }
+        self.output_rules as f32 / self.input_rules as f32
+    }
+}
+
+/// Returns true if every field of `rule` is a single prefix.
+pub fn is_prefix_rule(rule: &Rule) -> bool {
+    rule.field_sets.as_ref().is_none_or(|s| s.is_empty())
+        && range_to_prefixes_u32(rule.src_ip.min, rule.src_ip.max, 32).len() == 1
+        && range_to_prefixes_u32(rule.dst_ip.min, rule.dst_ip.max, 32).len() == 1
+        && range_to_prefixes_u16(rule.src_port.min, rule.src_port.max).len() == 1
+        && range_to_prefixes_u16(rule.dst_port.min, rule.dst_port.max).len() == 1
+        && range_to_prefixes_u8(rule.proto.min, rule.proto.max).len() == 1
+}
+
+/// Expand a single rule into prefix-only rules.
+///
+/// Field sets and bidirectional rules are expanded first (see `expand_rules`).
+pub fn expand_rule(rule: &Rule) -> Vec<Rule> {
+    if !rule.is_plain() {
+        return expand_rules(core::slice::from_ref(rule))
+            .iter()
+            .flat_map(expand_rule)
+            .collect();
+    }
+
+    let src = range_to_prefixes_u32(rule.src_ip.min, rule.src_ip.max, 32);
+    let dst = range_to_prefixes_u32(rule.dst_ip.min, rule.dst_ip.max, 32);
+    let sp = range_to_prefixes_u16(rule.src_port.min, rule.src_port.max);
+    let dp = range_to_prefixes_u16(rule.dst_port.min, rule.dst_port.max);
+    let pr = range_to_prefixes_u8(rule.proto.min, rule.proto.max);
+
+    let mut out = Vec::with_capacity(src.len() * dst.len() * sp.len() * dp.len() * pr.len());
+    for s in &src {
+        for d in &dst {
+            for a in &sp {
+                for b in &dp {
+                    for p in &pr {
+                        out.push(Rule {
+                            src_ip: s.to_range(),
+                            dst_ip: d.to_range(),
+                            src_port: a.to_range(),
+                            dst_port: b.to_range(),
+                            proto: p.to_range(),
+                            ..rule.clone()
+                        });
+                    }
+                }
+            }
+        }
+    }
+    out
+}
+
+/// Rewrite `rules` into an equivalent prefix-only rule set.
+pub fn to_prefix_rules(rules: &[Rule]) -> (Vec<Rule>, ExpansionStats) {
+    let mut out = Vec::with_capacity(rules.len());
+    let mut stats = ExpansionStats {
+        input_rules: rules.len(),
+        ..ExpansionStats::default()
+    };
+
+    for rule in rules {
+        let expanded = expand_rule(rule);
+        if expanded.len() == 1 {
+            stats.already_prefix += 1;
+        }
+        stats.max_expansion = stats.max_expansion.max(expanded.len());
+        out.extend(expanded);
+    }
+
+    stats.output_rules = out.len();
+    (out, stats)
+}
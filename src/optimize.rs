@@ -0,0 +1,181 @@
+//! Semantics-preserving rule-set optimizations.
+//!
+//! `merge_adjacent` folds rules that are consecutive in priority order, take
+//! the same action and differ in a single field whose ranges touch or overlap
+//! into one rule covering both. ACLs imported from other tools (one rule per
+//! port, per /24, ...) commonly shrink by a fifth or more this way, which
+//! directly reduces tree size and build time. Only actions are preserved: a
+//! packet that used to hit an absorbed rule now reports the surviving rule's
+//! id, which `MergeReport::absorbed` records.
+//!
+//! `reorder` moves rules the caller scores highly (hot rules, or rules that
+//! are cheap to check) towards the front, which shortens linear scans and
+//! leaf scans. Two rules only swap if no packet matches both, so every packet
+//! still hits exactly the same rule.
+
+use crate::dimension::Dimension;
+use crate::geometry::Region;
+use crate::rule::{expand_rules, Range, Rule};
+use alloc::collections::BinaryHeap;
+use alloc::vec::Vec;
+use core::cmp::Reverse;
+
+/// Outcome of `merge_adjacent`.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct MergeReport {
+    /// Number of input rules.
+    pub before: usize,
+    /// Number of rules after merging.
+    pub after: usize,
+    /// `(kept, absorbed)` rule id pairs, in merge order.
+    pub absorbed: Vec<(u32, u32)>,
+}
+
+impl MergeReport {
+    /// Fraction of rules removed (0.0 when nothing merged).
+    pub fn reduction(&self) -> f32 {
+        if self.before == 0 {
+            return 0.0;
+        }
+        (self.before - self.after) as f32 / self.before as f32
+    }
+}
+
+/// Merge consecutive-priority rules with identical actions that differ only
+/// in one field with adjacent or overlapping ranges.
+///
+/// The result is sorted by priority; merged rules keep the id and priority of
+/// their highest-priority member. Rules with field sets are left alone.
+/// Merging repeats until no pair qualifies, so a run of per-port rules
+/// collapses into a single port range.
+pub fn merge_adjacent(rules: &[Rule]) -> (Vec<Rule>, MergeReport) {
+    let mut current = rules.to_vec();
+    current.sort_by_key(|r| r.priority);
+    let mut report = MergeReport {
+        before: rules.len(),
+        ..MergeReport::default()
+    };
+
+    loop {
+        let mut merged: Vec<Rule> = Vec::with_capacity(current.len());
+        for rule in current.iter() {
+            if let Some(last) = merged.last_mut() {
+                if let Some(union) = merge_pair(last, rule) {
+                    report.absorbed.push((last.id, rule.id));
+                    *last = union;
+                    continue;
+                }
+            }
+            merged.push(rule.clone());
+        }
+        let done = merged.len() == current.len();
+        current = merged;
+        if done {
+            break;
+        }
+    }
+
+    report.after = current.len();
+    (current, report)
+}
+
+/// The single rule equivalent to `first` followed by `second`, if they merge.
+fn merge_pair(first: &Rule, second: &Rule) -> Option<Rule> {
+    if first.action != second.action
+        || first.zone != second.zone
+        || first.bidirectional != second.bidirectional
+        || first.field_sets.is_some()
+        || second.field_sets.is_some()
+    {
+        return None;
+    }
+
+    let (a, b) = (Region::from_rule(first), Region::from_rule(second));
+    let mut differing = Dimension::ALL.into_iter().filter(|&d| a.get(d) != b.get(d));
+    let Some(dim) = differing.next() else {
+        // Identical match: the second rule is unreachable.
+        return Some(first.clone());
+    };
+    if differing.next().is_some() {
+        return None;
+    }
+
+    let (x, y) = (a.get(dim), b.get(dim));
+    let touching = x.min <= y.max.saturating_add(1) && y.min <= x.max.saturating_add(1);
+    if !touching {
+        return None;
+    }
+    let union = a.with(dim, Range::new(x.min.min(y.min), x.max.max(y.max)));
+    Some(Rule {
+        zone: first.zone,
+        bidirectional: first.bidirectional,
+        ..union.to_rule(first.id, first.priority, first.action)
+    })
+}
+
+/// Reorder `rules` so that higher-`score` rules come first wherever that
+/// cannot change which rule a packet matches.
+///
+/// A rule never moves ahead of an earlier rule it overlaps; otherwise rules
+/// are placed greedily by descending score, ties keeping their original
+/// order. The result reuses the input's priority values, reassigned in the
+/// new order. Rules referencing address sets are assumed to overlap every
+/// rule, since their match changes with the sets.
+pub fn reorder(rules: &[Rule], score: impl Fn(&Rule) -> u64) -> Vec<Rule> {
+    let mut sorted = rules.to_vec();
+    sorted.sort_by_key(|r| r.priority);
+    let priorities: Vec<u32> = sorted.iter().map(|r| r.priority).collect();
+    let footprints: Vec<Option<Vec<Region>>> = sorted.iter().map(footprint).collect();
+
+    // Rule `j` waits for every earlier rule it overlaps.
+    let n = sorted.len();
+    let mut blockers = alloc::vec![0usize; n];
+    let mut unblocks: Vec<Vec<usize>> = alloc::vec![Vec::new(); n];
+    for j in 0..n {
+        for i in 0..j {
+            if overlaps(&sorted[i], &footprints[i], &sorted[j], &footprints[j]) {
+                blockers[j] += 1;
+                unblocks[i].push(j);
+            }
+        }
+    }
+
+    let mut ready: BinaryHeap<(u64, Reverse<usize>)> = (0..n)
+        .filter(|&j| blockers[j] == 0)
+        .map(|j| (score(&sorted[j]), Reverse(j)))
+        .collect();
+    let mut out = Vec::with_capacity(n);
+    while let Some((_, Reverse(i))) = ready.pop() {
+        for &j in &unblocks[i] {
+            blockers[j] -= 1;
+            if blockers[j] == 0 {
+                ready.push((score(&sorted[j]), Reverse(j)));
+            }
+        }
+        out.push(Rule {
+            priority: priorities[out.len()],
+            ..sorted[i].clone()
+        });
+    }
+    out
+}
+
+/// Regions matched by a rule, or `None` if they depend on address sets.
+fn footprint(rule: &Rule) -> Option<Vec<Region>> {
+    if rule.references_sets() {
+        return None;
+    }
+    let plain = expand_rules(core::slice::from_ref(rule));
+    Some(plain.iter().map(Region::from_rule).collect())
+}
+
+/// Returns true if some packet may match both rules.
+fn overlaps(a: &Rule, fa: &Option<Vec<Region>>, b: &Rule, fb: &Option<Vec<Region>>) -> bool {
+    if a.zone.min > b.zone.max || b.zone.min > a.zone.max {
+        return false;
+    }
+    match (fa, fb) {
+        (Some(fa), Some(fb)) => fa.iter().any(|x| fb.iter().any(|y| x.intersects(y))),
+        _ => true,
+    }
+}
@@ -7,25 +7,376 @@
 /// - Destination Port (L4)
 /// - IP Protocol (TCP, UDP, IGMP, etc.)
 ///
+/// plus the ingress interface/zone the packet arrived on, so zone-based
+/// policies ("from wan to lan") can match on it directly.
+///
 /// It is derived from the headers of the parsed packet.
-#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
 pub struct FiveTuple {
     /// Source IP address (big-endian/network byte order usually, but here u32 host order assumed for sim)
     pub src_ip: u32,
     /// Destination IP address
     pub dst_ip: u32,
+    /// Source L4 Port (ICMP/IGMP type, 0 if not applicable; see `L4Key`)
+    pub src_port: u16,
+    /// Destination L4 Port (ICMP code, 0 if not applicable; see `L4Key`)
+    pub dst_port: u16,
+    /// IP Protocol Number (e.g. 6 for TCP, 17 for UDP)
+    pub proto: u8,
+    /// Ingress interface index / zone id (0 when zones are not used)
+    pub zone: u32,
+}
+
+impl FiveTuple {
+    /// The port fields, read as the protocol's L4 key.
+    pub fn l4(&self) -> L4Key {
+        L4Key::decode(self.proto, self.src_port, self.dst_port)
+    }
+
+    /// The same tuple with its port fields holding `key`.
+    pub fn with_l4(mut self, key: L4Key) -> Self {
+        (self.src_port, self.dst_port) = key.ports();
+        self
+    }
+
+    /// The tuple of the opposite direction of the same flow.
+    ///
+    /// The zone is kept as is: the caller sets it if replies arrive on a
+    /// different interface.
+    pub fn reversed(&self) -> Self {
+        Self {
+            src_ip: self.dst_ip,
+            dst_ip: self.src_ip,
+            src_port: self.dst_port,
+            dst_port: self.src_port,
+            proto: self.proto,
+            zone: self.zone,
+        }
+    }
+
+    /// Direction-independent key of the flow: the endpoint with the smaller
+    /// `(ip, port)` becomes the source, so both directions of a connection
+    /// canonicalize to the same tuple.
+    pub fn canonicalize(&self) -> Self {
+        if self.is_canonical() {
+            *self
+        } else {
+            self.reversed()
+        }
+    }
+
+    /// Returns true if the tuple is already in canonical order.
+    pub fn is_canonical(&self) -> bool {
+        (self.src_ip, self.src_port) <= (self.dst_ip, self.dst_port)
+    }
+
+    /// The same tuple, arriving on `zone`.
+    pub fn in_zone(mut self, zone: u32) -> Self {
+        self.zone = zone;
+        self
+    }
+}
+
+/// The L4 fields of a packet, as carried in the port fields of a key.
+///
+/// Protocols without ports reuse the two port fields for their message type,
+/// as OpenFlow does, so rules can match e.g. ICMP echo requests with plain
+/// port ranges:
+/// - TCP and UDP: source and destination ports.
+/// - ICMP (and ICMPv6): type in `src_port`, code in `dst_port`.
+/// - IGMP: type in `src_port`, `dst_port` 0.
+/// - Other protocols, and fragments other than the first: both 0.
+///
+/// Every `PacketKey` follows this convention, so a classifier sees the same
+/// key whether it is fed parsed headers, a raw buffer or a `FiveTuple`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub enum L4Key {
+    Ports {
+        src: u16,
+        dst: u16,
+    },
+    Icmp {
+        icmp_type: u8,
+        code: u8,
+    },
+    Igmp {
+        igmp_type: u8,
+    },
+    #[default]
+    None,
+}
+
+impl L4Key {
+    /// Read the port fields of a `proto` packet. Values that do not fit the
+    /// protocol's key (an ICMP type above 255) are truncated.
+    pub fn decode(proto: u8, src_port: u16, dst_port: u16) -> Self {
+        match proto {
+            PROTO_TCP | PROTO_UDP => L4Key::Ports {
+                src: src_port,
+                dst: dst_port,
+            },
+            PROTO_ICMP | PROTO_ICMPV6 => L4Key::Icmp {
+                icmp_type: src_port as u8,
+                code: dst_port as u8,
+            },
+            PROTO_IGMP => L4Key::Igmp {
+                igmp_type: src_port as u8,
+            },
+            _ => L4Key::None,
+        }
+    }
+
+    /// The `(src_port, dst_port)` fields carrying the key.
+    pub fn ports(self) -> (u16, u16) {
+        match self {
+            L4Key::Ports { src, dst } => (src, dst),
+            L4Key::Icmp { icmp_type, code } => (icmp_type as u16, code as u16),
+            L4Key::Igmp { igmp_type } => (igmp_type as u16, 0),
+            L4Key::None => (0, 0),
+        }
+    }
+}
+
+/// Anything a 5-tuple can be read from, so classifiers take packets in the
+/// caller's own representation (a foreign struct, a view over a raw buffer)
+/// without the caller building a `FiveTuple` first.
+///
+/// Classifiers read each field once, through `to_five_tuple`.
+pub trait PacketKey {
+    /// Source IPv4 address.
+    fn src_ip(&self) -> u32;
+    /// Destination IPv4 address.
+    fn dst_ip(&self) -> u32;
+    /// Source L4 port, or ICMP/IGMP type (see `L4Key`).
+    fn src_port(&self) -> u16;
+    /// Destination L4 port, or ICMP code (see `L4Key`).
+    fn dst_port(&self) -> u16;
+    /// IP protocol number.
+    fn proto(&self) -> u8;
+    /// Ingress zone (0 when zones are not used).
+    fn zone(&self) -> u32 {
+        0
+    }
+
+    /// The port fields, read as the protocol's L4 key.
+    fn l4_key(&self) -> L4Key {
+        L4Key::decode(self.proto(), self.src_port(), self.dst_port())
+    }
+
+    /// The fields as a `FiveTuple`.
+    fn to_five_tuple(&self) -> FiveTuple {
+        FiveTuple {
+            src_ip: self.src_ip(),
+            dst_ip: self.dst_ip(),
+            src_port: self.src_port(),
+            dst_port: self.dst_port(),
+            proto: self.proto(),
+            zone: self.zone(),
+        }
+    }
+}
+
+impl PacketKey for FiveTuple {
+    fn src_ip(&self) -> u32 {
+        self.src_ip
+    }
+
+    fn dst_ip(&self) -> u32 {
+        self.dst_ip
+    }
+
+    fn src_port(&self) -> u16 {
+        self.src_port
+    }
+
+    fn dst_port(&self) -> u16 {
+        self.dst_port
+    }
+
+    fn proto(&self) -> u8 {
+        self.proto
+    }
+
+    fn zone(&self) -> u32 {
+        self.zone
+    }
+
+    fn to_five_tuple(&self) -> FiveTuple {
+        *self
+    }
+}
+
+impl<K: PacketKey + ?Sized> PacketKey for &K {
+    fn src_ip(&self) -> u32 {
+        (**self).src_ip()
+    }
+
+    fn dst_ip(&self) -> u32 {
+        (**self).dst_ip()
+    }
+
+    fn src_port(&self) -> u16 {
+        (**self).src_port()
+    }
+
+    fn dst_port(&self) -> u16 {
+        (**self).dst_port()
+    }
+
+    fn proto(&self) -> u8 {
+        (**self).proto()
+    }
+
+    fn zone(&self) -> u32 {
+        (**self).zone()
+    }
+
+    fn l4_key(&self) -> L4Key {
+        (**self).l4_key()
+    }
+
+    fn to_five_tuple(&self) -> FiveTuple {
+        (**self).to_five_tuple()
+    }
+}
+
+/// Zero-copy view of a raw IPv4 packet (from the IP header on), read as a
+/// `PacketKey` straight from the buffer.
+///
+/// The port fields follow the `L4Key` convention: ICMP type and code and
+/// IGMP type are read from the first fragment, and fragments other than the
+/// first give 0.
+#[derive(Debug, Clone, Copy)]
+pub struct Ipv4Bytes<'a> {
+    bytes: &'a [u8],
+    l4: L4Key,
+}
+
+impl<'a> Ipv4Bytes<'a> {
+    /// View `bytes`, or `None` if they do not hold a whole IPv4 header (of
+    /// at least 20 bytes, and the L4 key of a first TCP, UDP, ICMP or IGMP
+    /// fragment).
+    pub fn new(bytes: &'a [u8]) -> Option<Self> {
+        if bytes.len() < 20 || bytes[0] >> 4 != 4 {
+            return None;
+        }
+        let header_len = (bytes[0] & 0x0F) as usize * 4;
+        if header_len < 20 || header_len > bytes.len() {
+            return None;
+        }
+        let fragment_offset = u16::from_be_bytes([bytes[6], bytes[7]]) & 0x1FFF;
+        let l4 = match (bytes[9], fragment_offset) {
+            (PROTO_TCP | PROTO_UDP, 0) => {
+                let ports = bytes.get(header_len..header_len + 4)?;
+                L4Key::Ports {
+                    src: u16::from_be_bytes([ports[0], ports[1]]),
+                    dst: u16::from_be_bytes([ports[2], ports[3]]),
+                }
+            }
+            (PROTO_ICMP, 0) => {
+                let header = bytes.get(header_len..header_len + 2)?;
+                L4Key::Icmp {
+                    icmp_type: header[0],
+                    code: header[1],
+                }
+            }
+            (PROTO_IGMP, 0) => L4Key::Igmp {
+                igmp_type: *bytes.get(header_len)?,
+            },
+            _ => L4Key::None,
+        };
+        Some(Self { bytes, l4 })
+    }
+
+    /// Returns true if the packet is a fragment (first or later).
+    pub fn is_fragment(&self) -> bool {
+        u16::from_be_bytes([self.bytes[6], self.bytes[7]]) & 0x3FFF != 0
+    }
+
+    /// Bytes after the IPv4 header, up to the total length (or the end of
+    /// the buffer, if shorter).
+    pub fn payload(&self) -> &'a [u8] {
+        let header_len = (self.bytes[0] & 0x0F) as usize * 4;
+        let total_len = u16::from_be_bytes([self.bytes[2], self.bytes[3]]) as usize;
+        let end = total_len.min(self.bytes.len());
+        self.bytes.get(header_len..end).unwrap_or(&[])
+    }
+
+    fn u32_at(&self, at: usize) -> u32 {
+        u32::from_be_bytes([
+            self.bytes[at],
+            self.bytes[at + 1],
+            self.bytes[at + 2],
+            self.bytes[at + 3],
+        ])
+    }
+}
+
+impl PacketKey for Ipv4Bytes<'_> {
+    fn src_ip(&self) -> u32 {
+        self.u32_at(12)
+    }
+
+    fn dst_ip(&self) -> u32 {
+        self.u32_at(16)
+    }
+
+    fn src_port(&self) -> u16 {
+        self.l4.ports().0
+    }
+
+    fn dst_port(&self) -> u16 {
+        self.l4.ports().1
+    }
+
+    fn proto(&self) -> u8 {
+        self.bytes[9]
+    }
+
+    fn l4_key(&self) -> L4Key {
+        self.l4
+    }
+}
+
+/// IPv6 counterpart of `FiveTuple`, with 128-bit addresses (classified by
+/// `ipv6::V6Classifier`).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct SixTuple {
+    /// Source IPv6 address
+    pub src_ip: u128,
+    /// Destination IPv6 address
+    pub dst_ip: u128,
     /// Source L4 Port (0 if not applicable)
     pub src_port: u16,
     /// Destination L4 Port (0 if not applicable)
     pub dst_port: u16,
-    /// IP Protocol Number (e.g. 6 for TCP, 17 for UDP)
+    /// Next header (L4 protocol) number
     pub proto: u8,
+    /// Ingress interface index / zone id (0 when zones are not used)
+    pub zone: u32,
+}
+
+impl SixTuple {
+    /// The tuple of the opposite direction of the same flow (zone kept).
+    pub fn reversed(&self) -> Self {
+        Self {
+            src_ip: self.dst_ip,
+            dst_ip: self.src_ip,
+            src_port: self.dst_port,
+            dst_port: self.src_port,
+            proto: self.proto,
+            zone: self.zone,
+        }
+    }
 }
 
 /// IPv4 Header structure (simplified for simulation).
 ///
-/// Contains the basic IP fields. In a real no_std environment,
-/// this would be parsed from raw bytes.
+/// Contains the fixed 20 bytes of the header; options are not modeled.
 #[derive(Debug, Clone, Copy, Default)]
 pub struct Ipv4Header {
     /// Source IP Address
@@ -34,16 +385,65 @@ pub struct Ipv4Header {
     pub dst: u32,
     /// Protocol Number (defines the L4 header type)
     pub proto: u8,
-    // Add other fields if necessary for "completeness" simulation
     /// IP Version (implied 4)
     pub version: u8,
     /// Internet Header Length (IHL)
     pub ihl: u8,
     /// Time To Live (TTL)
     pub ttl: u8,
+    /// Type of Service (DSCP and ECN)
+    pub tos: u8,
+    /// Length of the packet, header included
+    pub total_len: u16,
+    /// Identification (shared by the fragments of a packet)
+    pub id: u16,
+    /// Flags (top 3 bits) and fragment offset
+    pub fragment: u16,
+    /// Header Checksum
+    pub checksum: u16,
 }
 
-/// TCP Header
+impl Ipv4Header {
+    /// The header as sent on the wire.
+    pub fn to_bytes(&self) -> [u8; 20] {
+        let mut bytes = [0; 20];
+        bytes[0] = self.version << 4 | (self.ihl & 0x0F);
+        bytes[1] = self.tos;
+        bytes[2..4].copy_from_slice(&self.total_len.to_be_bytes());
+        bytes[4..6].copy_from_slice(&self.id.to_be_bytes());
+        bytes[6..8].copy_from_slice(&self.fragment.to_be_bytes());
+        bytes[8] = self.ttl;
+        bytes[9] = self.proto;
+        bytes[10..12].copy_from_slice(&self.checksum.to_be_bytes());
+        bytes[12..16].copy_from_slice(&self.src.to_be_bytes());
+        bytes[16..20].copy_from_slice(&self.dst.to_be_bytes());
+        bytes
+    }
+
+    /// Checksum of the header, computed as if `checksum` were 0.
+    pub fn compute_checksum(&self) -> u16 {
+        internet_checksum(
+            &Self {
+                checksum: 0,
+                ..*self
+            }
+            .to_bytes(),
+        )
+    }
+
+    /// Returns true if `checksum` is right for the other fields.
+    pub fn verify_checksum(&self) -> bool {
+        internet_checksum(&self.to_bytes()) == 0
+    }
+
+    /// Sum of the pseudo-header TCP and UDP checksums start from, for a
+    /// segment of `len` bytes.
+    pub fn pseudo_header(&self, len: u16) -> Checksum {
+        Checksum::pseudo_header(self.src, self.dst, self.proto, len)
+    }
+}
+
+/// TCP Header (without options).
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
 pub struct TcpHeader {
     pub src_port: u16,
@@ -51,6 +451,49 @@ pub struct TcpHeader {
     pub sequence: u32,
     pub ack: u32,
     pub flags: u8,
+    pub window: u16,
+    pub checksum: u16,
+    pub urgent: u16,
+}
+
+impl TcpHeader {
+    /// The header as sent on the wire (data offset 5).
+    pub fn to_bytes(&self) -> [u8; 20] {
+        let mut bytes = [0; 20];
+        bytes[0..2].copy_from_slice(&self.src_port.to_be_bytes());
+        bytes[2..4].copy_from_slice(&self.dst_port.to_be_bytes());
+        bytes[4..8].copy_from_slice(&self.sequence.to_be_bytes());
+        bytes[8..12].copy_from_slice(&self.ack.to_be_bytes());
+        bytes[12] = 5 << 4;
+        bytes[13] = self.flags;
+        bytes[14..16].copy_from_slice(&self.window.to_be_bytes());
+        bytes[16..18].copy_from_slice(&self.checksum.to_be_bytes());
+        bytes[18..20].copy_from_slice(&self.urgent.to_be_bytes());
+        bytes
+    }
+
+    /// Checksum of the segment carrying `payload` in `ip`, computed as if
+    /// `checksum` were 0.
+    pub fn compute_checksum(&self, ip: &Ipv4Header, payload: &[u8]) -> u16 {
+        Self {
+            checksum: 0,
+            ..*self
+        }
+        .sum(ip, payload)
+    }
+
+    /// Returns true if `checksum` is right for the segment carrying
+    /// `payload` in `ip`.
+    pub fn verify_checksum(&self, ip: &Ipv4Header, payload: &[u8]) -> bool {
+        self.sum(ip, payload) == 0
+    }
+
+    fn sum(&self, ip: &Ipv4Header, payload: &[u8]) -> u16 {
+        let header = self.to_bytes();
+        let mut sum = ip.pseudo_header((header.len() + payload.len()) as u16);
+        sum.add(&header).add(payload);
+        sum.finish()
+    }
 }
 
 /// UDP Header.
@@ -64,12 +507,65 @@ pub struct UdpHeader {
     pub dst_port: u16,
     /// Length of the packet
     pub length: u16,
+    /// Checksum (0 if the sender did not compute one)
+    pub checksum: u16,
+}
+
+impl UdpHeader {
+    /// The header as sent on the wire.
+    pub fn to_bytes(&self) -> [u8; 8] {
+        let mut bytes = [0; 8];
+        bytes[0..2].copy_from_slice(&self.src_port.to_be_bytes());
+        bytes[2..4].copy_from_slice(&self.dst_port.to_be_bytes());
+        bytes[4..6].copy_from_slice(&self.length.to_be_bytes());
+        bytes[6..8].copy_from_slice(&self.checksum.to_be_bytes());
+        bytes
+    }
+
+    /// Checksum of the datagram carrying `payload` in `ip`, computed as if
+    /// `checksum` were 0 (a sum of 0 is sent as `0xFFFF`).
+    pub fn compute_checksum(&self, ip: &Ipv4Header, payload: &[u8]) -> u16 {
+        let sum = Self {
+            checksum: 0,
+            ..*self
+        }
+        .sum(ip, payload);
+        if sum == 0 {
+            0xFFFF
+        } else {
+            sum
+        }
+    }
+
+    /// Returns true if `checksum` is right for the datagram carrying
+    /// `payload` in `ip`, or is 0 (none computed).
+    pub fn verify_checksum(&self, ip: &Ipv4Header, payload: &[u8]) -> bool {
+        self.checksum == 0 || self.sum(ip, payload) == 0
+    }
+
+    fn sum(&self, ip: &Ipv4Header, payload: &[u8]) -> u16 {
+        let mut sum = ip.pseudo_header(self.length);
+        sum.add(&self.to_bytes()).add(payload);
+        sum.finish()
+    }
+}
+
+/// ICMP Header.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct IcmpHeader {
+    /// ICMP Type (Echo Request, Destination Unreachable, ...)
+    pub icmp_type: u8,
+    /// Subtype of the message
+    pub code: u8,
+    /// Header Checksum
+    pub checksum: u16,
 }
 
 /// IGMP Header.
 ///
 /// Internet Group Management Protocol, used for multicast correctness.
-/// This implementation simulates basic IGMP fields.
+/// This is the 8-byte IGMPv1/v2 message; `igmp::IgmpMessage` parses v3
+/// queries and reports too.
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
 pub struct IgmpHeader {
     /// IGMP Type (Query, Report, Leave)
@@ -82,6 +578,35 @@ pub struct IgmpHeader {
     pub group_addr: u32,
 }
 
+impl IgmpHeader {
+    /// The message as sent on the wire.
+    pub fn to_bytes(&self) -> [u8; 8] {
+        let mut bytes = [0; 8];
+        bytes[0] = self.igmp_type;
+        bytes[1] = self.max_resp_time;
+        bytes[2..4].copy_from_slice(&self.checksum.to_be_bytes());
+        bytes[4..8].copy_from_slice(&self.group_addr.to_be_bytes());
+        bytes
+    }
+
+    /// Checksum of the message, computed as if `checksum` were 0.
+    pub fn compute_checksum(&self) -> u16 {
+        internet_checksum(
+            &Self {
+                checksum: 0,
+                ..*self
+            }
+            .to_bytes(),
+        )
+    }
+
+    /// The query, report or leave this header holds, or `None` for an
+    /// unknown type (a v3 report holds no record here).
+    pub fn message(&self) -> Option<crate::igmp::IgmpMessage> {
+        crate::igmp::IgmpMessage::parse(&self.to_bytes())
+    }
+}
+
 /// Abstract Packet wrapper.
 ///
 /// Represents a fully parsed packet with IP and Layer 4 headers.
@@ -91,7 +616,7 @@ pub struct IgmpHeader {
 pub struct Packet {
     /// IPv4 Header
     pub ip: Ipv4Header,
-    /// Layer 4 Header (TCP, UDP, IGMP, or Unknown)
+    /// Layer 4 Header (TCP, UDP, ICMP, IGMP, or Unknown)
     pub l4: L4Header,
 }
 
@@ -99,19 +624,17 @@ pub struct Packet {
 pub enum L4Header {
     Tcp(TcpHeader),
     Udp(UdpHeader),
+    Icmp(IcmpHeader),
     Igmp(IgmpHeader),
     #[default]
     Unknown,
 }
 
 impl Packet {
-    /// Extract the 5-tuple from the packet
+    /// Extract the 5-tuple from the packet, with the L4 header's key in the
+    /// port fields (see `L4Key`).
     pub fn to_5tuple(&self) -> FiveTuple {
-        let (src_port, dst_port) = match self.l4 {
-            L4Header::Tcp(h) => (h.src_port, h.dst_port),
-            L4Header::Udp(h) => (h.src_port, h.dst_port),
-            _ => (0, 0),
-        };
+        let (src_port, dst_port) = self.l4_key().ports();
 
         FiveTuple {
             src_ip: self.ip.src,
@@ -119,11 +642,275 @@ impl Packet {
             proto: self.ip.proto,
             src_port,
             dst_port,
+            zone: 0,
+        }
+    }
+}
+
+impl PacketKey for Packet {
+    fn src_ip(&self) -> u32 {
+        self.ip.src
+    }
+
+    fn dst_ip(&self) -> u32 {
+        self.ip.dst
+    }
+
+    fn src_port(&self) -> u16 {
+        self.to_5tuple().src_port
+    }
+
+    fn dst_port(&self) -> u16 {
+        self.to_5tuple().dst_port
+    }
+
+    fn proto(&self) -> u8 {
+        self.ip.proto
+    }
+
+    /// The key of the L4 header, whatever `ip.proto` says.
+    fn l4_key(&self) -> L4Key {
+        match self.l4 {
+            L4Header::Tcp(h) => L4Key::Ports {
+                src: h.src_port,
+                dst: h.dst_port,
+            },
+            L4Header::Udp(h) => L4Key::Ports {
+                src: h.src_port,
+                dst: h.dst_port,
+            },
+            L4Header::Icmp(h) => L4Key::Icmp {
+                icmp_type: h.icmp_type,
+                code: h.code,
+            },
+            L4Header::Igmp(h) => L4Key::Igmp {
+                igmp_type: h.igmp_type,
+            },
+            L4Header::Unknown => L4Key::None,
         }
     }
+
+    fn to_five_tuple(&self) -> FiveTuple {
+        self.to_5tuple()
+    }
 }
 
+pub const PROTO_ICMP: u8 = 1;
+pub const PROTO_IGMP: u8 = 2;
 pub const PROTO_TCP: u8 = 6;
 pub const PROTO_UDP: u8 = 17;
-pub const PROTO_IGMP: u8 = 2;
-pub const PROTO_ICMP: u8 = 1;
+pub const PROTO_GRE: u8 = 47;
+pub const PROTO_ESP: u8 = 50;
+pub const PROTO_AH: u8 = 51;
+pub const PROTO_ICMPV6: u8 = 58;
+pub const PROTO_OSPF: u8 = 89;
+pub const PROTO_PIM: u8 = 103;
+pub const PROTO_VRRP: u8 = 112;
+pub const PROTO_SCTP: u8 = 132;
+
+pub const ICMP_ECHO_REPLY: u8 = 0;
+pub const ICMP_DEST_UNREACHABLE: u8 = 3;
+pub const ICMP_REDIRECT: u8 = 5;
+pub const ICMP_ECHO_REQUEST: u8 = 8;
+pub const ICMP_TIME_EXCEEDED: u8 = 11;
+
+pub const IGMP_MEMBERSHIP_QUERY: u8 = 0x11;
+pub const IGMP_V1_MEMBERSHIP_REPORT: u8 = 0x12;
+pub const IGMP_V2_MEMBERSHIP_REPORT: u8 = 0x16;
+pub const IGMP_LEAVE_GROUP: u8 = 0x17;
+pub const IGMP_V3_MEMBERSHIP_REPORT: u8 = 0x22;
+
+/// IANA protocol numbers with their usual (lowercase) names.
+pub const PROTOCOLS: &[(u8, &str)] = &[
+    (PROTO_ICMP, "icmp"),
+    (PROTO_IGMP, "igmp"),
+    (PROTO_TCP, "tcp"),
+    (PROTO_UDP, "udp"),
+    (PROTO_GRE, "gre"),
+    (PROTO_ESP, "esp"),
+    (PROTO_AH, "ah"),
+    (PROTO_ICMPV6, "icmpv6"),
+    (PROTO_OSPF, "ospf"),
+    (PROTO_PIM, "pim"),
+    (PROTO_VRRP, "vrrp"),
+    (PROTO_SCTP, "sctp"),
+];
+
+/// Name of protocol number `proto`, if listed in `PROTOCOLS`.
+pub fn protocol_name(proto: u8) -> Option<&'static str> {
+    PROTOCOLS
+        .iter()
+        .find(|&&(number, _)| number == proto)
+        .map(|&(_, name)| name)
+}
+
+/// Protocol number of `name` (case-insensitive), if listed in `PROTOCOLS`.
+pub fn protocol_by_name(name: &str) -> Option<u8> {
+    PROTOCOLS
+        .iter()
+        .find(|(_, known)| known.eq_ignore_ascii_case(name))
+        .map(|&(number, _)| number)
+}
+
+/// A well-known service: the protocols and destination ports it runs on.
+///
+/// Pass one to `Rule::service` to build the rule admitting it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Service {
+    pub name: &'static str,
+    pub protocols: &'static [u8],
+    /// Destination ports, ascending.
+    pub ports: &'static [u16],
+}
+
+impl Service {
+    pub const HTTP: Service = Service::new("http", &[PROTO_TCP], &[80, 8080]);
+    pub const HTTPS: Service = Service::new("https", &[PROTO_TCP], &[443]);
+    pub const DNS: Service = Service::new("dns", &[PROTO_TCP, PROTO_UDP], &[53]);
+    pub const NTP: Service = Service::new("ntp", &[PROTO_UDP], &[123]);
+    pub const SSH: Service = Service::new("ssh", &[PROTO_TCP], &[22]);
+    pub const SMTP: Service = Service::new("smtp", &[PROTO_TCP], &[25, 465, 587]);
+    pub const DHCP: Service = Service::new("dhcp", &[PROTO_UDP], &[67, 68]);
+    pub const SNMP: Service = Service::new("snmp", &[PROTO_UDP], &[161, 162]);
+    pub const SYSLOG: Service = Service::new("syslog", &[PROTO_UDP], &[514]);
+
+    /// Every service above.
+    pub const ALL: &'static [Service] = &[
+        Service::HTTP,
+        Service::HTTPS,
+        Service::DNS,
+        Service::NTP,
+        Service::SSH,
+        Service::SMTP,
+        Service::DHCP,
+        Service::SNMP,
+        Service::SYSLOG,
+    ];
+
+    pub const fn new(name: &'static str, protocols: &'static [u8], ports: &'static [u16]) -> Self {
+        Self {
+            name,
+            protocols,
+            ports,
+        }
+    }
+
+    /// The service in `ALL` named `name` (case-insensitive).
+    pub fn by_name(name: &str) -> Option<Service> {
+        Self::ALL
+            .iter()
+            .find(|s| s.name.eq_ignore_ascii_case(name))
+            .copied()
+    }
+
+    /// Returns true if `packet` is addressed to the service.
+    pub fn matches(&self, packet: &FiveTuple) -> bool {
+        self.protocols.contains(&packet.proto) && self.ports.contains(&packet.dst_port)
+    }
+}
+
+/// Running Internet checksum (RFC 1071): the ones' complement sum of 16-bit
+/// big-endian words, as used by IPv4, TCP, UDP and ICMP.
+///
+/// Data may be added in several slices; all but the last must have an even
+/// length, since an odd slice is padded with a zero byte.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Checksum {
+    sum: u64,
+}
+
+impl Checksum {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Sum of the IPv4 pseudo-header of a TCP or UDP segment of `len` bytes.
+    pub fn pseudo_header(src: u32, dst: u32, proto: u8, len: u16) -> Self {
+        let mut sum = Self::new();
+        sum.add(&src.to_be_bytes())
+            .add(&dst.to_be_bytes())
+            .add(&[0, proto])
+            .add(&len.to_be_bytes());
+        sum
+    }
+
+    /// Add `bytes` to the sum.
+    pub fn add(&mut self, bytes: &[u8]) -> &mut Self {
+        let mut words = bytes.chunks_exact(2);
+        for word in &mut words {
+            self.sum += u16::from_be_bytes([word[0], word[1]]) as u64;
+        }
+        if let [last] = words.remainder() {
+            self.sum += (*last as u64) << 8;
+        }
+        self
+    }
+
+    /// The checksum: the complement of the folded sum. Data holding a right
+    /// checksum gives 0.
+    pub fn finish(&self) -> u16 {
+        let mut sum = self.sum;
+        while sum > 0xFFFF {
+            sum = (sum & 0xFFFF) + (sum >> 16);
+        }
+        !(sum as u16)
+    }
+}
+
+/// Internet checksum of `bytes`; 0 if they hold a right checksum.
+pub fn internet_checksum(bytes: &[u8]) -> u16 {
+    Checksum::new().add(bytes).finish()
+}
+
+/// Why a raw packet failed checksum verification.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ChecksumError {
+    /// The total length disagrees with the header or the buffer.
+    Truncated,
+    /// Wrong IPv4 header checksum.
+    Ipv4Header,
+    /// Wrong TCP checksum.
+    Tcp,
+    /// Wrong UDP checksum.
+    Udp,
+    /// Wrong IGMP checksum.
+    Igmp,
+}
+
+impl Ipv4Bytes<'_> {
+    /// Check the IPv4 header checksum and, for unfragmented TCP, UDP and
+    /// IGMP packets, the L4 checksum, so a parsing pipeline can drop corrupted
+    /// packets before classifying them. Fragments carry part of a segment,
+    /// whose checksum cannot be checked before reassembly.
+    pub fn verify_checksums(&self) -> Result<(), ChecksumError> {
+        let bytes = self.bytes;
+        let header_len = (bytes[0] & 0x0F) as usize * 4;
+        let total_len = u16::from_be_bytes([bytes[2], bytes[3]]) as usize;
+        if header_len < 20 || total_len < header_len || total_len > bytes.len() {
+            return Err(ChecksumError::Truncated);
+        }
+        if internet_checksum(&bytes[..header_len]) != 0 {
+            return Err(ChecksumError::Ipv4Header);
+        }
+        let fragment = u16::from_be_bytes([bytes[6], bytes[7]]);
+        if fragment & 0x3FFF != 0 {
+            return Ok(());
+        }
+        let segment = &bytes[header_len..total_len];
+        let (proto, error) = match bytes[9] {
+            PROTO_TCP => (PROTO_TCP, ChecksumError::Tcp),
+            // A UDP checksum of 0 means none was computed.
+            PROTO_UDP if segment.get(6..8) == Some(&[0, 0]) => return Ok(()),
+            PROTO_UDP => (PROTO_UDP, ChecksumError::Udp),
+            // IGMP has no pseudo-header.
+            PROTO_IGMP if internet_checksum(segment) != 0 => return Err(ChecksumError::Igmp),
+            _ => return Ok(()),
+        };
+        let mut sum =
+            Checksum::pseudo_header(self.src_ip(), self.dst_ip(), proto, segment.len() as u16);
+        if sum.add(segment).finish() != 0 {
+            return Err(error);
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,38 @@
+//! Parallel batch classification.
+//!
+//! Classifiers are immutable after `build()`, so a single instance can serve
+//! lookups from all threads; these helpers split a packet batch across the
+//! rayon thread pool.
+
+use crate::classifier::Classifier;
+use crate::packet::FiveTuple;
+use crate::rule::Action;
+use alloc::vec;
+use alloc::vec::Vec;
+use rayon::prelude::*;
+
+/// Packets handed to `Classifier::classify_batch` at a time by each thread.
+const CHUNK: usize = 256;
+
+/// Classify `packets` in parallel, returning verdicts in packet order.
+pub fn classify_batch<C: Classifier + Sync>(
+    classifier: &C,
+    packets: &[FiveTuple],
+) -> Vec<Option<Action>> {
+    let mut out = vec![None; packets.len()];
+    out.par_chunks_mut(CHUNK)
+        .zip(packets.par_chunks(CHUNK))
+        .for_each(|(out, packets)| classifier.classify_batch(packets, out));
+    out
+}
+
+/// Ids of the matching rules for `packets`, computed in parallel.
+pub fn classify_rule_ids<C: Classifier + Sync>(
+    classifier: &C,
+    packets: &[FiveTuple],
+) -> Vec<Option<u32>> {
+    packets
+        .par_iter()
+        .map(|p| classifier.classify_rule(p).map(|r| r.id))
+        .collect()
+}
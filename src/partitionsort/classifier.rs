@@ -4,111 +4,212 @@
 //! "A Sorted-Partitioning Approach to Fast and Scalable Dynamic Packet Classification"
 //! Yingchareonthawornchai, et al. (IEEE Transactions on Networking 2018)
 //! <https://ieeexplore.ieee.org/document/7774710>
+//!
+//! The rules are split into sortable partitions (`tree::partition`), each
+//! searched as a `SortableTree` in five binary searches. Partitions are
+//! searched by best priority, stopping as soon as no remaining partition can
+//! beat the match found so far.
 
-use crate::classifier::Classifier;
+use crate::classifier::{Classifier, LookupStats};
+use crate::dimension::Dimension;
+use crate::fingerprint::Fingerprint;
+use crate::freeze::Freeze;
+use crate::geometry::Region;
+use crate::memory::{Footprint, MemoryStats, Tally};
 use crate::packet::FiveTuple;
-use crate::partitionsort::tree::{IntervalTree, Node};
-use crate::rule::{Action, Rule};
+use crate::partitionsort::tree::{partition, Level, Next, SortableTree};
+use crate::query::{Overlapping, RegionQuery};
+use crate::rule::{expand_rules, Action, Range, Rule};
+use crate::serial::{self, Codec};
+use crate::update::audit::{DecodeError, Reader};
 use alloc::vec::Vec;
 
+#[derive(Clone)]
 pub struct PartitionSortClassifier {
-    // For now, simpler version: Just multiple IntervalTrees (partitions) searched linearly?
-    // Or just one best one?
-    // If we want to implement the "Partition" part:
-    // We split rules into subsets. Each subset has its own IntervalTree.
-    trees: Vec<IntervalTree>,
+    /// Sortable partitions, by best priority.
+    partitions: Vec<SortableTree>,
+    fingerprint: Option<Fingerprint>,
 }
 
 impl PartitionSortClassifier {
-    // Heuristic: Evaluate a dimension. Returns a score (lower is better).
-    // Score = Max bucket size in the tree?
-    fn evaluate_dimension(rules: &[Rule], dim: usize) -> usize {
-        // Build a temporary tree (or just simulate) to find max collision depth
-        // Simulation is cheaper.
-        // But `IntervalTree::build` is fast enough for setup.
-        let tree = IntervalTree::build(rules.to_vec(), dim);
-        Self::get_max_bucket_size(&tree)
+    /// Fingerprint of the rules the classifier was built from.
+    pub fn fingerprint(&self) -> Option<Fingerprint> {
+        self.fingerprint
     }
 
-    fn get_max_bucket_size(tree: &IntervalTree) -> usize {
-        tree.root
-            .as_ref()
-            .map_or(0, |n| Self::max_bucket_recursive(n))
+    /// The partitions, by best priority.
+    pub fn partitions(&self) -> &[SortableTree] {
+        &self.partitions
     }
 
-    fn max_bucket_recursive(node: &Node) -> usize {
-        let my_size = node.rules.len();
-        let left_max = node
-            .left
-            .as_ref()
-            .map_or(0, |n| Self::max_bucket_recursive(n));
-        let right_max = node
-            .right
-            .as_ref()
-            .map_or(0, |n| Self::max_bucket_recursive(n));
-        my_size.max(left_max).max(right_max)
+    fn lookup(&self, packet: &FiveTuple, stats: &mut LookupStats) -> Option<&Rule> {
+        let mut best_match: Option<&Rule> = None;
+        for tree in &self.partitions {
+            if best_match.is_some_and(|best| best.priority <= tree.best_priority()) {
+                break;
+            }
+            stats.tables_probed += 1;
+            if let Some(rule) = tree.lookup(packet, stats) {
+                if best_match.is_none_or(|best| rule.priority < best.priority) {
+                    best_match = Some(rule);
+                }
+            }
+        }
+        best_match
     }
 }
 
 impl Classifier for PartitionSortClassifier {
     fn build(rules: &[Rule]) -> Self {
-        if rules.is_empty() {
-            return Self { trees: Vec::new() };
+        Self {
+            partitions: partition(&expand_rules(rules)),
+            fingerprint: Some(Fingerprint::of(rules)),
         }
+    }
 
-        // Implementation of a greedy logic:
-        // 1. Try to put ALL rules into one tree on best dim.
-        // 2. If max bucket size is too high, implies "bad sortability" for some rules.
-        // 3. (Partitioning Step - TODO for V2): Extract "bad" rules and put in next partition.
-        // For V1, we just pick the Single Best Dimension.
-        // This effectively makes it a "1D Layout Optimized" classifier.
-
-        // Check 5 dims
-        let mut best_dim = 0;
-        let mut min_max_bucket = usize::MAX;
-
-        for dim in 0..5 {
-            let score = Self::evaluate_dimension(rules, dim);
-            // Prefer Src/Dst IP (0,1) over Ports (2,3) if scores tie, generally more entropy
-            if score < min_max_bucket {
-                min_max_bucket = score;
-                best_dim = dim;
-            }
-        }
+    fn classify_rule(&self, packet: &FiveTuple) -> Option<&Rule> {
+        self.lookup(packet, &mut LookupStats::default())
+    }
 
-        let best_tree = IntervalTree::build(rules.to_vec(), best_dim);
+    fn classify_with_stats(&self, packet: &FiveTuple) -> (Option<Action>, LookupStats) {
+        let mut stats = LookupStats::default();
+        let action = self.lookup(packet, &mut stats).map(|r| r.action);
+        (action, stats)
+    }
 
-        Self {
-            trees: alloc::vec![best_tree],
-        }
+    fn classify_rule_with_stats(&self, packet: &FiveTuple) -> (Option<&Rule>, LookupStats) {
+        let mut stats = LookupStats::default();
+        let rule = self.lookup(packet, &mut stats);
+        (rule, stats)
     }
+}
 
-    fn classify(&self, packet: &FiveTuple) -> Option<Action> {
-        let mut best_match: Option<&Rule> = None;
+impl RegionQuery for PartitionSortClassifier {
+    fn rules_overlapping(&self, region: &Region) -> Vec<&Rule> {
+        let mut found = Overlapping::new(region);
+        for tree in &self.partitions {
+            let mut stack = alloc::vec![(tree.root(), 0)];
+            while let Some((level, depth)) = stack.pop() {
+                let range = region.get(tree.order()[depth]);
+                // Ranges are disjoint and sorted, so their ends are sorted too.
+                let first = level.ranges.partition_point(|r| r.max < range.min);
+                for (r, next) in level.ranges[first..].iter().zip(&level.next[first..]) {
+                    if r.min > range.max {
+                        break;
+                    }
+                    match next {
+                        Next::Level(below) => stack.push((below, depth + 1)),
+                        Next::Rules(rules) => found.scan(rules),
+                    }
+                }
+            }
+        }
+        found.finish()
+    }
+}
 
-        for tree in &self.trees {
-            // Extract value for this tree's dimension
-            let val = match tree.field_idx {
-                0 => packet.src_ip,
-                1 => packet.dst_ip,
-                2 => packet.src_port as u32,
-                3 => packet.dst_port as u32,
-                4 => packet.proto as u32,
-                _ => 0,
-            };
-
-            if let Some(rule) = tree.classify_packet(packet, val) {
-                match best_match {
-                    None => best_match = Some(rule),
-                    Some(best) => {
-                        if rule.priority < best.priority {
-                            best_match = Some(rule);
+impl MemoryStats for PartitionSortClassifier {
+    /// Levels count as internal nodes, one per field on every path.
+    fn footprint(&self) -> Footprint {
+        let mut bytes = size_of::<Self>() + self.partitions.capacity() * size_of::<SortableTree>();
+        let mut tally = Tally::new(0);
+        for tree in &self.partitions {
+            let mut stack = alloc::vec![(tree.root(), 0)];
+            while let Some((level, depth)) = stack.pop() {
+                tally.internal();
+                bytes += level.ranges.capacity() * size_of::<Range<u32>>()
+                    + level.next.capacity() * size_of::<Next>();
+                for next in &level.next {
+                    match next {
+                        Next::Level(below) => stack.push((below, depth + 1)),
+                        Next::Rules(rules) => {
+                            bytes += rules.capacity() * size_of::<Rule>();
+                            tally.leaf(depth + 1, rules);
                         }
                     }
                 }
             }
         }
+        Footprint {
+            bytes,
+            ..tally.finish()
+        }
+    }
+}
+
+impl Freeze for PartitionSortClassifier {
+    fn shrink_to_fit(&mut self) {
+        self.partitions.shrink_to_fit();
+        for tree in &mut self.partitions {
+            tree.shrink_to_fit();
+        }
+    }
+}
+
+impl Codec for PartitionSortClassifier {
+    fn serialize(&self) -> Vec<u8> {
+        let mut out = serial::header(serial::PARTITION_SORT, self.fingerprint);
+        serial::put_u32(&mut out, self.partitions.len() as u32);
+        for tree in &self.partitions {
+            out.extend(tree.order().map(|d| d.index() as u8));
+            encode_level(&mut out, tree.root());
+        }
+        out
+    }
+
+    fn deserialize(bytes: &[u8]) -> Result<Self, DecodeError> {
+        let mut r = serial::reader(bytes);
+        let fingerprint = serial::open(&mut r, serial::PARTITION_SORT)?;
+        let mut partitions = Vec::new();
+        for _ in 0..r.u32()? {
+            let mut order = [Dimension::SrcIp; Dimension::COUNT];
+            for slot in &mut order {
+                *slot = serial::dimension(r.u8()?)?;
+            }
+            // Each field exactly once.
+            if (1..Dimension::COUNT).any(|i| order[..i].contains(&order[i])) {
+                return Err(DecodeError::Corrupt);
+            }
+            let root = decode_level(&mut r, Dimension::COUNT)?;
+            partitions.push(SortableTree::new(order, root));
+        }
+        serial::finish(&r)?;
+        Ok(Self {
+            partitions,
+            fingerprint,
+        })
+    }
+}
 
-        best_match.map(|r| r.action)
+/// Write a level: the range count, then each range and what lies below it.
+fn encode_level(out: &mut Vec<u8>, level: &Level) {
+    serial::put_u32(out, level.ranges.len() as u32);
+    for (range, next) in level.ranges.iter().zip(&level.next) {
+        serial::put_u32(out, range.min);
+        serial::put_u32(out, range.max);
+        match next {
+            Next::Level(below) => encode_level(out, below),
+            Next::Rules(rules) => serial::encode_rules(out, rules),
+        }
+    }
+}
+
+/// Read a level with `fields` fields left, checking that its ranges are
+/// sorted and disjoint.
+fn decode_level(r: &mut Reader<'_>, fields: usize) -> Result<Level, DecodeError> {
+    let mut level = Level::default();
+    for _ in 0..r.u32()? {
+        let range = Range::new(r.u32()?, r.u32()?);
+        let after_last = level.ranges.last().is_none_or(|last| last.max < range.min);
+        if range.min > range.max || !after_last {
+            return Err(DecodeError::Corrupt);
+        }
+        level.ranges.push(range);
+        level.next.push(if fields == 1 {
+            Next::Rules(serial::decode_rules(r)?)
+        } else {
+            Next::Level(decode_level(r, fields - 1)?)
+        });
     }
+    Ok(level)
 }
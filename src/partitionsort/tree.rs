@@ -1,172 +1,240 @@
+use crate::classifier::LookupStats;
+use crate::dimension::Dimension;
+use crate::packet::FiveTuple;
 use crate::rule::{Range, Rule};
-use alloc::boxed::Box;
+use alloc::collections::BTreeMap;
 use alloc::vec::Vec;
 
-/// Node in the Interval Tree
+/// A partition of rules searched as a multi-field sorted structure.
+///
+/// The rules are sortable under the tree's field order: on the first field
+/// any two rules have disjoint or identical ranges, rules with identical
+/// ranges are in turn disjoint or identical on the second field, and so on.
+/// Each field is then one level of disjoint ranges sorted by start, and a
+/// lookup is one binary search per field down to the rules sharing all five
+/// ranges, which only differ by zone.
 #[derive(Debug, Clone)]
-pub struct Node {
-    pub center: u32,
-    pub left: Option<Box<Node>>,
-    pub right: Option<Box<Node>>,
-    // Rules covering the center point, sorted by priority?
-    // In a standard interval tree, we store intervals overlapping the center.
-    // However, for packet classification, we might want to store the Rule ID or reference.
-    // PartitionSort paper suggests storing rules in a data structure that supports fast stabbing queries.
-    // A simple list of rules at the node is fine for now, we iterate them.
-    pub rules: Vec<Rule>,
+pub struct SortableTree {
+    order: [Dimension; Dimension::COUNT],
+    root: Level,
+    len: usize,
+    best: u32,
 }
 
-impl Node {
-    pub fn new(center: u32, rules: Vec<Rule>) -> Self {
-        Self {
-            center,
-            left: None,
-            right: None,
-            rules,
-        }
-    }
+/// The disjoint ranges of one field, sorted by start, with what lies below.
+#[derive(Debug, Clone, Default)]
+pub(crate) struct Level {
+    pub(crate) ranges: Vec<Range<u32>>,
+    pub(crate) next: Vec<Next>,
 }
 
-/// A 1-Dimensional Interval Tree for a specific field Dimension.
 #[derive(Debug, Clone)]
-pub struct IntervalTree {
-    pub root: Option<Box<Node>>,
-    pub field_idx: usize, // 0=SrcIP, 1=DstIP, 2=SrcPort, 3=DstPort, 4=Proto
+pub(crate) enum Next {
+    Level(Level),
+    /// Rules sharing every field range, in priority order.
+    Rules(Vec<Rule>),
 }
 
-impl IntervalTree {
-    fn get_range(rule: &Rule, field_idx: usize) -> Range<u32> {
-        match field_idx {
-            0 => Range {
-                min: rule.src_ip.min,
-                max: rule.src_ip.max,
-            },
-            1 => Range {
-                min: rule.dst_ip.min,
-                max: rule.dst_ip.max,
-            },
-            2 => Range {
-                min: rule.src_port.min as u32,
-                max: rule.src_port.max as u32,
-            },
-            3 => Range {
-                min: rule.dst_port.min as u32,
-                max: rule.dst_port.max as u32,
-            },
-            4 => Range {
-                min: rule.proto.min as u32,
-                max: rule.proto.max as u32,
-            },
-            _ => panic!("Invalid field index"),
-        }
+impl SortableTree {
+    /// Build a tree over rules already known to be sortable under `order`.
+    pub(crate) fn new(order: [Dimension; Dimension::COUNT], root: Level) -> Self {
+        let mut tree = Self {
+            order,
+            root,
+            len: 0,
+            best: u32::MAX,
+        };
+        let (len, best) = tree.rules().fold((0, u32::MAX), |(len, best), r| {
+            (len + 1, best.min(r.priority))
+        });
+        tree.len = len;
+        tree.best = best;
+        tree
     }
 
-    pub fn build(rules: Vec<Rule>, field_idx: usize) -> Self {
-        let root = Self::build_recursive(rules, field_idx);
-        Self {
-            root: Some(Box::new(root)),
-            field_idx,
-        }
+    /// Field order of the levels.
+    pub fn order(&self) -> [Dimension; Dimension::COUNT] {
+        self.order
     }
 
-    fn build_recursive(rules: Vec<Rule>, field_idx: usize) -> Node {
-        if rules.is_empty() {
-            return Node::new(0, Vec::new()); // Dummy empty node? Or handle Option higher up.
-        }
+    /// Number of rules.
+    pub fn len(&self) -> usize {
+        self.len
+    }
 
-        // 1. Find center point (median of all endpoints) to balance the tree
-        let mut endpoints = Vec::with_capacity(rules.len() * 2);
-        for rule in &rules {
-            let range = Self::get_range(rule, field_idx);
-            endpoints.push(range.min);
-            endpoints.push(range.max);
-        }
-        endpoints.sort_unstable();
-        let center = endpoints[endpoints.len() / 2];
-
-        let mut left_rules = Vec::new();
-        let mut right_rules = Vec::new();
-        let mut center_rules = Vec::new();
-
-        for rule in rules {
-            let range = Self::get_range(&rule, field_idx);
-            if range.max < center {
-                left_rules.push(rule);
-            } else if range.min > center {
-                right_rules.push(rule);
-            } else {
-                // Overlaps center
-                center_rules.push(rule);
-            }
-        }
+    /// Returns true if the tree holds no rule.
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Best (lowest) priority of the rules, `u32::MAX` if empty.
+    pub fn best_priority(&self) -> u32 {
+        self.best
+    }
 
-        let mut node = Node::new(center, center_rules);
+    pub(crate) fn root(&self) -> &Level {
+        &self.root
+    }
 
-        if !left_rules.is_empty() {
-            node.left = Some(Box::new(Self::build_recursive(left_rules, field_idx)));
+    /// Every rule, level by level.
+    pub fn rules(&self) -> impl Iterator<Item = &Rule> + '_ {
+        let mut stack = alloc::vec![&self.root];
+        let mut leaves: Vec<&[Rule]> = Vec::new();
+        while let Some(level) = stack.pop() {
+            for next in &level.next {
+                match next {
+                    Next::Level(below) => stack.push(below),
+                    Next::Rules(rules) => leaves.push(rules),
+                }
+            }
         }
-        if !right_rules.is_empty() {
-            node.right = Some(Box::new(Self::build_recursive(right_rules, field_idx)));
+        leaves.into_iter().flatten()
+    }
+
+    /// Highest-priority rule matching `packet`.
+    pub fn lookup(&self, packet: &FiveTuple, stats: &mut LookupStats) -> Option<&Rule> {
+        let mut level = &self.root;
+        for dim in self.order {
+            stats.depth += 1;
+            let val = dim.packet_value(packet);
+            let i = level
+                .ranges
+                .partition_point(|r| r.min <= val)
+                .checked_sub(1)?;
+            if level.ranges[i].max < val {
+                return None;
+            }
+            match &level.next[i] {
+                Next::Level(below) => level = below,
+                Next::Rules(rules) => {
+                    // The path matched every field; only the zone is left.
+                    return rules.iter().find(|rule| {
+                        stats.rules_compared += 1;
+                        rule.zone.contains(packet.zone)
+                    });
+                }
+            }
         }
+        None
+    }
 
-        node
+    /// Release spare capacity.
+    pub fn shrink_to_fit(&mut self) {
+        let mut stack = alloc::vec![&mut self.root];
+        while let Some(level) = stack.pop() {
+            level.ranges.shrink_to_fit();
+            level.next.shrink_to_fit();
+            for next in &mut level.next {
+                match next {
+                    Next::Level(below) => stack.push(below),
+                    Next::Rules(rules) => rules.shrink_to_fit(),
+                }
+            }
+        }
     }
+}
 
-    pub fn classify_packet<'a>(
-        &'a self,
-        packet: &crate::packet::FiveTuple,
-        val: u32,
-    ) -> Option<&'a Rule> {
-        self.root
-            .as_ref()
-            .and_then(|root| Self::query_recursive_packet(root, packet, val))
+/// Split plain `rules` into sortable partitions, greedily: each partition
+/// takes, among the remaining rules in priority order, every rule that stays
+/// sortable with those already taken, under the field order that takes the
+/// most rules. Partitions come out by best priority.
+pub fn partition(rules: &[Rule]) -> Vec<SortableTree> {
+    let mut remaining = rules.to_vec();
+    remaining.sort_by_key(|r| r.priority);
+    let mut partitions = Vec::new();
+    while !remaining.is_empty() {
+        let mut best: Option<([Dimension; Dimension::COUNT], Draft, Vec<bool>, usize)> = None;
+        for first in Dimension::ALL {
+            // `first`, then the other fields in 5-tuple order.
+            let mut order = [first; Dimension::COUNT];
+            let rest = Dimension::ALL.into_iter().filter(|&d| d != first);
+            for (slot, dim) in order[1..].iter_mut().zip(rest) {
+                *slot = dim;
+            }
+            let mut draft = Draft::default();
+            let mut taken = alloc::vec![false; remaining.len()];
+            let mut count = 0;
+            for (i, rule) in remaining.iter().enumerate() {
+                if draft.fits(rule, &order) {
+                    draft.insert(rule, i as u32, &order);
+                    taken[i] = true;
+                    count += 1;
+                }
+            }
+            if best.as_ref().is_none_or(|b| count > b.3) {
+                best = Some((order, draft, taken, count));
+            }
+        }
+        let (order, draft, taken, _) = best.expect("five field orders were tried");
+        let root = draft.finish(&remaining);
+        partitions.push(SortableTree::new(order, root));
+        let mut i = 0;
+        remaining.retain(|_| {
+            i += 1;
+            !taken[i - 1]
+        });
     }
+    partitions
+}
 
-    fn query_recursive_packet<'a>(
-        node: &'a Node,
-        packet: &crate::packet::FiveTuple,
-        val: u32,
-    ) -> Option<&'a Rule> {
-        let mut best_match: Option<&Rule> = None;
-
-        // Scan current node's overlap list
-        for rule in &node.rules {
-            if rule.matches(packet) {
-                match best_match {
-                    None => best_match = Some(rule),
-                    Some(best) => {
-                        if rule.priority < best.priority {
-                            best_match = Some(rule);
-                        }
+/// A `Level` under construction, holding indices of the rules.
+#[derive(Default)]
+struct Draft {
+    /// Range start to range end and what lies below.
+    entries: BTreeMap<u32, (u32, DraftNext)>,
+}
+
+enum DraftNext {
+    Level(Draft),
+    Rules(Vec<u32>),
+}
+
+impl Draft {
+    /// Returns true if `rule` keeps the draft sortable under `order`.
+    fn fits(&self, rule: &Rule, order: &[Dimension]) -> bool {
+        let range = order[0].rule_range(rule);
+        // Ranges are disjoint, so only the last one starting before the end
+        // of `range` can overlap it.
+        match self.entries.range(..=range.max).next_back() {
+            Some((&min, (max, next))) if *max >= range.min => {
+                (min, *max) == (range.min, range.max)
+                    && match next {
+                        DraftNext::Level(below) => below.fits(rule, &order[1..]),
+                        DraftNext::Rules(_) => true,
                     }
-                }
             }
+            _ => true,
         }
+    }
 
-        // Check children based on value relative to center
-        let child_match = if val < node.center {
-            node.left
-                .as_ref()
-                .and_then(|n| Self::query_recursive_packet(n, packet, val))
-        } else if val > node.center {
-            node.right
-                .as_ref()
-                .and_then(|n| Self::query_recursive_packet(n, packet, val))
-        } else {
-            None
-        };
+    /// Add rule `idx` (which `fits`).
+    fn insert(&mut self, rule: &Rule, idx: u32, order: &[Dimension]) {
+        let range = order[0].rule_range(rule);
+        let (_, next) = self.entries.entry(range.min).or_insert_with(|| {
+            let next = if order.len() == 1 {
+                DraftNext::Rules(Vec::new())
+            } else {
+                DraftNext::Level(Draft::default())
+            };
+            (range.max, next)
+        });
+        match next {
+            DraftNext::Level(below) => below.insert(rule, idx, &order[1..]),
+            DraftNext::Rules(rules) => rules.push(idx),
+        }
+    }
 
-        match (best_match, child_match) {
-            (Some(b), Some(c)) => {
-                if b.priority < c.priority {
-                    Some(b)
-                } else {
-                    Some(c)
+    fn finish(self, rules: &[Rule]) -> Level {
+        let mut level = Level::default();
+        for (min, (max, next)) in self.entries {
+            level.ranges.push(Range::new(min, max));
+            level.next.push(match next {
+                DraftNext::Level(below) => Next::Level(below.finish(rules)),
+                DraftNext::Rules(idx) => {
+                    Next::Rules(idx.iter().map(|&i| rules[i as usize].clone()).collect())
                 }
-            }
-            (Some(b), None) => Some(b),
-            (None, Some(c)) => Some(c),
-            (None, None) => None,
+            });
         }
+        level
     }
 }
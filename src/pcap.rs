@@ -0,0 +1,164 @@
+//! Packet traces from pcap captures.
+//!
+//! Reads the classic libpcap file format (microsecond and nanosecond
+//! variants, either byte order) from memory and extracts the 5-tuple of every
+//! IPv4 packet, so real captures can be replayed against a classifier.
+//! Ethernet (with optional 802.1Q tags) and raw-IP link types are supported;
+//! other frames (ARP, IPv6, ...) are skipped.
+
+use crate::packet::{FiveTuple, Ipv4Bytes, PacketKey};
+use alloc::vec::Vec;
+
+/// Ethernet link type.
+pub const LINKTYPE_ETHERNET: u32 = 1;
+/// Raw IPv4/IPv6 link type.
+pub const LINKTYPE_RAW: u32 = 101;
+
+const MAGIC_MICROS: u32 = 0xA1B2_C3D4;
+const MAGIC_NANOS: u32 = 0xA1B2_3C4D;
+const ETHERTYPE_IPV4: u16 = 0x0800;
+const ETHERTYPE_VLAN: u16 = 0x8100;
+
+/// Error while reading a capture.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PcapError {
+    /// Not a pcap file (pcapng is not supported).
+    BadMagic,
+    /// Input ended in the middle of a header or record.
+    Truncated,
+    /// Link type other than Ethernet or raw IP.
+    UnsupportedLinkType(u32),
+}
+
+/// One captured frame.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PcapRecord<'a> {
+    /// Capture timestamp, in nanoseconds since the epoch.
+    pub timestamp_ns: u64,
+    /// Captured bytes (possibly truncated to the snap length).
+    pub data: &'a [u8],
+}
+
+/// Iterator over the records of an in-memory pcap file.
+#[derive(Debug, Clone)]
+pub struct PcapReader<'a> {
+    data: &'a [u8],
+    pos: usize,
+    big_endian: bool,
+    nanos: bool,
+    link_type: u32,
+}
+
+impl<'a> PcapReader<'a> {
+    /// Parse the global header.
+    pub fn new(data: &'a [u8]) -> Result<Self, PcapError> {
+        let magic = data.get(..4).ok_or(PcapError::Truncated)?;
+        let le = u32::from_le_bytes([magic[0], magic[1], magic[2], magic[3]]);
+        let be = u32::from_be_bytes([magic[0], magic[1], magic[2], magic[3]]);
+        let (big_endian, nanos) = match (le, be) {
+            (MAGIC_MICROS, _) => (false, false),
+            (MAGIC_NANOS, _) => (false, true),
+            (_, MAGIC_MICROS) => (true, false),
+            (_, MAGIC_NANOS) => (true, true),
+            _ => return Err(PcapError::BadMagic),
+        };
+        let mut reader = Self {
+            data,
+            pos: 0,
+            big_endian,
+            nanos,
+            link_type: 0,
+        };
+        reader.link_type = reader.u32_at(20)?;
+        reader.pos = 24;
+        Ok(reader)
+    }
+
+    /// Link type of the capture.
+    pub fn link_type(&self) -> u32 {
+        self.link_type
+    }
+
+    fn u32_at(&self, at: usize) -> Result<u32, PcapError> {
+        let b = self.data.get(at..at + 4).ok_or(PcapError::Truncated)?;
+        let b = [b[0], b[1], b[2], b[3]];
+        Ok(if self.big_endian {
+            u32::from_be_bytes(b)
+        } else {
+            u32::from_le_bytes(b)
+        })
+    }
+}
+
+impl<'a> Iterator for PcapReader<'a> {
+    type Item = Result<PcapRecord<'a>, PcapError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.pos >= self.data.len() {
+            return None;
+        }
+        let record = (|| {
+            let secs = self.u32_at(self.pos)? as u64;
+            let frac = self.u32_at(self.pos + 4)? as u64;
+            let incl_len = self.u32_at(self.pos + 8)? as usize;
+            let start = self.pos + 16;
+            let data = self
+                .data
+                .get(start..start + incl_len)
+                .ok_or(PcapError::Truncated)?;
+            self.pos = start + incl_len;
+            let frac_ns = if self.nanos { frac } else { frac * 1000 };
+            Ok(PcapRecord {
+                timestamp_ns: secs * 1_000_000_000 + frac_ns,
+                data,
+            })
+        })();
+        if record.is_err() {
+            // Stop after the first error.
+            self.pos = self.data.len();
+        }
+        Some(record)
+    }
+}
+
+/// 5-tuple of an IPv4 frame of the given link type, if it is one.
+///
+/// The port fields hold the TCP/UDP ports, the ICMP type and code or the IGMP
+/// type (see `packet::L4Key`), and 0 for other protocols and for fragments
+/// other than the first.
+pub fn extract_five_tuple(link_type: u32, frame: &[u8]) -> Option<FiveTuple> {
+    let ip = match link_type {
+        LINKTYPE_ETHERNET => {
+            let mut offset = 12;
+            let mut ethertype = u16::from_be_bytes([*frame.get(offset)?, *frame.get(offset + 1)?]);
+            while ethertype == ETHERTYPE_VLAN {
+                offset += 4;
+                ethertype = u16::from_be_bytes([*frame.get(offset)?, *frame.get(offset + 1)?]);
+            }
+            if ethertype != ETHERTYPE_IPV4 {
+                return None;
+            }
+            frame.get(offset + 2..)?
+        }
+        LINKTYPE_RAW => frame,
+        _ => return None,
+    };
+
+    Ipv4Bytes::new(ip).map(|ip| ip.to_five_tuple())
+}
+
+/// 5-tuples of all IPv4 packets in an in-memory pcap file, in capture order.
+pub fn read_five_tuples(data: &[u8]) -> Result<Vec<FiveTuple>, PcapError> {
+    let reader = PcapReader::new(data)?;
+    let link_type = reader.link_type();
+    if link_type != LINKTYPE_ETHERNET && link_type != LINKTYPE_RAW {
+        return Err(PcapError::UnsupportedLinkType(link_type));
+    }
+    let mut tuples = Vec::new();
+    for record in reader {
+        if let Some(tuple) = extract_five_tuple(link_type, record?.data) {
+            tuples.push(tuple);
+        }
+    }
+    Ok(tuples)
+}
@@ -0,0 +1,257 @@
+//! Prefix utilities.
+//!
+//! Range-to-prefix decomposition, prefix containment/overlap tests and
+//! prefix-to-range conversion for every field width used by the crate,
+//! including 128-bit values for IPv6 addresses.
+
+use crate::rule::Range;
+use alloc::vec::Vec;
+
+/// Represents a Prefix: value/len
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Prefix<T> {
+    pub value: T,
+    pub len: u32,
+}
+
+/// Unsigned integer types that prefixes can be built on.
+pub trait PrefixValue: Copy + Ord {
+    /// Width of the type in bits.
+    const BITS: u32;
+    /// Widen to `u128`.
+    fn to_u128(self) -> u128;
+    /// Narrow from `u128` (the value must fit).
+    fn from_u128(v: u128) -> Self;
+}
+
+macro_rules! impl_prefix_value {
+    ($($t:ty),*) => {
+        $(impl PrefixValue for $t {
+            const BITS: u32 = <$t>::BITS;
+            fn to_u128(self) -> u128 {
+                self as u128
+            }
+            fn from_u128(v: u128) -> Self {
+                v as $t
+            }
+        })*
+    };
+}
+
+impl_prefix_value!(u8, u16, u32, u64, u128);
+
+impl<T: PrefixValue> Prefix<T> {
+    /// Create a prefix, clearing the host bits of `value`.
+    pub fn new(value: T, len: u32) -> Self {
+        let len = len.min(T::BITS);
+        Self {
+            value: T::from_u128(value.to_u128() & Self::mask(len)),
+            len,
+        }
+    }
+
+    /// Network mask of a `len`-bit prefix, as a `u128` restricted to `T::BITS`.
+    fn mask(len: u32) -> u128 {
+        let full = if T::BITS == 128 {
+            u128::MAX
+        } else {
+            (1u128 << T::BITS) - 1
+        };
+        if len == 0 {
+            0
+        } else if len >= T::BITS {
+            full
+        } else {
+            full & !(full >> len)
+        }
+    }
+
+    /// Smallest value covered by the prefix.
+    pub fn first(&self) -> T {
+        T::from_u128(self.value.to_u128() & Self::mask(self.len))
+    }
+
+    /// Largest value covered by the prefix.
+    pub fn last(&self) -> T {
+        let full = if T::BITS == 128 {
+            u128::MAX
+        } else {
+            (1u128 << T::BITS) - 1
+        };
+        T::from_u128(self.first().to_u128() | (full & !Self::mask(self.len)))
+    }
+
+    /// The inclusive range covered by the prefix.
+    pub fn to_range(&self) -> Range<T> {
+        Range::new(self.first(), self.last())
+    }
+
+    /// Returns true if `value` falls inside the prefix.
+    pub fn contains(&self, value: T) -> bool {
+        value.to_u128() & Self::mask(self.len) == self.first().to_u128()
+    }
+
+    /// Returns true if `other` is entirely inside `self`.
+    pub fn covers(&self, other: &Prefix<T>) -> bool {
+        self.len <= other.len && self.contains(other.first())
+    }
+
+    /// Returns true if the two prefixes share at least one value.
+    ///
+    /// Prefixes either nest or are disjoint, so this is `covers` in either direction.
+    pub fn overlaps(&self, other: &Prefix<T>) -> bool {
+        self.covers(other) || other.covers(self)
+    }
+}
+
+/// Returns true if `range` is exactly one prefix.
+pub fn is_prefix<T: PrefixValue>(range: &Range<T>) -> bool {
+    range_to_prefixes(range.min, range.max).len() == 1
+}
+
+/// Decompose a range of any supported width into a minimal set of prefixes.
+pub fn range_to_prefixes<T: PrefixValue>(min: T, max: T) -> Vec<Prefix<T>> {
+    range_to_prefixes_u128(min.to_u128(), max.to_u128(), T::BITS)
+        .into_iter()
+        .map(|p| Prefix {
+            value: T::from_u128(p.value),
+            len: p.len,
+        })
+        .collect()
+}
+
+/// Decompose a range `[min, max]` of a `bits`-wide field (up to 128, e.g. IPv6) into prefixes.
+pub fn range_to_prefixes_u128(min: u128, max: u128, bits: u32) -> Vec<Prefix<u128>> {
+    let mut prefixes = Vec::new();
+    if min > max {
+        return prefixes;
+    }
+
+    let mut current = min;
+    loop {
+        // Largest aligned block starting at `current` that stays within `max`.
+        let align = current.trailing_zeros().min(bits);
+        let mut host_bits = align;
+        while host_bits > 0 {
+            let fits = if host_bits == 128 {
+                max == u128::MAX
+            } else {
+                current
+                    .checked_add((1u128 << host_bits) - 1)
+                    .is_some_and(|last| last <= max)
+            };
+            if fits {
+                break;
+            }
+            host_bits -= 1;
+        }
+
+        prefixes.push(Prefix {
+            value: current,
+            len: bits - host_bits,
+        });
+
+        let last = if host_bits == 128 {
+            u128::MAX
+        } else {
+            current + ((1u128 << host_bits) - 1)
+        };
+        if last >= max {
+            break;
+        }
+        current = last + 1;
+    }
+
+    prefixes
+}
+
+/// Decompose a range [min, max] into a minimal set of prefixes.
+pub fn range_to_prefixes_u32(min: u32, max: u32, bits: u32) -> Vec<Prefix<u32>> {
+    let mut prefixes = Vec::new();
+
+    // If range is invalid
+    if min > max {
+        return prefixes;
+    }
+
+    let mut current = min;
+    while current <= max {
+        // Find the longest prefix starting at `current` that is within [current, max]
+
+        // Try decreasing length (increasing size)
+        // A prefix of length L covers 2^(bits - L) addresses.
+        // It's valid if:
+        // 1. mask(current, L) == current (start alignment)
+        // 2. current + size - 1 <= max (end alignment)
+
+        // Optimization: start checking from the max possible size based on alignment
+        // The number of trailing zeros defines the max size alignment.
+        let trailing_zeros = current.trailing_zeros();
+        // The prefix length corresponding to 'trailing_zeros' size would be (bits - trailing_zeros).
+        // e.g., if trailing_zeros = 2 (size 4), bits=32, len could be 30.
+        // We can't have a size larger than what alignment allows.
+
+        let mut best_len = bits;
+
+        // Iterating from largest valid block size down to 0 (which is length bits to 0)
+        // We actually want smallest length (largest block).
+        // Max possible block size based on alignment: 1 << trailing_zeros
+        // We also are bounded by 'max'.
+
+        // Iterate len from (bits - trailing_zeros) down to 0? No, up to bits.
+        // Smallest len = 0 (size 2^32), Largest len = 32 (size 1).
+
+        // Start with the alignment constraint
+        let alignment_len = bits.saturating_sub(trailing_zeros);
+
+        // We also need to fit in [current, max].
+        // Let's iterate len from alignment_len to 32.
+        // We want the minimal valid len (maximum size).
+
+        for l in alignment_len..=bits {
+            let size = 1u64 << (bits - l);
+            // Check if fits
+            if (current as u64) + size - 1 <= (max as u64) {
+                best_len = l;
+                break;
+            }
+        }
+
+        prefixes.push(Prefix {
+            value: current,
+            len: best_len,
+        });
+
+        let size = 1u64 << (bits - best_len);
+        let next = (current as u64) + size;
+        if next > (max as u64) {
+            break;
+        }
+        current = next as u32;
+    }
+
+    prefixes
+}
+
+/// Decompose a u16 range (Ports)
+pub fn range_to_prefixes_u16(min: u16, max: u16) -> Vec<Prefix<u16>> {
+    let p32 = range_to_prefixes_u32(min as u32, max as u32, 16);
+    p32.into_iter()
+        .map(|p| Prefix {
+            value: p.value as u16,
+            len: p.len,
+        })
+        .collect()
+}
+
+/// Decompose a u8 range (Proto)
+pub fn range_to_prefixes_u8(min: u8, max: u8) -> Vec<Prefix<u8>> {
+    let p32 = range_to_prefixes_u32(min as u32, max as u32, 8);
+    p32.into_iter()
+        .map(|p| Prefix {
+            value: p.value as u8,
+            len: p.len,
+        })
+        .collect()
+}
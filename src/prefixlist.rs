@@ -0,0 +1,217 @@
+//! Bulk prefix lists fronting a classifier.
+//!
+//! GeoIP, bogon or reputation lists hold hundreds of thousands of prefixes
+//! sharing one action. Turned into 5-tuple rules they would dominate every
+//! tree build; instead `PrefixList::compile` reduces such a list to a
+//! dedicated lookup structure and `PrefixListFront` checks the lists before
+//! the main classifier.
+//!
+//! With a single action, longest-prefix match degenerates into membership:
+//! the compiler sorts the prefixes and merges overlapping or adjacent ones
+//! into disjoint ranges, so a lookup is one binary search over at most as
+//! many boundaries as there are prefixes (often far fewer, as feeds are
+//! highly aggregatable).
+
+use crate::classifier::{Classifier, LookupStats};
+use crate::dimension::Dimension;
+use crate::geometry::Region;
+use crate::packet::FiveTuple;
+use crate::prefix::Prefix;
+use crate::query::{Overlapping, RegionQuery};
+use crate::rule::{Action, Range, Rule, ANY_ZONE};
+use alloc::vec::Vec;
+
+/// Packet address a prefix list is matched against.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum ListField {
+    /// The source address.
+    Source,
+    /// The destination address.
+    Destination,
+    /// Either address.
+    Either,
+}
+
+/// A compiled prefix list and the action taken when it matches.
+#[derive(Debug, Clone)]
+pub struct PrefixList {
+    field: ListField,
+    /// Start of each disjoint range, ascending.
+    starts: Vec<u32>,
+    /// Inclusive end of each range.
+    ends: Vec<u32>,
+    /// Rule standing for the whole list, returned by lookups that hit it.
+    rule: Rule,
+}
+
+impl PrefixList {
+    /// Compile `prefixes` into a list matched on `field`.
+    ///
+    /// `id` and `action` describe the list as a rule: lookups that hit the
+    /// list return a wildcard rule carrying them.
+    pub fn compile(
+        id: u32,
+        prefixes: impl IntoIterator<Item = Prefix<u32>>,
+        field: ListField,
+        action: Action,
+    ) -> Self {
+        let mut ranges: Vec<Range<u32>> = prefixes.into_iter().map(|p| p.to_range()).collect();
+        ranges.sort_unstable_by_key(|r| r.min);
+
+        let mut starts: Vec<u32> = Vec::with_capacity(ranges.len());
+        let mut ends: Vec<u32> = Vec::with_capacity(ranges.len());
+        for range in ranges {
+            match ends.last_mut() {
+                // Overlapping or adjacent: extend the previous range.
+                Some(end) if range.min <= end.saturating_add(1) => {
+                    *end = (*end).max(range.max);
+                }
+                _ => {
+                    starts.push(range.min);
+                    ends.push(range.max);
+                }
+            }
+        }
+        starts.shrink_to_fit();
+        ends.shrink_to_fit();
+
+        Self {
+            field,
+            starts,
+            ends,
+            rule: Rule {
+                id,
+                priority: 0,
+                src_ip: Range::any(0, u32::MAX),
+                dst_ip: Range::any(0, u32::MAX),
+                src_port: Range::any(0, u16::MAX),
+                dst_port: Range::any(0, u16::MAX),
+                proto: Range::any(0, u8::MAX),
+                zone: ANY_ZONE,
+                bidirectional: false,
+                field_sets: None,
+                action,
+            },
+        }
+    }
+
+    /// Returns true if `addr` is covered by a prefix of the list.
+    pub fn contains(&self, addr: u32) -> bool {
+        let i = self.starts.partition_point(|&s| s <= addr);
+        i > 0 && addr <= self.ends[i - 1]
+    }
+
+    /// Returns true if some address of `range` is covered by the list.
+    pub fn intersects(&self, range: Range<u32>) -> bool {
+        let i = self.starts.partition_point(|&s| s <= range.max);
+        i > 0 && range.min <= self.ends[i - 1]
+    }
+
+    /// Returns true if some packet of `region` hits the list.
+    pub fn overlaps(&self, region: &Region) -> bool {
+        let src = || self.intersects(region.get(Dimension::SrcIp));
+        let dst = || self.intersects(region.get(Dimension::DstIp));
+        match self.field {
+            ListField::Source => src(),
+            ListField::Destination => dst(),
+            ListField::Either => src() || dst(),
+        }
+    }
+
+    /// Returns true if the packet's address(es) hit the list.
+    pub fn matches(&self, packet: &FiveTuple) -> bool {
+        match self.field {
+            ListField::Source => self.contains(packet.src_ip),
+            ListField::Destination => self.contains(packet.dst_ip),
+            ListField::Either => self.contains(packet.src_ip) || self.contains(packet.dst_ip),
+        }
+    }
+
+    /// Rule reported for packets hitting the list.
+    pub fn rule(&self) -> &Rule {
+        &self.rule
+    }
+
+    /// Field the list is matched on.
+    pub fn field(&self) -> ListField {
+        self.field
+    }
+
+    /// Number of disjoint ranges left after merging.
+    pub fn range_count(&self) -> usize {
+        self.starts.len()
+    }
+}
+
+/// Classifier checking prefix lists, in order, before a main classifier.
+///
+/// A list hit takes precedence over every rule of the main classifier, the way
+/// bogon and blocklist filters sit in front of a firewall policy.
+pub struct PrefixListFront<C> {
+    lists: Vec<PrefixList>,
+    main: C,
+}
+
+impl<C: Classifier> PrefixListFront<C> {
+    /// Front `main` with `lists`.
+    pub fn new(lists: Vec<PrefixList>, main: C) -> Self {
+        Self { lists, main }
+    }
+
+    /// The prefix lists, in the order they are checked.
+    pub fn lists(&self) -> &[PrefixList] {
+        &self.lists
+    }
+
+    /// The classifier consulted when no list matches.
+    pub fn main(&self) -> &C {
+        &self.main
+    }
+
+    fn list_hit(&self, packet: &FiveTuple) -> Option<&PrefixList> {
+        self.lists.iter().find(|list| list.matches(packet))
+    }
+}
+
+impl<C: Classifier> Classifier for PrefixListFront<C> {
+    /// Build the main classifier with no lists in front.
+    fn build(rules: &[Rule]) -> Self {
+        Self::new(Vec::new(), C::build(rules))
+    }
+
+    fn classify_rule(&self, packet: &FiveTuple) -> Option<&Rule> {
+        match self.list_hit(packet) {
+            Some(list) => Some(&list.rule),
+            None => self.main.classify_rule(packet),
+        }
+    }
+
+    fn classify_with_stats(&self, packet: &FiveTuple) -> (Option<Action>, LookupStats) {
+        match self.lists.iter().position(|list| list.matches(packet)) {
+            Some(i) => (
+                Some(self.lists[i].rule.action),
+                LookupStats {
+                    tables_probed: i as u32 + 1,
+                    ..LookupStats::default()
+                },
+            ),
+            None => {
+                let (action, mut stats) = self.main.classify_with_stats(packet);
+                stats.tables_probed += self.lists.len() as u32;
+                (action, stats)
+            }
+        }
+    }
+}
+
+impl<C: RegionQuery> RegionQuery for PrefixListFront<C> {
+    /// Lists are reported through their rule, like lookups hitting them.
+    fn rules_overlapping(&self, region: &Region) -> Vec<&Rule> {
+        let mut found = Overlapping::new(region);
+        let hits = self.lists.iter().filter(|list| list.overlaps(region));
+        found.extend(hits.map(|list| &list.rule));
+        found.extend(self.main.rules_overlapping(region));
+        found.finish()
+    }
+}
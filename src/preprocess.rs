@@ -0,0 +1,263 @@
+//! Rule-set preprocessing shared by the tree builders.
+//!
+//! Wildcard-heavy rules (e.g. "any src, any port, proto IGMP" or the final
+//! default rule) overlap every cut and are the main cause of rule duplication
+//! in decision trees. `split_wildcards` separates them from the specific rules
+//! so only the latter go through tree construction, while `WildcardSplit`
+//! serves the extracted ones from a small sidecar classifier.
+//!
+//! The protocol field is nearly always exact (TCP, UDP) or a wildcard, which
+//! makes it a poor cut dimension but an ideal first-level partition:
+//! `ProtoPartitioned` builds one classifier per protocol class (TCP, UDP,
+//! everything else) over only the rules that can match it, and dispatches each
+//! packet on its protocol before descending a much smaller tree.
+
+use crate::classifier::{Classifier, LookupStats};
+use crate::dimension::{Dimension, FIELDS};
+use crate::freeze::Freeze;
+use crate::geometry::Region;
+use crate::linear::LinearClassifier;
+use crate::packet::{FiveTuple, PROTO_TCP, PROTO_UDP};
+use crate::query::{Overlapping, RegionQuery};
+use crate::rule::{expand_rules, Action, Range, Rule};
+use alloc::vec::Vec;
+use hashbrown::HashMap;
+
+/// Default minimum number of wildcard fields for a rule to be extracted.
+pub const DEFAULT_MIN_WILDCARDS: usize = 3;
+
+/// Number of fields of `rule` that cover their whole domain.
+pub fn wildcard_count(rule: &Rule) -> usize {
+    FIELDS
+        .iter()
+        .filter(|field| {
+            let range = (field.rule_range)(rule);
+            range.min == 0 && range.max == field.max_value()
+        })
+        .count()
+}
+
+/// Split rules into `(specific, wildcard_heavy)`, where wildcard-heavy rules
+/// have at least `min_wildcards` wildcard fields. Relative order is preserved.
+pub fn split_wildcards(rules: &[Rule], min_wildcards: usize) -> (Vec<Rule>, Vec<Rule>) {
+    rules
+        .iter()
+        .cloned()
+        .partition(|r| wildcard_count(r) < min_wildcards)
+}
+
+/// Classifier serving specific rules from a tree and wildcard-heavy rules from a sidecar.
+///
+/// Both parts are queried and the higher-priority match wins, rules of equal
+/// priority winning in the order they were given to `build`, so results are
+/// identical to building a single `LinearClassifier` or decision tree over the
+/// whole rule set.
+pub struct WildcardSplit<C, S = LinearClassifier> {
+    main: C,
+    sidecar: S,
+    extracted: usize,
+    /// Position of the first rule with each id in the rules given to `build`,
+    /// to break priority ties between the two parts.
+    order: HashMap<u32, usize>,
+}
+
+impl<C: Classifier, S: Classifier> WildcardSplit<C, S> {
+    /// Build with a custom extraction threshold.
+    pub fn with_threshold(rules: &[Rule], min_wildcards: usize) -> Self {
+        let (specific, wildcards) = split_wildcards(rules, min_wildcards);
+        let mut order = HashMap::with_capacity(rules.len());
+        for (position, rule) in rules.iter().enumerate() {
+            order.entry(rule.id).or_insert(position);
+        }
+        Self {
+            main: C::build(&specific),
+            sidecar: S::build(&wildcards),
+            extracted: wildcards.len(),
+            order,
+        }
+    }
+
+    /// Number of rules routed to the sidecar.
+    pub fn extracted(&self) -> usize {
+        self.extracted
+    }
+
+    /// The classifier holding the specific rules.
+    pub fn main(&self) -> &C {
+        &self.main
+    }
+
+    /// The sidecar classifier holding the wildcard-heavy rules.
+    pub fn sidecar(&self) -> &S {
+        &self.sidecar
+    }
+
+    /// Pick the better of the two parts' matches: the lower priority, then
+    /// the rule given first to `build`.
+    fn better<'a>(&self, a: Option<&'a Rule>, b: Option<&'a Rule>) -> Option<&'a Rule> {
+        match (a, b) {
+            (Some(x), Some(y)) => {
+                let rank = |r: &Rule| (r.priority, self.order.get(&r.id).copied());
+                Some(if rank(y) < rank(x) { y } else { x })
+            }
+            (x, None) => x,
+            (None, y) => y,
+        }
+    }
+}
+
+impl<C: Classifier, S: Classifier> Classifier for WildcardSplit<C, S> {
+    fn build(rules: &[Rule]) -> Self {
+        Self::with_threshold(rules, DEFAULT_MIN_WILDCARDS)
+    }
+
+    fn classify_rule(&self, packet: &FiveTuple) -> Option<&Rule> {
+        self.better(
+            self.main.classify_rule(packet),
+            self.sidecar.classify_rule(packet),
+        )
+    }
+
+    fn classify_with_stats(&self, packet: &FiveTuple) -> (Option<Action>, LookupStats) {
+        let (rule, stats) = self.classify_rule_with_stats(packet);
+        (rule.map(|r| r.action), stats)
+    }
+
+    fn classify_rule_with_stats(&self, packet: &FiveTuple) -> (Option<&Rule>, LookupStats) {
+        let (a, main) = self.main.classify_rule_with_stats(packet);
+        let (b, side) = self.sidecar.classify_rule_with_stats(packet);
+        let stats = LookupStats {
+            depth: main.depth + side.depth,
+            rules_compared: main.rules_compared + side.rules_compared,
+            tables_probed: main.tables_probed + side.tables_probed,
+        };
+        (self.better(a, b), stats)
+    }
+}
+
+impl<C: RegionQuery, S: RegionQuery> RegionQuery for WildcardSplit<C, S> {
+    fn rules_overlapping(&self, region: &Region) -> Vec<&Rule> {
+        let mut found = Overlapping::new(region);
+        found.extend(self.main.rules_overlapping(region));
+        found.extend(self.sidecar.rules_overlapping(region));
+        found.finish()
+    }
+}
+
+/// Classifier partitioned by protocol: one inner classifier for TCP, one for
+/// UDP and one for every other protocol.
+///
+/// Rules with a wildcard (or wide) protocol go to every partition they can
+/// match; results are identical to a single classifier over all the rules.
+pub struct ProtoPartitioned<C> {
+    tcp: C,
+    udp: C,
+    other: C,
+    sizes: [usize; 3],
+}
+
+impl<C: Classifier> ProtoPartitioned<C> {
+    /// Partition `rules` and build each part with `build`, e.g. to use a
+    /// configured tree builder:
+    /// `ProtoPartitioned::build_with(&rules, |r| HyperSplitClassifier::from_builder(&b, r))`.
+    pub fn build_with(rules: &[Rule], build: impl Fn(&[Rule]) -> C) -> Self {
+        let rules = expand_rules(rules);
+        let [tcp, udp, other] = partition_by_proto(&rules);
+        Self {
+            sizes: [tcp.len(), udp.len(), other.len()],
+            tcp: build(&tcp),
+            udp: build(&udp),
+            other: build(&other),
+        }
+    }
+
+    /// Number of rules in the TCP, UDP and other partitions.
+    pub fn partition_sizes(&self) -> [usize; 3] {
+        self.sizes
+    }
+
+    /// The classifier for TCP packets.
+    pub fn tcp(&self) -> &C {
+        &self.tcp
+    }
+
+    /// The classifier for UDP packets.
+    pub fn udp(&self) -> &C {
+        &self.udp
+    }
+
+    /// The classifier for packets of any other protocol.
+    pub fn other(&self) -> &C {
+        &self.other
+    }
+
+    fn partition(&self, proto: u8) -> &C {
+        match proto {
+            PROTO_TCP => &self.tcp,
+            PROTO_UDP => &self.udp,
+            _ => &self.other,
+        }
+    }
+}
+
+/// Split plain `rules` into the rules matching TCP, UDP and any other
+/// protocol. A rule lands in every class its protocol range reaches.
+pub fn partition_by_proto(rules: &[Rule]) -> [Vec<Rule>; 3] {
+    let (mut tcp, mut udp, mut other) = (Vec::new(), Vec::new(), Vec::new());
+    for rule in rules {
+        let proto = rule.proto;
+        if proto.contains(PROTO_TCP) {
+            tcp.push(rule.clone());
+        }
+        if proto.contains(PROTO_UDP) {
+            udp.push(rule.clone());
+        }
+        if proto != Range::exact(PROTO_TCP) && proto != Range::exact(PROTO_UDP) {
+            other.push(rule.clone());
+        }
+    }
+    [tcp, udp, other]
+}
+
+impl<C: Classifier> Classifier for ProtoPartitioned<C> {
+    fn build(rules: &[Rule]) -> Self {
+        Self::build_with(rules, C::build)
+    }
+
+    fn classify_rule(&self, packet: &FiveTuple) -> Option<&Rule> {
+        self.partition(packet.proto).classify_rule(packet)
+    }
+
+    fn classify_with_stats(&self, packet: &FiveTuple) -> (Option<Action>, LookupStats) {
+        let (action, mut stats) = self.partition(packet.proto).classify_with_stats(packet);
+        // The protocol dispatch counts as one level.
+        stats.depth += 1;
+        (action, stats)
+    }
+}
+
+impl<C: RegionQuery> RegionQuery for ProtoPartitioned<C> {
+    fn rules_overlapping(&self, region: &Region) -> Vec<&Rule> {
+        let protos = region.get(Dimension::Proto);
+        let mut found = Overlapping::new(region);
+        if protos.contains(PROTO_TCP as u32) {
+            found.extend(self.tcp.rules_overlapping(region));
+        }
+        if protos.contains(PROTO_UDP as u32) {
+            found.extend(self.udp.rules_overlapping(region));
+        }
+        let only = |p: u8| protos == Range::exact(p as u32);
+        if !only(PROTO_TCP) && !only(PROTO_UDP) {
+            found.extend(self.other.rules_overlapping(region));
+        }
+        found.finish()
+    }
+}
+
+impl<C: Freeze> Freeze for ProtoPartitioned<C> {
+    fn shrink_to_fit(&mut self) {
+        self.tcp.shrink_to_fit();
+        self.udp.shrink_to_fit();
+        self.other.shrink_to_fit();
+    }
+}
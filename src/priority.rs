@@ -0,0 +1,233 @@
+//! Priority assignment helpers.
+//!
+//! Rules are ordered by `priority` (lower value = higher priority). These
+//! helpers derive priorities from list order and re-space crowded priorities
+//! so new rules can later be inserted between existing ones without
+//! renumbering the whole rule set.
+//!
+//! `BandedRules` partitions the priority space into system, user and default
+//! bands so that rules inserted into a band can never outrank rules of a
+//! higher band, whatever classifier the rule set is then built into.
+
+use crate::classifier::Classifier;
+use crate::rule::{Range, Rule};
+use alloc::vec::Vec;
+
+/// Default gap left between consecutive priorities.
+pub const DEFAULT_GAP: u32 = 10;
+
+/// Error returned when priorities do not fit in `u32`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PriorityOverflow;
+
+/// Assign priorities from rule order: the first rule gets `start`, the next
+/// `start + step`, and so on.
+pub fn assign_from_order(
+    rules: &mut [Rule],
+    start: u32,
+    step: u32,
+) -> Result<(), PriorityOverflow> {
+    let last = (rules.len().saturating_sub(1) as u64) * step as u64 + start as u64;
+    if last > u32::MAX as u64 {
+        return Err(PriorityOverflow);
+    }
+    for (i, rule) in rules.iter_mut().enumerate() {
+        rule.priority = start + i as u32 * step;
+    }
+    Ok(())
+}
+
+/// Re-space priorities so distinct values are `gap` apart, starting at `gap`.
+///
+/// Relative order is preserved and rules sharing a priority keep sharing it.
+/// The slice itself is not reordered.
+pub fn respace(rules: &mut [Rule], gap: u32) -> Result<(), PriorityOverflow> {
+    let mut distinct: Vec<u32> = rules.iter().map(|r| r.priority).collect();
+    distinct.sort_unstable();
+    distinct.dedup();
+
+    if distinct.len() as u64 * gap as u64 > u32::MAX as u64 {
+        return Err(PriorityOverflow);
+    }
+    for rule in rules.iter_mut() {
+        let rank = distinct.binary_search(&rule.priority).unwrap_or(0) as u32;
+        rule.priority = (rank + 1) * gap;
+    }
+    Ok(())
+}
+
+/// A free priority strictly between `higher` and `lower` (midpoint), if one exists.
+pub fn priority_between(higher: u32, lower: u32) -> Option<u32> {
+    if lower <= higher || lower - higher < 2 {
+        return None;
+    }
+    Some(higher + (lower - higher) / 2)
+}
+
+/// Returns true if some pair of consecutive distinct priorities leaves no room
+/// for an insertion between them.
+pub fn is_crowded(rules: &[Rule]) -> bool {
+    let mut distinct: Vec<u32> = rules.iter().map(|r| r.priority).collect();
+    distinct.sort_unstable();
+    distinct.dedup();
+    distinct.windows(2).any(|w| w[1] - w[0] < 2)
+}
+
+/// Named priority band, from highest to lowest precedence.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Band {
+    /// Rules owned by the system (anti-spoofing, management access, ...).
+    System,
+    /// Operator/user policy.
+    User,
+    /// Catch-all rules evaluated last.
+    Default,
+}
+
+/// Split of the priority space into three contiguous bands.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BandLayout {
+    user_start: u32,
+    default_start: u32,
+}
+
+impl BandLayout {
+    /// System band `[0, user_start)`, user band `[user_start, default_start)`
+    /// and default band `[default_start, u32::MAX]`.
+    ///
+    /// Returns `None` unless `0 < user_start < default_start`.
+    pub fn new(user_start: u32, default_start: u32) -> Option<Self> {
+        (0 < user_start && user_start < default_start).then_some(Self {
+            user_start,
+            default_start,
+        })
+    }
+
+    /// Priorities belonging to `band`.
+    pub fn range(&self, band: Band) -> Range<u32> {
+        match band {
+            Band::System => Range::new(0, self.user_start - 1),
+            Band::User => Range::new(self.user_start, self.default_start - 1),
+            Band::Default => Range::new(self.default_start, u32::MAX),
+        }
+    }
+
+    /// The band a priority falls in.
+    pub fn band_of(&self, priority: u32) -> Band {
+        if priority < self.user_start {
+            Band::System
+        } else if priority < self.default_start {
+            Band::User
+        } else {
+            Band::Default
+        }
+    }
+}
+
+impl Default for BandLayout {
+    /// 2^24 system priorities, 2^24 default priorities and the rest for users.
+    fn default() -> Self {
+        Self {
+            user_start: 0x0100_0000,
+            default_start: 0xFF00_0000,
+        }
+    }
+}
+
+/// Errors returned by `BandedRules`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BandError {
+    /// The rule's priority lies outside the band it was inserted into.
+    OutOfBand { band: Band, priority: u32 },
+    /// No priority is left at the end of the band.
+    BandFull(Band),
+    /// A rule with this id already exists.
+    DuplicateId(u32),
+}
+
+/// Rule set whose priorities are confined to named bands.
+#[derive(Debug, Clone, Default)]
+pub struct BandedRules {
+    layout: BandLayout,
+    rules: Vec<(Band, Rule)>,
+}
+
+impl BandedRules {
+    /// Create an empty rule set with the given band layout.
+    pub fn new(layout: BandLayout) -> Self {
+        Self {
+            layout,
+            rules: Vec::new(),
+        }
+    }
+
+    /// The band layout.
+    pub fn layout(&self) -> &BandLayout {
+        &self.layout
+    }
+
+    /// Insert `rule` into `band`, keeping its priority.
+    ///
+    /// Fails if the priority is outside the band, so e.g. a user rule can
+    /// never be given a priority that outranks system rules.
+    pub fn insert(&mut self, band: Band, rule: Rule) -> Result<(), BandError> {
+        if !self.layout.range(band).contains(rule.priority) {
+            return Err(BandError::OutOfBand {
+                band,
+                priority: rule.priority,
+            });
+        }
+        if self.rules.iter().any(|(_, r)| r.id == rule.id) {
+            return Err(BandError::DuplicateId(rule.id));
+        }
+        self.rules.push((band, rule));
+        Ok(())
+    }
+
+    /// Append `rule` after every rule already in `band`, `DEFAULT_GAP` past
+    /// the band's lowest priority. Returns the priority assigned.
+    pub fn push(&mut self, band: Band, mut rule: Rule) -> Result<u32, BandError> {
+        let range = self.layout.range(band);
+        let priority = match self.in_band(band).map(|r| r.priority).max() {
+            Some(last) => last
+                .checked_add(DEFAULT_GAP)
+                .filter(|&p| p <= range.max)
+                .ok_or(BandError::BandFull(band))?,
+            None => range.min,
+        };
+        rule.priority = priority;
+        self.insert(band, rule)?;
+        Ok(priority)
+    }
+
+    /// Remove the rule with the given id, returning it and its band.
+    pub fn remove(&mut self, id: u32) -> Option<(Band, Rule)> {
+        let idx = self.rules.iter().position(|(_, r)| r.id == id)?;
+        Some(self.rules.remove(idx))
+    }
+
+    /// Band of the rule with the given id.
+    pub fn band(&self, id: u32) -> Option<Band> {
+        self.rules.iter().find(|(_, r)| r.id == id).map(|(b, _)| *b)
+    }
+
+    /// Rules inserted into `band`, in insertion order.
+    pub fn in_band(&self, band: Band) -> impl Iterator<Item = &Rule> {
+        self.rules
+            .iter()
+            .filter(move |(b, _)| *b == band)
+            .map(|(_, r)| r)
+    }
+
+    /// All rules, in priority order (ties keep insertion order).
+    pub fn rules(&self) -> Vec<Rule> {
+        let mut rules: Vec<Rule> = self.rules.iter().map(|(_, r)| r.clone()).collect();
+        rules.sort_by_key(|r| r.priority);
+        rules
+    }
+
+    /// Build any classifier over the banded rule set.
+    pub fn build<C: Classifier>(&self) -> C {
+        C::build(&self.rules())
+    }
+}
@@ -0,0 +1,184 @@
+//! Region queries against built classifiers.
+//!
+//! `RegionQuery::rules_overlapping` answers "which rules could ever affect
+//! traffic in this part of packet space?" (e.g. from subnet X to subnet Y) by
+//! walking the classifier's own structure: decision trees descend every child
+//! the region reaches instead of the single one a packet would take, so only
+//! the relevant subtrees are visited.
+//!
+//! `RegionQuery::possible_matches` builds on it for what-if analysis: given a
+//! packet with some fields unknown (a region), it returns the rules and
+//! actions the packet could end up with, and whether it could match nothing.
+
+use crate::classifier::Classifier;
+use crate::compact::{BinaryNode, NodeView};
+use crate::geometry::{find_uncovered, Region};
+use crate::packet::FiveTuple;
+use crate::rule::{expand_rules, Action, Rule, ANY_ZONE};
+use alloc::vec::Vec;
+use hashbrown::HashSet;
+
+/// Classifier that can list the rules overlapping a region of packet space.
+pub trait RegionQuery: Classifier {
+    /// Rules matching at least one packet of `region`, once each and sorted
+    /// by priority.
+    ///
+    /// Zones are not part of the region, and address-set references are not
+    /// resolved: a rule bound to other zones, or whose sets currently hold no
+    /// address of the region, may still be listed.
+    fn rules_overlapping(&self, region: &Region) -> Vec<&Rule>;
+
+    /// Every rule matching `packet`, once each and sorted by priority, so the
+    /// rules the lookup's result beat follow it.
+    ///
+    /// Decision trees leave a rule out of the nodes where a better rule
+    /// covers the whole node (`BuildStats::shadowed_rules`), so they do not
+    /// list matches that can never win there.
+    fn classify_all(&self, packet: &FiveTuple) -> Vec<&Rule> {
+        let mut rules = self.rules_overlapping(&Region::from_packet(packet));
+        rules.retain(|r| r.matches(packet));
+        rules
+    }
+
+    /// Rules that win the lookup of at least one packet of `region`, i.e. the
+    /// possible results of classifying a packet whose fields are only known
+    /// to lie in the region.
+    fn possible_matches(&self, region: &Region) -> PossibleMatches<'_> {
+        possible_matches(self.rules_overlapping(region), region)
+    }
+}
+
+/// Possible outcomes of classifying any packet of a region.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PossibleMatches<'a> {
+    /// Rules matching some packet of the region before any other rule,
+    /// sorted by priority.
+    pub rules: Vec<&'a Rule>,
+    /// Whether some packet of the region may match no rule at all.
+    pub may_miss: bool,
+}
+
+impl PossibleMatches<'_> {
+    /// Distinct actions of the possible rules.
+    pub fn actions(&self) -> Vec<Action> {
+        let mut actions: Vec<Action> = Vec::new();
+        for rule in &self.rules {
+            if !actions.contains(&rule.action) {
+                actions.push(rule.action);
+            }
+        }
+        actions
+    }
+
+    /// Returns true if every packet of the region gets the same action.
+    pub fn is_decided(&self) -> bool {
+        !self.may_miss && self.actions().len() == 1
+    }
+}
+
+/// Keep the `candidates` (sorted by priority) that win some packet of
+/// `region`: a candidate is dropped when the candidates before it cover its
+/// part of the region.
+///
+/// Candidates bound to zones or referencing address sets never shadow later
+/// ones, so the result may list rules that cannot win, but never misses one.
+pub fn possible_matches<'a>(candidates: Vec<&'a Rule>, region: &Region) -> PossibleMatches<'a> {
+    let mut covered: Vec<Region> = Vec::new();
+    let mut rules = Vec::new();
+    for rule in candidates {
+        let own: Vec<Region> = expand_rules(core::slice::from_ref(rule))
+            .iter()
+            .filter_map(|r| Region::from_rule(r).intersection(region))
+            .collect();
+        if own.iter().any(|r| find_uncovered(r, &covered).is_some()) {
+            rules.push(rule);
+        }
+        if rule.zone == ANY_ZONE && !rule.references_sets() {
+            // A rule covering the whole region hides every later one.
+            if own.iter().any(|r| r == region) {
+                return PossibleMatches {
+                    rules,
+                    may_miss: false,
+                };
+            }
+            covered.extend(own);
+        }
+    }
+    PossibleMatches {
+        may_miss: find_uncovered(region, &covered).is_some(),
+        rules,
+    }
+}
+
+/// Returns true if some packet of `region` may match `rule`.
+pub fn rule_overlaps(rule: &Rule, region: &Region) -> bool {
+    if rule.is_plain() {
+        return Region::from_rule(rule).intersects(region);
+    }
+    expand_rules(core::slice::from_ref(rule))
+        .iter()
+        .any(|r| Region::from_rule(r).intersects(region))
+}
+
+/// Collects overlapping rules while walking a classifier, then returns each
+/// rule once in priority order.
+pub(crate) struct Overlapping<'a> {
+    region: Region,
+    rules: Vec<&'a Rule>,
+}
+
+impl<'a> Overlapping<'a> {
+    pub(crate) fn new(region: &Region) -> Self {
+        Self {
+            region: *region,
+            rules: Vec::new(),
+        }
+    }
+
+    /// Keep every rule of `rules` that overlaps the region.
+    pub(crate) fn scan(&mut self, rules: impl IntoIterator<Item = &'a Rule>) {
+        let region = self.region;
+        self.rules
+            .extend(rules.into_iter().filter(|r| rule_overlaps(r, &region)));
+    }
+
+    /// Add rules already known to overlap the region.
+    pub(crate) fn extend(&mut self, rules: impl IntoIterator<Item = &'a Rule>) {
+        self.rules.extend(rules);
+    }
+
+    /// Every binary tree leaf the region reaches below `node`.
+    pub(crate) fn descend<N: BinaryNode>(&mut self, node: &'a N) {
+        let mut stack = alloc::vec![node];
+        while let Some(node) = stack.pop() {
+            match node.view() {
+                NodeView::Internal {
+                    dimension,
+                    cut,
+                    left,
+                    right,
+                } => {
+                    let range = self.region.get(dimension);
+                    if range.max >= cut {
+                        stack.push(right);
+                    }
+                    if range.min < cut {
+                        stack.push(left);
+                    }
+                }
+                NodeView::Leaf(rules) => self.scan(rules),
+                NodeView::IndexedLeaf(index) => self.scan(index.rules()),
+            }
+        }
+    }
+
+    /// Rules found, deduplicated by id (copies made by rule expansion or
+    /// shared by several leaves are reported once) and sorted by priority.
+    pub(crate) fn finish(self) -> Vec<&'a Rule> {
+        let mut seen = HashSet::new();
+        let mut rules: Vec<&Rule> = self.rules;
+        rules.sort_by_key(|r| r.priority);
+        rules.retain(|r| seen.insert(r.id));
+        rules
+    }
+}
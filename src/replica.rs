@@ -0,0 +1,116 @@
+//! Per-worker classifier replicas.
+//!
+//! A single classifier shared by every core is read-only, but its hit
+//! counters are not: with many cores counting into the same cache lines,
+//! counter updates become the bottleneck. `Replicated` clones a frozen
+//! classifier once per worker, each copy with its own `Counted` counters on
+//! its own cache lines, so a worker's lookups touch no memory another worker
+//! writes.
+//!
+//! Counters are merged when read: `counters`, `unmatched` and `totals` sum
+//! the replicas at that moment.
+
+use crate::counters::{Counted, CounterSnapshot};
+use crate::freeze::{Freeze, FrozenClassifier};
+use crate::rule::Rule;
+use alloc::vec::Vec;
+use hashbrown::HashMap;
+
+/// A replica, aligned so that no two replicas share a cache line.
+#[repr(align(64))]
+struct Padded<T>(T);
+
+/// One frozen classifier copy per worker, each with its own hit counters.
+pub struct Replicated<C> {
+    replicas: Vec<Padded<Counted<FrozenClassifier<C>>>>,
+}
+
+impl<C: Freeze + Clone> Replicated<C> {
+    /// Replicate `classifier`, built over `rules`, for `workers` workers.
+    ///
+    /// Panics if `workers` is 0.
+    pub fn new(classifier: FrozenClassifier<C>, rules: &[Rule], workers: usize) -> Self {
+        assert!(
+            workers > 0,
+            "a replicated classifier needs at least one worker"
+        );
+        let mut replicas = Vec::with_capacity(workers);
+        for _ in 1..workers {
+            replicas.push(Padded(Counted::new(classifier.clone(), rules)));
+        }
+        replicas.push(Padded(Counted::new(classifier, rules)));
+        Self { replicas }
+    }
+
+    /// Build and freeze a classifier over `rules`, and replicate it for
+    /// `workers` workers.
+    pub fn build(rules: &[Rule], workers: usize) -> Self {
+        Self::new(C::build(rules).freeze(), rules, workers)
+    }
+}
+
+impl<C: Freeze> Replicated<C> {
+    /// Number of replicas.
+    pub fn len(&self) -> usize {
+        self.replicas.len()
+    }
+
+    /// Always false: there is at least one replica.
+    pub fn is_empty(&self) -> bool {
+        self.replicas.is_empty()
+    }
+
+    /// The replica of worker `worker`, to classify (and count) through.
+    ///
+    /// Panics if `worker` is out of range.
+    pub fn replica(&self, worker: usize) -> &Counted<FrozenClassifier<C>> {
+        &self.replicas[worker].0
+    }
+
+    /// Every replica, by worker.
+    pub fn replicas(&self) -> impl Iterator<Item = &Counted<FrozenClassifier<C>>> + '_ {
+        self.replicas.iter().map(|r| &r.0)
+    }
+
+    /// Counters of the rule with the given id, summed over the replicas.
+    pub fn counters(&self, rule_id: u32) -> Option<CounterSnapshot> {
+        let mut replicas = self.replicas();
+        let first = replicas.next()?.counters(rule_id)?;
+        Some(replicas.fold(first, |sum, r| {
+            add(sum, r.counters(rule_id).unwrap_or_default())
+        }))
+    }
+
+    /// Counters of packets that matched no rule, summed over the replicas.
+    pub fn unmatched(&self) -> CounterSnapshot {
+        self.replicas()
+            .fold(CounterSnapshot::default(), |sum, r| add(sum, r.unmatched()))
+    }
+
+    /// Counters of every rule, summed over the replicas, by rule id (in no
+    /// particular order).
+    pub fn totals(&self) -> Vec<(u32, CounterSnapshot)> {
+        let mut totals: HashMap<u32, CounterSnapshot> = HashMap::new();
+        for replica in self.replicas() {
+            for (id, snapshot) in replica.iter() {
+                let sum = totals.entry(id).or_default();
+                *sum = add(*sum, snapshot);
+            }
+        }
+        totals.into_iter().collect()
+    }
+
+    /// Zero the counters of every replica.
+    pub fn reset(&self) {
+        for replica in self.replicas() {
+            replica.reset();
+        }
+    }
+}
+
+fn add(a: CounterSnapshot, b: CounterSnapshot) -> CounterSnapshot {
+    CounterSnapshot {
+        packets: a.packets + b.packets,
+        bytes: a.bytes + b.bytes,
+    }
+}
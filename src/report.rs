@@ -0,0 +1,108 @@
+//! Human-readable build reports (requires the `std` feature).
+//!
+//! `report` builds a decision tree and summarizes it as a `BuildReport`:
+//! algorithm, configuration, rule count, memory, depth, largest leaf,
+//! replication factor and build time. Its `Display` output is a fixed-layout
+//! text block meant to be attached to deployment artifacts and change
+//! reviews, so two builds of a policy can be compared line by line:
+//!
+//! ```text
+//! Algorithm     cutsplit
+//! Config        leaf_threshold=10 max_depth=20 leaf_policy=Fixed secondary_threshold=none spfac=none
+//! Rules         1001
+//! Memory        2654.8 KiB
+//! Depth         20
+//! Nodes         1758 internal, 1759 leaves
+//! Largest leaf  112 rules
+//! Replication   37.15x
+//! Build time    513.926 ms
+//! ```
+
+use crate::dtree::{self, BuildStats, CutStrategy, TreeNode, TreeParams};
+use crate::geometry::Region;
+use crate::rule::{expand_rules, Rule};
+use alloc::string::{String, ToString};
+use core::fmt;
+use std::time::{Duration, Instant};
+
+/// Summary of a built decision tree.
+#[derive(Debug, Clone, PartialEq)]
+pub struct BuildReport {
+    /// Name of the algorithm.
+    pub algorithm: &'static str,
+    /// Limits the tree was built with.
+    pub params: TreeParams,
+    /// Rules the tree was built over (after expanding multi-range rules).
+    pub rules: usize,
+    /// Bytes held by the tree, root included.
+    pub memory_bytes: usize,
+    /// Shape of the tree.
+    pub stats: BuildStats,
+    /// Wall-clock build time.
+    pub build_time: Duration,
+}
+
+impl BuildReport {
+    /// Rule copies held by the leaves per rule (1.0 = no duplication).
+    pub fn replication(&self) -> f64 {
+        if self.rules == 0 {
+            return 1.0;
+        }
+        self.stats.stored_rules as f64 / self.rules as f64
+    }
+}
+
+/// Build the tree of `strategy` over `rules` and report on it.
+pub fn report<S: CutStrategy>(strategy: &S, rules: &[Rule]) -> (S::Node, BuildReport) {
+    let start = Instant::now();
+    let (root, stats) = dtree::build(strategy, rules, &Region::full());
+    let build_time = start.elapsed();
+    let report = BuildReport {
+        algorithm: S::NAME,
+        params: strategy.params(),
+        rules: expand_rules(rules).len(),
+        memory_bytes: size_of::<S::Node>() + root.heap_bytes(),
+        stats,
+        build_time,
+    };
+    (root, report)
+}
+
+impl fmt::Display for BuildReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let p = &self.params;
+        let or_none = |v: Option<usize>| v.map_or(String::from("none"), |v| v.to_string());
+        writeln!(f, "{:<14}{}", "Algorithm", self.algorithm)?;
+        writeln!(
+            f,
+            "{:<14}leaf_threshold={} max_depth={} leaf_policy={:?} secondary_threshold={} spfac={}",
+            "Config",
+            p.leaf_threshold,
+            p.max_depth,
+            p.leaf_policy,
+            or_none(p.secondary_threshold),
+            p.spfac.map_or(String::from("none"), |v| v.to_string()),
+        )?;
+        writeln!(f, "{:<14}{}", "Rules", self.rules)?;
+        writeln!(
+            f,
+            "{:<14}{:.1} KiB",
+            "Memory",
+            self.memory_bytes as f64 / 1024.0
+        )?;
+        writeln!(f, "{:<14}{}", "Depth", self.stats.max_depth)?;
+        writeln!(
+            f,
+            "{:<14}{} internal, {} leaves",
+            "Nodes", self.stats.internal_nodes, self.stats.leaves
+        )?;
+        writeln!(f, "{:<14}{} rules", "Largest leaf", self.stats.largest_leaf)?;
+        writeln!(f, "{:<14}{:.2}x", "Replication", self.replication())?;
+        writeln!(
+            f,
+            "{:<14}{:.3} ms",
+            "Build time",
+            self.build_time.as_secs_f64() * 1000.0
+        )
+    }
+}
@@ -0,0 +1,219 @@
+//! Rule-set validation before deployment.
+//!
+//! `analyze` reports the anomalies firewall operators look for in a policy,
+//! in first-match order (by priority, then position):
+//! - a rule is *shadowed* when rules ahead of it match every packet it
+//!   matches and some of them take a different action: it never applies,
+//!   and the policy does the opposite of what it says;
+//! - a rule is *redundant* when removing it changes the action of no packet,
+//!   either because rules ahead with its action cover it or because the
+//!   rules after it would take the same action wherever it matches;
+//! - two rules *conflict* when some packet matches both and they take
+//!   different actions. A conflict is a *generalization* when the better
+//!   rule is an exception carved out of the other (a permit ahead of a final
+//!   deny) and a *correlation* when they only partly overlap, which is
+//!   usually a mistake.
+//!
+//! Rules are compared as regions of packet space (`geometry`), with the same
+//! caveats: zones are compared as ranges, and address-set references are not
+//! resolved, so rules bound to other zones or referencing sets never cover
+//! another one (they may still conflict with it). The analysis compares
+//! every pair of rules; it is meant for validating a policy, not for the
+//! data path.
+
+use crate::geometry::{difference, effective_region, find_uncovered, is_dead, Region};
+use crate::rule::{expand_rules, Rule};
+use alloc::vec::Vec;
+
+/// How two conflicting rules overlap.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ConflictKind {
+    /// The better rule lies inside the other one.
+    Generalization,
+    /// The rules partly overlap.
+    Correlation,
+}
+
+/// Two rules some packet matches both of, with different actions.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Conflict {
+    /// Id of the later rule.
+    pub rule: u32,
+    /// Id of the rule ahead of it, which wins where they overlap.
+    pub with: u32,
+    pub kind: ConflictKind,
+}
+
+/// A rule that never applies, overridden by rules with other actions.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Shadowed {
+    pub rule: u32,
+    /// Rules ahead overlapping it with a different action.
+    pub by: Vec<u32>,
+}
+
+/// A rule whose removal changes no packet's action.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Redundant {
+    pub rule: u32,
+    /// Rules taking its packets with the same action: rules ahead covering
+    /// it, or rules after it matching where it wins.
+    pub by: Vec<u32>,
+}
+
+/// Anomalies of a rule set, each list in first-match order.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Report {
+    pub conflicts: Vec<Conflict>,
+    pub shadowed: Vec<Shadowed>,
+    pub redundant: Vec<Redundant>,
+}
+
+impl Report {
+    /// Returns true if no rule is shadowed or redundant and no rules
+    /// correlate. Generalizations are the normal shape of an ACL and are
+    /// not counted.
+    pub fn is_clean(&self) -> bool {
+        self.shadowed.is_empty()
+            && self.redundant.is_empty()
+            && self
+                .conflicts
+                .iter()
+                .all(|c| c.kind == ConflictKind::Generalization)
+    }
+
+    /// Conflicts where the rules only partly overlap.
+    pub fn correlations(&self) -> impl Iterator<Item = &Conflict> {
+        self.conflicts
+            .iter()
+            .filter(|c| c.kind == ConflictKind::Correlation)
+    }
+}
+
+/// Find the conflicting, shadowed and redundant rules of `rules`.
+///
+/// A rule that never applies is reported as shadowed or redundant, not as
+/// conflicting with the rules ahead of it. Each redundant rule can be
+/// removed on its own, but removing one may make another necessary (two
+/// copies of a rule are each redundant): remove them one at a time and
+/// analyze again.
+pub fn analyze(rules: &[Rule]) -> Report {
+    let mut order: Vec<usize> = (0..rules.len()).collect();
+    order.sort_by_key(|&i| rules[i].priority);
+    let regions: Vec<Vec<Region>> = rules.iter().map(regions_of).collect();
+    let overlap = |i: usize, j: usize| {
+        let (a, b) = (&rules[i].zone, &rules[j].zone);
+        a.min <= b.max
+            && b.min <= a.max
+            && regions[i]
+                .iter()
+                .any(|r| regions[j].iter().any(|o| r.intersects(o)))
+    };
+
+    let mut report = Report::default();
+    let mut dead = alloc::vec![false; rules.len()];
+    for (k, &i) in order.iter().enumerate() {
+        let rule = &rules[i];
+        // Dead rules win nowhere, so they neither cover nor conflict.
+        let ahead: Vec<usize> = order[..k]
+            .iter()
+            .copied()
+            .filter(|&j| !dead[j] && overlap(i, j))
+            .collect();
+        if is_dead(rules, i) {
+            dead[i] = true;
+            let other = ids(
+                rules,
+                ahead.iter().filter(|&&j| rules[j].action != rule.action),
+            );
+            if other.is_empty() {
+                report.redundant.push(Redundant {
+                    rule: rule.id,
+                    by: ids(rules, &ahead),
+                });
+            } else {
+                report.shadowed.push(Shadowed {
+                    rule: rule.id,
+                    by: other,
+                });
+            }
+            continue;
+        }
+        for &j in ahead.iter().filter(|&&j| rules[j].action != rule.action) {
+            let kind = if covers(&regions[i], &regions[j]) {
+                ConflictKind::Generalization
+            } else {
+                ConflictKind::Correlation
+            };
+            report.conflicts.push(Conflict {
+                rule: rule.id,
+                with: rules[j].id,
+                kind,
+            });
+        }
+        if let Some(by) = taken_over(rules, &regions, i, &order[k + 1..]) {
+            report.redundant.push(Redundant { rule: rule.id, by });
+        }
+    }
+    report
+}
+
+/// Regions of a rule, one per plain rule it expands to.
+fn regions_of(rule: &Rule) -> Vec<Region> {
+    expand_rules(core::slice::from_ref(rule))
+        .iter()
+        .map(Region::from_rule)
+        .collect()
+}
+
+/// Returns true if `outer` covers every region of `inner`.
+fn covers(outer: &[Region], inner: &[Region]) -> bool {
+    inner.iter().all(|r| find_uncovered(r, outer).is_none())
+}
+
+fn ids<'a>(rules: &[Rule], indices: impl IntoIterator<Item = &'a usize>) -> Vec<u32> {
+    indices.into_iter().map(|&j| rules[j].id).collect()
+}
+
+/// Rules of `later` (in first-match order) that would take every packet
+/// `rules[index]` wins with its action if it were removed, or `None` if one
+/// of them takes a different action first or some packet would match
+/// nothing.
+fn taken_over(
+    rules: &[Rule],
+    regions: &[Vec<Region>],
+    index: usize,
+    later: &[usize],
+) -> Option<Vec<u32>> {
+    let target = &rules[index];
+    let mut rest = effective_region(rules, index);
+    let mut by = Vec::new();
+    for &j in later {
+        if rest.is_empty() {
+            break;
+        }
+        let rule = &rules[j];
+        let zones = rule.zone.min <= target.zone.max && target.zone.min <= rule.zone.max;
+        if !zones
+            || !rest
+                .iter()
+                .any(|p| regions[j].iter().any(|r| r.intersects(p)))
+        {
+            continue;
+        }
+        if rule.action != target.action {
+            return None;
+        }
+        by.push(rule.id);
+        let takes_all = rule.zone.min <= target.zone.min
+            && target.zone.max <= rule.zone.max
+            && !rule.references_sets();
+        if takes_all {
+            rest = rest
+                .iter()
+                .flat_map(|piece| difference(piece, &regions[j]))
+                .collect();
+        }
+    }
+    rest.is_empty().then_some(by)
+}
@@ -0,0 +1,548 @@
+pub mod analysis;
+
+use crate::addrset::{AddressSets, SetId};
+use crate::dimension::Dimension;
+use crate::packet::{FiveTuple, Service, SixTuple, PROTO_ICMP, PROTO_IGMP, PROTO_TCP, PROTO_UDP};
+use crate::prefix::Prefix;
+use alloc::borrow::Cow;
+use alloc::boxed::Box;
+use alloc::vec::Vec;
+use core::fmt;
+
+/// Represents a range of values [min, max] inclusive.
+///
+/// Used for defining rule matches (e.g. port ranges, IP ranges).
+/// A single value is represented as min == max.
+/// "Any" (wildcard) is represented as the full range of the type.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct Range<T> {
+    /// Minimum value (inclusive)
+    pub min: T,
+    /// Maximum value (inclusive)
+    pub max: T,
+}
+
+impl<T: PartialOrd + Copy> Range<T> {
+    /// Check if a value is contained within the range.
+    pub fn contains(&self, val: T) -> bool {
+        val >= self.min && val <= self.max
+    }
+
+    /// Create a new range [min, max].
+    pub fn new(min: T, max: T) -> Self {
+        Self { min, max }
+    }
+
+    /// Create an exact match range [val, val].
+    pub fn exact(val: T) -> Self {
+        Self { min: val, max: val }
+    }
+
+    /// Create a wildcard range [min, max].
+    /// Semantically same as new, but reads better for "Any".
+    pub fn any(min: T, max: T) -> Self {
+        Self { min, max }
+    }
+}
+
+/// Rule Action.
+///
+/// The decision made when a packet matches a rule.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum Action {
+    /// Permit the packet to proceed.
+    Permit,
+    /// Deny/Drop the packet.
+    Deny,
+}
+
+/// Zone range matching every ingress interface/zone.
+pub const ANY_ZONE: Range<u32> = Range {
+    min: 0,
+    max: u32::MAX,
+};
+
+/// Additional ranges and negation per field, for rules such as
+/// "dport in {80, 443, 8080-8090}" or "src not in 10.0.0.0/8".
+///
+/// A field matches when the rule's own range or any range listed here
+/// contains the value; a negated field matches exactly the other values. The
+/// tree and tuple-space algorithms expand such rules into single-range rules
+/// (see `expand_rules`) while building; only `LinearClassifier` matches them
+/// directly.
+///
+/// A negated field becomes the ranges around the excluded ones. These are
+/// rarely prefix-aligned, so TSS, which further splits ranges into prefixes,
+/// can pay a large build cost for negated address fields.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct FieldSets {
+    pub src_ip: Vec<Range<u32>>,
+    pub dst_ip: Vec<Range<u32>>,
+    pub src_port: Vec<Range<u16>>,
+    pub dst_port: Vec<Range<u16>>,
+    pub proto: Vec<Range<u8>>,
+    /// Negated fields, as a bitmask of `1 << Dimension::index()`.
+    pub negated: u8,
+    /// Address set the source address must also belong to (see `addrset`).
+    pub src_set: Option<SetId>,
+    /// Address set the destination address must also belong to.
+    pub dst_set: Option<SetId>,
+}
+
+impl FieldSets {
+    /// Returns true if no field has an additional range, is negated or
+    /// references an address set.
+    pub fn is_empty(&self) -> bool {
+        self.src_ip.is_empty()
+            && self.dst_ip.is_empty()
+            && self.src_port.is_empty()
+            && self.dst_port.is_empty()
+            && self.proto.is_empty()
+            && self.negated == 0
+            && self.src_set.is_none()
+            && self.dst_set.is_none()
+    }
+
+    /// Returns true if the field matches values outside its ranges.
+    pub fn is_negated(&self, dim: Dimension) -> bool {
+        self.negated & (1 << dim.index()) != 0
+    }
+
+    /// Negate (or stop negating) a field.
+    pub fn set_negated(&mut self, dim: Dimension, negated: bool) {
+        if negated {
+            self.negated |= 1 << dim.index();
+        } else {
+            self.negated &= !(1 << dim.index());
+        }
+    }
+
+    /// Additional ranges of a field, widened to `u32`.
+    fn ranges(&self, dim: Dimension) -> Vec<Range<u32>> {
+        fn widen<T: Into<u32> + Copy>(ranges: &[Range<T>]) -> Vec<Range<u32>> {
+            ranges
+                .iter()
+                .map(|r| Range::new(r.min.into(), r.max.into()))
+                .collect()
+        }
+        match dim {
+            Dimension::SrcIp => self.src_ip.clone(),
+            Dimension::DstIp => self.dst_ip.clone(),
+            Dimension::SrcPort => widen(&self.src_port),
+            Dimension::DstPort => widen(&self.dst_port),
+            Dimension::Proto => widen(&self.proto),
+        }
+    }
+
+    fn mirrored(&self) -> Self {
+        let bit = |dim: Dimension| (self.negated >> dim.index()) & 1;
+        Self {
+            src_ip: self.dst_ip.clone(),
+            dst_ip: self.src_ip.clone(),
+            src_port: self.dst_port.clone(),
+            dst_port: self.src_port.clone(),
+            proto: self.proto.clone(),
+            negated: bit(Dimension::DstIp) << Dimension::SrcIp.index()
+                | bit(Dimension::SrcIp) << Dimension::DstIp.index()
+                | bit(Dimension::DstPort) << Dimension::SrcPort.index()
+                | bit(Dimension::SrcPort) << Dimension::DstPort.index()
+                | bit(Dimension::Proto) << Dimension::Proto.index(),
+            src_set: self.dst_set,
+            dst_set: self.src_set,
+        }
+    }
+}
+
+/// Classification Rule
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct Rule {
+    pub id: u32,
+    pub priority: u32, // Lower value = Higher priority
+    pub src_ip: Range<u32>,
+    pub dst_ip: Range<u32>,
+    pub src_port: Range<u16>,
+    pub dst_port: Range<u16>,
+    pub proto: Range<u8>,
+    /// Ingress interface index / zone id (`ANY_ZONE` when the rule is not zone-bound).
+    pub zone: Range<u32>,
+    /// Also match with source and destination (addresses and ports) swapped,
+    /// i.e. both directions of a connection.
+    pub bidirectional: bool,
+    /// Additional ranges per field (`None` for the usual one range per field).
+    pub field_sets: Option<Box<FieldSets>>,
+    pub action: Action,
+}
+
+impl Rule {
+    /// Rule matching every packet, with id and priority 0.
+    pub fn wildcard(action: Action) -> Self {
+        Rule {
+            id: 0,
+            priority: 0,
+            src_ip: Range::any(0, u32::MAX),
+            dst_ip: Range::any(0, u32::MAX),
+            src_port: Range::any(0, u16::MAX),
+            dst_port: Range::any(0, u16::MAX),
+            proto: Range::any(0, u8::MAX),
+            zone: ANY_ZONE,
+            bidirectional: false,
+            field_sets: None,
+            action,
+        }
+    }
+
+    /// Rule permitting TCP to `port` on `dst`, from anywhere.
+    ///
+    /// Like the other constructors below, id and priority are 0; set them
+    /// with `with_id` and `with_priority`.
+    pub fn tcp_service(dst: Prefix<u32>, port: u16) -> Self {
+        Rule {
+            dst_ip: dst.to_range(),
+            dst_port: Range::exact(port),
+            proto: Range::exact(PROTO_TCP),
+            ..Rule::wildcard(Action::Permit)
+        }
+    }
+
+    /// Rule permitting UDP to `port` on `dst`, from anywhere.
+    pub fn udp_service(dst: Prefix<u32>, port: u16) -> Self {
+        Rule {
+            proto: Range::exact(PROTO_UDP),
+            ..Rule::tcp_service(dst, port)
+        }
+    }
+
+    /// Rule permitting ICMP messages of `icmp_type` (and `code`, if given),
+    /// matched in the port fields as `L4Key` lays them out.
+    pub fn icmp(icmp_type: u8, code: Option<u8>) -> Self {
+        Rule {
+            src_port: Range::exact(icmp_type as u16),
+            dst_port: code.map_or(Range::new(0, u8::MAX as u16), |c| Range::exact(c as u16)),
+            proto: Range::exact(PROTO_ICMP),
+            ..Rule::wildcard(Action::Permit)
+        }
+    }
+
+    /// Rule permitting IGMP messages of `igmp_type`.
+    pub fn igmp(igmp_type: u8) -> Self {
+        Rule {
+            src_port: Range::exact(igmp_type as u16),
+            dst_port: Range::exact(0),
+            proto: Range::exact(PROTO_IGMP),
+            ..Rule::wildcard(Action::Permit)
+        }
+    }
+
+    /// Rule permitting IGMP messages (of `igmp_type`, if given) about the
+    /// multicast groups of `group`. It is meant for the keys of
+    /// `igmp::IgmpMessage::group_keys`, which carry the group in place of the
+    /// destination address and a v3 record type in the destination port.
+    pub fn igmp_group(igmp_type: Option<u8>, group: Prefix<u32>) -> Self {
+        Rule {
+            dst_ip: group.to_range(),
+            src_port: igmp_type.map_or(Range::new(0, u8::MAX as u16), |t| Range::exact(t as u16)),
+            dst_port: Range::new(0, u8::MAX as u16),
+            proto: Range::exact(PROTO_IGMP),
+            ..Rule::wildcard(Action::Permit)
+        }
+    }
+
+    /// Rule permitting `service` on `dst`, from anywhere. Services on several
+    /// protocols or ports give a rule with `field_sets`.
+    pub fn service(dst: Prefix<u32>, service: &Service) -> Self {
+        let mut rule = Rule {
+            dst_ip: dst.to_range(),
+            ..Rule::wildcard(Action::Permit)
+        };
+        let mut ports = Vec::new();
+        for &port in service.ports {
+            match ports.last_mut() {
+                Some(Range { max, .. }) if *max as u32 + 1 == port as u32 => *max = port,
+                _ => ports.push(Range::exact(port)),
+            }
+        }
+        let protocols: Vec<_> = service.protocols.iter().map(|&p| Range::exact(p)).collect();
+        if let Some((&first, rest)) = ports.split_first() {
+            rule.dst_port = first;
+            if !rest.is_empty() {
+                rule.field_sets_mut().dst_port = rest.to_vec();
+            }
+        }
+        if let Some((&first, rest)) = protocols.split_first() {
+            rule.proto = first;
+            if !rest.is_empty() {
+                rule.field_sets_mut().proto = rest.to_vec();
+            }
+        }
+        rule
+    }
+
+    /// The rule with id `id`.
+    pub fn with_id(self, id: u32) -> Self {
+        Rule { id, ..self }
+    }
+
+    /// The rule with priority `priority` (lower wins).
+    pub fn with_priority(self, priority: u32) -> Self {
+        Rule { priority, ..self }
+    }
+
+    /// The rule with action `action`.
+    pub fn with_action(self, action: Action) -> Self {
+        Rule { action, ..self }
+    }
+
+    /// The rule restricted to sources in `src`.
+    pub fn with_src(self, src: Prefix<u32>) -> Self {
+        Rule {
+            src_ip: src.to_range(),
+            ..self
+        }
+    }
+
+    /// Check if the rule matches a given 5-tuple
+    ///
+    /// Address-set references cannot be resolved without a registry, so a
+    /// rule carrying them matches nothing; use `matches_with` or a
+    /// `SetClassifier` for such rules.
+    pub fn matches(&self, tuple: &FiveTuple) -> bool {
+        self.matches_forward(tuple, None)
+            || (self.bidirectional && self.matches_forward(&tuple.reversed(), None))
+    }
+
+    /// Check if the rule matches a given 5-tuple, resolving address-set
+    /// references against `sets`.
+    pub fn matches_with(&self, tuple: &FiveTuple, sets: &AddressSets) -> bool {
+        self.matches_forward(tuple, Some(sets))
+            || (self.bidirectional && self.matches_forward(&tuple.reversed(), Some(sets)))
+    }
+
+    /// Returns true if the rule references an address set.
+    pub fn references_sets(&self) -> bool {
+        self.field_sets
+            .as_ref()
+            .is_some_and(|s| s.src_set.is_some() || s.dst_set.is_some())
+    }
+
+    /// The same rule with source and destination swapped.
+    pub fn mirrored(&self) -> Rule {
+        Rule {
+            src_ip: self.dst_ip,
+            dst_ip: self.src_ip,
+            src_port: self.dst_port,
+            dst_port: self.src_port,
+            field_sets: self.field_sets.as_ref().map(|s| Box::new(s.mirrored())),
+            ..self.clone()
+        }
+    }
+
+    /// The rule's additional ranges, created empty if it has none.
+    pub fn field_sets_mut(&mut self) -> &mut FieldSets {
+        self.field_sets.get_or_insert_with(Box::default)
+    }
+
+    /// Match everything except the field's ranges on `dim`.
+    pub fn negate(&mut self, dim: Dimension) {
+        self.field_sets_mut().set_negated(dim, true);
+    }
+
+    /// Returns true if the rule is one-way with a single positive range per field,
+    /// i.e. algorithms can place it without expanding it first.
+    pub fn is_plain(&self) -> bool {
+        !self.bidirectional && self.field_sets.as_ref().is_none_or(|s| s.is_empty())
+    }
+
+    /// Match in the rule's own direction only.
+    fn matches_forward(&self, tuple: &FiveTuple, registry: Option<&AddressSets>) -> bool {
+        let Some(sets) = self.field_sets.as_deref() else {
+            return self.src_ip.contains(tuple.src_ip)
+                && self.dst_ip.contains(tuple.dst_ip)
+                && self.src_port.contains(tuple.src_port)
+                && self.dst_port.contains(tuple.dst_port)
+                && self.proto.contains(tuple.proto)
+                && self.zone.contains(tuple.zone);
+        };
+        let neg = |dim| sets.is_negated(dim);
+        in_field(&self.src_ip, &sets.src_ip, tuple.src_ip) != neg(Dimension::SrcIp)
+            && in_field(&self.dst_ip, &sets.dst_ip, tuple.dst_ip) != neg(Dimension::DstIp)
+            && in_field(&self.src_port, &sets.src_port, tuple.src_port) != neg(Dimension::SrcPort)
+            && in_field(&self.dst_port, &sets.dst_port, tuple.dst_port) != neg(Dimension::DstPort)
+            && in_field(&self.proto, &sets.proto, tuple.proto) != neg(Dimension::Proto)
+            && self.zone.contains(tuple.zone)
+            && match registry {
+                Some(r) => {
+                    sets.src_set.is_none_or(|id| r.contains(id, tuple.src_ip))
+                        && sets.dst_set.is_none_or(|id| r.contains(id, tuple.dst_ip))
+                }
+                // Unresolved references fail closed.
+                None => sets.src_set.is_none() && sets.dst_set.is_none(),
+            }
+    }
+
+    /// Returns true if the rule only applies to some ingress zones.
+    pub fn is_zone_bound(&self) -> bool {
+        self.zone != ANY_ZONE
+    }
+}
+
+/// IPv6 classification rule: a `Rule` with 128-bit address ranges and one
+/// range per field.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct RuleV6 {
+    pub id: u32,
+    pub priority: u32, // Lower value = Higher priority
+    pub src_ip: Range<u128>,
+    pub dst_ip: Range<u128>,
+    pub src_port: Range<u16>,
+    pub dst_port: Range<u16>,
+    pub proto: Range<u8>,
+    /// Ingress interface index / zone id (`ANY_ZONE` when the rule is not zone-bound).
+    pub zone: Range<u32>,
+    /// Also match with source and destination (addresses and ports) swapped.
+    pub bidirectional: bool,
+    pub action: Action,
+}
+
+impl RuleV6 {
+    /// Check if the rule matches a given IPv6 tuple.
+    pub fn matches(&self, tuple: &SixTuple) -> bool {
+        self.matches_forward(tuple)
+            || (self.bidirectional && self.matches_forward(&tuple.reversed()))
+    }
+
+    fn matches_forward(&self, tuple: &SixTuple) -> bool {
+        self.src_ip.contains(tuple.src_ip)
+            && self.dst_ip.contains(tuple.dst_ip)
+            && self.src_port.contains(tuple.src_port)
+            && self.dst_port.contains(tuple.dst_port)
+            && self.proto.contains(tuple.proto)
+            && self.zone.contains(tuple.zone)
+    }
+}
+
+fn in_field<T: PartialOrd + Copy>(range: &Range<T>, more: &[Range<T>], val: T) -> bool {
+    range.contains(val) || more.iter().any(|r| r.contains(val))
+}
+
+/// Rewrite `rules` into plain rules (see `Rule::is_plain`) for algorithms that
+/// place rules by their field ranges. A rule with field sets becomes one rule
+/// per combination of ranges (negated fields are first split into the
+/// positive ranges of their complement), and a bidirectional rule is followed by its
+/// mirrors; all copies share the original's id, priority and action, so
+/// first-match semantics are unchanged. The input is returned as is when every
+/// rule is already plain.
+///
+/// Rules referencing an address set produce no copies: their sets are not
+/// known here, and they match nothing without them (see `Rule::matches`).
+/// `SetClassifier` serves such rules.
+pub fn expand_rules(rules: &[Rule]) -> Cow<'_, [Rule]> {
+    if rules.iter().all(Rule::is_plain) {
+        return Cow::Borrowed(rules);
+    }
+    let mut out = Vec::with_capacity(rules.len() * 2);
+    for rule in rules {
+        if rule.is_plain() {
+            out.push(rule.clone());
+            continue;
+        }
+        if rule.references_sets() {
+            continue;
+        }
+        let start = out.len();
+        let forward = Rule {
+            bidirectional: false,
+            field_sets: None,
+            ..rule.clone()
+        };
+        match rule.field_sets.as_deref() {
+            Some(sets) => push_combinations(&forward, sets, &mut out),
+            None => out.push(forward),
+        }
+        if rule.bidirectional {
+            let end = out.len();
+            for i in start..end {
+                let mirror = out[i].mirrored();
+                // Symmetric rules (or combinations) are not duplicated.
+                if !out[start..].contains(&mirror) {
+                    out.push(mirror);
+                }
+            }
+        }
+    }
+    Cow::Owned(out)
+}
+
+/// Push one copy of `base` per combination of the positive ranges of each field.
+fn push_combinations(base: &Rule, sets: &FieldSets, out: &mut Vec<Rule>) {
+    let [src, dst, sp, dp, pr] = Dimension::ALL.map(|dim| {
+        let mut ranges = sets.ranges(dim);
+        ranges.push(dim.rule_range(base));
+        if sets.is_negated(dim) {
+            complement(ranges, dim.max_value())
+        } else {
+            ranges
+        }
+    });
+
+    // A field negating its whole domain matches nothing: no rule is produced.
+    out.reserve(src.len() * dst.len() * sp.len() * dp.len() * pr.len());
+    for s in &src {
+        for d in &dst {
+            for a in &sp {
+                for b in &dp {
+                    for p in &pr {
+                        out.push(Rule {
+                            src_ip: *s,
+                            dst_ip: *d,
+                            src_port: Range::new(a.min as u16, a.max as u16),
+                            dst_port: Range::new(b.min as u16, b.max as u16),
+                            proto: Range::new(p.min as u8, p.max as u8),
+                            ..base.clone()
+                        });
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// Ranges of `0..=max` covered by none of `ranges`, in ascending order.
+pub(crate) fn complement(mut ranges: Vec<Range<u32>>, max: u32) -> Vec<Range<u32>> {
+    ranges.sort_by_key(|r| r.min);
+    let mut out = Vec::new();
+    // Next value not yet known to be covered (None once `max` is covered).
+    let mut next = Some(0u32);
+    for r in ranges {
+        let Some(start) = next else { break };
+        if r.min > start {
+            out.push(Range::new(start, r.min - 1));
+        }
+        if r.max >= start {
+            next = r.max.checked_add(1).filter(|&v| v <= max);
+        }
+    }
+    if let Some(start) = next {
+        out.push(Range::new(start, max));
+    }
+    out
+}
+
+impl fmt::Display for Rule {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "Rule(id={}, pri={}, action={:?})",
+            self.id, self.priority, self.action
+        )
+    }
+}
@@ -0,0 +1,182 @@
+//! Compact rule sets used while building decision trees.
+//!
+//! Builders track which rules reach each node as a bitset over the (expanded)
+//! input rule list instead of cloning the rules into every node: a node over
+//! `n` input rules costs `n / 8` bytes however many rules it holds, and two
+//! nodes holding the same rules have equal sets, which makes identical leaves
+//! trivial to spot. Rules are only cloned when a leaf is emitted.
+
+use crate::geometry::Region;
+use crate::leaf::BitVectorIndex;
+use crate::rule::{Rule, ANY_ZONE};
+use alloc::boxed::Box;
+use alloc::vec::Vec;
+use hashbrown::HashMap;
+
+/// Set of indices into a rule list.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct RuleSet {
+    words: Vec<u64>,
+    len: usize,
+}
+
+impl RuleSet {
+    /// Empty set over a list of `n` rules.
+    pub fn empty(n: usize) -> Self {
+        Self {
+            words: alloc::vec![0; n.div_ceil(64)],
+            len: 0,
+        }
+    }
+
+    /// Every rule of a list of `n` rules.
+    pub fn full(n: usize) -> Self {
+        let mut words = alloc::vec![!0u64; n.div_ceil(64)];
+        if !n.is_multiple_of(64) {
+            if let Some(last) = words.last_mut() {
+                *last = (1u64 << (n % 64)) - 1;
+            }
+        }
+        Self { words, len: n }
+    }
+
+    /// Add rule `i`.
+    pub fn insert(&mut self, i: usize) {
+        let word = &mut self.words[i / 64];
+        let bit = 1u64 << (i % 64);
+        if *word & bit == 0 {
+            *word |= bit;
+            self.len += 1;
+        }
+    }
+
+    /// Remove rule `i`.
+    pub fn remove(&mut self, i: usize) {
+        let word = &mut self.words[i / 64];
+        let bit = 1u64 << (i % 64);
+        if *word & bit != 0 {
+            *word &= !bit;
+            self.len -= 1;
+        }
+    }
+
+    /// Returns true if rule `i` is in the set.
+    pub fn contains(&self, i: usize) -> bool {
+        self.words
+            .get(i / 64)
+            .is_some_and(|w| w & (1u64 << (i % 64)) != 0)
+    }
+
+    /// Number of rules in the set.
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    /// Returns true if the set holds no rule.
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Indices in the set, ascending.
+    pub fn iter(&self) -> impl Iterator<Item = usize> + '_ {
+        self.words.iter().enumerate().flat_map(|(w, &word)| {
+            let mut word = word;
+            core::iter::from_fn(move || {
+                if word == 0 {
+                    return None;
+                }
+                let bit = word.trailing_zeros() as usize;
+                word &= word - 1;
+                Some(w * 64 + bit)
+            })
+        })
+    }
+
+    /// The rules of `all` in the set, in list order.
+    pub fn select<'a>(&self, all: &'a [Rule]) -> Vec<&'a Rule> {
+        self.iter().map(|i| &all[i]).collect()
+    }
+}
+
+/// The rule list a tree is built over, with the leaves made from it.
+///
+/// Bit-vector indexes are cached by rule set: a leaf holding the same rules
+/// as an earlier indexed leaf gets a copy of its index instead of a rebuild.
+pub(crate) struct RuleStore<'a> {
+    all: &'a [Rule],
+    indexes: HashMap<RuleSet, BitVectorIndex>,
+    shadowed: usize,
+}
+
+impl<'a> RuleStore<'a> {
+    pub(crate) fn new(all: &'a [Rule]) -> Self {
+        Self {
+            all,
+            indexes: HashMap::new(),
+            shadowed: 0,
+        }
+    }
+
+    /// Set of every rule.
+    pub(crate) fn full(&self) -> RuleSet {
+        RuleSet::full(self.all.len())
+    }
+
+    /// Empty set over the rules.
+    pub(crate) fn empty(&self) -> RuleSet {
+        RuleSet::empty(self.all.len())
+    }
+
+    /// Rule `i`.
+    pub(crate) fn get(&self, i: usize) -> &'a Rule {
+        &self.all[i]
+    }
+
+    /// The rules of `set`, in list order.
+    pub(crate) fn select(&self, set: &RuleSet) -> Vec<&'a Rule> {
+        set.select(self.all)
+    }
+
+    /// Copies of the rules of `set`, for a leaf.
+    pub(crate) fn leaf_rules(&self, set: &RuleSet) -> Vec<Rule> {
+        set.iter().map(|i| self.all[i].clone()).collect()
+    }
+
+    /// Bit-vector index over the rules of `set`.
+    pub(crate) fn leaf_index(&mut self, set: &RuleSet) -> Box<BitVectorIndex> {
+        if let Some(index) = self.indexes.get(set) {
+            return Box::new(index.clone());
+        }
+        let index = BitVectorIndex::build(&self.leaf_rules(set));
+        self.indexes.insert(set.clone(), index.clone());
+        Box::new(index)
+    }
+
+    /// Drop the rules of `set` that can never win in `region`: once a rule
+    /// matching every zone covers the whole region, the later rules of equal
+    /// or lower priority are shadowed there. Returns how many were dropped.
+    pub(crate) fn drop_shadowed(&mut self, set: &mut RuleSet, region: &Region) -> usize {
+        let all = self.all;
+        let covering = set
+            .iter()
+            .find(|&i| all[i].zone == ANY_ZONE && Region::from_rule(&all[i]).contains(region));
+        let Some(first) = covering else {
+            return 0;
+        };
+        let cutoff = all[first].priority;
+        let shadowed: Vec<usize> = set
+            .iter()
+            .filter(|&j| j > first && all[j].priority >= cutoff)
+            .collect();
+        for &j in &shadowed {
+            set.remove(j);
+        }
+        self.shadowed += shadowed.len();
+        shadowed.len()
+    }
+
+    /// Rule copies dropped as shadowed so far.
+    pub(crate) fn shadowed(&self) -> usize {
+        self.shadowed
+    }
+}
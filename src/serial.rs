@@ -0,0 +1,210 @@
+//! Binary serialization of built classifiers.
+//!
+//! Building a tree over tens of thousands of rules takes seconds; `Codec`
+//! lets a control plane build once and ship the structure to data-plane
+//! instances, which load it without rebuilding. The format is versioned and
+//! little-endian:
+//!
+//! - magic `CSTR`, format version, classifier kind;
+//! - the fingerprint of the rules the classifier was built from (a presence
+//!   byte, then the `u64` if present);
+//! - the structure, nodes in pre-order, with rules in the audit log's rule
+//!   encoding.
+//!
+//! Bit-vector leaf indexes are stored as their rule lists and rebuilt on
+//! load, which is cheap next to building the tree. Decoding checks the
+//! structure (child and rule indices, cut parameters) so a corrupt blob is
+//! rejected rather than failing lookups later.
+
+use crate::classifier::Classifier;
+use crate::compact::{BinaryNode, NodeView};
+use crate::dimension::Dimension;
+use crate::dtree::TreeNode;
+use crate::fingerprint::{Fingerprint, LoadError};
+use crate::leaf::BitVectorIndex;
+use crate::rule::Rule;
+use crate::update::audit::{decode_rule, encode_rule, DecodeError, Reader};
+use alloc::boxed::Box;
+use alloc::vec::Vec;
+
+const MAGIC: &[u8; 4] = b"CSTR";
+const VERSION: u8 = 1;
+/// Version of the audit log rule encoding used by `VERSION`.
+const RULE_VERSION: u8 = 6;
+
+/// Classifier kinds, the byte after the version.
+pub(crate) const HICUTS: u8 = 1;
+pub(crate) const HYPERSPLIT: u8 = 2;
+pub(crate) const COMPACT: u8 = 3;
+/// Kind 4 stored PartitionSort as a single interval tree and is no longer
+/// read.
+pub(crate) const PARTITION_SORT: u8 = 5;
+/// Kind 0 stored CutSplit as a boxed tree and is no longer read.
+pub(crate) const CUTSPLIT: u8 = 6;
+
+/// Deepest tree decoded. Trees are decoded recursively, so deeper blobs are
+/// rejected as corrupt rather than exhausting the stack; builders' depth
+/// limits stay far below it.
+pub(crate) const MAX_DEPTH: usize = 256;
+
+const NODE_INTERNAL: u8 = 0;
+const NODE_LEAF: u8 = 1;
+const NODE_INDEXED_LEAF: u8 = 2;
+
+/// Classifier that can be saved to and loaded from bytes.
+pub trait Codec: Classifier + Sized {
+    /// Serialize the classifier.
+    fn serialize(&self) -> Vec<u8>;
+
+    /// Load a classifier serialized by the same type.
+    fn deserialize(bytes: &[u8]) -> Result<Self, DecodeError>;
+
+    /// Load a classifier, refusing it unless it was built from the rules
+    /// with fingerprint `expected`.
+    fn deserialize_checked(bytes: &[u8], expected: Fingerprint) -> Result<Self, LoadError> {
+        let (_, found) = read_header(&mut reader(bytes))?;
+        Fingerprint::check(found, expected)?;
+        Ok(Self::deserialize(bytes)?)
+    }
+}
+
+pub(crate) fn reader(bytes: &[u8]) -> Reader<'_> {
+    Reader { bytes, pos: 0 }
+}
+
+/// Start a blob for a classifier of `kind`.
+pub(crate) fn header(kind: u8, fingerprint: Option<Fingerprint>) -> Vec<u8> {
+    let mut out = Vec::new();
+    out.extend_from_slice(MAGIC);
+    out.push(VERSION);
+    out.push(kind);
+    match fingerprint {
+        Some(fingerprint) => {
+            out.push(1);
+            out.extend_from_slice(&fingerprint.0.to_le_bytes());
+        }
+        None => out.push(0),
+    }
+    out
+}
+
+fn read_header(r: &mut Reader<'_>) -> Result<(u8, Option<Fingerprint>), DecodeError> {
+    if r.take(4)? != MAGIC {
+        return Err(DecodeError::BadMagic);
+    }
+    let version = r.u8()?;
+    if version != VERSION {
+        return Err(DecodeError::UnsupportedVersion(version));
+    }
+    let kind = r.u8()?;
+    let fingerprint = if r.u8()? != 0 {
+        Some(Fingerprint(r.u64()?))
+    } else {
+        None
+    };
+    Ok((kind, fingerprint))
+}
+
+/// Read the header of a blob expected to hold a classifier of `kind`.
+pub(crate) fn open(r: &mut Reader<'_>, kind: u8) -> Result<Option<Fingerprint>, DecodeError> {
+    let (found, fingerprint) = read_header(r)?;
+    if found != kind {
+        return Err(DecodeError::InvalidTag(found));
+    }
+    Ok(fingerprint)
+}
+
+/// Fail unless the whole blob was consumed.
+pub(crate) fn finish(r: &Reader<'_>) -> Result<(), DecodeError> {
+    if r.pos != r.bytes.len() {
+        return Err(DecodeError::Corrupt);
+    }
+    Ok(())
+}
+
+pub(crate) fn put_u32(out: &mut Vec<u8>, v: u32) {
+    out.extend_from_slice(&v.to_le_bytes());
+}
+
+pub(crate) fn dimension(tag: u8) -> Result<Dimension, DecodeError> {
+    Dimension::ALL
+        .get(tag as usize)
+        .copied()
+        .ok_or(DecodeError::InvalidTag(tag))
+}
+
+/// Write a count-prefixed rule list.
+pub(crate) fn encode_rules(out: &mut Vec<u8>, rules: &[Rule]) {
+    put_u32(out, rules.len() as u32);
+    for rule in rules {
+        encode_rule(out, rule);
+    }
+}
+
+pub(crate) fn decode_rules(r: &mut Reader<'_>) -> Result<Vec<Rule>, DecodeError> {
+    let len = r.u32()?;
+    // Grow as rules are read so a corrupt count cannot force a huge allocation.
+    let mut rules = Vec::new();
+    for _ in 0..len {
+        rules.push(decode_rule(r, RULE_VERSION)?);
+    }
+    Ok(rules)
+}
+
+pub(crate) fn decode_index(r: &mut Reader<'_>) -> Result<BitVectorIndex, DecodeError> {
+    Ok(BitVectorIndex::build(&decode_rules(r)?))
+}
+
+/// Write the binary tree rooted at `node`.
+pub(crate) fn encode_binary<N: BinaryNode>(out: &mut Vec<u8>, node: &N) {
+    match node.view() {
+        NodeView::Internal {
+            dimension,
+            cut,
+            left,
+            right,
+        } => {
+            out.push(NODE_INTERNAL);
+            out.push(dimension.index() as u8);
+            put_u32(out, cut);
+            encode_binary(out, left);
+            encode_binary(out, right);
+        }
+        NodeView::Leaf(rules) => {
+            out.push(NODE_LEAF);
+            encode_rules(out, rules);
+        }
+        NodeView::IndexedLeaf(index) => {
+            out.push(NODE_INDEXED_LEAF);
+            encode_rules(out, index.rules());
+        }
+    }
+}
+
+/// Read a binary tree written by `encode_binary`.
+pub(crate) fn decode_binary<N: BinaryNode + TreeNode>(
+    r: &mut Reader<'_>,
+) -> Result<N, DecodeError> {
+    decode_binary_at(r, 0)
+}
+
+fn decode_binary_at<N: BinaryNode + TreeNode>(
+    r: &mut Reader<'_>,
+    depth: usize,
+) -> Result<N, DecodeError> {
+    match r.u8()? {
+        NODE_INTERNAL => {
+            if depth >= MAX_DEPTH {
+                return Err(DecodeError::Corrupt);
+            }
+            let dimension = dimension(r.u8()?)?;
+            let cut = r.u32()?;
+            let left = decode_binary_at(r, depth + 1)?;
+            let right = decode_binary_at(r, depth + 1)?;
+            Ok(N::internal(dimension, cut, left, right))
+        }
+        NODE_LEAF => Ok(N::leaf(decode_rules(r)?)),
+        NODE_INDEXED_LEAF => Ok(N::indexed_leaf(Box::new(decode_index(r)?))),
+        tag => Err(DecodeError::InvalidTag(tag)),
+    }
+}
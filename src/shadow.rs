@@ -0,0 +1,254 @@
+//! Shadow (dry-run) classification.
+//!
+//! A `ShadowPair` serves every lookup from the live classifier while also
+//! evaluating a candidate built from a new rule set. Packets on which the two
+//! disagree are recorded so operators can validate a policy change against
+//! production traffic before cutting over.
+//!
+//! A `CheckedClassifier` instead runs two algorithms over the same rules on
+//! every lookup (e.g. a new tree against `LinearClassifier`) and panics or
+//! logs when they disagree, to soak-test an algorithm in debug or validation
+//! deployments. It is a `Classifier` itself, so it drops in wherever the
+//! algorithm under test would go.
+
+use crate::classifier::{Classifier, LookupStats, Verdict};
+use crate::counters::Cell64;
+use crate::packet::FiveTuple;
+use crate::rule::{Action, Rule};
+use alloc::vec::Vec;
+
+/// A packet on which the live and candidate classifiers disagreed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Divergence {
+    /// The packet that was classified.
+    pub packet: FiveTuple,
+    /// Verdict of the live classifier (the one actually applied).
+    pub live: Option<Action>,
+    /// Verdict the candidate classifier would have produced.
+    pub candidate: Option<Action>,
+}
+
+/// Live classifier paired with a candidate evaluated in the shadow.
+pub struct ShadowPair<L, C> {
+    live: L,
+    candidate: C,
+    divergences: Vec<Divergence>,
+    /// Max number of divergences kept; further ones are only counted.
+    max_recorded: usize,
+    evaluated: u64,
+    diverged: u64,
+}
+
+impl<L: Classifier, C: Classifier> ShadowPair<L, C> {
+    /// Pair a live classifier with a candidate, recording up to 1024 divergences.
+    pub fn new(live: L, candidate: C) -> Self {
+        Self::with_limit(live, candidate, 1024)
+    }
+
+    /// Pair a live classifier with a candidate, recording up to `max_recorded` divergences.
+    pub fn with_limit(live: L, candidate: C, max_recorded: usize) -> Self {
+        Self {
+            live,
+            candidate,
+            divergences: Vec::new(),
+            max_recorded,
+            evaluated: 0,
+            diverged: 0,
+        }
+    }
+
+    /// Classify with the live classifier, evaluating the candidate in the shadow.
+    ///
+    /// The returned verdict is always the live one.
+    pub fn classify(&mut self, packet: &FiveTuple) -> Option<Action> {
+        let live = self.live.classify(packet);
+        let candidate = self.candidate.classify(packet);
+
+        self.evaluated += 1;
+        if live != candidate {
+            self.diverged += 1;
+            if self.divergences.len() < self.max_recorded {
+                self.divergences.push(Divergence {
+                    packet: *packet,
+                    live,
+                    candidate,
+                });
+            }
+        }
+        live
+    }
+
+    /// Recorded divergences, in arrival order.
+    pub fn divergences(&self) -> &[Divergence] {
+        &self.divergences
+    }
+
+    /// Number of packets evaluated by both classifiers.
+    pub fn evaluated(&self) -> u64 {
+        self.evaluated
+    }
+
+    /// Total number of divergent packets (including ones not recorded).
+    pub fn diverged(&self) -> u64 {
+        self.diverged
+    }
+
+    /// Forget recorded divergences and reset counters.
+    pub fn reset(&mut self) {
+        self.divergences.clear();
+        self.evaluated = 0;
+        self.diverged = 0;
+    }
+
+    /// The live classifier.
+    pub fn live(&self) -> &L {
+        &self.live
+    }
+
+    /// The candidate classifier.
+    pub fn candidate(&self) -> &C {
+        &self.candidate
+    }
+
+    /// Replace the candidate (e.g. after editing the proposed rule set) and reset statistics.
+    pub fn set_candidate(&mut self, candidate: C) {
+        self.candidate = candidate;
+        self.reset();
+    }
+
+    /// Promote the candidate: consume the pair and return the candidate classifier.
+    pub fn cutover(self) -> C {
+        self.candidate
+    }
+}
+
+/// What a `CheckedClassifier` does when its classifiers disagree.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum OnDivergence {
+    /// Panic, reporting the packet and both verdicts.
+    #[default]
+    Panic,
+    /// Log a warning (with the `log` or `defmt` feature) and go on with the
+    /// primary's verdict.
+    Log,
+}
+
+/// Two classifiers over the same rules, checked against each other on every
+/// lookup. Results are the primary's.
+///
+/// The classifiers agree when they match rules with the same priority and
+/// action (or both match none): classifiers may pick any of several rules of
+/// equal priority, so rule ids are not compared. Rule sets whose overlapping
+/// rules of equal priority have different actions are ambiguous, and may
+/// still diverge.
+pub struct CheckedClassifier<A, B> {
+    primary: A,
+    reference: B,
+    on_divergence: OnDivergence,
+    checked: Cell64,
+    diverged: Cell64,
+}
+
+impl<A: Classifier, B: Classifier> CheckedClassifier<A, B> {
+    /// Check `primary` against `reference`, panicking on divergence.
+    pub fn new(primary: A, reference: B) -> Self {
+        Self {
+            primary,
+            reference,
+            on_divergence: OnDivergence::default(),
+            checked: Cell64::default(),
+            diverged: Cell64::default(),
+        }
+    }
+
+    /// React to divergences with `mode`.
+    pub fn with_on_divergence(mut self, mode: OnDivergence) -> Self {
+        self.on_divergence = mode;
+        self
+    }
+
+    /// The classifier whose results are returned.
+    pub fn primary(&self) -> &A {
+        &self.primary
+    }
+
+    /// The classifier it is checked against.
+    pub fn reference(&self) -> &B {
+        &self.reference
+    }
+
+    /// Number of lookups checked.
+    pub fn checked(&self) -> u64 {
+        self.checked.get()
+    }
+
+    /// Number of lookups on which the classifiers disagreed.
+    pub fn diverged(&self) -> u64 {
+        self.diverged.get()
+    }
+
+    /// Reset the counters.
+    pub fn reset(&self) {
+        self.checked.clear();
+        self.diverged.clear();
+    }
+
+    /// Compare the primary's match on `packet` with the reference's.
+    fn check(&self, packet: &FiveTuple, primary: Option<&Rule>) {
+        let reference = self.reference.classify_rule(packet);
+        self.checked.add(1);
+        let agree = match (primary, reference) {
+            (Some(a), Some(b)) => a.priority == b.priority && a.action == b.action,
+            (a, b) => a.is_none() && b.is_none(),
+        };
+        if agree {
+            return;
+        }
+        self.diverged.add(1);
+        let (primary, reference) = (Verdict::from(primary), Verdict::from(reference));
+        match self.on_divergence {
+            OnDivergence::Panic => panic!(
+                "classifiers diverged on {:?}: primary {:?}, reference {:?}",
+                packet, primary, reference
+            ),
+            OnDivergence::Log => {
+                #[cfg(feature = "log")]
+                log::warn!(
+                    "classifiers diverged on {:?}: primary {:?}, reference {:?}",
+                    packet,
+                    primary,
+                    reference
+                );
+                #[cfg(feature = "defmt")]
+                defmt::warn!(
+                    "classifiers diverged: primary rule {}, reference rule {}",
+                    primary.rule_id(),
+                    reference.rule_id()
+                );
+            }
+        }
+    }
+}
+
+impl<A: Classifier, B: Classifier> Classifier for CheckedClassifier<A, B> {
+    fn build(rules: &[Rule]) -> Self {
+        Self::new(A::build(rules), B::build(rules))
+    }
+
+    fn classify_rule(&self, packet: &FiveTuple) -> Option<&Rule> {
+        let rule = self.primary.classify_rule(packet);
+        self.check(packet, rule);
+        rule
+    }
+
+    fn classify_with_stats(&self, packet: &FiveTuple) -> (Option<Action>, LookupStats) {
+        let (rule, stats) = self.classify_rule_with_stats(packet);
+        (rule.map(|r| r.action), stats)
+    }
+
+    fn classify_rule_with_stats(&self, packet: &FiveTuple) -> (Option<&Rule>, LookupStats) {
+        let (rule, stats) = self.primary.classify_rule_with_stats(packet);
+        self.check(packet, rule);
+        (rule, stats)
+    }
+}
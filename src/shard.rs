@@ -0,0 +1,205 @@
+//! Rule sharding for multi-queue scaling.
+//!
+//! A `ShardedClassifier` splits the rule set across several independent
+//! classifiers (shards) by a key taken from the packet: the top bits of one
+//! field, source address by default, hashed to a shard. Each shard only holds
+//! the rules that can match packets of its keys, so it is smaller than a
+//! single classifier over all the rules, and shards share nothing: one can be
+//! pinned per core (or NUMA node) and fed by its own NIC queue, with
+//! `shard_of` giving the steering function. Lookups through the sharded
+//! classifier itself cost one shift and one multiply before the shard's own
+//! lookup.
+//!
+//! Rules whose key range spans several key blocks (short prefixes,
+//! wildcards) are copied to every shard one of those blocks hashes to, so
+//! results are identical to a single classifier over all the rules.
+
+use crate::classifier::{Classifier, LookupStats};
+use crate::dimension::Dimension;
+use crate::freeze::Freeze;
+use crate::geometry::Region;
+use crate::packet::FiveTuple;
+use crate::query::{Overlapping, RegionQuery};
+use crate::rule::{expand_rules, Action, Rule};
+use alloc::vec;
+use alloc::vec::Vec;
+
+/// Number of shards used by `Classifier::build`.
+pub const DEFAULT_SHARDS: usize = 4;
+
+/// Key blocks a rule's range is walked through before it is simply copied to
+/// every shard, per shard.
+const MAX_BLOCKS_PER_SHARD: u64 = 64;
+
+/// Packet field a `ShardedClassifier` dispatches on.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ShardKey {
+    /// Field the key is taken from.
+    pub dimension: Dimension,
+    /// Number of leading bits of the field forming the key. Rules whose
+    /// field is a prefix at least this long land in exactly one shard.
+    pub prefix_len: u32,
+}
+
+impl Default for ShardKey {
+    /// The /16 of the source address.
+    fn default() -> Self {
+        Self {
+            dimension: Dimension::SrcIp,
+            prefix_len: 16,
+        }
+    }
+}
+
+impl ShardKey {
+    /// Shift turning a field value into its key block.
+    fn shift(&self) -> u32 {
+        self.dimension.spec().bits - self.prefix_len
+    }
+}
+
+/// Classifier split into independent shards by a packet key.
+#[derive(Clone)]
+pub struct ShardedClassifier<C> {
+    shards: Vec<C>,
+    key: ShardKey,
+    sizes: Vec<usize>,
+}
+
+impl<C: Classifier> ShardedClassifier<C> {
+    /// Split `rules` into `shards` shards keyed by `key` and build each one
+    /// with `build`, e.g. to use a configured tree builder:
+    /// `ShardedClassifier::build_with(&rules, 8, ShardKey::default(), |r| HyperSplitClassifier::from_builder(&b, r))`.
+    ///
+    /// Panics if `shards` is 0 or `key.prefix_len` is longer than the field.
+    pub fn build_with(
+        rules: &[Rule],
+        shards: usize,
+        key: ShardKey,
+        build: impl Fn(&[Rule]) -> C,
+    ) -> Self {
+        assert!(shards > 0, "a sharded classifier needs at least one shard");
+        assert!(
+            key.prefix_len <= key.dimension.spec().bits,
+            "shard key longer than the {} field",
+            key.dimension.name()
+        );
+        let parts = partition_by_key(&expand_rules(rules), shards, key);
+        Self {
+            sizes: parts.iter().map(Vec::len).collect(),
+            shards: parts.iter().map(|part| build(part)).collect(),
+            key,
+        }
+    }
+
+    /// The key packets are dispatched on.
+    pub fn key(&self) -> ShardKey {
+        self.key
+    }
+
+    /// Number of shards.
+    pub fn len(&self) -> usize {
+        self.shards.len()
+    }
+
+    /// Always false: a sharded classifier has at least one shard.
+    pub fn is_empty(&self) -> bool {
+        self.shards.is_empty()
+    }
+
+    /// Number of rules held by each shard.
+    pub fn shard_sizes(&self) -> &[usize] {
+        &self.sizes
+    }
+
+    /// The shards, by index.
+    pub fn shards(&self) -> &[C] {
+        &self.shards
+    }
+
+    /// Shard `index`.
+    pub fn shard(&self, index: usize) -> Option<&C> {
+        self.shards.get(index)
+    }
+
+    /// Index of the shard classifying `packet`: the steering function for
+    /// pinning one shard per queue.
+    pub fn shard_of(&self, packet: &FiveTuple) -> usize {
+        let block = self.key.dimension.packet_value(packet) as u64 >> self.key.shift();
+        shard_of_block(block, self.shards.len())
+    }
+}
+
+/// Shard of key block `block` among `shards`.
+fn shard_of_block(block: u64, shards: usize) -> usize {
+    // Fibonacci hashing, then a multiply-shift reduction to [0, shards).
+    let hash = (block as u32).wrapping_mul(0x9E37_79B9) as u64;
+    ((hash * shards as u64) >> 32) as usize
+}
+
+/// Split plain `rules` into `shards` parts by `key`. A rule lands in every
+/// shard one of the key blocks its range covers hashes to.
+pub fn partition_by_key(rules: &[Rule], shards: usize, key: ShardKey) -> Vec<Vec<Rule>> {
+    let shift = key.shift();
+    let mut parts = vec![Vec::new(); shards];
+    let mut hit = vec![false; shards];
+    for rule in rules {
+        let range = key.dimension.rule_range(rule);
+        let (first, last) = ((range.min as u64) >> shift, (range.max as u64) >> shift);
+        if last - first >= MAX_BLOCKS_PER_SHARD * shards as u64 {
+            hit.fill(true);
+        } else {
+            hit.fill(false);
+            let mut left = shards;
+            for block in first..=last {
+                let shard = shard_of_block(block, shards);
+                if !hit[shard] {
+                    hit[shard] = true;
+                    left -= 1;
+                    if left == 0 {
+                        break;
+                    }
+                }
+            }
+        }
+        for (part, _) in parts.iter_mut().zip(&hit).filter(|(_, &h)| h) {
+            part.push(rule.clone());
+        }
+    }
+    parts
+}
+
+impl<C: Classifier> Classifier for ShardedClassifier<C> {
+    fn build(rules: &[Rule]) -> Self {
+        Self::build_with(rules, DEFAULT_SHARDS, ShardKey::default(), C::build)
+    }
+
+    fn classify_rule(&self, packet: &FiveTuple) -> Option<&Rule> {
+        self.shards[self.shard_of(packet)].classify_rule(packet)
+    }
+
+    fn classify_with_stats(&self, packet: &FiveTuple) -> (Option<Action>, LookupStats) {
+        let (action, mut stats) = self.shards[self.shard_of(packet)].classify_with_stats(packet);
+        // The shard dispatch counts as one level.
+        stats.depth += 1;
+        (action, stats)
+    }
+}
+
+impl<C: RegionQuery> RegionQuery for ShardedClassifier<C> {
+    fn rules_overlapping(&self, region: &Region) -> Vec<&Rule> {
+        let mut found = Overlapping::new(region);
+        for shard in &self.shards {
+            found.extend(shard.rules_overlapping(region));
+        }
+        found.finish()
+    }
+}
+
+impl<C: Freeze> Freeze for ShardedClassifier<C> {
+    fn shrink_to_fit(&mut self) {
+        for shard in &mut self.shards {
+            shard.shrink_to_fit();
+        }
+    }
+}
@@ -0,0 +1,132 @@
+//! Rule hit-rate estimation by sampling.
+//!
+//! Before counters are deployed, the share of traffic each rule will take can
+//! be estimated by classifying packets drawn from a model of the expected
+//! traffic: uniformly over (part of) the packet space, with the tuple mix of
+//! `generate_packets`, or from a flow trace with skewed flow sizes. Each rate
+//! is a plain sample proportion, with standard error
+//! `sqrt(rate * (1 - rate) / samples)`.
+
+use super::traffic::FlowDistribution;
+use super::Simulation;
+use crate::classifier::Classifier;
+use crate::geometry::Region;
+use crate::packet::FiveTuple;
+use alloc::vec::Vec;
+use hashbrown::HashMap;
+
+/// Distribution the estimator draws packets from.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum SamplingModel {
+    /// Every field uniform over its whole domain.
+    #[default]
+    Uniform,
+    /// Every field uniform within a region.
+    Within(Region),
+    /// The tuple mix of `Simulation::generate_packets`.
+    Traffic,
+    /// A trace of `n_flows` flows (as `generate_flows`) whose sizes follow
+    /// `distribution`.
+    Flows {
+        n_flows: usize,
+        distribution: FlowDistribution,
+    },
+}
+
+/// Estimated hit rate of one rule.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct HitRate {
+    /// Id of the rule.
+    pub rule_id: u32,
+    /// Sampled packets the rule matched.
+    pub hits: u64,
+    /// Estimated fraction of packets matching the rule.
+    pub rate: f64,
+}
+
+/// Result of `Simulation::estimate_hit_rates`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct HitEstimate {
+    /// Number of packets sampled.
+    pub samples: u64,
+    /// Sampled packets that matched no rule.
+    pub unmatched: u64,
+    /// Every rule hit at least once, by descending hits (ties by id).
+    pub rules: Vec<HitRate>,
+}
+
+impl HitEstimate {
+    /// Estimated hit rate of a rule (0.0 if it was never hit).
+    pub fn rate(&self, rule_id: u32) -> f64 {
+        self.rules
+            .iter()
+            .find(|r| r.rule_id == rule_id)
+            .map_or(0.0, |r| r.rate)
+    }
+
+    /// Estimated fraction of packets matching no rule.
+    pub fn unmatched_rate(&self) -> f64 {
+        if self.samples == 0 {
+            return 0.0;
+        }
+        self.unmatched as f64 / self.samples as f64
+    }
+
+    /// The `n` rules expected to take the most traffic.
+    pub fn top(&self, n: usize) -> &[HitRate] {
+        &self.rules[..n.min(self.rules.len())]
+    }
+}
+
+impl Simulation {
+    /// Estimate per-rule hit rates of `classifier` from `n_samples` packets
+    /// drawn from `model`.
+    pub fn estimate_hit_rates<C: Classifier>(
+        &mut self,
+        classifier: &C,
+        n_samples: usize,
+        model: &SamplingModel,
+    ) -> HitEstimate {
+        let packets = self.sample_model(n_samples, model);
+        let mut hits: HashMap<u32, u64> = HashMap::new();
+        let mut unmatched = 0;
+        for packet in &packets {
+            match classifier.classify_rule(packet) {
+                Some(rule) => *hits.entry(rule.id).or_default() += 1,
+                None => unmatched += 1,
+            }
+        }
+
+        let samples = packets.len() as u64;
+        let mut rules: Vec<HitRate> = hits
+            .into_iter()
+            .map(|(rule_id, hits)| HitRate {
+                rule_id,
+                hits,
+                rate: hits as f64 / samples as f64,
+            })
+            .collect();
+        rules.sort_by(|a, b| b.hits.cmp(&a.hits).then(a.rule_id.cmp(&b.rule_id)));
+        HitEstimate {
+            samples,
+            unmatched,
+            rules,
+        }
+    }
+
+    /// `n` packets drawn from `model`.
+    fn sample_model(&mut self, n: usize, model: &SamplingModel) -> Vec<FiveTuple> {
+        match model {
+            SamplingModel::Uniform => self.sample_packets_in(&Region::full(), n),
+            SamplingModel::Within(region) => self.sample_packets_in(region, n),
+            SamplingModel::Traffic => self.generate_packets(n),
+            SamplingModel::Flows {
+                n_flows,
+                distribution,
+            } => {
+                let flows = self.generate_flows(*n_flows);
+                self.generate_flow_trace(&flows, n, distribution)
+            }
+        }
+    }
+}
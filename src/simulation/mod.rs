@@ -1,20 +1,63 @@
-use crate::packet::{FiveTuple, PROTO_IGMP, PROTO_TCP, PROTO_UDP};
-use crate::rule::{Action, Range, Rule};
+pub mod estimate;
+pub mod topology;
+pub mod traffic;
+
+use crate::dimension::Dimension;
+use crate::geometry::Region;
+use crate::packet::{
+    FiveTuple, L4Key, IGMP_LEAVE_GROUP, IGMP_MEMBERSHIP_QUERY, IGMP_V2_MEMBERSHIP_REPORT,
+    IGMP_V3_MEMBERSHIP_REPORT, PROTO_IGMP, PROTO_TCP, PROTO_UDP,
+};
+use crate::rule::{Action, Range, Rule, ANY_ZONE};
 use alloc::vec::Vec;
 use rand::{Rng, SeedableRng};
 use rand_pcg::Pcg32;
 
+/// IGMP message types of generated packets.
+const IGMP_TYPES: [u8; 4] = [
+    IGMP_MEMBERSHIP_QUERY,
+    IGMP_V2_MEMBERSHIP_REPORT,
+    IGMP_LEAVE_GROUP,
+    IGMP_V3_MEMBERSHIP_REPORT,
+];
+
 pub struct Simulation {
     rng: Pcg32,
+    seed: u64,
 }
 
 impl Simulation {
     pub fn new(seed: u64) -> Self {
         Self {
             rng: Pcg32::seed_from_u64(seed),
+            seed,
+        }
+    }
+
+    /// Seed this simulation was created from.
+    pub fn seed(&self) -> u64 {
+        self.seed
+    }
+
+    /// Child simulation number `index`, with its own PRNG stream.
+    ///
+    /// Children depend only on the parent seed and `index` (not on how much of
+    /// the parent stream was consumed), so shards can be generated in any order,
+    /// on any thread or process, and still reproduce the same output. Children
+    /// can be split further.
+    pub fn child(&self, index: u64) -> Simulation {
+        let seed = splitmix64(self.seed ^ splitmix64(index));
+        Self {
+            rng: Pcg32::new(seed, index),
+            seed,
         }
     }
 
+    /// The first `n` child simulations (see [`Simulation::child`]).
+    pub fn split(&self, n: usize) -> Vec<Simulation> {
+        (0..n as u64).map(|i| self.child(i)).collect()
+    }
+
     pub fn generate_rules(&mut self, n_rules: usize) -> Vec<Rule> {
         let mut rules = Vec::with_capacity(n_rules);
 
@@ -49,6 +92,9 @@ impl Simulation {
             src_port: Range::any(0, 65535),
             dst_port: Range::any(0, 65535),
             proto: Range::any(0, 255),
+            zone: ANY_ZONE,
+            bidirectional: false,
+            field_sets: None,
             action: Action::Deny,
         });
 
@@ -74,6 +120,9 @@ impl Simulation {
             src_port: Range::any(1024, 65535),
             dst_port: Range::exact(self.gen_service_port()),
             proto: Range::exact(if self.rng.gen() { PROTO_TCP } else { PROTO_UDP }),
+            zone: ANY_ZONE,
+            bidirectional: false,
+            field_sets: None,
             action,
         }
     }
@@ -91,6 +140,9 @@ impl Simulation {
             src_port: Range::any(0, 65535),
             dst_port: Range::exact(80), // Web server in LAN
             proto: Range::exact(PROTO_TCP),
+            zone: ANY_ZONE,
+            bidirectional: false,
+            field_sets: None,
             action,
         }
     }
@@ -104,6 +156,9 @@ impl Simulation {
             src_port: Range::any(0, 65535),
             dst_port: Range::any(0, 65535),
             proto: Range::exact(PROTO_IGMP),
+            zone: ANY_ZONE,
+            bidirectional: false,
+            field_sets: None,
             action,
         }
     }
@@ -132,7 +187,7 @@ impl Simulation {
                 self.rng.gen()
             };
 
-            packets.push(FiveTuple {
+            let packet = FiveTuple {
                 src_ip,
                 dst_ip,
                 src_port: self.rng.gen(),
@@ -144,8 +199,50 @@ impl Simulation {
                 } else {
                     PROTO_UDP
                 },
+                zone: 0,
+            };
+            // IGMP carries its message type where the ports would be (see
+            // `L4Key`), drawn from the random source port.
+            packets.push(if packet.proto == PROTO_IGMP {
+                let igmp_type = IGMP_TYPES[packet.src_port as usize % IGMP_TYPES.len()];
+                packet.with_l4(L4Key::Igmp { igmp_type })
+            } else {
+                packet
             });
         }
         packets
     }
+
+    /// Generate a random packet guaranteed to fall inside `region`.
+    ///
+    /// Each field is drawn uniformly from the region's bounds.
+    pub fn sample_in_region(&mut self, region: &Region) -> FiveTuple {
+        let mut packet = FiveTuple::default();
+        for dim in Dimension::ALL {
+            let range = region.get(dim);
+            dim.set_packet_value(&mut packet, self.rng.gen_range(range.min..=range.max));
+        }
+        packet
+    }
+
+    /// Generate `n` random packets inside `region`.
+    pub fn sample_packets_in(&mut self, region: &Region, n: usize) -> Vec<FiveTuple> {
+        (0..n).map(|_| self.sample_in_region(region)).collect()
+    }
+
+    /// Generate a random packet matched by `rule` (ignoring higher-priority rules).
+    ///
+    /// The packet arrives on the lowest zone the rule accepts.
+    pub fn sample_for_rule(&mut self, rule: &Rule) -> FiveTuple {
+        self.sample_in_region(&Region::from_rule(rule))
+            .in_zone(rule.zone.min)
+    }
+}
+
+/// SplitMix64 finalizer, used to decorrelate derived seeds.
+fn splitmix64(x: u64) -> u64 {
+    let mut z = x.wrapping_add(0x9E37_79B9_7F4A_7C15);
+    z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+    z ^ (z >> 31)
 }
@@ -0,0 +1,343 @@
+//! Topology-aware rule generation.
+//!
+//! Models an enterprise network as a set of zones (WAN, DMZ, LAN, guest),
+//! each with its own subnet and servers exposing services, plus inter-zone
+//! policy templates. Rule sets generated from a `Topology` have the structure
+//! of real firewall policies: published services, outbound access per zone,
+//! host exceptions and zone-isolation blocks ending in a default deny.
+
+use super::Simulation;
+use crate::packet::{PROTO_TCP, PROTO_UDP};
+use crate::prefix::Prefix;
+use crate::rule::{Action, Range, Rule, ANY_ZONE};
+use alloc::vec::Vec;
+use rand::Rng;
+
+/// Role of a zone in the network.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ZoneKind {
+    /// The Internet.
+    Wan,
+    /// Publicly reachable servers.
+    Dmz,
+    /// Internal users and servers.
+    Lan,
+    /// Untrusted internal clients (guest Wi-Fi).
+    Guest,
+}
+
+/// A network zone with its address space.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Zone {
+    pub name: &'static str,
+    pub kind: ZoneKind,
+    pub subnet: Prefix<u32>,
+}
+
+/// A port/protocol pair offered by a server.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Service {
+    pub port: u16,
+    pub proto: u8,
+}
+
+impl Service {
+    pub const HTTP: Service = Service::tcp(80);
+    pub const HTTPS: Service = Service::tcp(443);
+    pub const DNS: Service = Service::udp(53);
+    pub const SMTP: Service = Service::tcp(25);
+    pub const SSH: Service = Service::tcp(22);
+    pub const LDAP: Service = Service::tcp(389);
+    pub const POSTGRES: Service = Service::tcp(5432);
+
+    pub const fn tcp(port: u16) -> Self {
+        Self {
+            port,
+            proto: PROTO_TCP,
+        }
+    }
+
+    pub const fn udp(port: u16) -> Self {
+        Self {
+            port,
+            proto: PROTO_UDP,
+        }
+    }
+}
+
+/// Services published by DMZ servers.
+const DMZ_SERVICES: [Service; 4] = [Service::HTTP, Service::HTTPS, Service::SMTP, Service::DNS];
+/// Services offered by internal servers.
+const LAN_SERVICES: [Service; 4] = [
+    Service::HTTPS,
+    Service::LDAP,
+    Service::POSTGRES,
+    Service::DNS,
+];
+/// Services users commonly reach on the Internet.
+const OUTBOUND_SERVICES: [Service; 4] = [
+    Service::HTTP,
+    Service::HTTPS,
+    Service::DNS,
+    Service::tcp(8080),
+];
+
+/// A host inside a zone exposing services.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Server {
+    /// Index into `Topology::zones`.
+    pub zone: usize,
+    pub addr: u32,
+    pub services: Vec<Service>,
+}
+
+/// What an inter-zone policy allows.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PolicyKind {
+    /// Clients may reach the services of the destination zone's servers.
+    Services,
+    /// Clients may reach any destination on common outbound ports.
+    Outbound,
+    /// Only web traffic is allowed.
+    WebOnly,
+    /// All traffic is blocked.
+    Deny,
+}
+
+/// Policy template between two zones.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ZonePolicy {
+    /// Source zone index.
+    pub from: usize,
+    /// Destination zone index.
+    pub to: usize,
+    pub kind: PolicyKind,
+}
+
+/// Zones, servers and inter-zone policies of a simulated network.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Topology {
+    pub zones: Vec<Zone>,
+    pub servers: Vec<Server>,
+    pub policies: Vec<ZonePolicy>,
+}
+
+impl Topology {
+    /// Typical enterprise layout without servers: WAN, DMZ, user LAN,
+    /// server LAN and guest network, with the usual inter-zone policies.
+    pub fn enterprise() -> Self {
+        let zones = alloc::vec![
+            Zone {
+                name: "wan",
+                kind: ZoneKind::Wan,
+                subnet: Prefix::new(0, 0),
+            },
+            Zone {
+                name: "dmz",
+                kind: ZoneKind::Dmz,
+                subnet: Prefix::new(0xCB00_7100, 24), // 203.0.113.0/24
+            },
+            Zone {
+                name: "lan-users",
+                kind: ZoneKind::Lan,
+                subnet: Prefix::new(0x0A01_0000, 16), // 10.1.0.0/16
+            },
+            Zone {
+                name: "lan-servers",
+                kind: ZoneKind::Lan,
+                subnet: Prefix::new(0x0A02_0000, 16), // 10.2.0.0/16
+            },
+            Zone {
+                name: "guest",
+                kind: ZoneKind::Guest,
+                subnet: Prefix::new(0xAC10_0000, 16), // 172.16.0.0/16
+            },
+        ];
+
+        use PolicyKind::*;
+        let (wan, dmz, users, servers, guest) = (0, 1, 2, 3, 4);
+        let policies = [
+            (wan, dmz, Services),
+            (users, wan, Outbound),
+            (users, dmz, Services),
+            (users, servers, Services),
+            (servers, wan, WebOnly),
+            (dmz, servers, Services),
+            (guest, wan, WebOnly),
+            (wan, users, Deny),
+            (wan, servers, Deny),
+            (guest, users, Deny),
+            (guest, servers, Deny),
+            (dmz, users, Deny),
+        ]
+        .into_iter()
+        .map(|(from, to, kind)| ZonePolicy { from, to, kind })
+        .collect();
+
+        Self {
+            zones,
+            servers: Vec::new(),
+            policies,
+        }
+    }
+
+    /// Servers located in zone `zone`.
+    pub fn servers_in(&self, zone: usize) -> impl Iterator<Item = &Server> {
+        self.servers.iter().filter(move |s| s.zone == zone)
+    }
+}
+
+impl Simulation {
+    /// Enterprise topology with `servers_per_zone` random servers in every
+    /// DMZ and LAN zone whose name ends in "servers" (user LANs stay client-only).
+    pub fn generate_topology(&mut self, servers_per_zone: usize) -> Topology {
+        let mut topology = Topology::enterprise();
+        for (zone_idx, zone) in topology.zones.iter().enumerate() {
+            let catalogue: &[Service] = match zone.kind {
+                ZoneKind::Dmz => &DMZ_SERVICES,
+                ZoneKind::Lan if zone.name.ends_with("servers") => &LAN_SERVICES,
+                _ => continue,
+            };
+            for _ in 0..servers_per_zone {
+                let addr = self.host_in(zone.subnet);
+                let count = self.rng.gen_range(1..=catalogue.len());
+                let first = self.rng.gen_range(0..catalogue.len());
+                let services = (0..count)
+                    .map(|k| catalogue[(first + k) % catalogue.len()])
+                    .collect();
+                topology.servers.push(Server {
+                    zone: zone_idx,
+                    addr,
+                    services,
+                });
+            }
+        }
+        topology
+    }
+
+    /// Generate a rule set from the topology: isolation rules between internal
+    /// zones, then `n_rules` rules from the permissive policies, then isolation
+    /// rules from the WAN and a default deny.
+    ///
+    /// Internal isolation comes first so "to any" permits (e.g. guest web
+    /// access) cannot open a path into protected zones. About one permissive
+    /// rule in ten is a host-level deny exception, as administrators add them
+    /// over time. Priorities follow rule order.
+    pub fn generate_enterprise_rules(&mut self, topology: &Topology, n_rules: usize) -> Vec<Rule> {
+        let mut rules = Vec::with_capacity(n_rules + topology.policies.len() + 1);
+        let isolation = |from_wan: bool| {
+            topology
+                .policies
+                .iter()
+                .filter(move |p| p.kind == PolicyKind::Deny)
+                .filter(move |p| (topology.zones[p.from].kind == ZoneKind::Wan) == from_wan)
+                .map(|p| isolation_rule(topology.zones[p.from].subnet, topology.zones[p.to].subnet))
+        };
+        let permits: Vec<&ZonePolicy> = topology
+            .policies
+            .iter()
+            .filter(|p| p.kind != PolicyKind::Deny)
+            .filter(|p| p.kind != PolicyKind::Services || topology.servers_in(p.to).count() > 0)
+            .collect();
+
+        rules.extend(isolation(false));
+        if !permits.is_empty() {
+            for _ in 0..n_rules {
+                let policy = permits[self.rng.gen_range(0..permits.len())];
+                let action = if self.rng.gen_bool(0.1) {
+                    Action::Deny
+                } else {
+                    Action::Permit
+                };
+                rules.push(self.gen_policy_rule(topology, policy, action));
+            }
+        }
+        rules.extend(isolation(true));
+        rules.push(isolation_rule(Prefix::new(0, 0), Prefix::new(0, 0)));
+
+        for (i, rule) in rules.iter_mut().enumerate() {
+            rule.id = i as u32;
+            rule.priority = i as u32;
+        }
+        rules
+    }
+
+    /// One rule instantiating `policy` (id and priority are set by the caller).
+    fn gen_policy_rule(
+        &mut self,
+        topology: &Topology,
+        policy: &ZonePolicy,
+        action: Action,
+    ) -> Rule {
+        let from = topology.zones[policy.from].subnet;
+        let to = topology.zones[policy.to].subnet;
+
+        let (dst_ip, service) = match policy.kind {
+            PolicyKind::Services => {
+                let servers: Vec<&Server> = topology.servers_in(policy.to).collect();
+                let server = servers[self.rng.gen_range(0..servers.len())];
+                let service = server.services[self.rng.gen_range(0..server.services.len())];
+                (Range::exact(server.addr), service)
+            }
+            PolicyKind::Outbound => {
+                let service = OUTBOUND_SERVICES[self.rng.gen_range(0..OUTBOUND_SERVICES.len())];
+                (self.client_block(to), service)
+            }
+            PolicyKind::WebOnly => {
+                let service = if self.rng.gen() {
+                    Service::HTTP
+                } else {
+                    Service::HTTPS
+                };
+                (self.client_block(to), service)
+            }
+            PolicyKind::Deny => return isolation_rule(from, to),
+        };
+
+        Rule {
+            id: 0,
+            priority: 0,
+            src_ip: self.client_block(from),
+            dst_ip,
+            src_port: Range::any(1024, 65535),
+            dst_port: Range::exact(service.port),
+            proto: Range::exact(service.proto),
+            zone: ANY_ZONE,
+            bidirectional: false,
+            field_sets: None,
+            action,
+        }
+    }
+
+    /// A random address inside `subnet`.
+    fn host_in(&mut self, subnet: Prefix<u32>) -> u32 {
+        let host_mask = (!0u32).checked_shr(subnet.len).unwrap_or(0);
+        subnet.first() | (self.rng.gen::<u32>() & host_mask)
+    }
+
+    /// The whole of `subnet` half the time, otherwise a random sub-block of it.
+    fn client_block(&mut self, subnet: Prefix<u32>) -> Range<u32> {
+        if self.rng.gen_bool(0.5) {
+            return subnet.to_range();
+        }
+        let len = (subnet.len + self.rng.gen_range(4..=16)).min(32);
+        Prefix::new(self.host_in(subnet), len).to_range()
+    }
+}
+
+/// Block everything from `from` to `to` (id and priority are set by the caller).
+fn isolation_rule(from: Prefix<u32>, to: Prefix<u32>) -> Rule {
+    Rule {
+        id: 0,
+        priority: 0,
+        src_ip: from.to_range(),
+        dst_ip: to.to_range(),
+        src_port: Range::any(0, 65535),
+        dst_port: Range::any(0, 65535),
+        proto: Range::any(0, 255),
+        zone: ANY_ZONE,
+        bidirectional: false,
+        field_sets: None,
+        action: Action::Deny,
+    }
+}
@@ -0,0 +1,239 @@
+//! Flow-level traffic generation.
+//!
+//! `generate_packets` draws every packet independently, which never repeats a
+//! 5-tuple and has no notion of direction. The generators here work on flows
+//! instead: port-NAT'd outbound connections sharing a small pool of public
+//! addresses and ephemeral ports, and request/response exchanges where both
+//! directions of a flow (or only one, on asymmetric paths) show up in the
+//! trace. This is the traffic that flow caches and connection tracking see.
+//!
+//! Flow sizes follow a `FlowDistribution`: real traces are dominated by a few
+//! heavy hitters, which makes them far more cache-friendly than uniformly
+//! random 5-tuples.
+
+use super::Simulation;
+use crate::packet::{FiveTuple, PROTO_TCP, PROTO_UDP};
+use crate::rule::Range;
+use alloc::vec::Vec;
+use rand::Rng;
+
+/// Public addresses and ports used by a source-NAT gateway.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct NatPool {
+    pub addrs: Range<u32>,
+    pub ports: Range<u16>,
+}
+
+impl Default for NatPool {
+    /// One public address (198.51.100.1) with 256 ephemeral ports.
+    fn default() -> Self {
+        Self {
+            addrs: Range::exact(0xC633_6401),
+            ports: Range::new(1024, 1279),
+        }
+    }
+}
+
+impl NatPool {
+    pub fn new(addrs: Range<u32>, ports: Range<u16>) -> Self {
+        Self { addrs, ports }
+    }
+
+    /// Number of distinct (address, port) pairs the pool can hand out.
+    pub fn capacity(&self) -> u64 {
+        let addrs = (self.addrs.max - self.addrs.min) as u64 + 1;
+        let ports = (self.ports.max - self.ports.min) as u64 + 1;
+        addrs * ports
+    }
+}
+
+/// A connection, identified by the tuple of its initiating direction.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Flow {
+    pub request: FiveTuple,
+}
+
+impl Flow {
+    /// Tuple of the packets flowing back to the initiator.
+    pub fn response(&self) -> FiveTuple {
+        self.request.reversed()
+    }
+}
+
+/// How packets are spread over flows.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum FlowDistribution {
+    /// Every flow is equally likely.
+    #[default]
+    Uniform,
+    /// The first `fraction` of flows carry `share` of the packets
+    /// (e.g. 0.1 / 0.9: 10% of flows carry 90% of packets).
+    HeavyHitters { fraction: f64, share: f64 },
+    /// The `i`-th flow (1-based) has weight `1 / i^exponent`.
+    Zipf { exponent: u32 },
+}
+
+impl FlowDistribution {
+    /// Relative weight of each of `n` flows.
+    fn weights(&self, n: usize) -> Vec<f64> {
+        match *self {
+            FlowDistribution::Uniform => alloc::vec![1.0; n],
+            FlowDistribution::HeavyHitters { fraction, share } => {
+                let heavy = ((n as f64 * fraction) as usize).clamp(1, n);
+                let light = n - heavy;
+                (0..n)
+                    .map(|i| {
+                        if i < heavy {
+                            share / heavy as f64
+                        } else {
+                            (1.0 - share) / light as f64
+                        }
+                    })
+                    .collect()
+            }
+            FlowDistribution::Zipf { exponent } => (1..=n)
+                .map(|rank| {
+                    let mut w = 1.0;
+                    for _ in 0..exponent {
+                        w /= rank as f64;
+                    }
+                    w
+                })
+                .collect(),
+        }
+    }
+}
+
+/// Draws flow indices according to a `FlowDistribution`.
+struct FlowSampler {
+    cumulative: Vec<f64>,
+}
+
+impl FlowSampler {
+    fn new(distribution: &FlowDistribution, n_flows: usize) -> Self {
+        let mut total = 0.0;
+        let cumulative = distribution
+            .weights(n_flows)
+            .into_iter()
+            .map(|w| {
+                total += w;
+                total
+            })
+            .collect();
+        Self { cumulative }
+    }
+
+    fn sample(&self, sim: &mut Simulation) -> usize {
+        let total = self.cumulative.last().copied().unwrap_or(0.0);
+        let x = sim.rng.gen::<f64>() * total;
+        self.cumulative
+            .partition_point(|&c| c <= x)
+            .min(self.cumulative.len() - 1)
+    }
+}
+
+/// Shape of request/response exchanges.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Exchange {
+    /// Average number of response packets per request packet (downloads dominate).
+    pub responses_per_request: u32,
+    /// Fraction of flows whose responses take another path and never appear.
+    pub one_way: f64,
+    /// How packets are spread over flows.
+    pub distribution: FlowDistribution,
+}
+
+impl Default for Exchange {
+    fn default() -> Self {
+        Self {
+            responses_per_request: 3,
+            one_way: 0.0,
+            distribution: FlowDistribution::Uniform,
+        }
+    }
+}
+
+/// Destination ports of outbound connections.
+const NAT_SERVICE_PORTS: [u16; 4] = [80, 443, 53, 8080];
+
+impl Simulation {
+    /// `n_flows` outbound connections as seen after source NAT.
+    ///
+    /// Sources come from `pool`, so ephemeral ports are reused across flows
+    /// once the pool is smaller than the flow count; destinations are public
+    /// servers on common service ports (DNS over UDP, the rest over TCP).
+    pub fn generate_nat_flows(&mut self, pool: &NatPool, n_flows: usize) -> Vec<Flow> {
+        (0..n_flows)
+            .map(|_| {
+                let dst_port = NAT_SERVICE_PORTS[self.rng.gen_range(0..NAT_SERVICE_PORTS.len())];
+                Flow {
+                    request: FiveTuple {
+                        src_ip: self.rng.gen_range(pool.addrs.min..=pool.addrs.max),
+                        dst_ip: self.rng.gen(),
+                        src_port: self.rng.gen_range(pool.ports.min..=pool.ports.max),
+                        dst_port,
+                        proto: if dst_port == 53 { PROTO_UDP } else { PROTO_TCP },
+                        zone: 0,
+                    },
+                }
+            })
+            .collect()
+    }
+
+    /// `n_flows` flows with the same tuple mix as `generate_packets`.
+    pub fn generate_flows(&mut self, n_flows: usize) -> Vec<Flow> {
+        self.generate_packets(n_flows)
+            .into_iter()
+            .map(|request| Flow { request })
+            .collect()
+    }
+
+    /// `n_packets` request packets drawn from `flows` according to `distribution`.
+    pub fn generate_flow_trace(
+        &mut self,
+        flows: &[Flow],
+        n_packets: usize,
+        distribution: &FlowDistribution,
+    ) -> Vec<FiveTuple> {
+        if flows.is_empty() {
+            return Vec::new();
+        }
+        let sampler = FlowSampler::new(distribution, flows.len());
+        (0..n_packets)
+            .map(|_| flows[sampler.sample(self)].request)
+            .collect()
+    }
+
+    /// `n_packets` packets from `flows`, in both directions, in arrival order.
+    ///
+    /// Each packet belongs to a flow drawn from `exchange.distribution` and is a response with
+    /// probability `k / (k + 1)` for `k = responses_per_request`, unless the
+    /// flow is one-way, in which case only requests are emitted.
+    pub fn generate_exchanges(
+        &mut self,
+        flows: &[Flow],
+        n_packets: usize,
+        exchange: &Exchange,
+    ) -> Vec<FiveTuple> {
+        if flows.is_empty() {
+            return Vec::new();
+        }
+        let one_way: Vec<bool> = flows
+            .iter()
+            .map(|_| self.rng.gen_bool(exchange.one_way))
+            .collect();
+        let k = exchange.responses_per_request;
+        let sampler = FlowSampler::new(&exchange.distribution, flows.len());
+
+        (0..n_packets)
+            .map(|_| {
+                let i = sampler.sample(self);
+                if !one_way[i] && self.rng.gen_range(0..=k) > 0 {
+                    flows[i].response()
+                } else {
+                    flows[i].request
+                }
+            })
+            .collect()
+    }
+}
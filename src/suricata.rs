@@ -0,0 +1,443 @@
+//! Suricata/Snort rule headers.
+//!
+//! Reads the header of IDS rules, one rule per line, so the packets an IDS
+//! rule set can apply to can be pre-filtered with this crate's classifiers:
+//!
+//! ```text
+//! alert tcp $HOME_NET any -> [10.0.0.0/8,!10.1.0.0/16] [80,8000:8100] (msg:"web"; sid:1000001;)
+//! ```
+//!
+//! (action, protocol, source addresses and ports, direction, destination
+//! addresses and ports). The options in parentheses are ignored except for
+//! `sid`, which becomes the rule id; rules without one get their 0-based
+//! index. Priorities follow the file order.
+//!
+//! Addresses and ports are `any`, a value (`a.b.c.d`, `a.b.c.d/len`, a
+//! port), a port range (`lo:hi`, `lo:` or `:hi`), a `$VARIABLE` defined in
+//! `Variables`, a negation `!x`, or a list `[x, y, ...]` of any of these,
+//! possibly nested. Negated list entries are excluded from the union of the
+//! others (or from everything when there are none). Fields matching several
+//! ranges become `FieldSets` on the rule.
+//!
+//! `pass` rules are permits, `drop` and `reject` rules denies, and alerting
+//! (`alert`, `log`) rules are permits: the classifier only tells which
+//! header matched. `<>` makes a bidirectional rule. Application-layer
+//! protocols are read as their transport (`http` as TCP, `dns` as TCP or
+//! UDP). IPv6 addresses are rejected with `ParseErrorKind::Ipv6`, so callers
+//! can skip such rules.
+//!
+//! Blank lines and lines starting with `#` (disabled rules) are skipped; a
+//! line ending with `\` continues on the next one.
+
+use crate::packet::{PROTO_ICMP, PROTO_TCP, PROTO_UDP};
+use crate::prefix::Prefix;
+use crate::rule::{complement, Action, FieldSets, Range, Rule, ANY_ZONE};
+use alloc::boxed::Box;
+use alloc::string::String;
+use alloc::vec::Vec;
+use core::fmt;
+use core::net::Ipv4Addr;
+use hashbrown::HashMap;
+
+/// Deepest `$VARIABLE` nesting followed (guards against cycles).
+const MAX_VARIABLE_DEPTH: usize = 16;
+
+/// Deepest nesting of `!` and `[...]` in a value (guards the stack against
+/// hostile rules).
+const MAX_NESTING: usize = 64;
+
+/// Error while parsing a rule file.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParseError {
+    /// 1-based line number (of the rule's first line).
+    pub line: usize,
+    pub kind: ParseErrorKind,
+}
+
+/// What was wrong with a rule.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ParseErrorKind {
+    /// A header field is missing.
+    MissingField,
+    /// Unknown action.
+    BadAction,
+    /// Unknown protocol.
+    BadProtocol,
+    /// Malformed address, prefix or address list.
+    BadAddress,
+    /// IPv6 address (not supported).
+    Ipv6,
+    /// Malformed port, port range or port list.
+    BadPort,
+    /// Direction other than `->` or `<>`.
+    BadDirection,
+    /// Variable not defined, or defined in terms of itself.
+    UnknownVariable(String),
+    /// An address or port field excludes every value.
+    EmptySet,
+    /// Malformed `sid` option.
+    BadSid,
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "line {}: {:?}", self.line, self.kind)
+    }
+}
+
+/// Values of the `$VARIABLES` used in rule headers (`HOME_NET`, `HTTP_PORTS`...).
+#[derive(Debug, Clone, Default)]
+pub struct Variables {
+    values: HashMap<String, String>,
+}
+
+impl Variables {
+    /// No variables defined.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Define `name` (without the `$`) as `value`, in header syntax.
+    pub fn set(&mut self, name: &str, value: &str) -> &mut Self {
+        self.values.insert(name.into(), value.into());
+        self
+    }
+
+    /// The value of `name`.
+    pub fn get(&self, name: &str) -> Option<&str> {
+        self.values.get(name).map(String::as_str)
+    }
+}
+
+/// Parse a rule file.
+pub fn parse_rules(input: &str, vars: &Variables) -> Result<Vec<Rule>, ParseError> {
+    let mut rules = Vec::new();
+    let mut pending = String::new();
+    let mut first_line = 0;
+    for (i, line) in input.lines().enumerate() {
+        let line = line.trim();
+        if pending.is_empty() {
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            first_line = i + 1;
+        }
+        if let Some(start) = line.strip_suffix('\\') {
+            pending.push_str(start);
+            continue;
+        }
+        pending.push_str(line);
+        let index = rules.len() as u32;
+        let (mut rule, sid) = parse_line(&pending, vars).map_err(|kind| ParseError {
+            line: first_line,
+            kind,
+        })?;
+        rule.id = sid.unwrap_or(index);
+        rule.priority = index;
+        rules.push(rule);
+        pending.clear();
+    }
+    if !pending.is_empty() {
+        return Err(ParseError {
+            line: first_line,
+            kind: ParseErrorKind::MissingField,
+        });
+    }
+    Ok(rules)
+}
+
+/// Parse one rule (header and options). The rule id is its `sid` (0 if it
+/// has none) and its priority 0.
+pub fn parse_rule(rule: &str, vars: &Variables) -> Result<Rule, ParseErrorKind> {
+    let (mut rule, sid) = parse_line(rule, vars)?;
+    rule.id = sid.unwrap_or(0);
+    Ok(rule)
+}
+
+/// A rule with id and priority 0, and its `sid`.
+fn parse_line(rule: &str, vars: &Variables) -> Result<(Rule, Option<u32>), ParseErrorKind> {
+    let (header, options) = match rule.split_once('(') {
+        Some((header, options)) => (header, Some(options)),
+        None => (rule, None),
+    };
+    let fields = tokens(header);
+    let [action, proto, src_ip, src_port, direction, dst_ip, dst_port] =
+        <[&str; 7]>::try_from(fields).map_err(|_| ParseErrorKind::MissingField)?;
+
+    let action = match action {
+        "alert" | "log" | "pass" => Action::Permit,
+        "drop" | "reject" | "rejectsrc" | "rejectdst" | "rejectboth" => Action::Deny,
+        _ => return Err(ParseErrorKind::BadAction),
+    };
+    let bidirectional = match direction {
+        "->" => false,
+        "<>" => true,
+        _ => return Err(ParseErrorKind::BadDirection),
+    };
+    let proto = protocols(proto)?;
+    let src_ip = field(src_ip, vars, u32::MAX, &address, ParseErrorKind::BadAddress)?;
+    let dst_ip = field(dst_ip, vars, u32::MAX, &address, ParseErrorKind::BadAddress)?;
+    let src_port = field(
+        src_port,
+        vars,
+        u16::MAX as u32,
+        &ports,
+        ParseErrorKind::BadPort,
+    )?;
+    let dst_port = field(
+        dst_port,
+        vars,
+        u16::MAX as u32,
+        &ports,
+        ParseErrorKind::BadPort,
+    )?;
+
+    let narrow16 = |r: &Range<u32>| Range::new(r.min as u16, r.max as u16);
+    let narrow8 = |r: &Range<u32>| Range::new(r.min as u8, r.max as u8);
+    let sets = FieldSets {
+        src_ip: src_ip[1..].to_vec(),
+        dst_ip: dst_ip[1..].to_vec(),
+        src_port: src_port[1..].iter().map(narrow16).collect(),
+        dst_port: dst_port[1..].iter().map(narrow16).collect(),
+        proto: proto[1..].iter().map(narrow8).collect(),
+        ..FieldSets::default()
+    };
+    let rule = Rule {
+        id: 0,
+        priority: 0,
+        src_ip: src_ip[0],
+        dst_ip: dst_ip[0],
+        src_port: narrow16(&src_port[0]),
+        dst_port: narrow16(&dst_port[0]),
+        proto: narrow8(&proto[0]),
+        zone: ANY_ZONE,
+        bidirectional,
+        field_sets: (!sets.is_empty()).then(|| Box::new(sets)),
+        action,
+    };
+    Ok((rule, options.map_or(Ok(None), sid)?))
+}
+
+/// Header fields: whitespace-separated, except inside brackets.
+fn tokens(header: &str) -> Vec<&str> {
+    let mut out = Vec::new();
+    let mut depth = 0usize;
+    let mut start = None;
+    for (i, c) in header.char_indices() {
+        match c {
+            '[' => depth += 1,
+            ']' => depth = depth.saturating_sub(1),
+            c if c.is_whitespace() && depth == 0 => {
+                if let Some(s) = start.take() {
+                    out.push(&header[s..i]);
+                }
+                continue;
+            }
+            _ => {}
+        }
+        start.get_or_insert(i);
+    }
+    if let Some(s) = start {
+        out.push(&header[s..]);
+    }
+    out
+}
+
+/// The `sid` option, if present.
+fn sid(options: &str) -> Result<Option<u32>, ParseErrorKind> {
+    for option in split_options(options) {
+        if let Some((name, value)) = option.split_once(':') {
+            if name.trim() == "sid" {
+                return value
+                    .trim()
+                    .parse()
+                    .map(Some)
+                    .map_err(|_| ParseErrorKind::BadSid);
+            }
+        }
+    }
+    Ok(None)
+}
+
+/// Options separated by unescaped `;` outside quotes (`options` is the text
+/// after the opening parenthesis).
+fn split_options(options: &str) -> impl Iterator<Item = &str> {
+    let mut quoted = false;
+    let mut escaped = false;
+    let mut start = 0;
+    let mut cuts = Vec::new();
+    for (i, c) in options.char_indices() {
+        match c {
+            _ if escaped => escaped = false,
+            '\\' => escaped = true,
+            '"' => quoted = !quoted,
+            ';' if !quoted => {
+                cuts.push(&options[start..i]);
+                start = i + 1;
+            }
+            _ => {}
+        }
+    }
+    let last = options[start..].trim_end();
+    cuts.push(last.strip_suffix(')').unwrap_or(last));
+    cuts.into_iter()
+}
+
+/// Protocol keyword to the protocol numbers it matches.
+fn protocols(s: &str) -> Result<Vec<Range<u32>>, ParseErrorKind> {
+    let tcp = Range::exact(PROTO_TCP as u32);
+    let udp = Range::exact(PROTO_UDP as u32);
+    Ok(match s.to_ascii_lowercase().as_str() {
+        "ip" | "any" | "pkthdr" => alloc::vec![Range::new(0, u8::MAX as u32)],
+        "tcp" | "tcp-pkt" | "tcp-stream" | "http" | "http2" | "tls" | "ssh" | "ftp" | "smtp"
+        | "imap" | "smb" | "dcerpc" | "rdp" => alloc::vec![tcp],
+        "udp" | "dhcp" | "ntp" | "snmp" | "tftp" | "sip" => alloc::vec![udp],
+        "dns" => alloc::vec![tcp, udp],
+        "icmp" => alloc::vec![Range::exact(PROTO_ICMP as u32)],
+        _ => return Err(ParseErrorKind::BadProtocol),
+    })
+}
+
+/// A single address or prefix.
+fn address(s: &str) -> Result<Range<u32>, ParseErrorKind> {
+    if s.contains(':') {
+        return Err(ParseErrorKind::Ipv6);
+    }
+    let addr = |s: &str| {
+        s.parse::<Ipv4Addr>()
+            .map(u32::from)
+            .map_err(|_| ParseErrorKind::BadAddress)
+    };
+    match s.split_once('/') {
+        Some((value, len)) => {
+            let value = addr(value)?;
+            match len.parse::<u32>() {
+                Ok(len) if len <= 32 => Ok(Prefix::new(value, len).to_range()),
+                _ => Err(ParseErrorKind::BadAddress),
+            }
+        }
+        None => addr(s).map(Range::exact),
+    }
+}
+
+/// A single port or port range.
+fn ports(s: &str) -> Result<Range<u32>, ParseErrorKind> {
+    let port = |s: &str| s.parse::<u16>().map_err(|_| ParseErrorKind::BadPort);
+    // Either bound of a range may be left open (`1024:`, `:1023`).
+    let bound = |s: &str, open: u16| if s.is_empty() { Ok(open) } else { port(s) };
+    let (lo, hi) = match s.split_once(':') {
+        Some((lo, hi)) => (bound(lo, 0)?, bound(hi, u16::MAX)?),
+        None => (port(s)?, port(s)?),
+    };
+    if lo > hi {
+        return Err(ParseErrorKind::BadPort);
+    }
+    Ok(Range::new(lo as u32, hi as u32))
+}
+
+/// The ranges of `0..=max` matched by an address or port field, ascending
+/// and disjoint.
+fn field(
+    s: &str,
+    vars: &Variables,
+    max: u32,
+    atom: &dyn Fn(&str) -> Result<Range<u32>, ParseErrorKind>,
+    malformed: ParseErrorKind,
+) -> Result<Vec<Range<u32>>, ParseErrorKind> {
+    let set = Values {
+        vars,
+        max,
+        atom,
+        malformed,
+    }
+    .parse(s, 0, 0)?;
+    if set.is_empty() {
+        return Err(ParseErrorKind::EmptySet);
+    }
+    Ok(set)
+}
+
+/// Parser of address or port values into sets of ranges.
+struct Values<'a> {
+    vars: &'a Variables,
+    max: u32,
+    atom: &'a dyn Fn(&str) -> Result<Range<u32>, ParseErrorKind>,
+    malformed: ParseErrorKind,
+}
+
+impl Values<'_> {
+    /// Ranges matched by `s`, normalized (ascending and disjoint), `depth`
+    /// variables and `nesting` negations or lists deep.
+    fn parse(
+        &self,
+        s: &str,
+        depth: usize,
+        nesting: usize,
+    ) -> Result<Vec<Range<u32>>, ParseErrorKind> {
+        let s = s.trim();
+        if nesting > MAX_NESTING {
+            return Err(self.malformed.clone());
+        }
+        if let Some(rest) = s.strip_prefix('!') {
+            return Ok(complement(self.parse(rest, depth, nesting + 1)?, self.max));
+        }
+        if let Some(name) = s.strip_prefix('$') {
+            let value = self
+                .vars
+                .get(name)
+                .filter(|_| depth < MAX_VARIABLE_DEPTH)
+                .ok_or_else(|| ParseErrorKind::UnknownVariable(name.into()))?;
+            return self.parse(value, depth + 1, nesting);
+        }
+        if s.eq_ignore_ascii_case("any") {
+            return Ok(alloc::vec![Range::new(0, self.max)]);
+        }
+        let Some(list) = s.strip_prefix('[') else {
+            return Ok(alloc::vec![(self.atom)(s)?]);
+        };
+        let list = list
+            .strip_suffix(']')
+            .ok_or_else(|| self.malformed.clone())?;
+
+        let mut included = Vec::new();
+        let mut excluded = Vec::new();
+        let mut any_included = false;
+        for item in list_items(list) {
+            let item = item.trim();
+            match item.strip_prefix('!') {
+                Some(negated) => excluded.extend(self.parse(negated, depth, nesting + 1)?),
+                None => {
+                    any_included = true;
+                    included.extend(self.parse(item, depth, nesting + 1)?);
+                }
+            }
+        }
+        if !any_included {
+            included.push(Range::new(0, self.max));
+        }
+        // included minus excluded = not (not included or excluded).
+        let mut outside = complement(included, self.max);
+        outside.extend(excluded);
+        Ok(complement(outside, self.max))
+    }
+}
+
+/// Comma-separated items of a list, keeping nested lists whole.
+fn list_items(list: &str) -> Vec<&str> {
+    let mut out = Vec::new();
+    let mut depth = 0usize;
+    let mut start = 0;
+    for (i, c) in list.char_indices() {
+        match c {
+            '[' => depth += 1,
+            ']' => depth = depth.saturating_sub(1),
+            ',' if depth == 0 => {
+                out.push(&list[start..i]);
+                start = i + 1;
+            }
+            _ => {}
+        }
+    }
+    out.push(&list[start..]);
+    out
+}
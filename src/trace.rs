@@ -0,0 +1,20 @@
+//! Optional build tracing.
+//!
+//! `build_trace!` forwards to `log::trace!` and/or `defmt::trace!` when the
+//! corresponding feature is enabled and compiles to nothing otherwise, so
+//! builders can report construction steps (leaf sizes, depths) without any
+//! cost in default builds. Messages must only use `{}` placeholders, which
+//! both backends understand.
+
+macro_rules! build_trace {
+    ($($arg:tt)*) => {{
+        #[cfg(feature = "log")]
+        log::trace!($($arg)*);
+        #[cfg(feature = "defmt")]
+        defmt::trace!($($arg)*);
+        #[cfg(not(any(feature = "log", feature = "defmt")))]
+        let _ = ($($arg)*);
+    }};
+}
+
+pub(crate) use build_trace;
@@ -0,0 +1,277 @@
+//! Path-compressed binary trie with longest-prefix-match lookup.
+//!
+//! `PrefixTrie` maps prefixes of any supported width (`u8` up to `u128`) to
+//! values. Nodes live in a single arena and refer to each other by index, so
+//! the structure is cheap to clone and works in no_std. Chains of single-child
+//! nodes are collapsed (Patricia style): every node either carries a value or
+//! branches, keeping lookups to at most one step per distinct stored length.
+
+use crate::prefix::{Prefix, PrefixValue};
+use alloc::vec::Vec;
+
+const NONE: u32 = u32::MAX;
+
+#[derive(Debug, Clone)]
+struct TrieNode<T, V> {
+    prefix: Prefix<T>,
+    value: Option<V>,
+    children: [u32; 2],
+}
+
+/// Longest-prefix-match trie keyed by prefixes.
+#[derive(Debug, Clone)]
+pub struct PrefixTrie<T, V> {
+    nodes: Vec<TrieNode<T, V>>,
+    free: Vec<u32>,
+    len: usize,
+}
+
+impl<T: PrefixValue, V> Default for PrefixTrie<T, V> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T: PrefixValue, V> PrefixTrie<T, V> {
+    /// Create an empty trie.
+    pub fn new() -> Self {
+        Self {
+            nodes: alloc::vec![TrieNode {
+                prefix: Prefix::new(T::from_u128(0), 0),
+                value: None,
+                children: [NONE; 2],
+            }],
+            free: Vec::new(),
+            len: 0,
+        }
+    }
+
+    /// Number of stored prefixes.
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    /// Returns true if no prefix is stored.
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Insert `value` under `prefix`, returning the previous value if any.
+    pub fn insert(&mut self, prefix: Prefix<T>, value: V) -> Option<V> {
+        let key = Prefix::new(prefix.value, prefix.len);
+        let mut idx = 0u32;
+
+        loop {
+            let node_len = self.nodes[idx as usize].prefix.len;
+            if node_len == key.len {
+                let old = self.nodes[idx as usize].value.replace(value);
+                if old.is_none() {
+                    self.len += 1;
+                }
+                return old;
+            }
+
+            let bit = bit_at(key.value, node_len);
+            let child = self.nodes[idx as usize].children[bit];
+            if child == NONE {
+                let leaf = self.alloc(key, Some(value));
+                self.nodes[idx as usize].children[bit] = leaf;
+                self.len += 1;
+                return None;
+            }
+
+            let child_prefix = self.nodes[child as usize].prefix;
+            let common = common_len(&child_prefix, &key);
+            if common >= child_prefix.len {
+                // Child covers the key: descend.
+                idx = child;
+                continue;
+            }
+
+            if common == key.len {
+                // Key covers the child: splice a new node above it.
+                let node = self.alloc(key, Some(value));
+                self.nodes[node as usize].children[bit_at(child_prefix.value, key.len)] = child;
+                self.nodes[idx as usize].children[bit] = node;
+                self.len += 1;
+                return None;
+            }
+
+            // Diverge below `common` bits: add a branching node.
+            let branch = self.alloc(Prefix::new(key.value, common), None);
+            let leaf = self.alloc(key, Some(value));
+            self.nodes[branch as usize].children[bit_at(child_prefix.value, common)] = child;
+            self.nodes[branch as usize].children[bit_at(key.value, common)] = leaf;
+            self.nodes[idx as usize].children[bit] = branch;
+            self.len += 1;
+            return None;
+        }
+    }
+
+    /// Value stored under exactly `prefix`.
+    pub fn get(&self, prefix: Prefix<T>) -> Option<&V> {
+        let key = Prefix::new(prefix.value, prefix.len);
+        self.find(&key)
+            .and_then(|(idx, _)| self.nodes[idx as usize].value.as_ref())
+    }
+
+    /// Mutable value stored under exactly `prefix`.
+    pub fn get_mut(&mut self, prefix: Prefix<T>) -> Option<&mut V> {
+        let key = Prefix::new(prefix.value, prefix.len);
+        let (idx, _) = self.find(&key)?;
+        self.nodes[idx as usize].value.as_mut()
+    }
+
+    /// Remove `prefix`, returning its value.
+    pub fn remove(&mut self, prefix: Prefix<T>) -> Option<V> {
+        let key = Prefix::new(prefix.value, prefix.len);
+        let (idx, parent) = self.find(&key)?;
+        let value = self.nodes[idx as usize].value.take()?;
+        self.len -= 1;
+
+        if idx != 0 {
+            // Collapse the node if it no longer carries a value and branches at most once.
+            let children = self.nodes[idx as usize].children;
+            let live: Vec<u32> = children.iter().copied().filter(|&c| c != NONE).collect();
+            if live.len() <= 1 {
+                let replacement = live.first().copied().unwrap_or(NONE);
+                let slot = self.nodes[parent as usize]
+                    .children
+                    .iter_mut()
+                    .find(|c| **c == idx)
+                    .expect("parent links to child");
+                *slot = replacement;
+                self.release(idx);
+            }
+        }
+        Some(value)
+    }
+
+    /// Longest stored prefix containing `value`, with its associated value.
+    pub fn longest_match(&self, value: T) -> Option<(Prefix<T>, &V)> {
+        let mut best = None;
+        let mut idx = 0u32;
+        loop {
+            let node = &self.nodes[idx as usize];
+            if !node.prefix.contains(value) {
+                break;
+            }
+            if let Some(v) = &node.value {
+                best = Some((node.prefix, v));
+            }
+            if node.prefix.len >= T::BITS {
+                break;
+            }
+            let next = node.children[bit_at(value, node.prefix.len)];
+            if next == NONE {
+                break;
+            }
+            idx = next;
+        }
+        best
+    }
+
+    /// Returns true if any stored prefix contains `value`.
+    pub fn contains(&self, value: T) -> bool {
+        self.longest_match(value).is_some()
+    }
+
+    /// All stored prefixes containing `value`, shortest first.
+    pub fn matches(&self, value: T) -> Vec<(Prefix<T>, &V)> {
+        let mut out = Vec::new();
+        let mut idx = 0u32;
+        loop {
+            let node = &self.nodes[idx as usize];
+            if !node.prefix.contains(value) {
+                break;
+            }
+            if let Some(v) = &node.value {
+                out.push((node.prefix, v));
+            }
+            if node.prefix.len >= T::BITS {
+                break;
+            }
+            let next = node.children[bit_at(value, node.prefix.len)];
+            if next == NONE {
+                break;
+            }
+            idx = next;
+        }
+        out
+    }
+
+    /// All stored `(prefix, value)` pairs in pre-order (shorter, lower prefixes first).
+    pub fn iter(&self) -> Vec<(Prefix<T>, &V)> {
+        let mut out = Vec::with_capacity(self.len);
+        let mut stack = alloc::vec![0u32];
+        while let Some(idx) = stack.pop() {
+            let node = &self.nodes[idx as usize];
+            if let Some(v) = &node.value {
+                out.push((node.prefix, v));
+            }
+            for &child in node.children.iter().rev() {
+                if child != NONE {
+                    stack.push(child);
+                }
+            }
+        }
+        out
+    }
+
+    /// Locate the node holding exactly `key`, returning `(node, parent)`.
+    fn find(&self, key: &Prefix<T>) -> Option<(u32, u32)> {
+        let mut idx = 0u32;
+        let mut parent = 0u32;
+        loop {
+            let node = &self.nodes[idx as usize];
+            if node.prefix.len == key.len {
+                return (node.prefix == *key).then_some((idx, parent));
+            }
+            if node.prefix.len > key.len || !node.prefix.covers(key) {
+                return None;
+            }
+            let next = node.children[bit_at(key.value, node.prefix.len)];
+            if next == NONE {
+                return None;
+            }
+            parent = idx;
+            idx = next;
+        }
+    }
+
+    fn alloc(&mut self, prefix: Prefix<T>, value: Option<V>) -> u32 {
+        let node = TrieNode {
+            prefix,
+            value,
+            children: [NONE; 2],
+        };
+        match self.free.pop() {
+            Some(idx) => {
+                self.nodes[idx as usize] = node;
+                idx
+            }
+            None => {
+                self.nodes.push(node);
+                (self.nodes.len() - 1) as u32
+            }
+        }
+    }
+
+    fn release(&mut self, idx: u32) {
+        let node = &mut self.nodes[idx as usize];
+        node.value = None;
+        node.children = [NONE; 2];
+        self.free.push(idx);
+    }
+}
+
+/// Bit `pos` (0 = most significant) of `value`.
+fn bit_at<T: PrefixValue>(value: T, pos: u32) -> usize {
+    ((value.to_u128() >> (T::BITS - 1 - pos)) & 1) as usize
+}
+
+/// Number of leading bits shared by two prefixes, bounded by their lengths.
+fn common_len<T: PrefixValue>(a: &Prefix<T>, b: &Prefix<T>) -> u32 {
+    let diff = (a.value.to_u128() ^ b.value.to_u128()).leading_zeros() - (128 - T::BITS);
+    diff.min(a.len).min(b.len)
+}
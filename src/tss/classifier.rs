@@ -8,11 +8,22 @@
 //! "TupleMerge: Building Online Packet Classifiers by Omitting Bits"
 //! James Daly, et al. (IEEE Transactions on Networking 2019)
 //! <https://ieeexplore.ieee.org/document/8038296>
+//!
+//! Tables are probed in order of the best rule they hold (as in
+//! priority-sorted tuple space search), so a lookup stops as soon as its
+//! current match outranks every remaining table.
 
-use crate::classifier::Classifier;
+use crate::classifier::{Classifier, Config, LookupStats};
+use crate::freeze::Freeze;
+use crate::geometry::Region;
+use crate::memory::{Footprint, MemoryStats, Tally};
 use crate::packet::FiveTuple;
-use crate::rule::{Action, Rule};
-use crate::tss::utils::{range_to_prefixes_u16, range_to_prefixes_u32, range_to_prefixes_u8};
+use crate::prefix::{range_to_prefixes_u16, range_to_prefixes_u32, range_to_prefixes_u8};
+use crate::query::{Overlapping, RegionQuery};
+use crate::rule::{expand_rules, Action, Rule};
+use crate::tss::hash::FxBuildHasher;
+use crate::update::policy::{Health, Monitored};
+use crate::update::DynamicClassifier;
 use alloc::vec::Vec;
 use hashbrown::HashMap;
 
@@ -37,6 +48,21 @@ impl Tuple {
             && self.proto_len <= other.proto_len
     }
 
+    /// Packed key mask keeping the prefix bits of every field.
+    fn mask(&self) -> u128 {
+        // `checked_shl` turns a zero-length prefix (shift by the full width) into 0.
+        let m32 = |len: u32| (!0u32).checked_shl(32 - len).unwrap_or(0);
+        let m16 = |len: u32| (!0u16).checked_shl(16 - len).unwrap_or(0);
+        let m8 = |len: u32| (!0u8).checked_shl(8 - len).unwrap_or(0);
+        TupleKey::pack(
+            m32(self.src_ip_len),
+            m32(self.dst_ip_len),
+            m16(self.src_port_len),
+            m16(self.dst_port_len),
+            m8(self.proto_len),
+        )
+    }
+
     /// Calculate total bit difference between two tuples.
     fn bit_difference(&self, other: &Tuple) -> u32 {
         (other.src_ip_len - self.src_ip_len)
@@ -47,25 +73,24 @@ impl Tuple {
     }
 }
 
-/// Key for the Hash Map: The masked values of the fields.
+/// Key for the Hash Map: the masked values of the fields packed into one
+/// integer (src_ip | dst_ip | src_port | dst_port | proto, high to low bits),
+/// so hashing and comparing a key is a couple of word operations.
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
-struct TupleKey {
-    src_ip: u32,
-    dst_ip: u32,
-    src_port: u16,
-    dst_port: u16,
-    proto: u8,
-}
+struct TupleKey(u128);
 
 impl TupleKey {
-    fn new(packet: &FiveTuple, tuple: &Tuple) -> Self {
-        Self {
-            src_ip: Self::mask_u32(packet.src_ip, tuple.src_ip_len),
-            dst_ip: Self::mask_u32(packet.dst_ip, tuple.dst_ip_len),
-            src_port: Self::mask_u16(packet.src_port, tuple.src_port_len),
-            dst_port: Self::mask_u16(packet.dst_port, tuple.dst_port_len),
-            proto: Self::mask_u8(packet.proto, tuple.proto_len),
-        }
+    /// Key of `packet` in a table with the given (precomputed) mask.
+    fn new(packet: &FiveTuple, mask: u128) -> Self {
+        Self(
+            Self::pack(
+                packet.src_ip,
+                packet.dst_ip,
+                packet.src_port,
+                packet.dst_port,
+                packet.proto,
+            ) & mask,
+        )
     }
 
     // Create a key from values but masked by the Tuple
@@ -77,55 +102,109 @@ impl TupleKey {
         proto: u8,
         tuple: &Tuple,
     ) -> Self {
-        Self {
-            src_ip: Self::mask_u32(src_ip, tuple.src_ip_len),
-            dst_ip: Self::mask_u32(dst_ip, tuple.dst_ip_len),
-            src_port: Self::mask_u16(src_port, tuple.src_port_len),
-            dst_port: Self::mask_u16(dst_port, tuple.dst_port_len),
-            proto: Self::mask_u8(proto, tuple.proto_len),
-        }
+        Self(Self::pack(src_ip, dst_ip, src_port, dst_port, proto) & tuple.mask())
     }
 
-    fn mask_u32(val: u32, len: u32) -> u32 {
-        if len == 0 {
-            return 0;
-        }
-        if len >= 32 {
-            return val;
-        }
-        val & (!0u32 << (32 - len))
+    fn pack(src_ip: u32, dst_ip: u32, src_port: u16, dst_port: u16, proto: u8) -> u128 {
+        (src_ip as u128) << 72
+            | (dst_ip as u128) << 40
+            | (src_port as u128) << 24
+            | (dst_port as u128) << 8
+            | proto as u128
     }
+}
 
-    fn mask_u16(val: u16, len: u32) -> u16 {
-        if len == 0 {
-            return 0;
-        }
-        if len >= 16 {
-            return val;
-        }
-        val & (!0u16 << (16 - len))
-    }
+/// Hash table of one tuple: masked key to bucket of rule slots.
+type Table = HashMap<TupleKey, Vec<u32>, FxBuildHasher>;
 
-    fn mask_u8(val: u8, len: u32) -> u8 {
-        if len == 0 {
-            return 0;
-        }
-        if len >= 8 {
-            return val;
+/// One tuple's table, as probed at lookup time.
+#[derive(Clone)]
+struct TupleTable {
+    /// Prefix lengths of the table.
+    tuple: Tuple,
+    /// Packed key mask of the tuple.
+    mask: u128,
+    /// Order of the highest-priority rule stored in the table.
+    best: u64,
+    buckets: Table,
+}
+
+impl TupleTable {
+    fn new(tuple: Tuple) -> Self {
+        Self {
+            tuple,
+            mask: tuple.mask(),
+            best: u64::MAX,
+            buckets: Table::default(),
         }
-        val & (!0u8 << (8 - len))
     }
 }
 
+/// Default `Config::merge_bits`: a conservative limit, grouping "very close"
+/// tuples only (a full 5-tuple has 104 bits).
+const DEFAULT_MERGE_BITS: u32 = 12;
+
 /// Tuple Space Classifier
+///
+/// Rules can be inserted and removed in place (`DynamicClassifier`): an
+/// insert goes into the best mergeable table like at build time, a removal
+/// deletes the rule's bucket entries and drops tables left empty.
+#[derive(Clone)]
 pub struct TSSClassifier {
-    /// List of tuples and their corresponding hash tables.
-    /// To support multiple rules per key (collisions due to merging), the value is a Vec<Rule>.
-    tables: HashMap<Tuple, HashMap<TupleKey, Vec<Rule>>>,
-    _marker: (),
+    /// Plain rules, by slot (in no particular order).
+    rules: Vec<Rule>,
+    /// Rank of each slot's rule: priority in the high half, insertion
+    /// sequence in the low half, so a lower order means a higher priority and
+    /// ties go to the older rule. Plain copies of one rule share its order.
+    order: Vec<u64>,
+    /// Rules that are not plain, by order, to hand them back on removal.
+    sources: HashMap<u64, Rule, FxBuildHasher>,
+    /// Sequence number of the next inserted rule.
+    next_seq: u32,
+    /// Most prefix bits a rule may lose to be merged into an existing table.
+    /// Higher = fewer tables, more collisions.
+    merge_bits: u32,
+    /// Tuple tables, contiguous and sorted by their best rule.
+    /// A rule expands into many prefix combinations, so buckets hold slots
+    /// of `rules` rather than copies, sorted by order; several rules may
+    /// share a bucket (collisions due to merging).
+    tables: Vec<TupleTable>,
 }
 
 impl TSSClassifier {
+    /// Probe tuple tables in order and keep the highest-priority match.
+    fn lookup(&self, packet: &FiveTuple, stats: &mut LookupStats) -> Option<&Rule> {
+        // Order and slot of the best match so far.
+        let mut best: Option<(u64, u32)> = None;
+
+        for table in &self.tables {
+            // Tables are sorted by their best rule: once a match beats it,
+            // no remaining table can improve on the match.
+            if best.is_some_and(|(b, _)| table.best >= b) {
+                break;
+            }
+            stats.tables_probed += 1;
+            let key = TupleKey::new(packet, table.mask);
+            let Some(bucket) = table.buckets.get(&key) else {
+                continue;
+            };
+            // Within a bucket, slots are sorted by order (best first).
+            for &slot in bucket {
+                let order = self.order[slot as usize];
+                if best.is_some_and(|(b, _)| order >= b) {
+                    break;
+                }
+                stats.rules_compared += 1;
+                if self.rules[slot as usize].matches(packet) {
+                    best = Some((order, slot));
+                    break;
+                }
+            }
+        }
+
+        best.map(|(_, slot)| &self.rules[slot as usize])
+    }
+
     /// Cartesian product of prefixes
     fn expand_rule(rule: &Rule) -> Vec<(Tuple, u32, u32, u16, u16, u8)> {
         let src_prefixes = range_to_prefixes_u32(rule.src_ip.min, rule.src_ip.max, 32);
@@ -157,103 +236,284 @@ impl TSSClassifier {
         }
         expanded
     }
-}
 
-impl Classifier for TSSClassifier {
-    fn build(rules: &[Rule]) -> Self {
-        let mut tables: HashMap<Tuple, HashMap<TupleKey, Vec<Rule>>> = HashMap::new();
-
-        // Configuration for TupleMerge
-        // Max bits difference allowed to merge. Higher = fewer tables, more collisions.
-        // A full 5-tuple has 96+ bits effectively.
-        // Let's try a conservative limit first to group "very close" ranges.
-        const MAX_MERGE_BITS: u32 = 12;
-
-        for rule in rules {
-            let expanded_parts = Self::expand_rule(rule);
-
-            for (rule_tuple, sip, dip, sport, dport, proto) in expanded_parts {
-                // TupleMerge Strategy: Find best existing table
-                let mut best_table_tuple: Option<Tuple> = None;
-                let mut min_diff = u32::MAX;
-
-                // collect keys to avoid borrow overlap if needed, or just iterate
-                for existing_tuple in tables.keys() {
-                    if existing_tuple.is_subset_of(&rule_tuple) {
-                        let diff = existing_tuple.bit_difference(&rule_tuple);
-                        if diff < min_diff && diff <= MAX_MERGE_BITS {
-                            min_diff = diff;
-                            best_table_tuple = Some(*existing_tuple);
-                        }
+    /// Add `rule` (any rule, plain or not) without re-sorting the tables.
+    fn add(&mut self, rule: Rule) {
+        if self.next_seq == u32::MAX {
+            self.renumber();
+        }
+        let order = (rule.priority as u64) << 32 | self.next_seq as u64;
+        self.next_seq += 1;
+        let copies = expand_rules(core::slice::from_ref(&rule)).into_owned();
+        if !rule.is_plain() {
+            self.sources.insert(order, rule);
+        }
+        for copy in copies {
+            let slot = self.rules.len() as u32;
+            self.rules.push(copy);
+            self.order.push(order);
+            self.place(slot);
+        }
+    }
+
+    /// Store slot `slot` in the tables.
+    fn place(&mut self, slot: u32) {
+        let order = self.order[slot as usize];
+        for (rule_tuple, sip, dip, sport, dport, proto) in
+            Self::expand_rule(&self.rules[slot as usize])
+        {
+            // TupleMerge Strategy: Find best existing table
+            let mut target = None;
+            let mut min_diff = u32::MAX;
+            for (i, table) in self.tables.iter().enumerate() {
+                if table.tuple.is_subset_of(&rule_tuple) {
+                    let diff = table.tuple.bit_difference(&rule_tuple);
+                    if diff < min_diff && diff <= self.merge_bits {
+                        min_diff = diff;
+                        target = Some(i);
                     }
                 }
+            }
 
-                // If no good match found, we use the rule's tuple as a new table
-                let target_tuple = best_table_tuple.unwrap_or(rule_tuple);
+            // If no good match found, we use the rule's tuple as a new table
+            let target = target.unwrap_or_else(|| {
+                self.tables.push(TupleTable::new(rule_tuple));
+                self.tables.len() - 1
+            });
+            let table = &mut self.tables[target];
+            table.best = table.best.min(order);
 
-                let table = tables.entry(target_tuple).or_default();
+            // Generate key using the TARGET tuple (masking based on table definition)
+            let key = TupleKey::from_values(sip, dip, sport, dport, proto, &table.tuple);
 
-                // Generate key using the TARGET tuple (masking based on table definition)
-                let key = TupleKey::from_values(sip, dip, sport, dport, proto, &target_tuple);
+            // Keep the bucket sorted by order. At build time rules arrive in
+            // order, so this appends without searching however hot the
+            // bucket gets; merged prefixes of one rule can land in the same
+            // bucket.
+            let bucket = table.buckets.entry(key).or_default();
+            let orders = &self.order;
+            match bucket.last() {
+                Some(&last) if last == slot => {}
+                Some(&last) if orders[last as usize] >= order => {
+                    let at = bucket.partition_point(|&s| orders[s as usize] <= order);
+                    let mut same = bucket[..at]
+                        .iter()
+                        .rev()
+                        .take_while(|&&s| orders[s as usize] == order);
+                    if !same.any(|&s| s == slot) {
+                        bucket.insert(at, slot);
+                    }
+                }
+                _ => bucket.push(slot),
+            }
+        }
+    }
 
-                let bucket = table.entry(key).or_default();
-                // Insert rule if better priority or just append?
-                // Since we have collisions, we MUST append and scan all.
-                // Optim: keep sorted by priority?
-                bucket.push(rule.clone());
-                // Sort bucket by priority (ascending value = higher priority)
-                bucket.sort_by_key(|r| r.priority);
+    /// Delete every bucket entry of slot `slot`, or rename them to `rename`.
+    fn unplace(&mut self, slot: u32, rename: Option<u32>) {
+        for (rule_tuple, sip, dip, sport, dport, proto) in
+            Self::expand_rule(&self.rules[slot as usize])
+        {
+            // The entry is in some table that could hold the prefix.
+            for table in &mut self.tables {
+                if !table.tuple.is_subset_of(&rule_tuple) {
+                    continue;
+                }
+                let key = TupleKey::from_values(sip, dip, sport, dport, proto, &table.tuple);
+                let Some(bucket) = table.buckets.get_mut(&key) else {
+                    continue;
+                };
+                match rename {
+                    Some(new) => bucket
+                        .iter_mut()
+                        .filter(|s| **s == slot)
+                        .for_each(|s| *s = new),
+                    None => {
+                        bucket.retain(|&s| s != slot);
+                        if bucket.is_empty() {
+                            table.buckets.remove(&key);
+                        }
+                    }
+                }
             }
         }
+    }
 
-        Self {
-            tables,
-            _marker: (),
+    /// Recompute each table's best rule, drop empty tables and restore the
+    /// probing order.
+    fn reindex(&mut self) {
+        self.tables.retain(|t| !t.buckets.is_empty());
+        for table in &mut self.tables {
+            table.best = table
+                .buckets
+                .values()
+                .map(|b| self.order[b[0] as usize])
+                .min()
+                .unwrap_or(u64::MAX);
         }
+        self.tables.sort_by_key(|t| t.best);
     }
 
-    fn classify(&self, packet: &FiveTuple) -> Option<Action> {
-        let mut best_match: Option<&Rule> = None;
-
-        for (tuple, table) in &self.tables {
-            let key = TupleKey::new(packet, tuple);
-            if let Some(bucket) = table.get(&key) {
-                // Determine if we found a match in this bucket
-                for rule in bucket {
-                    // Start with high priority check
-                    // If we already have a match with priority P, and this rule has priority > P (value < P), we check.
-                    // If rule priority < best_match priority (value > best), we can stop if sorted?
-                    // No, because we iterate tables in arbitrary order. We must scan all tables.
-
-                    // Optimization: If rule.priority >= best_match.priority (value >=), we can skip checking?
-                    // Only if we are sure this rule matches. But we aren't.
-                    // We need to check exact match first.
-
-                    if let Some(best) = best_match {
-                        if rule.priority >= best.priority {
-                            // This rule is lower or equal priority than what we have.
-                            // Since bucket is sorted, subsequent rules are also worse.
-                            break;
-                        }
-                    }
+    /// Reassign insertion sequences densely once they run out.
+    fn renumber(&mut self) {
+        let mut orders = self.order.clone();
+        orders.sort_unstable();
+        orders.dedup();
+        let rank = |order: u64| {
+            let seq = orders.binary_search(&order).unwrap_or(0) as u64;
+            order & !0xFFFF_FFFF | seq
+        };
+        for order in &mut self.order {
+            *order = rank(*order);
+        }
+        self.sources = core::mem::take(&mut self.sources)
+            .into_iter()
+            .map(|(order, rule)| (rank(order), rule))
+            .collect();
+        self.next_seq = orders.len() as u32;
+        self.reindex();
+    }
+}
 
-                    if rule.matches(packet) {
-                        match best_match {
-                            None => best_match = Some(rule),
-                            Some(best) => {
-                                if rule.priority < best.priority {
-                                    best_match = Some(rule);
-                                }
-                            }
-                        }
-                        // Since bucket is sorted, and we found a match, any subsequent match in *this* bucket
-                        // will be lower priority. So we can stop this bucket scan.
-                        break;
-                    }
-                }
+impl Classifier for TSSClassifier {
+    fn build(rules: &[Rule]) -> Self {
+        Self::build_with_config(rules, &Config::default())
+    }
+
+    fn build_with_config(rules: &[Rule], config: &Config) -> Self {
+        let mut sorted = rules.to_vec();
+        sorted.sort_by_key(|r| r.priority);
+        let mut tss = Self {
+            rules: Vec::with_capacity(sorted.len()),
+            order: Vec::with_capacity(sorted.len()),
+            sources: HashMap::default(),
+            next_seq: 0,
+            merge_bits: config.merge_bits.unwrap_or(DEFAULT_MERGE_BITS),
+            tables: Vec::new(),
+        };
+        for rule in sorted {
+            tss.add(rule);
+        }
+        tss.tables.sort_by_key(|t| t.best);
+        tss
+    }
+
+    fn classify_rule(&self, packet: &FiveTuple) -> Option<&Rule> {
+        self.lookup(packet, &mut LookupStats::default())
+    }
+
+    fn classify_with_stats(&self, packet: &FiveTuple) -> (Option<Action>, LookupStats) {
+        let mut stats = LookupStats::default();
+        let action = self.lookup(packet, &mut stats).map(|r| r.action);
+        (action, stats)
+    }
+
+    fn classify_rule_with_stats(&self, packet: &FiveTuple) -> (Option<&Rule>, LookupStats) {
+        let mut stats = LookupStats::default();
+        let rule = self.lookup(packet, &mut stats);
+        (rule, stats)
+    }
+}
+
+impl RegionQuery for TSSClassifier {
+    /// Tables are keyed by exact masked values and cannot be searched by
+    /// range, so this scans the rule list.
+    fn rules_overlapping(&self, region: &Region) -> Vec<&Rule> {
+        let mut found = Overlapping::new(region);
+        found.scan(&self.rules);
+        found.finish()
+    }
+}
+
+impl DynamicClassifier for TSSClassifier {
+    fn insert(&mut self, rule: Rule) {
+        self.add(rule);
+        self.tables.sort_by_key(|t| t.best);
+    }
+
+    /// Removes the highest-priority rule with this id.
+    fn remove(&mut self, id: u32) -> Option<Rule> {
+        // Rules expanding to no plain copy (e.g. a field negating its whole
+        // domain) are only found in `sources`.
+        let plain = (0..self.rules.len())
+            .filter(|&slot| self.rules[slot].id == id)
+            .map(|slot| self.order[slot]);
+        let sources = self
+            .sources
+            .iter()
+            .filter(|(_, rule)| rule.id == id)
+            .map(|(&order, _)| order);
+        let order = plain.chain(sources).min()?;
+        let mut removed = None;
+        // Remove the rule's plain copies, moving the last slot into each
+        // freed one so slots stay dense.
+        while let Some(slot) = self.order.iter().position(|&o| o == order) {
+            let (slot, last) = (slot as u32, self.rules.len() as u32 - 1);
+            self.unplace(slot, None);
+            if slot != last {
+                self.unplace(last, Some(slot));
+            }
+            self.order.swap_remove(slot as usize);
+            removed = Some(self.rules.swap_remove(slot as usize));
+        }
+        self.reindex();
+        self.sources.remove(&order).or(removed)
+    }
+}
+
+impl Monitored for TSSClassifier {
+    fn health(&self) -> Health {
+        // Plain copies of one rule share its order.
+        let mut orders = self.order.clone();
+        orders.sort_unstable();
+        orders.dedup();
+        let buckets = self.tables.iter().flat_map(|t| t.buckets.values());
+        Health {
+            rules: orders.len(),
+            stored_rules: buckets.clone().map(Vec::len).sum(),
+            largest_bucket: buckets.map(Vec::len).max().unwrap_or(0),
+            tables: self.tables.len(),
+        }
+    }
+}
+
+impl MemoryStats for TSSClassifier {
+    /// Tables count as internal nodes and buckets as leaves. Rules are
+    /// counted by id, so plain copies of one rule are duplicates.
+    fn footprint(&self) -> Footprint {
+        let bucket_entry = size_of::<TupleKey>() + size_of::<Vec<u32>>();
+        let mut bytes = size_of::<Self>()
+            + self.rules.capacity() * size_of::<Rule>()
+            + self.order.capacity() * size_of::<u64>()
+            + self.sources.capacity() * (size_of::<u64>() + size_of::<Rule>())
+            + self.tables.capacity() * size_of::<TupleTable>();
+        let mut tally = Tally::new(0);
+        for table in &self.tables {
+            tally.internal();
+            bytes += table.buckets.capacity() * bucket_entry;
+            for bucket in table.buckets.values() {
+                bytes += bucket.capacity() * size_of::<u32>();
+                tally.leaf(1, bucket.iter().map(|&slot| &self.rules[slot as usize]));
             }
         }
+        Footprint {
+            bytes,
+            ..tally.finish()
+        }
+    }
+}
 
-        best_match.map(|r| r.action)
+impl Freeze for TSSClassifier {
+    fn shrink_to_fit(&mut self) {
+        self.rules.shrink_to_fit();
+        self.order.shrink_to_fit();
+        self.sources.shrink_to_fit();
+        self.tables.shrink_to_fit();
+        for table in &mut self.tables {
+            table.buckets.shrink_to_fit();
+            for bucket in table.buckets.values_mut() {
+                bucket.shrink_to_fit();
+            }
+        }
     }
 }
@@ -1,2 +1,3 @@
 pub mod classifier;
+pub mod hash;
 pub mod utils;
@@ -0,0 +1,246 @@
+//! Two-stage classification: coarse bitmap prefilter, exact confirm.
+//!
+//! `TwoStage` splits the rules, in priority order, into groups of
+//! `group_size` and builds one exact classifier per group. Stage one cuts
+//! every field into `2^bucket_bits` equal buckets and keeps, per bucket, a
+//! bitmap of the groups with a rule reaching it; a lookup indexes the five
+//! bucket bitmaps of the packet directly (no search) and ANDs them. Stage two
+//! runs the exact classifiers of the surviving groups, best group first, and
+//! stops at the first match: groups hold contiguous priorities, so no later
+//! group can beat it.
+//!
+//! Buckets are coarse, so a surviving group may still hold no matching rule.
+//! `stats` counts candidates and confirmed ones to tune `bucket_bits` and
+//! `group_size` for a rule set and its traffic.
+
+use crate::classifier::{Classifier, LookupStats};
+use crate::counters::Cell64;
+use crate::dimension::Dimension;
+use crate::freeze::Freeze;
+use crate::geometry::Region;
+use crate::packet::FiveTuple;
+use crate::query::{Overlapping, RegionQuery};
+use crate::rule::{expand_rules, Action, Rule};
+use alloc::vec;
+use alloc::vec::Vec;
+
+/// Largest `bucket_bits` used (65536 buckets per field).
+pub const MAX_BUCKET_BITS: u32 = 16;
+
+/// Granularity of a `TwoStage` classifier.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TwoStageParams {
+    /// Buckets per field are `2^bucket_bits`, with `bucket_bits` capped at
+    /// the field width and at `MAX_BUCKET_BITS`.
+    pub bucket_bits: u32,
+    /// Rules per exact classifier.
+    pub group_size: usize,
+}
+
+impl Default for TwoStageParams {
+    /// 256 buckets per field, 32 rules per group.
+    fn default() -> Self {
+        Self {
+            bucket_bits: 8,
+            group_size: 32,
+        }
+    }
+}
+
+/// Prefilter activity since the counters were created or reset.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct TwoStageStats {
+    /// Lookups performed.
+    pub lookups: u64,
+    /// Groups that passed the prefilter and were searched.
+    pub candidates: u64,
+    /// Candidates whose exact classifier found a match.
+    pub confirmed: u64,
+}
+
+impl TwoStageStats {
+    /// Groups searched per lookup (lower is a sharper prefilter).
+    pub fn candidates_per_lookup(&self) -> f64 {
+        if self.lookups == 0 {
+            return 0.0;
+        }
+        self.candidates as f64 / self.lookups as f64
+    }
+
+    /// Fraction of searched groups that held the match (1.0 = no wasted
+    /// exact searches).
+    pub fn precision(&self) -> f64 {
+        if self.candidates == 0 {
+            return 1.0;
+        }
+        self.confirmed as f64 / self.candidates as f64
+    }
+}
+
+/// Bucket bitmaps of one field.
+#[derive(Debug, Clone)]
+struct FieldBuckets {
+    /// Shift turning a field value into its bucket.
+    shift: u32,
+    /// `2^bits * words` bitmap words, bucket-major.
+    bits: Vec<u64>,
+}
+
+/// Coarse per-field bitmaps in front of per-group exact classifiers.
+pub struct TwoStage<C> {
+    groups: Vec<C>,
+    words: usize,
+    fields: [FieldBuckets; Dimension::COUNT],
+    params: TwoStageParams,
+    lookups: Cell64,
+    candidates: Cell64,
+    confirmed: Cell64,
+}
+
+impl<C: Classifier> TwoStage<C> {
+    /// Build over `rules` with `params`, building each group with `build`.
+    ///
+    /// Panics if `params.group_size` is 0.
+    pub fn build_with(
+        rules: &[Rule],
+        params: TwoStageParams,
+        build: impl Fn(&[Rule]) -> C,
+    ) -> Self {
+        assert!(params.group_size > 0, "groups need at least one rule");
+        let mut sorted = rules.to_vec();
+        sorted.sort_by_key(|r| r.priority);
+        let chunks: Vec<&[Rule]> = sorted.chunks(params.group_size).collect();
+        let words = chunks.len().div_ceil(64).max(1);
+
+        let fields = Dimension::ALL.map(|dim| {
+            let width = dim.spec().bits;
+            let shift = width - params.bucket_bits.min(width).min(MAX_BUCKET_BITS);
+            let buckets = 1usize << (width - shift);
+            let mut bits = vec![0u64; buckets * words];
+            for (g, chunk) in chunks.iter().enumerate() {
+                for rule in expand_rules(chunk).iter() {
+                    let range = dim.rule_range(rule);
+                    for bucket in bucket_of(range.min, shift)..=bucket_of(range.max, shift) {
+                        bits[bucket as usize * words + g / 64] |= 1 << (g % 64);
+                    }
+                }
+            }
+            FieldBuckets { shift, bits }
+        });
+
+        Self {
+            groups: chunks.into_iter().map(build).collect(),
+            words,
+            fields,
+            params,
+            lookups: Cell64::default(),
+            candidates: Cell64::default(),
+            confirmed: Cell64::default(),
+        }
+    }
+
+    /// The granularity the classifier was built with.
+    pub fn params(&self) -> TwoStageParams {
+        self.params
+    }
+
+    /// The exact classifiers, best group first.
+    pub fn groups(&self) -> &[C] {
+        &self.groups
+    }
+
+    /// Prefilter counters.
+    pub fn stats(&self) -> TwoStageStats {
+        TwoStageStats {
+            lookups: self.lookups.get(),
+            candidates: self.candidates.get(),
+            confirmed: self.confirmed.get(),
+        }
+    }
+
+    /// Zero the prefilter counters.
+    pub fn reset_stats(&self) {
+        self.lookups.clear();
+        self.candidates.clear();
+        self.confirmed.clear();
+    }
+
+    /// Run stage one, then `search` on each surviving group until it finds a
+    /// match.
+    fn lookup<'a, T>(
+        &'a self,
+        packet: &FiveTuple,
+        mut search: impl FnMut(&'a C) -> Option<T>,
+    ) -> Option<T> {
+        self.lookups.add(1);
+        let mut offsets = [0usize; Dimension::COUNT];
+        for ((offset, field), dim) in offsets.iter_mut().zip(&self.fields).zip(Dimension::ALL) {
+            *offset = bucket_of(dim.packet_value(packet), field.shift) as usize * self.words;
+        }
+        for w in 0..self.words {
+            let mut word = !0u64;
+            for (field, offset) in self.fields.iter().zip(offsets) {
+                word &= field.bits[offset + w];
+            }
+            while word != 0 {
+                self.candidates.add(1);
+                let group = &self.groups[w * 64 + word.trailing_zeros() as usize];
+                if let Some(found) = search(group) {
+                    self.confirmed.add(1);
+                    return Some(found);
+                }
+                word &= word - 1;
+            }
+        }
+        None
+    }
+}
+
+/// Bucket of `value` (a shift by the full width leaves a single bucket).
+fn bucket_of(value: u32, shift: u32) -> u32 {
+    value.checked_shr(shift).unwrap_or(0)
+}
+
+impl<C: Classifier> Classifier for TwoStage<C> {
+    fn build(rules: &[Rule]) -> Self {
+        Self::build_with(rules, TwoStageParams::default(), C::build)
+    }
+
+    fn classify_rule(&self, packet: &FiveTuple) -> Option<&Rule> {
+        self.lookup(packet, |group| group.classify_rule(packet))
+    }
+
+    fn classify_with_stats(&self, packet: &FiveTuple) -> (Option<Action>, LookupStats) {
+        // The bucket lookup counts as one level; the searched groups add up.
+        let mut stats = LookupStats {
+            depth: 1,
+            ..LookupStats::default()
+        };
+        let action = self.lookup(packet, |group| {
+            let (action, s) = group.classify_with_stats(packet);
+            stats.depth = stats.depth.max(s.depth + 1);
+            stats.rules_compared += s.rules_compared;
+            stats.tables_probed += s.tables_probed;
+            action
+        });
+        (action, stats)
+    }
+}
+
+impl<C: RegionQuery> RegionQuery for TwoStage<C> {
+    fn rules_overlapping(&self, region: &Region) -> Vec<&Rule> {
+        let mut found = Overlapping::new(region);
+        for group in &self.groups {
+            found.extend(group.rules_overlapping(region));
+        }
+        found.finish()
+    }
+}
+
+impl<C: Freeze> Freeze for TwoStage<C> {
+    fn shrink_to_fit(&mut self) {
+        for group in &mut self.groups {
+            group.shrink_to_fit();
+        }
+    }
+}
@@ -0,0 +1,383 @@
+//! Audit log of rule mutations.
+//!
+//! Records every insert/remove/modify applied to a rule set together with a
+//! caller-supplied timestamp and full rule snapshots. The log can be queried by
+//! rule id or time window and serialized to a compact binary form so firewall
+//! changes can be archived and reviewed later.
+//!
+//! Timestamps are opaque `u64` values (e.g. seconds since epoch or a tick
+//! counter); the crate is no_std and has no clock of its own.
+
+use crate::addrset::SetId;
+use crate::rule::{Action, FieldSets, Range, Rule, ANY_ZONE};
+use crate::update::RuleChange;
+use alloc::boxed::Box;
+use alloc::collections::VecDeque;
+use alloc::vec::Vec;
+
+const MAGIC: &[u8; 4] = b"CSAL";
+/// Current format version. Versions 1 to 5 predate rule zones, the
+/// bidirectional flag, field sets, field negation and address-set references
+/// respectively, and are still decoded.
+const VERSION: u8 = 6;
+
+/// A recorded change.
+#[derive(Debug, Clone)]
+pub struct AuditEntry {
+    /// Monotonic sequence number assigned by the log.
+    pub seq: u64,
+    /// Timestamp supplied when the change was recorded.
+    pub timestamp: u64,
+    /// The change itself, including rule snapshots.
+    pub change: RuleChange,
+}
+
+/// Errors returned when decoding a serialized log or classifier.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DecodeError {
+    /// Missing or wrong magic header.
+    BadMagic,
+    /// Unsupported format version.
+    UnsupportedVersion(u8),
+    /// Input ended in the middle of an entry.
+    Truncated,
+    /// Unknown change or action tag.
+    InvalidTag(u8),
+    /// Structurally invalid content, e.g. an index out of bounds.
+    Corrupt,
+}
+
+/// Append-only log of rule mutations.
+///
+/// Optionally bounded: when a capacity limit is set, the oldest entries are
+/// discarded once it is reached (sequence numbers keep increasing).
+#[derive(Debug, Clone, Default)]
+pub struct AuditLog {
+    entries: VecDeque<AuditEntry>,
+    next_seq: u64,
+    limit: Option<usize>,
+}
+
+impl AuditLog {
+    /// Create an unbounded log.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Create a log keeping at most `limit` entries.
+    pub fn with_limit(limit: usize) -> Self {
+        Self {
+            limit: Some(limit),
+            ..Self::default()
+        }
+    }
+
+    /// Record a change and return its sequence number.
+    pub fn record(&mut self, timestamp: u64, change: RuleChange) -> u64 {
+        let seq = self.next_seq;
+        self.next_seq += 1;
+
+        if let Some(limit) = self.limit {
+            if limit == 0 {
+                return seq;
+            }
+            while self.entries.len() >= limit {
+                self.entries.pop_front();
+            }
+        }
+
+        self.entries.push_back(AuditEntry {
+            seq,
+            timestamp,
+            change,
+        });
+        seq
+    }
+
+    /// Record the insertion of `rule`.
+    pub fn record_insert(&mut self, timestamp: u64, rule: &Rule) -> u64 {
+        self.record(timestamp, RuleChange::Insert(rule.clone()))
+    }
+
+    /// Record the removal of `rule`.
+    pub fn record_remove(&mut self, timestamp: u64, rule: &Rule) -> u64 {
+        self.record(timestamp, RuleChange::Remove(rule.clone()))
+    }
+
+    /// Record the replacement of `before` by `after`.
+    pub fn record_modify(&mut self, timestamp: u64, before: &Rule, after: &Rule) -> u64 {
+        self.record(
+            timestamp,
+            RuleChange::Modify {
+                before: before.clone(),
+                after: after.clone(),
+            },
+        )
+    }
+
+    /// Number of entries currently held.
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    /// Returns true if no entries are held.
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Iterate over all held entries, oldest first.
+    pub fn entries(&self) -> impl Iterator<Item = &AuditEntry> {
+        self.entries.iter()
+    }
+
+    /// Entries affecting the rule with the given id.
+    pub fn for_rule(&self, rule_id: u32) -> impl Iterator<Item = &AuditEntry> {
+        self.entries
+            .iter()
+            .filter(move |e| e.change.rule_id() == rule_id)
+    }
+
+    /// Entries with `from <= timestamp <= to`.
+    pub fn between(&self, from: u64, to: u64) -> impl Iterator<Item = &AuditEntry> {
+        self.entries
+            .iter()
+            .filter(move |e| e.timestamp >= from && e.timestamp <= to)
+    }
+
+    /// Entries recorded after sequence number `seq` (exclusive).
+    pub fn since(&self, seq: u64) -> impl Iterator<Item = &AuditEntry> {
+        self.entries.iter().filter(move |e| e.seq > seq)
+    }
+
+    /// Serialize the log to a versioned little-endian binary format.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut out = Vec::new();
+        out.extend_from_slice(MAGIC);
+        out.push(VERSION);
+        out.extend_from_slice(&self.next_seq.to_le_bytes());
+        out.extend_from_slice(&(self.entries.len() as u32).to_le_bytes());
+
+        for entry in &self.entries {
+            out.extend_from_slice(&entry.seq.to_le_bytes());
+            out.extend_from_slice(&entry.timestamp.to_le_bytes());
+            match &entry.change {
+                RuleChange::Insert(rule) => {
+                    out.push(0);
+                    encode_rule(&mut out, rule);
+                }
+                RuleChange::Remove(rule) => {
+                    out.push(1);
+                    encode_rule(&mut out, rule);
+                }
+                RuleChange::Modify { before, after } => {
+                    out.push(2);
+                    encode_rule(&mut out, before);
+                    encode_rule(&mut out, after);
+                }
+            }
+        }
+        out
+    }
+
+    /// Decode a log produced by [`AuditLog::to_bytes`]. The result is unbounded.
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, DecodeError> {
+        let mut r = Reader { bytes, pos: 0 };
+        if r.take(4)? != MAGIC {
+            return Err(DecodeError::BadMagic);
+        }
+        let version = r.u8()?;
+        if !(1..=VERSION).contains(&version) {
+            return Err(DecodeError::UnsupportedVersion(version));
+        }
+        let next_seq = r.u64()?;
+        let count = r.u32()?;
+
+        // Grow as entries are read so a corrupt count cannot force a huge allocation.
+        let mut entries = VecDeque::new();
+        for _ in 0..count {
+            let seq = r.u64()?;
+            let timestamp = r.u64()?;
+            let change = match r.u8()? {
+                0 => RuleChange::Insert(decode_rule(&mut r, version)?),
+                1 => RuleChange::Remove(decode_rule(&mut r, version)?),
+                2 => RuleChange::Modify {
+                    before: decode_rule(&mut r, version)?,
+                    after: decode_rule(&mut r, version)?,
+                },
+                tag => return Err(DecodeError::InvalidTag(tag)),
+            };
+            entries.push_back(AuditEntry {
+                seq,
+                timestamp,
+                change,
+            });
+        }
+
+        Ok(Self {
+            entries,
+            next_seq,
+            limit: None,
+        })
+    }
+}
+
+pub(crate) fn encode_rule(out: &mut Vec<u8>, rule: &Rule) {
+    out.extend_from_slice(&rule.id.to_le_bytes());
+    out.extend_from_slice(&rule.priority.to_le_bytes());
+    out.extend_from_slice(&rule.src_ip.min.to_le_bytes());
+    out.extend_from_slice(&rule.src_ip.max.to_le_bytes());
+    out.extend_from_slice(&rule.dst_ip.min.to_le_bytes());
+    out.extend_from_slice(&rule.dst_ip.max.to_le_bytes());
+    out.extend_from_slice(&rule.src_port.min.to_le_bytes());
+    out.extend_from_slice(&rule.src_port.max.to_le_bytes());
+    out.extend_from_slice(&rule.dst_port.min.to_le_bytes());
+    out.extend_from_slice(&rule.dst_port.max.to_le_bytes());
+    out.push(rule.proto.min);
+    out.push(rule.proto.max);
+    out.extend_from_slice(&rule.zone.min.to_le_bytes());
+    out.extend_from_slice(&rule.zone.max.to_le_bytes());
+    out.push(rule.bidirectional as u8);
+    match rule.field_sets.as_deref() {
+        Some(sets) => {
+            out.push(1);
+            encode_ranges(out, &sets.src_ip, |out, v| {
+                out.extend_from_slice(&v.to_le_bytes())
+            });
+            encode_ranges(out, &sets.dst_ip, |out, v| {
+                out.extend_from_slice(&v.to_le_bytes())
+            });
+            encode_ranges(out, &sets.src_port, |out, v| {
+                out.extend_from_slice(&v.to_le_bytes())
+            });
+            encode_ranges(out, &sets.dst_port, |out, v| {
+                out.extend_from_slice(&v.to_le_bytes())
+            });
+            encode_ranges(out, &sets.proto, |out, v| out.push(v));
+            out.push(sets.negated);
+            encode_set_id(out, sets.src_set);
+            encode_set_id(out, sets.dst_set);
+        }
+        None => out.push(0),
+    }
+    out.push(match rule.action {
+        Action::Permit => 0,
+        Action::Deny => 1,
+    });
+}
+
+pub(crate) fn decode_rule(r: &mut Reader<'_>, version: u8) -> Result<Rule, DecodeError> {
+    Ok(Rule {
+        id: r.u32()?,
+        priority: r.u32()?,
+        src_ip: Range::new(r.u32()?, r.u32()?),
+        dst_ip: Range::new(r.u32()?, r.u32()?),
+        src_port: Range::new(r.u16()?, r.u16()?),
+        dst_port: Range::new(r.u16()?, r.u16()?),
+        proto: Range::new(r.u8()?, r.u8()?),
+        zone: if version >= 2 {
+            Range::new(r.u32()?, r.u32()?)
+        } else {
+            ANY_ZONE
+        },
+        bidirectional: version >= 3 && r.u8()? != 0,
+        field_sets: if version >= 4 && r.u8()? != 0 {
+            Some(Box::new(FieldSets {
+                src_ip: decode_ranges(r, Reader::u32)?,
+                dst_ip: decode_ranges(r, Reader::u32)?,
+                src_port: decode_ranges(r, Reader::u16)?,
+                dst_port: decode_ranges(r, Reader::u16)?,
+                proto: decode_ranges(r, Reader::u8)?,
+                negated: if version >= 5 { r.u8()? } else { 0 },
+                src_set: decode_set_id(r, version)?,
+                dst_set: decode_set_id(r, version)?,
+            }))
+        } else {
+            None
+        },
+        action: match r.u8()? {
+            0 => Action::Permit,
+            1 => Action::Deny,
+            tag => return Err(DecodeError::InvalidTag(tag)),
+        },
+    })
+}
+
+/// Write a count-prefixed list of ranges.
+fn encode_ranges<T: Copy>(out: &mut Vec<u8>, ranges: &[Range<T>], put: fn(&mut Vec<u8>, T)) {
+    out.extend_from_slice(&(ranges.len() as u32).to_le_bytes());
+    for range in ranges {
+        put(out, range.min);
+        put(out, range.max);
+    }
+}
+
+fn encode_set_id(out: &mut Vec<u8>, id: Option<SetId>) {
+    match id {
+        Some(id) => {
+            out.push(1);
+            out.extend_from_slice(&id.to_le_bytes());
+        }
+        None => out.push(0),
+    }
+}
+
+fn decode_set_id(r: &mut Reader<'_>, version: u8) -> Result<Option<SetId>, DecodeError> {
+    if version < 6 || r.u8()? == 0 {
+        return Ok(None);
+    }
+    Ok(Some(r.u32()?))
+}
+
+fn decode_ranges<'a, T>(
+    r: &mut Reader<'a>,
+    get: fn(&mut Reader<'a>) -> Result<T, DecodeError>,
+) -> Result<Vec<Range<T>>, DecodeError> {
+    let len = r.u32()?;
+    // Grow as ranges are read so a corrupt count cannot force a huge allocation.
+    let mut ranges = Vec::new();
+    for _ in 0..len {
+        ranges.push(Range {
+            min: get(r)?,
+            max: get(r)?,
+        });
+    }
+    Ok(ranges)
+}
+
+pub(crate) struct Reader<'a> {
+    pub(crate) bytes: &'a [u8],
+    pub(crate) pos: usize,
+}
+
+impl<'a> Reader<'a> {
+    pub(crate) fn take(&mut self, n: usize) -> Result<&'a [u8], DecodeError> {
+        let end = self.pos.checked_add(n).ok_or(DecodeError::Truncated)?;
+        let slice = self
+            .bytes
+            .get(self.pos..end)
+            .ok_or(DecodeError::Truncated)?;
+        self.pos = end;
+        Ok(slice)
+    }
+
+    pub(crate) fn u8(&mut self) -> Result<u8, DecodeError> {
+        Ok(self.take(1)?[0])
+    }
+
+    pub(crate) fn u16(&mut self) -> Result<u16, DecodeError> {
+        let b = self.take(2)?;
+        Ok(u16::from_le_bytes([b[0], b[1]]))
+    }
+
+    pub(crate) fn u32(&mut self) -> Result<u32, DecodeError> {
+        let b = self.take(4)?;
+        Ok(u32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+    }
+
+    pub(crate) fn u64(&mut self) -> Result<u64, DecodeError> {
+        let b = self.take(8)?;
+        let mut buf = [0u8; 8];
+        buf.copy_from_slice(b);
+        Ok(u64::from_le_bytes(buf))
+    }
+}
@@ -0,0 +1,106 @@
+//! Rule-set diffs.
+//!
+//! `diff_rules` turns an old and a new rule set into the changes that take
+//! one to the other, so a policy push can be applied to a live classifier as
+//! a few updates instead of a full rebuild. Rules are paired by id first and
+//! then by content: a rule that was only renumbered or moved to another
+//! priority becomes a single `Modify` rather than a remove and an insert.
+
+use super::RuleChange;
+use crate::rule::{Range, Rule};
+use alloc::vec::Vec;
+use hashbrown::HashMap;
+
+/// Changes turning `old` into `new`, assuming rule ids are unique in each set.
+///
+/// Removals come first, then modifications, then insertions, so an id freed
+/// by a removed rule can be reused by an inserted one. Rules present in both
+/// sets unchanged produce no change.
+pub fn diff_rules(old: &[Rule], new: &[Rule]) -> Vec<RuleChange> {
+    let by_id: HashMap<u32, usize> = old.iter().enumerate().map(|(i, r)| (r.id, i)).collect();
+    let mut old_used = alloc::vec![false; old.len()];
+    let mut modified = Vec::new();
+    let mut unmatched = Vec::new();
+
+    // Same id: unchanged or modified in place.
+    for rule in new {
+        match by_id.get(&rule.id) {
+            Some(&i) if !old_used[i] => {
+                old_used[i] = true;
+                if old[i] != *rule {
+                    modified.push(RuleChange::Modify {
+                        before: old[i].clone(),
+                        after: rule.clone(),
+                    });
+                }
+            }
+            _ => unmatched.push(rule),
+        }
+    }
+
+    // Same content under another id or priority: renumbered.
+    let mut by_fields: HashMap<FieldKey, Vec<usize>> = HashMap::new();
+    for (i, rule) in old.iter().enumerate().filter(|&(i, _)| !old_used[i]) {
+        by_fields.entry(FieldKey::of(rule)).or_default().push(i);
+    }
+    let mut inserted = Vec::new();
+    for rule in unmatched {
+        let candidates = by_fields.get_mut(&FieldKey::of(rule));
+        let pos = candidates.as_ref().and_then(|c| {
+            c.iter()
+                .position(|&i| !old_used[i] && same_content(&old[i], rule))
+        });
+        match (candidates, pos) {
+            (Some(candidates), Some(pos)) => {
+                let i = candidates.swap_remove(pos);
+                old_used[i] = true;
+                modified.push(RuleChange::Modify {
+                    before: old[i].clone(),
+                    after: rule.clone(),
+                });
+            }
+            _ => inserted.push(RuleChange::Insert(rule.clone())),
+        }
+    }
+
+    let mut changes: Vec<RuleChange> = old
+        .iter()
+        .zip(&old_used)
+        .filter(|&(_, &used)| !used)
+        .map(|(rule, _)| RuleChange::Remove(rule.clone()))
+        .collect();
+    changes.extend(modified);
+    changes.extend(inserted);
+    changes
+}
+
+/// Hashable summary of a rule's match fields, to find content matches quickly.
+#[derive(PartialEq, Eq, Hash)]
+struct FieldKey {
+    src_ip: Range<u32>,
+    dst_ip: Range<u32>,
+    src_port: Range<u16>,
+    dst_port: Range<u16>,
+    proto: Range<u8>,
+}
+
+impl FieldKey {
+    fn of(rule: &Rule) -> Self {
+        Self {
+            src_ip: rule.src_ip,
+            dst_ip: rule.dst_ip,
+            src_port: rule.src_port,
+            dst_port: rule.dst_port,
+            proto: rule.proto,
+        }
+    }
+}
+
+/// Returns true if the rules are equal apart from their id and priority.
+fn same_content(a: &Rule, b: &Rule) -> bool {
+    *a == Rule {
+        id: a.id,
+        priority: a.priority,
+        ..b.clone()
+    }
+}
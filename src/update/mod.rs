@@ -0,0 +1,103 @@
+//! Dynamic rule-set updates.
+//!
+//! Describes mutations applied to a live rule set so they can be recorded,
+//! replayed against a plain rule list, or fed to classifiers that support
+//! in-place updates.
+
+pub mod audit;
+pub mod diff;
+pub mod policy;
+pub mod rebuild;
+
+use crate::classifier::Classifier;
+use crate::rule::Rule;
+use alloc::vec::Vec;
+
+pub use diff::diff_rules;
+pub use policy::{AutoRebuild, RebuildPolicy};
+pub use rebuild::Rebuilding;
+
+/// Classifier whose rule set can be changed after it was built.
+pub trait DynamicClassifier: Classifier {
+    /// Add a rule.
+    fn insert(&mut self, rule: Rule);
+
+    /// Remove the rule with the given id, returning it.
+    fn remove(&mut self, id: u32) -> Option<Rule>;
+
+    /// Apply a recorded change.
+    ///
+    /// Returns false if the change could not be applied (e.g. removing an unknown id).
+    fn apply(&mut self, change: &RuleChange) -> bool {
+        match change {
+            RuleChange::Insert(rule) => {
+                self.insert(rule.clone());
+                true
+            }
+            RuleChange::Remove(rule) => self.remove(rule.id).is_some(),
+            RuleChange::Modify { before, after } => {
+                if self.remove(before.id).is_none() {
+                    return false;
+                }
+                self.insert(after.clone());
+                true
+            }
+        }
+    }
+}
+
+/// A single mutation of a rule set.
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum RuleChange {
+    /// A new rule was added.
+    Insert(Rule),
+    /// An existing rule was removed. Holds the rule as it was before removal.
+    Remove(Rule),
+    /// An existing rule was replaced by a new version with the same id.
+    Modify {
+        /// Rule before the change.
+        before: Rule,
+        /// Rule after the change.
+        after: Rule,
+    },
+}
+
+impl RuleChange {
+    /// Id of the rule affected by this change.
+    pub fn rule_id(&self) -> u32 {
+        match self {
+            RuleChange::Insert(rule) => rule.id,
+            RuleChange::Remove(rule) => rule.id,
+            RuleChange::Modify { after, .. } => after.id,
+        }
+    }
+
+    /// Apply the change to a plain rule list (rules are matched by id).
+    ///
+    /// Returns false if the change could not be applied (e.g. removing an unknown id).
+    pub fn apply(&self, rules: &mut Vec<Rule>) -> bool {
+        match self {
+            RuleChange::Insert(rule) => {
+                rules.push(rule.clone());
+                true
+            }
+            RuleChange::Remove(rule) => match rules.iter().position(|r| r.id == rule.id) {
+                Some(idx) => {
+                    rules.remove(idx);
+                    true
+                }
+                None => false,
+            },
+            RuleChange::Modify { before, after } => {
+                match rules.iter_mut().find(|r| r.id == before.id) {
+                    Some(slot) => {
+                        *slot = after.clone();
+                        true
+                    }
+                    None => false,
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,272 @@
+//! When to rebuild a classifier that is updated in place.
+//!
+//! In-place updates keep a dynamic classifier correct but not as good as a
+//! fresh build: TSS inserts merge into tables chosen for the old rule set,
+//! so buckets grow and removals leave rule copies scattered. `RebuildPolicy`
+//! watches the changes applied since the last build and the classifier's
+//! `Health`, and says when a rebuild is due and why (`Trigger`).
+//!
+//! The policy only decides; `AutoRebuild` applies changes in place and
+//! rebuilds when the policy fires. A caller that builds on another thread
+//! uses the policy directly: `record` each change, `check` the live
+//! classifier's health, build from the current rules when it fires, swap the
+//! result in and call `rebuilt` with the new classifier's health.
+
+use super::DynamicClassifier;
+use crate::classifier::{Classifier, LookupStats};
+use crate::geometry::Region;
+use crate::packet::FiveTuple;
+use crate::query::RegionQuery;
+use crate::rule::{Action, Rule};
+use alloc::vec::Vec;
+
+/// Shape of a classifier, as far as update degradation goes.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct Health {
+    /// Rules held.
+    pub rules: usize,
+    /// Rule copies held by the lookup structure (buckets, leaves).
+    pub stored_rules: usize,
+    /// Rule copies in the largest bucket a lookup may scan.
+    pub largest_bucket: usize,
+    /// Tables a lookup may probe.
+    pub tables: usize,
+}
+
+impl Health {
+    /// Rule copies per rule (1.0 = no duplication).
+    pub fn replication(&self) -> f64 {
+        if self.rules == 0 {
+            return 1.0;
+        }
+        self.stored_rules as f64 / self.rules as f64
+    }
+}
+
+/// Classifier that can report its `Health`.
+pub trait Monitored {
+    /// Current health.
+    fn health(&self) -> Health;
+}
+
+/// Why a rebuild is due.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Trigger {
+    /// This many changes were applied since the last build.
+    Changes(usize),
+    /// Replication grew from `baseline` at the last build to `current`.
+    Replication { baseline: f64, current: f64 },
+    /// The largest bucket holds this many rule copies.
+    BucketLen(usize),
+}
+
+/// Decides when a dynamically updated classifier should be rebuilt.
+///
+/// By default a rebuild is due once replication doubles or a bucket grows
+/// past 32 rule copies. Health triggers compare against the health at the
+/// last build and only fire on degradation since then: a rule set whose fresh
+/// build already has a bucket over the limit does not rebuild on every
+/// change.
+#[derive(Debug, Clone)]
+pub struct RebuildPolicy {
+    max_changes: Option<usize>,
+    replication_growth: Option<f64>,
+    max_bucket_len: Option<usize>,
+    baseline: Health,
+    changes: usize,
+}
+
+impl Default for RebuildPolicy {
+    fn default() -> Self {
+        Self {
+            max_changes: None,
+            replication_growth: Some(2.0),
+            max_bucket_len: Some(32),
+            baseline: Health::default(),
+            changes: 0,
+        }
+    }
+}
+
+impl RebuildPolicy {
+    /// A policy that never fires, to enable triggers on one by one.
+    pub fn never() -> Self {
+        Self {
+            max_changes: None,
+            replication_growth: None,
+            max_bucket_len: None,
+            ..Self::default()
+        }
+    }
+
+    /// Rebuild after `changes` changes, whatever the health.
+    pub fn with_max_changes(mut self, changes: usize) -> Self {
+        self.max_changes = Some(changes);
+        self
+    }
+
+    /// Rebuild once replication reaches `factor` times its value at the last
+    /// build.
+    pub fn with_replication_growth(mut self, factor: f64) -> Self {
+        self.replication_growth = Some(factor);
+        self
+    }
+
+    /// Rebuild once the largest bucket holds more than `len` rule copies.
+    pub fn with_max_bucket_len(mut self, len: usize) -> Self {
+        self.max_bucket_len = Some(len);
+        self
+    }
+
+    /// Changes recorded since the last build.
+    pub fn changes(&self) -> usize {
+        self.changes
+    }
+
+    /// Health at the last build.
+    pub fn baseline(&self) -> Health {
+        self.baseline
+    }
+
+    /// Count a change applied to the classifier.
+    pub fn record(&mut self) {
+        self.changes += 1;
+    }
+
+    /// Whether a classifier in `health` should be rebuilt.
+    pub fn check(&self, health: &Health) -> Option<Trigger> {
+        if self.max_changes.is_some_and(|max| self.changes >= max) {
+            return Some(Trigger::Changes(self.changes));
+        }
+        let baseline = self.baseline.replication();
+        let current = health.replication();
+        if self
+            .replication_growth
+            .is_some_and(|factor| current > baseline && current >= baseline * factor)
+        {
+            return Some(Trigger::Replication { baseline, current });
+        }
+        let len = health.largest_bucket;
+        if self
+            .max_bucket_len
+            .is_some_and(|max| len > max && len > self.baseline.largest_bucket)
+        {
+            return Some(Trigger::BucketLen(len));
+        }
+        None
+    }
+
+    /// Start over from a fresh build in `health`.
+    pub fn rebuilt(&mut self, health: Health) {
+        self.baseline = health;
+        self.changes = 0;
+    }
+}
+
+/// Applies changes to a dynamic classifier in place and rebuilds it when its
+/// `RebuildPolicy` fires.
+///
+/// A rebuild costs a full `build()`, paid by the change that fires the
+/// policy; lookups never see a half-updated classifier.
+pub struct AutoRebuild<C> {
+    rules: Vec<Rule>,
+    inner: C,
+    policy: RebuildPolicy,
+    rebuilds: usize,
+    last_trigger: Option<Trigger>,
+}
+
+impl<C: DynamicClassifier + Monitored> AutoRebuild<C> {
+    /// Build over `rules`, rebuilding as `policy` decides.
+    pub fn new(rules: &[Rule], mut policy: RebuildPolicy) -> Self {
+        let inner = C::build(rules);
+        policy.rebuilt(inner.health());
+        Self {
+            rules: rules.to_vec(),
+            inner,
+            policy,
+            rebuilds: 0,
+            last_trigger: None,
+        }
+    }
+
+    /// The current rule set, in insertion order.
+    pub fn rules(&self) -> &[Rule] {
+        &self.rules
+    }
+
+    /// The classifier serving lookups.
+    pub fn inner(&self) -> &C {
+        &self.inner
+    }
+
+    pub fn policy(&self) -> &RebuildPolicy {
+        &self.policy
+    }
+
+    /// Number of rebuilds performed since construction.
+    pub fn rebuilds(&self) -> usize {
+        self.rebuilds
+    }
+
+    /// What fired the latest rebuild, if any happened.
+    pub fn last_trigger(&self) -> Option<Trigger> {
+        self.last_trigger
+    }
+
+    /// Rebuild now, whatever the policy says.
+    pub fn rebuild(&mut self) {
+        self.inner = C::build(&self.rules);
+        self.policy.rebuilt(self.inner.health());
+        self.rebuilds += 1;
+    }
+
+    fn changed(&mut self) {
+        self.policy.record();
+        if let Some(trigger) = self.policy.check(&self.inner.health()) {
+            self.last_trigger = Some(trigger);
+            self.rebuild();
+        }
+    }
+}
+
+impl<C: DynamicClassifier + Monitored> Classifier for AutoRebuild<C> {
+    fn build(rules: &[Rule]) -> Self {
+        Self::new(rules, RebuildPolicy::default())
+    }
+
+    fn classify_rule(&self, packet: &FiveTuple) -> Option<&Rule> {
+        self.inner.classify_rule(packet)
+    }
+
+    fn classify_batch(&self, packets: &[FiveTuple], out: &mut [Option<Action>]) {
+        self.inner.classify_batch(packets, out)
+    }
+
+    fn classify_with_stats(&self, packet: &FiveTuple) -> (Option<Action>, LookupStats) {
+        self.inner.classify_with_stats(packet)
+    }
+}
+
+impl<C: DynamicClassifier + Monitored + RegionQuery> RegionQuery for AutoRebuild<C> {
+    fn rules_overlapping(&self, region: &Region) -> Vec<&Rule> {
+        self.inner.rules_overlapping(region)
+    }
+}
+
+impl<C: DynamicClassifier + Monitored> DynamicClassifier for AutoRebuild<C> {
+    fn insert(&mut self, rule: Rule) {
+        self.inner.insert(rule.clone());
+        self.rules.push(rule);
+        self.changed();
+    }
+
+    fn remove(&mut self, id: u32) -> Option<Rule> {
+        let rule = self.inner.remove(id)?;
+        if let Some(idx) = self.rules.iter().position(|r| r == &rule) {
+            self.rules.remove(idx);
+        }
+        self.changed();
+        Some(rule)
+    }
+}
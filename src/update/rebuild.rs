@@ -0,0 +1,84 @@
+//! Dynamic updates by full reconstruction.
+
+use super::DynamicClassifier;
+use crate::classifier::{Classifier, LookupStats};
+use crate::geometry::Region;
+use crate::packet::FiveTuple;
+use crate::query::RegionQuery;
+use crate::rule::{Action, Rule};
+use alloc::vec::Vec;
+
+/// Makes any classifier dynamic by rebuilding it after every change.
+///
+/// Correct by construction but pays a full `build()` per update, so it is
+/// only suitable for small or rarely changing rule sets (and as the baseline
+/// native update paths are checked against).
+pub struct Rebuilding<C> {
+    rules: Vec<Rule>,
+    inner: C,
+    rebuilds: usize,
+}
+
+impl<C: Classifier> Rebuilding<C> {
+    /// The current rule set, in insertion order.
+    pub fn rules(&self) -> &[Rule] {
+        &self.rules
+    }
+
+    /// The classifier built over the current rule set.
+    pub fn inner(&self) -> &C {
+        &self.inner
+    }
+
+    /// Number of rebuilds performed since construction.
+    pub fn rebuilds(&self) -> usize {
+        self.rebuilds
+    }
+
+    fn rebuild(&mut self) {
+        self.inner = C::build(&self.rules);
+        self.rebuilds += 1;
+    }
+}
+
+impl<C: Classifier> Classifier for Rebuilding<C> {
+    fn build(rules: &[Rule]) -> Self {
+        Self {
+            rules: rules.to_vec(),
+            inner: C::build(rules),
+            rebuilds: 0,
+        }
+    }
+
+    fn classify_rule(&self, packet: &FiveTuple) -> Option<&Rule> {
+        self.inner.classify_rule(packet)
+    }
+
+    fn classify_batch(&self, packets: &[FiveTuple], out: &mut [Option<Action>]) {
+        self.inner.classify_batch(packets, out)
+    }
+
+    fn classify_with_stats(&self, packet: &FiveTuple) -> (Option<Action>, LookupStats) {
+        self.inner.classify_with_stats(packet)
+    }
+}
+
+impl<C: RegionQuery> RegionQuery for Rebuilding<C> {
+    fn rules_overlapping(&self, region: &Region) -> Vec<&Rule> {
+        self.inner.rules_overlapping(region)
+    }
+}
+
+impl<C: Classifier> DynamicClassifier for Rebuilding<C> {
+    fn insert(&mut self, rule: Rule) {
+        self.rules.push(rule);
+        self.rebuild();
+    }
+
+    fn remove(&mut self, id: u32) -> Option<Rule> {
+        let idx = self.rules.iter().position(|r| r.id == id)?;
+        let rule = self.rules.remove(idx);
+        self.rebuild();
+        Some(rule)
+    }
+}
@@ -0,0 +1,192 @@
+use cutsplit::accel::{verify, DeviceImage, Mismatch, PacketBatch, DEVICE_LEAF, NO_MATCH};
+use cutsplit::classifier::Classifier;
+use cutsplit::compact::{CompactTree, FreezeCompact};
+use cutsplit::cutsplit::builder::Builder as CutSplitBuilder;
+use cutsplit::cutsplit::classifier::CutSplitClassifier;
+use cutsplit::fingerprint::{Fingerprint, LoadError};
+use cutsplit::hypersplit::classifier::HyperSplitClassifier;
+use cutsplit::linear::LinearClassifier;
+use cutsplit::simulation::Simulation;
+use cutsplit::update::audit::DecodeError;
+
+#[test]
+fn test_device_image_matches_cpu() {
+    let mut sim = Simulation::new(3254);
+    let mut rules = sim.generate_rules(1500);
+    rules.pop();
+    let packets = sim.generate_packets(2000);
+    let batch = PacketBatch::pack(&packets);
+    assert_eq!(batch.len(), packets.len());
+    assert!((0..batch.len()).all(|i| batch.get(i) == packets[i]));
+
+    let linear = LinearClassifier::build(&rules);
+    // The secondary index leaves are flattened into plain lists.
+    let indexed = CutSplitClassifier::from_builder(
+        &CutSplitBuilder::new(64, 8).with_secondary_index(16),
+        &rules,
+    );
+    for tree in [
+        indexed.to_compact(),
+        HyperSplitClassifier::build(&rules).to_compact(),
+    ] {
+        let image = DeviceImage::new(&tree);
+        assert_eq!(image.nodes.len(), tree.node_count());
+        assert!(image.nodes.iter().any(|n| n.kind == DEVICE_LEAF));
+        let results = image.run(&batch);
+        assert!(results.contains(&NO_MATCH));
+        assert!(verify(&linear, &image, &batch, &results).is_empty());
+        assert!(verify(&tree, &image, &batch, &results).is_empty());
+    }
+
+    // A faulty accelerator is caught, as are missing results.
+    let image = DeviceImage::new(&CompactTree::build(&rules));
+    let mut results = image.run(&batch);
+    let hit = results.iter().position(|&r| r != NO_MATCH).unwrap();
+    let expected = image.rule_id(results[hit]);
+    results[hit] = NO_MATCH;
+    results.pop();
+    assert_eq!(
+        verify(&linear, &image, &batch, &results),
+        [
+            Mismatch {
+                packet: hit,
+                expected,
+                actual: None,
+            },
+            Mismatch {
+                packet: batch.len() - 1,
+                expected: linear
+                    .classify_rule(&packets[batch.len() - 1])
+                    .map(|r| r.id),
+                actual: None,
+            },
+        ]
+    );
+
+    let empty = DeviceImage::new(&CompactTree::build(&[]));
+    assert_eq!(empty.lookup(&batch, 0), NO_MATCH);
+}
+
+#[test]
+fn test_device_blobs() {
+    let mut sim = Simulation::new(5);
+    let rules = sim.generate_rules(50);
+    let packets = sim.generate_packets(10);
+    let image = DeviceImage::new(&CompactTree::build(&rules));
+    let bytes = image.to_bytes();
+    assert_eq!(&bytes[..6], b"CSDI\x02\x01");
+    assert_eq!(bytes[6..14], Fingerprint::of(&rules).0.to_le_bytes());
+    let len = |at: usize| u32::from_le_bytes(bytes[at..at + 4].try_into().unwrap()) as usize;
+    assert_eq!(
+        (len(14), len(18), len(22)),
+        (image.nodes.len(), image.leaf_rules.len(), image.rules.len())
+    );
+    assert_eq!(
+        bytes.len(),
+        26 + 16 * image.nodes.len() + 4 * image.leaf_rules.len() + 56 * image.rules.len()
+    );
+    assert_eq!(DeviceImage::from_bytes(&bytes), Ok(image.clone()));
+
+    let batch = PacketBatch::pack(&packets);
+    let bytes = batch.to_bytes();
+    assert_eq!(bytes.len(), 4 + 17 * packets.len());
+    assert_eq!(&bytes[4..8], &packets[0].src_ip.to_le_bytes());
+    assert_eq!(bytes[4 + 12 * packets.len()], packets[0].proto);
+}
+
+#[test]
+fn test_device_image_fingerprint_check() {
+    let mut sim = Simulation::new(3256);
+    let rules = sim.generate_rules(200);
+    let image = DeviceImage::new(&HyperSplitClassifier::build(&rules).to_compact());
+    let expected = Fingerprint::of(&rules);
+    assert_eq!(image.fingerprint, Some(expected));
+    let bytes = image.to_bytes();
+    assert_eq!(
+        DeviceImage::from_bytes_checked(&bytes, expected),
+        Ok(image.clone())
+    );
+
+    // An image of the previous policy is refused.
+    let mut updated = rules.clone();
+    updated[3].priority += 1;
+    let current = Fingerprint::of(&updated);
+    assert_eq!(
+        DeviceImage::from_bytes_checked(&bytes, current),
+        Err(LoadError::Stale {
+            expected: current,
+            found: Some(expected),
+        })
+    );
+
+    // Version 1 images carry no fingerprint: they decode, but never check.
+    let mut v1 = bytes[..5].to_vec();
+    v1[4] = 1;
+    v1.extend_from_slice(&bytes[14..]);
+    let old = DeviceImage::from_bytes(&v1).unwrap();
+    assert_eq!(old.fingerprint, None);
+    assert_eq!(old.nodes, image.nodes);
+    assert!(matches!(
+        DeviceImage::from_bytes_checked(&v1, expected),
+        Err(LoadError::Stale { found: None, .. })
+    ));
+
+    assert_eq!(
+        DeviceImage::from_bytes(&bytes[..bytes.len() - 1]),
+        Err(DecodeError::Truncated)
+    );
+    assert_eq!(DeviceImage::from_bytes(b"CSAL"), Err(DecodeError::BadMagic));
+    assert_eq!(
+        DeviceImage::from_bytes(b"CSDI\x09"),
+        Err(DecodeError::UnsupportedVersion(9))
+    );
+}
+
+#[test]
+fn test_device_image_rejects_unwalkable_graphs() {
+    let rules = Simulation::new(3254).generate_rules(100);
+    let image = DeviceImage::new(&CompactTree::build(&rules));
+    assert_ne!(image.nodes[0].kind, DEVICE_LEAF);
+    let leaf = image
+        .nodes
+        .iter()
+        .position(|n| n.kind == DEVICE_LEAF && n.left > 0)
+        .unwrap();
+    let corrupt = |edit: &dyn Fn(&mut DeviceImage)| {
+        let mut bad = image.clone();
+        edit(&mut bad);
+        DeviceImage::from_bytes(&bad.to_bytes())
+    };
+
+    // Unknown kinds, out-of-range children and children looping back.
+    assert_eq!(corrupt(&|i| i.nodes[0].kind = 5), Err(DecodeError::Corrupt));
+    assert_eq!(
+        corrupt(&|i| i.nodes[0].kind = 0xFE),
+        Err(DecodeError::Corrupt)
+    );
+    let past = image.nodes.len() as u32;
+    assert_eq!(
+        corrupt(&|i| i.nodes[0].left = past),
+        Err(DecodeError::Corrupt)
+    );
+    assert_eq!(
+        corrupt(&|i| i.nodes[0].right = 0),
+        Err(DecodeError::Corrupt)
+    );
+    // Leaf slices past `leaf_rules` and entries past `rules`.
+    let entries = image.leaf_rules.len() as u32;
+    assert_eq!(
+        corrupt(&|i| i.nodes[leaf].value = entries),
+        Err(DecodeError::Corrupt)
+    );
+    assert_eq!(
+        corrupt(&|i| i.nodes[leaf].left = u32::MAX),
+        Err(DecodeError::Corrupt)
+    );
+    let past = image.rules.len() as u32;
+    assert_eq!(
+        corrupt(&|i| i.leaf_rules[0] = past),
+        Err(DecodeError::Corrupt)
+    );
+    assert_eq!(corrupt(&|_| {}), Ok(image.clone()));
+}
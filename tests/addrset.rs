@@ -0,0 +1,231 @@
+use cutsplit::addrset::{AddressSets, DeltaStats, SetClassifier, SetDelta};
+use cutsplit::classifier::Classifier;
+use cutsplit::hypersplit::classifier::HyperSplitClassifier;
+use cutsplit::packet::FiveTuple;
+use cutsplit::prefix::Prefix;
+use cutsplit::rule::{Range, Rule};
+use cutsplit::simulation::Simulation;
+
+const BLOCKLIST: u32 = 1;
+const SERVERS: u32 = 2;
+
+fn reference<'a>(rules: &'a [Rule], sets: &AddressSets, packet: &FiveTuple) -> Option<&'a Rule> {
+    rules
+        .iter()
+        .filter(|r| r.matches_with(packet, sets))
+        .min_by_key(|r| r.priority)
+}
+
+#[test]
+fn test_address_set_registry() {
+    let mut sets = AddressSets::new();
+    sets.define(
+        BLOCKLIST,
+        [Prefix::new(0x0A00_0000, 8), Prefix::new(0xC0A8_0100, 24)],
+    );
+    sets.insert_range(SERVERS, Range::new(0x0A01_0000, 0x0A01_00FF));
+
+    assert!(sets.contains(BLOCKLIST, 0x0A01_0203));
+    assert!(sets.contains(BLOCKLIST, 0xC0A8_01FE));
+    assert!(!sets.contains(BLOCKLIST, 0xC0A8_0201));
+    assert!(!sets.contains(42, 0x0A01_0203));
+    assert_eq!(sets.memberships(0x0A01_0010), [BLOCKLIST, SERVERS]);
+    assert_eq!(sets.memberships(0x0A02_0010), [BLOCKLIST]);
+    assert_eq!(sets.set_len(BLOCKLIST), 2);
+    assert_eq!(sets.len(), 2);
+
+    // Both sets sharing a prefix store it once.
+    sets.insert(SERVERS, Prefix::new(0x0A00_0000, 8));
+    assert_eq!(sets.prefix_count(), 3);
+    assert!(!sets.insert(SERVERS, Prefix::new(0x0A00_0000, 8)));
+    assert!(sets.remove(BLOCKLIST, Prefix::new(0x0A00_0000, 8)));
+    assert!(!sets.remove(BLOCKLIST, Prefix::new(0x0A00_0000, 8)));
+    assert!(sets.contains(SERVERS, 0x0A7F_0000));
+    assert!(!sets.contains(BLOCKLIST, 0x0A7F_0000));
+
+    // Redefining replaces the content; undefining forgets the set.
+    sets.define(BLOCKLIST, [Prefix::new(0x0B00_0000, 8)]);
+    assert_eq!(sets.prefixes(BLOCKLIST), [Prefix::new(0x0B00_0000, 8)]);
+    assert!(sets.undefine(BLOCKLIST));
+    assert!(!sets.is_defined(BLOCKLIST));
+    assert_eq!(sets.prefix_count(), 2);
+}
+
+#[test]
+fn test_set_classifier_matches_reference() {
+    let mut sim = Simulation::new(5150);
+    let mut rules = sim.generate_rules(300);
+    // Every seventh rule only applies to blocklisted sources, every eleventh
+    // to traffic between servers in either direction.
+    for (i, rule) in rules.iter_mut().enumerate() {
+        if i % 7 == 0 {
+            rule.field_sets_mut().src_set = Some(BLOCKLIST);
+        } else if i % 11 == 0 {
+            let sets = rule.field_sets_mut();
+            sets.src_set = Some(SERVERS);
+            sets.dst_set = Some(SERVERS);
+            rule.bidirectional = true;
+        }
+    }
+
+    let mut sets = AddressSets::new();
+    sets.define(
+        BLOCKLIST,
+        [Prefix::new(0xC0A8_0000, 17), Prefix::new(0x0800_0000, 5)],
+    );
+    sets.define(
+        SERVERS,
+        [Prefix::new(0xC0A8_8000, 17), Prefix::new(0x5000_0000, 4)],
+    );
+
+    let mut packets = sim.generate_packets(2000);
+    for rule in &rules {
+        let packet = sim.sample_for_rule(rule);
+        packets.push(packet);
+        packets.push(packet.reversed());
+    }
+
+    let classifier = SetClassifier::<HyperSplitClassifier>::new(&rules, sets.clone());
+    assert_eq!(
+        classifier.referencing(),
+        rules.iter().filter(|r| r.references_sets()).count()
+    );
+    let mut hits = 0;
+    for p in &packets {
+        let expected = reference(&rules, &sets, p);
+        hits += expected.is_some_and(|r| r.references_sets()) as usize;
+        assert_eq!(
+            classifier.classify_rule(p).map(|r| r.id),
+            expected.map(|r| r.id),
+            "{:?}",
+            p
+        );
+        assert_eq!(
+            classifier.classify_with_stats(p).0,
+            expected.map(|r| r.action)
+        );
+    }
+    assert!(hits > 0);
+
+    // Referenced sets that are not defined are empty.
+    let empty = SetClassifier::<HyperSplitClassifier>::build(&rules);
+    for p in &packets {
+        let got = empty.classify_rule(p);
+        assert!(got.is_none_or(|r| !r.references_sets()));
+    }
+}
+
+#[test]
+fn test_feed_updates_propagate_without_rebuild() {
+    let mut sim = Simulation::new(808);
+    let mut rules = sim.generate_rules(200);
+    // Highest priority: drop anything from the blocklist.
+    let mut drop = rules[0].clone();
+    drop.id = rules.len() as u32;
+    drop.priority = 0;
+    drop.src_ip = Range::any(0, u32::MAX);
+    drop.dst_ip = Range::any(0, u32::MAX);
+    drop.src_port = Range::any(0, u16::MAX);
+    drop.dst_port = Range::any(0, u16::MAX);
+    drop.proto = Range::any(0, u8::MAX);
+    drop.zone = cutsplit::rule::ANY_ZONE;
+    drop.field_sets_mut().src_set = Some(BLOCKLIST);
+    for rule in &mut rules {
+        rule.priority += 1;
+    }
+    rules.push(drop.clone());
+
+    let mut sets = AddressSets::new();
+    sets.define(BLOCKLIST, [Prefix::new(0x0100_0000, 8)]);
+    let mut classifier = SetClassifier::<HyperSplitClassifier>::new(&rules, sets);
+    let packets = sim.generate_packets(1000);
+    let check = |classifier: &SetClassifier<HyperSplitClassifier>| {
+        for p in &packets {
+            let expected = reference(&rules, classifier.sets(), p);
+            assert_eq!(
+                classifier.classify_rule(p).map(|r| r.id),
+                expected.map(|r| r.id)
+            );
+        }
+    };
+    check(&classifier);
+
+    let mut packet = packets[0];
+    packet.src_ip = 0xCB00_7101;
+    assert_ne!(
+        classifier.classify_rule(&packet).map(|r| r.id),
+        Some(drop.id)
+    );
+
+    let generation = classifier.sets().generation();
+    let delta = SetDelta {
+        set: BLOCKLIST,
+        added: vec![Prefix::new(0xCB00_7100, 24), Prefix::new(0x0100_0000, 8)],
+        removed: vec![Prefix::new(0x0200_0000, 8)],
+    };
+    assert_eq!(
+        classifier.apply(&delta),
+        DeltaStats {
+            added: 1,
+            removed: 0
+        }
+    );
+    assert!(classifier.sets().generation() > generation);
+    assert_eq!(
+        classifier.classify_rule(&packet).map(|r| r.id),
+        Some(drop.id)
+    );
+    check(&classifier);
+
+    assert!(classifier.remove_prefix(BLOCKLIST, Prefix::new(0xCB00_7100, 24)));
+    assert_ne!(
+        classifier.classify_rule(&packet).map(|r| r.id),
+        Some(drop.id)
+    );
+    assert!(classifier.insert_prefix(BLOCKLIST, Prefix::new(0xCB00_0000, 8)));
+    assert_eq!(
+        classifier.classify_rule(&packet).map(|r| r.id),
+        Some(drop.id)
+    );
+    classifier.define_set(BLOCKLIST, []);
+    assert_eq!(classifier.sets().set_len(BLOCKLIST), 0);
+    check(&classifier);
+}
+
+#[test]
+fn test_unresolved_set_references_fail_closed() {
+    use cutsplit::cutsplit::classifier::CutSplitClassifier;
+    use cutsplit::linear::LinearClassifier;
+    use cutsplit::rule::{expand_rules, Action};
+    use cutsplit::tss::classifier::TSSClassifier;
+
+    // "Deny sources in the blocklist" ahead of a default permit.
+    let mut deny = Rule {
+        id: 1,
+        priority: 0,
+        ..Rule::wildcard(Action::Deny)
+    };
+    deny.field_sets_mut().src_set = Some(BLOCKLIST);
+    let permit = Rule {
+        id: 2,
+        priority: 1,
+        ..Rule::wildcard(Action::Permit)
+    };
+    let rules = vec![deny.clone(), permit];
+    assert_eq!(expand_rules(&rules).len(), 1);
+
+    // Without a registry the reference cannot be resolved: the rule matches
+    // nothing, instead of every source.
+    let packets = Simulation::new(3217).generate_packets(200);
+    let linear = LinearClassifier::build(&rules);
+    let cutsplit = CutSplitClassifier::build(&rules);
+    let hypersplit = HyperSplitClassifier::build(&rules);
+    let tss = TSSClassifier::build(&rules);
+    for p in &packets {
+        assert!(!deny.matches(p));
+        assert_eq!(linear.classify_rule(p).map(|r| r.id), Some(2));
+        assert_eq!(cutsplit.classify_rule(p).map(|r| r.id), Some(2));
+        assert_eq!(hypersplit.classify_rule(p).map(|r| r.id), Some(2));
+        assert_eq!(tss.classify_rule(p).map(|r| r.id), Some(2));
+    }
+}
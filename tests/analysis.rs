@@ -0,0 +1,119 @@
+use cutsplit::classifier::Classifier;
+use cutsplit::linear::LinearClassifier;
+use cutsplit::packet::PROTO_TCP;
+use cutsplit::prefix::Prefix;
+use cutsplit::rule::analysis::{analyze, Conflict, ConflictKind, Redundant, Shadowed};
+use cutsplit::rule::{Action, Range, Rule};
+use cutsplit::simulation::Simulation;
+
+#[test]
+fn test_policy_anomalies() {
+    let server = Prefix::new(0xc0a8_0105, 32);
+    let lan = Prefix::new(0xc0a8_0000, 16);
+    let admins = Prefix::new(0x0a00_0000, 8);
+    let tcp_to = |dst: Prefix<u32>, ports: Range<u16>| Rule {
+        dst_ip: dst.to_range(),
+        dst_port: ports,
+        proto: Range::exact(PROTO_TCP),
+        ..Rule::wildcard(Action::Permit)
+    };
+    let rules = [
+        Rule::tcp_service(server, 22).with_src(admins),
+        // Never applies: rule 1 permits all of it.
+        Rule::tcp_service(server, 22)
+            .with_src(Prefix::new(0x0a01_0000, 16))
+            .with_action(Action::Deny),
+        Rule::tcp_service(server, 443),
+        Rule::tcp_service(server, 443),
+        tcp_to(lan, Range::new(400, 500)).with_action(Action::Deny),
+        tcp_to(Prefix::new(0, 0), Range::new(0, 1023)).with_src(admins),
+        // The final deny would drop these packets anyway.
+        tcp_to(lan, Range::exact(8000))
+            .with_src(Prefix::new(0x0a02_0000, 16))
+            .with_action(Action::Deny),
+        Rule::wildcard(Action::Deny),
+    ];
+    let rules: Vec<Rule> = rules
+        .into_iter()
+        .enumerate()
+        .map(|(i, r)| r.with_id(i as u32 + 1).with_priority(i as u32 + 1))
+        .collect();
+
+    let report = analyze(&rules);
+    assert_eq!(
+        report.shadowed,
+        vec![Shadowed {
+            rule: 2,
+            by: vec![1]
+        }]
+    );
+    // Either copy of the 443 rule can go, but not both.
+    assert_eq!(
+        report.redundant,
+        vec![
+            Redundant {
+                rule: 3,
+                by: vec![4]
+            },
+            Redundant {
+                rule: 4,
+                by: vec![3]
+            },
+            Redundant {
+                rule: 7,
+                by: vec![8]
+            },
+        ]
+    );
+    let conflict = |rule, with, kind| Conflict { rule, with, kind };
+    use ConflictKind::*;
+    assert_eq!(
+        report.conflicts,
+        vec![
+            conflict(5, 3, Generalization),
+            conflict(6, 5, Correlation),
+            conflict(8, 1, Generalization),
+            conflict(8, 3, Generalization),
+            conflict(8, 6, Generalization),
+        ]
+    );
+    assert_eq!(report.correlations().count(), 1);
+    assert!(!report.is_clean());
+
+    let clean = [rules[0].clone(), rules[2].clone(), rules[7].clone()];
+    assert!(analyze(&clean).is_clean());
+}
+
+#[test]
+fn test_analysis_agrees_with_lookups() {
+    let mut sim = Simulation::new(32622);
+    let rules = sim.generate_rules(60);
+    let report = analyze(&rules);
+    let linear = LinearClassifier::build(&rules);
+    let mut packets = sim.generate_packets(500);
+    for rule in &rules {
+        packets.extend((0..20).map(|_| sim.sample_for_rule(rule)));
+    }
+
+    // Shadowed rules never win.
+    for s in &report.shadowed {
+        assert!(packets
+            .iter()
+            .all(|p| linear.classify_rule(p).map(|r| r.id) != Some(s.rule)));
+    }
+    // Each redundant rule can go without changing any action.
+    for r in &report.redundant {
+        let without: Vec<Rule> = rules.iter().filter(|x| x.id != r.rule).cloned().collect();
+        let pruned = LinearClassifier::build(&without);
+        for p in &packets {
+            assert_eq!(pruned.classify(p), linear.classify(p), "rule {}", r.rule);
+        }
+    }
+    // Conflicting rules take different actions, the better one ahead.
+    assert!(!report.conflicts.is_empty());
+    for c in &report.conflicts {
+        let [a, b] = [c.rule, c.with].map(|id| rules.iter().find(|r| r.id == id).unwrap());
+        assert_ne!(a.action, b.action);
+        assert!(a.priority >= b.priority);
+    }
+}
@@ -0,0 +1,136 @@
+use cutsplit::approx::{ApproxParams, ApproximateClassifier, BloomFilter};
+use cutsplit::classifier::Classifier;
+use cutsplit::hypersplit::classifier::HyperSplitClassifier;
+use cutsplit::linear::LinearClassifier;
+use cutsplit::rule::{Action, Range, Rule, ANY_ZONE};
+use cutsplit::simulation::Simulation;
+
+#[test]
+fn test_bloom_filter_has_no_false_negatives() {
+    let mut filter = BloomFilter::new(1000, 10, 7);
+    for key in 0..1000u64 {
+        filter.insert(key * 7919);
+    }
+    assert!((0..1000u64).all(|key| filter.contains(key * 7919)));
+    let false_positives = (0..10_000u64)
+        .filter(|&key| filter.contains(key * 7919 + 1))
+        .count();
+    assert!(false_positives < 500, "{}", false_positives);
+    assert_eq!(filter.size_bytes(), 1256);
+}
+
+#[test]
+fn test_approximate_prefilter() {
+    let mut sim = Simulation::new(3252);
+    let mut rules = sim.generate_rules(500);
+    // Without the trailing default deny, most packets can skip the exact check.
+    rules.pop();
+    // A broad deny ahead of everything: telnet from anywhere.
+    for rule in &mut rules {
+        rule.priority += 1;
+    }
+    rules.insert(
+        0,
+        Rule {
+            id: 9000,
+            priority: 0,
+            src_ip: Range::any(0, u32::MAX),
+            dst_ip: Range::any(0, u32::MAX),
+            src_port: Range::any(0, u16::MAX),
+            dst_port: Range::exact(23),
+            proto: Range::exact(6),
+            zone: ANY_ZONE,
+            bidirectional: false,
+            field_sets: None,
+            action: Action::Deny,
+        },
+    );
+    let mut packets = sim.generate_packets(2000);
+    for rule in rules.iter().chain(core::iter::repeat_n(&rules[0], 100)) {
+        packets.push(sim.sample_for_rule(rule));
+    }
+    let linear = LinearClassifier::build(&rules);
+    let denied = |p| linear.classify(p) == Some(Action::Deny);
+
+    let exact = ApproximateClassifier::<HyperSplitClassifier>::build_with(
+        &rules,
+        ApproxParams {
+            max_broad_rules: usize::MAX,
+            ..ApproxParams::default()
+        },
+        HyperSplitClassifier::build,
+    );
+    assert_eq!(exact.omitted_rules(), 0);
+    for p in &packets {
+        assert_eq!(exact.classify(p) == Action::Deny, denied(p), "{:?}", p);
+        if denied(p) {
+            assert!(exact.prefilter(p));
+        }
+    }
+    let report = exact.analyze(&packets);
+    assert_eq!(report.packets, packets.len());
+    assert_eq!(report.false_permits, 0);
+    assert!(report.fallback_rate() < 1.0, "{:?}", report);
+    assert_eq!(
+        report.fallbacks - report.false_positives,
+        packets.iter().filter(|p| denied(p)).count()
+    );
+
+    // Omitting the broad deny rules only ever turns denies into permits.
+    let lossy = ApproximateClassifier::<HyperSplitClassifier>::build_with(
+        &rules,
+        ApproxParams {
+            max_keys_per_rule: 1,
+            max_broad_rules: 0,
+            ..ApproxParams::default()
+        },
+        HyperSplitClassifier::build,
+    );
+    assert!(lossy.omitted_rules() > 0);
+    let mut false_permits = 0;
+    for p in &packets {
+        match lossy.classify(p) {
+            Action::Deny => assert!(denied(p)),
+            Action::Permit => false_permits += usize::from(denied(p)),
+        }
+    }
+    let report = lossy.analyze(&packets);
+    assert_eq!(report.false_permits, false_permits);
+    assert!(report.false_permit_rate() > 0.0, "{:?}", report);
+    assert!(report.fallback_rate() <= exact.analyze(&packets).fallback_rate());
+}
+
+#[test]
+fn test_approximate_prefix_extremes() {
+    let mut sim = Simulation::new(3252);
+    let rules = sim.generate_rules(200);
+    let mut packets = sim.generate_packets(1000);
+    for rule in &rules {
+        packets.push(sim.sample_for_rule(rule));
+    }
+    let linear = LinearClassifier::build(&rules);
+    let denied = |p| linear.classify(p) == Some(Action::Deny);
+    assert!(packets.iter().any(&denied));
+
+    for prefix_len in [0, 32] {
+        let approx = ApproximateClassifier::<HyperSplitClassifier>::build_with(
+            &rules,
+            ApproxParams {
+                prefix_len,
+                max_broad_rules: usize::MAX,
+                ..ApproxParams::default()
+            },
+            HyperSplitClassifier::build,
+        );
+        assert_eq!(approx.omitted_rules(), 0);
+        for p in &packets {
+            assert_eq!(approx.classify(p) == Action::Deny, denied(p), "{:?}", p);
+        }
+        let report = approx.analyze(&packets);
+        assert_eq!(report.false_permits, 0);
+        if prefix_len == 0 {
+            // A single block: every deny rule covers every packet's key.
+            assert_eq!(report.fallback_rate(), 1.0);
+        }
+    }
+}
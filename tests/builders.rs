@@ -0,0 +1,1087 @@
+use cutsplit::classifier::Classifier;
+use cutsplit::cutsplit::builder::Builder as CutSplitBuilder;
+use cutsplit::cutsplit::classifier::CutSplitClassifier;
+use cutsplit::cutsplit::tree::Dimension;
+use cutsplit::geometry::Region;
+use cutsplit::hicuts::builder::Builder as HiCutsBuilder;
+use cutsplit::hicuts::classifier::{HiCutsClassifier, OutOfRange};
+use cutsplit::hicuts::tree::Node as HiCutsNode;
+use cutsplit::hypersplit::builder::Builder as HyperSplitBuilder;
+use cutsplit::hypersplit::classifier::HyperSplitClassifier;
+use cutsplit::leaf::LeafPolicy;
+use cutsplit::linear::LinearClassifier;
+use cutsplit::memory::MemoryStats;
+use cutsplit::rule::{Range, Rule};
+use cutsplit::serial::Codec;
+use cutsplit::simulation::Simulation;
+
+#[test]
+fn test_adaptive_leaf_policy_correctness() {
+    assert_eq!(LeafPolicy::Fixed.threshold(10, 5, 3.0), 10);
+    assert_eq!(LeafPolicy::adaptive().threshold(10, 0, 1.0), 10);
+    assert_eq!(LeafPolicy::adaptive().threshold(10, 10, 1.0), 20);
+    assert_eq!(LeafPolicy::adaptive().threshold(10, 0, 2.0), 20);
+
+    let mut sim = Simulation::new(4242);
+    let rules = sim.generate_rules(1000);
+    let packets = sim.generate_packets(1000);
+    let policy = LeafPolicy::adaptive();
+
+    let linear = LinearClassifier::build(&rules);
+    let cutsplit = CutSplitClassifier::from_builder(
+        &CutSplitBuilder::new(10, 20).with_leaf_policy(policy),
+        &rules,
+    );
+    let hicuts = HiCutsClassifier::from_builder(
+        &HiCutsBuilder::new(10, 20).with_leaf_policy(policy),
+        &rules,
+    );
+    let hypersplit = HyperSplitClassifier::from_builder(
+        &HyperSplitBuilder::new(8, 32).with_leaf_policy(policy),
+        &rules,
+    );
+
+    for p in &packets {
+        let expected = linear.classify(p);
+        assert_eq!(cutsplit.classify(p), expected);
+        assert_eq!(hicuts.classify(p), expected);
+        assert_eq!(hypersplit.classify(p), expected);
+    }
+}
+
+#[test]
+fn test_secondary_index_for_oversized_leaves() {
+    use cutsplit::classifier::LookupStats;
+    use cutsplit::leaf::BitVectorIndex;
+
+    let mut sim = Simulation::new(777);
+    let rules = sim.generate_rules(800);
+    let packets = sim.generate_packets(1000);
+    let linear = LinearClassifier::build(&rules);
+
+    let index = BitVectorIndex::build(&rules);
+    // Shallow trees force large leaves at max depth.
+    let cutsplit = CutSplitClassifier::from_builder(
+        &CutSplitBuilder::new(10, 3).with_secondary_index(32),
+        &rules,
+    );
+    let hicuts =
+        HiCutsClassifier::from_builder(&HiCutsBuilder::new(10, 2).with_secondary_index(32), &rules);
+    let hypersplit = HyperSplitClassifier::from_builder(
+        &HyperSplitBuilder::new(8, 3).with_secondary_index(32),
+        &rules,
+    );
+
+    for p in &packets {
+        let expected = linear.classify(p);
+        let mut stats = LookupStats::default();
+        assert_eq!(index.lookup(p, &mut stats).map(|r| r.action), expected);
+        assert_eq!(cutsplit.classify(p), expected);
+        assert_eq!(hicuts.classify(p), expected);
+        assert_eq!(hypersplit.classify(p), expected);
+
+        let (_, stats) = hypersplit.classify_with_stats(p);
+        assert!(stats.rules_compared <= 32);
+    }
+}
+
+#[test]
+fn test_wildcard_split_matches_single_classifier() {
+    use cutsplit::preprocess::{split_wildcards, wildcard_count, WildcardSplit};
+    use cutsplit::tss::classifier::TSSClassifier;
+
+    let mut sim = Simulation::new(31337);
+    let rules = sim.generate_rules(1000);
+    let packets = sim.generate_packets(1000);
+
+    let (specific, wildcards) = split_wildcards(&rules, 3);
+    assert_eq!(specific.len() + wildcards.len(), rules.len());
+    assert!(wildcards.iter().all(|r| wildcard_count(r) >= 3));
+    // The trailing default rule is fully wildcarded.
+    assert_eq!(wildcard_count(rules.last().unwrap()), 5);
+
+    let linear = LinearClassifier::build(&rules);
+    let hypersplit: WildcardSplit<HyperSplitClassifier> = WildcardSplit::build(&rules);
+    let hicuts: WildcardSplit<HiCutsClassifier, TSSClassifier> = WildcardSplit::build(&rules);
+    assert_eq!(hypersplit.extracted(), wildcards.len());
+
+    for p in &packets {
+        let expected = linear.classify(p);
+        assert_eq!(hypersplit.classify(p), expected);
+        assert_eq!(hicuts.classify(p), expected);
+        assert_eq!(hypersplit.classify_with_stats(p).0, expected);
+    }
+}
+
+#[test]
+fn test_wildcard_split_breaks_ties_in_rule_order() {
+    use cutsplit::packet::FiveTuple;
+    use cutsplit::preprocess::WildcardSplit;
+    use cutsplit::rule::Action;
+
+    // Same priority: the wildcard rule goes to the sidecar, the specific one
+    // to the tree, and whichever was given first must win.
+    let wildcard = Rule {
+        id: 1,
+        priority: 5,
+        ..Rule::wildcard(Action::Deny)
+    };
+    let specific = Rule {
+        id: 2,
+        priority: 5,
+        src_ip: Range::new(10, 20),
+        dst_ip: Range::new(0, 100),
+        dst_port: Range::exact(80),
+        proto: Range::exact(6),
+        ..Rule::wildcard(Action::Permit)
+    };
+    let p = FiveTuple {
+        src_ip: 15,
+        dst_ip: 0,
+        src_port: 0,
+        dst_port: 80,
+        proto: 6,
+        zone: 0,
+    };
+
+    for rules in [
+        vec![wildcard.clone(), specific.clone()],
+        vec![specific.clone(), wildcard.clone()],
+    ] {
+        let split: WildcardSplit<HyperSplitClassifier> = WildcardSplit::build(&rules);
+        assert_eq!(split.extracted(), 1);
+        let expected = LinearClassifier::build(&rules)
+            .classify_rule(&p)
+            .unwrap()
+            .id;
+        assert_eq!(expected, rules[0].id);
+        assert_eq!(split.classify_rule(&p).unwrap().id, expected);
+        let (rule, stats) = split.classify_rule_with_stats(&p);
+        assert_eq!(rule.unwrap().id, expected);
+        assert!(stats.rules_compared >= 2);
+    }
+}
+
+#[test]
+fn test_prefix_normalization_preserves_semantics() {
+    use cutsplit::normalize::{is_prefix_rule, to_prefix_rules};
+
+    let mut sim = Simulation::new(55);
+    let rules = sim.generate_rules(60);
+    let packets = sim.generate_packets(2000);
+
+    let (prefix_rules, stats) = to_prefix_rules(&rules);
+    assert_eq!(stats.input_rules, rules.len());
+    assert_eq!(stats.output_rules, prefix_rules.len());
+    assert!(stats.ratio() >= 1.0);
+    assert!(prefix_rules.iter().all(is_prefix_rule));
+
+    let original = LinearClassifier::build(&rules);
+    let normalized = LinearClassifier::build(&prefix_rules);
+    for p in &packets {
+        assert_eq!(original.classify(p), normalized.classify(p));
+    }
+}
+
+#[test]
+fn test_elementary_intervals_cover_domain() {
+    use cutsplit::cutsplit::tree::Dimension;
+    use cutsplit::interval::ElementaryIntervals;
+
+    let mut sim = Simulation::new(8);
+    let rules = sim.generate_rules(200);
+    let packets = sim.generate_packets(500);
+
+    for dim in Dimension::ALL {
+        let ei = ElementaryIntervals::build(&rules, dim);
+        let ivs = ei.intervals();
+        assert_eq!(ivs[0].range.min, 0);
+        assert_eq!(ivs.last().unwrap().range.max, dim.max_value());
+        for w in ivs.windows(2) {
+            assert_eq!(w[0].range.max + 1, w[1].range.min);
+        }
+        assert!(ei.max_overlap() <= rules.len());
+
+        for p in &packets {
+            let v = dim.packet_value(p);
+            let iv = ei.locate(v);
+            assert!(iv.range.contains(v));
+            let expected: Vec<u32> = (0..rules.len() as u32)
+                .filter(|&i| dim.rule_range(&rules[i as usize]).contains(v))
+                .collect();
+            assert_eq!(iv.rules, expected);
+        }
+    }
+}
+
+#[test]
+fn test_hicuts_out_of_range_modes() {
+    let mut sim = Simulation::new(3199);
+    let rules = sim.generate_rules(300);
+    let linear = LinearClassifier::build(&rules);
+
+    // Tree covering only the LAN destinations.
+    let lan = Range::new(0xC0A8_0000, 0xC0A8_FFFF);
+    let region = Region::full().with(Dimension::DstIp, lan);
+    let builder = HiCutsBuilder::new(10, 20);
+    let clamp = HiCutsClassifier::from_tree(builder.build_region(&rules, &region));
+    let strict = HiCutsClassifier::from_tree(builder.build_region(&rules, &region))
+        .with_out_of_range(OutOfRange::Error);
+
+    let packets = sim.generate_packets(2000);
+    let mut outside = 0;
+    for p in &packets {
+        if lan.contains(p.dst_ip) {
+            let expected = linear.classify_rule(p).map(|r| r.id);
+            assert_eq!(clamp.classify_rule(p).map(|r| r.id), expected);
+            assert_eq!(strict.try_classify_rule(p).unwrap().map(|r| r.id), expected);
+        } else {
+            outside += 1;
+            // Clamping never returns a rule that does not match.
+            if let Some(rule) = clamp.classify_rule(p) {
+                assert!(rule.matches(p));
+            }
+            let err = strict.try_classify_rule(p).unwrap_err();
+            assert_eq!(err.value, p.dst_ip);
+            assert_eq!(err.dimension, Dimension::DstIp);
+            assert_eq!(strict.classify(p), None);
+        }
+    }
+    assert!(outside > 0);
+    for tree in [&clamp, &strict] {
+        let mut out = vec![None; packets.len()];
+        tree.classify_batch(&packets, &mut out);
+        assert!(packets
+            .iter()
+            .zip(&out)
+            .all(|(p, &a)| tree.classify(p) == a));
+    }
+
+    // Full-space trees never report out-of-range packets.
+    let full = HiCutsClassifier::build(&rules).with_out_of_range(OutOfRange::Error);
+    assert!(packets.iter().all(|p| full.try_classify_rule(p).is_ok()));
+}
+
+/// Regions and rule counts of the leaves of a HiCuts tree.
+fn hicuts_leaves(node: &HiCutsNode, region: Region, out: &mut Vec<(Region, usize)>) {
+    match node {
+        HiCutsNode::Internal {
+            dimension,
+            start,
+            end,
+            step,
+            num_cuts,
+            children,
+        } => {
+            for (i, child) in children.iter().enumerate() {
+                let i = i as u32;
+                let max = if i == num_cuts - 1 {
+                    *end
+                } else {
+                    start + (i + 1) * step - 1
+                };
+                let range = Range::new(start + i * step, max);
+                hicuts_leaves(child, region.with(*dimension, range), out);
+            }
+        }
+        HiCutsNode::Leaf { rules } => out.push((region, rules.len())),
+        HiCutsNode::IndexedLeaf { index } => out.push((region, index.rules().len())),
+    }
+}
+
+#[test]
+fn test_hicuts_clipped_rules_drop_shadowed_copies() {
+    let mut sim = Simulation::new(3231);
+    let mut rules = sim.generate_rules(400);
+    let builder = HiCutsBuilder::new(10, 20);
+
+    // A leading catch-all leaves nothing else reachable: a single leaf.
+    let mut catch_all = rules.last().unwrap().clone();
+    catch_all.id = 10_000;
+    catch_all.priority = 0;
+    let mut shadowed = vec![catch_all];
+    shadowed.extend(rules.iter().cloned().map(|mut r| {
+        r.priority += 1;
+        r
+    }));
+    let root = builder.build(&shadowed);
+    assert!(matches!(&root, HiCutsNode::Leaf { rules } if rules.len() == 1));
+
+    // Leaves inside a high-priority block hold the block alone, and results
+    // are unchanged.
+    for r in &mut rules {
+        r.priority += 1;
+    }
+    let mut block = rules.last().unwrap().clone();
+    block.id = 10_001;
+    block.priority = 0;
+    block.src_ip = Range::new(0, 0x7FFF_FFFF);
+    rules.insert(0, block.clone());
+
+    let mut leaves = Vec::new();
+    hicuts_leaves(&builder.build(&rules), Region::full(), &mut leaves);
+    let inside: Vec<usize> = leaves
+        .iter()
+        .filter(|(region, _)| Region::from_rule(&block).contains(region))
+        .map(|&(_, n)| n)
+        .collect();
+    assert!(!inside.is_empty());
+    assert!(inside.iter().all(|&n| n == 1), "{:?}", inside);
+
+    let hicuts = HiCutsClassifier::from_builder(&builder, &rules);
+    let linear = LinearClassifier::build(&rules);
+    for p in sim.generate_packets(3000) {
+        assert_eq!(
+            hicuts.classify_rule(&p).map(|r| r.id),
+            linear.classify_rule(&p).map(|r| r.id)
+        );
+    }
+}
+
+#[test]
+fn test_hypersplit_replication_stop() {
+    let mut sim = Simulation::new(3232);
+    let mut rules = sim.generate_rules(300);
+    // Wildcard-dense tail: every rule pins a single field.
+    let base = rules.pop().unwrap();
+    for (i, p) in sim.generate_packets(300).iter().enumerate() {
+        let mut r = base.clone();
+        r.id = 1000 + i as u32;
+        r.priority = 1000 + i as u32;
+        match i % 3 {
+            0 => r.src_ip = Range::new(p.src_ip & !0xFF, p.src_ip | 0xFF),
+            1 => r.dst_ip = Range::new(p.dst_ip & !0xFF, p.dst_ip | 0xFF),
+            _ => r.dst_port = Range::exact(p.dst_port),
+        }
+        rules.push(r);
+    }
+    rules.push(Rule {
+        id: 5000,
+        priority: 5000,
+        ..base
+    });
+
+    let (free, free_stats) =
+        HyperSplitClassifier::from_builder_with_stats(&HyperSplitBuilder::new(8, 32), &rules);
+    let (bounded, stats) = HyperSplitClassifier::from_builder_with_stats(
+        &HyperSplitBuilder::new(8, 32).with_spfac(1.2),
+        &rules,
+    );
+    assert_eq!(free_stats.replication_stops, 0);
+    assert!(stats.replication_stops > 0);
+    assert!(stats.stored_rules < free_stats.stored_rules);
+    for s in [free_stats, stats] {
+        assert_eq!(s.internal_nodes + 1, s.leaves);
+        let stops = s.threshold_stops + s.depth_stops + s.no_split_stops + s.replication_stops;
+        assert_eq!(stops, s.leaves);
+    }
+
+    let linear = LinearClassifier::build(&rules);
+    for p in sim.generate_packets(2000) {
+        let expected = linear.classify_rule(&p).map(|r| r.id);
+        assert_eq!(bounded.classify_rule(&p).map(|r| r.id), expected);
+        assert_eq!(free.classify_rule(&p).map(|r| r.id), expected);
+    }
+}
+
+#[test]
+fn test_protocol_partitioning() {
+    use cutsplit::preprocess::ProtoPartitioned;
+    use cutsplit::query::RegionQuery;
+
+    let mut sim = Simulation::new(3233);
+    let rules = sim.generate_rules(600);
+    let packets = sim.generate_packets(3000);
+    let linear = LinearClassifier::build(&rules);
+
+    let cutsplit = ProtoPartitioned::<CutSplitClassifier>::build(&rules);
+    let hicuts = ProtoPartitioned::<HiCutsClassifier>::build(&rules);
+    let builder = HyperSplitBuilder::new(8, 32).with_spfac(4.0);
+    let hypersplit =
+        ProtoPartitioned::build_with(&rules, |r| HyperSplitClassifier::from_builder(&builder, r));
+
+    let [tcp, udp, other] = cutsplit.partition_sizes();
+    assert!(tcp < rules.len() && udp < rules.len() && other < rules.len());
+    assert!(tcp + udp + other >= rules.len());
+
+    for p in &packets {
+        let expected = linear.classify_rule(p).map(|r| r.id);
+        assert_eq!(cutsplit.classify_rule(p).map(|r| r.id), expected);
+        assert_eq!(hicuts.classify_rule(p).map(|r| r.id), expected);
+        assert_eq!(hypersplit.classify_rule(p).map(|r| r.id), expected);
+        assert_eq!(hypersplit.classify_with_stats(p).0, linear.classify(p));
+    }
+
+    let udp_only = Region::full().with(Dimension::Proto, Range::exact(17));
+    let mut found: Vec<u32> = hypersplit
+        .rules_overlapping(&udp_only)
+        .iter()
+        .map(|r| r.id)
+        .collect();
+    found.sort_unstable();
+    let mut expected: Vec<u32> = rules
+        .iter()
+        .filter(|r| r.proto.contains(17))
+        .map(|r| r.id)
+        .collect();
+    expected.sort_unstable();
+    assert_eq!(found, expected);
+}
+
+#[test]
+fn test_dimension_heuristics() {
+    use cutsplit::cutsplit::tree::Node as CutSplitNode;
+    use cutsplit::heuristic::{DimensionHeuristic, DistinctEndpoints, Entropy, SpaceMeasure};
+
+    /// Always cuts destination ports while they can be cut.
+    struct PortsFirst;
+    impl DimensionHeuristic for PortsFirst {
+        fn score(&self, _: &[&Rule], dim: Dimension, _: &Region) -> f32 {
+            if dim == Dimension::DstPort {
+                1.0
+            } else {
+                0.0
+            }
+        }
+    }
+
+    let mut sim = Simulation::new(3234);
+    let rules = sim.generate_rules(800);
+    let wildcard = rules.last().unwrap().clone();
+    let port = |min: u16, max: u16| Rule {
+        dst_port: Range::new(min, max),
+        ..wildcard.clone()
+    };
+    let same = [port(80, 80), port(80, 80), port(80, 80)];
+    let spread = [port(0, 9), port(10, 19), port(20, 29), port(30, 39)];
+    let (same, spread): (Vec<&Rule>, Vec<&Rule>) = (same.iter().collect(), spread.iter().collect());
+    let full = Region::full();
+    assert_eq!(Entropy.score(&same, Dimension::DstPort, &full), 0.0);
+    assert!((Entropy.score(&spread, Dimension::DstPort, &full) - 2.0).abs() < 1e-3);
+    assert_eq!(
+        DistinctEndpoints.score(&same, Dimension::DstPort, &full),
+        2.0
+    );
+    assert_eq!(
+        DistinctEndpoints.score(&spread, Dimension::DstPort, &full),
+        4.0
+    );
+    assert_eq!(
+        DistinctEndpoints.score(&spread, Dimension::SrcIp, &full),
+        0.0
+    );
+    assert_eq!(SpaceMeasure.score(&spread, Dimension::SrcIp, &full), 0.0);
+    assert_eq!(
+        Entropy.select(&spread, &Dimension::ALL, &full),
+        Some(Dimension::DstPort)
+    );
+    // Clipped to ports 0..=9, only the first rule remains on the dimension.
+    let narrow = full.with(Dimension::DstPort, Range::new(0, 9));
+    assert_eq!(Entropy.score(&spread, Dimension::DstPort, &narrow), 0.0);
+
+    let mut packets = sim.generate_packets(2000);
+    packets.extend(rules.iter().map(|r| sim.sample_for_rule(r)));
+    let linear = LinearClassifier::build(&rules);
+
+    let heuristics: [fn() -> Box<dyn DimensionHeuristic>; 3] = [
+        || Box::new(DistinctEndpoints),
+        || Box::new(Entropy),
+        || Box::new(SpaceMeasure),
+    ];
+    for make in heuristics {
+        let mut cutsplit = CutSplitBuilder::new(10, 24);
+        cutsplit.dimension_heuristic = Some(make());
+        let mut hicuts = HiCutsBuilder::new(10, 24);
+        hicuts.dimension_heuristic = Some(make());
+        let mut hypersplit = HyperSplitBuilder::new(8, 32);
+        hypersplit.dimension_heuristic = Some(make());
+
+        let cutsplit = CutSplitClassifier::from_builder(&cutsplit, &rules);
+        let hicuts = HiCutsClassifier::from_builder(&hicuts, &rules);
+        let hypersplit = HyperSplitClassifier::from_builder(&hypersplit, &rules);
+        for p in &packets {
+            let expected = linear.classify_rule(p).map(|r| r.id);
+            assert_eq!(cutsplit.classify_rule(p).map(|r| r.id), expected);
+            assert_eq!(hicuts.classify_rule(p).map(|r| r.id), expected);
+            assert_eq!(hypersplit.classify_rule(p).map(|r| r.id), expected);
+        }
+    }
+
+    let root = CutSplitBuilder::new(10, 24)
+        .with_dimension_heuristic(PortsFirst)
+        .build(&rules);
+    assert!(matches!(
+        root,
+        CutSplitNode::Internal {
+            dimension: Dimension::DstPort,
+            ..
+        }
+    ));
+}
+
+#[test]
+fn test_cut_cost_models() {
+    use cutsplit::cost::{Balance, Cut, CutCost, LargestChild, Replication};
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+
+    let cut = Cut {
+        dimension: Dimension::DstPort,
+        depth: 0,
+        rules: 10,
+        children: &[6, 8],
+    };
+    assert_eq!(cut.total(), 14);
+    assert_eq!(cut.largest(), 8);
+    assert!((Replication.cost(&cut) - 1.4).abs() < 1e-6);
+    assert!((Balance::default().cost(&cut) - 9.4).abs() < 1e-6);
+    assert_eq!(LargestChild.cost(&cut), 8.0);
+
+    let mut sim = Simulation::new(3235);
+    let rules = sim.generate_rules(600);
+    let mut packets = sim.generate_packets(2000);
+    packets.extend(rules.iter().map(|r| sim.sample_for_rule(r)));
+    let linear = LinearClassifier::build(&rules);
+
+    // The defaults are the builders' own models.
+    let tree = |node: &dyn std::fmt::Debug| format!("{:?}", node);
+    assert_eq!(
+        tree(&CutSplitBuilder::new(10, 24).build(&rules)),
+        tree(
+            &CutSplitBuilder::new(10, 24)
+                .with_cut_cost(Replication)
+                .build(&rules)
+        )
+    );
+    assert_eq!(
+        tree(&HyperSplitBuilder::new(8, 32).build(&rules)),
+        tree(
+            &HyperSplitBuilder::new(8, 32)
+                .with_cut_cost(Balance::default())
+                .build(&rules)
+        )
+    );
+
+    // A closure optimizing for depth alone, counting its calls.
+    let calls = Arc::new(AtomicUsize::new(0));
+    let counter = calls.clone();
+    let shallow = move |cut: &Cut| {
+        counter.fetch_add(1, Ordering::Relaxed);
+        cut.largest() as f32
+    };
+    let cutsplit = CutSplitClassifier::from_builder(
+        &CutSplitBuilder::new(10, 24).with_cut_cost(shallow.clone()),
+        &rules,
+    );
+    let hicuts = HiCutsClassifier::from_builder(
+        &HiCutsBuilder::new(10, 24).with_cut_cost(Replication),
+        &rules,
+    );
+    let hypersplit = HyperSplitClassifier::from_builder(
+        &HyperSplitBuilder::new(8, 32).with_cut_cost(shallow),
+        &rules,
+    );
+    assert!(calls.load(Ordering::Relaxed) > 0);
+    for p in &packets {
+        let expected = linear.classify_rule(p).map(|r| r.id);
+        assert_eq!(cutsplit.classify_rule(p).map(|r| r.id), expected);
+        assert_eq!(hicuts.classify_rule(p).map(|r| r.id), expected);
+        assert_eq!(hypersplit.classify_rule(p).map(|r| r.id), expected);
+    }
+}
+
+#[test]
+fn test_rule_set_tracking() {
+    use cutsplit::ruleset::RuleSet;
+
+    let full = RuleSet::full(130);
+    assert_eq!(full.len(), 130);
+    assert_eq!(full.iter().count(), 130);
+    assert!(full.contains(129) && !full.contains(130));
+
+    let mut set = RuleSet::empty(130);
+    for i in [3, 64, 65, 129, 64] {
+        set.insert(i);
+    }
+    assert_eq!(set.len(), 4);
+    assert_eq!(set.iter().collect::<Vec<_>>(), vec![3, 64, 65, 129]);
+    set.remove(64);
+    set.remove(64);
+    assert_eq!(set.len(), 3);
+    let mut same = RuleSet::empty(130);
+    for i in [129, 65, 3] {
+        same.insert(i);
+    }
+    assert_eq!(set, same);
+
+    // Trees built from rule sets, with indexed leaves shared by set, match
+    // a linear search (the leaves keep the rules' list order).
+    let mut sim = Simulation::new(3237);
+    let rules = sim.generate_rules(1500);
+    let mut packets = sim.generate_packets(2000);
+    packets.extend(rules.iter().map(|r| sim.sample_for_rule(r)));
+    let linear = LinearClassifier::build(&rules);
+    let cutsplit = CutSplitClassifier::from_builder(
+        &CutSplitBuilder::new(16, 8).with_secondary_index(16),
+        &rules,
+    );
+    let hicuts =
+        HiCutsClassifier::from_builder(&HiCutsBuilder::new(16, 4).with_secondary_index(16), &rules);
+    let (hypersplit, stats) = HyperSplitClassifier::from_builder_with_stats(
+        &HyperSplitBuilder::new(16, 6).with_secondary_index(16),
+        &rules,
+    );
+    assert!(stats.stored_rules >= rules.len());
+    for p in &packets {
+        let expected = linear.classify_rule(p).map(|r| r.id);
+        assert_eq!(cutsplit.classify_rule(p).map(|r| r.id), expected);
+        assert_eq!(hicuts.classify_rule(p).map(|r| r.id), expected);
+        assert_eq!(hypersplit.classify_rule(p).map(|r| r.id), expected);
+    }
+}
+
+#[test]
+fn test_shadowed_rules_dropped_from_leaves() {
+    let mut sim = Simulation::new(3238);
+    let mut rules = sim.generate_rules(400);
+    let wildcard = rules.pop().unwrap();
+    // A block of the destination space taken by one rule, with worse rules
+    // inside it that can never win.
+    let block = Range::new(0x0A00_0000, 0x0AFF_FFFF);
+    let n = rules.len() as u32;
+    rules.insert(
+        0,
+        Rule {
+            id: n + 1,
+            priority: 0,
+            dst_ip: block,
+            ..wildcard.clone()
+        },
+    );
+    for i in 0..40u32 {
+        let host = 0x0A00_0000 + i * 0x0004_0000;
+        rules.push(Rule {
+            id: n + 2 + i,
+            priority: n + 2 + i,
+            dst_ip: Range::new(host, host + 0xFF),
+            dst_port: Range::exact(1000 + i as u16),
+            ..wildcard.clone()
+        });
+    }
+    rules.push(Rule {
+        id: 10_000,
+        priority: 10_000,
+        ..wildcard
+    });
+
+    let mut packets = sim.generate_packets(2000);
+    packets.extend(rules.iter().map(|r| sim.sample_for_rule(r)));
+    let linear = LinearClassifier::build(&rules);
+
+    let (_, cutsplit_dropped) = CutSplitBuilder::new(8, 24).build_counting_shadowed(&rules);
+    let (hicuts, hicuts_dropped) =
+        HiCutsBuilder::new(8, 24).build_region_counting_shadowed(&rules, &Region::full());
+    let (hypersplit, stats) =
+        HyperSplitClassifier::from_builder_with_stats(&HyperSplitBuilder::new(8, 32), &rules);
+    assert!(cutsplit_dropped > 0);
+    assert!(hicuts_dropped > 0);
+    assert!(stats.shadowed_rules > 0);
+
+    let cutsplit = CutSplitClassifier::from_builder(&CutSplitBuilder::new(8, 24), &rules);
+    let hicuts = HiCutsClassifier::from_tree(hicuts);
+    for p in &packets {
+        let expected = linear.classify_rule(p).map(|r| r.id);
+        assert_eq!(cutsplit.classify_rule(p).map(|r| r.id), expected);
+        assert_eq!(hicuts.classify_rule(p).map(|r| r.id), expected);
+        assert_eq!(hypersplit.classify_rule(p).map(|r| r.id), expected);
+    }
+}
+
+#[test]
+fn test_custom_cut_strategy() {
+    use cutsplit::classifier::LookupStats;
+    use cutsplit::dtree::{self, CutStrategy, NodeCut, TreeNode, TreeParams};
+    use cutsplit::leaf::BitVectorIndex;
+    use cutsplit::packet::FiveTuple;
+
+    /// Halves the node on its widest dimension (relative to the domain).
+    struct Halving;
+
+    enum Node {
+        Internal(Dimension, u32, Box<Node>, Box<Node>),
+        Leaf(Vec<Rule>),
+        Indexed(Box<BitVectorIndex>),
+    }
+
+    impl TreeNode for Node {
+        fn leaf(rules: Vec<Rule>) -> Self {
+            Node::Leaf(rules)
+        }
+        fn indexed_leaf(index: Box<BitVectorIndex>) -> Self {
+            Node::Indexed(index)
+        }
+    }
+
+    impl CutStrategy for Halving {
+        type Node = Node;
+        const NAME: &'static str = "halving";
+
+        fn params(&self) -> TreeParams {
+            TreeParams {
+                leaf_threshold: 8,
+                max_depth: 40,
+                leaf_policy: LeafPolicy::Fixed,
+                secondary_threshold: Some(32),
+                spfac: Some(3.0),
+            }
+        }
+
+        fn choose_cut(&self, _: &[&Rule], region: &Region, _: usize) -> Option<NodeCut> {
+            let share = |d: Dimension| {
+                let r = region.get(d);
+                (r.max - r.min) as f64 / d.max_value() as f64
+            };
+            let dim = Dimension::ALL
+                .into_iter()
+                .max_by(|a, b| share(*a).total_cmp(&share(*b)))?;
+            let r = region.get(dim);
+            (r.min < r.max).then(|| NodeCut::binary(dim, r.min + (r.max - r.min) / 2 + 1))
+        }
+
+        fn internal(&self, cut: &NodeCut, children: Vec<Node>) -> Node {
+            let [left, right] = <[Node; 2]>::try_from(children).ok().unwrap();
+            Node::Internal(
+                cut.dimension,
+                cut.children[1].min,
+                Box::new(left),
+                Box::new(right),
+            )
+        }
+    }
+
+    fn lookup<'a>(mut node: &'a Node, p: &FiveTuple) -> Option<&'a Rule> {
+        loop {
+            match node {
+                Node::Internal(dim, cut, left, right) => {
+                    node = if dim.packet_value(p) < *cut {
+                        left
+                    } else {
+                        right
+                    };
+                }
+                Node::Leaf(rules) => {
+                    return rules
+                        .iter()
+                        .filter(|r| r.matches(p))
+                        .min_by_key(|r| r.priority)
+                }
+                Node::Indexed(index) => return index.lookup(p, &mut LookupStats::default()),
+            }
+        }
+    }
+
+    let mut sim = Simulation::new(3239);
+    let rules = sim.generate_rules(500);
+    let mut packets = sim.generate_packets(2000);
+    packets.extend(rules.iter().map(|r| sim.sample_for_rule(r)));
+    let linear = LinearClassifier::build(&rules);
+
+    let (root, stats) = dtree::build(&Halving, &rules, &Region::full());
+    assert!(stats.internal_nodes > 0);
+    assert_eq!(stats.leaves, stats.internal_nodes + 1);
+    for p in &packets {
+        assert_eq!(
+            lookup(&root, p).map(|r| r.id),
+            linear.classify_rule(p).map(|r| r.id)
+        );
+    }
+
+    // The built-in trees report the same statistics.
+    let (_, stats) = HiCutsBuilder::new(10, 24).build_with_stats(&rules);
+    assert!(stats.leaves > stats.internal_nodes);
+    let (_, stats) = CutSplitBuilder::new(10, 24).build_with_stats(&rules);
+    assert_eq!(stats.leaves, stats.internal_nodes + 1);
+}
+
+/// Cut counts of the internal nodes of a HiCuts tree.
+fn hicuts_cut_counts(node: &HiCutsNode, out: &mut Vec<u32>) {
+    if let HiCutsNode::Internal {
+        num_cuts, children, ..
+    } = node
+    {
+        out.push(*num_cuts);
+        for child in children {
+            hicuts_cut_counts(child, out);
+        }
+    }
+}
+
+#[test]
+fn test_hicuts_adaptive_cut_counts() {
+    let mut sim = Simulation::new(3243);
+    let rules = sim.generate_rules(2000);
+    let packets = sim.generate_packets(1000);
+    let linear = LinearClassifier::build(&rules);
+
+    // Fan-out grows past 16 when the space budget allows it.
+    let wide = HiCutsBuilder::new(10, 20).with_max_cuts(256);
+    let mut counts = Vec::new();
+    hicuts_cut_counts(&wide.build(&rules), &mut counts);
+    assert!(counts.iter().all(|&c| c.is_power_of_two() && c <= 256));
+    assert!(counts.iter().any(|&c| c > 16), "{:?}", counts);
+    let hicuts = HiCutsClassifier::from_builder(&wide, &rules);
+    for p in &packets {
+        assert_eq!(hicuts.classify(p), linear.classify(p));
+    }
+
+    // Without room to replicate, every node is cut in two.
+    let mut tight = HiCutsBuilder::new(10, 20);
+    tight.spfac = 1;
+    let mut counts = Vec::new();
+    hicuts_cut_counts(&tight.build(&rules), &mut counts);
+    assert!(!counts.is_empty());
+    assert!(counts.iter().all(|&c| c == 2));
+}
+
+#[test]
+fn test_worst_case_bounded_build() {
+    use cutsplit::dtree::{WorstCase, WorstCaseError};
+    use cutsplit::rule::ANY_ZONE;
+
+    let mut sim = Simulation::new(3244);
+    let rules = sim.generate_rules(2000);
+    let packets = sim.generate_packets(2000);
+    let linear = LinearClassifier::build(&rules);
+    let bound = WorstCase {
+        max_depth: 6,
+        max_leaf_compares: 4,
+        max_indexed_rules: 4096,
+    };
+
+    let (_, stats) = CutSplitBuilder::new(10, 20)
+        .build_bounded(&rules, bound)
+        .unwrap();
+    assert!(stats.max_depth <= bound.max_depth);
+    assert!(stats.max_leaf_compares <= bound.max_leaf_compares);
+
+    let classifiers: Vec<(&str, Box<dyn Classifier>)> = vec![
+        (
+            "CutSplit",
+            Box::new(
+                CutSplitClassifier::from_builder_bounded(
+                    &CutSplitBuilder::new(10, 20),
+                    &rules,
+                    bound,
+                )
+                .unwrap(),
+            ),
+        ),
+        (
+            "HiCuts",
+            Box::new(
+                HiCutsClassifier::from_builder_bounded(&HiCutsBuilder::new(10, 20), &rules, bound)
+                    .unwrap(),
+            ),
+        ),
+        (
+            "HyperSplit",
+            Box::new(
+                HyperSplitClassifier::from_builder_bounded(
+                    &HyperSplitBuilder::new(8, 32),
+                    &rules,
+                    bound,
+                )
+                .unwrap(),
+            ),
+        ),
+    ];
+    for (name, classifier) in &classifiers {
+        for p in &packets {
+            let (action, work) = classifier.classify_with_stats(p);
+            assert_eq!(action, linear.classify(p), "{}", name);
+            assert!(work.depth as usize <= bound.max_depth, "{}", name);
+            assert!(
+                work.rules_compared as usize <= bound.max_leaf_compares,
+                "{}",
+                name
+            );
+        }
+    }
+
+    // Too many rules for one indexed leaf.
+    let tiny = WorstCase {
+        max_depth: 0,
+        max_leaf_compares: 4,
+        max_indexed_rules: 100,
+    };
+    assert!(matches!(
+        CutSplitBuilder::new(10, 20).build_bounded(&rules, tiny),
+        Err(WorstCaseError::IndexedRules { depth: 0, .. })
+    ));
+
+    // Zone-bound rules are checked one by one even in an indexed leaf.
+    let zoned: Vec<Rule> = rules
+        .iter()
+        .cloned()
+        .map(|mut r| {
+            if r.zone == ANY_ZONE {
+                r.zone = Range::exact(1);
+            }
+            r
+        })
+        .collect();
+    let unbounded = WorstCase {
+        max_indexed_rules: usize::MAX,
+        ..tiny
+    };
+    assert!(matches!(
+        HyperSplitBuilder::new(8, 32).build_bounded(&zoned, unbounded),
+        Err(WorstCaseError::LeafCompares { depth: 0, .. })
+    ));
+}
+
+#[test]
+fn test_build_with_config() {
+    use cutsplit::classifier::Config;
+    use cutsplit::memory::MemoryStats;
+    use cutsplit::partitionsort::classifier::PartitionSortClassifier;
+    use cutsplit::tss::classifier::TSSClassifier;
+    use cutsplit::update::policy::Monitored;
+
+    let mut sim = Simulation::new(3263);
+    let rules = sim.generate_rules(400);
+    let packets = sim.generate_packets(1000);
+    let linear = LinearClassifier::build(&rules);
+    fn check<C: Classifier>(
+        name: &str,
+        c: &C,
+        linear: &LinearClassifier,
+        packets: &[cutsplit::packet::FiveTuple],
+    ) {
+        for p in packets {
+            assert_eq!(c.classify(p), linear.classify(p), "{name} {p:?}");
+        }
+    }
+
+    // The default config builds what `build` does.
+    let default = Config::new();
+    assert_eq!(
+        CutSplitClassifier::build_with_config(&rules, &default).footprint(),
+        CutSplitClassifier::build(&rules).footprint()
+    );
+    assert_eq!(
+        HyperSplitClassifier::build_with_config(&rules, &default).footprint(),
+        HyperSplitClassifier::build(&rules).footprint()
+    );
+
+    // Sweep the tree parameters.
+    for leaf_threshold in [2, 8, 32] {
+        for max_depth in [4, 12] {
+            let config = Config::new()
+                .with_leaf_threshold(leaf_threshold)
+                .with_max_depth(max_depth);
+            let cutsplit = CutSplitClassifier::build_with_config(&rules, &config);
+            let hicuts = HiCutsClassifier::build_with_config(&rules, &config);
+            let hypersplit = HyperSplitClassifier::build_with_config(&rules, &config);
+            for footprint in [
+                cutsplit.footprint(),
+                hicuts.footprint(),
+                hypersplit.footprint(),
+            ] {
+                assert!(footprint.max_depth <= max_depth);
+            }
+            check("cutsplit", &cutsplit, &linear, &packets);
+            check("hicuts", &hicuts, &linear, &packets);
+            check("hypersplit", &hypersplit, &linear, &packets);
+        }
+    }
+    let fine = Config::new().with_leaf_threshold(2).with_max_depth(30);
+    let coarse = Config::new().with_leaf_threshold(64).with_max_depth(30);
+    assert!(
+        HyperSplitClassifier::build_with_config(&rules, &fine)
+            .footprint()
+            .leaves
+            > HyperSplitClassifier::build_with_config(&rules, &coarse)
+                .footprint()
+                .leaves
+    );
+
+    // A space factor bounds replication.
+    let unbounded = CutSplitClassifier::build_with_config(&rules, &fine).footprint();
+    let bounded = CutSplitClassifier::build_with_config(&rules, &fine.with_spfac(1.1));
+    check("cutsplit spfac", &bounded, &linear, &packets);
+    assert!(bounded.footprint().stored_rules <= unbounded.stored_rules);
+    check(
+        "hicuts spfac",
+        &HiCutsClassifier::build_with_config(&rules, &fine.with_spfac(1.5)),
+        &linear,
+        &packets,
+    );
+
+    // TSS merges fewer tuples with fewer merge bits.
+    let exact = TSSClassifier::build_with_config(&rules, &Config::new().with_merge_bits(0));
+    let merged = TSSClassifier::build_with_config(&rules, &Config::new().with_merge_bits(24));
+    assert!(exact.health().tables > merged.health().tables);
+    check("tss exact", &exact, &linear, &packets);
+    check("tss merged", &merged, &linear, &packets);
+
+    // Classifiers without parameters ignore the config.
+    check(
+        "ps",
+        &PartitionSortClassifier::build_with_config(&rules, &fine),
+        &linear,
+        &packets,
+    );
+}
+
+#[test]
+fn test_hicuts_shares_identical_subtrees() {
+    // Wildcard-heavy rules: most cuts fall inside the ranges of most rules.
+    let mut sim = Simulation::new(3264);
+    let mut rules = sim.generate_rules(300);
+    for (i, rule) in rules.iter_mut().enumerate() {
+        if i % 3 != 0 {
+            rule.src_ip = Range::new(0, u32::MAX);
+            rule.src_port = Range::new(0, u16::MAX);
+        }
+    }
+    let packets = sim.generate_packets(2000);
+    let shared = HiCutsClassifier::from_builder(&HiCutsBuilder::new(4, 20), &rules);
+    let separate = HiCutsClassifier::from_builder(
+        &HiCutsBuilder::new(4, 20).with_shared_subtrees(false),
+        &rules,
+    );
+    let linear = LinearClassifier::build(&rules);
+    for p in &packets {
+        let expected = linear.classify_rule(p).map(|r| r.id);
+        assert_eq!(shared.classify_rule(p).map(|r| r.id), expected);
+        assert_eq!(separate.classify_rule(p).map(|r| r.id), expected);
+    }
+
+    let (shared_fp, separate_fp) = (shared.footprint(), separate.footprint());
+    assert!(shared_fp.nodes < separate_fp.nodes);
+    assert!(shared_fp.bytes * 4 < separate_fp.bytes * 3);
+    assert!(shared_fp.stored_rules < separate_fp.stored_rules);
+    assert_eq!(shared_fp.max_depth, separate_fp.max_depth);
+    assert_eq!(shared_fp.rules, separate_fp.rules);
+
+    // Shared subtrees are written once and referenced afterwards; each tree
+    // loads back with the sharing it was saved with.
+    let (shared_bytes, separate_bytes) = (shared.serialize(), separate.serialize());
+    assert!(shared_bytes.len() < separate_bytes.len());
+    for (bytes, fp) in [(&shared_bytes, shared_fp), (&separate_bytes, separate_fp)] {
+        let loaded = HiCutsClassifier::deserialize(bytes).unwrap();
+        let loaded_fp = loaded.footprint();
+        assert_eq!(
+            (loaded_fp.nodes, loaded_fp.leaves, loaded_fp.stored_rules),
+            (fp.nodes, fp.leaves, fp.stored_rules)
+        );
+        assert_eq!(&loaded.serialize(), bytes);
+        for p in &packets {
+            assert_eq!(
+                loaded.classify_rule(p).map(|r| r.id),
+                linear.classify_rule(p).map(|r| r.id)
+            );
+        }
+    }
+}
@@ -0,0 +1,74 @@
+use cutsplit::classbench::{parse_rules, parse_trace, TraceEntry};
+use cutsplit::classifier::Classifier;
+use cutsplit::cutsplit::classifier::CutSplitClassifier;
+use cutsplit::hicuts::classifier::HiCutsClassifier;
+use cutsplit::hypersplit::classifier::HyperSplitClassifier;
+use cutsplit::linear::LinearClassifier;
+use cutsplit::partitionsort::classifier::PartitionSortClassifier;
+use cutsplit::rule::Rule;
+use cutsplit::tss::classifier::TSSClassifier;
+
+/// Checked-in rule sets with traces of expected matches (see `classbench` for the formats).
+const CORPUS: [(&str, &str, &str); 3] = [
+    (
+        "acl1",
+        include_str!("data/acl1.rules"),
+        include_str!("data/acl1.trace"),
+    ),
+    (
+        "fw1",
+        include_str!("data/fw1.rules"),
+        include_str!("data/fw1.trace"),
+    ),
+    (
+        "ipc1",
+        include_str!("data/ipc1.rules"),
+        include_str!("data/ipc1.trace"),
+    ),
+];
+
+fn check<C: Classifier>(algorithm: &str, set: &str, rules: &[Rule], trace: &[TraceEntry]) {
+    let classifier = C::build(rules);
+    for (i, entry) in trace.iter().enumerate() {
+        let got = classifier.classify_rule(&entry.packet).map(|r| r.id);
+        assert_eq!(
+            got, entry.expected,
+            "{} on {}: trace line {} ({:?})",
+            algorithm, set, i, entry.packet
+        );
+        let action = entry.expected.map(|id| rules[id as usize].action);
+        assert_eq!(classifier.classify(&entry.packet), action);
+    }
+}
+
+#[test]
+fn test_corpus_verdicts() {
+    for (set, rules, trace) in CORPUS {
+        let rules = parse_rules(rules).unwrap();
+        let trace = parse_trace(trace).unwrap();
+        assert!(!rules.is_empty() && !trace.is_empty());
+
+        check::<LinearClassifier>("Linear", set, &rules, &trace);
+        check::<CutSplitClassifier>("CutSplit", set, &rules, &trace);
+        check::<HiCutsClassifier>("HiCuts", set, &rules, &trace);
+        check::<HyperSplitClassifier>("HyperSplit", set, &rules, &trace);
+        check::<TSSClassifier>("TSS", set, &rules, &trace);
+        check::<PartitionSortClassifier>("PartitionSort", set, &rules, &trace);
+    }
+}
+
+#[test]
+fn test_classbench_parse_errors() {
+    let err = parse_rules("@1.2.3.4/33\t0.0.0.0/0\t0 : 65535\t0 : 65535\t0x06/0xFF\n").unwrap_err();
+    assert_eq!(err.line, 1);
+    assert!(parse_rules("# comment\n\n1.2.3.4/32\t0.0.0.0/0\t0 : 1\t0 : 1\t0x06/0xFF").is_err());
+    assert!(parse_rules("@1.2.3.4/32\t0.0.0.0/0\t0 : 1\t0 : 1\t0x06/0x0F").is_err());
+    assert!(parse_trace("1 2 3 4 5").is_err());
+
+    let rules =
+        parse_rules("@10.0.0.0/8\t0.0.0.0/0\t0 : 65535\t80 : 80\t0x06/0xFF\t0x0000/0x0200\tdeny\n")
+            .unwrap();
+    assert_eq!(rules[0].src_ip.min, 0x0A00_0000);
+    assert_eq!(rules[0].src_ip.max, 0x0AFF_FFFF);
+    assert_eq!(rules[0].action, cutsplit::rule::Action::Deny);
+}
@@ -106,3 +106,1112 @@ fn test_large_rule_set_correctness() {
         );
     }
 }
+
+#[test]
+fn test_shadow_pair_reports_divergences() {
+    use cutsplit::rule::Action;
+    use cutsplit::shadow::ShadowPair;
+
+    let mut sim = Simulation::new(2024);
+    let rules = sim.generate_rules(200);
+    let packets = sim.generate_packets(500);
+
+    // Candidate flips every Permit rule to Deny.
+    let mut candidate_rules = rules.clone();
+    for rule in &mut candidate_rules {
+        rule.action = Action::Deny;
+    }
+
+    let reference = LinearClassifier::build(&rules);
+    let mut shadow = ShadowPair::new(
+        HyperSplitClassifier::build(&rules),
+        CutSplitClassifier::build(&candidate_rules),
+    );
+
+    let mut expected = 0;
+    for packet in &packets {
+        let live = shadow.classify(packet);
+        assert_eq!(live, reference.classify(packet));
+        if live == Some(Action::Permit) {
+            expected += 1;
+        }
+    }
+
+    assert_eq!(shadow.evaluated(), packets.len() as u64);
+    assert_eq!(shadow.diverged(), expected);
+    assert!(shadow
+        .divergences()
+        .iter()
+        .all(|d| d.live == Some(Action::Permit) && d.candidate == Some(Action::Deny)));
+}
+
+#[test]
+fn test_checked_classifier_detects_divergence() {
+    use cutsplit::rule::Action;
+    use cutsplit::shadow::{CheckedClassifier, OnDivergence};
+
+    let mut sim = Simulation::new(3245);
+    let rules = sim.generate_rules(200);
+    let packets = sim.generate_packets(500);
+
+    // Agreeing algorithms: results are the primary's, nothing diverges.
+    let checked: CheckedClassifier<CutSplitClassifier, LinearClassifier> =
+        CheckedClassifier::build(&rules);
+    let linear = LinearClassifier::build(&rules);
+    for packet in &packets {
+        assert_eq!(checked.classify(packet), linear.classify(packet));
+        assert_eq!(
+            checked.classify_with_stats(packet).0,
+            linear.classify(packet)
+        );
+    }
+    assert_eq!(checked.checked(), 2 * packets.len() as u64);
+    assert_eq!(checked.diverged(), 0);
+
+    // A reference over flipped actions disagrees on every Permit.
+    let mut flipped = rules.clone();
+    for rule in &mut flipped {
+        rule.action = Action::Deny;
+    }
+    let logged = CheckedClassifier::new(
+        HyperSplitClassifier::build(&rules),
+        LinearClassifier::build(&flipped),
+    )
+    .with_on_divergence(OnDivergence::Log);
+    let mut expected = 0;
+    for packet in &packets {
+        let action = logged.classify(packet);
+        assert_eq!(action, linear.classify(packet));
+        if action == Some(Action::Permit) {
+            expected += 1;
+        }
+    }
+    assert!(expected > 0);
+    assert_eq!(logged.diverged(), expected);
+    logged.reset();
+    assert_eq!(logged.checked(), 0);
+
+    let strict = CheckedClassifier::new(
+        HyperSplitClassifier::build(&rules),
+        LinearClassifier::build(&flipped),
+    );
+    let permitted = packets
+        .iter()
+        .find(|p| linear.classify(p) == Some(Action::Permit))
+        .unwrap();
+    let result =
+        std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| strict.classify(permitted)));
+    assert!(result.is_err());
+}
+
+#[test]
+fn test_checked_classifier_allows_any_tied_rule() {
+    use cutsplit::rule::Rule;
+    use cutsplit::shadow::CheckedClassifier;
+
+    // Every rule has a twin of the same priority and action. The reference
+    // gets each twin first, so the two sides match different rules of the
+    // same priority, as TSS may on its own.
+    let mut sim = Simulation::new(3245);
+    let (mut rules, mut swapped) = (Vec::new(), Vec::new());
+    for rule in sim.generate_rules(200) {
+        let twin = Rule {
+            id: rule.id + 10_000,
+            ..rule.clone()
+        };
+        rules.extend([rule.clone(), twin.clone()]);
+        swapped.extend([twin, rule]);
+    }
+    let packets = sim.generate_packets(500);
+
+    let checked = CheckedClassifier::new(
+        TSSClassifier::build(&rules),
+        LinearClassifier::build(&swapped),
+    );
+    let linear = LinearClassifier::build(&rules);
+    for packet in &packets {
+        assert_eq!(checked.classify(packet), linear.classify(packet));
+        assert_eq!(
+            checked.classify_with_stats(packet).0,
+            linear.classify(packet)
+        );
+        assert_ne!(
+            checked.classify_rule(packet).map(|r| r.id),
+            checked.reference().classify_rule(packet).map(|r| r.id)
+        );
+    }
+    assert_eq!(checked.checked(), 3 * packets.len() as u64);
+    assert_eq!(checked.diverged(), 0);
+}
+
+#[test]
+fn test_classify_with_stats_matches_classify() {
+    fn check<C: Classifier>(c: &C, packets: &[cutsplit::packet::FiveTuple]) -> (u32, u32, u32) {
+        let mut totals = (0, 0, 0);
+        for p in packets {
+            let (action, stats) = c.classify_with_stats(p);
+            assert_eq!(action, c.classify(p));
+            totals.0 += stats.depth;
+            totals.1 += stats.rules_compared;
+            totals.2 += stats.tables_probed;
+        }
+        totals
+    }
+
+    let mut sim = Simulation::new(99);
+    let rules = sim.generate_rules(1000);
+    let packets = sim.generate_packets(200);
+
+    let (_, linear_cmp, _) = check(&LinearClassifier::build(&rules), &packets);
+    assert!(linear_cmp > 0);
+    for (depth, cmp, _) in [
+        check(&CutSplitClassifier::build(&rules), &packets),
+        check(&HiCutsClassifier::build(&rules), &packets),
+        check(&HyperSplitClassifier::build(&rules), &packets),
+    ] {
+        assert!(depth > 0);
+        assert!(cmp < linear_cmp);
+    }
+    let (_, _, probed) = check(&TSSClassifier::build(&rules), &packets);
+    assert!(probed > 0);
+    check(&PartitionSortClassifier::build(&rules), &packets);
+}
+
+#[test]
+fn test_classify_verdict_distinguishes_deny_from_no_match() {
+    use cutsplit::classifier::Verdict;
+    use cutsplit::packet::FiveTuple;
+    use cutsplit::rule::{Action, Range, Rule, ANY_ZONE};
+
+    let deny = Rule {
+        id: 7,
+        priority: 0,
+        src_ip: Range::new(0, 0xFFFF),
+        dst_ip: Range::any(0, u32::MAX),
+        src_port: Range::any(0, u16::MAX),
+        dst_port: Range::any(0, u16::MAX),
+        proto: Range::any(0, u8::MAX),
+        zone: ANY_ZONE,
+        bidirectional: false,
+        field_sets: None,
+        action: Action::Deny,
+    };
+    let c = LinearClassifier::build(&[deny]);
+    let denied = FiveTuple {
+        src_ip: 1,
+        dst_ip: 2,
+        src_port: 3,
+        dst_port: 4,
+        proto: 6,
+        zone: 0,
+    };
+    let unmatched = FiveTuple {
+        src_ip: 0x0001_0000,
+        ..denied
+    };
+
+    let verdict = c.classify_verdict(&denied);
+    assert_eq!(
+        verdict,
+        Verdict::Matched {
+            action: Action::Deny,
+            rule_id: 7
+        }
+    );
+    assert_eq!(verdict.action(), c.classify(&denied));
+    assert_eq!(verdict.rule_id(), Some(7));
+
+    let verdict = c.classify_verdict(&unmatched);
+    assert_eq!(verdict, Verdict::NoMatch);
+    assert!(!verdict.is_match());
+    assert_eq!(verdict.action_or(Action::Deny), Action::Deny);
+}
+
+#[test]
+fn test_classify_full_reports_matched_rule() {
+    use cutsplit::classifier::{MatchResult, Verdict};
+    use cutsplit::packet::FiveTuple;
+    use cutsplit::rule::Rule;
+
+    fn check<C: Classifier>(rules: &[Rule], packets: &[FiveTuple], reference: &LinearClassifier) {
+        let c = C::build(rules);
+        for p in packets {
+            let full = c.classify_full(p);
+            let expected = reference.classify_rule(p);
+            assert_eq!(full.map(|m| m.rule_id), expected.map(|r| r.id), "{:?}", p);
+            if let Some(m) = full {
+                assert_eq!(m.priority, m.rule.priority);
+                assert_eq!(m.action, m.rule.action);
+                assert_eq!(m.rule_id, m.rule.id);
+                assert_eq!(Verdict::from(m), c.classify_verdict(p));
+            }
+        }
+    }
+
+    let mut sim = Simulation::new(2511);
+    let rules = sim.generate_rules(300);
+    let packets = sim.generate_packets(400);
+    let linear = LinearClassifier::build(&rules);
+    check::<LinearClassifier>(&rules, &packets, &linear);
+    check::<CutSplitClassifier>(&rules, &packets, &linear);
+    check::<HiCutsClassifier>(&rules, &packets, &linear);
+    check::<HyperSplitClassifier>(&rules, &packets, &linear);
+    check::<TSSClassifier>(&rules, &packets, &linear);
+    check::<PartitionSortClassifier>(&rules, &packets, &linear);
+
+    let rule = &rules[3];
+    assert_eq!(
+        MatchResult::from(rule),
+        MatchResult {
+            rule_id: rule.id,
+            priority: rule.priority,
+            action: rule.action,
+            rule,
+        }
+    );
+}
+
+#[test]
+fn test_multitable_isolates_and_shares_tables() {
+    use cutsplit::multitable::MultiTableClassifier;
+
+    let mut sim = Simulation::new(5);
+    let tenant_a = sim.generate_rules(300);
+    let tenant_b = sim.generate_rules(300);
+    let packets = sim.generate_packets(300);
+
+    let mut multi = MultiTableClassifier::<HyperSplitClassifier>::new();
+    multi.insert_table(1, &tenant_a);
+    multi.insert_table(2, &tenant_b);
+    multi.insert_table(3, &tenant_a);
+    assert_eq!(multi.len(), 3);
+    assert_eq!(multi.distinct_classifiers(), 2);
+
+    let linear_a = LinearClassifier::build(&tenant_a);
+    let linear_b = LinearClassifier::build(&tenant_b);
+    for p in &packets {
+        assert_eq!(multi.classify(1, p), linear_a.classify(p));
+        assert_eq!(multi.classify(2, p), linear_b.classify(p));
+        assert_eq!(multi.classify(3, p), linear_a.classify(p));
+        assert_eq!(multi.classify(4, p), None);
+        assert_eq!(
+            multi.classify_full(2, p).map(|m| m.rule_id),
+            linear_b.classify_rule(p).map(|r| r.id)
+        );
+        assert!(multi.classify_full(4, p).is_none());
+    }
+
+    multi.insert_table(3, &tenant_b);
+    assert_eq!(multi.distinct_classifiers(), 2);
+    assert!(multi.remove_table(1));
+    assert_eq!(multi.distinct_classifiers(), 1);
+    assert!(!multi.remove_table(1));
+}
+
+#[test]
+fn test_zone_bound_rules() {
+    use cutsplit::hypersplit::builder::Builder;
+    use cutsplit::rule::Range;
+
+    const WAN: u32 = 1;
+    const LAN: u32 = 2;
+
+    let mut sim = Simulation::new(2024);
+    let mut rules = sim.generate_rules(500);
+    for (i, rule) in rules.iter_mut().enumerate() {
+        rule.zone = match i % 3 {
+            0 => Range::exact(WAN),
+            1 => Range::new(LAN, LAN + 1),
+            _ => rule.zone,
+        };
+    }
+    let packets: Vec<_> = sim
+        .generate_packets(2000)
+        .into_iter()
+        .enumerate()
+        .map(|(i, p)| p.in_zone(i as u32 % 4))
+        .collect();
+
+    let linear = LinearClassifier::build(&rules);
+    let indexed =
+        HyperSplitClassifier::from_builder(&Builder::new(64, 4).with_secondary_index(8), &rules);
+    let all: [(&str, Box<dyn Classifier>); 6] = [
+        ("CutSplit", Box::new(CutSplitClassifier::build(&rules))),
+        ("HiCuts", Box::new(HiCutsClassifier::build(&rules))),
+        ("HyperSplit", Box::new(HyperSplitClassifier::build(&rules))),
+        ("HyperSplit/indexed", Box::new(indexed)),
+        ("TSS", Box::new(TSSClassifier::build(&rules))),
+        (
+            "PartitionSort",
+            Box::new(PartitionSortClassifier::build(&rules)),
+        ),
+    ];
+    for p in &packets {
+        let expected = linear.classify_rule(p).map(|r| r.id);
+        if let Some(id) = expected {
+            assert!(rules[id as usize].zone.contains(p.zone));
+        }
+        for (name, c) in &all {
+            assert_eq!(
+                c.classify_rule(p).map(|r| r.id),
+                expected,
+                "{} {:?}",
+                name,
+                p
+            );
+        }
+    }
+
+    // The same packet is treated differently depending on where it came from.
+    let rule = &rules[0];
+    let packet = sim.sample_for_rule(rule);
+    assert_eq!(packet.zone, WAN);
+    assert_eq!(linear.classify_rule(&packet).map(|r| r.id), Some(rule.id));
+    assert_ne!(
+        linear.classify_rule(&packet.in_zone(LAN)).map(|r| r.id),
+        Some(rule.id)
+    );
+    assert!(rule.is_zone_bound());
+    assert!(!rules[2].is_zone_bound());
+}
+
+#[test]
+fn test_bidirectional_rules() {
+    use cutsplit::hypersplit::builder::Builder;
+
+    let mut sim = Simulation::new(77);
+    let mut rules = sim.generate_rules(300);
+    for rule in rules.iter_mut().step_by(2) {
+        rule.bidirectional = true;
+    }
+    // Every rule's own traffic, in both directions, plus random packets.
+    let mut packets = sim.generate_packets(1000);
+    for rule in &rules {
+        let packet = sim.sample_for_rule(rule);
+        packets.push(packet);
+        packets.push(packet.reversed());
+    }
+
+    let linear = LinearClassifier::build(&rules);
+    let indexed =
+        HyperSplitClassifier::from_builder(&Builder::new(64, 4).with_secondary_index(8), &rules);
+    let all: [(&str, Box<dyn Classifier>); 6] = [
+        ("CutSplit", Box::new(CutSplitClassifier::build(&rules))),
+        ("HiCuts", Box::new(HiCutsClassifier::build(&rules))),
+        ("HyperSplit", Box::new(HyperSplitClassifier::build(&rules))),
+        ("HyperSplit/indexed", Box::new(indexed)),
+        ("TSS", Box::new(TSSClassifier::build(&rules))),
+        (
+            "PartitionSort",
+            Box::new(PartitionSortClassifier::build(&rules)),
+        ),
+    ];
+    for p in &packets {
+        let expected = linear.classify_rule(p).map(|r| r.id);
+        for (name, c) in &all {
+            assert_eq!(
+                c.classify_rule(p).map(|r| r.id),
+                expected,
+                "{} {:?}",
+                name,
+                p
+            );
+        }
+    }
+
+    // A bidirectional rule matches its reply traffic, like its mirror does.
+    let rule = &rules[0];
+    let reply = sim.sample_for_rule(rule).reversed();
+    assert!(rule.matches(&reply));
+    assert!(rule.mirrored().matches(&reply));
+}
+
+#[test]
+fn test_multi_range_rules() {
+    use cutsplit::hypersplit::builder::Builder;
+    use cutsplit::rule::{expand_rules, Range};
+
+    let mut sim = Simulation::new(4242);
+    let mut rules = sim.generate_rules(300);
+    for (i, rule) in rules.iter_mut().enumerate() {
+        match i % 4 {
+            0 => {
+                let sets = rule.field_sets_mut();
+                sets.dst_port = vec![Range::exact(443), Range::new(8080, 8090)];
+            }
+            1 => {
+                let sets = rule.field_sets_mut();
+                sets.src_ip = vec![Range::new(0xC0A80000, 0xC0A8FFFF)];
+                sets.proto = vec![Range::exact(17)];
+                rule.bidirectional = true;
+            }
+            _ => {}
+        }
+    }
+    let mut packets = sim.generate_packets(1000);
+    for rule in &rules {
+        let mut packet = sim.sample_for_rule(rule);
+        packets.push(packet);
+        if let Some(sets) = &rule.field_sets {
+            if let Some(port) = sets.dst_port.last() {
+                packet.dst_port = port.max;
+                packets.push(packet);
+            }
+            if let Some(ip) = sets.src_ip.first() {
+                packet.src_ip = ip.min;
+                packets.push(packet.reversed());
+            }
+        }
+    }
+
+    // Each rule becomes one plain rule per range combination (and direction).
+    let expanded = expand_rules(&rules);
+    assert!(expanded.iter().all(|r| r.is_plain()));
+    assert!(expanded.len() > rules.len());
+    assert_eq!(expand_rules(&expanded).len(), expanded.len());
+
+    let linear = LinearClassifier::build(&rules);
+    let indexed =
+        HyperSplitClassifier::from_builder(&Builder::new(64, 4).with_secondary_index(8), &rules);
+    let all: [(&str, Box<dyn Classifier>); 6] = [
+        ("CutSplit", Box::new(CutSplitClassifier::build(&rules))),
+        ("HiCuts", Box::new(HiCutsClassifier::build(&rules))),
+        ("HyperSplit", Box::new(HyperSplitClassifier::build(&rules))),
+        ("HyperSplit/indexed", Box::new(indexed)),
+        ("TSS", Box::new(TSSClassifier::build(&rules))),
+        (
+            "PartitionSort",
+            Box::new(PartitionSortClassifier::build(&rules)),
+        ),
+    ];
+    for p in &packets {
+        let expected = linear.classify_rule(p).map(|r| r.id);
+        for (name, c) in &all {
+            assert_eq!(
+                c.classify_rule(p).map(|r| r.id),
+                expected,
+                "{} {:?}",
+                name,
+                p
+            );
+        }
+    }
+}
+
+#[test]
+fn test_negated_fields() {
+    use cutsplit::cutsplit::tree::Dimension;
+    use cutsplit::rule::{expand_rules, Range};
+
+    let mut sim = Simulation::new(31337);
+    let mut rules = sim.generate_rules(300);
+    for (i, rule) in rules.iter_mut().enumerate() {
+        match i % 5 {
+            0 => rule.negate(Dimension::SrcIp),
+            1 => {
+                rule.field_sets_mut().dst_port = vec![Range::exact(22)];
+                rule.negate(Dimension::DstPort);
+            }
+            2 => {
+                rule.negate(Dimension::Proto);
+                rule.bidirectional = true;
+            }
+            _ => {}
+        }
+    }
+    let mut packets = sim.generate_packets(1000);
+    for rule in &rules {
+        let packet = sim.sample_for_rule(rule);
+        packets.push(packet);
+        packets.push(packet.reversed());
+    }
+
+    let linear = LinearClassifier::build(&rules);
+    // TSS is left out: complements of arbitrary ranges decompose into so
+    // many prefixes that its build takes minutes (it is checked below).
+    let all: [(&str, Box<dyn Classifier>); 4] = [
+        ("CutSplit", Box::new(CutSplitClassifier::build(&rules))),
+        ("HiCuts", Box::new(HiCutsClassifier::build(&rules))),
+        ("HyperSplit", Box::new(HyperSplitClassifier::build(&rules))),
+        (
+            "PartitionSort",
+            Box::new(PartitionSortClassifier::build(&rules)),
+        ),
+    ];
+    for p in &packets {
+        let expected = linear.classify_rule(p).map(|r| r.id);
+        for (name, c) in &all {
+            assert_eq!(
+                c.classify_rule(p).map(|r| r.id),
+                expected,
+                "{} {:?}",
+                name,
+                p
+            );
+        }
+    }
+
+    // "src not in 10.0.0.0/8" splits into the two ranges around it.
+    let mut rule = rules[3].clone();
+    rule.src_ip = Range::new(0x0A00_0000, 0x0AFF_FFFF);
+    rule.negate(Dimension::SrcIp);
+    let expanded = expand_rules(std::slice::from_ref(&rule));
+    let src: Vec<_> = expanded.iter().map(|r| r.src_ip).collect();
+    assert_eq!(
+        src,
+        [
+            Range::new(0, 0x09FF_FFFF),
+            Range::new(0x0B00_0000, u32::MAX)
+        ]
+    );
+    let inside = sim.sample_for_rule(&rules[3]);
+    let mut outside = inside;
+    outside.src_ip = 0x0B00_0001;
+    let mut excluded = inside;
+    excluded.src_ip = 0x0A01_0203;
+    assert!(rule.matches(&outside));
+    assert!(!rule.matches(&excluded));
+
+    // "Deny ssh from outside 10.0.0.0/8, permit the rest".
+    let mut ssh = rules[3].clone();
+    ssh.src_ip = Range::new(0x0A00_0000, 0x0AFF_FFFF);
+    ssh.dst_ip = Range::any(0, u32::MAX);
+    ssh.src_port = Range::any(0, 65535);
+    ssh.dst_port = Range::exact(22);
+    ssh.proto = Range::exact(6);
+    ssh.zone = cutsplit::rule::ANY_ZONE;
+    ssh.negate(Dimension::SrcIp);
+    let mut rest = ssh.clone();
+    rest.id += 1;
+    rest.priority += 1;
+    rest.field_sets = None;
+    rest.src_ip = Range::any(0, u32::MAX);
+    let acl = [ssh.clone(), rest];
+    let linear = LinearClassifier::build(&acl);
+    let tss = TSSClassifier::build(&acl);
+    for src_ip in [
+        0,
+        0x09FF_FFFF,
+        0x0A00_0000,
+        0x0A12_3456,
+        0x0AFF_FFFF,
+        0x0B00_0000,
+        u32::MAX,
+    ] {
+        let mut p = inside;
+        p.src_ip = src_ip;
+        p.dst_port = 22;
+        p.proto = 6;
+        assert_eq!(
+            tss.classify_rule(&p).map(|r| r.id),
+            linear.classify_rule(&p).map(|r| r.id)
+        );
+        assert_eq!(
+            linear.classify_rule(&p).map(|r| r.id),
+            Some(if (0x0A00_0000..=0x0AFF_FFFF).contains(&src_ip) {
+                ssh.id + 1
+            } else {
+                ssh.id
+            })
+        );
+    }
+
+    // Negating a whole field leaves nothing to match.
+    rule.src_ip = Range::any(0, u32::MAX);
+    assert!(expand_rules(std::slice::from_ref(&rule)).is_empty());
+}
+
+#[test]
+fn test_frozen_classifiers_match_originals() {
+    use cutsplit::freeze::{Freeze, FrozenClassifier};
+
+    fn check<C: Freeze>(rules: &[cutsplit::rule::Rule], packets: &[cutsplit::packet::FiveTuple]) {
+        let expected: Vec<_> = {
+            let c = C::build(rules);
+            packets
+                .iter()
+                .map(|p| c.classify_rule(p).map(|r| r.id))
+                .collect()
+        };
+        let frozen = C::build(rules).freeze();
+        let built = FrozenClassifier::<C>::build(rules);
+        for (p, e) in packets.iter().zip(&expected) {
+            assert_eq!(frozen.classify_rule(p).map(|r| r.id), *e);
+            assert_eq!(built.classify_rule(p).map(|r| r.id), *e);
+            assert_eq!(
+                frozen.classify_with_stats(p),
+                frozen.inner().classify_with_stats(p)
+            );
+        }
+    }
+
+    let mut sim = Simulation::new(77);
+    let rules = sim.generate_rules(800);
+    let packets = sim.generate_packets(300);
+    check::<LinearClassifier>(&rules, &packets);
+    check::<CutSplitClassifier>(&rules, &packets);
+    check::<HiCutsClassifier>(&rules, &packets);
+    check::<HyperSplitClassifier>(&rules, &packets);
+    check::<TSSClassifier>(&rules, &packets);
+    check::<PartitionSortClassifier>(&rules, &packets);
+}
+
+#[test]
+fn test_compact_encoding_matches_trees() {
+    use cutsplit::compact::{CompactTree, FreezeCompact};
+    use cutsplit::cutsplit::builder::Builder as CutSplitBuilder;
+    use cutsplit::hypersplit::builder::Builder as HyperSplitBuilder;
+
+    fn check<C: FreezeCompact>(c: C, packets: &[cutsplit::packet::FiveTuple]) -> CompactTree {
+        let expected: Vec<_> = packets
+            .iter()
+            .map(|p| (c.classify_rule(p).cloned(), c.classify_with_stats(p)))
+            .collect();
+        let compact = c.to_compact();
+        for (p, (rule, stats)) in packets.iter().zip(&expected) {
+            assert_eq!(compact.classify_rule(p), rule.as_ref());
+            assert_eq!(compact.classify_with_stats(p), *stats);
+        }
+        let frozen = c.freeze_compact();
+        for (p, (rule, _)) in packets.iter().zip(&expected) {
+            assert_eq!(frozen.classify_rule(p), rule.as_ref());
+        }
+        compact
+    }
+
+    let mut sim = Simulation::new(123);
+    let rules = sim.generate_rules(2000);
+    let packets = sim.generate_packets(1000);
+
+    let cutsplit = CutSplitClassifier::build(&rules);
+    assert_eq!(
+        cutsplit.tree().node_count(),
+        cutsplit.to_compact().node_count()
+    );
+    let compact = check(cutsplit, &packets);
+    assert!(compact.rule_count() <= rules.len());
+    check(HyperSplitClassifier::build(&rules), &packets);
+    check(
+        CutSplitClassifier::from_builder(
+            &CutSplitBuilder::new(64, 8).with_secondary_index(16),
+            &rules,
+        ),
+        &packets,
+    );
+    check(
+        HyperSplitClassifier::from_builder(
+            &HyperSplitBuilder::new(64, 8).with_secondary_index(16),
+            &rules,
+        ),
+        &packets,
+    );
+
+    let built = CompactTree::build(&rules);
+    let reference = LinearClassifier::build(&rules);
+    assert!(built.node_count() > 1);
+    for p in &packets {
+        assert_eq!(built.classify(p), reference.classify(p));
+    }
+    assert_eq!(CompactTree::build(&[]).classify(&packets[0]), None);
+}
+
+#[test]
+fn test_tss_hot_bucket() {
+    use cutsplit::rule::{Action, Range, Rule};
+
+    // Rules sharing one TSS bucket, given worst priority first: lookups must
+    // still return the best match.
+    let mut sim = Simulation::new(3242);
+    let base = sim.generate_rules(0).remove(0);
+    let rules: Vec<Rule> = (0..500u32)
+        .map(|i| {
+            let mut rule = base.clone();
+            rule.id = i;
+            rule.priority = i;
+            rule.action = if i % 3 == 0 {
+                Action::Permit
+            } else {
+                Action::Deny
+            };
+            rule.src_ip = Range::new(0x0a00_0000, 0x0aff_ffff);
+            rule.dst_ip = Range::new(0xc0a8_0000, 0xc0a8_ffff);
+            rule.dst_port = if i == 0 {
+                Range::new(1024, 1039)
+            } else {
+                Range::exact(1024 + (i % 16) as u16)
+            };
+            rule
+        })
+        .rev()
+        .collect();
+    let linear = LinearClassifier::build(&rules);
+    let tss = TSSClassifier::build(&rules);
+    for rule in &rules {
+        let packet = sim.sample_for_rule(rule);
+        assert_eq!(
+            tss.classify_rule(&packet).map(|r| r.id),
+            linear.classify_rule(&packet).map(|r| r.id),
+            "packet {:?}",
+            packet
+        );
+    }
+    // Every packet in the block hits the first rule.
+    let packet = sim.sample_for_rule(&rules[rules.len() - 1]);
+    assert_eq!(tss.classify_rule(&packet).map(|r| r.id), Some(0));
+}
+
+#[test]
+fn test_sharded_classifier_matches_linear() {
+    use cutsplit::dimension::Dimension;
+    use cutsplit::shard::{ShardKey, ShardedClassifier};
+
+    let mut sim = Simulation::new(4242);
+    let rules = sim.generate_rules(1000);
+    let packets = sim.generate_packets(1000);
+    let linear = LinearClassifier::build(&rules);
+
+    let keys = [
+        ShardKey::default(),
+        ShardKey {
+            dimension: Dimension::DstIp,
+            prefix_len: 24,
+        },
+        ShardKey {
+            dimension: Dimension::DstPort,
+            prefix_len: 8,
+        },
+    ];
+    for key in keys {
+        for shards in [1, 3, 8] {
+            let sharded =
+                ShardedClassifier::build_with(&rules, shards, key, HyperSplitClassifier::build);
+            assert_eq!(sharded.len(), shards);
+            // The trailing wildcard rule is in every shard.
+            assert!(sharded.shard_sizes().iter().all(|&n| n > 0));
+            for p in &packets {
+                let shard = sharded.shard_of(p);
+                assert!(shard < shards);
+                let expected = linear.classify_rule(p).map(|r| r.id);
+                assert_eq!(sharded.classify_rule(p).map(|r| r.id), expected, "{:?}", p);
+                assert_eq!(
+                    sharded.shard(shard).unwrap().classify_rule(p).map(|r| r.id),
+                    expected
+                );
+            }
+        }
+    }
+
+    // Specific rules are split rather than copied everywhere.
+    let sharded = ShardedClassifier::<LinearClassifier>::build(&rules);
+    let total: usize = sharded.shard_sizes().iter().sum();
+    assert!(
+        total < rules.len() * sharded.len(),
+        "{:?}",
+        sharded.shard_sizes()
+    );
+}
+
+#[test]
+fn test_two_stage_matches_linear() {
+    use cutsplit::twostage::{TwoStage, TwoStageParams};
+
+    let mut sim = Simulation::new(3253);
+    let rules = sim.generate_rules(600);
+    let packets = sim.generate_packets(1000);
+    let linear = LinearClassifier::build(&rules);
+
+    for (bucket_bits, group_size) in [(0, 16), (4, 1), (8, 32), (16, 100), (32, 7)] {
+        let params = TwoStageParams {
+            bucket_bits,
+            group_size,
+        };
+        let two = TwoStage::build_with(&rules, params, HyperSplitClassifier::build);
+        assert_eq!(two.groups().len(), rules.len().div_ceil(group_size));
+        for p in &packets {
+            let expected = linear.classify_rule(p).map(|r| r.id);
+            assert_eq!(two.classify_rule(p).map(|r| r.id), expected, "{:?}", p);
+            assert_eq!(two.classify_with_stats(p).0, linear.classify(p));
+        }
+        let stats = two.stats();
+        assert_eq!(stats.lookups, 2 * packets.len() as u64);
+        // Every packet matches the trailing wildcard rule.
+        assert_eq!(stats.confirmed, stats.lookups);
+        assert!(stats.candidates >= stats.confirmed);
+        assert!(stats.precision() <= 1.0);
+        two.reset_stats();
+        assert_eq!(two.stats().lookups, 0);
+    }
+    // Same-sized groups need fewer exact searches with finer buckets.
+    let grouped = |bits| {
+        let params = TwoStageParams {
+            bucket_bits: bits,
+            group_size: 16,
+        };
+        let two = TwoStage::build_with(&rules, params, LinearClassifier::build);
+        for p in &packets {
+            two.classify(p);
+        }
+        two.stats().candidates_per_lookup()
+    };
+    assert!(grouped(8) < grouped(0), "{} {}", grouped(8), grouped(0));
+}
+
+#[test]
+fn test_classify_batch_matches_classify() {
+    use cutsplit::compact::FreezeCompact;
+    use cutsplit::cutsplit::builder::Builder as CutSplitBuilder;
+    use cutsplit::freeze::Freeze;
+    use cutsplit::hicuts::builder::Builder as HiCutsBuilder;
+    use cutsplit::packet::FiveTuple;
+    use cutsplit::rule::Action;
+
+    fn check<C: Classifier>(classifier: &C, packets: &[FiveTuple]) {
+        let expected: Vec<_> = packets.iter().map(|p| classifier.classify(p)).collect();
+        let mut out = vec![Some(Action::Permit); packets.len()];
+        classifier.classify_batch(packets, &mut out);
+        assert_eq!(out, expected);
+        classifier.classify_batch(&[], &mut []);
+    }
+
+    let mut sim = Simulation::new(3255);
+    let rules = sim.generate_rules(1000);
+    let mut packets = sim.generate_packets(3000);
+    // Repeated packets land in the same leaves.
+    packets.extend_from_within(..500);
+
+    let cutsplit = CutSplitClassifier::from_builder(
+        &CutSplitBuilder::new(64, 8).with_secondary_index(16),
+        &rules,
+    );
+    check(&LinearClassifier::build(&rules), &packets);
+    check(&cutsplit, &packets);
+    check(&cutsplit.to_compact(), &packets);
+    check(&CutSplitClassifier::build(&rules).freeze(), &packets);
+    check(&HiCutsClassifier::build(&rules), &packets);
+    check(
+        &HiCutsClassifier::from_builder(
+            &HiCutsBuilder::new(64, 8).with_secondary_index(16),
+            &rules,
+        ),
+        &packets,
+    );
+    check(&HyperSplitClassifier::build(&rules), &packets);
+    check(&TSSClassifier::build(&rules), &packets);
+    check(&cutsplit::compact::CompactTree::build(&[]), &packets);
+}
+
+#[test]
+fn test_classify_accepts_packet_keys() {
+    use cutsplit::packet::{FiveTuple, Ipv4Bytes, PacketKey, PROTO_TCP, PROTO_UDP};
+
+    /// A caller's own packet type, without zones.
+    struct Flow {
+        addrs: (u32, u32),
+        ports: (u16, u16),
+        proto: u8,
+    }
+
+    impl PacketKey for Flow {
+        fn src_ip(&self) -> u32 {
+            self.addrs.0
+        }
+
+        fn dst_ip(&self) -> u32 {
+            self.addrs.1
+        }
+
+        fn src_port(&self) -> u16 {
+            self.ports.0
+        }
+
+        fn dst_port(&self) -> u16 {
+            self.ports.1
+        }
+
+        fn proto(&self) -> u8 {
+            self.proto
+        }
+    }
+
+    /// IPv4 header (20 bytes) followed by the ports.
+    fn raw(p: &FiveTuple) -> Vec<u8> {
+        let mut bytes = vec![0x45, 0, 0, 24, 0, 0, 0x40, 0, 64, p.proto, 0, 0];
+        bytes.extend(p.src_ip.to_be_bytes());
+        bytes.extend(p.dst_ip.to_be_bytes());
+        bytes.extend(p.src_port.to_be_bytes());
+        bytes.extend(p.dst_port.to_be_bytes());
+        bytes
+    }
+
+    let mut sim = Simulation::new(32552);
+    let rules = sim.generate_rules(300);
+    let classifier = HyperSplitClassifier::build(&rules);
+    // Only TCP and UDP packets carry ports on the wire.
+    let packets = sim.generate_packets(500);
+    for p in packets
+        .iter()
+        .filter(|p| [PROTO_TCP, PROTO_UDP].contains(&p.proto))
+    {
+        let p = p.in_zone(0);
+        let expected = classifier.classify(&p);
+        let bytes = raw(&p);
+        let view = Ipv4Bytes::new(&bytes).unwrap();
+        assert_eq!(view.to_five_tuple(), p);
+        assert_eq!(classifier.classify(&view), expected);
+        let flow = Flow {
+            addrs: (p.src_ip, p.dst_ip),
+            ports: (p.src_port, p.dst_port),
+            proto: p.proto,
+        };
+        assert_eq!(classifier.classify(&flow), expected);
+    }
+
+    // Later fragments carry no ports; truncated headers are rejected.
+    let p = FiveTuple {
+        src_ip: 1,
+        dst_ip: 2,
+        src_port: 3,
+        dst_port: 4,
+        proto: PROTO_TCP,
+        zone: 0,
+    };
+    let mut bytes = raw(&p);
+    bytes[7] = 1;
+    let view = Ipv4Bytes::new(&bytes).unwrap();
+    assert_eq!((view.src_port(), view.dst_port()), (0, 0));
+    assert!(Ipv4Bytes::new(&bytes[..19]).is_none());
+    bytes[7] = 0;
+    assert!(Ipv4Bytes::new(&bytes[..22]).is_none());
+}
+
+#[test]
+fn test_partition_sort_partitions() {
+    use cutsplit::partitionsort::tree::partition;
+    use cutsplit::rule::{expand_rules, Rule};
+
+    let mut sim = Simulation::new(3257);
+    let rules = sim.generate_rules(1000);
+    let expanded = expand_rules(&rules);
+    let ps = PartitionSortClassifier::build(&rules);
+    let partitions = ps.partitions();
+    assert!(partitions.len() > 1);
+    assert_eq!(
+        partitions.iter().map(|t| t.len()).sum::<usize>(),
+        expanded.len()
+    );
+    for pair in partitions.windows(2) {
+        assert!(pair[0].best_priority() <= pair[1].best_priority());
+    }
+    for tree in partitions {
+        let mut order = tree.order().map(|d| d.index());
+        order.sort_unstable();
+        assert_eq!(order, [0, 1, 2, 3, 4]);
+        // A partition is sortable on its own.
+        let own: Vec<Rule> = tree.rules().cloned().collect();
+        assert_eq!(partition(&own).len(), 1);
+    }
+
+    let linear = LinearClassifier::build(&rules);
+    for packet in sim.generate_packets(2000) {
+        assert_eq!(
+            ps.classify_rule(&packet).map(|r| r.id),
+            linear.classify_rule(&packet).map(|r| r.id),
+            "packet {:?}",
+            packet
+        );
+    }
+}
+
+#[test]
+fn test_efficuts_matches_linear() {
+    use cutsplit::dimension::Dimension;
+    use cutsplit::efficuts::{categorize, EffiCuts, EffiCutsParams, Pattern};
+    use cutsplit::rule::expand_rules;
+
+    let mut sim = Simulation::new(32582);
+    let rules = sim.generate_rules(1000);
+    let packets = sim.generate_packets(2000);
+    let linear = LinearClassifier::build(&rules);
+
+    let params = EffiCutsParams::default();
+    let parts = categorize(&expand_rules(&rules), params);
+    assert!(parts.len() > 1);
+    assert_eq!(
+        parts.iter().map(|(_, part)| part.len()).sum::<usize>(),
+        expand_rules(&rules).len()
+    );
+    for (pattern, part) in &parts {
+        for rule in part {
+            let own = Pattern::of(rule, params.largeness);
+            assert_eq!(own.0 & !pattern.0, 0);
+        }
+    }
+    // The trailing wildcard rule is large in every field.
+    let wildcard = Pattern::of(rules.last().unwrap(), params.largeness);
+    assert!(Dimension::ALL.iter().all(|&d| wildcard.is_large(d)));
+
+    let cutsplit = EffiCuts::<CutSplitClassifier>::build(&rules);
+    let hicuts = EffiCuts::<HiCutsClassifier>::build_with(&rules, params, HiCutsClassifier::build);
+    assert_eq!(cutsplit.classifiers().len(), parts.len());
+    assert_eq!(
+        cutsplit.category_sizes().iter().sum::<usize>(),
+        expand_rules(&rules).len()
+    );
+    for p in &packets {
+        let expected = linear.classify_rule(p).map(|r| r.id);
+        assert_eq!(cutsplit.classify_rule(p).map(|r| r.id), expected);
+        assert_eq!(hicuts.classify_rule(p).map(|r| r.id), expected);
+        assert_eq!(cutsplit.classify_with_stats(p).0, linear.classify(p));
+    }
+}
+
+#[test]
+fn test_first_match_wins_whatever_the_rule_order() {
+    use cutsplit::rule::{Action, Rule};
+
+    let mut sim = Simulation::new(3261);
+    let mut rules = sim.generate_rules(300);
+    let packets = sim.generate_packets(1000);
+    let linear = LinearClassifier::build(&rules);
+    // Leaves list their rules by priority, not in the order they came in.
+    rules.reverse();
+    let cutsplit = CutSplitClassifier::build(&rules);
+    let hicuts = HiCutsClassifier::build(&rules);
+    let hypersplit = HyperSplitClassifier::build(&rules);
+    for p in &packets {
+        let expected = linear.classify_rule(p).map(|r| r.id);
+        assert_eq!(cutsplit.classify_rule(p).map(|r| r.id), expected);
+        assert_eq!(hicuts.classify_rule(p).map(|r| r.id), expected);
+        assert_eq!(hypersplit.classify_rule(p).map(|r| r.id), expected);
+    }
+
+    // Rules of equal priority win in the order they were given.
+    let tied = [
+        Rule::wildcard(Action::Deny).with_id(1).with_priority(5),
+        Rule::wildcard(Action::Permit).with_id(2).with_priority(5),
+        Rule::wildcard(Action::Permit).with_id(3).with_priority(9),
+    ];
+    let p = packets[0];
+    assert_eq!(
+        LinearClassifier::build(&tied).classify_rule(&p).unwrap().id,
+        1
+    );
+    assert_eq!(
+        CutSplitClassifier::build(&tied)
+            .classify_rule(&p)
+            .unwrap()
+            .id,
+        1
+    );
+    assert_eq!(
+        HiCutsClassifier::build(&tied).classify_rule(&p).unwrap().id,
+        1
+    );
+    assert_eq!(
+        HyperSplitClassifier::build(&tied)
+            .classify_rule(&p)
+            .unwrap()
+            .id,
+        1
+    );
+}
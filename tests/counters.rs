@@ -0,0 +1,183 @@
+use cutsplit::classifier::Classifier;
+use cutsplit::counters::{Counted, CounterSnapshot};
+use cutsplit::hypersplit::classifier::HyperSplitClassifier;
+use cutsplit::linear::LinearClassifier;
+use cutsplit::simulation::Simulation;
+
+#[test]
+fn test_packet_and_byte_counters() {
+    let mut sim = Simulation::new(31);
+    let rules = sim.generate_rules(300);
+    let packets = sim.generate_packets(1000);
+
+    let counted = Counted::<HyperSplitClassifier>::build(&rules);
+    let reference = LinearClassifier::build(&rules);
+
+    let mut expected = vec![CounterSnapshot::default(); rules.len()];
+    for (i, p) in packets.iter().enumerate() {
+        let len = 64 + i as u32;
+        assert_eq!(counted.classify_with_len(p, len), reference.classify(p));
+        let id = reference.classify_rule(p).unwrap().id as usize;
+        expected[id].packets += 1;
+        expected[id].bytes += len as u64;
+    }
+    // Plain lookups count packets but no bytes.
+    counted.classify(&packets[0]);
+    expected[reference.classify_rule(&packets[0]).unwrap().id as usize].packets += 1;
+
+    for rule in &rules {
+        assert_eq!(counted.counters(rule.id), Some(expected[rule.id as usize]));
+    }
+    assert_eq!(counted.iter().count(), rules.len());
+    // The generated rule set ends with a catch-all, so nothing is unmatched.
+    assert_eq!(counted.unmatched(), CounterSnapshot::default());
+    assert_eq!(counted.counters(u32::MAX), None);
+
+    counted.reset();
+    assert!(counted.iter().all(|(_, c)| c == CounterSnapshot::default()));
+}
+
+#[test]
+fn test_unmatched_counter() {
+    let counted = Counted::<LinearClassifier>::build(&[]);
+    let packet = Simulation::new(1).generate_packets(1)[0];
+    assert_eq!(counted.classify_with_len(&packet, 1500), None);
+    assert_eq!(
+        counted.unmatched(),
+        CounterSnapshot {
+            packets: 1,
+            bytes: 1500
+        }
+    );
+}
+
+#[test]
+fn test_sampled_mirroring() {
+    let mut sim = Simulation::new(8);
+    let rules = sim.generate_rules(50);
+    let target = &rules[3];
+    let packet = sim.sample_for_rule(target);
+
+    let mut counted = Counted::<LinearClassifier>::build(&rules);
+    let matched = counted.classify_rule(&packet).unwrap().id;
+    counted.reset();
+    assert!(counted.set_sample_rate(matched, 4));
+    assert!(!counted.set_sample_rate(u32::MAX, 4));
+
+    let mirrored: Vec<bool> = (0..12)
+        .map(|_| counted.classify_sampled(&packet, 100).mirror)
+        .collect();
+    let expected: Vec<bool> = (1..=12).map(|n| n % 4 == 0).collect();
+    assert_eq!(mirrored, expected);
+
+    let sampled = counted.classify_sampled(&packet, 100);
+    assert_eq!(sampled.verdict.rule_id(), Some(matched));
+    assert_eq!(counted.counters(matched).unwrap().packets, 13);
+
+    assert!(counted.set_sample_rate(matched, 0));
+    assert!(!counted.classify_sampled(&packet, 100).mirror);
+}
+
+/// Atomic counters can be shared across cores; the `single-core` build trades
+/// that for `Cell`s and is exercised by the other tests in this file.
+#[test]
+fn test_top_rules_over_a_period() {
+    let mut sim = Simulation::new(77);
+    let rules = sim.generate_rules(100);
+    let counted = Counted::<HyperSplitClassifier>::build(&rules);
+
+    // Warm-up traffic hits rule 3 heavily; the period then favours rule 5.
+    let hot3 = sim.sample_for_rule(&rules[3]);
+    let hot5 = sim.sample_for_rule(&rules[5]);
+    let id3 = counted.classify_rule(&hot3).unwrap().id;
+    let id5 = counted.classify_rule(&hot5).unwrap().id;
+    assert_ne!(id3, id5);
+    for _ in 0..50 {
+        counted.classify_with_len(&hot3, 100);
+    }
+    let top = counted.top(1);
+    assert_eq!(top[0].rule_id, id3);
+    assert_eq!(top[0].delta.packets, 51);
+
+    let baseline = counted.baseline();
+    for _ in 0..10 {
+        counted.classify_with_len(&hot5, 60);
+    }
+    for _ in 0..4 {
+        counted.classify_with_len(&hot3, 100);
+    }
+    let top = counted.top_since(&baseline, 5);
+    assert_eq!(top.len(), 2);
+    assert_eq!(top[0].rule_id, id5);
+    assert_eq!(
+        top[0].delta,
+        CounterSnapshot {
+            packets: 10,
+            bytes: 600
+        }
+    );
+    assert_eq!(top[0].total.packets, 11);
+    assert_eq!(top[1].rule_id, id3);
+    assert_eq!(top[1].delta.packets, 4);
+    assert_eq!(top[1].total.packets, 55);
+
+    // Overall ranking and truncation.
+    let all = counted.top(usize::MAX);
+    assert_eq!(all.len(), 2);
+    assert_eq!(all[0].rule_id, id3);
+    assert!(counted.top(0).is_empty());
+}
+
+#[cfg(not(feature = "single-core"))]
+#[test]
+fn test_atomic_counters_are_sync() {
+    fn assert_sync<T: Sync + Send>() {}
+    assert_sync::<Counted<HyperSplitClassifier>>();
+}
+
+#[cfg(not(feature = "single-core"))]
+#[test]
+fn test_replicated_counters_merge() {
+    use cutsplit::replica::Replicated;
+
+    let mut sim = Simulation::new(58);
+    let rules = sim.generate_rules(300);
+    let packets = sim.generate_packets(2000);
+    let reference = LinearClassifier::build(&rules);
+
+    let replicated = Replicated::<HyperSplitClassifier>::build(&rules, 4);
+    assert_eq!(replicated.len(), 4);
+    std::thread::scope(|s| {
+        for (worker, chunk) in packets.chunks(500).enumerate() {
+            let replica = replicated.replica(worker);
+            s.spawn(move || {
+                for p in chunk {
+                    replica.classify_with_len(p, 100);
+                }
+            });
+        }
+    });
+
+    // Each replica counted only its own worker's packets.
+    for (replica, chunk) in replicated.replicas().zip(packets.chunks(500)) {
+        let counted: u64 = replica.iter().map(|(_, c)| c.packets).sum();
+        assert_eq!(counted + replica.unmatched().packets, chunk.len() as u64);
+    }
+
+    let mut expected = vec![CounterSnapshot::default(); rules.len()];
+    for p in &packets {
+        let c = &mut expected[reference.classify_rule(p).unwrap().id as usize];
+        c.packets += 1;
+        c.bytes += 100;
+    }
+    for (id, snapshot) in replicated.totals() {
+        assert_eq!(snapshot, expected[id as usize], "rule {}", id);
+        assert_eq!(replicated.counters(id), Some(snapshot));
+    }
+    assert_eq!(replicated.totals().len(), rules.len());
+    assert_eq!(replicated.unmatched(), CounterSnapshot::default());
+    assert_eq!(replicated.counters(u32::MAX), None);
+
+    replicated.reset();
+    assert!(replicated.totals().iter().all(|(_, c)| c.packets == 0));
+}
@@ -0,0 +1,236 @@
+use cutsplit::dimension::Dimension;
+use cutsplit::hypersplit::classifier::HyperSplitClassifier;
+use cutsplit::linear::LinearClassifier;
+use cutsplit::partitionsort::classifier::PartitionSortClassifier;
+use cutsplit::rule::{Range, Rule};
+use cutsplit::simulation::Simulation;
+use cutsplit::tss::classifier::TSSClassifier;
+use cutsplit::update::policy::{Health, Monitored, Trigger};
+use cutsplit::update::{AutoRebuild, DynamicClassifier, RebuildPolicy, Rebuilding, RuleChange};
+use rand::{Rng, SeedableRng};
+use rand_pcg::Pcg32;
+
+/// Give some rules extra ranges or a negated field, including negations of
+/// a whole domain, which expand to no plain rule at all.
+fn with_field_sets(rng: &mut Pcg32, mut rule: Rule) -> Rule {
+    match rng.gen_range(0..8) {
+        0 => rule.field_sets_mut().dst_port.push(Range::exact(rng.gen())),
+        1 => rule.negate(Dimension::ALL[rng.gen_range(0..Dimension::COUNT)]),
+        2 => {
+            rule.src_port = Range::new(0, u16::MAX);
+            rule.negate(Dimension::SrcPort);
+        }
+        _ => {}
+    }
+    rule
+}
+
+/// Interleave random inserts, removes, modifications and classifications on
+/// `D`, checking every verdict against a naive scan of the reference rule list.
+fn differential<D: DynamicClassifier>(seed: u64, steps: usize) {
+    let mut sim = Simulation::new(seed);
+    let mut rng = Pcg32::seed_from_u64(seed);
+    let mut reference: Vec<Rule> = sim
+        .generate_rules(30)
+        .into_iter()
+        .map(|rule| with_field_sets(&mut rng, rule))
+        .collect();
+    let mut classifier = D::build(&reference);
+    let mut next_id = reference.len() as u32;
+
+    // Unique priorities keep the expected winner unambiguous.
+    let fresh_priority = |rng: &mut Pcg32, rules: &[Rule]| loop {
+        let p = rng.gen_range(0..10_000);
+        if rules.iter().all(|r| r.priority != p) {
+            return p;
+        }
+    };
+
+    for step in 0..steps {
+        let change = match rng.gen_range(0..4) {
+            0 => {
+                let mut rule = with_field_sets(&mut rng, sim.generate_rules(1).swap_remove(0));
+                rule.id = next_id;
+                rule.priority = fresh_priority(&mut rng, &reference);
+                next_id += 1;
+                Some(RuleChange::Insert(rule))
+            }
+            1 if !reference.is_empty() => {
+                let victim = reference[rng.gen_range(0..reference.len())].clone();
+                Some(RuleChange::Remove(victim))
+            }
+            2 if !reference.is_empty() => {
+                let before = reference[rng.gen_range(0..reference.len())].clone();
+                let mut after = with_field_sets(&mut rng, sim.generate_rules(1).swap_remove(0));
+                after.id = before.id;
+                after.priority = fresh_priority(&mut rng, &reference);
+                Some(RuleChange::Modify { before, after })
+            }
+            _ => None,
+        };
+
+        if let Some(change) = change {
+            assert!(change.apply(&mut reference));
+            assert!(classifier.apply(&change), "step {}: {:?}", step, change);
+        }
+
+        // Removing an unknown id is a no-op.
+        assert!(classifier.remove(u32::MAX).is_none());
+
+        for packet in sim.generate_packets(20) {
+            let expected = reference
+                .iter()
+                .filter(|r| r.matches(&packet))
+                .min_by_key(|r| r.priority)
+                .map(|r| r.id);
+            assert_eq!(
+                classifier.classify_rule(&packet).map(|r| r.id),
+                expected,
+                "step {}: {:?}",
+                step,
+                packet
+            );
+        }
+    }
+}
+
+#[test]
+fn test_dynamic_updates_linear() {
+    differential::<LinearClassifier>(1, 300);
+}
+
+#[test]
+fn test_dynamic_updates_rebuilding() {
+    differential::<Rebuilding<TSSClassifier>>(2, 100);
+    differential::<Rebuilding<PartitionSortClassifier>>(3, 100);
+    differential::<Rebuilding<HyperSplitClassifier>>(4, 100);
+}
+
+#[test]
+fn test_dynamic_updates_auto_rebuild() {
+    differential::<AutoRebuild<TSSClassifier>>(8, 300);
+    differential::<AutoRebuild<LinearClassifier>>(9, 100);
+}
+
+#[test]
+fn test_dynamic_updates_tss() {
+    differential::<TSSClassifier>(5, 300);
+    differential::<TSSClassifier>(6, 300);
+}
+
+#[test]
+fn test_tss_updates_keep_expanded_rules_whole() {
+    use cutsplit::classifier::Classifier;
+
+    let mut sim = Simulation::new(7);
+    let rules = sim.generate_rules(50);
+    let mut tss = TSSClassifier::build(&rules);
+
+    // Bidirectional, two destination ports, no port 22 from 10.0.0.0/8.
+    let mut rule = rules[3].clone();
+    rule.id = 500;
+    rule.priority = 0;
+    rule.bidirectional = true;
+    rule.field_sets_mut().dst_port.push(Range::exact(8443));
+    rule.src_port = Range::exact(22);
+    rule.negate(Dimension::SrcPort);
+    tss.insert(rule.clone());
+
+    let mut packet = sim.sample_for_rule(&rule);
+    packet.src_port = 1000;
+    packet.dst_port = 8443;
+    assert!(rule.matches(&packet));
+    assert_eq!(tss.classify_rule(&packet).map(|r| r.id), Some(500));
+    assert_eq!(
+        tss.classify_rule(&packet.reversed()).map(|r| r.id),
+        Some(500)
+    );
+
+    assert_eq!(tss.remove(500), Some(rule));
+    assert_eq!(tss.remove(500), None);
+    assert_ne!(tss.classify_rule(&packet).map(|r| r.id), Some(500));
+
+    // Emptied tables are dropped.
+    for rule in &rules {
+        assert_eq!(tss.remove(rule.id).map(|r| r.id), Some(rule.id));
+    }
+    let (action, stats) = tss.classify_with_stats(&packet);
+    assert_eq!(action, None);
+    assert_eq!(stats.tables_probed, 0);
+}
+
+#[test]
+fn test_rebuild_policy_triggers() {
+    let health = |stored_rules, largest_bucket| Health {
+        rules: 100,
+        stored_rules,
+        largest_bucket,
+        tables: 10,
+    };
+    let mut policy = RebuildPolicy::default();
+    policy.rebuilt(health(150, 40));
+    assert_eq!(policy.check(&health(250, 40)), None);
+    assert_eq!(
+        policy.check(&health(300, 40)),
+        Some(Trigger::Replication {
+            baseline: 1.5,
+            current: 3.0
+        })
+    );
+    // Over the limit, but no worse than the fresh build.
+    assert_eq!(policy.check(&health(150, 40)), None);
+    assert_eq!(policy.check(&health(150, 41)), Some(Trigger::BucketLen(41)));
+
+    let mut policy = RebuildPolicy::never().with_max_changes(3);
+    policy.rebuilt(health(100, 1));
+    for _ in 0..2 {
+        policy.record();
+    }
+    assert_eq!(policy.check(&health(1000, 1000)), None);
+    policy.record();
+    assert_eq!(policy.check(&health(100, 1)), Some(Trigger::Changes(3)));
+    policy.rebuilt(health(100, 1));
+    assert_eq!(policy.changes(), 0);
+}
+
+#[test]
+fn test_auto_rebuild_restores_tss_buckets() {
+    use cutsplit::classifier::Classifier;
+
+    let mut sim = Simulation::new(3258);
+    let rules = sim.generate_rules(0);
+    let policy = RebuildPolicy::never().with_max_bucket_len(16);
+    let mut auto = AutoRebuild::<TSSClassifier>::new(&rules, policy);
+
+    // A /24 rule inserted before the host rules that outrank it: in place,
+    // the hosts merge into its table and share one bucket; a fresh build
+    // gives them a table of their own.
+    let mut net = rules[0].clone();
+    net.id = 1000;
+    net.priority = 1000;
+    net.dst_ip = Range::new(0xc0a8_0100, 0xc0a8_01ff);
+    net.dst_port = Range::exact(80);
+    net.proto = Range::exact(6);
+    auto.insert(net.clone());
+    for i in 1..=40u32 {
+        let mut host = net.clone();
+        host.id = i;
+        host.priority = i;
+        host.dst_ip = Range::exact(0xc0a8_0100 + i);
+        auto.insert(host);
+        assert!(auto.inner().health().largest_bucket <= 16);
+    }
+    assert_eq!(auto.rebuilds(), 1);
+    assert_eq!(auto.last_trigger(), Some(Trigger::BucketLen(17)));
+    assert_eq!(auto.policy().changes(), 40 - 16);
+    assert_eq!(auto.rules().len(), rules.len() + 41);
+
+    let linear = LinearClassifier::build(auto.rules());
+    for rule in auto.rules() {
+        let packet = sim.sample_for_rule(rule);
+        assert_eq!(
+            auto.classify_rule(&packet).map(|r| r.id),
+            linear.classify_rule(&packet).map(|r| r.id)
+        );
+    }
+}
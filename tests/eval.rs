@@ -0,0 +1,32 @@
+#![cfg(feature = "std")]
+
+use cutsplit::eval::compare;
+use cutsplit::simulation::Simulation;
+
+#[test]
+fn test_compare_all_algorithms_agree() {
+    let mut sim = Simulation::new(7);
+    let rules = sim.generate_rules(300);
+    let packets = sim.generate_packets(300);
+
+    let comparison = compare(&rules, &packets);
+    assert_eq!(comparison.rows.len(), 6);
+    for row in &comparison.rows {
+        assert_eq!(row.mismatches, 0, "{} disagrees with Linear", row.name);
+        assert!(row.p50_ns <= row.p99_ns && row.p99_ns <= row.max_ns);
+        assert!(row.memory.bytes > 0 && row.memory.rules > 0);
+    }
+    let linear = &comparison.rows[0];
+    assert!(linear.rules_compared.mean >= 1.0);
+    assert_eq!(linear.depth.p99, 0);
+    let tss = comparison.rows.iter().find(|r| r.name == "TSS").unwrap();
+    assert!(tss.tables_probed.mean >= 1.0);
+    let hypersplit = comparison
+        .rows
+        .iter()
+        .find(|r| r.name == "HyperSplit")
+        .unwrap();
+    assert!(hypersplit.depth.mean > 0.0);
+    let table = comparison.to_string();
+    assert!(table.contains("HyperSplit") && table.contains("Memory (KB)"));
+}
@@ -0,0 +1,286 @@
+//! Tests for optional subsystems; each runs only when its feature is enabled
+//! (`cargo test --all-features`).
+
+#[cfg(feature = "serde")]
+#[test]
+fn test_serde_derives() {
+    fn assert_serde<T: serde::Serialize + serde::de::DeserializeOwned>() {}
+    assert_serde::<cutsplit::rule::Rule>();
+    assert_serde::<cutsplit::packet::FiveTuple>();
+    assert_serde::<cutsplit::prefix::Prefix<u32>>();
+    assert_serde::<cutsplit::geometry::Region>();
+    assert_serde::<cutsplit::update::RuleChange>();
+}
+
+#[cfg(feature = "rayon")]
+#[test]
+fn test_parallel_batch_matches_sequential() {
+    use cutsplit::classifier::Classifier;
+    use cutsplit::hypersplit::classifier::HyperSplitClassifier;
+    use cutsplit::parallel::{classify_batch, classify_rule_ids};
+    use cutsplit::simulation::Simulation;
+
+    let mut sim = Simulation::new(11);
+    let rules = sim.generate_rules(500);
+    let packets = sim.generate_packets(5000);
+    let classifier = HyperSplitClassifier::build(&rules);
+
+    let sequential: Vec<_> = packets.iter().map(|p| classifier.classify(p)).collect();
+    assert_eq!(classify_batch(&classifier, &packets), sequential);
+    let ids: Vec<_> = packets
+        .iter()
+        .map(|p| classifier.classify_rule(p).map(|r| r.id))
+        .collect();
+    assert_eq!(classify_rule_ids(&classifier, &packets), ids);
+}
+
+#[cfg(feature = "pcap")]
+#[test]
+fn test_pcap_five_tuples() {
+    use cutsplit::packet::FiveTuple;
+    use cutsplit::pcap::{read_five_tuples, PcapError};
+
+    // Global header: little-endian, microseconds, Ethernet.
+    let mut file = vec![0xD4, 0xC3, 0xB2, 0xA1, 2, 0, 4, 0];
+    file.extend_from_slice(&[0; 8]);
+    file.extend_from_slice(&65535u32.to_le_bytes());
+    file.extend_from_slice(&1u32.to_le_bytes());
+
+    let mut push_frame = |frame: &[u8]| {
+        file.extend_from_slice(&1u32.to_le_bytes());
+        file.extend_from_slice(&0u32.to_le_bytes());
+        file.extend_from_slice(&(frame.len() as u32).to_le_bytes());
+        file.extend_from_slice(&(frame.len() as u32).to_le_bytes());
+        file.extend_from_slice(frame);
+    };
+
+    // Ethernet + 802.1Q tag + IPv4 + UDP 10.0.0.1:5353 -> 10.0.0.2:53.
+    let mut udp = vec![0u8; 12];
+    udp.extend_from_slice(&[0x81, 0x00, 0x00, 0x0A, 0x08, 0x00]);
+    udp.extend_from_slice(&[0x45, 0, 0, 28, 0, 0, 0, 0, 64, 17, 0, 0]);
+    udp.extend_from_slice(&[10, 0, 0, 1, 10, 0, 0, 2]);
+    udp.extend_from_slice(&[0x14, 0xE9, 0x00, 0x35, 0, 8, 0, 0]);
+    push_frame(&udp);
+
+    // ARP frame: skipped.
+    let mut arp = vec![0u8; 12];
+    arp.extend_from_slice(&[0x08, 0x06]);
+    arp.extend_from_slice(&[0; 28]);
+    push_frame(&arp);
+
+    let tuples = read_five_tuples(&file).unwrap();
+    assert_eq!(
+        tuples,
+        vec![FiveTuple {
+            src_ip: 0x0A00_0001,
+            dst_ip: 0x0A00_0002,
+            src_port: 5353,
+            dst_port: 53,
+            proto: 17,
+            zone: 0,
+        }]
+    );
+
+    assert_eq!(
+        read_five_tuples(&file[..file.len() - 3]),
+        Err(PcapError::Truncated)
+    );
+    assert_eq!(read_five_tuples(&[0; 24]), Err(PcapError::BadMagic));
+}
+
+#[cfg(feature = "ffi")]
+#[test]
+fn test_ffi_round_trip() {
+    use cutsplit::ffi::*;
+
+    let rules = [
+        CutsplitRule {
+            id: 7,
+            priority: 0,
+            src_ip_min: 0,
+            src_ip_max: u32::MAX,
+            dst_ip_min: 0x0A00_0000,
+            dst_ip_max: 0x0AFF_FFFF,
+            src_port_min: 0,
+            src_port_max: 65535,
+            dst_port_min: 80,
+            dst_port_max: 80,
+            proto_min: 6,
+            proto_max: 6,
+            zone_min: 0,
+            zone_max: u32::MAX,
+            flags: 0,
+            action: CUTSPLIT_PERMIT,
+        },
+        CutsplitRule {
+            id: 9,
+            priority: 1,
+            src_ip_min: 0,
+            src_ip_max: u32::MAX,
+            dst_ip_min: 0x0A00_0000,
+            dst_ip_max: 0x0AFF_FFFF,
+            src_port_min: 0,
+            src_port_max: 65535,
+            dst_port_min: 0,
+            dst_port_max: 65535,
+            proto_min: 0,
+            proto_max: 255,
+            zone_min: 0,
+            zone_max: u32::MAX,
+            flags: 0,
+            action: CUTSPLIT_DENY,
+        },
+    ];
+    let packet = |dst_ip, dst_port| CutsplitPacket {
+        src_ip: 1,
+        dst_ip,
+        src_port: 1234,
+        dst_port,
+        proto: 6,
+        zone: 0,
+    };
+
+    unsafe {
+        let c = cutsplit_build(rules.as_ptr(), rules.len());
+        assert_eq!(
+            cutsplit_classify(c, &packet(0x0A01_0203, 80)),
+            CUTSPLIT_PERMIT
+        );
+        assert_eq!(cutsplit_classify_rule(c, &packet(0x0A01_0203, 80)), 7);
+        assert_eq!(
+            cutsplit_classify(c, &packet(0x0A01_0203, 22)),
+            CUTSPLIT_DENY
+        );
+        assert_eq!(
+            cutsplit_classify(c, &packet(0x0B00_0000, 80)),
+            CUTSPLIT_NO_MATCH
+        );
+        assert_eq!(cutsplit_classify_rule(c, &packet(0x0B00_0000, 80)), -1);
+        cutsplit_free(c);
+        cutsplit_free(core::ptr::null_mut());
+    }
+}
+
+#[cfg(feature = "std")]
+#[test]
+fn test_csv_import() {
+    use cutsplit::csv::{parse_rules, read_rules, CsvErrorKind, CsvMapping};
+    use cutsplit::rule::{Action, Range};
+
+    let input = "\
+name,Action,src,dst,sport,dport,proto,comment
+web,permit,any,10.0.0.0/8,,80,tcp,\"front, public\"
+
+# DNS resolvers
+dns,ALLOW,192.168.1.1-192.168.1.9,*,1024-65535,53,UDP,
+rest,drop,,,,,,\"catch \"\"all\"\"\"
+";
+    let rules = parse_rules(input, &CsvMapping::default()).unwrap();
+    assert_eq!(rules.len(), 3);
+    assert_eq!(rules[0].dst_ip, Range::new(0x0A00_0000, 0x0AFF_FFFF));
+    assert_eq!(rules[0].src_ip, Range::new(0, u32::MAX));
+    assert_eq!(rules[0].dst_port, Range::exact(80));
+    assert_eq!(rules[0].proto, Range::exact(6));
+    assert_eq!(rules[1].src_ip, Range::new(0xC0A8_0101, 0xC0A8_0109));
+    assert_eq!(rules[1].src_port, Range::new(1024, 65535));
+    assert_eq!(rules[1].proto, Range::exact(17));
+    assert_eq!(rules[1].action, Action::Permit);
+    assert_eq!(rules[2].action, Action::Deny);
+    assert_eq!(rules[2].proto, Range::new(0, 255));
+    assert_eq!((rules[2].id, rules[2].priority), (2, 2));
+    assert_eq!(
+        read_rules(input.as_bytes(), &CsvMapping::default()).unwrap(),
+        rules
+    );
+
+    // Headerless, by position, with another delimiter.
+    let mapping = CsvMapping::by_index(0, 1, 2, 3, 4, 5).with_delimiter(';');
+    let rules = parse_rules("1.2.3.4;any;any;22:22;6;deny\n", &mapping).unwrap();
+    assert_eq!(rules[0].src_ip, Range::exact(0x0102_0304));
+    assert_eq!(rules[0].dst_port, Range::exact(22));
+
+    // Errors report the line.
+    let kind = |input: &str, mapping: &CsvMapping| parse_rules(input, mapping).unwrap_err();
+    let err = kind(
+        "src,dst,sport,dport,proto,action\n\nany,any,any,80,tcp,maybe\n",
+        &CsvMapping::default(),
+    );
+    assert_eq!((err.line, err.kind), (3, CsvErrorKind::BadAction));
+    let err = kind("src,dst\n", &CsvMapping::default());
+    assert_eq!(err.kind, CsvErrorKind::UnknownColumn("sport".into()));
+    assert_eq!(
+        kind("", &CsvMapping::default()).kind,
+        CsvErrorKind::MissingHeader
+    );
+    let err = kind(
+        "1.2.3.4,any,any,99999,6,deny\n",
+        &CsvMapping::by_index(0, 1, 2, 3, 4, 5),
+    );
+    assert_eq!((err.line, err.kind), (1, CsvErrorKind::BadPortRange));
+    let err = kind(
+        "10.0.0.0/33,any,any,any,6,deny\n",
+        &CsvMapping::by_index(0, 1, 2, 3, 4, 5),
+    );
+    assert_eq!(err.kind, CsvErrorKind::BadAddress);
+    let err = kind(
+        "any,any,any,any,foo,deny\n",
+        &CsvMapping::by_index(0, 1, 2, 3, 4, 5),
+    );
+    assert_eq!(err.kind, CsvErrorKind::BadProtocol);
+    // Protocols are also accepted by name.
+    let rules = parse_rules(
+        "any,any,any,any,sctp,deny\n",
+        &CsvMapping::by_index(0, 1, 2, 3, 4, 5),
+    )
+    .unwrap();
+    assert_eq!(rules[0].proto, Range::exact(132));
+    let err = kind(
+        "any,any,any,any,6\n",
+        &CsvMapping::by_index(0, 1, 2, 3, 4, 5),
+    );
+    assert_eq!(err.kind, CsvErrorKind::MissingField(5));
+    let err = kind("\"any,any\n", &CsvMapping::by_index(0, 1, 2, 3, 4, 5));
+    assert_eq!(err.kind, CsvErrorKind::UnterminatedQuote);
+}
+
+#[cfg(feature = "std")]
+#[test]
+fn test_build_report() {
+    use cutsplit::classifier::Classifier;
+    use cutsplit::cutsplit::builder::Builder as CutSplitBuilder;
+    use cutsplit::hicuts::builder::Builder as HiCutsBuilder;
+    use cutsplit::hicuts::classifier::HiCutsClassifier;
+    use cutsplit::hypersplit::builder::Builder as HyperSplitBuilder;
+    use cutsplit::linear::LinearClassifier;
+    use cutsplit::report::report;
+    use cutsplit::simulation::Simulation;
+
+    let mut sim = Simulation::new(3248);
+    let rules = sim.generate_rules(1000);
+
+    let (_, cutsplit) = report(&CutSplitBuilder::new(10, 20), &rules);
+    let (_, hypersplit) = report(&HyperSplitBuilder::new(8, 32).with_spfac(3.0), &rules);
+    let (hicuts, hicuts_report) = report(&HiCutsBuilder::new(10, 20), &rules);
+    for r in [&cutsplit, &hypersplit, &hicuts_report] {
+        assert_eq!(r.rules, rules.len());
+        assert!(
+            r.memory_bytes >= r.stats.stored_rules * std::mem::size_of::<cutsplit::rule::Rule>()
+        );
+        assert!(r.stats.largest_leaf > 0);
+        assert!(r.replication() >= 1.0);
+        let text = r.to_string();
+        assert!(text.starts_with(&format!("Algorithm     {}\n", r.algorithm)));
+        assert!(text.contains(&format!("Rules         {}\n", rules.len())));
+        assert!(text.contains(&format!("Depth         {}\n", r.stats.max_depth)));
+        assert_eq!(text.lines().count(), 9);
+    }
+    assert!(hypersplit.to_string().contains("spfac=3\n"));
+    assert!(cutsplit.to_string().contains("secondary_threshold=none"));
+
+    // The reported tree is the one the builder makes.
+    let classifier = HiCutsClassifier::from_tree(hicuts);
+    let linear = LinearClassifier::build(&rules);
+    for p in &sim.generate_packets(200) {
+        assert_eq!(classifier.classify(p), linear.classify(p));
+    }
+}
@@ -0,0 +1,77 @@
+use cutsplit::classifier::Classifier;
+use cutsplit::compact::FreezeCompact;
+use cutsplit::cutsplit::classifier::CutSplitClassifier;
+use cutsplit::fingerprint::{Fingerprint, LoadError};
+use cutsplit::hicuts::builder::Builder as HiCutsBuilder;
+use cutsplit::hicuts::classifier::HiCutsClassifier;
+use cutsplit::hypersplit::classifier::HyperSplitClassifier;
+use cutsplit::rule::{Action, FieldSets, Range};
+use cutsplit::simulation::Simulation;
+
+#[test]
+fn test_fingerprint_tracks_rule_content() {
+    let mut sim = Simulation::new(3256);
+    let rules = sim.generate_rules(300);
+    let fingerprint = Fingerprint::of(&rules);
+    assert_eq!(Fingerprint::of(&rules.clone()), fingerprint);
+    // Pinned: the hash must not change across versions or platforms.
+    assert_eq!(Fingerprint::of(&[]).to_string(), "a8c7f832281a39c5");
+
+    let mut changed = Vec::new();
+    let mut edit = |f: &dyn Fn(&mut Vec<cutsplit::rule::Rule>)| {
+        let mut edited = rules.clone();
+        f(&mut edited);
+        changed.push(Fingerprint::of(&edited));
+    };
+    edit(&|r| r.swap(0, 1));
+    edit(&|r| {
+        r.pop();
+    });
+    edit(&|r| r[7].priority += 1);
+    edit(&|r| r[7].dst_port = Range::new(0, 1));
+    edit(&|r| r[7].bidirectional = !r[7].bidirectional);
+    edit(&|r| {
+        r[7].action = match r[7].action {
+            Action::Permit => Action::Deny,
+            Action::Deny => Action::Permit,
+        }
+    });
+    edit(&|r| r[7].field_sets = Some(Box::new(FieldSets::default())));
+    for (i, f) in changed.iter().enumerate() {
+        assert_ne!(*f, fingerprint, "edit {i}");
+        assert!(changed[..i].iter().all(|g| g != f), "edit {i}");
+    }
+
+    assert_eq!(Fingerprint::check(Some(fingerprint), fingerprint), Ok(()));
+    assert_eq!(
+        Fingerprint::check(None, fingerprint),
+        Err(LoadError::Stale {
+            expected: fingerprint,
+            found: None,
+        })
+    );
+}
+
+#[test]
+fn test_trees_remember_their_fingerprint() {
+    let mut sim = Simulation::new(32560);
+    let rules = sim.generate_rules(200);
+    let expected = Some(Fingerprint::of(&rules));
+
+    let cutsplit = CutSplitClassifier::build(&rules);
+    assert_eq!(cutsplit.fingerprint(), expected);
+    assert_eq!(cutsplit.to_compact().fingerprint(), expected);
+    let hypersplit = HyperSplitClassifier::build(&rules);
+    assert_eq!(hypersplit.fingerprint(), expected);
+    assert_eq!(hypersplit.to_compact().fingerprint(), expected);
+    assert_eq!(HiCutsClassifier::build(&rules).fingerprint(), expected);
+
+    // A tree built elsewhere is unknown until told.
+    let tree = HiCutsClassifier::from_tree(HiCutsBuilder::new(10, 20).build(&rules));
+    assert_eq!(tree.fingerprint(), None);
+    let fingerprint = Fingerprint::of(&rules);
+    assert_eq!(
+        tree.with_fingerprint(fingerprint).fingerprint(),
+        Some(fingerprint)
+    );
+}
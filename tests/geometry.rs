@@ -0,0 +1,214 @@
+use cutsplit::cutsplit::tree::Dimension;
+use cutsplit::geometry::{
+    difference, effective_region, find_uncovered, is_dead, total_volume, verify_complete, Region,
+};
+use cutsplit::rule::{Action, Range, Rule};
+use cutsplit::simulation::Simulation;
+
+#[test]
+fn test_region_subtraction_volumes_and_points() {
+    let mut sim = Simulation::new(5);
+    let rules = sim.generate_rules(60);
+    let packets = sim.generate_packets(500);
+    let regions: Vec<Region> = rules.iter().map(Region::from_rule).collect();
+
+    assert_eq!(Region::full().volume(), 1u128 << 104);
+
+    for a in &regions {
+        for b in regions.iter().take(20) {
+            let pieces = a.subtract(b);
+            let inter = a.intersection(b).map_or(0, |r| r.volume());
+            assert_eq!(total_volume(&pieces) + inter, a.volume());
+            for (i, p) in pieces.iter().enumerate() {
+                assert!(a.contains(p));
+                assert!(!p.intersects(b));
+                for q in &pieces[i + 1..] {
+                    assert!(!p.intersects(q));
+                }
+            }
+        }
+    }
+
+    // Packets in (a - union(others)) match a but none of the others.
+    let rest = difference(&regions[0], &regions[1..10]);
+    for p in &packets {
+        let in_rest = rest.iter().any(|r| r.contains_packet(p));
+        let expected =
+            regions[0].contains_packet(p) && !regions[1..10].iter().any(|r| r.contains_packet(p));
+        assert_eq!(in_rest, expected);
+    }
+
+    // Round trip through a rule
+    let r = regions[3].to_rule(1, 1, Action::Permit);
+    assert_eq!(Region::from_rule(&r), regions[3]);
+}
+
+#[test]
+fn test_sampling_inside_rule_regions() {
+    let mut sim = Simulation::new(21);
+    let rules = sim.generate_rules(100);
+    for rule in &rules {
+        for _ in 0..20 {
+            let p = sim.sample_for_rule(rule);
+            assert!(rule.matches(&p), "{} does not match {:?}", rule, p);
+        }
+    }
+
+    let region = Region::from_rule(&rules[0]).intersection(&Region::from_rule(&rules[0]));
+    let packets = sim.sample_packets_in(&region.unwrap(), 50);
+    assert!(packets.iter().all(|p| rules[0].matches(p)));
+}
+
+#[test]
+fn test_completeness_verification() {
+    let mut sim = Simulation::new(3225);
+    let mut rules = sim.generate_rules(200);
+    assert_eq!(verify_complete(&rules), Ok(()));
+
+    // Without the default rule a gap exists, and its witness matches nothing.
+    let default = rules.pop().unwrap();
+    let gap = verify_complete(&rules).unwrap_err();
+    let witness = gap.first_packet();
+    assert!(gap.contains_packet(&witness));
+    assert!(!rules.iter().any(|r| r.matches(&witness)));
+    let p = sim.sample_in_region(&gap);
+    assert!(!rules.iter().any(|r| r.matches(&p)));
+
+    // Covering the space piecewise is as good as a default rule.
+    let low = Rule {
+        proto: Range::new(0, 16),
+        ..default.clone()
+    };
+    let high = Rule {
+        proto: Range::new(17, 255),
+        ..default.clone()
+    };
+    rules.push(low.clone());
+    assert!(verify_complete(&rules).is_err());
+    rules.push(high);
+    assert_eq!(verify_complete(&rules), Ok(()));
+
+    // A default bound to one zone does not cover the others.
+    let zoned = Rule {
+        zone: Range::exact(1),
+        ..default
+    };
+    assert!(verify_complete(&[low, zoned]).is_err());
+
+    let full = Region::full();
+    let halves = [
+        full.with(Dimension::SrcPort, Range::new(0, 1023)),
+        full.with(Dimension::SrcPort, Range::new(1024, 65535)),
+    ];
+    assert_eq!(find_uncovered(&full, &halves), None);
+    assert_eq!(find_uncovered(&full, &halves[..1]), Some(halves[1]));
+}
+
+#[test]
+fn test_effective_regions() {
+    use cutsplit::classifier::Classifier;
+    use cutsplit::linear::LinearClassifier;
+
+    let mut sim = Simulation::new(3226);
+    let mut rules = sim.generate_rules(80);
+    // A copy of an earlier rule placed last is fully shadowed.
+    let copy = Rule {
+        id: 1000,
+        priority: 1000,
+        ..rules[5].clone()
+    };
+    rules.push(copy);
+    let classifier = LinearClassifier::build(&rules);
+
+    let first = rules.iter().position(|r| r.priority == 0).unwrap();
+    assert_eq!(
+        effective_region(&rules, first),
+        vec![Region::from_rule(&rules[first])]
+    );
+    let last = rules.len() - 1;
+    assert!(effective_region(&rules, last).is_empty());
+    assert!(is_dead(&rules, last));
+
+    for (i, rule) in rules.iter().enumerate() {
+        let pieces = effective_region(&rules, i);
+        assert_eq!(pieces.is_empty(), is_dead(&rules, i), "rule {}", rule.id);
+        for piece in &pieces {
+            assert!(Region::from_rule(rule).contains(piece));
+            for p in sim.sample_packets_in(piece, 5) {
+                assert_eq!(classifier.classify_rule(&p).map(|r| r.id), Some(rule.id));
+            }
+        }
+        // Packets the rule wins fall inside its effective region.
+        for _ in 0..5 {
+            let p = sim.sample_for_rule(rule);
+            if classifier.classify_rule(&p).map(|r| r.id) == Some(rule.id) {
+                assert!(pieces.iter().any(|r| r.contains_packet(&p)));
+            }
+        }
+    }
+}
+
+#[test]
+fn test_dimension_accessors() {
+    use cutsplit::dimension::Dimension as CoreDimension;
+
+    // The old path names the same type.
+    let _: CoreDimension = Dimension::SrcIp;
+    assert_eq!(CoreDimension::ALL.len(), CoreDimension::COUNT);
+    assert_eq!(CoreDimension::from_index(CoreDimension::COUNT), None);
+
+    let mut sim = Simulation::new(3240);
+    let rule = sim.generate_rules(10)[3].clone();
+    let packet = sim.sample_for_rule(&rule);
+    let mut copy = Region::full().to_rule(rule.id, rule.priority, rule.action);
+    let mut names = Vec::new();
+    for dim in CoreDimension::ALL {
+        assert_eq!(CoreDimension::from_index(dim.index()), Some(dim));
+        assert!(dim.rule_range(&rule).contains(dim.packet_value(&packet)));
+        assert!(dim.rule_range(&rule).max <= dim.max_value());
+        dim.set_rule_range(&mut copy, dim.rule_range(&rule));
+        names.push(dim.name());
+    }
+    names.dedup();
+    assert_eq!(names.len(), CoreDimension::COUNT);
+    assert_eq!(Region::from_rule(&copy), Region::from_rule(&rule));
+    assert_eq!(
+        Region::from_rule(&rule).to_rule(rule.id, rule.priority, rule.action),
+        copy
+    );
+}
+
+#[test]
+fn test_field_specs() {
+    use cutsplit::dimension::{Dimension as CoreDimension, FIELDS};
+    use cutsplit::packet::FiveTuple;
+
+    assert_eq!(FIELDS.len(), CoreDimension::COUNT);
+    let mut sim = Simulation::new(3241);
+    let rule = sim.generate_rules(10)[4].clone();
+    let packet = sim.sample_for_rule(&rule);
+    let mut copy = FiveTuple::default();
+    for (field, dim) in FIELDS.iter().zip(CoreDimension::ALL) {
+        assert_eq!(field.dimension, dim);
+        assert!(core::ptr::eq(dim.spec(), field));
+        assert_eq!(field.name, dim.name());
+        assert_eq!(field.max_value(), dim.max_value());
+        assert!(field.bits <= 32);
+        assert_eq!((field.rule_range)(&rule), dim.rule_range(&rule));
+        assert_eq!((field.packet_value)(&packet), dim.packet_value(&packet));
+
+        // Setting the largest value round-trips; wider values are truncated.
+        dim.set_packet_value(&mut copy, field.max_value());
+        assert_eq!(dim.packet_value(&copy), field.max_value());
+        dim.set_packet_value(&mut copy, dim.packet_value(&packet));
+    }
+    assert_eq!(copy.src_ip, packet.src_ip);
+    assert_eq!(copy.dst_ip, packet.dst_ip);
+    assert_eq!(copy.src_port, packet.src_port);
+    assert_eq!(copy.dst_port, packet.dst_port);
+    assert_eq!(copy.proto, packet.proto);
+    assert_eq!(
+        Region::from_rule(&rule).first_packet().src_ip,
+        rule.src_ip.min
+    );
+}
@@ -0,0 +1,57 @@
+use cutsplit::classifier::Classifier;
+use cutsplit::groups::Grouped;
+use cutsplit::hypersplit::classifier::HyperSplitClassifier;
+use cutsplit::linear::LinearClassifier;
+use cutsplit::rule::Rule;
+use cutsplit::simulation::Simulation;
+
+const MAINTENANCE: u32 = 7;
+const OFFICE_HOURS: u32 = 9;
+
+fn group_of(rule: &Rule) -> Option<u32> {
+    match rule.id % 5 {
+        0 => Some(MAINTENANCE),
+        1 => Some(OFFICE_HOURS),
+        _ => None,
+    }
+}
+
+#[test]
+fn test_toggling_groups_matches_rebuild() {
+    let mut sim = Simulation::new(60);
+    let rules = sim.generate_rules(300);
+    let packets = sim.generate_packets(2000);
+
+    let mut grouped = Grouped::<HyperSplitClassifier>::new(&rules, group_of);
+    assert_eq!(grouped.is_enabled(MAINTENANCE), Some(true));
+    assert_eq!(grouped.is_enabled(1), None);
+    assert!(!grouped.disable(1));
+    assert_eq!(grouped.groups().count(), 2);
+
+    let states = [(true, true), (false, true), (true, false), (false, false)];
+    for (maintenance, office) in states {
+        assert!(grouped.set_enabled(MAINTENANCE, maintenance));
+        assert!(grouped.set_enabled(OFFICE_HOURS, office));
+        let active: Vec<Rule> = rules
+            .iter()
+            .filter(|r| match group_of(r) {
+                Some(MAINTENANCE) => maintenance,
+                Some(_) => office,
+                None => true,
+            })
+            .cloned()
+            .collect();
+        let reference = LinearClassifier::build(&active);
+        for p in &packets {
+            assert_eq!(
+                grouped.classify_rule(p).map(|r| r.id),
+                reference.classify_rule(p).map(|r| r.id)
+            );
+            assert_eq!(grouped.classify_with_stats(p).0, reference.classify(p));
+        }
+    }
+
+    assert!(grouped.enable(MAINTENANCE));
+    assert_eq!(grouped.is_enabled(MAINTENANCE), Some(true));
+    assert_eq!(grouped.is_enabled(OFFICE_HOURS), Some(false));
+}
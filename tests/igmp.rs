@@ -0,0 +1,336 @@
+use cutsplit::classifier::Classifier;
+use cutsplit::hypersplit::classifier::HyperSplitClassifier;
+use cutsplit::igmp::snooping::{is_snooped, MembershipFront, MembershipTable};
+use cutsplit::igmp::{
+    GroupRecord, IgmpMessage, IgmpVersion, ALLOW_NEW_SOURCES, BLOCK_OLD_SOURCES,
+    CHANGE_TO_EXCLUDE_MODE, CHANGE_TO_INCLUDE_MODE, MODE_IS_EXCLUDE,
+};
+use cutsplit::linear::LinearClassifier;
+use cutsplit::packet::{
+    internet_checksum, ChecksumError, FiveTuple, IgmpHeader, Ipv4Bytes, Ipv4Header, PacketKey,
+    IGMP_LEAVE_GROUP, IGMP_MEMBERSHIP_QUERY, IGMP_V1_MEMBERSHIP_REPORT, IGMP_V2_MEMBERSHIP_REPORT,
+    IGMP_V3_MEMBERSHIP_REPORT, PROTO_IGMP,
+};
+use cutsplit::prefix::Prefix;
+use cutsplit::rule::{Action, Rule};
+
+const HOST: u32 = 0x0a00_0005;
+
+/// IGMPv3 report holding `records` (type, group, sources), with an auxiliary
+/// word after the first record and its checksum filled in.
+fn v3_report(records: &[(u8, u32, &[u32])]) -> Vec<u8> {
+    let mut bytes = vec![IGMP_V3_MEMBERSHIP_REPORT, 0, 0, 0, 0, 0];
+    bytes.extend((records.len() as u16).to_be_bytes());
+    for (i, &(record_type, group, sources)) in records.iter().enumerate() {
+        let aux = if i == 0 { 1 } else { 0 };
+        bytes.extend([record_type, aux]);
+        bytes.extend((sources.len() as u16).to_be_bytes());
+        bytes.extend(group.to_be_bytes());
+        for source in sources {
+            bytes.extend(source.to_be_bytes());
+        }
+        bytes.extend(vec![0xAA; aux as usize * 4]);
+    }
+    let checksum = internet_checksum(&bytes);
+    bytes[2..4].copy_from_slice(&checksum.to_be_bytes());
+    bytes
+}
+
+/// IPv4 packet from `HOST` to `dst` carrying the IGMP message `igmp`.
+fn ipv4(dst: u32, igmp: &[u8]) -> Vec<u8> {
+    let mut ip = Ipv4Header {
+        src: HOST,
+        dst,
+        proto: PROTO_IGMP,
+        version: 4,
+        ihl: 5,
+        ttl: 1,
+        total_len: 20 + igmp.len() as u16,
+        ..Ipv4Header::default()
+    };
+    ip.checksum = ip.compute_checksum();
+    let mut bytes = ip.to_bytes().to_vec();
+    bytes.extend(igmp);
+    bytes
+}
+
+#[test]
+fn test_igmp_messages() {
+    let header = |igmp_type, max_resp_time, group_addr| IgmpHeader {
+        igmp_type,
+        max_resp_time,
+        checksum: 0,
+        group_addr,
+    };
+    assert_eq!(
+        header(IGMP_MEMBERSHIP_QUERY, 0, 0).message(),
+        Some(IgmpMessage::Query {
+            version: IgmpVersion::V1,
+            group: 0,
+            max_resp_time: 0,
+            sources: vec![],
+        })
+    );
+    let specific = header(IGMP_MEMBERSHIP_QUERY, 100, 0xef01_0203).message();
+    assert!(matches!(
+        specific,
+        Some(IgmpMessage::Query {
+            version: IgmpVersion::V2,
+            group: 0xef01_0203,
+            ..
+        })
+    ));
+    assert_eq!(specific.unwrap().groups(), vec![0xef01_0203]);
+    assert_eq!(
+        header(IGMP_V1_MEMBERSHIP_REPORT, 0, 0xe000_0101).message(),
+        Some(IgmpMessage::Report {
+            version: IgmpVersion::V1,
+            group: 0xe000_0101,
+        })
+    );
+    let leave = header(IGMP_LEAVE_GROUP, 0, 0xef00_0001).message().unwrap();
+    assert_eq!(leave, IgmpMessage::Leave { group: 0xef00_0001 });
+    assert_eq!(leave.igmp_type(), IGMP_LEAVE_GROUP);
+    assert_eq!(header(0x42, 0, 0).message(), None);
+
+    // Group-and-source-specific v3 query.
+    let mut query = header(IGMP_MEMBERSHIP_QUERY, 10, 0xef00_0001)
+        .to_bytes()
+        .to_vec();
+    query.extend([0x02, 125, 0, 2]);
+    query.extend(0x0a00_0001u32.to_be_bytes());
+    query.extend(0x0a00_0002u32.to_be_bytes());
+    assert_eq!(
+        IgmpMessage::parse(&query),
+        Some(IgmpMessage::Query {
+            version: IgmpVersion::V3,
+            group: 0xef00_0001,
+            max_resp_time: 10,
+            sources: vec![0x0a00_0001, 0x0a00_0002],
+        })
+    );
+    assert_eq!(IgmpMessage::parse(&query[..19]), None);
+
+    let report = v3_report(&[
+        (CHANGE_TO_EXCLUDE_MODE, 0xef00_0001, &[]),
+        (ALLOW_NEW_SOURCES, 0xef00_0002, &[0x0a00_0001]),
+        (CHANGE_TO_INCLUDE_MODE, 0xef00_0003, &[]),
+        (BLOCK_OLD_SOURCES, 0xef00_0004, &[0x0a00_0001]),
+    ]);
+    assert_eq!(internet_checksum(&report), 0);
+    let Some(IgmpMessage::ReportV3 { records }) = IgmpMessage::parse(&report) else {
+        panic!("v3 report did not parse");
+    };
+    assert_eq!(
+        records[1],
+        GroupRecord {
+            record_type: ALLOW_NEW_SOURCES,
+            group: 0xef00_0002,
+            sources: vec![0x0a00_0001],
+        }
+    );
+    let joins: Vec<bool> = records.iter().map(GroupRecord::joins).collect();
+    let leaves: Vec<bool> = records.iter().map(GroupRecord::leaves).collect();
+    assert_eq!(joins, [true, true, false, false]);
+    assert_eq!(leaves, [false, false, true, false]);
+    assert_eq!(IgmpMessage::parse(&report[..report.len() - 1]), None);
+
+    // From the IP packet, with the IGMP checksum checked on the way.
+    let mut raw = ipv4(0xe000_0016, &report);
+    let view = Ipv4Bytes::new(&raw).unwrap();
+    assert_eq!(view.verify_checksums(), Ok(()));
+    let message = IgmpMessage::from_ipv4(&view).unwrap();
+    assert_eq!(
+        message.groups(),
+        vec![0xef00_0001, 0xef00_0002, 0xef00_0003, 0xef00_0004]
+    );
+    assert_eq!(view.l4_key().ports(), (IGMP_V3_MEMBERSHIP_REPORT as u16, 0));
+    raw[25] ^= 1;
+    let view = Ipv4Bytes::new(&raw).unwrap();
+    assert_eq!(view.verify_checksums(), Err(ChecksumError::Igmp));
+}
+
+#[test]
+fn test_igmp_group_admission() {
+    // Hosts may join 239.1.0.0/16 but not 239.1.9.0/24, and nothing else;
+    // leaving is always allowed.
+    let rules = vec![
+        Rule {
+            id: 1,
+            priority: 1,
+            ..Rule::igmp_group(Some(IGMP_LEAVE_GROUP), Prefix::new(0xe000_0000, 4))
+        },
+        Rule {
+            id: 2,
+            priority: 2,
+            action: Action::Deny,
+            ..Rule::igmp_group(None, Prefix::new(0xef01_0900, 24))
+        },
+        Rule {
+            id: 3,
+            priority: 3,
+            ..Rule::igmp_group(None, Prefix::new(0xef01_0000, 16))
+        },
+        Rule {
+            id: 4,
+            priority: 4,
+            ..Rule::wildcard(Action::Deny)
+        },
+    ];
+    let linear = LinearClassifier::build(&rules);
+    let tree = HyperSplitClassifier::build(&rules);
+
+    let report = v3_report(&[
+        (MODE_IS_EXCLUDE, 0xef01_0001, &[]),
+        (MODE_IS_EXCLUDE, 0xef01_0901, &[]),
+        (ALLOW_NEW_SOURCES, 0xef02_0001, &[0x0a00_0001]),
+    ]);
+    let raw = ipv4(0xe000_0016, &report);
+    let view = Ipv4Bytes::new(&raw).unwrap();
+    let packet = view.to_five_tuple();
+    let message = IgmpMessage::from_ipv4(&view).unwrap();
+
+    // The packet itself goes to 224.0.0.22 and only the final deny takes it.
+    assert_eq!(linear.classify_rule(&packet).map(|r| r.id), Some(4));
+    let keys = message.group_keys(&packet);
+    assert_eq!(keys[0].1.dst_ip, 0xef01_0001);
+    assert_eq!(keys[2].1.dst_port, ALLOW_NEW_SOURCES as u16);
+    assert_eq!(keys[2].1.src_ip, HOST);
+    let ids = |groups: Vec<(u32, Option<&Rule>)>| -> Vec<(u32, Option<u32>)> {
+        groups
+            .into_iter()
+            .map(|(g, r)| (g, r.map(|r| r.id)))
+            .collect()
+    };
+    let expected = vec![
+        (0xef01_0001, Some(3)),
+        (0xef01_0901, Some(2)),
+        (0xef02_0001, Some(4)),
+    ];
+    assert_eq!(ids(message.classify_groups(&linear, &packet)), expected);
+    assert_eq!(ids(message.classify_groups(&tree, &packet)), expected);
+
+    // A v2 report's key is the packet's own; a leave names its group.
+    let v2 = IgmpMessage::Report {
+        version: IgmpVersion::V2,
+        group: 0xef01_0902,
+    };
+    let to_group = FiveTuple {
+        src_ip: HOST,
+        dst_ip: 0xef01_0902,
+        src_port: IGMP_V2_MEMBERSHIP_REPORT as u16,
+        dst_port: 0,
+        proto: PROTO_IGMP,
+        zone: 0,
+    };
+    assert_eq!(v2.group_keys(&to_group), vec![(0xef01_0902, to_group)]);
+    let leave = IgmpMessage::Leave { group: 0xef01_0902 };
+    let to_routers = FiveTuple {
+        dst_ip: 0xe000_0002,
+        src_port: IGMP_LEAVE_GROUP as u16,
+        ..to_group
+    };
+    assert_eq!(
+        ids(leave.classify_groups(&tree, &to_routers)),
+        vec![(0xef01_0902, Some(1))]
+    );
+}
+
+#[test]
+fn test_membership_table() {
+    let mut table = MembershipTable::new(260);
+    let header = |igmp_type, group_addr| IgmpHeader {
+        igmp_type,
+        max_resp_time: 0,
+        checksum: 0,
+        group_addr,
+    };
+    let v2_join = header(IGMP_V2_MEMBERSHIP_REPORT, 0xef00_0001);
+    table.learn(&v2_join.message().unwrap(), 1, 0);
+    table.learn(&v2_join.message().unwrap(), 2, 100);
+    let report = v3_report(&[
+        (MODE_IS_EXCLUDE, 0xef00_0002, &[]),
+        (CHANGE_TO_INCLUDE_MODE, 0xef00_0001, &[]),
+    ]);
+    let raw = ipv4(0xe000_0016, &report);
+    let message = table.observe(&Ipv4Bytes::new(&raw).unwrap(), 3, 100);
+    assert!(matches!(message, Some(IgmpMessage::ReportV3 { .. })));
+    // Zone 3 never had members of the first group; leaving changes nothing.
+    assert_eq!(table.member_zones(0xef00_0001), vec![1, 2]);
+    assert_eq!(table.member_zones(0xef00_0002), vec![3]);
+    assert!(table.is_member(0xef00_0002, 3) && !table.is_member(0xef00_0002, 1));
+
+    // Queries change nothing; a leave takes its zone out at once.
+    let query = header(IGMP_MEMBERSHIP_QUERY, 0);
+    table.learn(&query.message().unwrap(), 1, 200);
+    table.learn(
+        &header(IGMP_LEAVE_GROUP, 0xef00_0001).message().unwrap(),
+        2,
+        200,
+    );
+    assert_eq!(table.member_zones(0xef00_0001), vec![1]);
+    assert!(!table.leave(0xef00_0001, 2));
+
+    // Zone 1 stopped reporting at 0: its membership is gone after 260.
+    assert_eq!(table.expire(259), 0);
+    assert_eq!(table.expire(260), 1);
+    assert_eq!(table.groups(), vec![0xef00_0002]);
+    let to = |dst_ip| FiveTuple {
+        src_ip: HOST,
+        dst_ip,
+        src_port: 5000,
+        dst_port: 5000,
+        proto: 17,
+        zone: 1,
+    };
+    assert!(table.has_local_members(&to(0xef00_0002)));
+    assert!(!table.has_local_members(&to(0xef00_0001)));
+    assert!(table.filters(&to(0xef00_0001)));
+    // Only snooped groups are filtered: not unicast, not 224.0.0.0/24.
+    assert!(!table.filters(&to(0x0a00_0001)));
+    assert!(!table.filters(&to(0xe000_00fb)));
+    assert!(is_snooped(0xe000_0100) && !is_snooped(0xe000_0016));
+    assert_eq!(table.expire(360), 1);
+    assert!(table.groups().is_empty());
+}
+
+#[test]
+fn test_membership_front() {
+    let rules = vec![
+        Rule {
+            id: 1,
+            priority: 1,
+            action: Action::Deny,
+            ..Rule::udp_service(Prefix::new(0xef00_0000, 8), 5000)
+        },
+        Rule {
+            id: 2,
+            priority: 2,
+            ..Rule::wildcard(Action::Permit)
+        },
+    ];
+    let mut front: MembershipFront<HyperSplitClassifier> = MembershipFront::build(&rules);
+    let to = |dst_ip, dst_port| FiveTuple {
+        src_ip: HOST,
+        dst_ip,
+        src_port: 40000,
+        dst_port,
+        proto: 17,
+        zone: 0,
+    };
+    let id = |front: &MembershipFront<_>, p| front.classify_rule(&p).map(|r: &Rule| r.id);
+    // No members yet: every snooped group is dropped by the front.
+    assert_eq!(id(&front, to(0xef01_0001, 6000)), Some(0));
+    assert_eq!(front.classify(&to(0xef01_0001, 6000)), Some(Action::Deny));
+    assert_eq!(id(&front, to(0xe000_0005, 6000)), Some(2));
+    assert_eq!(id(&front, to(0x0a00_0001, 6000)), Some(2));
+
+    // With members, the policy decides.
+    front.table_mut().join(0xef01_0001, 4, 0);
+    front.table_mut().join(0xef00_0001, 4, 0);
+    assert_eq!(id(&front, to(0xef01_0001, 6000)), Some(2));
+    assert_eq!(id(&front, to(0xef00_0001, 5000)), Some(1));
+    let (action, stats) = front.classify_with_stats(&to(0xef01_0002, 6000));
+    assert_eq!((action, stats.tables_probed), (Some(Action::Deny), 1));
+    assert!(front.rule().matches(&to(0xef01_0002, 6000)));
+}
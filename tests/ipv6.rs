@@ -0,0 +1,161 @@
+use cutsplit::classifier::{Classifier, Verdict};
+use cutsplit::cutsplit::classifier::CutSplitClassifier;
+use cutsplit::hicuts::classifier::HiCutsClassifier;
+use cutsplit::hypersplit::classifier::HyperSplitClassifier;
+use cutsplit::ipv6::{prefix_range, V6Classifier};
+use cutsplit::linear::LinearClassifier;
+use cutsplit::packet::SixTuple;
+use cutsplit::partitionsort::classifier::PartitionSortClassifier;
+use cutsplit::rule::{Action, Range, RuleV6, ANY_ZONE};
+use cutsplit::tss::classifier::TSSClassifier;
+use rand::{Rng, SeedableRng};
+use rand_pcg::Pcg32;
+
+/// Addresses under a few /32 sites, so rules and packets overlap.
+fn address(rng: &mut Pcg32) -> u128 {
+    let site = 0x2001_0db8_0000_0000_0000_0000_0000_0000u128 + (rng.gen_range(0..4u128) << 96);
+    site | rng.gen_range(0..8u128) << 64 | rng.gen::<u64>() as u128
+}
+
+fn random_rules(rng: &mut Pcg32, n: usize) -> Vec<RuleV6> {
+    let mut rules: Vec<RuleV6> = (0..n)
+        .map(|i| {
+            let field = |rng: &mut Pcg32| match rng.gen_range(0..4) {
+                0 => Range::any(0, u128::MAX),
+                len => prefix_range(address(rng), [0, 32, 64, 128][len]),
+            };
+            RuleV6 {
+                id: i as u32,
+                priority: i as u32,
+                src_ip: field(rng),
+                dst_ip: field(rng),
+                src_port: Range::any(0, u16::MAX),
+                dst_port: if rng.gen_bool(0.5) {
+                    Range::exact(rng.gen_range(0..4) * 1000)
+                } else {
+                    Range::any(0, u16::MAX)
+                },
+                proto: Range::exact(if rng.gen_bool(0.5) { 6 } else { 17 }),
+                zone: ANY_ZONE,
+                bidirectional: rng.gen_bool(0.2),
+                action: if rng.gen_bool(0.7) {
+                    Action::Permit
+                } else {
+                    Action::Deny
+                },
+            }
+        })
+        .collect();
+    // Edge of the address space.
+    rules[0].dst_ip = Range::new(u128::MAX - 5, u128::MAX);
+    rules
+}
+
+fn check<C: Classifier>(rules: &[RuleV6], packets: &[SixTuple]) {
+    let classifier = V6Classifier::<C>::build(rules);
+    for p in packets {
+        let expected = rules
+            .iter()
+            .filter(|r| r.matches(p))
+            .min_by_key(|r| r.priority);
+        assert_eq!(classifier.classify_rule(p), expected, "{:?}", p);
+        assert_eq!(
+            classifier.classify_verdict(p),
+            expected.map_or(Verdict::NoMatch, |r| Verdict::Matched {
+                action: r.action,
+                rule_id: r.id
+            })
+        );
+    }
+}
+
+#[test]
+fn test_v6_classifiers_match_linear_scan() {
+    let mut rng = Pcg32::seed_from_u64(3253);
+    let rules = random_rules(&mut rng, 100);
+    let mut packets: Vec<SixTuple> = (0..1000)
+        .map(|_| SixTuple {
+            src_ip: address(&mut rng),
+            dst_ip: address(&mut rng),
+            src_port: rng.gen(),
+            dst_port: rng.gen_range(0..4) * 1000,
+            proto: if rng.gen_bool(0.5) { 6 } else { 17 },
+            zone: 0,
+        })
+        .collect();
+    packets[0].dst_ip = u128::MAX;
+    packets[1].dst_ip = u128::MAX - 6;
+    // Packets at rule range boundaries.
+    for rule in &rules[..50] {
+        packets.push(SixTuple {
+            src_ip: rule.src_ip.min,
+            dst_ip: rule.dst_ip.max,
+            src_port: 1,
+            dst_port: rule.dst_port.min,
+            proto: rule.proto.min,
+            zone: 0,
+        });
+    }
+
+    check::<LinearClassifier>(&rules, &packets);
+    check::<CutSplitClassifier>(&rules, &packets);
+    check::<HiCutsClassifier>(&rules, &packets);
+    check::<HyperSplitClassifier>(&rules, &packets);
+    check::<TSSClassifier>(&rules, &packets);
+    check::<PartitionSortClassifier>(&rules, &packets);
+}
+
+#[test]
+fn test_v6_prefixes_and_projection() {
+    let net = u128::from(core::net::Ipv6Addr::new(0x2001, 0xdb8, 0, 0, 0, 0, 0, 1));
+    assert_eq!(
+        prefix_range(net, 32),
+        Range::new(0x2001_0db8 << 96, (0x2001_0db8 << 96) | (u128::MAX >> 32))
+    );
+    assert_eq!(prefix_range(net, 128), Range::exact(net));
+    assert_eq!(prefix_range(net, 0), Range::new(0, u128::MAX));
+
+    let rule = RuleV6 {
+        id: 9,
+        priority: 0,
+        src_ip: prefix_range(net, 64),
+        dst_ip: Range::any(0, u128::MAX),
+        src_port: Range::any(0, u16::MAX),
+        dst_port: Range::exact(443),
+        proto: Range::exact(6),
+        zone: ANY_ZONE,
+        bidirectional: true,
+        action: Action::Deny,
+    };
+    let v6 = V6Classifier::<HyperSplitClassifier>::build(&[rule]);
+    // Below, inside and above the /64.
+    assert_eq!(v6.address_classes(), 3);
+    let inner = &v6.inner().classify_rule(&v6.project(&SixTuple {
+        src_ip: net,
+        dst_port: 443,
+        proto: 6,
+        ..SixTuple::default()
+    }));
+    assert_eq!(
+        inner.map(|r| (r.src_ip, r.dst_ip)),
+        Some((Range::exact(1), Range::any(0, u32::MAX)))
+    );
+
+    let packet = SixTuple {
+        src_ip: 1,
+        dst_ip: net + 5,
+        src_port: 443,
+        dst_port: 5000,
+        proto: 6,
+        zone: 0,
+    };
+    assert_eq!(v6.classify(&packet), Some(Action::Deny));
+    assert_eq!(v6.classify(&packet.reversed()), Some(Action::Deny));
+    assert_eq!(
+        v6.classify(&SixTuple {
+            src_port: 80,
+            ..packet
+        }),
+        None
+    );
+}
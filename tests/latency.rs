@@ -0,0 +1,56 @@
+use cutsplit::classifier::Classifier;
+use cutsplit::latency::{Histogram, Timed};
+use cutsplit::linear::LinearClassifier;
+use cutsplit::simulation::Simulation;
+use std::cell::Cell;
+
+#[test]
+fn test_histogram_quantiles_within_precision() {
+    let mut h = Histogram::new();
+    for v in 1..=10_000u64 {
+        h.record(v);
+    }
+    assert_eq!(h.count(), 10_000);
+    assert_eq!(h.min(), 1);
+    assert_eq!(h.max(), 10_000);
+
+    for (q, exact) in [(0.5, 5_000u64), (0.99, 9_900), (0.999, 9_990)] {
+        let v = h.value_at_quantile(q);
+        assert!(v >= exact, "q={} v={} exact={}", q, v, exact);
+        assert!(
+            v as f64 <= exact as f64 * 1.07,
+            "q={} v={} exact={}",
+            q,
+            v,
+            exact
+        );
+    }
+
+    let mut other = Histogram::new();
+    other.record(u64::MAX);
+    h.merge(&other);
+    assert_eq!(h.max(), u64::MAX);
+    assert_eq!(h.value_at_quantile(1.0), u64::MAX);
+}
+
+#[test]
+fn test_timed_wrapper_records_every_lookup() {
+    let mut sim = Simulation::new(3);
+    let rules = sim.generate_rules(50);
+    let packets = sim.generate_packets(100);
+
+    // Fake clock advancing by 10 ticks per reading.
+    let ticks = Cell::new(0u64);
+    let clock = || {
+        ticks.set(ticks.get() + 10);
+        ticks.get()
+    };
+
+    let linear = LinearClassifier::build(&rules);
+    let mut timed = Timed::new(LinearClassifier::build(&rules), clock);
+    for p in &packets {
+        assert_eq!(timed.classify(p), linear.classify(p));
+    }
+    assert_eq!(timed.histogram().count(), 100);
+    assert_eq!(timed.histogram().value_at_quantile(0.99), 10);
+}
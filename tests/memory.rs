@@ -0,0 +1,64 @@
+use cutsplit::classifier::Classifier;
+use cutsplit::cutsplit::classifier::CutSplitClassifier;
+use cutsplit::freeze::Freeze;
+use cutsplit::hicuts::classifier::HiCutsClassifier;
+use cutsplit::hypersplit::classifier::HyperSplitClassifier;
+use cutsplit::linear::LinearClassifier;
+use cutsplit::memory::{Footprint, MemoryStats};
+use cutsplit::partitionsort::classifier::PartitionSortClassifier;
+use cutsplit::rule::Rule;
+use cutsplit::simulation::Simulation;
+use cutsplit::tss::classifier::TSSClassifier;
+
+/// Check `C`'s footprint over `rules` for consistency, returning it.
+fn check<C: Classifier + MemoryStats + Freeze>(rules: &[Rule]) -> Footprint {
+    let mut classifier = C::build(rules);
+    let f = classifier.footprint();
+    assert_eq!(f.rules, rules.len());
+    assert!(f.stored_rules >= f.rules);
+    assert!(f.duplication() >= 1.0);
+    assert!(f.leaves > 0 && f.leaves <= f.nodes);
+    assert!(f.average_leaf_size() > 0.0);
+    assert!(f.bytes >= f.rules * size_of::<Rule>());
+
+    // Shrinking only drops spare capacity.
+    classifier.shrink_to_fit();
+    let shrunk = classifier.footprint();
+    assert!(shrunk.bytes <= f.bytes);
+    assert_eq!(
+        Footprint {
+            bytes: f.bytes,
+            ..shrunk
+        },
+        f
+    );
+    f
+}
+
+#[test]
+fn test_memory_footprints() {
+    let rules = Simulation::new(3259).generate_rules(500);
+
+    let linear = check::<LinearClassifier>(&rules);
+    assert_eq!(linear.duplication(), 1.0);
+    assert_eq!((linear.nodes, linear.leaves, linear.max_depth), (1, 1, 0));
+
+    let cutsplit = check::<CutSplitClassifier>(&rules);
+    let hicuts = check::<HiCutsClassifier>(&rules);
+    let hypersplit = check::<HyperSplitClassifier>(&rules);
+    for tree in [cutsplit, hicuts, hypersplit] {
+        assert!(tree.nodes > 1 && tree.max_depth > 0);
+        assert!(tree.bytes > linear.bytes);
+    }
+    check::<TSSClassifier>(&rules);
+    let ps = check::<PartitionSortClassifier>(&rules);
+    assert_eq!(ps.max_depth, 5);
+}
+
+#[test]
+fn test_memory_footprint_of_empty_classifier() {
+    let f = LinearClassifier::build(&[]).footprint();
+    assert_eq!((f.rules, f.stored_rules), (0, 0));
+    assert_eq!(f.duplication(), 1.0);
+    assert_eq!(f.average_leaf_size(), 0.0);
+}
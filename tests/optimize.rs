@@ -0,0 +1,150 @@
+use cutsplit::classifier::Classifier;
+use cutsplit::linear::LinearClassifier;
+use cutsplit::optimize::{merge_adjacent, reorder};
+use cutsplit::rule::{Action, Range, Rule, ANY_ZONE};
+use cutsplit::simulation::Simulation;
+use std::collections::HashMap;
+
+fn rule(id: u32, dst_ip: Range<u32>, dst_port: Range<u16>, action: Action) -> Rule {
+    Rule {
+        id,
+        priority: id,
+        src_ip: Range::any(0, u32::MAX),
+        dst_ip,
+        src_port: Range::any(0, u16::MAX),
+        dst_port,
+        proto: Range::exact(6),
+        zone: ANY_ZONE,
+        bidirectional: false,
+        field_sets: None,
+        action,
+    }
+}
+
+#[test]
+fn test_per_port_rules_collapse() {
+    let host = Range::exact(0x0A00_0001);
+    let mut rules: Vec<Rule> = (0..20)
+        .map(|i| rule(i, host, Range::exact(8000 + i as u16), Action::Permit))
+        .collect();
+    // Same ports on the neighbouring /31 half: merges once the ports have.
+    rules.extend((20..40).map(|i| {
+        rule(
+            i,
+            Range::exact(0x0A00_0000),
+            Range::exact(8000 + (i - 20) as u16),
+            Action::Permit,
+        )
+    }));
+    // Adjacent but with another action: must stay separate.
+    rules.push(rule(40, host, Range::exact(8020), Action::Deny));
+
+    let (merged, report) = merge_adjacent(&rules);
+    assert_eq!(report.before, 41);
+    assert_eq!(report.after, 2);
+    assert_eq!(report.absorbed.len(), 39);
+    assert_eq!(merged[0].id, 0);
+    assert_eq!(merged[0].dst_ip, Range::new(0x0A00_0000, 0x0A00_0001));
+    assert_eq!(merged[0].dst_port, Range::new(8000, 8019));
+    assert_eq!(merged[1].id, 40);
+}
+
+#[test]
+fn test_merging_preserves_actions() {
+    let mut sim = Simulation::new(3223);
+    let mut rules = sim.generate_rules(300);
+    // Split some rules on their destination port so there is something to merge.
+    let mut next_id = rules.len() as u32;
+    let mut split = Vec::new();
+    for r in &rules {
+        let ports = r.dst_port;
+        if r.id % 3 == 0 && ports.min < ports.max {
+            let mid = ports.min + (ports.max - ports.min) / 2;
+            split.push(Rule {
+                dst_port: Range::new(ports.min, mid),
+                ..r.clone()
+            });
+            split.push(Rule {
+                id: next_id,
+                dst_port: Range::new(mid + 1, ports.max),
+                ..r.clone()
+            });
+            next_id += 1;
+        } else {
+            split.push(r.clone());
+        }
+    }
+    rules = split;
+
+    let (merged, report) = merge_adjacent(&rules);
+    assert!(report.after < report.before);
+    assert!(report.reduction() > 0.0);
+
+    let original = LinearClassifier::build(&rules);
+    let optimized = LinearClassifier::build(&merged);
+    let mut packets = sim.generate_packets(5000);
+    packets.extend(rules.iter().map(|r| sim.sample_for_rule(r)));
+    for p in &packets {
+        assert_eq!(optimized.classify(p), original.classify(p), "{:?}", p);
+    }
+}
+
+#[test]
+fn test_reordering_preserves_matches() {
+    let mut sim = Simulation::new(3224);
+    let rules = sim.generate_rules(400);
+    let packets = sim.generate_packets(5000);
+    let original = LinearClassifier::build(&rules);
+
+    let mut hits: HashMap<u32, u64> = HashMap::new();
+    for p in &packets {
+        if let Some(r) = original.classify_rule(p) {
+            *hits.entry(r.id).or_default() += 1;
+        }
+    }
+    let reordered = reorder(&rules, |r| hits.get(&r.id).copied().unwrap_or(0));
+    assert_eq!(reordered.len(), rules.len());
+    let mut priorities: Vec<u32> = reordered.iter().map(|r| r.priority).collect();
+    priorities.sort_unstable();
+    assert_eq!(priorities, (0..rules.len() as u32).collect::<Vec<_>>());
+    assert!(reordered.iter().zip(&rules).any(|(a, b)| a.id != b.id));
+
+    let optimized = LinearClassifier::build(&reordered);
+    let position: HashMap<u32, u32> = reordered.iter().map(|r| (r.id, r.priority)).collect();
+    let (mut before, mut after) = (0u64, 0u64);
+    let mut probes = packets.clone();
+    probes.extend(rules.iter().map(|r| sim.sample_for_rule(r)));
+    for p in &probes {
+        let expected = original.classify_rule(p).map(|r| r.id);
+        assert_eq!(
+            optimized.classify_rule(p).map(|r| r.id),
+            expected,
+            "{:?}",
+            p
+        );
+    }
+    for p in &packets {
+        let id = original.classify_rule(p).unwrap().id;
+        before += id as u64;
+        after += position[&id] as u64;
+    }
+    assert!(after < before, "{} >= {}", after, before);
+}
+
+#[test]
+fn test_overlapping_rules_keep_their_order() {
+    let host = Range::exact(0x0A00_0001);
+    let rules = vec![
+        rule(0, host, Range::exact(22), Action::Deny),
+        rule(1, host, Range::any(0, u16::MAX), Action::Permit),
+        rule(
+            2,
+            Range::exact(0x0A00_0002),
+            Range::exact(80),
+            Action::Permit,
+        ),
+    ];
+    let hot = |r: &Rule| r.id as u64;
+    let ids: Vec<u32> = reorder(&rules, hot).iter().map(|r| r.id).collect();
+    assert_eq!(ids, vec![2, 0, 1]);
+}
@@ -0,0 +1,321 @@
+use cutsplit::classifier::Classifier;
+use cutsplit::hypersplit::classifier::HyperSplitClassifier;
+use cutsplit::linear::LinearClassifier;
+use cutsplit::packet::{
+    internet_checksum, protocol_by_name, protocol_name, Checksum, ChecksumError, FiveTuple,
+    IcmpHeader, IgmpHeader, Ipv4Bytes, Ipv4Header, L4Header, L4Key, Packet, PacketKey, Service,
+    TcpHeader, UdpHeader, ICMP_DEST_UNREACHABLE, ICMP_ECHO_REQUEST, IGMP_V2_MEMBERSHIP_REPORT,
+    PROTOCOLS, PROTO_GRE, PROTO_ICMP, PROTO_IGMP, PROTO_TCP, PROTO_UDP,
+};
+use cutsplit::prefix::Prefix;
+use cutsplit::rule::{Action, Range, Rule};
+use cutsplit::simulation::Simulation;
+
+fn packet(dst_ip: u32, dst_port: u16, proto: u8) -> FiveTuple {
+    FiveTuple {
+        src_ip: 0x0a00_0001,
+        dst_ip,
+        src_port: 40000,
+        dst_port,
+        proto,
+        zone: 0,
+    }
+}
+
+#[test]
+fn test_protocol_names() {
+    for &(number, name) in PROTOCOLS {
+        assert_eq!(protocol_name(number), Some(name));
+        assert_eq!(protocol_by_name(name), Some(number));
+    }
+    assert_eq!(protocol_by_name("GRE"), Some(PROTO_GRE));
+    assert_eq!(protocol_by_name("ip"), None);
+    assert_eq!(protocol_name(255), None);
+}
+
+#[test]
+fn test_service_rules() {
+    let server = Prefix::new(0xc0a8_0105, 32);
+    let lan = Prefix::new(0xc0a8_0000, 16);
+
+    let ssh = Rule::tcp_service(server, 22).with_src(Prefix::new(0x0a00_0000, 8));
+    assert_eq!(ssh.dst_ip, Range::exact(0xc0a8_0105));
+    assert_eq!(ssh.action, Action::Permit);
+    assert!(ssh.is_plain());
+    assert!(ssh.matches(&packet(0xc0a8_0105, 22, PROTO_TCP)));
+    assert!(!ssh.matches(&packet(0xc0a8_0105, 22, PROTO_UDP)));
+    assert!(!ssh.matches(&packet(0xc0a8_0105, 22, PROTO_TCP).reversed()));
+    assert!(Rule::udp_service(server, 123).matches(&packet(0xc0a8_0105, 123, PROTO_UDP)));
+
+    // Services on several protocols or ports.
+    let dns = Rule::service(lan, &Service::DNS);
+    let dhcp = Rule::service(lan, &Service::DHCP);
+    let http = Rule::service(lan, &Service::HTTP);
+    assert!(dhcp.is_plain());
+    assert_eq!(dhcp.dst_port, Range::new(67, 68));
+    assert!(!dns.is_plain() && !http.is_plain());
+    for service in Service::ALL {
+        let rule = Rule::service(lan, service);
+        for &proto in service.protocols {
+            for &port in service.ports {
+                let p = packet(0xc0a8_2a2a, port, proto);
+                assert!(service.matches(&p));
+                assert!(rule.matches(&p), "{} {:?}", service.name, p);
+            }
+        }
+        assert!(!rule.matches(&packet(0xc0a8_2a2a, 9, PROTO_TCP)));
+        assert!(!rule.matches(&packet(0x0a00_0002, service.ports[0], service.protocols[0])));
+    }
+    assert_eq!(Service::by_name("NTP"), Some(Service::NTP));
+    assert_eq!(Service::by_name("gopher"), None);
+
+    let policy = [
+        Rule::service(lan, &Service::DNS)
+            .with_id(1)
+            .with_priority(1),
+        Rule::tcp_service(server, 443).with_id(2).with_priority(2),
+        Rule::wildcard(Action::Deny).with_id(3).with_priority(3),
+    ];
+    let classifier = LinearClassifier::build(&policy);
+    let id = |p| classifier.classify_rule(&p).map(|r| r.id);
+    assert_eq!(id(packet(0xc0a8_0105, 53, PROTO_UDP)), Some(1));
+    assert_eq!(id(packet(0xc0a8_0105, 443, PROTO_TCP)), Some(2));
+    assert_eq!(id(packet(0xc0a8_0106, 443, PROTO_TCP)), Some(3));
+}
+
+#[test]
+fn test_icmp_igmp_keys() {
+    /// IPv4 header (20 bytes) followed by `l4`.
+    fn raw(proto: u8, l4: &[u8]) -> Vec<u8> {
+        let mut bytes = vec![0x45, 0, 0, 0, 0, 0, 0x40, 0, 64, proto, 0, 0];
+        bytes.extend(0x0a00_0001u32.to_be_bytes());
+        bytes.extend(0xe000_0001u32.to_be_bytes());
+        bytes.extend(l4);
+        bytes
+    }
+
+    let ip = |proto| Ipv4Header {
+        src: 0x0a00_0001,
+        dst: 0xe000_0001,
+        proto,
+        version: 4,
+        ihl: 5,
+        ttl: 64,
+        ..Ipv4Header::default()
+    };
+    let ping = Packet {
+        ip: ip(PROTO_ICMP),
+        l4: L4Header::Icmp(IcmpHeader {
+            icmp_type: ICMP_ECHO_REQUEST,
+            code: 0,
+            checksum: 0,
+        }),
+    };
+    let unreachable = Packet {
+        ip: ip(PROTO_ICMP),
+        l4: L4Header::Icmp(IcmpHeader {
+            icmp_type: ICMP_DEST_UNREACHABLE,
+            code: 3,
+            checksum: 0,
+        }),
+    };
+    let report = Packet {
+        ip: ip(PROTO_IGMP),
+        l4: L4Header::Igmp(IgmpHeader {
+            igmp_type: IGMP_V2_MEMBERSHIP_REPORT,
+            max_resp_time: 0,
+            checksum: 0,
+            group_addr: 0xe000_0001,
+        }),
+    };
+
+    // Parsed headers, raw buffers and tuples give the same key.
+    let echo = ping.to_5tuple();
+    assert_eq!(
+        (echo.src_port, echo.dst_port),
+        (ICMP_ECHO_REQUEST as u16, 0)
+    );
+    assert_eq!(
+        unreachable.l4_key(),
+        L4Key::Icmp {
+            icmp_type: ICMP_DEST_UNREACHABLE,
+            code: 3
+        }
+    );
+    assert_eq!(unreachable.to_5tuple().l4(), unreachable.l4_key());
+    let bytes = raw(PROTO_ICMP, &[ICMP_DEST_UNREACHABLE, 3, 0, 0]);
+    let view = Ipv4Bytes::new(&bytes).unwrap();
+    assert_eq!(view.to_five_tuple(), unreachable.to_5tuple());
+    let join = report.to_5tuple();
+    assert_eq!(
+        join.l4(),
+        L4Key::Igmp {
+            igmp_type: IGMP_V2_MEMBERSHIP_REPORT
+        }
+    );
+    assert_eq!(join.dst_port, 0);
+    let bytes = raw(
+        PROTO_IGMP,
+        &[IGMP_V2_MEMBERSHIP_REPORT, 0, 0, 0, 0xe0, 0, 0, 1],
+    );
+    assert_eq!(Ipv4Bytes::new(&bytes).unwrap().to_five_tuple(), join);
+    assert!(Ipv4Bytes::new(&raw(PROTO_ICMP, &[8])).is_none());
+    // The L4 key is never read from inside a short (IHL < 5) header.
+    let mut short = bytes.clone();
+    short[0] = 0x44;
+    assert!(Ipv4Bytes::new(&short).is_none());
+    // Nor past the buffer, with options longer than what was captured.
+    let mut long = bytes.clone();
+    long[0] = 0x4f;
+    assert!(Ipv4Bytes::new(&long).is_none());
+    assert_eq!(L4Key::decode(PROTO_GRE, 1, 2), L4Key::None);
+    assert_eq!(echo.with_l4(L4Key::None).l4().ports(), (0, 0));
+
+    // Rules match message types through the port fields.
+    let rules = [
+        Rule::icmp(ICMP_ECHO_REQUEST, None)
+            .with_id(1)
+            .with_priority(1),
+        Rule::icmp(ICMP_DEST_UNREACHABLE, Some(4))
+            .with_id(2)
+            .with_priority(2),
+        Rule::igmp(IGMP_V2_MEMBERSHIP_REPORT)
+            .with_id(3)
+            .with_priority(3),
+        Rule::wildcard(Action::Deny).with_id(4).with_priority(4),
+    ];
+    let classifier = HyperSplitClassifier::build(&rules);
+    let id = |p: &Packet| classifier.classify_rule(&p.to_5tuple()).map(|r| r.id);
+    assert_eq!(id(&ping), Some(1));
+    assert_eq!(id(&unreachable), Some(4));
+    assert_eq!(id(&report), Some(3));
+    assert_eq!(
+        classifier.classify(&view),
+        classifier.classify(&unreachable)
+    );
+
+    // Generated IGMP packets follow the convention too.
+    let mut sim = Simulation::new(7);
+    let igmp: Vec<_> = sim
+        .generate_packets(500)
+        .into_iter()
+        .filter(|p| p.proto == PROTO_IGMP)
+        .collect();
+    assert!(!igmp.is_empty());
+    for p in igmp {
+        assert!(matches!(p.l4(), L4Key::Igmp { .. }));
+        assert_eq!(p.with_l4(p.l4()), p);
+    }
+}
+
+#[test]
+fn test_checksums() {
+    // The usual worked example: 192.168.0.1 -> 192.168.0.199, 115 bytes, DF.
+    let mut ip = Ipv4Header {
+        src: 0xc0a8_0001,
+        dst: 0xc0a8_00c7,
+        proto: PROTO_UDP,
+        version: 4,
+        ihl: 5,
+        ttl: 64,
+        total_len: 0x73,
+        fragment: 0x4000,
+        ..Ipv4Header::default()
+    };
+    assert_eq!(ip.compute_checksum(), 0xb861);
+    assert!(!ip.verify_checksum());
+    ip.checksum = 0xb861;
+    assert!(ip.verify_checksum());
+    assert_eq!(internet_checksum(&ip.to_bytes()), 0);
+
+    // Sums may be built from slices; only the last may be odd.
+    let data = [1u8, 2, 3, 4, 5, 6, 7];
+    assert_eq!(
+        Checksum::new().add(&data[..4]).add(&data[4..]).finish(),
+        internet_checksum(&data)
+    );
+
+    /// IPv4 packet of `ip` (its length and checksum filled in) carrying `l4`.
+    fn raw(ip: Ipv4Header, l4: &[u8]) -> Vec<u8> {
+        let mut ip = Ipv4Header {
+            total_len: 20 + l4.len() as u16,
+            ..ip
+        };
+        ip.checksum = ip.compute_checksum();
+        let mut bytes = ip.to_bytes().to_vec();
+        bytes.extend(l4);
+        bytes
+    }
+
+    let payload = b"GET / HTTP/1.1\r\n\r\n";
+    let tcp_ip = Ipv4Header {
+        proto: PROTO_TCP,
+        ..ip
+    };
+    let mut tcp = TcpHeader {
+        src_port: 40000,
+        dst_port: 80,
+        sequence: 1,
+        flags: 0x18,
+        window: 512,
+        ..TcpHeader::default()
+    };
+    tcp.checksum = tcp.compute_checksum(&tcp_ip, payload);
+    assert!(tcp.verify_checksum(&tcp_ip, payload));
+    assert!(!tcp.verify_checksum(&tcp_ip, b"GET / HTTP/1.0\r\n\r\n"));
+    let mut segment = tcp.to_bytes().to_vec();
+    segment.extend(payload);
+    let mut bytes = raw(tcp_ip, &segment);
+    assert_eq!(Ipv4Bytes::new(&bytes).unwrap().verify_checksums(), Ok(()));
+    bytes[30] ^= 1;
+    assert_eq!(
+        Ipv4Bytes::new(&bytes).unwrap().verify_checksums(),
+        Err(ChecksumError::Tcp)
+    );
+    bytes[30] ^= 1;
+    bytes[8] -= 1;
+    assert_eq!(
+        Ipv4Bytes::new(&bytes).unwrap().verify_checksums(),
+        Err(ChecksumError::Ipv4Header)
+    );
+    assert_eq!(
+        Ipv4Bytes::new(&bytes[..bytes.len() - 1])
+            .unwrap()
+            .verify_checksums(),
+        Err(ChecksumError::Truncated)
+    );
+
+    let mut udp = UdpHeader {
+        src_port: 5353,
+        dst_port: 53,
+        length: 8 + 3,
+        checksum: 0,
+    };
+    let query = [0xab, 0xcd, 0xef];
+    let udp_ip = Ipv4Header {
+        proto: PROTO_UDP,
+        ..ip
+    };
+    // A datagram without a checksum is accepted.
+    assert!(udp.verify_checksum(&udp_ip, &query));
+    let mut datagram = udp.to_bytes().to_vec();
+    datagram.extend(query);
+    assert_eq!(
+        Ipv4Bytes::new(&raw(udp_ip, &datagram))
+            .unwrap()
+            .verify_checksums(),
+        Ok(())
+    );
+    udp.checksum = udp.compute_checksum(&udp_ip, &query);
+    assert_ne!(udp.checksum, 0);
+    assert!(udp.verify_checksum(&udp_ip, &query));
+    let mut datagram = udp.to_bytes().to_vec();
+    datagram.extend(query);
+    let mut bytes = raw(udp_ip, &datagram);
+    assert_eq!(Ipv4Bytes::new(&bytes).unwrap().verify_checksums(), Ok(()));
+    *bytes.last_mut().unwrap() ^= 0x80;
+    assert_eq!(
+        Ipv4Bytes::new(&bytes).unwrap().verify_checksums(),
+        Err(ChecksumError::Udp)
+    );
+}
@@ -0,0 +1,121 @@
+use cutsplit::prefix::{range_to_prefixes, range_to_prefixes_u128, range_to_prefixes_u32, Prefix};
+use cutsplit::rule::Range;
+
+#[test]
+fn test_prefix_decomposition_all_widths() {
+    // u32 generic path agrees with the legacy function
+    for &(min, max) in &[(0u32, u32::MAX), (1, 14), (0xC0A80000, 0xC0A800FF), (5, 5)] {
+        let legacy = range_to_prefixes_u32(min, max, 32);
+        let generic = range_to_prefixes(min, max);
+        assert_eq!(legacy, generic);
+        // Prefixes tile the range exactly
+        assert_eq!(generic.first().unwrap().first(), min);
+        assert_eq!(generic.last().unwrap().last(), max);
+        for w in generic.windows(2) {
+            assert_eq!(w[0].last() + 1, w[1].first());
+        }
+    }
+
+    // 128-bit ranges, including the full IPv6 space
+    let full = range_to_prefixes_u128(0, u128::MAX, 128);
+    assert_eq!(full, vec![Prefix { value: 0, len: 0 }]);
+    let doc = 0x2001_0db8u128 << 96;
+    let ps = range_to_prefixes::<u128>(doc, doc + (1u128 << 96) - 1);
+    assert_eq!(
+        ps,
+        vec![Prefix {
+            value: doc,
+            len: 32
+        }]
+    );
+    let odd = range_to_prefixes::<u128>(u128::MAX - 2, u128::MAX);
+    assert_eq!(odd.len(), 2);
+    assert_eq!(odd[1].last(), u128::MAX);
+}
+
+#[test]
+fn test_prefix_containment_and_ranges() {
+    let net = Prefix::new(0x0A01_0203u32, 8); // host bits cleared
+    assert_eq!(net.value, 0x0A00_0000);
+    assert_eq!(net.to_range(), Range::new(0x0A00_0000, 0x0AFF_FFFF));
+    assert!(net.contains(0x0A12_3456));
+    assert!(!net.contains(0x0B00_0000));
+
+    let sub = Prefix::new(0x0A01_0000u32, 16);
+    assert!(net.covers(&sub));
+    assert!(!sub.covers(&net));
+    assert!(sub.overlaps(&net));
+    assert!(!sub.overlaps(&Prefix::new(0x0A02_0000u32, 16)));
+
+    let host = Prefix::new(u128::MAX, 128);
+    assert_eq!(host.to_range(), Range::new(u128::MAX, u128::MAX));
+    assert!(Prefix::new(0u128, 0).covers(&host));
+}
+
+#[test]
+fn test_prefix_trie_longest_match_against_brute_force() {
+    use cutsplit::trie::PrefixTrie;
+    use rand::{Rng, SeedableRng};
+
+    let mut rng = rand::rngs::StdRng::seed_from_u64(11);
+    // Keep keys inside 10.0.0.0/16 so prefixes of all lengths overlap.
+    let mut trie = PrefixTrie::new();
+    let mut reference: Vec<(Prefix<u32>, u32)> = Vec::new();
+
+    for i in 0..2000u32 {
+        let p = Prefix::new(
+            0x0A00_0000 | (rng.gen::<u32>() & 0xFFFF),
+            rng.gen_range(0..=32),
+        );
+        if trie.insert(p, i).is_none() {
+            reference.push((p, i));
+        } else {
+            reference.iter_mut().find(|(q, _)| *q == p).unwrap().1 = i;
+        }
+    }
+    // Remove a third of them
+    for k in (0..reference.len()).step_by(3).rev() {
+        let (p, v) = reference.remove(k);
+        assert_eq!(trie.remove(p), Some(v));
+        assert_eq!(trie.remove(p), None);
+    }
+    assert_eq!(trie.len(), reference.len());
+    assert_eq!(trie.iter().len(), reference.len());
+
+    for _ in 0..5000 {
+        let addr: u32 = 0x0A00_0000 | (rng.gen::<u32>() & 0xFFFF);
+        let expected = reference
+            .iter()
+            .filter(|(p, _)| p.contains(addr))
+            .max_by_key(|(p, _)| p.len)
+            .map(|(p, v)| (*p, *v));
+        let got = trie.longest_match(addr).map(|(p, v)| (p, *v));
+        assert_eq!(got, expected);
+        assert_eq!(
+            trie.matches(addr).len(),
+            reference.iter().filter(|(p, _)| p.contains(addr)).count()
+        );
+    }
+}
+
+#[test]
+fn test_prefix_trie_u128() {
+    use cutsplit::trie::PrefixTrie;
+
+    let mut trie = PrefixTrie::new();
+    let doc = 0x2001_0db8u128 << 96;
+    trie.insert(Prefix::new(doc, 32), "doc");
+    trie.insert(Prefix::new(doc | (1 << 80), 48), "site");
+    trie.insert(Prefix::new(0u128, 0), "default");
+
+    assert_eq!(
+        trie.longest_match(doc | (1 << 80) | 7).map(|(_, v)| *v),
+        Some("site")
+    );
+    assert_eq!(trie.longest_match(doc | 7).map(|(_, v)| *v), Some("doc"));
+    assert_eq!(
+        trie.longest_match(u128::MAX).map(|(_, v)| *v),
+        Some("default")
+    );
+    assert_eq!(trie.get(Prefix::new(doc, 32)), Some(&"doc"));
+}
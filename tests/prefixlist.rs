@@ -0,0 +1,106 @@
+use cutsplit::classifier::Classifier;
+use cutsplit::hypersplit::classifier::HyperSplitClassifier;
+use cutsplit::prefix::Prefix;
+use cutsplit::prefixlist::{ListField, PrefixList, PrefixListFront};
+use cutsplit::rule::Action;
+use cutsplit::simulation::Simulation;
+use cutsplit::trie::PrefixTrie;
+use rand::{Rng, SeedableRng};
+use rand_pcg::Pcg32;
+
+#[test]
+fn test_compiled_list_matches_trie() {
+    let mut rng = Pcg32::seed_from_u64(99);
+    let prefixes: Vec<Prefix<u32>> = (0..100_000)
+        .map(|_| Prefix::new(rng.gen(), rng.gen_range(8..=28)))
+        .collect();
+
+    let list = PrefixList::compile(1, prefixes.iter().copied(), ListField::Source, Action::Deny);
+    let mut trie = PrefixTrie::new();
+    for &p in &prefixes {
+        trie.insert(p, ());
+    }
+    assert!(list.range_count() <= prefixes.len());
+
+    let mut probes: Vec<u32> = (0..50_000).map(|_| rng.gen()).collect();
+    for p in prefixes.iter().take(5_000) {
+        probes.extend([
+            p.first(),
+            p.last(),
+            p.first().wrapping_sub(1),
+            p.last().wrapping_add(1),
+        ]);
+    }
+    for addr in probes {
+        assert_eq!(list.contains(addr), trie.contains(addr), "{:#x}", addr);
+    }
+}
+
+#[test]
+fn test_adjacent_prefixes_merge() {
+    let list = PrefixList::compile(
+        1,
+        [
+            Prefix::new(0x0A00_0000, 9),
+            Prefix::new(0x0A80_0000, 9),
+            Prefix::new(0x0A10_0000, 16),
+            Prefix::new(0xFFFF_FF00, 24),
+            Prefix::new(0, 32),
+        ],
+        ListField::Destination,
+        Action::Deny,
+    );
+    assert_eq!(list.range_count(), 3);
+    assert!(list.contains(0x0AFF_FFFF));
+    assert!(!list.contains(0x0B00_0000));
+    assert!(list.contains(u32::MAX));
+    assert!(list.contains(0));
+    assert!(!list.contains(1));
+}
+
+#[test]
+fn test_prefix_lists_front_the_main_classifier() {
+    let mut sim = Simulation::new(1234);
+    let rules = sim.generate_rules(200);
+    let packets = sim.generate_packets(2000);
+
+    let bogons = PrefixList::compile(
+        1000,
+        [Prefix::new(0x0A00_0000, 8), Prefix::new(0xAC10_0000, 12)],
+        ListField::Either,
+        Action::Deny,
+    );
+    let allowed = PrefixList::compile(
+        1001,
+        [Prefix::new(0x0800_0000, 8)],
+        ListField::Source,
+        Action::Permit,
+    );
+    let front = PrefixListFront::new(
+        vec![bogons.clone(), allowed.clone()],
+        HyperSplitClassifier::build(&rules),
+    );
+    let main = HyperSplitClassifier::build(&rules);
+
+    let mut hits = 0;
+    for p in &packets {
+        let expected = if bogons.matches(p) {
+            Some(1000)
+        } else if allowed.matches(p) {
+            Some(1001)
+        } else {
+            main.classify_rule(p).map(|r| r.id)
+        };
+        hits += (expected >= Some(1000)) as usize;
+        assert_eq!(front.classify_rule(p).map(|r| r.id), expected);
+        assert_eq!(front.classify_with_stats(p).0, front.classify(p));
+    }
+    assert!(hits > 0);
+
+    let mut p = packets[0];
+    p.src_ip = 0x0A01_0101;
+    assert_eq!(front.classify(&p), Some(Action::Deny));
+    p.src_ip = 0x0801_0101;
+    p.dst_ip = 0x0801_0102;
+    assert_eq!(front.classify(&p), Some(Action::Permit));
+}
@@ -0,0 +1,203 @@
+use cutsplit::classifier::Classifier;
+use cutsplit::compact::FreezeCompact;
+use cutsplit::cutsplit::classifier::CutSplitClassifier;
+use cutsplit::cutsplit::tree::Dimension;
+use cutsplit::geometry::Region;
+use cutsplit::hicuts::classifier::HiCutsClassifier;
+use cutsplit::hypersplit::classifier::HyperSplitClassifier;
+use cutsplit::linear::LinearClassifier;
+use cutsplit::packet::FiveTuple;
+use cutsplit::partitionsort::classifier::PartitionSortClassifier;
+use cutsplit::preprocess::WildcardSplit;
+use cutsplit::query::{rule_overlaps, RegionQuery};
+use cutsplit::rule::{Range, Rule};
+use cutsplit::simulation::Simulation;
+use cutsplit::tss::classifier::TSSClassifier;
+
+/// Regions of various shapes: rule boxes, subnet pairs and single ports.
+fn regions(sim: &mut Simulation, rules: &[Rule]) -> Vec<Region> {
+    let mut out = vec![Region::full()];
+    for rule in rules.iter().step_by(7) {
+        out.push(Region::from_rule(rule));
+        let p = sim.sample_for_rule(rule);
+        out.push(Region::from_packet(&p));
+        out.push(
+            Region::full()
+                .with(
+                    Dimension::SrcIp,
+                    Range::new(p.src_ip & !0xFFFF, p.src_ip | 0xFFFF),
+                )
+                .with(
+                    Dimension::DstIp,
+                    Range::new(p.dst_ip & !0xFF, p.dst_ip | 0xFF),
+                ),
+        );
+        out.push(Region::full().with(Dimension::DstPort, Range::exact(p.dst_port as u32)));
+    }
+    out
+}
+
+fn check<C: RegionQuery>(name: &str, classifier: &C, rules: &[Rule], regions: &[Region]) {
+    for region in regions {
+        let mut expected: Vec<u32> = rules
+            .iter()
+            .filter(|r| rule_overlaps(r, region))
+            .map(|r| r.id)
+            .collect();
+        expected.sort_unstable();
+        let found = classifier.rules_overlapping(region);
+        assert!(found.windows(2).all(|w| w[0].priority <= w[1].priority));
+        let mut ids: Vec<u32> = found.iter().map(|r| r.id).collect();
+        ids.sort_unstable();
+        assert_eq!(ids, expected, "{} on {:?}", name, region);
+    }
+}
+
+#[test]
+fn test_rules_overlapping_matches_brute_force() {
+    let mut sim = Simulation::new(3227);
+    let mut rules = sim.generate_rules(300);
+    rules[3].bidirectional = true;
+    rules[10]
+        .field_sets_mut()
+        .dst_port
+        .push(Range::new(5000, 5100));
+    let regions = regions(&mut sim, &rules);
+
+    check("linear", &LinearClassifier::build(&rules), &rules, &regions);
+    check(
+        "cutsplit",
+        &CutSplitClassifier::build(&rules),
+        &rules,
+        &regions,
+    );
+    check("hicuts", &HiCutsClassifier::build(&rules), &rules, &regions);
+    check(
+        "hypersplit",
+        &HyperSplitClassifier::build(&rules),
+        &rules,
+        &regions,
+    );
+    check("tss", &TSSClassifier::build(&rules), &rules, &regions);
+    check(
+        "ps",
+        &PartitionSortClassifier::build(&rules),
+        &rules,
+        &regions,
+    );
+    check(
+        "compact",
+        &HyperSplitClassifier::build(&rules).freeze_compact(),
+        &rules,
+        &regions,
+    );
+    check(
+        "wildcard split",
+        &WildcardSplit::<CutSplitClassifier>::build(&rules),
+        &rules,
+        &regions,
+    );
+}
+
+#[test]
+fn test_overlapping_rules_cover_every_lookup() {
+    let mut sim = Simulation::new(32270);
+    let rules = sim.generate_rules(200);
+    let classifier = HyperSplitClassifier::build(&rules);
+    let subnet = Region::full()
+        .with(Dimension::SrcIp, Range::new(0x0A00_0000, 0x0AFF_FFFF))
+        .with(Dimension::DstPort, Range::new(0, 1023));
+    let relevant = classifier.rules_overlapping(&subnet);
+    assert!(!relevant.is_empty());
+    for p in sim.sample_packets_in(&subnet, 500) {
+        let id = classifier.classify_rule(&p).map(|r| r.id);
+        assert!(relevant.iter().any(|r| Some(r.id) == id));
+    }
+}
+
+#[test]
+fn test_wildcard_queries_match_exhaustive_lookup() {
+    let mut sim = Simulation::new(3228);
+    let mut rules = sim.generate_rules(150);
+    rules.pop(); // No default rule, so some packets miss.
+    let linear = LinearClassifier::build(&rules);
+    let hypersplit = HyperSplitClassifier::build(&rules);
+
+    for rule in rules.iter().step_by(5) {
+        // Unknown protocol: small enough to enumerate.
+        let p = sim.sample_for_rule(rule);
+        let region = Region::from_packet(&p).any(Dimension::Proto);
+        let mut expected: Vec<u32> = Vec::new();
+        let mut missed = false;
+        for proto in 0..=255u8 {
+            match linear.classify_rule(&FiveTuple { proto, ..p }) {
+                Some(r) if !expected.contains(&r.id) => expected.push(r.id),
+                Some(_) => {}
+                None => missed = true,
+            }
+        }
+        expected.sort_unstable();
+
+        let possible = hypersplit.possible_matches(&region);
+        let mut ids: Vec<u32> = possible.rules.iter().map(|r| r.id).collect();
+        ids.sort_unstable();
+        assert_eq!(ids, expected, "{:?}", region);
+        assert_eq!(possible.may_miss, missed);
+        assert_eq!(linear.possible_matches(&region), possible);
+
+        // Unknown ports: every sampled outcome is among the possible ones.
+        let region = Region::from_packet(&p)
+            .any(Dimension::SrcPort)
+            .any(Dimension::DstPort);
+        let possible = hypersplit.possible_matches(&region);
+        for q in sim.sample_packets_in(&region, 200) {
+            match linear.classify_rule(&q) {
+                Some(r) => assert!(possible.rules.iter().any(|c| c.id == r.id)),
+                None => assert!(possible.may_miss),
+            }
+        }
+    }
+}
+
+#[test]
+fn test_decided_wildcard_query() {
+    let mut sim = Simulation::new(32280);
+    let rules = sim.generate_rules(50);
+    let linear = LinearClassifier::build(&rules);
+
+    // Whatever the packet, the default rule catches it.
+    let anything = linear.possible_matches(&Region::full());
+    assert!(!anything.may_miss);
+    assert_eq!(anything.rules.last().unwrap().id, rules.last().unwrap().id);
+
+    // A packet fully specified has exactly one outcome.
+    let p = sim.generate_packets(1)[0];
+    let exact = linear.possible_matches(&Region::from_packet(&p));
+    assert!(exact.is_decided());
+    assert_eq!(exact.rules.len(), 1);
+    assert_eq!(exact.rules[0].id, linear.classify_rule(&p).unwrap().id);
+    assert_eq!(exact.actions(), vec![linear.classify(&p).unwrap()]);
+}
+
+#[test]
+fn test_classify_all_lists_matches_by_priority() {
+    let mut sim = Simulation::new(32610);
+    let mut rules = sim.generate_rules(300);
+    rules[5].bidirectional = true;
+    let packets = sim.generate_packets(300);
+    let linear = LinearClassifier::build(&rules);
+    let hypersplit = HyperSplitClassifier::build(&rules);
+    let tss = TSSClassifier::build(&rules);
+    for p in &packets {
+        let mut expected: Vec<&Rule> = rules.iter().filter(|r| r.matches(p)).collect();
+        expected.sort_by_key(|r| r.priority);
+        let ids = |found: Vec<&Rule>| found.iter().map(|r| r.id).collect::<Vec<_>>();
+        let all = ids(expected);
+        assert_eq!(ids(linear.classify_all(p)), all);
+        assert_eq!(ids(tss.classify_all(p)), all);
+        // Trees leave out matches a better rule shadows in a whole node.
+        let tree = ids(hypersplit.classify_all(p));
+        assert_eq!(tree.first(), all.first());
+        assert!(tree.iter().all(|id| all.contains(id)));
+    }
+}
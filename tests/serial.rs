@@ -0,0 +1,243 @@
+use cutsplit::classifier::Classifier;
+use cutsplit::compact::{CompactTree, FreezeCompact};
+use cutsplit::cutsplit::builder::Builder as CutSplitBuilder;
+use cutsplit::cutsplit::classifier::CutSplitClassifier;
+use cutsplit::fingerprint::{Fingerprint, LoadError};
+use cutsplit::hicuts::builder::Builder as HiCutsBuilder;
+use cutsplit::hicuts::classifier::{HiCutsClassifier, OutOfRange};
+use cutsplit::hypersplit::builder::Builder as HyperSplitBuilder;
+use cutsplit::hypersplit::classifier::HyperSplitClassifier;
+use cutsplit::packet::FiveTuple;
+use cutsplit::partitionsort::classifier::PartitionSortClassifier;
+use cutsplit::serial::Codec;
+use cutsplit::simulation::Simulation;
+use cutsplit::update::audit::DecodeError;
+
+/// Round-trip `classifier` and check the copy classifies like it.
+fn round_trip<C: Codec>(classifier: &C, packets: &[FiveTuple]) -> Vec<u8> {
+    let bytes = classifier.serialize();
+    let loaded = C::deserialize(&bytes).unwrap();
+    assert_eq!(loaded.serialize(), bytes);
+    for p in packets {
+        assert_eq!(
+            loaded.classify_rule(p).map(|r| r.id),
+            classifier.classify_rule(p).map(|r| r.id)
+        );
+    }
+    // Every strict prefix is rejected, as is trailing data.
+    for len in (0..bytes.len()).step_by(bytes.len() / 97 + 1) {
+        assert!(C::deserialize(&bytes[..len]).is_err());
+    }
+    let mut padded = bytes.clone();
+    padded.push(0);
+    assert_eq!(C::deserialize(&padded).err(), Some(DecodeError::Corrupt));
+    bytes
+}
+
+#[test]
+fn test_trees_round_trip() {
+    let mut sim = Simulation::new(32562);
+    let rules = sim.generate_rules(800);
+    let packets = sim.generate_packets(1000);
+
+    let cutsplit = CutSplitClassifier::from_builder(
+        &CutSplitBuilder::new(64, 8).with_secondary_index(16),
+        &rules,
+    );
+    round_trip(&cutsplit, &packets);
+    round_trip(&cutsplit.to_compact(), &packets);
+    round_trip(&CutSplitClassifier::build(&rules), &packets);
+    round_trip(
+        &HyperSplitClassifier::from_builder(
+            &HyperSplitBuilder::new(64, 4).with_secondary_index(8),
+            &rules,
+        ),
+        &packets,
+    );
+    round_trip(&CompactTree::build(&rules), &packets);
+    round_trip(&CompactTree::build(&[]), &packets);
+    round_trip(&HiCutsClassifier::build(&rules), &packets);
+    round_trip(
+        &HiCutsClassifier::from_builder(
+            &HiCutsBuilder::new(64, 8).with_secondary_index(16),
+            &rules,
+        ),
+        &packets,
+    );
+    round_trip(&PartitionSortClassifier::build(&rules), &packets);
+    round_trip(&PartitionSortClassifier::build(&[]), &packets);
+
+    // Lookup options survive the trip.
+    let strict = HiCutsClassifier::build(&rules).with_out_of_range(OutOfRange::Error);
+    let bytes = strict.serialize();
+    assert_eq!(
+        HiCutsClassifier::deserialize(&bytes).unwrap().serialize(),
+        bytes
+    );
+}
+
+#[test]
+fn test_deserialize_checks_fingerprint_and_kind() {
+    let mut sim = Simulation::new(32563);
+    let rules = sim.generate_rules(300);
+    let tree = HyperSplitClassifier::build(&rules);
+    let bytes = tree.serialize();
+    let expected = Fingerprint::of(&rules);
+
+    let loaded = HyperSplitClassifier::deserialize_checked(&bytes, expected).unwrap();
+    assert_eq!(loaded.fingerprint(), Some(expected));
+
+    let mut updated = rules.clone();
+    updated.pop();
+    let current = Fingerprint::of(&updated);
+    assert_eq!(
+        HyperSplitClassifier::deserialize_checked(&bytes, current).err(),
+        Some(LoadError::Stale {
+            expected: current,
+            found: Some(expected),
+        })
+    );
+    assert_eq!(
+        HyperSplitClassifier::deserialize_checked(b"CSDI", current).err(),
+        Some(LoadError::Decode(DecodeError::BadMagic))
+    );
+
+    // A tree without a known source never passes the check.
+    let tree = HiCutsClassifier::from_tree(HiCutsBuilder::new(10, 20).build(&rules));
+    assert!(matches!(
+        HiCutsClassifier::deserialize_checked(&tree.serialize(), expected),
+        Err(LoadError::Stale { found: None, .. })
+    ));
+
+    // Blobs of another classifier kind are refused.
+    assert_eq!(
+        CutSplitClassifier::deserialize(&bytes).err(),
+        Some(DecodeError::InvalidTag(2))
+    );
+    // CutSplit blobs from before the flat layout had kind 0.
+    let mut boxed = CutSplitClassifier::build(&rules).serialize();
+    boxed[5] = 0;
+    assert_eq!(
+        CutSplitClassifier::deserialize(&boxed).err(),
+        Some(DecodeError::InvalidTag(0))
+    );
+    let mut future = bytes.clone();
+    future[4] = 9;
+    assert_eq!(
+        HyperSplitClassifier::deserialize(&future).err(),
+        Some(DecodeError::UnsupportedVersion(9))
+    );
+}
+
+#[test]
+fn test_corrupt_compact_tree_is_rejected() {
+    let mut sim = Simulation::new(32564);
+    let rules = sim.generate_rules(100);
+    let tree = HyperSplitClassifier::build(&rules).to_compact();
+    let bytes = tree.serialize();
+    assert!(tree.node_count() > 1);
+
+    // Header (4 + 1 + 1 + 9 bytes) and node count, then the root: kind,
+    // value, left, right. Point the root's left child back at itself.
+    let root = 15 + 4;
+    assert!((bytes[root] as usize) < 5);
+    let mut cycle = bytes.clone();
+    cycle[root + 5..root + 9].copy_from_slice(&0u32.to_le_bytes());
+    assert_eq!(
+        CompactTree::deserialize(&cycle).err(),
+        Some(DecodeError::Corrupt)
+    );
+    let mut kind = bytes.clone();
+    kind[root] = 0x80;
+    assert_eq!(
+        CompactTree::deserialize(&kind).err(),
+        Some(DecodeError::InvalidTag(0x80))
+    );
+}
+
+#[test]
+fn test_deeply_nested_trees_are_rejected() {
+    // Chains of internal nodes far deeper than any builder makes must fail
+    // cleanly instead of overflowing the stack while decoding.
+    let rules = Simulation::new(32566).generate_rules(20);
+    let header_len = 15;
+    let mut hypersplit = HyperSplitClassifier::build(&rules).serialize();
+    hypersplit.truncate(header_len);
+    for _ in 0..200_000 {
+        hypersplit.extend([0, 0]);
+        hypersplit.extend(1u32.to_le_bytes());
+    }
+    assert_eq!(
+        HyperSplitClassifier::deserialize(&hypersplit).err(),
+        Some(DecodeError::Corrupt)
+    );
+
+    // HiCuts adds the out-of-range mode; each node has a single cut.
+    let mut hicuts = HiCutsClassifier::build(&rules).serialize();
+    hicuts.truncate(header_len + 1);
+    for _ in 0..200_000 {
+        hicuts.extend([0, 0]);
+        for v in [0u32, u32::MAX, 1, 1] {
+            hicuts.extend(v.to_le_bytes());
+        }
+    }
+    assert_eq!(
+        HiCutsClassifier::deserialize(&hicuts).err(),
+        Some(DecodeError::Corrupt)
+    );
+}
+
+#[test]
+fn test_hicuts_back_references_are_checked() {
+    let rules = Simulation::new(3264).generate_rules(20);
+    let mut header = HiCutsClassifier::build(&rules).serialize();
+    header.truncate(16);
+    let internal = |out: &mut Vec<u8>, cuts: u32| {
+        out.extend([0, 0]);
+        for v in [0, u32::MAX, u32::MAX / cuts, cuts] {
+            out.extend(v.to_le_bytes());
+        }
+    };
+    let shared = |out: &mut Vec<u8>, index: u32| {
+        out.push(3);
+        out.extend(index.to_le_bytes());
+    };
+    let leaf = |out: &mut Vec<u8>| out.extend([1, 0, 0, 0, 0]);
+    let decode = |bytes: &[u8]| HiCutsClassifier::deserialize(bytes).err();
+
+    // A reference to a subtree not decoded yet.
+    let mut blob = header.clone();
+    internal(&mut blob, 1);
+    shared(&mut blob, 0);
+    assert_eq!(decode(&blob), Some(DecodeError::Corrupt));
+
+    // Each level references the one below twice: a small blob, but a tree
+    // of 2^100 nodes once expanded.
+    let mut blob = header.clone();
+    for _ in 0..100 {
+        internal(&mut blob, 2);
+    }
+    leaf(&mut blob);
+    for index in 0..100 {
+        shared(&mut blob, index);
+    }
+    assert_eq!(decode(&blob), Some(DecodeError::Corrupt));
+
+    // A chain of 200 single-cut nodes (indices 0 to 200, leaf first) may be
+    // referenced next to itself, but not from deeper than the depth cap.
+    let mut chain = header.clone();
+    internal(&mut chain, 2);
+    for _ in 0..200 {
+        internal(&mut chain, 1);
+    }
+    leaf(&mut chain);
+    let mut blob = chain.clone();
+    shared(&mut blob, 200);
+    assert_eq!(decode(&blob), None);
+    let mut blob = chain;
+    for _ in 0..100 {
+        internal(&mut blob, 1);
+    }
+    shared(&mut blob, 200);
+    assert_eq!(decode(&blob), Some(DecodeError::Corrupt));
+}
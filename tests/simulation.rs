@@ -0,0 +1,222 @@
+use cutsplit::classifier::Classifier;
+use cutsplit::geometry::Region;
+use cutsplit::linear::LinearClassifier;
+use cutsplit::packet::FiveTuple;
+use cutsplit::rule::Action;
+use cutsplit::simulation::estimate::SamplingModel;
+use cutsplit::simulation::topology::PolicyKind;
+use cutsplit::simulation::traffic::{Exchange, FlowDistribution, NatPool};
+use cutsplit::simulation::Simulation;
+
+#[test]
+fn test_split_streams_are_reproducible() {
+    let parent = Simulation::new(99);
+    let mut a = parent.split(4);
+
+    // Consuming the parent does not change its children.
+    let mut consumed = Simulation::new(99);
+    consumed.generate_rules(50);
+    let mut b = consumed.split(4);
+
+    for (x, y) in a.iter_mut().zip(b.iter_mut()) {
+        assert_eq!(x.seed(), y.seed());
+        assert_eq!(x.generate_packets(100), y.generate_packets(100));
+    }
+
+    // Children are distinct from each other and from the parent.
+    let first = a[0].generate_packets(100);
+    let second = a[1].generate_packets(100);
+    assert_ne!(first, second);
+    assert_ne!(Simulation::new(99).generate_packets(100), first);
+
+    // Single children match the corresponding split entry.
+    let mut third = parent.child(2);
+    let mut again = parent.split(3).pop().unwrap();
+    assert_eq!(third.generate_packets(20), again.generate_packets(20));
+}
+
+#[test]
+fn test_enterprise_rules_follow_topology() {
+    let mut sim = Simulation::new(2025);
+    let topology = sim.generate_topology(4);
+    assert_eq!(topology.servers.len(), 8); // DMZ + server LAN
+    assert!(topology
+        .servers
+        .iter()
+        .all(|s| { topology.zones[s.zone].subnet.contains(s.addr) && !s.services.is_empty() }));
+
+    let rules = sim.generate_enterprise_rules(&topology, 200);
+    let denies = topology
+        .policies
+        .iter()
+        .filter(|p| p.kind == PolicyKind::Deny)
+        .count();
+    assert_eq!(rules.len(), 200 + denies + 1);
+    assert!(rules
+        .iter()
+        .enumerate()
+        .all(|(i, r)| r.priority == i as u32));
+
+    // Every rule stays within one of the policy's zone pairs.
+    for rule in &rules[..rules.len() - 1] {
+        assert!(topology.policies.iter().any(|p| {
+            let from = topology.zones[p.from].subnet.to_range();
+            let to = topology.zones[p.to].subnet.to_range();
+            from.min <= rule.src_ip.min
+                && rule.src_ip.max <= from.max
+                && to.min <= rule.dst_ip.min
+                && rule.dst_ip.max <= to.max
+        }));
+    }
+
+    // Guests cannot reach internal servers.
+    let server = topology.servers_in(3).next().unwrap();
+    let packet = FiveTuple {
+        src_ip: 0xAC10_0001,
+        dst_ip: server.addr,
+        src_port: 40000,
+        dst_port: server.services[0].port,
+        proto: server.services[0].proto,
+        zone: 0,
+    };
+    let classifier = LinearClassifier::build(&rules);
+    assert_eq!(classifier.classify(&packet), Some(Action::Deny));
+}
+
+#[test]
+fn test_nat_flows_and_exchanges() {
+    let mut sim = Simulation::new(404);
+    let pool = NatPool::default();
+    assert_eq!(pool.capacity(), 256);
+
+    let flows = sim.generate_nat_flows(&pool, 1000);
+    assert!(flows
+        .iter()
+        .all(|f| pool.addrs.contains(f.request.src_ip) && pool.ports.contains(f.request.src_port)));
+    // 1000 flows over 256 ports: ports must repeat.
+    let mut ports: Vec<u16> = flows.iter().map(|f| f.request.src_port).collect();
+    ports.sort_unstable();
+    ports.dedup();
+    assert!(ports.len() <= 256);
+
+    let packets = sim.generate_exchanges(&flows, 5000, &Exchange::default());
+    let responses = packets
+        .iter()
+        .filter(|p| pool.addrs.contains(p.dst_ip))
+        .count();
+    assert!(responses > 3000 && responses < 4500, "{}", responses);
+    assert!(packets
+        .iter()
+        .all(|p| flows.iter().any(|f| *p == f.request || *p == f.response())));
+
+    let one_way = Exchange {
+        one_way: 1.0,
+        ..Exchange::default()
+    };
+    let packets = sim.generate_exchanges(&flows, 1000, &one_way);
+    assert!(packets.iter().all(|p| pool.addrs.contains(p.src_ip)));
+}
+
+#[test]
+fn test_heavy_hitter_traces() {
+    let mut sim = Simulation::new(7);
+    let flows = sim.generate_flows(1000);
+
+    let heavy = FlowDistribution::HeavyHitters {
+        fraction: 0.1,
+        share: 0.9,
+    };
+    let trace = sim.generate_flow_trace(&flows, 20000, &heavy);
+    let top: Vec<FiveTuple> = flows[..100].iter().map(|f| f.request).collect();
+    let hits = trace.iter().filter(|p| top.contains(p)).count();
+    assert!(hits > 17000 && hits < 19000, "{}", hits);
+
+    let zipf = sim.generate_flow_trace(&flows, 20000, &FlowDistribution::Zipf { exponent: 1 });
+    let first = zipf.iter().filter(|p| **p == flows[0].request).count();
+    let tenth = zipf.iter().filter(|p| **p == flows[9].request).count();
+    assert!(first > tenth * 5, "{} vs {}", first, tenth);
+
+    let uniform = sim.generate_flow_trace(&flows, 20000, &FlowDistribution::Uniform);
+    let hits = uniform.iter().filter(|p| top.contains(p)).count();
+    assert!(hits > 1500 && hits < 2500, "{}", hits);
+}
+
+#[test]
+fn test_canonical_flow_keys() {
+    use std::collections::HashSet;
+
+    let mut sim = Simulation::new(55);
+    let flows = sim.generate_flows(200);
+    let mut keys = HashSet::new();
+    for flow in &flows {
+        let (req, resp) = (flow.request, flow.response());
+        assert_eq!(req.canonicalize(), resp.canonicalize());
+        assert!(req.canonicalize().is_canonical());
+        assert!(req.is_canonical() != resp.is_canonical() || req == resp);
+        keys.insert(req.canonicalize());
+        keys.insert(resp.canonicalize());
+    }
+    let distinct: HashSet<_> = flows.iter().map(|f| f.request).collect();
+    assert_eq!(keys.len(), distinct.len());
+
+    // Same address on both sides: ports decide.
+    let loopback = FiveTuple {
+        src_ip: 0x7F00_0001,
+        dst_ip: 0x7F00_0001,
+        src_port: 9000,
+        dst_port: 80,
+        proto: 6,
+        zone: 0,
+    };
+    assert_eq!(loopback.canonicalize().src_port, 80);
+}
+
+#[test]
+fn test_hit_rate_estimation() {
+    let rules = Simulation::new(3229).generate_rules(200);
+    let classifier = LinearClassifier::build(&rules);
+
+    // Against the model's own traffic, estimates track the measured shares.
+    let estimate =
+        Simulation::new(1).estimate_hit_rates(&classifier, 20000, &SamplingModel::Traffic);
+    let trace = Simulation::new(2).generate_packets(20000);
+    assert_eq!(estimate.samples, 20000);
+    for hot in estimate.top(5) {
+        let measured = trace
+            .iter()
+            .filter(|p| classifier.classify_rule(p).map(|r| r.id) == Some(hot.rule_id))
+            .count() as f64
+            / trace.len() as f64;
+        assert!(
+            (hot.rate - measured).abs() < 0.02,
+            "{:?} vs {}",
+            hot,
+            measured
+        );
+    }
+    let total: u64 = estimate.rules.iter().map(|r| r.hits).sum::<u64>() + estimate.unmatched;
+    assert_eq!(total, estimate.samples);
+    assert!(estimate.rules.windows(2).all(|w| w[0].hits >= w[1].hits));
+
+    // Uniformly over the packet space, the default rule takes almost everything.
+    let default = rules.last().unwrap().id;
+    let uniform = Simulation::new(3).estimate_hit_rates(&classifier, 5000, &SamplingModel::Uniform);
+    assert_eq!(uniform.top(1)[0].rule_id, default);
+    assert!(uniform.rate(default) > 0.9);
+    assert_eq!(uniform.unmatched_rate(), 0.0);
+
+    // Inside the first rule's box, it wins every packet.
+    let within = SamplingModel::Within(Region::from_rule(&rules[0]));
+    let estimate = Simulation::new(4).estimate_hit_rates(&classifier, 1000, &within);
+    assert_eq!(estimate.rate(rules[0].id), 1.0);
+    assert_eq!(estimate.rules.len(), 1);
+
+    // Skewed flow traces are reproducible from the seed.
+    let flows = SamplingModel::Flows {
+        n_flows: 500,
+        distribution: FlowDistribution::Zipf { exponent: 1 },
+    };
+    let a = Simulation::new(5).estimate_hit_rates(&classifier, 5000, &flows);
+    let b = Simulation::new(5).estimate_hit_rates(&classifier, 5000, &flows);
+    assert_eq!(a, b);
+}
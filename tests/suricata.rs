@@ -0,0 +1,176 @@
+use cutsplit::classifier::Classifier;
+use cutsplit::hypersplit::classifier::HyperSplitClassifier;
+use cutsplit::linear::LinearClassifier;
+use cutsplit::packet::FiveTuple;
+use cutsplit::rule::{Action, Range};
+use cutsplit::suricata::{parse_rule, parse_rules, ParseErrorKind, Variables};
+
+fn ip(a: u8, b: u8, c: u8, d: u8) -> u32 {
+    u32::from_be_bytes([a, b, c, d])
+}
+
+fn packet(src_ip: u32, src_port: u16, dst_ip: u32, dst_port: u16, proto: u8) -> FiveTuple {
+    FiveTuple {
+        src_ip,
+        dst_ip,
+        src_port,
+        dst_port,
+        proto,
+        zone: 0,
+    }
+}
+
+#[test]
+fn test_suricata_headers() {
+    let mut vars = Variables::new();
+    vars.set("HOME_NET", "[192.168.0.0/16,10.0.0.0/8]")
+        .set("EXTERNAL_NET", "!$HOME_NET")
+        .set("HTTP_PORTS", "[80,8080]");
+
+    let input = r#"
+# Local policy
+drop tcp $EXTERNAL_NET any -> $HOME_NET 22 (msg:"ssh in"; sid:2001;)
+alert http $HOME_NET any -> $EXTERNAL_NET $HTTP_PORTS (msg:"web; out"; \
+    content:"GET"; sid:2002; rev:1;)
+pass udp any 1024: <> [10.1.0.0/16,!10.1.2.0/24] [53,!53] (msg:"never")
+pass dns any any -> any 53
+#alert tcp any any -> any any (sid:9;)
+alert ip any any -> any ![0:1023,8080]
+"#;
+    let err = parse_rules(input, &vars).unwrap_err();
+    assert_eq!((err.line, err.kind), (6, ParseErrorKind::EmptySet));
+
+    let input = input.replace("[53,!53]", "[53:60,!54]");
+    let rules = parse_rules(&input, &vars).unwrap();
+    assert_eq!(rules.len(), 5);
+    let ids: Vec<u32> = rules.iter().map(|r| r.id).collect();
+    assert_eq!(ids, [2001, 2002, 2, 3, 4]);
+    assert!(rules
+        .iter()
+        .enumerate()
+        .all(|(i, r)| r.priority == i as u32));
+    assert_eq!(rules[0].action, Action::Deny);
+    assert_eq!(rules[0].dst_port, Range::exact(22));
+    assert_eq!(rules[1].proto, Range::exact(6));
+    assert!(rules[2].bidirectional);
+    assert_eq!(rules[2].src_port, Range::new(1024, u16::MAX));
+
+    let linear = LinearClassifier::build(&rules);
+    let tree = HyperSplitClassifier::build(&rules);
+    let check = |p: FiveTuple, expected: Option<u32>| {
+        assert_eq!(linear.classify_rule(&p).map(|r| r.id), expected, "{:?}", p);
+        assert_eq!(tree.classify_rule(&p).map(|r| r.id), expected, "{:?}", p);
+    };
+    let outside = ip(8, 8, 8, 8);
+    let home = ip(192, 168, 1, 1);
+    check(packet(outside, 4000, home, 22, 6), Some(2001));
+    check(packet(home, 4000, home, 22, 6), None);
+    check(packet(home, 4000, outside, 8080, 6), Some(2002));
+    check(packet(home, 4000, outside, 8080, 17), None);
+    // Excluded subnet and port, and the reverse direction.
+    check(packet(outside, 2000, ip(10, 1, 3, 1), 55, 17), Some(2));
+    check(packet(outside, 2000, ip(10, 1, 2, 1), 55, 17), None);
+    check(packet(outside, 2000, ip(10, 1, 3, 1), 54, 17), None);
+    check(packet(ip(10, 1, 3, 1), 55, outside, 2000, 17), Some(2));
+    check(packet(outside, 5, outside, 53, 6), Some(3));
+    check(packet(outside, 5, outside, 2000, 1), Some(4));
+    check(packet(outside, 5, outside, 1000, 1), None);
+}
+
+#[test]
+fn test_suricata_header_errors() {
+    let vars = Variables::new();
+    let kind = |rule: &str| parse_rule(rule, &vars).unwrap_err();
+    assert_eq!(
+        kind("alert tcp any any -> any"),
+        ParseErrorKind::MissingField
+    );
+    assert_eq!(
+        kind("notify tcp any any -> any any"),
+        ParseErrorKind::BadAction
+    );
+    assert_eq!(
+        kind("alert sctp any any -> any any"),
+        ParseErrorKind::BadProtocol
+    );
+    assert_eq!(
+        kind("alert tcp 1.2.3 any -> any any"),
+        ParseErrorKind::BadAddress
+    );
+    assert_eq!(
+        kind("alert tcp 1.2.3.4/40 any -> any any"),
+        ParseErrorKind::BadAddress
+    );
+    assert_eq!(
+        kind("alert tcp [1.2.3.4 any -> any any"),
+        ParseErrorKind::MissingField
+    );
+    // Deep nesting is refused rather than overflowing the stack.
+    let bangs = "!".repeat(200_000);
+    assert_eq!(
+        kind(&format!("alert tcp {bangs}any any -> any any")),
+        ParseErrorKind::BadAddress
+    );
+    let (open, close) = ("[".repeat(200_000), "]".repeat(200_000));
+    assert_eq!(
+        kind(&format!("alert tcp {open}1.2.3.4{close} any -> any any")),
+        ParseErrorKind::BadAddress
+    );
+    assert_eq!(
+        kind(&format!("alert tcp any {open}80{close} -> any any")),
+        ParseErrorKind::BadPort
+    );
+    let nested = format!("{}1.2.3.4{}", "[".repeat(8), "]".repeat(8));
+    assert!(parse_rule(&format!("alert tcp !!{nested} any -> any any"), &vars).is_ok());
+    assert_eq!(
+        kind("alert tcp 2001:db8::1 any -> any any"),
+        ParseErrorKind::Ipv6
+    );
+    assert_eq!(
+        kind("alert tcp any 70000 -> any any"),
+        ParseErrorKind::BadPort
+    );
+    assert_eq!(
+        kind("alert tcp any 90:80 -> any any"),
+        ParseErrorKind::BadPort
+    );
+    assert_eq!(
+        kind("alert tcp any any <- any any"),
+        ParseErrorKind::BadDirection
+    );
+    assert_eq!(
+        kind("alert tcp any !any -> any any"),
+        ParseErrorKind::EmptySet
+    );
+    assert_eq!(
+        kind("alert tcp $HOME_NET any -> any any"),
+        ParseErrorKind::UnknownVariable("HOME_NET".into())
+    );
+    assert_eq!(
+        kind("alert tcp any any -> any any (sid:x;)"),
+        ParseErrorKind::BadSid
+    );
+
+    let mut cyclic = Variables::new();
+    cyclic.set("A", "$B").set("B", "[1.2.3.4,$A]");
+    assert!(matches!(
+        parse_rule("alert tcp $A any -> any any", &cyclic),
+        Err(ParseErrorKind::UnknownVariable(_))
+    ));
+
+    // sid without a trailing semicolon, and a rule without options.
+    let rule = parse_rule("alert tcp any any -> 10.0.0.1 [80, 443] (sid:7)", &vars).unwrap();
+    assert_eq!(rule.id, 7);
+    assert_eq!(rule.dst_ip, Range::exact(0x0A00_0001));
+    assert_eq!(rule.dst_port, Range::exact(80));
+    assert_eq!(rule.field_sets.unwrap().dst_port, [Range::exact(443)]);
+    assert_eq!(
+        parse_rule("pass icmp any any -> any any", &vars)
+            .unwrap()
+            .id,
+        0
+    );
+
+    let err = parse_rules("\nalert tcp any any -> any any \\\n", &vars).unwrap_err();
+    assert_eq!((err.line, err.kind), (2, ParseErrorKind::MissingField));
+}
@@ -0,0 +1,236 @@
+use cutsplit::rule::{Action, Range, Rule, ANY_ZONE};
+use cutsplit::update::audit::{AuditLog, DecodeError};
+use cutsplit::update::RuleChange;
+
+fn rule(id: u32, action: Action) -> Rule {
+    Rule {
+        id,
+        priority: id,
+        src_ip: Range::new(0x0A000000, 0x0AFFFFFF),
+        dst_ip: Range::any(0, u32::MAX),
+        src_port: Range::any(0, 65535),
+        dst_port: Range::exact(443),
+        proto: Range::exact(6),
+        zone: ANY_ZONE,
+        bidirectional: false,
+        field_sets: None,
+        action,
+    }
+}
+
+#[test]
+fn test_audit_log_query_and_roundtrip() {
+    let mut log = AuditLog::new();
+    let r1 = rule(1, Action::Permit);
+    let mut r2 = rule(2, Action::Deny);
+    r2.bidirectional = true;
+    r2.field_sets_mut().dst_port = vec![Range::exact(80), Range::new(8080, 8090)];
+    r2.negate(cutsplit::cutsplit::tree::Dimension::SrcIp);
+    r2.field_sets_mut().dst_set = Some(7);
+    let r1b = rule(1, Action::Deny);
+
+    log.record_insert(100, &r1);
+    log.record_insert(110, &r2);
+    log.record_modify(120, &r1, &r1b);
+    log.record_remove(130, &r2);
+
+    assert_eq!(log.len(), 4);
+    assert_eq!(log.for_rule(1).count(), 2);
+    assert_eq!(log.between(105, 125).count(), 2);
+    assert_eq!(log.since(1).count(), 2);
+
+    let decoded = AuditLog::from_bytes(&log.to_bytes()).unwrap();
+    assert_eq!(decoded.len(), log.len());
+    for (a, b) in decoded.entries().zip(log.entries()) {
+        assert_eq!(a.seq, b.seq);
+        assert_eq!(a.timestamp, b.timestamp);
+        assert_eq!(a.change.rule_id(), b.change.rule_id());
+    }
+    match &decoded.entries().nth(1).unwrap().change {
+        RuleChange::Insert(rule) => assert_eq!(rule, &r2),
+        other => panic!("unexpected change {:?}", other),
+    }
+
+    // Replaying the log reproduces the final rule set.
+    let mut rules = Vec::new();
+    for entry in decoded.entries() {
+        assert!(entry.change.apply(&mut rules));
+    }
+    assert_eq!(rules.len(), 1);
+    assert_eq!(rules[0].action, Action::Deny);
+
+    assert!(matches!(
+        AuditLog::from_bytes(b"XXXX"),
+        Err(DecodeError::BadMagic)
+    ));
+
+    // A huge entry count with nothing behind it is truncated input, not an
+    // allocation of billions of entries.
+    let mut huge = log.to_bytes();
+    huge.truncate(13);
+    huge.extend(u32::MAX.to_le_bytes());
+    assert!(matches!(
+        AuditLog::from_bytes(&huge),
+        Err(DecodeError::Truncated)
+    ));
+}
+
+#[test]
+fn test_audit_log_limit_drops_oldest() {
+    let mut log = AuditLog::with_limit(2);
+    for id in 0..5 {
+        log.record(id as u64, RuleChange::Insert(rule(id, Action::Permit)));
+    }
+    let seqs: Vec<u64> = log.entries().map(|e| e.seq).collect();
+    assert_eq!(seqs, vec![3, 4]);
+}
+
+#[test]
+fn test_priority_assignment_and_respacing() {
+    use cutsplit::priority::{assign_from_order, is_crowded, priority_between, respace};
+
+    let mut rules: Vec<Rule> = (0..4).map(|id| rule(id, Action::Permit)).collect();
+    assign_from_order(&mut rules, 0, 1).unwrap();
+    assert!(is_crowded(&rules));
+
+    rules[2].priority = 1; // tie with rule 1
+    respace(&mut rules, 10).unwrap();
+    let prios: Vec<u32> = rules.iter().map(|r| r.priority).collect();
+    assert_eq!(prios, vec![10, 20, 20, 30]);
+    assert!(!is_crowded(&rules));
+
+    assert_eq!(priority_between(20, 30), Some(25));
+    assert_eq!(priority_between(20, 21), None);
+    assert!(assign_from_order(&mut rules, u32::MAX, 1).is_err());
+}
+
+#[test]
+fn test_priority_bands() {
+    use cutsplit::classifier::Classifier;
+    use cutsplit::hypersplit::classifier::HyperSplitClassifier;
+    use cutsplit::packet::FiveTuple;
+    use cutsplit::priority::{Band, BandError, BandLayout, BandedRules};
+
+    let layout = BandLayout::new(100, 1000).unwrap();
+    assert!(BandLayout::new(0, 10).is_none());
+    assert!(BandLayout::new(10, 10).is_none());
+    assert_eq!(layout.band_of(99), Band::System);
+    assert_eq!(layout.band_of(100), Band::User);
+    assert_eq!(layout.band_of(u32::MAX), Band::Default);
+
+    let mut banded = BandedRules::new(layout);
+    assert_eq!(banded.push(Band::User, rule(1, Action::Permit)), Ok(100));
+    assert_eq!(banded.push(Band::System, rule(2, Action::Deny)), Ok(0));
+    assert_eq!(banded.push(Band::User, rule(3, Action::Deny)), Ok(110));
+    assert_eq!(banded.push(Band::Default, rule(4, Action::Deny)), Ok(1000));
+
+    // A user rule cannot claim a system priority.
+    let mut sneaky = rule(5, Action::Permit);
+    sneaky.priority = 1;
+    assert_eq!(
+        banded.insert(Band::User, sneaky),
+        Err(BandError::OutOfBand {
+            band: Band::User,
+            priority: 1
+        })
+    );
+    let mut dup = rule(1, Action::Permit);
+    dup.priority = 500;
+    assert_eq!(
+        banded.insert(Band::User, dup),
+        Err(BandError::DuplicateId(1))
+    );
+
+    // The system deny wins over the earlier-inserted user permit.
+    let c: HyperSplitClassifier = banded.build();
+    let packet = FiveTuple {
+        src_ip: 0x0A00_0001,
+        dst_port: 443,
+        proto: 6,
+        ..FiveTuple::default()
+    };
+    assert_eq!(c.classify_rule(&packet).map(|r| r.id), Some(2));
+
+    assert_eq!(banded.band(3), Some(Band::User));
+    assert_eq!(
+        banded.remove(2).map(|(b, r)| (b, r.id)),
+        Some((Band::System, 2))
+    );
+    let c: HyperSplitClassifier = banded.build();
+    assert_eq!(c.classify_rule(&packet).map(|r| r.id), Some(1));
+
+    let mut full = BandedRules::new(BandLayout::new(1, 5).unwrap());
+    assert_eq!(full.push(Band::System, rule(1, Action::Deny)), Ok(0));
+    assert_eq!(
+        full.push(Band::System, rule(2, Action::Deny)),
+        Err(BandError::BandFull(Band::System))
+    );
+}
+
+#[test]
+fn test_rule_set_diff() {
+    use cutsplit::classifier::Classifier;
+    use cutsplit::hypersplit::classifier::HyperSplitClassifier;
+    use cutsplit::linear::LinearClassifier;
+    use cutsplit::simulation::Simulation;
+    use cutsplit::update::{diff_rules, DynamicClassifier, Rebuilding};
+
+    let mut sim = Simulation::new(909);
+    let old = sim.generate_rules(200);
+    let mut new = old.clone();
+    // 3 modified in place, 2 renumbered, 1 re-prioritized, 4 removed, some added.
+    new[10].action = match new[10].action {
+        Action::Permit => Action::Deny,
+        Action::Deny => Action::Permit,
+    };
+    new[11].dst_port = Range::exact(8443);
+    new[12].zone = Range::exact(3);
+    new[20].id = 1000;
+    new[21].id = 1001;
+    new[30].priority = 30_000;
+    for idx in [150, 100, 60, 40] {
+        new.remove(idx);
+    }
+    let added: Vec<Rule> = sim
+        .generate_rules(5)
+        .into_iter()
+        .map(|mut r| {
+            r.id += 2000;
+            r.priority += 10_000;
+            r
+        })
+        .collect();
+    new.extend(added.iter().cloned());
+
+    let changes = diff_rules(&old, &new);
+    let count = |f: fn(&RuleChange) -> bool| changes.iter().filter(|c| f(c)).count();
+    assert_eq!(count(|c| matches!(c, RuleChange::Remove(_))), 4);
+    assert_eq!(count(|c| matches!(c, RuleChange::Modify { .. })), 6);
+    assert_eq!(count(|c| matches!(c, RuleChange::Insert(_))), added.len());
+    assert!(diff_rules(&new, &new).is_empty());
+
+    // Replaying the diff on a list yields the new rule set.
+    let mut replayed = old.clone();
+    for change in &changes {
+        assert!(change.apply(&mut replayed));
+    }
+    let sorted = |rules: &[Rule]| {
+        let mut v = rules.to_vec();
+        v.sort_by_key(|r| r.id);
+        v
+    };
+    assert_eq!(sorted(&replayed), sorted(&new));
+
+    // And on a live classifier, it classifies like one built from scratch.
+    let mut live = Rebuilding::<HyperSplitClassifier>::build(&old);
+    for change in &changes {
+        assert!(live.apply(change));
+    }
+    let reference = LinearClassifier::build(&new);
+    for p in sim.generate_packets(1000) {
+        assert_eq!(
+            live.classify_rule(&p).map(|r| r.id),
+            reference.classify_rule(&p).map(|r| r.id)
+        );
+    }
+}